@@ -29,22 +29,46 @@ pub struct InputConfig {
     pub tap_config: Option<TapConfig>,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub map_to_output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tablet_mapping: Option<TabletMapping>,
+    /// Keep the aspect ratio of the tablet, leaving parts of the mapped area unreachable
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub keep_aspect_ratio: Option<bool>,
+}
+
+/// Area the active area of a drawing tablet is mapped to
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum TabletMapping {
+    /// The output set by `map_to_output`
+    #[default]
+    Output,
+    /// Region of that output in logical coordinates
+    Region {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    /// The focused window
+    FocusedWindow,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct AccelConfig {
-    #[serde(with = "AccelProfileDef")]
+    #[serde(with = "AccelProfileDef", default)]
     pub profile: Option<AccelProfile>,
     pub speed: f64,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct ScrollConfig {
-    #[serde(with = "ScrollMethodDef")]
+    #[serde(with = "ScrollMethodDef", default)]
     pub method: Option<ScrollMethod>,
     pub natural_scroll: Option<bool>,
     pub scroll_button: Option<u32>,
     pub scroll_factor: Option<f64>,
+    /// Combine high-resolution wheel events into whole clicks, for clients misbehaving otherwise
+    pub discrete_scroll: Option<bool>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -63,7 +87,7 @@ impl Default for DeviceState {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TapConfig {
     pub enabled: bool,
-    #[serde(with = "TapButtonMapDef")]
+    #[serde(with = "TapButtonMapDef", default)]
     pub button_map: Option<TapButtonMap>,
     pub drag: bool,
     pub drag_lock: bool,
@@ -1,8 +1,9 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use cosmic_config::{cosmic_config_derive::CosmicConfigEntry, CosmicConfigEntry};
+use cosmic_settings_config::shortcuts::{self, action::Direction};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 pub mod input;
 pub mod workspace;
@@ -13,6 +14,7 @@ pub struct CosmicCompConfig {
     pub workspaces: workspace::WorkspaceConfig,
     pub input_default: input::InputConfig,
     pub input_touchpad: input::InputConfig,
+    /// Overrides for individual devices, keyed by device name or `vendor:product` id in hex
     pub input_devices: HashMap<String, input::InputConfig>,
     pub xkb_config: XkbConfig,
     /// Autotiling enabled
@@ -23,8 +25,78 @@ pub struct CosmicCompConfig {
     pub autotile_behavior: TileBehavior,
     /// Active hint enabled
     pub active_hint: bool,
+    /// Run Xwayland, so X11 applications can be used
+    pub xwayland: bool,
     /// Let X11 applications scale themselves
     pub descale_xwayland: bool,
+    /// App-ids of X11 applications needing unscaled pixels, like games.
+    /// While any of their windows is mapped, X11 applications are descaled regardless of `descale_xwayland`.
+    pub xwayland_unscaled_apps: Vec<String>,
+    /// When to allow tearing page flips for fullscreen windows
+    pub tearing: TearingConfig,
+    /// Low latency policy for fullscreen X11 windows, which are usually games
+    pub game_mode: GameModeConfig,
+    /// How requests of windows to be activated are handled
+    pub activation: ActivationPolicy,
+    /// App-ids of windows, which should always be decorated by the compositor,
+    /// even if they prefer to draw their own decorations
+    pub force_server_side_decorations: Vec<String>,
+    /// App-ids of windows, which are shown as black rectangles in screenshots and screen captures
+    pub capture_excluded_apps: Vec<String>,
+    /// Timeouts for dimming and blanking outputs on inactivity
+    pub idle: IdleConfig,
+    /// When to use adaptive sync on outputs, that have it enabled
+    pub adaptive_sync: AdaptiveSyncMode,
+    /// Which gpus are used for composition on multi-gpu systems
+    pub gpu: GpuConfig,
+    /// Outputs without a physical display, e.g. for remote desktop sessions or screencasts
+    pub virtual_outputs: Vec<VirtualOutputConfig>,
+    /// Modes added to outputs by connector name, e.g. for monitors with broken EDIDs
+    pub custom_modes: HashMap<String, Vec<CustomMode>>,
+    /// Behavior of the built-in screen magnifier
+    pub zoom: ZoomConfig,
+    /// Color filter applied to the content of all outputs, e.g. for color blindness
+    pub color_filter: ColorFilterConfig,
+    /// Flashing the screen when an application rings the bell
+    pub visual_bell: VisualBell,
+    /// Size of the cursor and finding the pointer on large screens
+    pub cursor: CursorConfig,
+    /// Wallpapers drawn by the compositor itself
+    pub wallpaper: WallpaperConfig,
+    /// Blurring the background behind translucent surfaces
+    pub blur: BlurConfig,
+    /// Radius of the rounded corners of windows in logical pixels, 0 disables rounding
+    pub corner_radius: u32,
+    /// Shadows drawn behind windows
+    pub shadow: ShadowConfig,
+    /// Dimming all windows but the focused one
+    pub dim_unfocused: DimConfig,
+    /// Behavior of the Alt+Tab window switcher
+    pub window_switcher: WindowSwitcherConfig,
+    /// Accessibility features changing how keyboard input is handled
+    pub keyboard_accessibility: KeyboardAccessibilityConfig,
+    /// Actions bound to multi-finger touchpad gestures
+    pub gestures: GestureConfig,
+    /// Modifiers to hold for moving windows by dragging them with the left mouse button,
+    /// or resizing them with the right one, anywhere inside of them. None disables it.
+    pub window_drag_modifiers: shortcuts::Modifiers,
+    /// Named sets of shortcuts replacing all other shortcuts while active, like in i3
+    pub binding_modes: HashMap<String, BindingMode>,
+    /// Bindings of the compositor's own actions, added to the built-in defaults.
+    /// Binding a default to `Disable` removes it.
+    pub compositor_shortcuts: HashMap<shortcuts::Binding, CompositorAction>,
+    /// Built-in set of default shortcuts, custom shortcuts are applied on top of it
+    pub keybinding_profile: KeybindingProfile,
+    /// Names of the workspaces in order, workspaces past the end are named after their number
+    pub workspace_names: Vec<String>,
+    /// Settings of individual workspaces, keyed by their name or number, starting at "1"
+    pub workspace_overrides: HashMap<String, workspace::WorkspaceOverride>,
+    /// Commands run on compositor events
+    pub hooks: Vec<Hook>,
+    /// Collecting frame timings and resource usage, for tracking down stutter
+    pub metrics: MetricsConfig,
+    /// Clients allowed to use protocols, that can read or control the whole session
+    pub protocol_permissions: ProtocolPermissionsConfig,
 }
 
 impl Default for CosmicCompConfig {
@@ -50,7 +122,45 @@ impl Default for CosmicCompConfig {
             autotile: Default::default(),
             autotile_behavior: Default::default(),
             active_hint: true,
+            xwayland: true,
             descale_xwayland: false,
+            xwayland_unscaled_apps: Vec::new(),
+            tearing: Default::default(),
+            game_mode: Default::default(),
+            activation: Default::default(),
+            force_server_side_decorations: Vec::new(),
+            capture_excluded_apps: Vec::new(),
+            idle: Default::default(),
+            adaptive_sync: Default::default(),
+            gpu: Default::default(),
+            virtual_outputs: Vec::new(),
+            custom_modes: HashMap::new(),
+            zoom: Default::default(),
+            color_filter: Default::default(),
+            visual_bell: Default::default(),
+            cursor: Default::default(),
+            wallpaper: Default::default(),
+            blur: Default::default(),
+            corner_radius: 0,
+            shadow: Default::default(),
+            dim_unfocused: Default::default(),
+            window_switcher: Default::default(),
+            keyboard_accessibility: Default::default(),
+            gestures: Default::default(),
+            window_drag_modifiers: shortcuts::Modifiers {
+                ctrl: false,
+                alt: false,
+                shift: false,
+                logo: true,
+            },
+            binding_modes: HashMap::new(),
+            compositor_shortcuts: HashMap::new(),
+            keybinding_profile: Default::default(),
+            workspace_names: Vec::new(),
+            workspace_overrides: HashMap::new(),
+            hooks: Vec::new(),
+            metrics: Default::default(),
+            protocol_permissions: Default::default(),
         }
     }
 }
@@ -62,13 +172,666 @@ pub enum TileBehavior {
     PerWorkspace,
 }
 
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TearingMode {
+    /// Never allow tearing
+    Never,
+    /// Allow tearing for fullscreen windows requesting it via `wp_tearing_control_v1`
+    #[default]
+    ClientHint,
+    /// Allow tearing for every fullscreen window
+    Always,
+}
+
+/// Which fullscreen windows may be presented with tearing.
+/// This has no effect yet, as the drm compositor of smithay can't submit async page flips.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TearingConfig {
+    #[serde(default)]
+    pub mode: TearingMode,
+    /// Force tearing on or off for specific app-ids, regardless of `mode`
+    #[serde(default)]
+    pub app_overrides: HashMap<String, bool>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GameModeConfig {
+    /// Show fullscreen X11 windows without animations or dimming of their output, and don't
+    /// let touchpad swipes switch workspaces while they are focused
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Allow tearing for fullscreen X11 windows, regardless of the `tearing` mode
+    #[serde(default)]
+    pub tearing: bool,
+}
+
+impl Default for GameModeConfig {
+    fn default() -> GameModeConfig {
+        GameModeConfig {
+            enabled: true,
+            tearing: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Built-in default shortcuts, for users coming from other desktops
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum KeybindingProfile {
+    /// The default shortcuts of COSMIC
+    #[default]
+    Cosmic,
+    /// Shortcuts of the default configuration of i3 and sway
+    I3,
+    /// Shortcuts similar to the ones of GNOME Shell
+    Gnome,
+}
+
+/// Focus stealing prevention for windows requesting to be activated
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ActivationPolicy {
+    /// Focus windows, if the request was caused by user input,
+    /// otherwise mark their workspace as urgent
+    #[default]
+    Allow,
+    /// Never focus windows, only mark their workspace as urgent
+    MarkUrgent,
+    /// Ignore activation requests, including urgency hints of X11 windows
+    Deny,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AdaptiveSyncMode {
+    /// Keep adaptive sync enabled at all times
+    #[default]
+    Always,
+    /// Only enable adaptive sync while a window is fullscreen
+    OnlyFullscreen,
+    /// Never enable adaptive sync
+    Never,
+}
+
+/// Which gpu composites an output, that is connected to a secondary gpu
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RenderDevicePolicy {
+    /// Render on the gpu driving the output, unless the visible windows are rendered by the primary gpu
+    #[default]
+    Auto,
+    /// Always render on the primary gpu and copy the result to the gpu driving the output,
+    /// e.g. to compose on an integrated gpu and only use the dedicated gpu for scanout.
+    PreferPrimary,
+    /// Always render on the gpu driving the output
+    PreferScanout,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GpuConfig {
+    /// Path of the drm node used as the primary gpu, overridden by `COSMIC_RENDER_DEVICE`.
+    /// If unset or not present, the gpu used to boot is chosen.
+    #[serde(default)]
+    pub primary_device: Option<String>,
+    #[serde(default)]
+    pub render_policy: RenderDevicePolicy,
+    /// Overrides of `render_policy` for specific connectors, e.g. `HDMI-A-1`
+    #[serde(default)]
+    pub output_render_policy: HashMap<String, RenderDevicePolicy>,
+}
+
+impl GpuConfig {
+    pub fn render_policy_for(&self, output: &str) -> RenderDevicePolicy {
+        self.output_render_policy
+            .get(output)
+            .copied()
+            .unwrap_or(self.render_policy)
+    }
+}
+
+/// Mode not advertised by a monitor
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum CustomMode {
+    /// Timings in the format of an xrandr modeline,
+    /// e.g. `"148.50 1920 2008 2052 2200 1080 1084 1089 1125 +hsync +vsync"`
+    Modeline(String),
+    /// Timings calculated with the VESA Coordinated Video Timings formula
+    Cvt {
+        width: u16,
+        height: u16,
+        refresh: f64,
+        /// Use reduced blanking intervals, needed by many digital displays for high resolutions
+        #[serde(default)]
+        reduced_blanking: bool,
+    },
+    /// Timings calculated with the VESA Generalized Timing Formula
+    Gtf {
+        width: u16,
+        height: u16,
+        refresh: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VirtualOutputConfig {
+    /// Name of the output, exposed to clients with a `VIRTUAL-` prefix
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in mHz
+    #[serde(default = "default_virtual_refresh")]
+    pub refresh: u32,
+}
+
+fn default_virtual_refresh() -> u32 {
+    60_000
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WallpaperConfig {
+    /// Wallpaper of outputs without an entry in `outputs`,
+    /// `None` leaves drawing the wallpaper to a separate client
+    #[serde(default)]
+    pub default: Option<Wallpaper>,
+    /// Wallpapers by connector name
+    #[serde(default)]
+    pub outputs: HashMap<String, Wallpaper>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Wallpaper {
+    pub source: WallpaperSource,
+    #[serde(default)]
+    pub scaling: WallpaperScaling,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum WallpaperSource {
+    /// PNG or JPEG image file
+    Path(PathBuf),
+    /// Solid color in RGB
+    Color([f32; 3]),
+    /// Images of a directory, shown one after another in alphabetical order
+    Slideshow {
+        directory: PathBuf,
+        /// Seconds each image is shown
+        #[serde(default = "default_slideshow_interval")]
+        interval: u32,
+    },
+}
+
+fn default_slideshow_interval() -> u32 {
+    15 * 60
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum WallpaperScaling {
+    /// Cover the whole output, cropping the image
+    #[default]
+    Fill,
+    /// Show the whole image, with bars where it doesn't cover the output
+    Fit,
+    /// Cover the whole output, ignoring the aspect ratio of the image
+    Stretch,
+    /// Repeat the image at its original size
+    Tile,
+}
+
+/// Shell command run whenever `event` happens.
+///
+/// Details of the event are passed in `COSMIC_HOOK_*` environment variables.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    pub command: String,
+    /// Only run for windows with this app id
+    #[serde(default)]
+    pub app_id: Option<String>,
+    /// Only run for windows with a title containing this
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Seconds after which a hook, that the compositor waits for, is killed and counts as
+    /// failed. Defaults to 10 seconds.
+    #[serde(default)]
+    pub timeout: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum HookEvent {
+    /// A window was mapped, matching `app_id` and `title` of the hook
+    WindowMapped,
+    /// The active workspace of an output changed
+    WorkspaceSwitched,
+    OutputConnected,
+    OutputDisconnected,
+    /// Outputs were dimmed or turned off because of inactivity
+    IdleEntered,
+    /// Input woke the session up again
+    IdleExited,
+    /// logind asked to lock the session, e.g. before suspending.
+    /// Suspending waits up to two seconds for a lock screen started by this hook.
+    LockRequested,
+    /// logind asked to unlock the session
+    UnlockRequested,
+    /// The system is about to suspend
+    Suspending,
+    /// The system resumed from suspend
+    Resumed,
+    /// A client without a security context wants to capture the screen or a window.
+    /// Capturing only starts once all of these hooks exited successfully.
+    ScreenCaptureRequested,
+}
+
+/// Protocols, that can read or control the whole session
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PrivilegedProtocol {
+    /// Capturing outputs, workspaces and windows
+    Screencopy,
+    /// Reading and setting the clipboard without focus
+    DataControl,
+    /// Configuring outputs and turning them off
+    OutputManagement,
+    /// Emulating keyboards and pointers
+    VirtualInput,
+    /// Listing and controlling all windows
+    ForeignToplevel,
+    /// Locking the session
+    SessionLock,
+    /// Acting as input method, which sees all typed text
+    InputMethod,
+    /// Placing panels, backgrounds and overlays, e.g. above fullscreen windows
+    LayerShell,
+    /// Listing and switching workspaces
+    Workspace,
+    /// Controlling the compositor through the IPC socket and the `org.cosmic.Comp`
+    /// D-Bus service, only checked if `restrict` is set
+    CompositorControl,
+}
+
+impl PrivilegedProtocol {
+    pub const ALL: [PrivilegedProtocol; 10] = [
+        PrivilegedProtocol::Screencopy,
+        PrivilegedProtocol::DataControl,
+        PrivilegedProtocol::OutputManagement,
+        PrivilegedProtocol::VirtualInput,
+        PrivilegedProtocol::ForeignToplevel,
+        PrivilegedProtocol::SessionLock,
+        PrivilegedProtocol::InputMethod,
+        PrivilegedProtocol::LayerShell,
+        PrivilegedProtocol::Workspace,
+        PrivilegedProtocol::CompositorControl,
+    ];
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProtocolPermissionsConfig {
+    /// Only clients started by cosmic-session can use all privileged protocols.
+    /// Otherwise every client outside of a sandbox can.
+    ///
+    /// This also limits the IPC socket and the methods of the `org.cosmic.Comp` D-Bus
+    /// service to processes allowed `CompositorControl`, its properties stay readable.
+    #[serde(default)]
+    pub restrict: bool,
+    /// Clients allowed to use some privileged protocols regardless, e.g. sandboxed ones
+    #[serde(default)]
+    pub allow: Vec<ProtocolPermission>,
+}
+
+/// Privileged protocols for clients matching all of `executable`, `systemd_unit` and `app_id`,
+/// that are set
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProtocolPermission {
+    /// Absolute path of the executable of the client
+    #[serde(default)]
+    pub executable: Option<PathBuf>,
+    /// systemd unit the client runs in, like `obs.service`
+    #[serde(default)]
+    pub systemd_unit: Option<String>,
+    /// App id of the security context of sandboxed clients, e.g. of a Flatpak
+    #[serde(default)]
+    pub app_id: Option<String>,
+    pub protocols: Vec<PrivilegedProtocol>,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IdleConfig {
+    /// Seconds of inactivity after which outputs are dimmed, `None` disables dimming
+    #[serde(default)]
+    pub dim_timeout: Option<u32>,
+    /// Seconds of inactivity after which outputs are turned off, `None` disables blanking
+    #[serde(default)]
+    pub screen_off_timeout: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    /// Answer `GetMetrics` requests on the IPC socket
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address like `127.0.0.1:9300` to serve metrics on in the Prometheus text format
+    #[serde(default)]
+    pub prometheus_address: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ZoomConfig {
+    /// Percentage the zoom level changes by with every keybinding press or scroll step
+    #[serde(default = "default_zoom_increment")]
+    pub increment: u32,
+    /// Scale up without filtering, showing crisp pixels instead of a blurry image
+    #[serde(default)]
+    pub crisp: bool,
+    /// Fingers of a touchpad pinch zooming the screen, 0 only zooms while Super+Alt are held
+    #[serde(default)]
+    pub pinch_fingers: u32,
+}
+
+impl Default for ZoomConfig {
+    fn default() -> ZoomConfig {
+        ZoomConfig {
+            increment: default_zoom_increment(),
+            crisp: false,
+            pinch_fingers: 0,
+        }
+    }
+}
+
+fn default_zoom_increment() -> u32 {
+    50
+}
+
+/// Filter changing the colors of everything shown on the outputs
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ColorFilter {
+    #[default]
+    Grayscale,
+    /// Inverted colors, for more contrast on bright content
+    Inverted,
+    /// Correction for red-blindness
+    Protanopia,
+    /// Correction for green-blindness
+    Deuteranopia,
+    /// Correction for blue-blindness
+    Tritanopia,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ColorFilterConfig {
+    /// Filter toggled by the `ToggleColorFilter` compositor shortcut, Super+Alt+C by default
+    #[serde(default)]
+    pub filter: ColorFilter,
+    /// Apply the filter right away, instead of waiting for it to be toggled
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// What flashes when an application rings the bell
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum VisualBell {
+    #[default]
+    Off,
+    /// The border of the focused window, or the whole output if no window is focused
+    Window,
+    /// The whole output
+    Output,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CursorConfig {
+    /// Size of the cursors drawn by the compositor in logical pixels, e.g. 48, 64 or 96
+    /// for large cursors, overriding `XCURSOR_SIZE`
+    #[serde(default)]
+    pub size: Option<u32>,
+    /// Pressing and releasing Ctrl on its own draws a shrinking ring around the pointer
+    #[serde(default)]
+    pub locate_pointer: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BlurConfig {
+    /// Distance in pixels between the samples of every pass
+    #[serde(default = "default_blur_radius")]
+    pub radius: f32,
+    /// Number of times the background is scaled down and up again,
+    /// every additional pass roughly doubles the strength of the blur
+    #[serde(default = "default_blur_passes")]
+    pub passes: u32,
+    /// App-ids of windows, which get the background blurred behind them
+    #[serde(default)]
+    pub windows: Vec<String>,
+    /// Namespaces of layer-shell surfaces (e.g. "panel"), which get the background blurred behind them
+    #[serde(default)]
+    pub layers: Vec<String>,
+}
+
+impl Default for BlurConfig {
+    fn default() -> BlurConfig {
+        BlurConfig {
+            radius: default_blur_radius(),
+            passes: default_blur_passes(),
+            windows: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+fn default_blur_radius() -> f32 {
+    4.0
+}
+
+fn default_blur_passes() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct ShadowConfig {
+    /// Distance in logical pixels over which the shadow fades out
+    #[serde(default = "default_shadow_radius")]
+    pub radius: u32,
+    /// Offset of the shadow relative to the window in logical pixels
+    #[serde(default = "default_shadow_offset")]
+    pub offset: (i32, i32),
+    /// Opacity of the darkest part of the shadow, 0.0 disables shadows
+    #[serde(default)]
+    pub opacity: f32,
+    /// Draw shadows behind tiled windows as well, not just floating ones
+    #[serde(default)]
+    pub tiled: bool,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> ShadowConfig {
+        ShadowConfig {
+            radius: default_shadow_radius(),
+            offset: default_shadow_offset(),
+            opacity: 0.0,
+            tiled: false,
+        }
+    }
+}
+
+fn default_shadow_radius() -> u32 {
+    16
+}
+
+fn default_shadow_offset() -> (i32, i32) {
+    (0, 4)
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DimConfig {
+    /// How much unfocused windows are darkened, from 0.0 (not at all) to 1.0 (black)
+    #[serde(default)]
+    pub strength: f32,
+    /// How much color unfocused windows lose, from 0.0 (none) to 1.0 (grayscale)
+    #[serde(default)]
+    pub desaturate: f32,
+    /// App-ids of windows, which are never dimmed, e.g. video players
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WindowSwitcherConfig {
+    /// List the windows of all workspaces, not just the active one
+    #[serde(default)]
+    pub all_workspaces: bool,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct KeyboardAccessibilityConfig {
+    /// Modifiers pressed on their own apply to the next key, pressed twice they stay
+    /// active until pressed again
+    #[serde(default)]
+    pub sticky_keys: bool,
+    /// Milliseconds a key has to be held, before its press is registered, 0 disables slow keys
+    #[serde(default)]
+    pub slow_keys_delay: u32,
+    /// Milliseconds after releasing a key, during which presses of the same key are ignored,
+    /// 0 disables bounce keys
+    #[serde(default)]
+    pub bounce_keys_delay: u32,
+    /// Move the pointer and click with the keys of the numpad
+    #[serde(default)]
+    pub mouse_keys: bool,
+    /// Enables the `WarpPointer` compositor shortcuts, by default Super+Alt with the keys of
+    /// the numpad warps the pointer to a cell of a 3×3 grid of the active output or, with `0`,
+    /// to the focused window, with the number keys to an output
+    #[serde(default)]
+    pub pointer_warping: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Gesture {
+    /// Swipe in the given direction, which is inverted with natural scrolling
+    Swipe(Direction),
+    /// Fingers moving towards each other
+    PinchIn,
+    /// Fingers moving away from each other
+    PinchOut,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GestureBinding {
+    pub fingers: u32,
+    pub gesture: Gesture,
+    pub action: shortcuts::Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GestureConfig {
+    /// Gestures without a binding are forwarded to clients.
+    ///
+    /// Switching workspaces with a swipe along the axis of the workspace layout
+    /// follows the fingers, every other action is triggered once the gesture ends.
+    #[serde(default)]
+    pub bindings: Vec<GestureBinding>,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        let swipe = |direction, action| GestureBinding {
+            fingers: 4,
+            gesture: Gesture::Swipe(direction),
+            action,
+        };
+        GestureConfig {
+            bindings: vec![
+                swipe(Direction::Left, shortcuts::Action::PreviousWorkspace),
+                swipe(Direction::Right, shortcuts::Action::NextWorkspace),
+                swipe(Direction::Up, shortcuts::Action::PreviousWorkspace),
+                swipe(Direction::Down, shortcuts::Action::NextWorkspace),
+            ],
+        }
+    }
+}
+
+impl GestureConfig {
+    pub fn action(&self, fingers: u32, gesture: Gesture) -> Option<&shortcuts::Action> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.fingers == fingers && binding.gesture == gesture)
+            .map(|binding| &binding.action)
+    }
+
+    /// Whether any swipe gestures with this many fingers are bound
+    pub fn binds_swipe(&self, fingers: u32) -> bool {
+        self.bindings.iter().any(|binding| {
+            binding.fingers == fingers && matches!(binding.gesture, Gesture::Swipe(_))
+        })
+    }
+
+    /// Whether any pinch gestures with this many fingers are bound
+    pub fn binds_pinch(&self, fingers: u32) -> bool {
+        self.bindings.iter().any(|binding| {
+            binding.fingers == fingers
+                && matches!(binding.gesture, Gesture::PinchIn | Gesture::PinchOut)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BindingMode {
+    /// Shortcut entering the mode, Escape or Return leave it again
+    pub enter: shortcuts::Binding,
+    /// Shortcuts active in the mode, other keys are forwarded to clients
+    #[serde(default)]
+    pub bindings: Vec<(shortcuts::Binding, shortcuts::Action)>,
+    /// Leave the mode after triggering one of its shortcuts, e.g. for a launch mode
+    #[serde(default)]
+    pub oneshot: bool,
+}
+
+/// Actions of the compositor itself, which aren't part of the shortcuts of cosmic-settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CompositorAction {
+    /// Removes a default binding
+    Disable,
+    /// Zooms the screen magnifier in by the configured increment
+    ZoomIn,
+    /// Zooms the screen magnifier out by the configured increment
+    ZoomOut,
+    /// Turns the configured color filter on or off
+    ToggleColorFilter,
+    /// Turns all outputs off, until the next input
+    TurnOffOutputs,
+    /// Turns the active output off, until the next input
+    TurnOffActiveOutput,
+    /// Lets the user select a region, window or output to take a screenshot of
+    InteractiveScreenshot,
+    /// Opens the window switcher, starting from the least recently used window.
+    /// `System(WindowSwitcher)` of the regular shortcuts opens it the other way around.
+    WindowSwitcherPrevious,
+    /// Switches tablets between their configured mapping and the focused window
+    ToggleTabletMapping,
+    /// Moves the pointer to a region of the screen, if pointer warping is enabled
+    WarpPointer(PointerWarp),
+    /// Gives the shortcuts back to the user, while the focused window inhibits them
+    /// or holds an X11 keyboard grab
+    RestoreShortcuts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PointerWarp {
+    /// Center of the focused window
+    Window,
+    /// Center of the nth output from the left, counting from 0
+    Output(usize),
+    /// Center of a cell of a 3×3 grid of the active output, by column and row from 0 to 2
+    Cell(u8, u8),
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct XkbConfig {
     pub rules: String,
     pub model: String,
     pub layout: String,
     pub variant: String,
+    /// Comma separated xkb options, e.g. `caps:escape,grp:alt_shift_toggle`
     pub options: Option<String>,
+    /// Key used to start compose sequences, e.g. `ralt`, added to the options as `compose:<key>`
+    pub compose_key: Option<String>,
     #[serde(default = "default_repeat_delay")]
     pub repeat_delay: u32,
     #[serde(default = "default_repeat_rate")]
@@ -83,6 +846,7 @@ impl Default for XkbConfig {
             layout: String::new(),
             variant: String::new(),
             options: None,
+            compose_key: None,
             repeat_delay: default_repeat_delay(),
             repeat_rate: default_repeat_rate(),
         }
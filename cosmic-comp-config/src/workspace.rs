@@ -22,6 +22,20 @@ impl Default for WorkspaceConfig {
     }
 }
 
+/// Settings of a workspace overriding the global ones, applied when the workspace is created
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceOverride {
+    /// Whether windows are tiled, instead of following `autotile`
+    #[serde(default)]
+    pub tiling: Option<bool>,
+    /// Outer and inner gaps between tiled windows, instead of the ones of the theme
+    #[serde(default)]
+    pub gaps: Option<(u32, u32)>,
+    /// Whether unfocused windows are dimmed, `false` disables `dim_unfocused`
+    #[serde(default)]
+    pub dim_unfocused: Option<bool>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum WorkspaceMode {
     OutputBound,
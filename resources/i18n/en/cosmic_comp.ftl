@@ -20,4 +20,7 @@ window-menu-close-all = Close all windows
 window-menu-resize-edge-top = Top
 window-menu-resize-edge-left = Left
 window-menu-resize-edge-right = Right
-window-menu-resize-edge-bottom = Bottom
\ No newline at end of file
+window-menu-resize-edge-bottom = Bottomlock-fallback-title = Locked
+lock-fallback-prompt = The lock screen stopped working, enter your password to unlock
+lock-fallback-checking = Checking…
+lock-fallback-failed = Authentication failed, please try again
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use crate::{
-    config::{OutputConfig, OutputState},
+    config::{MonitorSerial, OutputConfig, OutputState},
     shell::Shell,
     utils::prelude::*,
 };
@@ -18,7 +18,7 @@ use smithay::{
     output::{Mode as OutputMode, Output, PhysicalProperties, Scale, Subpixel},
     reexports::{
         calloop::{LoopHandle, RegistrationToken},
-        drm::control::{connector, crtc, Device as ControlDevice, ModeTypeFlags},
+        drm::control::{connector, crtc, Device as ControlDevice, Mode, ModeTypeFlags},
         rustix::fs::OFlags,
         wayland_server::{protocol::wl_buffer::WlBuffer, DisplayHandle, Weak},
     },
@@ -48,6 +48,8 @@ pub struct Device {
     pub dev_node: DrmNode,
     pub render_node: DrmNode,
     pub egl: Option<EGLInternals>,
+    /// EGL failed to initialize, so outputs of this device are composited by the cpu
+    pub software_rendering: bool,
 
     pub outputs: HashMap<connector::Handle, Output>,
     pub surfaces: HashMap<crtc::Handle, Surface>,
@@ -60,6 +62,8 @@ pub struct Device {
     pub leasing_global: Option<DrmLeaseState>,
     pub active_leases: Vec<DrmLease>,
     pub active_buffers: HashSet<Weak<WlBuffer>>,
+    /// ICC profile and night light temperature last loaded into the gamma ramp of each crtc
+    pub gamma: HashMap<crtc::Handle, (Option<PathBuf>, Option<u32>)>,
 
     event_token: Option<RegistrationToken>,
     pub socket: Option<Socket>,
@@ -75,6 +79,7 @@ impl fmt::Debug for Device {
             .field("drm", &self.drm)
             .field("gbm", &self.gbm)
             .field("egl", &self.egl)
+            .field("software_rendering", &self.software_rendering)
             .field("supports_atomic", &self.supports_atomic)
             .field("leased_connectors", &self.leased_connectors)
             .field("leasing_global", &self.leasing_global)
@@ -145,18 +150,26 @@ impl State {
 
         let gbm = GbmDevice::new(fd)
             .with_context(|| format!("Failed to initialize GBM device for {}", path.display()))?;
-        let (render_node, render_formats) = {
-            let egl = init_egl(&gbm)?;
-
-            let render_node = egl
-                .device
-                .try_get_render_node()
-                .ok()
-                .and_then(std::convert::identity)
-                .unwrap_or(drm_node);
-            let render_formats = egl.context.dmabuf_texture_formats().clone();
-
-            (render_node, render_formats)
+        let (render_node, render_formats) = match init_egl(&gbm) {
+            Ok(egl) => {
+                let render_node = egl
+                    .device
+                    .try_get_render_node()
+                    .ok()
+                    .and_then(std::convert::identity)
+                    .unwrap_or(drm_node);
+                let render_formats = egl.context.dmabuf_texture_formats().clone();
+
+                (render_node, Some(render_formats))
+            }
+            Err(err) => {
+                warn!(
+                    ?err,
+                    "Failed to initialize EGL for {}, falling back to software rendering.",
+                    path.display()
+                );
+                (drm_node, None)
+            }
         };
 
         let token = self
@@ -179,21 +192,26 @@ impl State {
             )
             .with_context(|| format!("Failed to add drm device to event loop: {}", dev))?;
 
-        let socket = match self.create_socket(dh, render_node, render_formats.clone()) {
-            Ok(socket) => Some(socket),
-            Err(err) => {
-                warn!(
-                    ?err,
-                    "Failed to initialize hardware-acceleration for clients on {}.", render_node,
-                );
-                None
+        // clients can't use a gpu without GL either
+        let socket = render_formats.as_ref().and_then(|render_formats| {
+            match self.create_socket(dh, render_node, render_formats.clone()) {
+                Ok(socket) => Some(socket),
+                Err(err) => {
+                    warn!(
+                        ?err,
+                        "Failed to initialize hardware-acceleration for clients on {}.",
+                        render_node,
+                    );
+                    None
+                }
             }
-        };
+        });
 
         let mut device = Device {
             dev_node: drm_node,
             render_node,
             egl: None,
+            software_rendering: render_formats.is_none(),
 
             outputs: HashMap::new(),
             surfaces: HashMap::new(),
@@ -214,6 +232,7 @@ impl State {
                 })
                 .ok(),
             active_leases: Vec::new(),
+            gamma: HashMap::new(),
             active_buffers: HashSet::new(),
 
             event_token: Some(token),
@@ -225,9 +244,11 @@ impl State {
         let mut w = self.common.shell.read().unwrap().global_space().size.w as u32;
 
         {
+            let backend = self.backend.kms();
             for (conn, maybe_crtc) in connectors {
                 match device.connector_added(
-                    self.backend.kms().primary_node.as_ref(),
+                    backend.primary_node.as_ref(),
+                    &backend.custom_modes,
                     conn,
                     maybe_crtc,
                     (w, 0),
@@ -237,7 +258,7 @@ impl State {
                 ) {
                     Ok((output, should_expose)) => {
                         if should_expose {
-                            w += output.config().mode_size().w as u32;
+                            w += output.config().transformed_size().w as u32;
                             wl_outputs.push(output.clone());
                         }
                         device.outputs.insert(conn, output);
@@ -253,6 +274,9 @@ impl State {
             self.backend.kms().drm_devices.insert(drm_node, device);
         }
 
+        self.backend
+            .kms()
+            .update_render_devices(&self.common.config.cosmic_conf.gpu)?;
         self.backend.kms().refresh_used_devices()?;
 
         self.common
@@ -326,6 +350,7 @@ impl State {
                 for (conn, maybe_crtc) in changes.added {
                     match device.connector_added(
                         backend.primary_node.as_ref(),
+                        &backend.custom_modes,
                         conn,
                         maybe_crtc,
                         (w, 0),
@@ -335,7 +360,7 @@ impl State {
                     ) {
                         Ok((output, should_expose)) => {
                             if should_expose {
-                                w += output.config().mode_size().w as u32;
+                                w += output.config().transformed_size().w as u32;
                                 outputs_added.push(output.clone());
                             }
 
@@ -349,6 +374,9 @@ impl State {
             }
         }
 
+        self.backend
+            .kms()
+            .update_render_devices(&self.common.config.cosmic_conf.gpu)?;
         self.backend.kms().refresh_used_devices()?;
 
         self.common
@@ -382,6 +410,7 @@ impl State {
     pub fn device_removed(&mut self, dev: dev_t, dh: &DisplayHandle) -> Result<()> {
         let drm_node = DrmNode::from_dev_id(dev)?;
         let mut outputs_removed = Vec::new();
+        let mut removed_render_node = None;
         let backend = self.backend.kms();
         if let Some(mut device) = backend.drm_devices.remove(&drm_node) {
             if let Some(mut leasing_global) = device.leasing_global.take() {
@@ -400,12 +429,35 @@ impl State {
                     .destroy_global::<State>(dh, socket.dmabuf_global);
                 dh.remove_global::<State>(socket.drm_global);
             }
+            if device.egl.take().is_some() {
+                backend.api.as_mut().remove_node(&device.render_node);
+            }
+            removed_render_node = Some(device.render_node);
         }
         self.common
             .output_configuration_state
             .remove_heads(outputs_removed.iter());
 
         if self.backend.kms().session.is_active() {
+            let backend = self.backend.kms();
+            // the removed device might have been the primary gpu
+            if let Err(err) = backend.update_render_devices(&self.common.config.cosmic_conf.gpu) {
+                warn!(
+                    ?err,
+                    "Failed to update render devices after removing {}", drm_node
+                );
+            }
+            // other outputs might have rendered on the removed device
+            if let Err(err) = backend.refresh_used_devices() {
+                warn!(
+                    ?err,
+                    "Failed to refresh used devices after removing {}", drm_node
+                );
+            }
+            if let Some(render_node) = removed_render_node {
+                let dmabufs = self.common.shell.read().unwrap().client_dmabufs();
+                backend.reimport_dmabufs(render_node, dmabufs.into_iter());
+            }
             for output in outputs_removed {
                 self.common.remove_output(&output);
             }
@@ -474,6 +526,7 @@ impl Device {
     pub fn connector_added(
         &mut self,
         primary_node: Option<&DrmNode>,
+        custom_modes: &HashMap<String, Vec<Mode>>,
         conn: connector::Handle,
         maybe_crtc: Option<crtc::Handle>,
         position: (u32, u32),
@@ -528,7 +581,11 @@ impl Device {
                 .user_data()
                 .insert_if_missing(|| RefCell::new(OutputConfig::default()));
 
-            populate_modes(&mut self.drm, &output, conn, position)
+            let custom_modes = custom_modes
+                .get(&output.name())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            populate_modes(&mut self.drm, &output, conn, custom_modes, position)
                 .with_context(|| "Failed to enumerate connector modes")?;
 
             let has_surface = if let Some(crtc) = maybe_crtc {
@@ -584,7 +641,7 @@ fn create_output_for_conn(drm: &mut DrmDevice, conn: connector::Handle) -> Resul
     let edid_info = drm_helpers::edid_info(drm, conn);
     let (phys_w, phys_h) = conn_info.size().unwrap_or((0, 0));
 
-    Ok(Output::new(
+    let output = Output::new(
         interface,
         PhysicalProperties {
             size: (phys_w as i32, phys_h as i32).into(),
@@ -605,23 +662,35 @@ fn create_output_for_conn(drm: &mut DrmDevice, conn: connector::Handle) -> Resul
                 .map(|info| info.model.clone())
                 .unwrap_or_else(|_| String::from("Unknown")),
         },
-    ))
+    );
+    if let Some(serial) = edid_info.ok().and_then(|info| info.serial) {
+        output
+            .user_data()
+            .insert_if_missing(|| MonitorSerial(serial));
+    }
+    Ok(output)
 }
 
 fn populate_modes(
     drm: &mut DrmDevice,
     output: &Output,
     conn: connector::Handle,
+    custom_modes: &[Mode],
     position: (u32, u32),
 ) -> Result<()> {
     let conn_info = drm.get_connector(conn, false)?;
     let max_bpc = drm_helpers::get_max_bpc(drm, conn)?.map(|(_val, range)| range.end.min(16));
-    let Some(mode) = conn_info
-        .modes()
-        .iter()
-        .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+    // custom modes are meant to fix broken EDIDs, so prefer them over the advertised modes
+    let Some(mode) = custom_modes
+        .first()
+        .or_else(|| {
+            conn_info
+                .modes()
+                .iter()
+                .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+        })
+        .or(conn_info.modes().first())
         .copied()
-        .or(conn_info.modes().get(0).copied())
     else {
         anyhow::bail!("No mode found");
     };
@@ -631,24 +700,16 @@ fn populate_modes(
         .unwrap_or(1.0);
 
     let refresh_rate = drm_helpers::calculate_refresh_rate(mode);
-    let output_mode = OutputMode {
-        size: (mode.size().0 as i32, mode.size().1 as i32).into(),
-        refresh: refresh_rate as i32,
-    };
+    let output_mode = output_mode(mode);
+    let transform = drm_helpers::panel_orientation(drm, conn).unwrap_or(Transform::Normal);
 
-    for mode in conn_info.modes() {
-        let refresh_rate = drm_helpers::calculate_refresh_rate(*mode);
-        let mode = OutputMode {
-            size: (mode.size().0 as i32, mode.size().1 as i32).into(),
-            refresh: refresh_rate as i32,
-        };
-        output.add_mode(mode);
+    for mode in conn_info.modes().iter().chain(custom_modes) {
+        output.add_mode(output_mode(*mode));
     }
     output.set_preferred(output_mode);
     output.change_current_state(
         Some(output_mode),
-        // TODO: Readout property for monitor rotation
-        Some(Transform::Normal),
+        Some(transform),
         Some(Scale::Fractional(scale)),
         Some(Point::from((position.0 as i32, position.1 as i32))),
     );
@@ -663,12 +724,20 @@ fn populate_modes(
         position,
         max_bpc,
         scale,
+        transform,
         ..std::mem::take(&mut *output_config)
     };
 
     Ok(())
 }
 
+pub fn output_mode(mode: Mode) -> OutputMode {
+    OutputMode {
+        size: (mode.size().0 as i32, mode.size().1 as i32).into(),
+        refresh: drm_helpers::calculate_refresh_rate(mode) as i32,
+    }
+}
+
 pub fn calculate_scale(
     interface: connector::Interface,
     monitor_size_mm: (u32, u32),
@@ -9,12 +9,15 @@ use smithay::reexports::drm::control::{
     property, AtomicCommitFlags, Device as ControlDevice, Mode, ModeFlags, PlaneType,
     ResourceHandle,
 };
+use smithay::utils::Transform;
 use std::{
     collections::HashMap,
     ops::Range,
     panic::{catch_unwind, AssertUnwindSafe},
 };
 
+use super::icc::{linear_ramp, whitepoint, Vcgt};
+
 pub fn display_configuration(
     device: &mut impl ControlDevice,
     supports_atomic: bool,
@@ -169,6 +172,8 @@ pub fn interface_name(device: &impl ControlDevice, connector: connector::Handle)
 pub struct EdidInfo {
     pub model: String,
     pub manufacturer: String,
+    /// Serial number, telling apart monitors of the same model
+    pub serial: Option<String>,
 }
 
 pub fn edid_info(device: &impl ControlDevice, connector: connector::Handle) -> Result<EdidInfo> {
@@ -178,6 +183,7 @@ pub fn edid_info(device: &impl ControlDevice, connector: connector::Handle) -> R
     let edid_info = device.get_property(edid_prop)?;
     let mut manufacturer = "Unknown".into();
     let mut model = "Unknown".into();
+    let mut serial = None;
     let props = device.get_properties(connector)?;
     let (ids, vals) = props.as_props_and_values();
     for (&id, &val) in ids.iter().zip(vals.iter()) {
@@ -209,6 +215,21 @@ pub fn edid_info(device: &impl ControlDevice, connector: connector::Handle) -> R
                     } else {
                         format!("{}", edid.product.product_code)
                     };
+                    serial = edid
+                        .descriptors
+                        .0
+                        .iter()
+                        .find_map(|x| match x {
+                            MonitorDescriptor::SerialNumber(serial) => {
+                                Some(serial.trim_end_matches('\0').trim().to_string())
+                            }
+                            _ => None,
+                        })
+                        .filter(|serial| !serial.is_empty())
+                        .or_else(|| {
+                            (edid.product.serial_number != 0)
+                                .then(|| edid.product.serial_number.to_string())
+                        });
                 }
             }
             break;
@@ -218,6 +239,7 @@ pub fn edid_info(device: &impl ControlDevice, connector: connector::Handle) -> R
     Ok(EdidInfo {
         model,
         manufacturer,
+        serial,
     })
 }
 
@@ -346,6 +368,21 @@ pub fn calculate_refresh_rate(mode: Mode) -> u32 {
     refresh as u32
 }
 
+/// Returns the transform needed to display content upright on a panel mounted rotated,
+/// e.g. in tablets and handhelds.
+pub fn panel_orientation(dev: &impl ControlDevice, conn: connector::Handle) -> Result<Transform> {
+    let (val_type, val) = get_property_val(dev, conn, "panel orientation")?;
+    match val_type.convert_value(val) {
+        property::Value::Enum(Some(val)) => Ok(match val.name().to_str()? {
+            "Upside Down" => Transform::_180,
+            "Left Side Up" => Transform::_90,
+            "Right Side Up" => Transform::_270,
+            _ => Transform::Normal,
+        }),
+        _ => Ok(Transform::Normal),
+    }
+}
+
 pub fn supports_vrr(dev: &impl ControlDevice, conn: connector::Handle) -> Result<bool> {
     get_property_val(dev, conn, "vrr_capable").map(|(val_type, val)| {
         match val_type.convert_value(val) {
@@ -356,6 +393,38 @@ pub fn supports_vrr(dev: &impl ControlDevice, conn: connector::Handle) -> Result
     })
 }
 
+/// Loads the calibration curves into the gamma ramp of `crtc`, or resets it if `None`,
+/// shifting the white point to the `night_light` color temperature in kelvin
+pub fn set_gamma(
+    dev: &impl ControlDevice,
+    crtc: crtc::Handle,
+    vcgt: Option<&Vcgt>,
+    night_light: Option<u32>,
+) -> Result<()> {
+    let size = dev.get_crtc(crtc)?.gamma_length() as usize;
+    if size == 0 {
+        return match (vcgt, night_light) {
+            (None, None) => Ok(()),
+            _ => Err(anyhow!("crtc has no gamma ramp")),
+        };
+    }
+
+    let mut ramp = match vcgt {
+        Some(vcgt) => vcgt.ramp(size),
+        None => linear_ramp(size),
+    };
+    if let Some(temperature) = night_light {
+        for (channel, factor) in ramp.iter_mut().zip(whitepoint(temperature)) {
+            for value in channel.iter_mut() {
+                *value = (*value as f64 * factor).round() as u16;
+            }
+        }
+    }
+    let [red, green, blue] = ramp;
+    dev.set_gamma(crtc, &red, &green, &blue)?;
+    Ok(())
+}
+
 pub fn set_vrr(
     dev: &impl ControlDevice,
     crtc: crtc::Handle,
@@ -363,23 +432,28 @@ pub fn set_vrr(
     vrr: bool,
 ) -> Result<bool> {
     if supports_vrr(dev, conn)? {
-        dev.set_property(
-            conn,
-            get_prop(dev, crtc, "VRR_ENABLED")?,
-            property::Value::UnsignedRange(if vrr { 1 } else { 0 }).into(),
-        )
-        .map_err(Into::<anyhow::Error>::into)
-        .and_then(|_| get_property_val(dev, crtc, "VRR_ENABLED"))
-        .map(|(val_type, val)| match val_type.convert_value(val) {
-            property::Value::UnsignedRange(vrr) => vrr == 1,
-            property::Value::Boolean(vrr) => vrr,
-            _ => false,
-        })
+        set_crtc_vrr(dev, crtc, vrr)
     } else {
         Ok(false)
     }
 }
 
+/// Sets the `VRR_ENABLED` property of `crtc` without checking the connectors for support
+pub fn set_crtc_vrr(dev: &impl ControlDevice, crtc: crtc::Handle, vrr: bool) -> Result<bool> {
+    dev.set_property(
+        crtc,
+        get_prop(dev, crtc, "VRR_ENABLED")?,
+        property::Value::UnsignedRange(if vrr { 1 } else { 0 }).into(),
+    )
+    .map_err(Into::<anyhow::Error>::into)
+    .and_then(|_| get_property_val(dev, crtc, "VRR_ENABLED"))
+    .map(|(val_type, val)| match val_type.convert_value(val) {
+        property::Value::UnsignedRange(vrr) => vrr == 1,
+        property::Value::Boolean(vrr) => vrr,
+        _ => false,
+    })
+}
+
 pub fn get_max_bpc(
     dev: &impl ControlDevice,
     conn: connector::Handle,
@@ -419,3 +493,18 @@ pub fn set_max_bpc(dev: &impl ControlDevice, conn: connector::Handle, bpc: u32)
         _ => unreachable!(),
     })
 }
+
+/// Resets HDR metadata and colorspace of a connector to their SDR defaults.
+///
+/// We only composite SDR content, but a previous drm master (e.g. a game running in
+/// a nested session) might have left the connector in a HDR mode.
+pub fn reset_hdr(dev: &impl ControlDevice, conn: connector::Handle) -> Result<()> {
+    if let Ok(prop) = get_prop(dev, conn, "HDR_OUTPUT_METADATA") {
+        dev.set_property(conn, prop, property::Value::Blob(0).into())?;
+    }
+    if let Ok(prop) = get_prop(dev, conn, "Colorspace") {
+        // 0 is always the `Default` colorspace
+        dev.set_property(conn, prop, 0)?;
+    }
+    Ok(())
+}
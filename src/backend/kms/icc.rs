@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Calibration curves of ICC profiles.
+//!
+//! Only the `vcgt` (video card gamma table) tag is supported, which is what calibration
+//! tools use to store the per-channel curves meant to be loaded into the gamma ramp
+//! of the gpu. Color transforms of the profile itself are left to color-managed clients.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+
+const HEADER_SIZE: usize = 128;
+const VCGT_SIGNATURE: &[u8; 4] = b"vcgt";
+const VCGT_TYPE_TABLE: u32 = 0;
+const VCGT_TYPE_FORMULA: u32 = 1;
+/// Resolution formula based curves are sampled at
+const FORMULA_SAMPLES: usize = 1024;
+
+/// Per channel calibration curves, normalized to `0.0..=1.0`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vcgt {
+    curves: [Vec<f64>; 3],
+}
+
+impl Vcgt {
+    /// Reads the calibration curves of the ICC profile at `path`, `None` if it has none
+    pub fn from_profile(path: &Path) -> Result<Option<Vcgt>> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read ICC profile {}", path.display()))?;
+        Vcgt::parse(&data)
+            .with_context(|| format!("Failed to parse ICC profile {}", path.display()))
+    }
+
+    fn parse(data: &[u8]) -> Result<Option<Vcgt>> {
+        let tag_count = read_u32(data, HEADER_SIZE)? as usize;
+        let Some(tag) = (0..tag_count)
+            .map(|i| HEADER_SIZE + 4 + i * 12)
+            .take_while(|entry| entry + 12 <= data.len())
+            .find(|entry| data.get(*entry..*entry + 4) == Some(&VCGT_SIGNATURE[..]))
+        else {
+            return Ok(None);
+        };
+        let offset = read_u32(data, tag + 4)? as usize;
+        let size = read_u32(data, tag + 8)? as usize;
+        let tag = data
+            .get(offset..offset.saturating_add(size))
+            .ok_or(anyhow!("vcgt tag out of bounds"))?;
+
+        if tag.get(0..4) != Some(&VCGT_SIGNATURE[..]) {
+            return Err(anyhow!("Invalid vcgt tag type"));
+        }
+        let curves = match read_u32(tag, 8)? {
+            VCGT_TYPE_TABLE => {
+                let channels = read_u16(tag, 12)? as usize;
+                let entries = read_u16(tag, 14)? as usize;
+                let entry_size = read_u16(tag, 16)? as usize;
+                if !matches!(channels, 1 | 3) || entries < 2 || !matches!(entry_size, 1 | 2) {
+                    return Err(anyhow!("Unsupported vcgt table layout"));
+                }
+
+                let read_curve = |channel: usize| {
+                    (0..entries)
+                        .map(|i| {
+                            let pos = 18 + (channel * entries + i) * entry_size;
+                            Ok(match entry_size {
+                                1 => {
+                                    *tag.get(pos).ok_or(anyhow!("vcgt table truncated"))? as f64
+                                        / u8::MAX as f64
+                                }
+                                _ => read_u16(tag, pos)? as f64 / u16::MAX as f64,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()
+                };
+                if channels == 1 {
+                    let curve = read_curve(0)?;
+                    [curve.clone(), curve.clone(), curve]
+                } else {
+                    [read_curve(0)?, read_curve(1)?, read_curve(2)?]
+                }
+            }
+            VCGT_TYPE_FORMULA => {
+                let read_curve = |channel: usize| -> Result<Vec<f64>> {
+                    let param = |i: usize| read_s15_fixed16(tag, 12 + (channel * 3 + i) * 4);
+                    let (gamma, min, max) = (param(0)?, param(1)?, param(2)?);
+                    Ok((0..FORMULA_SAMPLES)
+                        .map(|i| {
+                            let x = i as f64 / (FORMULA_SAMPLES - 1) as f64;
+                            min + (max - min) * x.powf(gamma)
+                        })
+                        .collect::<Vec<_>>())
+                };
+                [read_curve(0)?, read_curve(1)?, read_curve(2)?]
+            }
+            ty => return Err(anyhow!("Unknown vcgt type {}", ty)),
+        };
+
+        Ok(Some(Vcgt { curves }))
+    }
+
+    /// Resamples the curves to a gamma ramp with `size` entries per channel
+    pub fn ramp(&self, size: usize) -> [Vec<u16>; 3] {
+        self.curves.clone().map(|curve| {
+            (0..size)
+                .map(|i| {
+                    let pos = i as f64 / (size.max(2) - 1) as f64 * (curve.len() - 1) as f64;
+                    let (idx, fract) = (pos.floor() as usize, pos.fract());
+                    let lower = curve[idx];
+                    let upper = curve[(idx + 1).min(curve.len() - 1)];
+                    let value = lower + (upper - lower) * fract;
+                    (value.clamp(0.0, 1.0) * u16::MAX as f64).round() as u16
+                })
+                .collect()
+        })
+    }
+}
+
+/// Identity gamma ramp with `size` entries per channel
+pub fn linear_ramp(size: usize) -> [Vec<u16>; 3] {
+    let ramp = (0..size)
+        .map(|i| (i as u64 * u16::MAX as u64 / (size.max(2) - 1) as u64) as u16)
+        .collect::<Vec<_>>();
+    [ramp.clone(), ramp.clone(), ramp]
+}
+
+/// Intensity of the color channels of a black body at `temperature` kelvin relative to white,
+/// approximated after Tanner Helland
+pub fn whitepoint(temperature: u32) -> [f64; 3] {
+    let t = temperature.clamp(1000, 6500) as f64 / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (t - 60.0).powf(-0.1332047592)
+    };
+    let green = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+    [red, green, blue].map(|channel| channel.clamp(0.0, 255.0) / 255.0)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
+    data.get(pos..pos + 4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or(anyhow!("ICC profile truncated"))
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16> {
+    data.get(pos..pos + 2)
+        .map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or(anyhow!("ICC profile truncated"))
+}
+
+fn read_s15_fixed16(data: &[u8], pos: usize) -> Result<f64> {
+    read_u32(data, pos).map(|val| val as i32 as f64 / 65536.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Minimal profile with just the given tag
+    fn profile(signature: &[u8; 4], tag: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data.extend(1u32.to_be_bytes());
+        data.extend(signature);
+        data.extend((HEADER_SIZE as u32 + 16).to_be_bytes());
+        data.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        data
+    }
+
+    fn vcgt_tag(ty: u32, body: &[u8]) -> Vec<u8> {
+        let mut tag = VCGT_SIGNATURE.to_vec();
+        tag.extend([0; 4]);
+        tag.extend(ty.to_be_bytes());
+        tag.extend(body);
+        tag
+    }
+
+    #[test]
+    fn test_vcgt_table() {
+        let mut body = Vec::new();
+        body.extend(3u16.to_be_bytes());
+        body.extend(2u16.to_be_bytes());
+        body.extend(2u16.to_be_bytes());
+        for value in [0u16, 65535, 0, 32768, 65535, 0] {
+            body.extend(value.to_be_bytes());
+        }
+        let vcgt = Vcgt::parse(&profile(VCGT_SIGNATURE, &vcgt_tag(VCGT_TYPE_TABLE, &body)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            vcgt.ramp(3),
+            [
+                vec![0, 32768, 65535],
+                vec![0, 16384, 32768],
+                vec![65535, 32768, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vcgt_single_channel_table() {
+        let mut body = Vec::new();
+        body.extend(1u16.to_be_bytes());
+        body.extend(2u16.to_be_bytes());
+        body.extend(1u16.to_be_bytes());
+        body.extend([255, 0]);
+        let vcgt = Vcgt::parse(&profile(VCGT_SIGNATURE, &vcgt_tag(VCGT_TYPE_TABLE, &body)))
+            .unwrap()
+            .unwrap();
+        let inverted = vec![65535, 0];
+        assert_eq!(vcgt.ramp(2), [inverted.clone(), inverted.clone(), inverted]);
+    }
+
+    #[test]
+    fn test_vcgt_formula() {
+        let mut body = Vec::new();
+        for _ in 0..3 {
+            // gamma 2.0, min 0.0, max 1.0
+            for param in [2 << 16, 0, 1 << 16] {
+                body.extend((param as u32).to_be_bytes());
+            }
+        }
+        let vcgt = Vcgt::parse(&profile(
+            VCGT_SIGNATURE,
+            &vcgt_tag(VCGT_TYPE_FORMULA, &body),
+        ))
+        .unwrap()
+        .unwrap();
+        for channel in vcgt.ramp(3) {
+            assert_eq!(channel[0], 0);
+            assert!((channel[1] as i32 - 16384).abs() < 64);
+            assert_eq!(channel[2], 65535);
+        }
+    }
+
+    #[test]
+    fn test_no_vcgt() {
+        assert_eq!(Vcgt::parse(&profile(b"desc", &[0; 16])).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_vcgt() {
+        // truncated header
+        assert!(Vcgt::parse(&[0; 64]).is_err());
+        // unknown type
+        let data = profile(VCGT_SIGNATURE, &vcgt_tag(2, &[0; 18]));
+        assert!(Vcgt::parse(&data).is_err());
+        // table data missing
+        let mut body = Vec::new();
+        body.extend(3u16.to_be_bytes());
+        body.extend(256u16.to_be_bytes());
+        body.extend(2u16.to_be_bytes());
+        let data = profile(VCGT_SIGNATURE, &vcgt_tag(VCGT_TYPE_TABLE, &body));
+        assert!(Vcgt::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_linear_ramp() {
+        let [red, green, blue] = linear_ramp(256);
+        assert_eq!(red.len(), 256);
+        assert_eq!((red[0], red[255]), (0, 65535));
+        assert_eq!(red[1], 257);
+        assert_eq!(red, green);
+        assert_eq!(red, blue);
+    }
+
+    #[test]
+    fn test_whitepoint() {
+        assert_eq!(whitepoint(6500)[0], 1.0);
+        // warmer light has less blue
+        let warm = whitepoint(3000);
+        assert_eq!(warm[0], 1.0);
+        assert!(warm[2] < whitepoint(6500)[2]);
+        assert!(warm[1] > warm[2]);
+        // clamped to the supported range
+        assert_eq!(whitepoint(100), whitepoint(1000));
+        assert_eq!(whitepoint(20000), whitepoint(6500));
+    }
+}
@@ -1,9 +1,17 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::{config::OutputState, shell::Shell, state::BackendData, utils::prelude::*};
+use crate::{
+    config::OutputState,
+    dbus::logind::LogindState,
+    ipc::protocol::OutputMetrics,
+    shell::Shell,
+    state::BackendData,
+    utils::{prelude::*, recover},
+};
 
 use anyhow::{Context, Result};
 use calloop::LoopSignal;
+use cosmic_comp_config::{CustomMode, GpuConfig};
 use render::gles::GbmGlowBackend;
 use smithay::{
     backend::{
@@ -11,7 +19,7 @@ use smithay::{
             dmabuf::Dmabuf,
             gbm::{GbmAllocator, GbmBufferFlags},
         },
-        drm::{DrmDeviceFd, DrmNode, NodeType},
+        drm::{DrmDevice, DrmDeviceFd, DrmNode, NodeType},
         egl::{context::ContextPriority, EGLContext, EGLDevice, EGLDisplay},
         input::InputEvent,
         libinput::{LibinputInputBackend, LibinputSessionInterface},
@@ -22,29 +30,32 @@ use smithay::{
     output::Output,
     reexports::{
         calloop::{Dispatcher, EventLoop, LoopHandle},
-        drm::control::{crtc, Device as _},
+        drm::control::{crtc, Device as _, Mode},
         input::{self, Libinput},
         wayland_server::{Client, DisplayHandle},
     },
     utils::{DevPath, Size},
     wayland::{dmabuf::DmabufGlobal, relative_pointer::RelativePointerManagerState},
 };
-use tracing::{error, info, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 
 use std::{
     borrow::BorrowMut,
     collections::{HashMap, HashSet},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{atomic::AtomicBool, Arc, RwLock},
 };
 
 mod device;
 mod drm_helpers;
+mod icc;
+mod modeline;
 pub mod render;
 mod socket;
 mod surface;
 
 use device::*;
+use icc::Vcgt;
 pub use surface::Timings;
 
 use super::render::init_shaders;
@@ -57,6 +68,11 @@ pub struct KmsState {
     // Mesa llvmpipe renderer, if supported and there are no render nodes
     pub software_renderer: Option<GlowRenderer>,
     pub api: GpuManager<GbmGlowBackend<DrmDeviceFd>>,
+    /// Modes added by the user by connector name
+    pub custom_modes: HashMap<String, Vec<Mode>>,
+    /// Color temperature in kelvin the gamma ramps of all outputs are shifted to
+    pub night_light: Option<u32>,
+    pub logind: Option<LogindState>,
 
     session: LibSeatSession,
     libinput: Libinput,
@@ -75,7 +91,7 @@ pub fn init_backend(
         .context("Failed to initialize libinput backend")?;
 
     // get our primary gpu
-    let primary = determine_primary_gpu(session.seat());
+    let primary = determine_primary_gpu(session.seat(), &state.common.config.cosmic_conf.gpu);
     if let Some(primary) = primary.as_ref() {
         info!("Using {} as primary gpu for rendering.", primary);
     }
@@ -123,6 +139,11 @@ pub fn init_backend(
         primary_node: primary,
         software_renderer,
         api: GpuManager::new(GbmGlowBackend::new()).context("Failed to initialize gpu backend")?,
+        custom_modes: modeline::custom_modes(&state.common.config.cosmic_conf.custom_modes),
+        night_light: None,
+        logind: crate::dbus::logind::init(&event_loop.handle())
+            .map_err(|err| warn!(?err, "Failed to connect to logind"))
+            .ok(),
 
         session,
         libinput: libinput_context,
@@ -165,10 +186,16 @@ fn init_libinput(
             state.backend.kms().input_devices.remove(device.name());
         }
 
-        state.process_input_event(event, true);
+        if recover::catch_panic("handling an input event", || {
+            state.process_input_event(event, true)
+        })
+        .is_none()
+        {
+            recover::check_poison(&state.common.shell);
+        }
 
         for output in state.common.shell.read().unwrap().outputs() {
-            state.backend.kms().schedule_render(output);
+            state.backend.kms().schedule_cursor_render(output);
         }
     })
     .map_err(|err| err.error)
@@ -180,12 +207,19 @@ fn init_libinput(
     Ok(libinput_context)
 }
 
-fn determine_primary_gpu(seat: String) -> Option<DrmNode> {
+fn determine_primary_gpu(seat: String, config: &GpuConfig) -> Option<DrmNode> {
     if let Some(node) = std::env::var("COSMIC_RENDER_DEVICE")
         .ok()
         .and_then(|x| DrmNode::from_path(x).ok())
     {
         Some(node)
+    } else if let Some(node) = config
+        .primary_device
+        .as_ref()
+        .and_then(|x| DrmNode::from_path(x).ok())
+        .and_then(|x| x.node_with_type(NodeType::Render).and_then(Result::ok))
+    {
+        Some(node)
     } else {
         let primary_node = primary_gpu(&seat)
             .ok()
@@ -279,6 +313,8 @@ impl State {
             if let Some(lease_state) = device.leasing_global.as_mut() {
                 lease_state.resume::<State>();
             }
+            // other drm masters may have changed the gamma ramps meanwhile
+            device.gamma.clear();
         }
 
         // update state and schedule new render,
@@ -322,6 +358,8 @@ impl State {
     }
 
     fn pause_session(&mut self) {
+        self.cancel_key_repetition();
+
         let backend = self.backend.kms();
         backend.libinput.suspend();
         for device in backend.drm_devices.values_mut() {
@@ -358,44 +396,19 @@ impl KmsState {
                         .unwrap_or(false)
                 });
 
-        let mut last_err = anyhow::anyhow!("Dmabuf cannot be imported on any gpu");
-        for device in expected_node.into_iter().chain(other_nodes.into_iter()) {
-            let mut _egl = None;
-            let egl_display = if let Some(egl_display) =
-                device.egl.as_ref().map(|internals| &internals.display)
-            {
-                egl_display
-            } else {
-                _egl = Some(init_egl(&device.gbm).context("Failed to initialize egl context")?);
-                &_egl.as_ref().unwrap().display
-            };
-
-            let result = egl_display
-                .create_image_from_dmabuf(&dmabuf)
-                .map(|image| {
-                    unsafe {
-                        smithay::backend::egl::ffi::egl::DestroyImageKHR(
-                            **egl_display.get_display_handle(),
-                            image,
-                        );
-                    };
-                    device.render_node
-                })
-                .map_err(Into::into);
+        import_dmabuf(expected_node.into_iter().chain(other_nodes), &dmabuf)
+    }
 
-            match result {
-                Ok(node) => {
-                    dmabuf.set_node(node); // so the MultiRenderer knows what node to use
-                    return Ok(node);
-                }
-                Err(err) => {
-                    trace!(?err, "Failed to import dmabuf on {:?}", device.render_node);
-                    last_err = err;
-                }
+    /// Moves client buffers, that were imported on the removed gpu `node`, to the remaining ones.
+    ///
+    /// Buffers, that live in the memory of the removed gpu, can't be recovered
+    /// and stay unrenderable until the client reallocates them.
+    pub fn reimport_dmabufs(&mut self, node: DrmNode, dmabufs: impl Iterator<Item = Dmabuf>) {
+        for dmabuf in dmabufs.filter(|dmabuf| dmabuf.node() == Some(node)) {
+            if let Err(err) = import_dmabuf(self.drm_devices.values_mut(), &dmabuf) {
+                debug!(?err, "Failed to move client buffer away from {}", node);
             }
         }
-
-        Err(last_err)
     }
 
     pub fn schedule_render(&mut self, output: &Output) {
@@ -409,6 +422,142 @@ impl KmsState {
         }
     }
 
+    /// Frame timings of all outputs driven by a surface, skipping surface threads that don't answer
+    pub fn output_metrics(&self) -> Vec<OutputMetrics> {
+        self.drm_devices
+            .values()
+            .flat_map(|d| d.surfaces.values())
+            .filter_map(|s| s.metrics())
+            .collect()
+    }
+
+    pub fn dpms(&self, output: &Output) -> Option<bool> {
+        self.drm_devices
+            .values()
+            .flat_map(|d| d.surfaces.values())
+            .find(|s| s.output == *output)
+            .map(|s| s.get_dpms())
+    }
+
+    pub fn set_dpms(&mut self, output: &Output, on: bool) {
+        for surface in self
+            .drm_devices
+            .values_mut()
+            .flat_map(|d| d.surfaces.values_mut())
+            .filter(|s| s.output == *output)
+        {
+            surface.set_dpms(on);
+        }
+    }
+
+    pub fn schedule_cursor_render(&mut self, output: &Output) {
+        for surface in self
+            .drm_devices
+            .values()
+            .flat_map(|d| d.surfaces.values())
+            .filter(|s| s.output == *output || s.output.mirroring().is_some_and(|o| &o == output))
+        {
+            surface.schedule_cursor_render();
+        }
+    }
+
+    /// Re-evaluates the primary gpu and which gpu renders each output,
+    /// e.g. after the config changed or gpus were hotplugged.
+    pub fn update_render_devices(&mut self, config: &GpuConfig) -> Result<()> {
+        let mut primary_node = determine_primary_gpu(self.session.seat(), config);
+        if primary_node.is_some_and(|node| {
+            !self
+                .drm_devices
+                .values()
+                .any(|device| device.render_node == node)
+        }) {
+            // the preferred gpu is gone (e.g. an unplugged eGPU), fall back to any remaining one
+            let fallback = self
+                .drm_devices
+                .values()
+                .filter(|device| !device.software_rendering)
+                .chain(self.drm_devices.values())
+                .map(|device| device.render_node)
+                .next();
+            if let Some(fallback) = fallback {
+                info!(
+                    "Primary gpu {} is not available, failing over to {}.",
+                    primary_node.unwrap(),
+                    fallback
+                );
+                primary_node = Some(fallback);
+            }
+        }
+        if primary_node != self.primary_node {
+            if let Some(node) = primary_node.as_ref() {
+                info!("Switching to {} as primary gpu for rendering.", node);
+            }
+            self.primary_node = primary_node;
+            self.refresh_used_devices()?;
+        }
+
+        for device in self.drm_devices.values_mut() {
+            let primary_node = self.primary_node.unwrap_or(device.render_node);
+            for surface in device.surfaces.values_mut() {
+                let policy = config.render_policy_for(&surface.output.name());
+                surface.set_render_devices(primary_node, policy);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the modes offered by outputs, after the custom modes in the config changed
+    pub fn update_custom_modes(&mut self, config: &HashMap<String, Vec<CustomMode>>) {
+        let custom_modes = modeline::custom_modes(config);
+
+        for device in self.drm_devices.values() {
+            for (conn, output) in device.outputs.iter() {
+                let Ok(conn_info) = device.drm.get_connector(*conn, false) else {
+                    continue;
+                };
+                let new_modes = conn_info
+                    .modes()
+                    .iter()
+                    .chain(custom_modes.get(&output.name()).into_iter().flatten())
+                    .map(|mode| output_mode(*mode))
+                    .collect::<Vec<_>>();
+                for mode in output.modes() {
+                    if !new_modes.contains(&mode) && output.current_mode() != Some(mode) {
+                        output.delete_mode(mode);
+                    }
+                }
+                for mode in new_modes {
+                    output.add_mode(mode);
+                }
+            }
+        }
+
+        self.custom_modes = custom_modes;
+    }
+
+    /// Shifts the colors of all outputs to `temperature` kelvin, or back to normal if `None`
+    pub fn set_night_light(&mut self, temperature: Option<u32>) {
+        self.night_light = temperature;
+        for device in self.drm_devices.values_mut() {
+            for (crtc, surface) in device.surfaces.iter() {
+                if let Err(err) = apply_gamma(
+                    &device.drm,
+                    &mut device.gamma,
+                    *crtc,
+                    &surface.output,
+                    temperature,
+                ) {
+                    warn!(
+                        ?err,
+                        "Failed to apply night light to {}",
+                        surface.output.name()
+                    );
+                }
+            }
+        }
+    }
+
     pub fn target_node_for_output(&self, output: &Output) -> Option<DrmNode> {
         self.drm_devices
             .values()
@@ -422,7 +571,7 @@ impl KmsState {
 
         for device in self.drm_devices.values_mut() {
             if device.in_use(self.primary_node.as_ref()) {
-                if device.egl.is_none() {
+                if device.egl.is_none() && !device.software_rendering {
                     let egl = init_egl(&device.gbm).context("Failed to create EGL context")?;
                     let mut renderer = unsafe {
                         GlowRenderer::new(
@@ -482,32 +631,30 @@ impl KmsState {
                 for new_device in used_devices.difference(&known_nodes) {
                     let (render_node, egl, gbm) = if node == *new_device {
                         // we need to make sure to do partial borrows here, as device.surfaces is borrowed mutable
-                        (
-                            device.render_node,
-                            device.egl.as_ref().unwrap(),
-                            device.gbm.clone(),
-                        )
+                        (device.render_node, device.egl.as_ref(), device.gbm.clone())
                     } else {
                         let device = others
                             .iter_mut()
                             .find(|d| d.render_node == *new_device)
                             .unwrap();
-                        (
-                            device.render_node,
-                            device.egl.as_ref().unwrap(),
-                            device.gbm.clone(),
-                        )
+                        (device.render_node, device.egl.as_ref(), device.gbm.clone())
                     };
 
+                    // devices without an egl context are rendered in software
+                    let egl = egl
+                        .map(|egl| {
+                            EGLContext::new_shared_with_priority(
+                                &egl.display,
+                                &egl.context,
+                                ContextPriority::High,
+                            )
+                            .context("Failed to create shared EGL context")
+                        })
+                        .transpose()?;
                     surface.add_node(
                         render_node,
                         GbmAllocator::new(gbm, GbmBufferFlags::RENDERING | GbmBufferFlags::SCANOUT),
-                        EGLContext::new_shared_with_priority(
-                            &egl.display,
-                            &egl.context,
-                            ContextPriority::High,
-                        )
-                        .context("Failed to create shared EGL context")?,
+                        egl,
                     );
                 }
             }
@@ -604,6 +751,9 @@ impl KmsState {
                         .surfaces
                         .retain(|_, surface| surface.output != *output);
                 }
+                device
+                    .gamma
+                    .retain(|crtc, _| device.surfaces.contains_key(crtc));
             }
 
             // reconfigure existing
@@ -613,9 +763,17 @@ impl KmsState {
                 let drm = &mut device.drm;
                 let conn = surface.connector;
                 let conn_info = drm.get_connector(conn, false)?;
+                // custom modes are validated like any other mode by the atomic test commit
+                // done when creating the compositor or by `set_mode`, before being applied.
                 let mode = conn_info
                     .modes()
                     .iter()
+                    .chain(
+                        self.custom_modes
+                            .get(&surface.output.name())
+                            .into_iter()
+                            .flatten(),
+                    )
                     // match the size
                     .filter(|mode| {
                         let (x, y) = mode.size();
@@ -629,6 +787,20 @@ impl KmsState {
                     .ok_or(anyhow::anyhow!("Unable to find matching mode"))?;
 
                 if !test_only {
+                    if let Err(err) = apply_gamma(
+                        drm,
+                        &mut device.gamma,
+                        *crtc,
+                        &surface.output,
+                        self.night_light,
+                    ) {
+                        warn!(
+                            ?err,
+                            "Failed to apply calibration curves to {}",
+                            surface.output.name()
+                        );
+                    }
+
                     if !surface.is_active() {
                         let drm_surface = drm
                             .create_surface(*crtc, *mode, &[conn])
@@ -640,6 +812,14 @@ impl KmsState {
                             .unwrap_or(false);
                         surface.output.set_adaptive_sync(vrr);
 
+                        if let Err(err) = drm_helpers::reset_hdr(drm, conn) {
+                            warn!(
+                                ?err,
+                                "Failed to reset hdr metadata on connector: {}",
+                                surface.output.name()
+                            );
+                        }
+
                         if let Some(bpc) = output_config.max_bpc {
                             if let Err(err) = drm_helpers::set_max_bpc(drm, conn, bpc) {
                                 warn!(
@@ -678,6 +858,7 @@ impl KmsState {
                 for (conn, crtc) in new_pairings {
                     let (output, _) = device.connector_added(
                         self.primary_node.as_ref(),
+                        &self.custom_modes,
                         conn,
                         Some(crtc),
                         (0, w),
@@ -686,7 +867,7 @@ impl KmsState {
                         startup_done.clone(),
                     )?;
                     if output.mirroring().is_none() {
-                        w += output.config().mode_size().w as u32;
+                        w += output.config().transformed_size().w as u32;
                     }
                     all_outputs.push(output);
                 }
@@ -722,3 +903,79 @@ impl KmsState {
         Ok(all_outputs)
     }
 }
+
+/// Calibration curves of the ICC profile configured for `output`
+fn load_vcgt(output: &Output) -> Option<Vcgt> {
+    // a missing or broken profile resets the output to an uncalibrated ramp
+    let path = output.config().icc_profile.clone()?;
+    Vcgt::from_profile(&path)
+        .inspect_err(|err| warn!(?err, "Failed to load ICC profile for {}", output.name()))
+        .ok()
+        .flatten()
+}
+
+/// Loads the calibration curves of `output` and the night light into the gamma ramp of `crtc`,
+/// unless they are loaded already
+fn apply_gamma(
+    drm: &DrmDevice,
+    applied: &mut HashMap<crtc::Handle, (Option<PathBuf>, Option<u32>)>,
+    crtc: crtc::Handle,
+    output: &Output,
+    night_light: Option<u32>,
+) -> Result<()> {
+    let gamma = (output.config().icc_profile.clone(), night_light);
+    if applied.get(&crtc) == Some(&gamma) {
+        return Ok(());
+    }
+    let vcgt = load_vcgt(output);
+    drm_helpers::set_gamma(drm, crtc, vcgt.as_ref(), night_light)?;
+    applied.insert(crtc, gamma);
+    Ok(())
+}
+
+/// Tests, which gpu can import `dmabuf`, starting with the first of `devices`
+fn import_dmabuf<'a>(
+    devices: impl Iterator<Item = &'a mut Device>,
+    dmabuf: &Dmabuf,
+) -> Result<DrmNode> {
+    let mut last_err = anyhow::anyhow!("Dmabuf cannot be imported on any gpu");
+    for device in devices {
+        if device.software_rendering {
+            continue;
+        }
+
+        let mut _egl = None;
+        let egl_display =
+            if let Some(egl_display) = device.egl.as_ref().map(|internals| &internals.display) {
+                egl_display
+            } else {
+                _egl = Some(init_egl(&device.gbm).context("Failed to initialize egl context")?);
+                &_egl.as_ref().unwrap().display
+            };
+
+        let result = egl_display
+            .create_image_from_dmabuf(dmabuf)
+            .map(|image| {
+                unsafe {
+                    smithay::backend::egl::ffi::egl::DestroyImageKHR(
+                        **egl_display.get_display_handle(),
+                        image,
+                    );
+                };
+                device.render_node
+            })
+            .map_err(Into::into);
+        match result {
+            Ok(node) => {
+                dmabuf.set_node(node); // so the MultiRenderer knows what node to use
+                return Ok(node);
+            }
+            Err(err) => {
+                trace!(?err, "Failed to import dmabuf on {:?}", device.render_node);
+                last_err = err;
+            }
+        }
+    }
+
+    Err(last_err)
+}
@@ -0,0 +1,421 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Modes configured by the user instead of being read from the EDID of a monitor.
+
+use anyhow::{Context, Result};
+use cosmic_comp_config::CustomMode;
+use drm_ffi::drm_mode_modeinfo;
+use smithay::reexports::drm::control::{Mode, ModeFlags, ModeTypeFlags};
+use std::{collections::HashMap, os::raw::c_char};
+use tracing::warn;
+
+// CVT 1.2 constants, as used by libxcvt
+const CVT_H_GRANULARITY: u32 = 8;
+const CVT_MIN_V_PORCH: u32 = 3;
+const CVT_MIN_V_BPORCH: u32 = 6;
+const CVT_CLOCK_STEP: u32 = 250;
+const CVT_MIN_VSYNC_BP: f64 = 550.0;
+const CVT_HSYNC_PERCENTAGE: u32 = 8;
+const CVT_C_PRIME: f64 = 30.0;
+const CVT_M_PRIME: f64 = 300.0;
+const CVT_RB_MIN_VBLANK: f64 = 460.0;
+const CVT_RB_H_SYNC: u32 = 32;
+const CVT_RB_H_BLANK: u32 = 160;
+const CVT_RB_VFPORCH: u32 = 3;
+
+// GTF constants, as used by xf86gtf
+const GTF_CELL_GRAN: f64 = 8.0;
+const GTF_MIN_PORCH: u32 = 1;
+const GTF_V_SYNC_RQD: u32 = 3;
+const GTF_H_SYNC_PERCENT: f64 = 8.0;
+const GTF_MIN_VSYNC_PLUS_BP: f64 = 550.0;
+const GTF_C_PRIME: f64 = 30.0;
+const GTF_M_PRIME: f64 = 300.0;
+
+#[derive(Debug)]
+struct Timings {
+    /// Pixel clock in kHz
+    clock: u32,
+    hdisplay: u32,
+    hsync_start: u32,
+    hsync_end: u32,
+    htotal: u32,
+    vdisplay: u32,
+    vsync_start: u32,
+    vsync_end: u32,
+    vtotal: u32,
+    flags: ModeFlags,
+}
+
+impl Timings {
+    fn into_mode(self) -> Result<Mode> {
+        anyhow::ensure!(
+            self.hdisplay > 0
+                && self.hdisplay <= self.hsync_start
+                && self.hsync_start <= self.hsync_end
+                && self.hsync_end <= self.htotal
+                && self.htotal <= u16::MAX as u32,
+            "Invalid horizontal timings"
+        );
+        anyhow::ensure!(
+            self.vdisplay > 0
+                && self.vdisplay <= self.vsync_start
+                && self.vsync_start <= self.vsync_end
+                && self.vsync_end <= self.vtotal
+                && self.vtotal <= u16::MAX as u32,
+            "Invalid vertical timings"
+        );
+        anyhow::ensure!(self.clock > 0, "Invalid pixel clock");
+
+        let mut name = [0 as c_char; 32];
+        for (dst, src) in name
+            .iter_mut()
+            .zip(format!("{}x{}", self.hdisplay, self.vdisplay).bytes())
+        {
+            *dst = src as c_char;
+        }
+
+        let vrefresh =
+            (self.clock as u64 * 1000 / (self.htotal as u64 * self.vtotal as u64)) as u32;
+        Ok(Mode::from(drm_mode_modeinfo {
+            clock: self.clock,
+            hdisplay: self.hdisplay as u16,
+            hsync_start: self.hsync_start as u16,
+            hsync_end: self.hsync_end as u16,
+            htotal: self.htotal as u16,
+            hskew: 0,
+            vdisplay: self.vdisplay as u16,
+            vsync_start: self.vsync_start as u16,
+            vsync_end: self.vsync_end as u16,
+            vtotal: self.vtotal as u16,
+            vscan: 0,
+            vrefresh,
+            flags: self.flags.bits(),
+            type_: ModeTypeFlags::USERDEF.bits(),
+            name,
+        }))
+    }
+}
+
+/// Converts a configured mode into a drm mode
+pub fn custom_mode(mode: &CustomMode) -> Result<Mode> {
+    match mode {
+        CustomMode::Modeline(modeline) => parse_modeline(modeline),
+        CustomMode::Cvt {
+            width,
+            height,
+            refresh,
+            reduced_blanking,
+        } => cvt(*width as u32, *height as u32, *refresh, *reduced_blanking),
+        CustomMode::Gtf {
+            width,
+            height,
+            refresh,
+        } => gtf(*width as u32, *height as u32, *refresh),
+    }
+}
+
+/// Converts all configured modes, skipping invalid ones, keyed by connector name
+pub fn custom_modes(config: &HashMap<String, Vec<CustomMode>>) -> HashMap<String, Vec<Mode>> {
+    config
+        .iter()
+        .map(|(connector, modes)| {
+            let modes = modes
+                .iter()
+                .filter_map(|mode| match custom_mode(mode) {
+                    Ok(mode) => Some(mode),
+                    Err(err) => {
+                        warn!(
+                            ?err,
+                            ?mode,
+                            "Ignoring invalid custom mode for {}",
+                            connector
+                        );
+                        None
+                    }
+                })
+                .collect();
+            (connector.clone(), modes)
+        })
+        .collect()
+}
+
+/// Parses the timings of an xrandr/X11 style modeline,
+/// e.g. `148.50 1920 2008 2052 2200 1080 1084 1089 1125 +hsync +vsync`.
+///
+/// A leading `Modeline` keyword and quoted mode name are skipped.
+fn parse_modeline(modeline: &str) -> Result<Mode> {
+    let mut tokens = modeline.split_whitespace().peekable();
+    if tokens
+        .peek()
+        .is_some_and(|token| token.eq_ignore_ascii_case("modeline"))
+    {
+        tokens.next();
+    }
+    if tokens.peek().is_some_and(|token| token.starts_with('"')) {
+        let name = tokens.next().unwrap();
+        if name.len() == 1 || !name.ends_with('"') {
+            tokens
+                .by_ref()
+                .find(|token| token.ends_with('"'))
+                .context("Unterminated mode name")?;
+        }
+    }
+
+    let clock = tokens
+        .next()
+        .context("Missing pixel clock")?
+        .parse::<f64>()
+        .context("Invalid pixel clock")?;
+    let mut timings = [0u32; 8];
+    for timing in timings.iter_mut() {
+        *timing = tokens
+            .next()
+            .context("Missing timings")?
+            .parse()
+            .context("Invalid timings")?;
+    }
+    let [hdisplay, hsync_start, hsync_end, htotal, vdisplay, vsync_start, vsync_end, vtotal] =
+        timings;
+
+    let mut flags = ModeFlags::empty();
+    for flag in tokens {
+        flags |= match flag.to_ascii_lowercase().as_str() {
+            "+hsync" => ModeFlags::PHSYNC,
+            "-hsync" => ModeFlags::NHSYNC,
+            "+vsync" => ModeFlags::PVSYNC,
+            "-vsync" => ModeFlags::NVSYNC,
+            "interlace" => ModeFlags::INTERLACE,
+            "doublescan" => ModeFlags::DBLSCAN,
+            "+csync" => ModeFlags::PCSYNC,
+            "-csync" => ModeFlags::NCSYNC,
+            "composite" => ModeFlags::CSYNC,
+            flag => anyhow::bail!("Unknown modeline flag: {}", flag),
+        };
+    }
+
+    Timings {
+        clock: (clock * 1000.0).round() as u32,
+        hdisplay,
+        hsync_start,
+        hsync_end,
+        htotal,
+        vdisplay,
+        vsync_start,
+        vsync_end,
+        vtotal,
+        flags,
+    }
+    .into_mode()
+}
+
+/// Calculates a mode using the VESA Coordinated Video Timings formula
+fn cvt(hdisplay: u32, vdisplay: u32, refresh: f64, reduced_blanking: bool) -> Result<Mode> {
+    anyhow::ensure!(
+        hdisplay > 0 && vdisplay > 0 && refresh > 0.0,
+        "Invalid size or refresh rate"
+    );
+
+    let hdisplay = hdisplay - hdisplay % CVT_H_GRANULARITY;
+    // the length of the vsync pulse encodes the aspect ratio
+    let vsync = if vdisplay % 3 == 0 && vdisplay * 4 / 3 == hdisplay {
+        4
+    } else if vdisplay % 9 == 0 && vdisplay * 16 / 9 == hdisplay {
+        5
+    } else if vdisplay % 10 == 0 && vdisplay * 16 / 10 == hdisplay {
+        6
+    } else if (vdisplay % 4 == 0 && vdisplay * 5 / 4 == hdisplay)
+        || (vdisplay % 9 == 0 && vdisplay * 15 / 9 == hdisplay)
+    {
+        7
+    } else {
+        10
+    };
+
+    let timings = if !reduced_blanking {
+        // estimated horizontal period in µs
+        let hperiod =
+            (1_000_000.0 / refresh - CVT_MIN_VSYNC_BP) / (vdisplay + CVT_MIN_V_PORCH) as f64;
+        anyhow::ensure!(hperiod > 0.0, "Refresh rate too high");
+        let vsync_and_bp = ((CVT_MIN_VSYNC_BP / hperiod) as u32 + 1).max(vsync + CVT_MIN_V_BPORCH);
+        let vtotal = vdisplay + vsync_and_bp + CVT_MIN_V_PORCH;
+
+        let hblank_percentage = (CVT_C_PRIME - CVT_M_PRIME * hperiod / 1000.0).max(20.0);
+        let mut hblank = (hdisplay as f64 * hblank_percentage / (100.0 - hblank_percentage)) as u32;
+        hblank -= hblank % (2 * CVT_H_GRANULARITY);
+        let htotal = hdisplay + hblank;
+
+        let hsync_end = hdisplay + hblank / 2;
+        let mut hsync = htotal * CVT_HSYNC_PERCENTAGE / 100;
+        hsync -= hsync % CVT_H_GRANULARITY;
+
+        let clock = (htotal as f64 * 1000.0 / hperiod) as u32;
+        Timings {
+            clock: clock - clock % CVT_CLOCK_STEP,
+            hdisplay,
+            hsync_start: hsync_end - hsync,
+            hsync_end,
+            htotal,
+            vdisplay,
+            vsync_start: vdisplay + CVT_MIN_V_PORCH,
+            vsync_end: vdisplay + CVT_MIN_V_PORCH + vsync,
+            vtotal,
+            flags: ModeFlags::NHSYNC | ModeFlags::PVSYNC,
+        }
+    } else {
+        let hperiod = (1_000_000.0 / refresh - CVT_RB_MIN_VBLANK) / vdisplay as f64;
+        anyhow::ensure!(hperiod > 0.0, "Refresh rate too high");
+        let vblank_lines = ((CVT_RB_MIN_VBLANK / hperiod) as u32 + 1)
+            .max(CVT_RB_VFPORCH + vsync + CVT_MIN_V_BPORCH);
+        let vtotal = vdisplay + vblank_lines;
+        let htotal = hdisplay + CVT_RB_H_BLANK;
+        let hsync_end = hdisplay + CVT_RB_H_BLANK / 2;
+
+        let clock = (refresh * vtotal as f64 * htotal as f64 / 1000.0) as u32;
+        Timings {
+            clock: clock - clock % CVT_CLOCK_STEP,
+            hdisplay,
+            hsync_start: hsync_end - CVT_RB_H_SYNC,
+            hsync_end,
+            htotal,
+            vdisplay,
+            vsync_start: vdisplay + CVT_RB_VFPORCH,
+            vsync_end: vdisplay + CVT_RB_VFPORCH + vsync,
+            vtotal,
+            flags: ModeFlags::PHSYNC | ModeFlags::NVSYNC,
+        }
+    };
+
+    timings.into_mode()
+}
+
+/// Calculates a mode using the VESA Generalized Timing Formula
+fn gtf(hdisplay: u32, vdisplay: u32, refresh: f64) -> Result<Mode> {
+    anyhow::ensure!(
+        hdisplay > 0 && vdisplay > 0 && refresh > 0.0,
+        "Invalid size or refresh rate"
+    );
+
+    let hdisplay = ((hdisplay as f64 / GTF_CELL_GRAN).round() * GTF_CELL_GRAN) as u32;
+
+    // estimated horizontal period in µs
+    let hperiod_est = (1.0 / refresh - GTF_MIN_VSYNC_PLUS_BP / 1_000_000.0)
+        / (vdisplay + GTF_MIN_PORCH) as f64
+        * 1_000_000.0;
+    anyhow::ensure!(hperiod_est > 0.0, "Refresh rate too high");
+    let vsync_and_bp = (GTF_MIN_VSYNC_PLUS_BP / hperiod_est).round() as u32;
+    let vtotal = vdisplay + vsync_and_bp + GTF_MIN_PORCH;
+    let refresh_est = 1.0 / hperiod_est / vtotal as f64 * 1_000_000.0;
+    let hperiod = hperiod_est / (refresh / refresh_est);
+
+    let duty_cycle = GTF_C_PRIME - GTF_M_PRIME * hperiod / 1000.0;
+    let hblank = ((hdisplay as f64 * duty_cycle / (100.0 - duty_cycle) / (2.0 * GTF_CELL_GRAN))
+        .round()
+        * (2.0 * GTF_CELL_GRAN)) as u32;
+    let htotal = hdisplay + hblank;
+    let hsync = ((GTF_H_SYNC_PERCENT / 100.0 * htotal as f64 / GTF_CELL_GRAN).round()
+        * GTF_CELL_GRAN) as u32;
+    let hsync_start = hdisplay + hblank / 2 - hsync;
+
+    Timings {
+        clock: (htotal as f64 / hperiod * 1000.0) as u32,
+        hdisplay,
+        hsync_start,
+        hsync_end: hsync_start + hsync,
+        htotal,
+        vdisplay,
+        vsync_start: vdisplay + GTF_MIN_PORCH,
+        vsync_end: vdisplay + GTF_MIN_PORCH + GTF_V_SYNC_RQD,
+        vtotal,
+        flags: ModeFlags::NHSYNC | ModeFlags::PVSYNC,
+    }
+    .into_mode()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn timings(mode: &Mode) -> (u32, (u16, u16, u16, u16), (u16, u16, u16, u16)) {
+        let (hdisplay, vdisplay) = mode.size();
+        let (hsync_start, hsync_end, htotal) = mode.hsync();
+        let (vsync_start, vsync_end, vtotal) = mode.vsync();
+        (
+            mode.clock(),
+            (hdisplay, hsync_start, hsync_end, htotal),
+            (vdisplay, vsync_start, vsync_end, vtotal),
+        )
+    }
+
+    #[test]
+    fn test_parse_modeline() {
+        let mode =
+            parse_modeline("148.50 1920 2008 2052 2200 1080 1084 1089 1125 +hsync +vsync").unwrap();
+        assert_eq!(
+            timings(&mode),
+            (148500, (1920, 2008, 2052, 2200), (1080, 1084, 1089, 1125))
+        );
+        assert_eq!(mode.flags(), ModeFlags::PHSYNC | ModeFlags::PVSYNC);
+        assert_eq!(mode.vrefresh(), 60);
+
+        let named = parse_modeline(
+            "Modeline \"1920x1080 custom\" 148.50 1920 2008 2052 2200 1080 1084 1089 1125 -HSync +VSync",
+        )
+        .unwrap();
+        assert_eq!(timings(&named), timings(&mode));
+        assert_eq!(named.flags(), ModeFlags::NHSYNC | ModeFlags::PVSYNC);
+    }
+
+    #[test]
+    fn test_parse_invalid_modeline() {
+        assert!(parse_modeline("").is_err());
+        assert!(parse_modeline("148.50 1920 2008 2052 2200 1080 1084 1089").is_err());
+        assert!(parse_modeline("148.50 1920 2008 2052 2200 1080 1084 1089 1125 +bogus").is_err());
+        assert!(parse_modeline("\"unterminated 148.50 1920 2008 2052 2200").is_err());
+        // sync before the active area
+        assert!(parse_modeline("148.50 1920 1900 2052 2200 1080 1084 1089 1125").is_err());
+        assert!(parse_modeline("0 1920 2008 2052 2200 1080 1084 1089 1125").is_err());
+    }
+
+    #[test]
+    fn test_cvt() {
+        // `cvt 1920 1080 60`
+        let mode = cvt(1920, 1080, 60.0, false).unwrap();
+        assert_eq!(
+            timings(&mode),
+            (173000, (1920, 2048, 2248, 2576), (1080, 1083, 1088, 1120))
+        );
+        assert_eq!(mode.flags(), ModeFlags::NHSYNC | ModeFlags::PVSYNC);
+
+        // `cvt 1024 768 60`
+        let mode = cvt(1024, 768, 60.0, false).unwrap();
+        assert_eq!(
+            timings(&mode),
+            (63500, (1024, 1072, 1176, 1328), (768, 771, 775, 798))
+        );
+
+        // `cvt -r 1920 1080 60`
+        let mode = cvt(1920, 1080, 60.0, true).unwrap();
+        assert_eq!(
+            timings(&mode),
+            (138500, (1920, 1968, 2000, 2080), (1080, 1083, 1088, 1111))
+        );
+        assert_eq!(mode.flags(), ModeFlags::PHSYNC | ModeFlags::NVSYNC);
+
+        assert!(cvt(0, 1080, 60.0, false).is_err());
+        assert!(cvt(1920, 1080, 0.0, true).is_err());
+    }
+
+    #[test]
+    fn test_gtf() {
+        // `gtf 1920 1080 60`
+        let mode = gtf(1920, 1080, 60.0).unwrap();
+        let (clock, horizontal, vertical) = timings(&mode);
+        assert_eq!(clock / 100, 1727);
+        assert_eq!(horizontal, (1920, 2040, 2248, 2576));
+        assert_eq!(vertical, (1080, 1081, 1084, 1118));
+        assert_eq!(mode.flags(), ModeFlags::NHSYNC | ModeFlags::PVSYNC);
+
+        assert!(gtf(1920, 0, 60.0).is_err());
+    }
+}
@@ -8,7 +8,7 @@ use std::{
 use smithay::backend::{
     allocator::{
         dmabuf::{AnyError, Dmabuf, DmabufAllocator},
-        gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
+        gbm::GbmAllocator,
         Allocator,
     },
     drm::DrmNode,
@@ -23,7 +23,6 @@ use tracing::warn;
 pub struct GbmPixmanBackend<A: AsFd + 'static> {
     devices: HashMap<DrmNode, GbmAllocator<A>>,
     needs_enumeration: AtomicBool,
-    allocator_flags: GbmBufferFlags,
 }
 
 pub struct GbmPixmanDevice {
@@ -42,33 +41,21 @@ impl fmt::Debug for GbmPixmanDevice {
     }
 }
 
-impl<A: AsFd + 'static> GbmPixmanBackend<A> {
-    pub fn new() -> Self {
-        GbmPixmanBackend {
-            devices: HashMap::new(),
-            needs_enumeration: AtomicBool::new(false),
-            allocator_flags: GbmBufferFlags::RENDERING,
-        }
-    }
-
-    pub fn with_allocator_flags(allocator_flags: GbmBufferFlags) -> Self {
+impl<A: AsFd + 'static> Default for GbmPixmanBackend<A> {
+    fn default() -> Self {
         GbmPixmanBackend {
             devices: HashMap::new(),
-            needs_enumeration: AtomicBool::new(false),
-            allocator_flags,
+            needs_enumeration: AtomicBool::new(true),
         }
     }
+}
 
-    pub fn set_allocator_flags(&mut self, flags: GbmBufferFlags) {
-        self.allocator_flags = flags;
-    }
-
-    pub fn add_node(&mut self, node: DrmNode, gbm: GbmDevice<A>) {
+impl<A: AsFd + 'static> GbmPixmanBackend<A> {
+    pub fn add_node(&mut self, node: DrmNode, allocator: GbmAllocator<A>) {
         if self.devices.contains_key(&node) {
             return;
         }
 
-        let allocator = GbmAllocator::new(gbm, self.allocator_flags);
         self.devices.insert(node, allocator);
         self.needs_enumeration.store(true, Ordering::SeqCst);
     }
@@ -1,13 +1,26 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
+//! Rendering and presentation of a single output.
+//!
+//! Every [`Surface`] runs on its own thread with its own [`GpuManager`]s, so outputs are rendered
+//! concurrently, even when they are driven by different gpus. A slow output only delays the
+//! frames of others, if it holds the shell lock while waiting for the gpu, which is why
+//! rendering steps waiting for it (like blurring) only lock the shell to collect their elements.
+
 use crate::{
     backend::render::{
+        color_filter::{color_filter_elements, ColorFilterBuffer},
         element::{CosmicElement, DamageElement},
-        init_shaders, workspace_elements, CursorMode, ElementFilter, GlMultiRenderer, CLEAR_COLOR,
+        init_shaders,
+        software::software_elements,
+        workspace_elements,
+        zoom::{self, output_zoom, zoom_elements, ZoomBuffer},
+        CursorMode, ElementFilter, GlMultiRenderer, CLEAR_COLOR,
     },
+    ipc::protocol::{OutputMetrics, Percentiles},
     shell::Shell,
     state::SurfaceDmabufFeedback,
-    utils::{prelude::*, quirks::workspace_overview_is_open},
+    utils::{prelude::*, quirks::workspace_overview_is_open, recover},
     wayland::{
         handlers::screencopy::{submit_buffer, FrameHolder, SessionData},
         protocols::screencopy::{
@@ -18,6 +31,7 @@ use crate::{
 
 use anyhow::{Context, Result};
 use calloop::channel::Channel;
+use cosmic_comp_config::RenderDevicePolicy;
 use smithay::{
     backend::{
         allocator::{
@@ -43,7 +57,7 @@ use smithay::{
             multigpu::{Error as MultiError, GpuManager},
             sync::SyncPoint,
             utils::with_renderer_surface_state,
-            Bind, ImportDma, Offscreen, Renderer, Texture,
+            Bind, ImportDma, Offscreen, Renderer, Texture, TextureFilter,
         },
     },
     desktop::utils::OutputPresentationFeedback,
@@ -88,8 +102,13 @@ use std::{
 mod timings;
 pub use self::timings::Timings;
 
-use super::{drm_helpers, render::gles::GbmGlowBackend};
+use super::{
+    drm_helpers,
+    render::{gles::GbmGlowBackend, pixman::GbmPixmanBackend},
+};
 
+#[cfg(feature = "debug")]
+use crate::debug::FrameStats;
 #[cfg(feature = "debug")]
 use smithay_egui::EguiState;
 
@@ -100,6 +119,9 @@ static AMD_LOGO: &'static [u8] = include_bytes!("../../../../resources/icons/amd
 #[cfg(feature = "debug")]
 static NVIDIA_LOGO: &'static [u8] = include_bytes!("../../../../resources/icons/nvidia.svg");
 
+/// Time to wait for the surface thread to answer a metrics request, it may be busy rendering
+const METRICS_TIMEOUT: Duration = Duration::from_millis(100);
+
 #[derive(Debug)]
 pub struct Surface {
     pub(super) connector: connector::Handle,
@@ -108,8 +130,10 @@ pub struct Surface {
     known_nodes: HashSet<DrmNode>,
 
     active: Arc<AtomicBool>,
+    dpms: bool,
     feedback: HashMap<DrmNode, SurfaceDmabufFeedback>,
-    plane_formats: FormatSet,
+    primary_plane_formats: FormatSet,
+    overlay_plane_formats: FormatSet,
 
     loop_handle: LoopHandle<'static, State>,
     thread_command: Sender<ThreadCommand>,
@@ -119,9 +143,14 @@ pub struct Surface {
 pub struct SurfaceThreadState {
     // rendering
     api: GpuManager<GbmGlowBackend<DrmDeviceFd>>,
+    software_api: GpuManager<GbmPixmanBackend<DrmDeviceFd>>,
+    /// Nodes without a working GL implementation, rendered by `software_api`
+    software_nodes: HashSet<DrmNode>,
     primary_node: DrmNode,
     target_node: DrmNode,
+    render_policy: RenderDevicePolicy,
     active: Arc<AtomicBool>,
+    dpms: bool,
     compositor: Option<GbmDrmCompositor>,
 
     state: QueueState,
@@ -132,6 +161,8 @@ pub struct SurfaceThreadState {
     output: Output,
     mirroring: Option<Output>,
     mirroring_textures: HashMap<DrmNode, MirroringState>,
+    zoom_buffer: Option<ZoomBuffer>,
+    color_filter_buffer: Option<ColorFilterBuffer>,
 
     shell: Arc<RwLock<Shell>>,
 
@@ -140,12 +171,16 @@ pub struct SurfaceThreadState {
 
     #[cfg(feature = "debug")]
     egui: EguiState,
+    #[cfg(feature = "debug")]
+    frame_stats: FrameStats,
 }
 
 #[derive(Debug)]
 struct MirroringState {
     texture: TextureRenderBuffer<GlesTexture>,
     damage_tracker: OutputDamageTracker,
+    size: Size<i32, BufferCoords>,
+    transform: Transform,
 }
 
 impl MirroringState {
@@ -154,12 +189,7 @@ impl MirroringState {
         format: Fourcc,
         output: &Output,
     ) -> Result<Self> {
-        let size = output
-            .current_mode()
-            .map(|mode| mode.size)
-            .unwrap_or_default()
-            .to_logical(1)
-            .to_buffer(1, Transform::Normal);
+        let size = Self::buffer_size(output);
         let opaque_regions = vec![Rectangle::from_loc_and_size((0, 0), size)];
 
         let texture = Offscreen::<GlesTexture>::create_buffer(renderer, format, size)?;
@@ -177,8 +207,24 @@ impl MirroringState {
         Ok(MirroringState {
             texture: texture_buffer,
             damage_tracker,
+            size,
+            transform,
         })
     }
+
+    fn buffer_size(output: &Output) -> Size<i32, BufferCoords> {
+        output
+            .current_mode()
+            .map(|mode| mode.size)
+            .unwrap_or_default()
+            .to_logical(1)
+            .to_buffer(1, Transform::Normal)
+    }
+
+    /// Returns if the texture doesn't match the mode or transform of `output` anymore
+    fn is_outdated(&self, output: &Output) -> bool {
+        self.size != Self::buffer_size(output) || self.transform != output.current_transform()
+    }
 }
 
 pub type GbmDrmCompositor = DrmCompositor<
@@ -228,7 +274,7 @@ pub enum ThreadCommand {
     NodeAdded {
         node: DrmNode,
         gbm: GbmAllocator<DrmDeviceFd>,
-        egl: EGLContext, // TODO: Option for software rendering
+        egl: Option<EGLContext>,
     },
     NodeRemoved {
         node: DrmNode,
@@ -236,7 +282,11 @@ pub enum ThreadCommand {
     UpdateMirroring(Option<Output>),
     VBlank(Option<DrmEventMetadata>),
     ScheduleRender,
+    ScheduleCursorRender,
     SetMode(Mode, SyncSender<Result<()>>),
+    SetDpms(bool),
+    SetRenderDevices(DrmNode, RenderDevicePolicy),
+    GetMetrics(SyncSender<OutputMetrics>),
     End,
 }
 
@@ -308,31 +358,25 @@ impl Surface {
                     state
                         .common
                         .send_dmabuf_feedback(&output_clone, &states, |source_node| {
-                            Some(
-                                surface
-                                    .feedback
-                                    .entry(source_node)
-                                    .or_insert_with(|| {
-                                        let render_formats = kms
-                                            .api
-                                            .single_renderer(&source_node)
-                                            .unwrap()
-                                            .dmabuf_formats();
-                                        let target_formats = kms
-                                            .api
-                                            .single_renderer(&target_node)
-                                            .unwrap()
-                                            .dmabuf_formats();
-                                        get_surface_dmabuf_feedback(
-                                            source_node,
-                                            target_node,
-                                            render_formats,
-                                            target_formats,
-                                            surface.plane_formats.clone(),
-                                        )
-                                    })
-                                    .clone(),
-                            )
+                            if let Some(feedback) = surface.feedback.get(&source_node) {
+                                return Some(feedback.clone());
+                            }
+
+                            // gpus rendered in software have no feedback to offer
+                            let render_formats =
+                                kms.api.single_renderer(&source_node).ok()?.dmabuf_formats();
+                            let target_formats =
+                                kms.api.single_renderer(&target_node).ok()?.dmabuf_formats();
+                            let feedback = get_surface_dmabuf_feedback(
+                                source_node,
+                                target_node,
+                                render_formats,
+                                target_formats,
+                                surface.primary_plane_formats.clone(),
+                                surface.overlay_plane_formats.clone(),
+                            );
+                            surface.feedback.insert(source_node, feedback.clone());
+                            Some(feedback)
                         });
                 }
                 Event::Closed => {}
@@ -345,8 +389,10 @@ impl Surface {
             output: output.clone(),
             known_nodes: HashSet::new(),
             active,
+            dpms: true,
             feedback: HashMap::new(),
-            plane_formats: FormatSet::default(),
+            primary_plane_formats: FormatSet::default(),
+            overlay_plane_formats: FormatSet::default(),
             loop_handle: evlh.clone(),
             thread_command: tx,
             thread_token,
@@ -361,7 +407,13 @@ impl Surface {
         self.active.load(Ordering::SeqCst)
     }
 
-    pub fn add_node(&mut self, node: DrmNode, gbm: GbmAllocator<DrmDeviceFd>, egl: EGLContext) {
+    /// Makes `node` available for rendering, in software if no EGL context is given
+    pub fn add_node(
+        &mut self,
+        node: DrmNode,
+        gbm: GbmAllocator<DrmDeviceFd>,
+        egl: Option<EGLContext>,
+    ) {
         self.known_nodes.insert(node);
         let _ = self
             .thread_command
@@ -383,18 +435,51 @@ impl Surface {
         let _ = self.thread_command.send(ThreadCommand::ScheduleRender);
     }
 
+    /// Schedules a render caused by input, which doesn't need to be presented immediately with VRR
+    pub fn schedule_cursor_render(&self) {
+        let _ = self
+            .thread_command
+            .send(ThreadCommand::ScheduleCursorRender);
+    }
+
+    /// Updates the primary gpu and which gpu renders this output
+    pub fn set_render_devices(&mut self, primary_node: DrmNode, policy: RenderDevicePolicy) {
+        let _ = self
+            .thread_command
+            .send(ThreadCommand::SetRenderDevices(primary_node, policy));
+    }
+
     pub fn set_mirroring(&mut self, output: Option<Output>) {
         let _ = self
             .thread_command
             .send(ThreadCommand::UpdateMirroring(output));
     }
 
+    pub fn get_dpms(&self) -> bool {
+        self.dpms
+    }
+
+    /// Turns the connected display on or off, without changing the output configuration
+    pub fn set_dpms(&mut self, on: bool) {
+        if self.dpms != on {
+            self.dpms = on;
+            let _ = self.thread_command.send(ThreadCommand::SetDpms(on));
+        }
+    }
+
     pub fn set_mode(&mut self, mode: Mode) -> Result<()> {
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
         let _ = self.thread_command.send(ThreadCommand::SetMode(mode, tx));
         rx.recv().context("Surface thread died")?
     }
 
+    /// Timings of the recently presented frames, `None` if the surface thread doesn't answer
+    pub fn metrics(&self) -> Option<OutputMetrics> {
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        let _ = self.thread_command.send(ThreadCommand::GetMetrics(tx));
+        rx.recv_timeout(METRICS_TIMEOUT).ok()
+    }
+
     pub fn suspend(&mut self) {
         let _ = self.thread_command.send(ThreadCommand::Suspend);
     }
@@ -407,19 +492,16 @@ impl Surface {
         vrr: bool,
     ) -> Result<()> {
         let (tx, rx) = std::sync::mpsc::sync_channel(1);
-        self.plane_formats = surface
-            .plane_info()
-            .formats
+        self.primary_plane_formats = surface.plane_info().formats.iter().copied().collect();
+        self.overlay_plane_formats = surface
+            .planes()
+            .overlay
             .iter()
-            .copied()
-            .chain(
-                surface
-                    .planes()
-                    .overlay
-                    .iter()
-                    .flat_map(|p| p.formats.iter().cloned()),
-            )
+            .flat_map(|p| p.formats.iter().cloned())
+            .filter(|format| !self.primary_plane_formats.contains(format))
             .collect::<FormatSet>();
+        // the planes might have changed, so the cached tranches are stale
+        self.feedback.clear();
 
         let _ = self.thread_command.send(ThreadCommand::Resume {
             surface,
@@ -456,12 +538,8 @@ fn surface_thread(
 
     let api = GpuManager::new(GbmGlowBackend::<DrmDeviceFd>::default())
         .context("Failed to initialize rendering api")?;
-    /*
-    let software_api = GpuManager::new(GbmPixmanBackend::<DrmDeviceFd>::with_allocator_flags(
-        gbm_flags,
-    ))
-    .context("Failed to initialize software rendering");
-    */
+    let software_api = GpuManager::new(GbmPixmanBackend::<DrmDeviceFd>::default())
+        .context("Failed to initialize software rendering")?;
 
     #[cfg(feature = "debug")]
     let egui = {
@@ -474,12 +552,18 @@ fn surface_thread(
         state.context().set_visuals(visuals);
         state
     };
+    #[cfg(feature = "debug")]
+    let frame_stats = FrameStats::new(&output);
 
     let mut state = SurfaceThreadState {
         api,
+        software_api,
+        software_nodes: HashSet::new(),
         primary_node,
         target_node,
+        render_policy: RenderDevicePolicy::default(),
         active,
+        dpms: true,
         compositor: None,
 
         state: QueueState::Idle,
@@ -490,12 +574,16 @@ fn surface_thread(
         output,
         mirroring: None,
         mirroring_textures: HashMap::new(),
+        zoom_buffer: None,
+        color_filter_buffer: None,
 
         shell,
         loop_handle: event_loop.handle(),
         clock: Clock::new(),
         #[cfg(feature = "debug")]
         egui,
+        #[cfg(feature = "debug")]
+        frame_stats,
     };
 
     let signal = event_loop.get_signal();
@@ -530,6 +618,13 @@ fn surface_thread(
 
                 state.queue_redraw(false);
             }
+            Event::Msg(ThreadCommand::ScheduleCursorRender) => {
+                if !startup_done.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                state.queue_redraw_with_pacing(false, true);
+            }
             Event::Msg(ThreadCommand::UpdateMirroring(mirroring_output)) => {
                 state.update_mirroring(mirroring_output);
             }
@@ -540,6 +635,22 @@ fn surface_thread(
                     let _ = result.send(Err(anyhow::anyhow!("Set mode with inactive surface")));
                 }
             }
+            Event::Msg(ThreadCommand::GetMetrics(result)) => {
+                let _ = result.send(state.metrics());
+            }
+            Event::Msg(ThreadCommand::SetDpms(on)) => {
+                state.set_dpms(on);
+            }
+            Event::Msg(ThreadCommand::SetRenderDevices(primary_node, policy)) => {
+                if state.primary_node != primary_node || state.render_policy != policy {
+                    state.primary_node = primary_node;
+                    state.render_policy = policy;
+                    // the texture might belong to a different gpu now
+                    state.zoom_buffer = None;
+                    state.color_filter_buffer = None;
+                    state.queue_redraw(false);
+                }
+            }
             Event::Closed | Event::Msg(ThreadCommand::End) => {
                 signal.stop();
                 signal.wakeup();
@@ -555,7 +666,10 @@ impl SurfaceThreadState {
     fn suspend(&mut self) {
         self.active.store(false, Ordering::SeqCst);
         let _ = self.compositor.take();
+        self.cancel_pending_frame();
+    }
 
+    fn cancel_pending_frame(&mut self) {
         match std::mem::replace(&mut self.state, QueueState::Idle) {
             QueueState::Idle => {}
             QueueState::Queued(token) | QueueState::WaitingForEstimatedVBlank(token) => {
@@ -593,11 +707,17 @@ impl SurfaceThreadState {
             planes.overlay = vec![];
         }
 
-        let render_formats = self
-            .api
-            .single_renderer(&self.target_node)
-            .unwrap()
-            .dmabuf_formats();
+        let render_formats = if self.software_nodes.contains(&self.target_node) {
+            self.software_api
+                .single_renderer(&self.target_node)
+                .unwrap()
+                .dmabuf_formats()
+        } else {
+            self.api
+                .single_renderer(&self.target_node)
+                .unwrap()
+                .dmabuf_formats()
+        };
 
         self.timings
             .set_refresh_interval(Some(Duration::from_secs_f64(
@@ -622,10 +742,16 @@ impl SurfaceThreadState {
             ],
             render_formats,
             cursor_size,
-            Some(gbm),
+            // without a gbm device for the cursor plane, the cursor is always composited
+            (!disable_hardware_cursor()).then_some(gbm),
         ) {
-            Ok(compositor) => {
+            Ok(mut compositor) => {
                 self.active.store(true, Ordering::SeqCst);
+                if !self.dpms {
+                    if let Err(err) = compositor.clear() {
+                        warn!(?err, "Failed to keep output turned off");
+                    }
+                }
                 self.compositor = Some(compositor);
                 Ok(())
             }
@@ -636,13 +762,32 @@ impl SurfaceThreadState {
         }
     }
 
+    fn set_dpms(&mut self, on: bool) {
+        self.dpms = on;
+        if on {
+            self.queue_redraw(false);
+        } else {
+            if let Some(compositor) = self.compositor.as_mut() {
+                if let Err(err) = compositor.clear() {
+                    error!(?err, "Failed to turn off output");
+                }
+            }
+            self.cancel_pending_frame();
+        }
+    }
+
     fn node_added(
         &mut self,
         node: DrmNode,
         gbm: GbmAllocator<DrmDeviceFd>,
-        egl: EGLContext,
+        egl: Option<EGLContext>,
     ) -> Result<()> {
-        //if let Some(egl) = egl {
+        let Some(egl) = egl else {
+            self.software_api.as_mut().add_node(node, gbm);
+            self.software_nodes.insert(node);
+            return Ok(());
+        };
+
         let mut renderer =
             unsafe { GlowRenderer::new(egl) }.context("Failed to create renderer")?;
         init_shaders(renderer.borrow_mut()).context("Failed to initialize shaders")?;
@@ -661,18 +806,16 @@ impl SurfaceThreadState {
         }
 
         self.api.as_mut().add_node(node, gbm, renderer);
-        /*
-        } else {
-            self.software_api.as_mut().add_node(node, gbm);
-        }
-        */
 
         Ok(())
     }
 
     fn node_removed(&mut self, node: DrmNode) {
         self.api.as_mut().remove_node(&node);
-        //self.software_api.as_mut().remove_node(node);
+        self.software_api.as_mut().remove_node(&node);
+        self.software_nodes.remove(&node);
+        self.zoom_buffer = None;
+        self.color_filter_buffer = None;
     }
 
     fn on_vblank(&mut self, metadata: Option<DrmEventMetadata>) {
@@ -735,7 +878,13 @@ impl SurfaceThreadState {
             QueueState::WaitingForEstimatedVBlankAndQueued { .. } => unreachable!(),
         };
 
-        if redraw_needed || self.shell.read().unwrap().animations_going() {
+        if redraw_needed
+            || self
+                .shell
+                .read()
+                .unwrap()
+                .output_animations_going(&self.output)
+        {
             self.queue_redraw(false);
         } else {
             self.send_frame_callbacks();
@@ -757,7 +906,12 @@ impl SurfaceThreadState {
 
         self.frame_callback_seq = self.frame_callback_seq.wrapping_add(1);
 
-        if self.shell.read().unwrap().animations_going() {
+        if self
+            .shell
+            .read()
+            .unwrap()
+            .output_animations_going(&self.output)
+        {
             self.queue_redraw(false);
         } else {
             self.send_frame_callbacks();
@@ -765,9 +919,17 @@ impl SurfaceThreadState {
     }
 
     fn queue_redraw(&mut self, force: bool) {
+        self.queue_redraw_with_pacing(force, false)
+    }
+
+    /// Queues a redraw, `fixed_rate` keeps it aligned to the refresh interval even if VRR is enabled.
+    fn queue_redraw_with_pacing(&mut self, force: bool, fixed_rate: bool) {
         let Some(_compositor) = self.compositor.as_mut() else {
             return;
         };
+        if !self.dpms {
+            return;
+        }
 
         if let QueueState::WaitingForVBlank { .. } = &self.state {
             // We're waiting for VBlank, request a redraw afterwards.
@@ -789,8 +951,10 @@ impl SurfaceThreadState {
             };
         }
 
-        let estimated_presentation = self.timings.next_presentation_time(&self.clock);
-        let render_start = self.timings.next_render_time(&self.clock);
+        let estimated_presentation = self.timings.next_presentation_time(&self.clock, fixed_rate);
+        let render_start = self
+            .timings
+            .next_render_time(&self.clock, estimated_presentation);
 
         let timer = if render_start.is_zero() {
             trace!("Running late for frame.");
@@ -803,10 +967,29 @@ impl SurfaceThreadState {
         let token = self
             .loop_handle
             .insert_source(timer, move |_time, _, state| {
-                if let Err(err) = state.redraw(estimated_presentation) {
-                    let name = state.output.name();
-                    warn!(?name, "Failed to submit rendering: {:?}", err);
-                    state.queue_redraw(true);
+                // windows panicking are left out already, this only catches the rest of the frame
+                let res = recover::catch_panic("rendering a frame", || {
+                    state.redraw(estimated_presentation)
+                });
+                match res {
+                    Some(Ok(())) => {}
+                    Some(Err(err)) => {
+                        let name = state.output.name();
+                        warn!(?name, "Failed to submit rendering: {:?}", err);
+                        state.queue_redraw(true);
+                    }
+                    None => {
+                        // skip the frame, but keep frame callbacks coming
+                        let name = state.output.name();
+                        error!(?name, "Skipped frame after panic");
+                        if matches!(
+                            state.state,
+                            QueueState::Queued(_)
+                                | QueueState::WaitingForEstimatedVBlankAndQueued { .. }
+                        ) {
+                            state.frame_queued(false, estimated_presentation);
+                        }
+                    }
                 }
                 return TimeoutAction::Drop;
             })
@@ -840,17 +1023,60 @@ impl SurfaceThreadState {
         }
     }
 
+    /// Toggles VRR on the crtc, if the configured mode depends on the windows of the output
+    fn update_vrr(&mut self) {
+        let Some(compositor) = self.compositor.as_ref() else {
+            return;
+        };
+
+        let vrr = self.output.adaptive_sync()
+            && self
+                .shell
+                .read()
+                .unwrap()
+                .adaptive_sync_wanted(&self.output);
+        if vrr == self.timings.vrr() {
+            return;
+        }
+
+        let surface = compositor.surface();
+        match drm_helpers::set_crtc_vrr(surface, surface.crtc(), vrr) {
+            Ok(enabled) => self.timings.set_vrr(enabled),
+            Err(err) => {
+                warn!(
+                    ?err,
+                    "Failed to toggle VRR, disabling it for {}",
+                    self.output.name()
+                );
+                self.output.set_adaptive_sync(false);
+                self.timings.set_vrr(false);
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "render_frame", skip_all, fields(output = %self.output.name()))]
     fn redraw(&mut self, estimated_presentation: Duration) -> Result<()> {
+        self.update_vrr();
+
+        if self.software_nodes.contains(&self.target_node) {
+            return self.redraw_software(estimated_presentation);
+        }
+
         let Some(compositor) = self.compositor.as_mut() else {
             return Ok(());
         };
 
-        let render_node = render_node_for_output(
+        let mut render_node = render_node_for_output(
             self.mirroring.as_ref().unwrap_or(&self.output),
             &self.primary_node,
             &self.target_node,
+            self.render_policy,
             &*self.shell.read().unwrap(),
         );
+        // a gpu without GL can't render for others
+        if self.software_nodes.contains(&render_node) {
+            render_node = self.target_node;
+        }
 
         let mut renderer = if render_node != self.target_node {
             self.api
@@ -860,7 +1086,8 @@ impl SurfaceThreadState {
             self.api.single_renderer(&self.target_node).unwrap()
         };
 
-        self.timings.start_render(&self.clock);
+        self.timings
+            .start_render(&self.clock, estimated_presentation);
 
         let mut elements = {
             let shell = self.shell.read().unwrap();
@@ -894,7 +1121,7 @@ impl SurfaceThreadState {
                 #[cfg(not(feature = "debug"))]
                 None,
                 #[cfg(feature = "debug")]
-                Some((&self.egui, &self.timings)),
+                Some((&self.egui, &self.timings, &self.frame_stats)),
             )
             .map_err(|err| {
                 anyhow::format_err!("Failed to accumulate elements for rendering: {:?}", err)
@@ -902,8 +1129,49 @@ impl SurfaceThreadState {
         };
         self.timings.elements_done(&self.clock);
 
+        #[cfg(feature = "debug")]
+        let debug_active = self.mirroring.is_none() && self.shell.read().unwrap().debug_active;
+        #[cfg(feature = "debug")]
+        if debug_active {
+            self.frame_stats.update_elements(&elements);
+        } else {
+            self.frame_stats.reset(&self.output);
+        }
+
+        #[cfg(feature = "debug")]
+        if debug_active {
+            elements.splice(0..0, self.frame_stats.damage_elements().map(Into::into));
+        }
+
+        let mut upscale_filter = None;
+        if self.mirroring.is_none() {
+            elements = zoom_elements(
+                &mut renderer,
+                &mut self.zoom_buffer,
+                &self.shell,
+                &self.output,
+                elements,
+            )
+            .context("Failed to render zoomed output")?;
+            let shell = self.shell.read().unwrap();
+            if output_zoom(&shell, &self.output).is_some() {
+                upscale_filter = Some(zoom::upscale_filter(&shell));
+            }
+        }
+        elements = color_filter_elements(
+            &mut renderer,
+            &mut self.color_filter_buffer,
+            &self.shell,
+            self.mirroring.as_ref().unwrap_or(&self.output),
+            elements,
+            upscale_filter,
+        )
+        .context("Failed to apply color filter")?;
+
         // we can't use the elements after `compositor.render_frame`,
-        // so let's collect everything we need for screencopy now
+        // so let's collect everything we need for screencopy now.
+        // Screencopy blits the presented frame, so the damage has to be tracked
+        // on the magnified and filtered elements like for the other backends.
         let frames: Vec<(
             ScreencopySession,
             ScreencopyFrame,
@@ -976,7 +1244,15 @@ impl SurfaceThreadState {
                     .is_some_and(|mode| mode != mirror_mode)
             }) || mirrored_output.current_scale().fractional_scale()
                 != self.output.current_scale().fractional_scale()
+                || mirrored_output.current_transform() != self.output.current_transform()
         }) {
+            if self
+                .mirroring_textures
+                .get(&self.target_node)
+                .is_some_and(|state| state.is_outdated(mirrored_output))
+            {
+                self.mirroring_textures.remove(&self.target_node);
+            }
             let mirroring_state = {
                 let entry = self.mirroring_textures.entry(self.target_node);
                 let mut new_state = None;
@@ -1057,16 +1333,35 @@ impl SurfaceThreadState {
             renderer = self.api.single_renderer(&self.target_node).unwrap();
             compositor.render_frame(&mut renderer, &elements, [0.0, 0.0, 0.0, 1.0])
         } else {
-            compositor.render_frame(
+            if let Some(filter) = upscale_filter {
+                renderer.upscale_filter(filter)?;
+            }
+            let res = compositor.render_frame(
                 &mut renderer,
                 &elements,
                 CLEAR_COLOR, // TODO use a theme neutral color
-            )
+            );
+            if upscale_filter.is_some() {
+                renderer.upscale_filter(TextureFilter::Linear)?;
+            }
+            res
         };
         self.timings.draw_done(&self.clock);
 
         match res {
             Ok(frame_result) => {
+                #[cfg(feature = "debug")]
+                if debug_active {
+                    self.frame_stats.update_planes(
+                        match &frame_result.primary_element {
+                            PrimaryPlaneElement::Element(elem) => Some(*elem),
+                            PrimaryPlaneElement::Swapchain(_) => None,
+                        },
+                        &frame_result.overlay_elements,
+                        frame_result.cursor_element,
+                    );
+                }
+
                 let (tx, rx) = std::sync::mpsc::channel();
 
                 let feedback = if !frame_result.is_empty && self.mirroring.is_none() {
@@ -1229,30 +1524,7 @@ impl SurfaceThreadState {
                             self.send_dmabuf_feedback(states);
                         }
 
-                        if x.is_ok() {
-                            let new_state = QueueState::WaitingForVBlank {
-                                redraw_needed: false,
-                            };
-                            match mem::replace(&mut self.state, new_state) {
-                                QueueState::Idle => unreachable!(),
-                                QueueState::Queued(_) => (),
-                                QueueState::WaitingForVBlank { .. } => unreachable!(),
-                                QueueState::WaitingForEstimatedVBlank(estimated_vblank)
-                                | QueueState::WaitingForEstimatedVBlankAndQueued {
-                                    estimated_vblank,
-                                    ..
-                                } => {
-                                    self.loop_handle.remove(estimated_vblank);
-                                }
-                            };
-
-                            if self.mirroring.is_none() {
-                                self.frame_callback_seq = self.frame_callback_seq.wrapping_add(1);
-                                self.send_frame_callbacks();
-                            }
-                        } else {
-                            self.queue_estimated_vblank(estimated_presentation);
-                        }
+                        self.frame_queued(x.is_ok(), estimated_presentation);
                     }
                     Err(err) => {
                         for (session, frame, _) in frames {
@@ -1278,6 +1550,109 @@ impl SurfaceThreadState {
         Ok(())
     }
 
+    /// Composites the output with pixman, if the target gpu doesn't support GL.
+    ///
+    /// Mirroring, zoom, color filters and screencopy require GL and aren't available in this mode.
+    fn redraw_software(&mut self, estimated_presentation: Duration) -> Result<()> {
+        let Some(compositor) = self.compositor.as_mut() else {
+            return Ok(());
+        };
+
+        let mut renderer = self
+            .software_api
+            .single_renderer(&self.target_node)
+            .map_err(|err| anyhow::anyhow!("Failed to get software renderer: {}", err))?;
+
+        self.timings
+            .start_render(&self.clock, estimated_presentation);
+        let elements =
+            software_elements(&mut renderer, &self.shell, self.clock.now(), &self.output).map_err(
+                |err| anyhow::format_err!("Failed to accumulate elements for rendering: {:?}", err),
+            )?;
+        self.timings.elements_done(&self.clock);
+
+        for (session, frame) in self.output.take_pending_frames() {
+            session
+                .user_data()
+                .get::<SessionData>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .reset();
+            frame.fail(FailureReason::Unknown);
+        }
+
+        let res = compositor.render_frame(&mut renderer, &elements, CLEAR_COLOR);
+        self.timings.draw_done(&self.clock);
+
+        match res {
+            Ok(frame_result) => {
+                let feedback = (!frame_result.is_empty).then(|| {
+                    // without screencopy, nothing is ever sent through this channel
+                    let (_, rx) = std::sync::mpsc::channel();
+                    (
+                        self.shell
+                            .read()
+                            .unwrap()
+                            .take_presentation_feedback(&self.output, &frame_result.states),
+                        rx,
+                    )
+                });
+
+                if frame_result.needs_sync() {
+                    if let PrimaryPlaneElement::Swapchain(elem) = &frame_result.primary_element {
+                        elem.sync.wait()?;
+                    }
+                }
+
+                match compositor.queue_frame(feedback) {
+                    x @ Ok(()) | x @ Err(FrameError::EmptyFrame) => {
+                        self.timings.submitted_for_presentation(&self.clock);
+                        let states = frame_result.states;
+                        self.send_dmabuf_feedback(states);
+                        self.frame_queued(x.is_ok(), estimated_presentation);
+                    }
+                    Err(err) => {
+                        return Err(err).with_context(|| "Failed to submit result for display");
+                    }
+                }
+            }
+            Err(err) => {
+                compositor.reset_buffers();
+                anyhow::bail!("Rendering failed: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the queue state after a frame was handed to the display
+    fn frame_queued(&mut self, queued: bool, estimated_presentation: Duration) {
+        if queued {
+            let new_state = QueueState::WaitingForVBlank {
+                redraw_needed: false,
+            };
+            match mem::replace(&mut self.state, new_state) {
+                QueueState::Idle => unreachable!(),
+                QueueState::Queued(_) => (),
+                QueueState::WaitingForVBlank { .. } => unreachable!(),
+                QueueState::WaitingForEstimatedVBlank(estimated_vblank)
+                | QueueState::WaitingForEstimatedVBlankAndQueued {
+                    estimated_vblank, ..
+                } => {
+                    self.loop_handle.remove(estimated_vblank);
+                }
+            };
+
+            if self.mirroring.is_none() {
+                self.frame_callback_seq = self.frame_callback_seq.wrapping_add(1);
+                self.send_frame_callbacks();
+            }
+        } else {
+            self.queue_estimated_vblank(estimated_presentation);
+        }
+    }
+
     fn queue_estimated_vblank(&mut self, target_presentation_time: Duration) {
         match mem::take(&mut self.state) {
             QueueState::Idle => unreachable!(),
@@ -1320,6 +1695,25 @@ impl SurfaceThreadState {
         self.mirroring_textures.clear();
     }
 
+    fn metrics(&self) -> OutputMetrics {
+        let timings = &self.timings;
+        let percentiles = |percentile: fn(&Timings, f64) -> Duration| Percentiles {
+            p50: percentile(timings, 0.5).as_secs_f64() * 1000.0,
+            p90: percentile(timings, 0.9).as_secs_f64() * 1000.0,
+            p99: percentile(timings, 0.99).as_secs_f64() * 1000.0,
+            max: percentile(timings, 1.0).as_secs_f64() * 1000.0,
+        };
+        OutputMetrics {
+            name: self.output.name(),
+            frames: timings.previous_frames.len(),
+            fps: timings.avg_fps(),
+            frame_time: percentiles(Timings::frametime_percentile),
+            render_time: percentiles(Timings::rendertime_percentile),
+            missed_deadlines: timings.missed_frames(timings.previous_frames.len()),
+            missed_deadlines_total: timings.missed_frames_total(),
+        }
+    }
+
     fn send_frame_callbacks(&mut self) {
         if self.mirroring.is_none() {
             let _ = self
@@ -1335,6 +1729,11 @@ impl SurfaceThreadState {
     }
 }
 
+/// Escape hatch for drivers with broken cursor planes
+fn disable_hardware_cursor() -> bool {
+    std::env::var("COSMIC_DISABLE_HW_CURSOR").is_ok_and(|value| value == "1")
+}
+
 fn source_node_for_surface(w: &WlSurface) -> Option<DrmNode> {
     with_renderer_surface_state(w, |state| {
         state
@@ -1348,11 +1747,17 @@ fn render_node_for_output(
     output: &Output,
     primary_node: &DrmNode,
     target_node: &DrmNode,
+    policy: RenderDevicePolicy,
     shell: &Shell,
 ) -> DrmNode {
     if target_node == primary_node {
         return *target_node;
     }
+    match policy {
+        RenderDevicePolicy::PreferPrimary => return *primary_node,
+        RenderDevicePolicy::PreferScanout => return *target_node,
+        RenderDevicePolicy::Auto => {}
+    }
 
     let workspace = shell.active_space(output);
     let nodes = workspace
@@ -1380,7 +1785,8 @@ fn get_surface_dmabuf_feedback(
     target_node: DrmNode,
     render_formats: FormatSet,
     target_formats: FormatSet,
-    plane_formats: FormatSet,
+    primary_plane_formats: FormatSet,
+    overlay_plane_formats: FormatSet,
 ) -> SurfaceDmabufFeedback {
     let combined_formats = render_formats
         .intersection(&target_formats)
@@ -1390,7 +1796,11 @@ fn get_surface_dmabuf_feedback(
     // We limit the scan-out trache to formats we can also render from
     // so that there is always a fallback render path available in case
     // the supplied buffer can not be scanned out directly
-    let planes_formats = plane_formats
+    let primary_formats = primary_plane_formats
+        .intersection(&combined_formats)
+        .copied()
+        .collect::<FormatSet>();
+    let overlay_formats = overlay_plane_formats
         .intersection(&combined_formats)
         .copied()
         .collect::<FormatSet>();
@@ -1411,14 +1821,20 @@ fn get_surface_dmabuf_feedback(
     let render_feedback = builder.clone().build().unwrap();
     // we would want to do this in other cases as well, but same thing as above applies
     let scanout_feedback = if target_node == render_node {
-        builder
-            .add_preference_tranche(
-                target_node.dev_id(),
-                Some(zwp_linux_dmabuf_feedback_v1::TrancheFlags::Scanout),
-                planes_formats,
-            )
-            .build()
-            .unwrap()
+        // Formats of the primary plane come first, as that is the plane
+        // fullscreen surfaces get scanned out on. Formats only overlay planes
+        // support are still preferred over the render-only tranche.
+        let mut builder = builder;
+        for formats in [primary_formats, overlay_formats] {
+            if !formats.is_empty() {
+                builder = builder.add_preference_tranche(
+                    target_node.dev_id(),
+                    Some(zwp_linux_dmabuf_feedback_v1::TrancheFlags::Scanout),
+                    formats,
+                );
+            }
+        }
+        builder.build().unwrap()
     } else {
         builder.build().unwrap()
     };
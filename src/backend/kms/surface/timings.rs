@@ -3,12 +3,18 @@ use std::{collections::VecDeque, num::NonZeroU64, time::Duration};
 use smithay::utils::{Clock, Monotonic, Time};
 use tracing::error;
 
+/// Smallest slack left between submitting a frame and its deadline
 const FRAME_TIME_BUFFER: Duration = Duration::from_millis(1);
-const FRAME_TIME_WINDOW: usize = 3;
+/// Amount of frames considered to predict the time to render the next one
+const PREDICTION_WINDOW: usize = 10;
+/// How quickly the slack shrinks again per frame after a deadline was missed
+const SAFETY_MARGIN_DECAY: Duration = Duration::from_micros(20);
 
 pub struct Timings {
     refresh_interval_ns: Option<NonZeroU64>,
     vrr: bool,
+    safety_margin: Duration,
+    missed_total: usize,
 
     pub pending_frame: Option<PendingFrame>,
     pub previous_frames: VecDeque<Frame>,
@@ -17,6 +23,7 @@ pub struct Timings {
 #[derive(Debug)]
 pub struct PendingFrame {
     render_start: Time<Monotonic>,
+    target_presentation: Option<Duration>,
     render_duration_elements: Option<Duration>,
     render_duration_draw: Option<Duration>,
     presentation_submitted: Option<Time<Monotonic>>,
@@ -25,6 +32,8 @@ pub struct PendingFrame {
 #[derive(Debug)]
 pub struct Frame {
     pub render_start: Time<Monotonic>,
+    /// Presentation time the frame was scheduled for, if it could be estimated
+    pub target_presentation: Option<Duration>,
     pub render_duration_elements: Duration,
     pub render_duration_draw: Duration,
     pub presentation_submitted: Time<Monotonic>,
@@ -36,9 +45,29 @@ impl Frame {
         self.render_duration_elements + self.render_duration_draw
     }
 
-    fn frame_time(&self) -> Duration {
+    pub fn frame_time(&self) -> Duration {
         Time::elapsed(&self.render_start, self.presentation_presented)
     }
+
+    fn submit_time(&self) -> Duration {
+        Time::elapsed(&self.render_start, self.presentation_submitted)
+    }
+
+    /// Time left between submitting the frame and its target presentation,
+    /// negative if it was submitted too late
+    pub fn margin(&self) -> Option<f64> {
+        let target = self.target_presentation?;
+        let submitted: Duration = self.presentation_submitted.into();
+        Some(target.as_secs_f64() - submitted.as_secs_f64())
+    }
+
+    /// Whether the frame was presented at least one refresh later than scheduled
+    pub fn missed(&self, refresh_interval: Duration) -> bool {
+        self.target_presentation.is_some_and(|target| {
+            let presented: Duration = self.presentation_presented.into();
+            presented > target + refresh_interval / 2
+        })
+    }
 }
 
 impl Timings {
@@ -55,6 +84,8 @@ impl Timings {
         Self {
             refresh_interval_ns,
             vrr,
+            safety_margin: FRAME_TIME_BUFFER,
+            missed_total: 0,
 
             pending_frame: None,
             previous_frames: VecDeque::new(),
@@ -74,15 +105,23 @@ impl Timings {
             .and_then(NonZeroU64::new);
 
         self.previous_frames.clear();
+        self.safety_margin = FRAME_TIME_BUFFER;
+    }
+
+    pub fn vrr(&self) -> bool {
+        self.vrr
     }
 
     pub fn set_vrr(&mut self, vrr: bool) {
         self.vrr = vrr;
     }
 
-    pub fn start_render(&mut self, clock: &Clock<Monotonic>) {
+    /// Starts tracking a new frame, meant to be presented at `estimated_presentation`
+    pub fn start_render(&mut self, clock: &Clock<Monotonic>, estimated_presentation: Duration) {
         self.pending_frame = Some(PendingFrame {
             render_start: clock.now(),
+            target_presentation: (!estimated_presentation.is_zero())
+                .then_some(estimated_presentation),
             render_duration_elements: None,
             render_duration_draw: None,
             presentation_submitted: None,
@@ -115,13 +154,26 @@ impl Timings {
 
     pub fn presented(&mut self, value: Time<Monotonic>) {
         if let Some(frame) = self.pending_frame.take() {
-            self.previous_frames.push_back(Frame {
+            let frame = Frame {
                 render_start: frame.render_start,
+                target_presentation: frame.target_presentation,
                 render_duration_elements: frame.render_duration_elements.unwrap_or_default(),
                 render_duration_draw: frame.render_duration_draw.unwrap_or_default(),
                 presentation_submitted: frame.presentation_submitted.unwrap(),
                 presentation_presented: value,
-            });
+            };
+
+            // back off quickly after a miss, but only slowly close in on the deadline again
+            let refresh_interval = self.refresh_interval();
+            self.safety_margin = if frame.missed(refresh_interval) {
+                self.missed_total += 1;
+                (self.safety_margin * 2).min(refresh_interval / 2)
+            } else {
+                self.safety_margin.saturating_sub(SAFETY_MARGIN_DECAY)
+            }
+            .max(FRAME_TIME_BUFFER);
+
+            self.previous_frames.push_back(frame);
             while self.previous_frames.len() > Self::WINDOW_SIZE {
                 self.previous_frames.pop_front();
             }
@@ -195,6 +247,65 @@ impl Timings {
         )
     }
 
+    /// Smallest margin to the deadline of the last `window` frames, in seconds
+    pub fn min_margin(&self, window: usize) -> Option<f64> {
+        self.previous_frames
+            .iter()
+            .rev()
+            .take(window)
+            .filter_map(|f| f.margin())
+            .reduce(f64::min)
+    }
+
+    /// Average margin to the deadline of the last `window` frames, in seconds
+    pub fn avg_margin(&self, window: usize) -> Option<f64> {
+        let margins = self
+            .previous_frames
+            .iter()
+            .rev()
+            .take(window)
+            .filter_map(|f| f.margin())
+            .collect::<Vec<_>>();
+        (!margins.is_empty()).then(|| margins.iter().sum::<f64>() / margins.len() as f64)
+    }
+
+    /// Amount of the last `window` frames, that were presented later than scheduled
+    pub fn missed_frames(&self, window: usize) -> usize {
+        let refresh_interval = self.refresh_interval();
+        self.previous_frames
+            .iter()
+            .rev()
+            .take(window)
+            .filter(|f| f.missed(refresh_interval))
+            .count()
+    }
+
+    /// Frame time below which `percentile` (between 0 and 1) of the last frames were presented
+    pub fn frametime_percentile(&self, percentile: f64) -> Duration {
+        percentile_of(
+            self.previous_frames.iter().map(|f| f.frame_time()),
+            percentile,
+        )
+    }
+
+    /// Render time below which `percentile` (between 0 and 1) of the last frames were rendered
+    pub fn rendertime_percentile(&self, percentile: f64) -> Duration {
+        percentile_of(
+            self.previous_frames.iter().map(|f| f.render_time()),
+            percentile,
+        )
+    }
+
+    /// Amount of all frames so far, that were presented later than scheduled
+    pub fn missed_frames_total(&self) -> usize {
+        self.missed_total
+    }
+
+    /// Slack currently added to the predicted render time
+    pub fn safety_margin(&self) -> Duration {
+        self.safety_margin
+    }
+
     pub fn avg_fps(&self) -> f64 {
         if self.previous_frames.is_empty() {
             return 0.0;
@@ -209,7 +320,11 @@ impl Timings {
         1.0 / (secs / self.previous_frames.len() as f64)
     }
 
-    pub fn next_presentation_time(&self, clock: &Clock<Monotonic>) -> Duration {
+    /// Estimates the monotonic time of the next presentation, or zero if it is unknown.
+    ///
+    /// `fixed_rate` keeps frames aligned to the refresh interval even with VRR,
+    /// so that e.g. cursor updates don't make the refresh rate jump around.
+    pub fn next_presentation_time(&self, clock: &Clock<Monotonic>, fixed_rate: bool) -> Duration {
         let mut now = clock.now().into();
 
         let Some(refresh_interval_ns) = self.refresh_interval_ns else {
@@ -248,23 +363,55 @@ impl Timings {
 
         // If VRR is enabled and more than one frame passed since last presentation, assume that we
         // can present immediately.
-        if self.vrr && to_next_ns > refresh_interval_ns {
-            Duration::ZERO
+        if self.vrr && !fixed_rate && to_next_ns > refresh_interval_ns {
+            now
         } else {
-            last_presentation_time + Duration::from_nanos(to_next_ns) - now
+            last_presentation_time + Duration::from_nanos(to_next_ns)
         }
     }
 
-    pub fn next_render_time(&self, clock: &Clock<Monotonic>) -> Duration {
-        let estimated_presentation_time = self.next_presentation_time(clock);
-        if estimated_presentation_time.is_zero() {
+    /// Predicts how long the next frame takes from starting to render until it is submitted
+    pub fn predicted_render_time(&self) -> Option<Duration> {
+        if self.previous_frames.len() < PREDICTION_WINDOW {
+            return None;
+        }
+
+        self.previous_frames
+            .iter()
+            .rev()
+            .take(PREDICTION_WINDOW)
+            .map(|f| f.submit_time())
+            .max()
+    }
+
+    /// Delay after which rendering should start to just meet `estimated_presentation`.
+    ///
+    /// Composition is pushed as close to the deadline as the predicted render time allows,
+    /// to minimize the latency between input and the frame being displayed.
+    pub fn next_render_time(
+        &self,
+        clock: &Clock<Monotonic>,
+        estimated_presentation: Duration,
+    ) -> Duration {
+        if estimated_presentation.is_zero() {
             return Duration::ZERO;
         }
 
-        let Some(avg_frametime) = self.avg_frametime(FRAME_TIME_WINDOW) else {
+        let Some(render_time) = self.predicted_render_time() else {
             return Duration::ZERO;
         };
 
-        estimated_presentation_time.saturating_sub(avg_frametime + FRAME_TIME_BUFFER)
+        let now: Duration = clock.now().into();
+        estimated_presentation.saturating_sub(now + render_time + self.safety_margin)
+    }
+}
+
+fn percentile_of(durations: impl Iterator<Item = Duration>, percentile: f64) -> Duration {
+    let mut durations = durations.collect::<Vec<_>>();
+    if durations.is_empty() {
+        return Duration::ZERO;
     }
+    durations.sort_unstable();
+    let idx = ((durations.len() - 1) as f64 * percentile.clamp(0.0, 1.0)).round() as usize;
+    durations[idx]
 }
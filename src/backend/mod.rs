@@ -8,6 +8,7 @@ use tracing::{info, warn};
 pub mod render;
 
 pub mod kms;
+pub mod virtual_output;
 pub mod winit;
 pub mod x11;
 // TODO
@@ -65,6 +66,8 @@ pub fn init_backend_auto(
             .unwrap()
             .seats
             .add_seat(initial_seat);
+        state.update_virtual_outputs();
+        state.update_wallpapers();
 
         {
             {
@@ -0,0 +1,593 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Background blur behind translucent windows and layer surfaces.
+//!
+//! For every surface matching the `blur` config, everything below it on the output (windows,
+//! layer surfaces and the wallpaper) is rendered offscreen and blurred with the dual kawase
+//! algorithm, which scales the texture down and up again over a few passes. The matching part
+//! of the result is drawn behind the surface.
+//!
+//! Every blurred surface keeps its own textures in the size of the output, which are only
+//! rendered again, when something below it got damaged.
+
+use crate::{
+    backend::render::{
+        element::{AsGlowRenderer, ClippedRenderElement},
+        wallpaper::wallpaper_elements,
+        ElementFilter, CLEAR_COLOR,
+    },
+    shell::{CosmicMapped, CosmicMappedRenderElement, WorkspaceRenderElement},
+    utils::{prelude::*, quirks::WORKSPACE_OVERVIEW_NAMESPACE},
+    wayland::protocols::workspace::WorkspaceHandle,
+};
+use cosmic_comp_config::BlurConfig;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::{Error as RenderError, OutputDamageTracker},
+            element::{
+                surface::render_elements_from_surface_tree, Element, Id, Kind, RenderElement,
+            },
+            gles::{
+                GlesError, GlesRenderer, GlesTexProgram, GlesTexture, Uniform, UniformName,
+                UniformType,
+            },
+            glow::{GlowFrame, GlowRenderer},
+            utils::CommitCounter,
+            Bind, Frame, Offscreen, Renderer, Texture,
+        },
+    },
+    desktop::{layer_map_for_output, LayerSurface, PopupManager},
+    output::{Output, WeakOutput},
+    reexports::wayland_server::{backend::ObjectId, protocol::wl_surface::WlSurface, Resource},
+    utils::{Buffer as BufferCoords, Physical, Rectangle, Scale, Size, Transform},
+    wayland::{compositor::with_states, seat::WaylandFocus, shell::wlr_layer::Layer},
+};
+use std::{
+    borrow::{Borrow, BorrowMut},
+    cell::RefCell,
+    sync::RwLock,
+};
+
+pub static BLUR_DOWN_SHADER: &str = include_str!("./shaders/blur_down.frag");
+pub static BLUR_UP_SHADER: &str = include_str!("./shaders/blur_up.frag");
+
+/// Every pass halves the size of the texture, so more passes don't make a visible difference
+const MAX_PASSES: u32 = 6;
+
+pub struct BlurShaders {
+    down: GlesTexProgram,
+    up: GlesTexProgram,
+}
+
+impl BlurShaders {
+    pub fn compile(renderer: &mut GlesRenderer) -> Result<BlurShaders, GlesError> {
+        let uniforms = [
+            UniformName::new("halfpixel", UniformType::_2f),
+            UniformName::new("offset", UniformType::_1f),
+        ];
+        Ok(BlurShaders {
+            down: renderer.compile_custom_texture_shader(BLUR_DOWN_SHADER, &uniforms)?,
+            up: renderer.compile_custom_texture_shader(BLUR_UP_SHADER, &uniforms)?,
+        })
+    }
+}
+
+/// Offscreen textures used to blur the background of one surface on an output
+struct SurfaceBlur {
+    output: WeakOutput,
+    surface: ObjectId,
+    damage_tracker: OutputDamageTracker,
+    /// The unblurred background, updated with damage tracking
+    source: GlesTexture,
+    /// The blurred background, followed by the scaled down textures of every pass
+    levels: Vec<GlesTexture>,
+    scale: f64,
+    radius: f32,
+    commit: CommitCounter,
+}
+type BlurCache = RefCell<Vec<SurfaceBlur>>;
+
+impl SurfaceBlur {
+    fn new(
+        renderer: &mut GlowRenderer,
+        output: &Output,
+        surface: ObjectId,
+        size: Size<i32, Physical>,
+        scale: f64,
+        passes: usize,
+    ) -> Result<SurfaceBlur, GlesError> {
+        let buffer_size = size.to_logical(1).to_buffer(1, Transform::Normal);
+        let source =
+            Offscreen::<GlesTexture>::create_buffer(renderer, Fourcc::Abgr8888, buffer_size)?;
+        let levels = (0..=passes)
+            .map(|i| {
+                let size = ((buffer_size.w >> i).max(1), (buffer_size.h >> i).max(1));
+                Offscreen::<GlesTexture>::create_buffer(renderer, Fourcc::Abgr8888, size.into())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SurfaceBlur {
+            output: output.downgrade(),
+            surface,
+            damage_tracker: OutputDamageTracker::new(size, scale, Transform::Normal),
+            source,
+            levels,
+            scale,
+            radius: 0.,
+            commit: CommitCounter::default(),
+        })
+    }
+
+    fn is_outdated(&self, size: Size<i32, Physical>, scale: f64, passes: usize) -> bool {
+        let buffer_size = self.source.size();
+        (buffer_size.w, buffer_size.h) != (size.w, size.h)
+            || self.levels.len() != passes + 1
+            || self.scale != scale
+    }
+
+    /// Renders `elements` and blurs them again, if they were damaged or the radius changed
+    fn render(
+        &mut self,
+        renderer: &mut GlowRenderer,
+        (down, up): &(GlesTexProgram, GlesTexProgram),
+        elements: &[WorkspaceRenderElement<GlowRenderer>],
+        radius: f32,
+    ) -> Result<(), GlesError> {
+        let res = match self.damage_tracker.render_output_with(
+            renderer,
+            self.source.clone(),
+            1,
+            elements,
+            CLEAR_COLOR,
+        ) {
+            Ok(res) => res,
+            Err(RenderError::Rendering(err)) => return Err(err),
+            Err(RenderError::OutputNoMode(_)) => unreachable!(),
+        };
+
+        if res.damage.is_some() || self.radius != radius {
+            renderer.wait(&res.sync)?;
+
+            let renderer = BorrowMut::<GlesRenderer>::borrow_mut(renderer);
+            let levels = &self.levels;
+            for i in 1..levels.len() {
+                let src = if i == 1 { &self.source } else { &levels[i - 1] };
+                blur_pass(renderer, down, src, &levels[i], radius)?;
+            }
+            for i in (0..levels.len() - 1).rev() {
+                blur_pass(renderer, up, &levels[i + 1], &levels[i], radius)?;
+            }
+
+            self.radius = radius;
+            self.commit.increment();
+        }
+
+        Ok(())
+    }
+}
+
+/// Elements of an output from top to bottom, without any blur
+#[derive(Default)]
+struct Scene {
+    elements: Vec<WorkspaceRenderElement<GlowRenderer>>,
+    /// Surfaces to blur the background of, with the index of the first element below them
+    blurred: Vec<(WlSurface, usize)>,
+}
+
+impl Scene {
+    fn collect(
+        renderer: &mut GlowRenderer,
+        shell: &Shell,
+        output: &Output,
+        workspace: &WorkspaceHandle,
+        element_filter: ElementFilter,
+        config: &BlurConfig,
+    ) -> Scene {
+        let mut scene = Scene::default();
+        let scale = output.current_scale().fractional_scale();
+
+        scene.push_layers(renderer, output, Layer::Overlay, element_filter, config);
+        scene.push_layers(renderer, output, Layer::Top, element_filter, config);
+
+        if element_filter != ElementFilter::LayerShellOnly {
+            if let Some(set) = shell.workspaces.sets.get(output) {
+                let sticky = set.sticky_layer.mapped().filter_map(|mapped| {
+                    set.sticky_layer
+                        .element_geometry(mapped)
+                        .map(|geometry| (mapped, geometry))
+                });
+                let windows = set
+                    .workspaces
+                    .iter()
+                    .filter(|w| w.handle == *workspace)
+                    .flat_map(|w| {
+                        w.mapped().filter_map(move |mapped| {
+                            w.element_geometry(mapped)
+                                .map(|geometry| (mapped, geometry))
+                        })
+                    });
+
+                for (mapped, geometry) in sticky.chain(windows) {
+                    let location = (geometry.loc - mapped.geometry().loc.as_local())
+                        .as_logical()
+                        .to_physical_precise_round(scale);
+                    let elements = mapped
+                        .split_render_elements::<_, CosmicMappedRenderElement<GlowRenderer>>(
+                            renderer,
+                            location,
+                            scale.into(),
+                            1.0,
+                        )
+                        .join();
+                    let blurred = config
+                        .windows
+                        .contains(&mapped.active_window().app_id())
+                        .then(|| mapped.wl_surface().map(|surface| surface.into_owned()))
+                        .flatten();
+                    scene.push(elements.into_iter().map(Into::into), blurred);
+                }
+            }
+        }
+
+        scene.push_layers(renderer, output, Layer::Bottom, element_filter, config);
+        scene.push_layers(renderer, output, Layer::Background, element_filter, config);
+        scene.push(
+            wallpaper_elements(renderer, shell, output)
+                .join()
+                .into_iter()
+                .map(WorkspaceRenderElement::from),
+            None,
+        );
+
+        scene
+    }
+
+    fn push_layers(
+        &mut self,
+        renderer: &mut GlowRenderer,
+        output: &Output,
+        layer: Layer,
+        element_filter: ElementFilter,
+        config: &BlurConfig,
+    ) {
+        let layer_map = layer_map_for_output(output);
+        let scale = Scale::from(output.current_scale().fractional_scale());
+
+        for surface in layer_map.layers_on(layer).rev().filter(|s| {
+            !(element_filter == ElementFilter::ExcludeWorkspaceOverview
+                && s.namespace() == WORKSPACE_OVERVIEW_NAMESPACE)
+        }) {
+            let Some(geometry) = layer_map.layer_geometry(surface) else {
+                continue;
+            };
+            let location = geometry.loc.to_physical_precise_round(scale);
+
+            let mut elements = Vec::<WorkspaceRenderElement<GlowRenderer>>::new();
+            for (popup, popup_offset) in PopupManager::popups_for_surface(surface.wl_surface()) {
+                let offset = (popup_offset - popup.geometry().loc)
+                    .to_f64()
+                    .to_physical(scale)
+                    .to_i32_round();
+                elements.extend(render_elements_from_surface_tree(
+                    renderer,
+                    popup.wl_surface(),
+                    location + offset,
+                    scale,
+                    1.0,
+                    Kind::Unspecified,
+                ));
+            }
+            elements.extend(render_elements_from_surface_tree(
+                renderer,
+                surface.wl_surface(),
+                location,
+                scale,
+                1.0,
+                Kind::Unspecified,
+            ));
+
+            let blurred = config
+                .layers
+                .iter()
+                .any(|namespace| namespace == surface.namespace())
+                .then(|| surface.wl_surface().clone());
+            self.push(elements, blurred);
+        }
+    }
+
+    fn push(
+        &mut self,
+        elements: impl IntoIterator<Item = WorkspaceRenderElement<GlowRenderer>>,
+        blurred: Option<WlSurface>,
+    ) {
+        self.elements.extend(elements);
+        if let Some(surface) = blurred {
+            self.blurred.push((surface, self.elements.len()));
+        }
+    }
+}
+
+/// The blurred backgrounds of the surfaces of an output for the current frame
+#[derive(Debug, Clone)]
+pub struct BlurredBackground {
+    size: Size<i32, Physical>,
+    surfaces: Vec<(WlSurface, GlesTexture, CommitCounter)>,
+}
+
+impl BlurredBackground {
+    /// Updates the blurred backgrounds of the surfaces on `workspace` of `output`,
+    /// which are configured to be blurred
+    ///
+    /// The shell is only locked while collecting the elements, not while rendering and blurring them.
+    pub fn for_output<R>(
+        renderer: &mut R,
+        shell: &RwLock<Shell>,
+        output: &Output,
+        workspace: &WorkspaceHandle,
+        element_filter: ElementFilter,
+    ) -> Result<Option<BlurredBackground>, GlesError>
+    where
+        R: AsGlowRenderer,
+    {
+        let config = shell.read().unwrap().blur.clone();
+        if config.passes == 0 || (config.windows.is_empty() && config.layers.is_empty()) {
+            return Ok(None);
+        }
+        let passes = config.passes.min(MAX_PASSES) as usize;
+        let scale = output.current_scale().fractional_scale();
+        let size = output
+            .geometry()
+            .size
+            .as_logical()
+            .to_f64()
+            .to_physical(scale)
+            .to_i32_round();
+        if size.w <= 0 || size.h <= 0 {
+            return Ok(None);
+        }
+
+        let renderer = renderer.glow_renderer_mut();
+        let scene = {
+            let shell = shell.read().unwrap();
+            Scene::collect(renderer, &shell, output, workspace, element_filter, &config)
+        };
+        if scene.blurred.is_empty() {
+            return Ok(None);
+        }
+
+        let (shaders, mut cached) = {
+            let user_data = Borrow::<GlesRenderer>::borrow(&*renderer)
+                .egl_context()
+                .user_data();
+            let shaders = user_data
+                .get::<BlurShaders>()
+                .expect("Custom Shaders not initialized");
+            let shaders = (shaders.down.clone(), shaders.up.clone());
+
+            user_data.insert_if_missing(|| BlurCache::new(Vec::new()));
+            let mut cache = user_data.get::<BlurCache>().unwrap().borrow_mut();
+            cache.retain(|blur| blur.output.upgrade().is_some());
+            // the ones of this output not reused below belong to surfaces, that are gone
+            let (cached, others) = std::mem::take(&mut *cache)
+                .into_iter()
+                .partition::<Vec<_>, _>(|blur| blur.output.upgrade().as_ref() == Some(output));
+            *cache = others;
+            (shaders, cached)
+        };
+
+        let mut blurs = Vec::with_capacity(scene.blurred.len());
+        let mut surfaces = Vec::with_capacity(scene.blurred.len());
+        for (surface, below) in &scene.blurred {
+            let id = surface.id();
+            let blur = cached
+                .iter()
+                .position(|blur| blur.surface == id)
+                .map(|idx| cached.swap_remove(idx))
+                .filter(|blur| !blur.is_outdated(size, scale, passes));
+            let mut blur = match blur {
+                Some(blur) => blur,
+                None => SurfaceBlur::new(renderer, output, id, size, scale, passes)?,
+            };
+            blur.render(renderer, &shaders, &scene.elements[*below..], config.radius)?;
+
+            surfaces.push((surface.clone(), blur.levels[0].clone(), blur.commit));
+            blurs.push(blur);
+        }
+
+        Borrow::<GlesRenderer>::borrow(&*renderer)
+            .egl_context()
+            .user_data()
+            .get::<BlurCache>()
+            .unwrap()
+            .borrow_mut()
+            .extend(blurs);
+
+        Ok(Some(BlurredBackground { size, surfaces }))
+    }
+
+    /// Returns if `window` has a blurred background
+    pub fn wants_window(&self, window: &CosmicMapped) -> bool {
+        window
+            .wl_surface()
+            .is_some_and(|surface| self.texture(&surface).is_some())
+    }
+
+    /// Returns if `layer` has a blurred background
+    pub fn wants_layer(&self, layer: &LayerSurface) -> bool {
+        self.texture(layer.wl_surface()).is_some()
+    }
+
+    fn texture(&self, surface: &WlSurface) -> Option<(&GlesTexture, CommitCounter)> {
+        self.surfaces
+            .iter()
+            .find(|(s, _, _)| s == surface)
+            .map(|(_, texture, commit)| (texture, *commit))
+    }
+
+    /// Blur elements for the `windows` with a blurred background, given their geometry on the output.
+    ///
+    /// The elements are clipped to the rounded corners of the windows.
+    pub fn window_elements<'a, R: AsGlowRenderer>(
+        &self,
+        renderer: &R,
+        windows: impl Iterator<Item = (&'a CosmicMapped, Rectangle<i32, Local>)>,
+        scale: f64,
+    ) -> Vec<ClippedRenderElement<BlurElement>> {
+        windows
+            .filter_map(|(window, geometry)| {
+                let surface = window.wl_surface()?;
+                let geometry = geometry.as_logical().to_physical_precise_round(scale);
+                let elem = self.element(&surface, geometry, 1.0)?;
+                Some(if window.rounded_geometry().is_some() {
+                    let radius = (window.corner_radius() as f64 * scale) as f32;
+                    let texture_size = elem.texture.size();
+                    ClippedRenderElement::new(renderer, elem, geometry, radius, scale.into())
+                        .with_texture_size(texture_size)
+                } else {
+                    ClippedRenderElement::unclipped(elem)
+                })
+            })
+            .collect()
+    }
+
+    /// Blur elements for the windows of `workspace` matching the config
+    pub fn workspace_elements<R: AsGlowRenderer>(
+        &self,
+        renderer: &R,
+        workspace: &Workspace,
+        scale: f64,
+    ) -> Vec<ClippedRenderElement<BlurElement>> {
+        self.window_elements(
+            renderer,
+            workspace.mapped().filter_map(|window| {
+                workspace
+                    .element_geometry(window)
+                    .map(|geometry| (window, geometry))
+            }),
+            scale,
+        )
+    }
+
+    /// Element drawing the blurred background in `geometry`, behind `surface`
+    pub fn element(
+        &self,
+        surface: &WlSurface,
+        geometry: Rectangle<i32, Physical>,
+        alpha: f32,
+    ) -> Option<BlurElement> {
+        let (texture, commit) = self.texture(surface)?;
+        let geometry = geometry.intersection(Rectangle::from_loc_and_size((0, 0), self.size))?;
+        let id = with_states(surface, |states| {
+            states
+                .data_map
+                .insert_if_missing_threadsafe(|| BlurId(Id::new()));
+            states.data_map.get::<BlurId>().unwrap().0.clone()
+        });
+
+        Some(BlurElement {
+            id,
+            texture: texture.clone(),
+            commit,
+            src: Rectangle::from_loc_and_size(
+                (geometry.loc.x as f64, geometry.loc.y as f64),
+                (geometry.size.w as f64, geometry.size.h as f64),
+            ),
+            geometry,
+            alpha,
+        })
+    }
+}
+
+/// Id of the blur element of a surface, to track its damage across frames
+struct BlurId(Id);
+
+fn blur_pass(
+    renderer: &mut GlesRenderer,
+    program: &GlesTexProgram,
+    src: &GlesTexture,
+    dst: &GlesTexture,
+    offset: f32,
+) -> Result<(), GlesError> {
+    let src_size = src.size();
+    let dst_size = dst.size();
+    let area = Rectangle::from_loc_and_size((0, 0), (dst_size.w, dst_size.h));
+
+    renderer.bind(dst.clone())?;
+    let mut frame = renderer.render(area.size, Transform::Normal)?;
+    frame.render_texture_from_to(
+        src,
+        Rectangle::from_loc_and_size((0., 0.), src_size.to_f64()),
+        area,
+        &[area],
+        &[],
+        Transform::Normal,
+        1.0,
+        Some(program),
+        &[
+            Uniform::new(
+                "halfpixel",
+                [0.5 / src_size.w as f32, 0.5 / src_size.h as f32],
+            ),
+            Uniform::new("offset", offset),
+        ],
+    )?;
+    frame.finish()?;
+
+    Ok(())
+}
+
+/// Part of the blurred background of an output, drawn behind a surface
+#[derive(Debug, Clone)]
+pub struct BlurElement {
+    id: Id,
+    texture: GlesTexture,
+    commit: CommitCounter,
+    src: Rectangle<f64, BufferCoords>,
+    geometry: Rectangle<i32, Physical>,
+    alpha: f32,
+}
+
+impl Element for BlurElement {
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.commit
+    }
+
+    fn src(&self) -> Rectangle<f64, BufferCoords> {
+        self.src
+    }
+
+    fn geometry(&self, _scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.geometry
+    }
+
+    fn alpha(&self) -> f32 {
+        self.alpha
+    }
+}
+
+impl RenderElement<GlowRenderer> for BlurElement {
+    fn draw(
+        &self,
+        frame: &mut GlowFrame<'_>,
+        src: Rectangle<f64, BufferCoords>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        opaque_regions: &[Rectangle<i32, Physical>],
+    ) -> Result<(), GlesError> {
+        frame.render_texture_from_to(
+            &self.texture,
+            src,
+            dst,
+            damage,
+            opaque_regions,
+            Transform::Normal,
+            self.alpha,
+        )
+    }
+}
@@ -0,0 +1,387 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Color filters for accessibility, changing the colors of the composited content of an output.
+//!
+//! Like the magnifier this is a post-composition stage: while a filter is active, the output is
+//! rendered offscreen and drawn with a shader applying a color matrix. Nothing is rendered
+//! offscreen while no filter is active. Screencopy clients receive the unfiltered content.
+
+use crate::{
+    backend::render::{
+        element::{AsGlowRenderer, CosmicElement, FromGlesError},
+        CLEAR_COLOR,
+    },
+    shell::CosmicMappedRenderElement,
+    utils::prelude::*,
+};
+use cosmic_comp_config::ColorFilter;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::{Error as RenderError, OutputDamageTracker},
+            element::{
+                texture::{TextureRenderBuffer, TextureRenderElement},
+                Element, Id, Kind, RenderElement, UnderlyingStorage,
+            },
+            gles::{
+                GlesError, GlesFrame, GlesRenderer, GlesTexProgram, GlesTexture, Uniform,
+                UniformName, UniformType,
+            },
+            glow::{GlowFrame, GlowRenderer},
+            utils::{CommitCounter, DamageSet, OpaqueRegions},
+            Bind, ImportAll, ImportMem, Offscreen, Renderer, Texture, TextureFilter,
+        },
+    },
+    output::Output,
+    utils::{Buffer as BufferCoords, Physical, Point, Rectangle, Scale, Size, Transform},
+};
+use std::{
+    borrow::{Borrow, BorrowMut},
+    sync::{Arc, RwLock},
+};
+
+pub static COLOR_FILTER_SHADER: &str = include_str!("./shaders/color_filter.frag");
+
+type Matrix = [[f32; 3]; 3];
+
+const IDENTITY: Matrix = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+// Simulations of the color vision deficiencies at full severity, by Machado et al. (2009)
+const PROTANOPIA: Matrix = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+const DEUTERANOPIA: Matrix = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+const TRITANOPIA: Matrix = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+
+// Moving the lost differences into channels, that can still be told apart
+const SHIFT_RED_GREEN: Matrix = [[0., 0., 0.], [0.7, 1., 0.], [0.7, 0., 1.]];
+const SHIFT_BLUE: Matrix = [[1., 0., 0.7], [0., 1., 0.7], [0., 0., 0.]];
+
+/// Correction adding the error between the original and the `simulation`, moved by `shift`
+fn daltonize(simulation: Matrix, shift: Matrix) -> Matrix {
+    let mut matrix = IDENTITY;
+    for (i, row) in matrix.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value += (0..3)
+                .map(|k| shift[i][k] * (IDENTITY[k][j] - simulation[k][j]))
+                .sum::<f32>();
+        }
+    }
+    matrix
+}
+
+/// Color matrix and offset of `filter`
+fn filter_matrix(filter: ColorFilter) -> (Matrix, [f32; 3]) {
+    match filter {
+        ColorFilter::Grayscale => ([[0.2126, 0.7152, 0.0722]; 3], [0., 0., 0.]),
+        ColorFilter::Inverted => ([[-1., 0., 0.], [0., -1., 0.], [0., 0., -1.]], [1., 1., 1.]),
+        ColorFilter::Protanopia => (daltonize(PROTANOPIA, SHIFT_RED_GREEN), [0., 0., 0.]),
+        ColorFilter::Deuteranopia => (daltonize(DEUTERANOPIA, SHIFT_RED_GREEN), [0., 0., 0.]),
+        ColorFilter::Tritanopia => (daltonize(TRITANOPIA, SHIFT_BLUE), [0., 0., 0.]),
+    }
+}
+
+/// Texture shader applying a color matrix
+pub struct ColorFilterShader(pub GlesTexProgram);
+
+impl ColorFilterShader {
+    pub fn compile(renderer: &mut GlesRenderer) -> Result<ColorFilterShader, GlesError> {
+        renderer
+            .compile_custom_texture_shader(
+                COLOR_FILTER_SHADER,
+                &[
+                    UniformName::new("red", UniformType::_3f),
+                    UniformName::new("green", UniformType::_3f),
+                    UniformName::new("blue", UniformType::_3f),
+                    UniformName::new("offset", UniformType::_3f),
+                ],
+            )
+            .map(ColorFilterShader)
+    }
+
+    pub fn get<R: AsGlowRenderer>(renderer: &R) -> GlesTexProgram {
+        Borrow::<GlesRenderer>::borrow(renderer.glow_renderer())
+            .egl_context()
+            .user_data()
+            .get::<ColorFilterShader>()
+            .expect("Custom Shaders not initialized")
+            .0
+            .clone()
+    }
+}
+
+/// Offscreen copy of the composited output, which is then drawn filtered
+#[derive(Debug)]
+pub struct ColorFilterBuffer {
+    texture: TextureRenderBuffer<GlesTexture>,
+    damage_tracker: OutputDamageTracker,
+    size: Size<i32, BufferCoords>,
+    transform: Transform,
+    /// Changing the filter needs a new texture, so the whole output is damaged
+    filter: ColorFilter,
+}
+
+impl ColorFilterBuffer {
+    fn new<R>(renderer: &mut R, output: &Output, filter: ColorFilter) -> Result<Self, R::Error>
+    where
+        R: Renderer + Offscreen<GlesTexture> + AsGlowRenderer,
+    {
+        let size = Self::buffer_size(output);
+        let opaque_regions = vec![Rectangle::from_loc_and_size((0, 0), size)];
+
+        let texture = Offscreen::<GlesTexture>::create_buffer(renderer, Fourcc::Abgr8888, size)?;
+        let transform = output.current_transform();
+        let texture = TextureRenderBuffer::from_texture(
+            renderer.glow_renderer(),
+            texture,
+            1,
+            transform,
+            Some(opaque_regions),
+        );
+
+        Ok(ColorFilterBuffer {
+            texture,
+            damage_tracker: OutputDamageTracker::from_output(output),
+            size,
+            transform,
+            filter,
+        })
+    }
+
+    fn buffer_size(output: &Output) -> Size<i32, BufferCoords> {
+        output
+            .current_mode()
+            .map(|mode| mode.size)
+            .unwrap_or_default()
+            .to_logical(1)
+            .to_buffer(1, Transform::Normal)
+    }
+
+    /// Returns if the texture doesn't match `output` or `filter` anymore
+    fn is_outdated(&self, output: &Output, filter: ColorFilter) -> bool {
+        self.size != Self::buffer_size(output)
+            || self.transform != output.current_transform()
+            || self.filter != filter
+    }
+}
+
+/// The offscreen copy of an output, drawn with a color filter
+pub struct ColorFilterElement {
+    elem: TextureRenderElement<GlesTexture>,
+    program: GlesTexProgram,
+    filter: ColorFilter,
+}
+
+impl ColorFilterElement {
+    fn uniforms(&self) -> Vec<Uniform<'static>> {
+        let (matrix, offset) = filter_matrix(self.filter);
+        vec![
+            Uniform::new("red", matrix[0]),
+            Uniform::new("green", matrix[1]),
+            Uniform::new("blue", matrix[2]),
+            Uniform::new("offset", offset),
+        ]
+    }
+}
+
+impl Element for ColorFilterElement {
+    fn id(&self) -> &Id {
+        self.elem.id()
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.elem.current_commit()
+    }
+
+    fn src(&self) -> Rectangle<f64, BufferCoords> {
+        self.elem.src()
+    }
+
+    fn geometry(&self, scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.elem.geometry(scale)
+    }
+
+    fn location(&self, scale: Scale<f64>) -> Point<i32, Physical> {
+        self.elem.location(scale)
+    }
+
+    fn transform(&self) -> Transform {
+        self.elem.transform()
+    }
+
+    fn damage_since(
+        &self,
+        scale: Scale<f64>,
+        commit: Option<CommitCounter>,
+    ) -> DamageSet<i32, Physical> {
+        self.elem.damage_since(scale, commit)
+    }
+
+    fn opaque_regions(&self, scale: Scale<f64>) -> OpaqueRegions<i32, Physical> {
+        self.elem.opaque_regions(scale)
+    }
+
+    fn alpha(&self) -> f32 {
+        self.elem.alpha()
+    }
+
+    fn kind(&self) -> Kind {
+        self.elem.kind()
+    }
+}
+
+impl RenderElement<GlowRenderer> for ColorFilterElement {
+    fn draw(
+        &self,
+        frame: &mut GlowFrame<'_>,
+        src: Rectangle<f64, BufferCoords>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        opaque_regions: &[Rectangle<i32, Physical>],
+    ) -> Result<(), GlesError> {
+        BorrowMut::<GlesFrame>::borrow_mut(frame)
+            .override_default_tex_program(self.program.clone(), self.uniforms());
+        let res = RenderElement::<GlowRenderer>::draw(
+            &self.elem,
+            frame,
+            src,
+            dst,
+            damage,
+            opaque_regions,
+        );
+        BorrowMut::<GlesFrame>::borrow_mut(frame).clear_tex_program_override();
+        res
+    }
+
+    fn underlying_storage(&self, _renderer: &mut GlowRenderer) -> Option<UnderlyingStorage> {
+        None
+    }
+}
+
+/// Post-composition stage filtering the colors of `elements`, if a color filter is active.
+///
+/// The elements are rendered into `buffer` and replaced by a single element drawing it with
+/// the filter. `upscale_filter` is used while rendering them, for the magnifier.
+pub fn color_filter_elements<R>(
+    renderer: &mut R,
+    buffer: &mut Option<ColorFilterBuffer>,
+    shell: &Arc<RwLock<Shell>>,
+    output: &Output,
+    elements: Vec<CosmicElement<R>>,
+    upscale_filter: Option<TextureFilter>,
+) -> Result<Vec<CosmicElement<R>>, R::Error>
+where
+    R: Renderer
+        + ImportAll
+        + ImportMem
+        + Bind<GlesTexture>
+        + Offscreen<GlesTexture>
+        + AsGlowRenderer,
+    <R as Renderer>::TextureId: Clone + 'static,
+    <R as Renderer>::Error: FromGlesError,
+    CosmicElement<R>: RenderElement<R>,
+    CosmicMappedRenderElement<R>: RenderElement<R>,
+{
+    let Some(filter) = shell.read().unwrap().color_filter else {
+        // don't keep the texture around while no filter is used
+        *buffer = None;
+        return Ok(elements);
+    };
+
+    if buffer
+        .as_ref()
+        .is_some_and(|buffer| buffer.is_outdated(output, filter))
+    {
+        *buffer = None;
+    }
+    if buffer.is_none() {
+        *buffer = Some(ColorFilterBuffer::new(renderer, output, filter)?);
+    }
+    let filter_buffer = buffer.as_mut().unwrap();
+
+    if let Some(upscale_filter) = upscale_filter {
+        renderer.upscale_filter(upscale_filter)?;
+    }
+    let damage_tracker = &mut filter_buffer.damage_tracker;
+    let res = filter_buffer.texture.render().draw::<_, R::Error>(|tex| {
+        let res = match damage_tracker.render_output_with(
+            renderer,
+            tex.clone(),
+            1,
+            &elements,
+            CLEAR_COLOR,
+        ) {
+            Ok(res) => res,
+            Err(RenderError::Rendering(err)) => return Err(err),
+            Err(RenderError::OutputNoMode(_)) => unreachable!(),
+        };
+
+        renderer.wait(&res.sync)?;
+
+        let transform = output.current_transform();
+        let area = tex.size().to_logical(1, transform);
+
+        Ok(res
+            .damage
+            .cloned()
+            .map(|v| {
+                v.into_iter()
+                    .map(|r| r.to_logical(1).to_buffer(1, transform, &area))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default())
+    });
+    if upscale_filter.is_some() {
+        renderer.upscale_filter(TextureFilter::Linear)?;
+    }
+    res?;
+
+    let elem = TextureRenderElement::from_texture_render_buffer(
+        (0., 0.),
+        &filter_buffer.texture,
+        Some(1.0),
+        None,
+        Some(output.geometry().size.as_logical()),
+        Kind::Unspecified,
+    );
+    Ok(vec![CosmicElement::ColorFilter(ColorFilterElement {
+        elem,
+        program: ColorFilterShader::get(renderer),
+        filter,
+    })])
+}
+
+impl State {
+    /// Applies `filter` to all outputs, or shows colors as they are if `None`
+    pub fn set_color_filter(&mut self, filter: Option<ColorFilter>) {
+        let mut shell = self.common.shell.write().unwrap();
+        if shell.color_filter == filter {
+            return;
+        }
+        shell.color_filter = filter;
+
+        let outputs = shell.outputs().cloned().collect::<Vec<_>>();
+        std::mem::drop(shell);
+        for output in &outputs {
+            self.backend.schedule_render(output);
+        }
+    }
+
+    /// Toggles the configured color filter
+    pub fn toggle_color_filter(&mut self) {
+        let active = self.common.shell.read().unwrap().color_filter.is_some();
+        let filter = self.common.config.cosmic_conf.color_filter.filter;
+        self.set_color_filter((!active).then_some(filter));
+    }
+}
@@ -19,9 +19,7 @@ use smithay::{
     },
     reexports::wayland_server::protocol::wl_surface,
     render_elements,
-    utils::{
-        Buffer as BufferCoords, IsAlive, Logical, Monotonic, Point, Scale, Size, Time, Transform,
-    },
+    utils::{IsAlive, Logical, Monotonic, Physical, Point, Scale, Size, Time, Transform},
     wayland::compositor::{get_role, with_states},
 };
 use std::{collections::HashMap, io::Read, sync::Mutex, time::Duration};
@@ -99,6 +97,19 @@ impl Cursor {
         let size = self.size * scale;
         frame(millis, size, &self.icons)
     }
+
+    /// Returns the image closest to the cursor size at a fractional `scale`
+    /// and its size in logical coordinates.
+    pub fn get_scaled_image(&self, scale: f64, millis: u32) -> (Image, Size<i32, Logical>) {
+        let size = (self.size as f64 * scale).round() as u32;
+        let image = frame(millis, size, &self.icons);
+        // themes might not provide the nominal size, keep the cursor size consistent anyway
+        let logical_size = Size::from((
+            (image.width * self.size / image.size.max(1)) as i32,
+            (image.height * self.size / image.size.max(1)) as i32,
+        ));
+        (image, logical_size)
+    }
 }
 
 fn nearest_images(size: u32, images: &[Image]) -> impl Iterator<Item = &Image> {
@@ -163,7 +174,7 @@ pub fn draw_surface_cursor<R>(
     surface: &wl_surface::WlSurface,
     location: impl Into<Point<i32, Logical>>,
     scale: impl Into<Scale<f64>>,
-) -> Vec<(CursorRenderElement<R>, Point<i32, BufferCoords>)>
+) -> Vec<(CursorRenderElement<R>, Point<i32, Physical>)>
 where
     R: Renderer + ImportAll,
     <R as Renderer>::TextureId: Clone + 'static,
@@ -178,11 +189,7 @@ where
             .lock()
             .unwrap()
             .hotspot
-            .to_buffer(
-                1,
-                Transform::Normal,
-                &Size::from((1, 1)), /* Size doesn't matter for Transform::Normal */
-            )
+            .to_physical_precise_round(scale)
     });
 
     render_elements_from_surface_tree(
@@ -230,6 +237,7 @@ pub type CursorState = Mutex<CursorStateInner>;
 pub struct CursorStateInner {
     current_cursor: CursorShape,
     pub cursors: HashMap<CursorShape, Cursor>,
+    size: u32,
     current_image: Option<Image>,
     image_cache: Vec<(Image, MemoryRenderBuffer)>,
 }
@@ -238,6 +246,17 @@ impl CursorStateInner {
     pub fn set_shape(&mut self, shape: CursorShape) {
         self.current_cursor = shape;
     }
+
+    /// Reloads the cursors at `size`, or the size of `XCURSOR_SIZE` if `None`
+    pub fn set_size(&mut self, size: Option<u32>) {
+        let (theme, default_size) = load_cursor_theme();
+        let size = size.unwrap_or(default_size);
+        if size != self.size {
+            self.cursors = load_cursors(&theme, size);
+            self.size = size;
+            self.image_cache.clear();
+        }
+    }
 }
 
 pub fn load_cursor_theme() -> (CursorTheme, u32) {
@@ -256,64 +275,48 @@ impl Default for CursorStateInner {
         let (theme, size) = load_cursor_theme();
         CursorStateInner {
             current_cursor: CursorShape::Default,
-            cursors: {
-                let mut map = HashMap::new();
-                map.insert(
-                    CursorShape::Default,
-                    Cursor::load(&theme, CursorShape::Default, size),
-                );
-                map.insert(
-                    CursorShape::ColResize,
-                    Cursor::load(&theme, CursorShape::ColResize, size),
-                );
-                map.insert(
-                    CursorShape::RowResize,
-                    Cursor::load(&theme, CursorShape::RowResize, size),
-                );
-                map.insert(
-                    CursorShape::Grab,
-                    Cursor::load(&theme, CursorShape::Grab, size),
-                );
-                map.insert(
-                    CursorShape::NorthResize,
-                    Cursor::load(&theme, CursorShape::NorthResize, size),
-                );
-                map.insert(
-                    CursorShape::SouthResize,
-                    Cursor::load(&theme, CursorShape::SouthResize, size),
-                );
-                map.insert(
-                    CursorShape::EastResize,
-                    Cursor::load(&theme, CursorShape::EastResize, size),
-                );
-                map.insert(
-                    CursorShape::WestResize,
-                    Cursor::load(&theme, CursorShape::WestResize, size),
-                );
-                map.insert(
-                    CursorShape::NorthEastResize,
-                    Cursor::load(&theme, CursorShape::NorthEastResize, size),
-                );
-                map.insert(
-                    CursorShape::SouthEastResize,
-                    Cursor::load(&theme, CursorShape::SouthEastResize, size),
-                );
-                map.insert(
-                    CursorShape::NorthWestResize,
-                    Cursor::load(&theme, CursorShape::NorthWestResize, size),
-                );
-                map.insert(
-                    CursorShape::SouthWestResize,
-                    Cursor::load(&theme, CursorShape::SouthWestResize, size),
-                );
-                map
-            },
+            cursors: load_cursors(&theme, size),
+            size,
             current_image: None,
             image_cache: Vec::new(),
         }
     }
 }
 
+fn load_cursors(theme: &CursorTheme, size: u32) -> HashMap<CursorShape, Cursor> {
+    [
+        CursorShape::Default,
+        CursorShape::ColResize,
+        CursorShape::RowResize,
+        CursorShape::Grab,
+        CursorShape::NorthResize,
+        CursorShape::SouthResize,
+        CursorShape::EastResize,
+        CursorShape::WestResize,
+        CursorShape::NorthEastResize,
+        CursorShape::SouthEastResize,
+        CursorShape::NorthWestResize,
+        CursorShape::SouthWestResize,
+    ]
+    .into_iter()
+    .map(|shape| (shape, Cursor::load(theme, shape, size)))
+    .collect()
+}
+
+impl State {
+    /// Reloads the cursors of all seats at `size`, or the size of `XCURSOR_SIZE` if `None`
+    pub fn set_cursor_size(&mut self, size: Option<u32>) {
+        let shell = self.common.shell.read().unwrap();
+        for seat in shell.seats.iter() {
+            let cursor_state = seat.user_data().get::<CursorState>().unwrap();
+            cursor_state.lock().unwrap().set_size(size);
+        }
+        for output in shell.outputs() {
+            self.backend.schedule_render(output);
+        }
+    }
+}
+
 #[profiling::function]
 pub fn draw_cursor<R>(
     renderer: &mut R,
@@ -322,7 +325,7 @@ pub fn draw_cursor<R>(
     scale: Scale<f64>,
     time: Time<Monotonic>,
     draw_default: bool,
-) -> Vec<(CursorRenderElement<R>, Point<i32, BufferCoords>)>
+) -> Vec<(CursorRenderElement<R>, Point<i32, Physical>)>
 where
     R: Renderer + ImportMem + ImportAll,
     <R as Renderer>::TextureId: Send + Clone + 'static,
@@ -347,15 +350,19 @@ where
         return draw_surface_cursor(renderer, wl_surface, location.to_i32_round(), scale);
     // TODO: Handle other named cursors
     } else if draw_default && CursorImageStatus::default_named() == cursor_status {
-        let integer_scale = scale.x.max(scale.y).ceil() as u32;
-
         let seat_userdata = seat.user_data();
         let mut state_ref = seat_userdata.get::<CursorState>().unwrap().lock().unwrap();
         let state = &mut *state_ref;
-        let frame = state.cursors.get(&state.current_cursor).unwrap().get_image(
-            integer_scale,
-            Into::<Duration>::into(time).as_millis() as u32,
-        );
+        // Pick the image matching the physical size on this output, so it is displayed unscaled,
+        // which allows the cursor to be copied onto the cursor plane as is.
+        let (frame, logical_size) = state
+            .cursors
+            .get(&state.current_cursor)
+            .unwrap()
+            .get_scaled_image(
+                scale.x.max(scale.y),
+                Into::<Duration>::into(time).as_millis() as u32,
+            );
 
         let pointer_images = &mut state.image_cache;
         let maybe_image =
@@ -369,7 +376,7 @@ where
                     &frame.pixels_rgba,
                     Fourcc::Argb8888,
                     (frame.width as i32, frame.height as i32),
-                    integer_scale as i32,
+                    1,
                     Transform::Normal,
                     None,
                 );
@@ -378,7 +385,11 @@ where
             }
         };
 
-        let hotspot = Point::<i32, BufferCoords>::from((frame.xhot as i32, frame.yhot as i32));
+        let hotspot = Point::<f64, Logical>::from((
+            frame.xhot as f64 * logical_size.w as f64 / frame.width as f64,
+            frame.yhot as f64 * logical_size.h as f64 / frame.height as f64,
+        ))
+        .to_physical_precise_round(scale);
         state.current_image = Some(frame);
 
         return vec![(
@@ -389,7 +400,7 @@ where
                     &pointer_image,
                     None,
                     None,
-                    None,
+                    Some(logical_size),
                     Kind::Cursor,
                 )
                 .expect("Failed to import cursor bitmap"),
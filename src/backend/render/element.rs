@@ -1,5 +1,7 @@
 use crate::shell::{CosmicMappedRenderElement, WorkspaceRenderElement};
 
+#[cfg(feature = "debug")]
+use smithay::backend::renderer::element::solid::SolidColorRenderElement;
 use smithay::{
     backend::renderer::{
         element::{
@@ -8,15 +10,19 @@ use smithay::{
             utils::{CropRenderElement, Relocate, RelocateRenderElement, RescaleRenderElement},
             Element, Id, Kind, RenderElement, UnderlyingStorage,
         },
-        gles::{GlesError, GlesTexture},
+        gles::{GlesError, GlesFrame, GlesTexProgram, GlesTexture, Uniform},
         glow::{GlowFrame, GlowRenderer},
         utils::{CommitCounter, DamageSet, OpaqueRegions},
         ImportAll, ImportMem, Renderer,
     },
-    utils::{Buffer as BufferCoords, Logical, Physical, Point, Rectangle, Scale},
+    utils::{Buffer as BufferCoords, Logical, Physical, Point, Rectangle, Scale, Size, Transform},
 };
+use std::borrow::BorrowMut;
 
-use super::{cursor::CursorRenderElement, GlMultiRenderer};
+use super::{
+    color_filter::ColorFilterElement, cursor::CursorRenderElement, GlMultiRenderer,
+    RoundedCornersShader,
+};
 
 pub enum CosmicElement<R>
 where
@@ -34,8 +40,16 @@ where
             RelocateRenderElement<RescaleRenderElement<TextureRenderElement<GlesTexture>>>,
         >,
     ),
+    Zoom(
+        CropRenderElement<
+            RelocateRenderElement<RescaleRenderElement<TextureRenderElement<GlesTexture>>>,
+        >,
+    ),
+    ColorFilter(ColorFilterElement),
     #[cfg(feature = "debug")]
     Egui(TextureRenderElement<GlesTexture>),
+    #[cfg(feature = "debug")]
+    DamageFlash(SolidColorRenderElement),
 }
 
 impl<R> Element for CosmicElement<R>
@@ -52,8 +66,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.id(),
             CosmicElement::AdditionalDamage(elem) => elem.id(),
             CosmicElement::Mirror(elem) => elem.id(),
+            CosmicElement::Zoom(elem) => elem.id(),
+            CosmicElement::ColorFilter(elem) => elem.id(),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.id(),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.id(),
         }
     }
 
@@ -65,8 +83,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.current_commit(),
             CosmicElement::AdditionalDamage(elem) => elem.current_commit(),
             CosmicElement::Mirror(elem) => elem.current_commit(),
+            CosmicElement::Zoom(elem) => elem.current_commit(),
+            CosmicElement::ColorFilter(elem) => elem.current_commit(),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.current_commit(),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.current_commit(),
         }
     }
 
@@ -78,8 +100,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.src(),
             CosmicElement::AdditionalDamage(elem) => elem.src(),
             CosmicElement::Mirror(elem) => elem.src(),
+            CosmicElement::Zoom(elem) => elem.src(),
+            CosmicElement::ColorFilter(elem) => elem.src(),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.src(),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.src(),
         }
     }
 
@@ -91,8 +117,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.geometry(scale),
             CosmicElement::AdditionalDamage(elem) => elem.geometry(scale),
             CosmicElement::Mirror(elem) => elem.geometry(scale),
+            CosmicElement::Zoom(elem) => elem.geometry(scale),
+            CosmicElement::ColorFilter(elem) => elem.geometry(scale),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.geometry(scale),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.geometry(scale),
         }
     }
 
@@ -104,8 +134,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.location(scale),
             CosmicElement::AdditionalDamage(elem) => elem.location(scale),
             CosmicElement::Mirror(elem) => elem.location(scale),
+            CosmicElement::Zoom(elem) => elem.location(scale),
+            CosmicElement::ColorFilter(elem) => elem.location(scale),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.location(scale),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.location(scale),
         }
     }
 
@@ -117,8 +151,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.transform(),
             CosmicElement::AdditionalDamage(elem) => elem.transform(),
             CosmicElement::Mirror(elem) => elem.transform(),
+            CosmicElement::Zoom(elem) => elem.transform(),
+            CosmicElement::ColorFilter(elem) => elem.transform(),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.transform(),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.transform(),
         }
     }
 
@@ -134,8 +172,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.damage_since(scale, commit),
             CosmicElement::AdditionalDamage(elem) => elem.damage_since(scale, commit),
             CosmicElement::Mirror(elem) => elem.damage_since(scale, commit),
+            CosmicElement::Zoom(elem) => elem.damage_since(scale, commit),
+            CosmicElement::ColorFilter(elem) => elem.damage_since(scale, commit),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.damage_since(scale, commit),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.damage_since(scale, commit),
         }
     }
 
@@ -147,8 +189,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.opaque_regions(scale),
             CosmicElement::AdditionalDamage(elem) => elem.opaque_regions(scale),
             CosmicElement::Mirror(elem) => elem.opaque_regions(scale),
+            CosmicElement::Zoom(elem) => elem.opaque_regions(scale),
+            CosmicElement::ColorFilter(elem) => elem.opaque_regions(scale),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.opaque_regions(scale),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.opaque_regions(scale),
         }
     }
 
@@ -160,8 +206,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.alpha(),
             CosmicElement::AdditionalDamage(elem) => elem.alpha(),
             CosmicElement::Mirror(elem) => elem.alpha(),
+            CosmicElement::Zoom(elem) => elem.alpha(),
+            CosmicElement::ColorFilter(elem) => elem.alpha(),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.alpha(),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.alpha(),
         }
     }
 
@@ -173,8 +223,12 @@ where
             CosmicElement::MoveGrab(elem) => elem.kind(),
             CosmicElement::AdditionalDamage(elem) => elem.kind(),
             CosmicElement::Mirror(elem) => elem.kind(),
+            CosmicElement::Zoom(elem) => elem.kind(),
+            CosmicElement::ColorFilter(elem) => elem.kind(),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => elem.kind(),
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.kind(),
         }
     }
 }
@@ -202,7 +256,7 @@ where
             CosmicElement::AdditionalDamage(elem) => {
                 RenderElement::<R>::draw(elem, frame, src, dst, damage, opaque_regions)
             }
-            CosmicElement::Mirror(elem) => {
+            CosmicElement::Mirror(elem) | CosmicElement::Zoom(elem) => {
                 let elem = {
                     let glow_frame = R::glow_frame_mut(frame);
                     RenderElement::<GlowRenderer>::draw(
@@ -217,6 +271,15 @@ where
                 };
                 elem
             }
+            CosmicElement::ColorFilter(elem) => RenderElement::<GlowRenderer>::draw(
+                elem,
+                R::glow_frame_mut(frame),
+                src,
+                dst,
+                damage,
+                opaque_regions,
+            )
+            .map_err(FromGlesError::from_gles_error),
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => {
                 let elem = {
@@ -233,6 +296,10 @@ where
                 };
                 elem
             }
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => {
+                RenderElement::<R>::draw(elem, frame, src, dst, damage, opaque_regions)
+            }
         }
     }
 
@@ -243,7 +310,7 @@ where
             CosmicElement::Dnd(elem) => elem.underlying_storage(renderer),
             CosmicElement::MoveGrab(elem) => elem.underlying_storage(renderer),
             CosmicElement::AdditionalDamage(elem) => elem.underlying_storage(renderer),
-            CosmicElement::Mirror(elem) => {
+            CosmicElement::Mirror(elem) | CosmicElement::Zoom(elem) => {
                 let glow_renderer = renderer.glow_renderer_mut();
                 match elem.underlying_storage(glow_renderer) {
                     Some(UnderlyingStorage::Wayland(buffer)) => {
@@ -252,6 +319,7 @@ where
                     _ => None,
                 }
             }
+            CosmicElement::ColorFilter(_) => None,
             #[cfg(feature = "debug")]
             CosmicElement::Egui(elem) => {
                 let glow_renderer = renderer.glow_renderer_mut();
@@ -262,6 +330,8 @@ where
                     _ => None,
                 }
             }
+            #[cfg(feature = "debug")]
+            CosmicElement::DamageFlash(elem) => elem.underlying_storage(renderer),
         }
     }
 }
@@ -315,6 +385,18 @@ where
     }
 }
 
+#[cfg(feature = "debug")]
+impl<R> From<SolidColorRenderElement> for CosmicElement<R>
+where
+    R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+    <R as Renderer>::TextureId: 'static,
+    CosmicMappedRenderElement<R>: RenderElement<R>,
+{
+    fn from(elem: SolidColorRenderElement) -> Self {
+        Self::DamageFlash(elem)
+    }
+}
+
 pub trait AsGlowRenderer
 where
     Self: Renderer,
@@ -411,6 +493,286 @@ impl<R: Renderer> RenderElement<R> for DamageElement {
     }
 }
 
+/// Element without content, damaging its area whenever its commit changes.
+///
+/// Used for effects changing how other elements are drawn, while the elements themselves stay the same.
+pub struct EffectDamageElement {
+    id: Id,
+    commit: CommitCounter,
+    geometry: Rectangle<i32, Physical>,
+}
+
+impl EffectDamageElement {
+    pub fn new(id: Id, commit: CommitCounter, geometry: Rectangle<i32, Physical>) -> Self {
+        EffectDamageElement {
+            id,
+            commit,
+            geometry,
+        }
+    }
+}
+
+impl Element for EffectDamageElement {
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.commit
+    }
+
+    fn src(&self) -> Rectangle<f64, BufferCoords> {
+        Rectangle::from_loc_and_size((0.0, 0.0), (1.0, 1.0))
+    }
+
+    fn geometry(&self, _scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.geometry
+    }
+
+    fn damage_since(
+        &self,
+        _scale: Scale<f64>,
+        commit: Option<CommitCounter>,
+    ) -> DamageSet<i32, Physical> {
+        if commit == Some(self.commit) {
+            DamageSet::default()
+        } else {
+            DamageSet::from_slice(&[Rectangle::from_loc_and_size((0, 0), self.geometry.size)])
+        }
+    }
+}
+
+impl<R: Renderer> RenderElement<R> for EffectDamageElement {
+    fn draw(
+        &self,
+        _frame: &mut <R as Renderer>::Frame<'_>,
+        _src: Rectangle<f64, BufferCoords>,
+        _dst: Rectangle<i32, Physical>,
+        _damage: &[Rectangle<i32, Physical>],
+        _opaque_regions: &[Rectangle<i32, Physical>],
+    ) -> Result<(), <R as Renderer>::Error> {
+        Ok(())
+    }
+}
+
+/// Element clipped to an area with rounded corners, e.g. the window and header of a mapped element,
+/// which can also be dimmed
+pub struct ClippedRenderElement<E> {
+    elem: E,
+    /// Shader doing the clipping and dimming, `None` if the element is drawn as is
+    program: Option<GlesTexProgram>,
+    /// Area with rounded corners, in the same coordinate space as the geometry of `elem`
+    geometry: Rectangle<i32, Physical>,
+    radius: f32,
+    scale: Scale<f64>,
+    /// Size of the texture drawn by `elem`, if it doesn't draw the whole texture
+    texture_size: Option<Size<i32, BufferCoords>>,
+    dim: f32,
+    desaturate: f32,
+}
+
+impl<E: Element> ClippedRenderElement<E> {
+    pub fn new<R: AsGlowRenderer>(
+        renderer: &R,
+        elem: E,
+        geometry: Rectangle<i32, Physical>,
+        radius: f32,
+        scale: Scale<f64>,
+    ) -> Self {
+        ClippedRenderElement {
+            elem,
+            program: (radius > 0.).then(|| RoundedCornersShader::get(renderer)),
+            geometry,
+            radius,
+            scale,
+            texture_size: None,
+            dim: 0.,
+            desaturate: 0.,
+        }
+    }
+
+    pub fn unclipped(elem: E) -> Self {
+        ClippedRenderElement {
+            elem,
+            program: None,
+            geometry: Rectangle::default(),
+            radius: 0.,
+            scale: Scale::from(1.),
+            texture_size: None,
+            dim: 0.,
+            desaturate: 0.,
+        }
+    }
+
+    /// Sets the size of the whole texture, for elements only drawing a part of it
+    pub fn with_texture_size(mut self, size: Size<i32, BufferCoords>) -> Self {
+        self.texture_size = Some(size);
+        self
+    }
+
+    /// Darkens and desaturates the element by the given amounts between 0.0 and 1.0
+    pub fn with_dim<R: AsGlowRenderer>(mut self, renderer: &R, dim: f32, desaturate: f32) -> Self {
+        self.dim = dim;
+        self.desaturate = desaturate;
+        if self.program.is_none() && (dim > 0. || desaturate > 0.) {
+            self.program = Some(RoundedCornersShader::get(renderer));
+        }
+        self
+    }
+
+    /// The rounded-off corners, relative to the geometry of `elem`
+    fn corners(&self, scale: Scale<f64>) -> [Rectangle<i32, Physical>; 4] {
+        let loc = self.geometry.loc - self.elem.geometry(scale).loc;
+        let size = self.geometry.size;
+        let r = self.radius.ceil() as i32;
+        [
+            Rectangle::from_loc_and_size(loc, (r, r)),
+            Rectangle::from_loc_and_size((loc.x + size.w - r, loc.y), (r, r)),
+            Rectangle::from_loc_and_size((loc.x, loc.y + size.h - r), (r, r)),
+            Rectangle::from_loc_and_size((loc.x + size.w - r, loc.y + size.h - r), (r, r)),
+        ]
+    }
+
+    fn uniforms(&self) -> Vec<Uniform<'static>> {
+        let elem_geo = self.elem.geometry(self.scale);
+        let (src_offset, src_size) = match self.texture_size {
+            Some(size) => {
+                let src = self.elem.src();
+                (
+                    [
+                        (src.loc.x / size.w as f64) as f32,
+                        (src.loc.y / size.h as f64) as f32,
+                    ],
+                    [
+                        (src.size.w / size.w as f64) as f32,
+                        (src.size.h / size.h as f64) as f32,
+                    ],
+                )
+            }
+            None => ([0., 0.], [1., 1.]),
+        };
+
+        vec![
+            Uniform::new(
+                "geo_size",
+                [self.geometry.size.w as f32, self.geometry.size.h as f32],
+            ),
+            Uniform::new(
+                "elem_offset",
+                [
+                    (elem_geo.loc.x - self.geometry.loc.x) as f32,
+                    (elem_geo.loc.y - self.geometry.loc.y) as f32,
+                ],
+            ),
+            Uniform::new(
+                "elem_size",
+                [elem_geo.size.w as f32, elem_geo.size.h as f32],
+            ),
+            Uniform::new("src_offset", src_offset),
+            Uniform::new("src_size", src_size),
+            Uniform::new("radius", self.radius),
+            Uniform::new("dim", self.dim),
+            Uniform::new("saturation", 1. - self.desaturate),
+        ]
+    }
+}
+
+impl<E: Element> Element for ClippedRenderElement<E> {
+    fn id(&self) -> &Id {
+        self.elem.id()
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.elem.current_commit()
+    }
+
+    fn src(&self) -> Rectangle<f64, BufferCoords> {
+        self.elem.src()
+    }
+
+    fn geometry(&self, scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.elem.geometry(scale)
+    }
+
+    fn location(&self, scale: Scale<f64>) -> Point<i32, Physical> {
+        self.elem.location(scale)
+    }
+
+    fn transform(&self) -> Transform {
+        self.elem.transform()
+    }
+
+    fn damage_since(
+        &self,
+        scale: Scale<f64>,
+        commit: Option<CommitCounter>,
+    ) -> DamageSet<i32, Physical> {
+        self.elem.damage_since(scale, commit)
+    }
+
+    fn opaque_regions(&self, scale: Scale<f64>) -> OpaqueRegions<i32, Physical> {
+        let regions = self.elem.opaque_regions(scale);
+        if self.program.is_none() || self.radius <= 0. {
+            return regions;
+        }
+
+        let corners = self.corners(scale);
+        let regions = regions
+            .iter()
+            .flat_map(|region| region.subtract_rects(corners))
+            .collect::<Vec<_>>();
+        OpaqueRegions::from_slice(&regions)
+    }
+
+    fn alpha(&self) -> f32 {
+        self.elem.alpha()
+    }
+
+    fn kind(&self) -> Kind {
+        self.elem.kind()
+    }
+}
+
+impl<R, E> RenderElement<R> for ClippedRenderElement<E>
+where
+    R: AsGlowRenderer + Renderer,
+    <R as Renderer>::Error: FromGlesError,
+    E: RenderElement<R>,
+{
+    fn draw(
+        &self,
+        frame: &mut R::Frame<'_>,
+        src: Rectangle<f64, BufferCoords>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+        opaque_regions: &[Rectangle<i32, Physical>],
+    ) -> Result<(), R::Error> {
+        // the clipping doesn't account for buffer transforms
+        let Some(program) = self
+            .program
+            .as_ref()
+            .filter(|_| self.elem.transform() == Transform::Normal)
+        else {
+            return self.elem.draw(frame, src, dst, damage, opaque_regions);
+        };
+
+        BorrowMut::<GlesFrame>::borrow_mut(R::glow_frame_mut(frame))
+            .override_default_tex_program(program.clone(), self.uniforms());
+        let res = self.elem.draw(frame, src, dst, damage, opaque_regions);
+        BorrowMut::<GlesFrame>::borrow_mut(R::glow_frame_mut(frame)).clear_tex_program_override();
+        res
+    }
+
+    fn underlying_storage(&self, renderer: &mut R) -> Option<UnderlyingStorage> {
+        // scanning out the buffer directly would lose the rounded corners
+        if self.program.is_some() {
+            None
+        } else {
+            self.elem.underlying_storage(renderer)
+        }
+    }
+}
+
 pub trait FromGlesError {
     fn from_gles_error(err: GlesError) -> Self;
 }
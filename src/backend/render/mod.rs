@@ -9,13 +9,20 @@ use std::{
 };
 
 #[cfg(feature = "debug")]
-use crate::debug::fps_ui;
+use crate::debug::{fps_ui, FrameStats};
 use crate::{
     backend::{kms::render::gles::GbmGlowBackend, render::element::DamageElement},
+    bell,
+    input::{
+        locate_pointer::{self, SeatLocatePointer},
+        sticky_keys::SeatStickyKeysState,
+    },
     shell::{
         element::CosmicMappedKey,
         focus::target::WindowGroup,
-        grabs::{SeatMenuGrabState, SeatMoveGrabState},
+        grabs::{
+            SeatMenuGrabState, SeatMoveGrabState, SeatScreenshotGrabState, SeatWindowSwitcherState,
+        },
         layout::tiling::ANIMATION_DURATION,
         CosmicMappedRenderElement, OverviewMode, SeatExt, SessionLock, Trigger, WorkspaceDelta,
         WorkspaceRenderElement,
@@ -26,15 +33,15 @@ use crate::{
     },
     wayland::{
         handlers::{
-            data_device::get_dnd_icon,
-            screencopy::{render_session, FrameHolder, SessionData},
+            data_device::{dnd_icon_location, get_dnd_icon, SeatDnD},
+            screencopy::{output_hidden_areas, render_session, FrameHolder, SessionData},
         },
         protocols::workspace::WorkspaceHandle,
     },
 };
 
 use cosmic::Theme;
-use cosmic_comp_config::workspace::WorkspaceLayout;
+use cosmic_comp_config::{workspace::WorkspaceLayout, ShadowConfig};
 use element::FromGlesError;
 use keyframe::{ease, functions::EaseInOutCubic};
 use smithay::{
@@ -50,8 +57,8 @@ use smithay::{
                 AsRenderElements, Element, Id, Kind, RenderElement,
             },
             gles::{
-                element::PixelShaderElement, GlesError, GlesPixelProgram, GlesRenderer, Uniform,
-                UniformName, UniformType,
+                element::PixelShaderElement, GlesError, GlesPixelProgram, GlesRenderer,
+                GlesTexProgram, GlesTexture, Uniform, UniformName, UniformType,
             },
             glow::GlowRenderer,
             multigpu::{Error as MultiError, MultiFrame, MultiRenderer},
@@ -71,14 +78,25 @@ use smithay::{
     },
 };
 
+use tracing::warn;
+
 #[cfg(feature = "debug")]
 use smithay_egui::EguiState;
 
 pub mod animations;
 
+pub mod blur;
+pub mod color_filter;
 pub mod cursor;
 pub mod element;
+pub mod software;
+pub mod thumbnail;
+pub mod wallpaper;
+pub mod zoom;
+use self::blur::{BlurShaders, BlurredBackground};
+use self::color_filter::ColorFilterShader;
 use self::element::{AsGlowRenderer, CosmicElement};
+use self::wallpaper::wallpaper_elements;
 
 use super::kms::Timings;
 
@@ -114,6 +132,8 @@ impl<'a> AsMut<GlowRenderer> for RendererRef<'a> {
 pub static CLEAR_COLOR: Color32F = Color32F::new(0.153, 0.161, 0.165, 1.0);
 pub static OUTLINE_SHADER: &str = include_str!("./shaders/rounded_outline.frag");
 pub static RECTANGLE_SHADER: &str = include_str!("./shaders/rounded_rectangle.frag");
+pub static ROUNDED_TEXTURE_SHADER: &str = include_str!("./shaders/rounded_texture.frag");
+pub static SHADOW_SHADER: &str = include_str!("./shaders/shadow.frag");
 pub static GROUP_COLOR: [f32; 3] = [0.788, 0.788, 0.788];
 pub static ACTIVE_GROUP_COLOR: [f32; 3] = [0.58, 0.922, 0.922];
 
@@ -127,6 +147,7 @@ pub enum Usage {
     FocusIndicator,
     PotentialGroupIndicator,
     SnappingIndicator,
+    Shadow,
 }
 
 #[derive(Clone)]
@@ -360,11 +381,125 @@ impl BackdropShader {
     }
 }
 
+pub struct ShadowShader(pub GlesPixelProgram);
+
+#[derive(PartialEq)]
+struct ShadowSettings {
+    config: ShadowConfig,
+    radius: f32,
+    alpha: f32,
+    scale: f64,
+}
+type ShadowCache = RefCell<HashMap<Key, (ShadowSettings, PixelShaderElement)>>;
+
+impl ShadowShader {
+    pub fn get<R: AsGlowRenderer>(renderer: &R) -> GlesPixelProgram {
+        Borrow::<GlesRenderer>::borrow(renderer.glow_renderer())
+            .egl_context()
+            .user_data()
+            .get::<ShadowShader>()
+            .expect("Custom Shaders not initialized")
+            .0
+            .clone()
+    }
+
+    /// Element drawing the shadow of a window at `window_geo` with rounded corners of `radius`
+    pub fn element<R: AsGlowRenderer>(
+        renderer: &R,
+        key: impl Into<Key>,
+        window_geo: Rectangle<i32, Local>,
+        radius: f32,
+        alpha: f32,
+        scale: f64,
+        config: &ShadowConfig,
+    ) -> PixelShaderElement {
+        let blur = config.radius as i32;
+        let mut geo = window_geo;
+        geo.loc += (config.offset.0 - blur, config.offset.1 - blur).into();
+        geo.size += (blur * 2, blur * 2).into();
+
+        let settings = ShadowSettings {
+            config: *config,
+            radius,
+            alpha,
+            scale,
+        };
+
+        let user_data = Borrow::<GlesRenderer>::borrow(renderer.glow_renderer())
+            .egl_context()
+            .user_data();
+
+        user_data.insert_if_missing(|| ShadowCache::new(HashMap::new()));
+        let mut cache = user_data.get::<ShadowCache>().unwrap().borrow_mut();
+        cache.retain(|k, _| match k {
+            Key::Static(_) => true,
+            Key::Group(a) => a.upgrade().is_some(),
+            Key::Window(_, w) => w.alive(),
+        });
+
+        let key = key.into();
+        if cache
+            .get(&key)
+            .filter(|(old_settings, _)| &settings == old_settings)
+            .is_none()
+        {
+            let shader = Self::get(renderer);
+            // the shader works in physical pixels, the offset moves the shadow away from the window
+            let window_loc = [
+                ((blur - config.offset.0) as f64 * scale) as f32,
+                ((blur - config.offset.1) as f64 * scale) as f32,
+            ];
+
+            let elem = PixelShaderElement::new(
+                shader,
+                geo.as_logical(),
+                None,
+                alpha * config.opacity,
+                vec![
+                    Uniform::new("window_loc", window_loc),
+                    Uniform::new("blur", (blur as f64 * scale) as f32),
+                    Uniform::new("radius", (radius as f64 * scale) as f32),
+                ],
+                Kind::Unspecified,
+            );
+            cache.insert(key.clone(), (settings, elem));
+        }
+
+        let elem = &mut cache.get_mut(&key).unwrap().1;
+        if elem.geometry(1.0.into()).to_logical(1) != geo.as_logical() {
+            elem.resize(geo.as_logical(), None);
+        }
+        elem.clone()
+    }
+}
+
+/// Texture shader clipping windows to rounded corners and dimming unfocused ones
+pub struct RoundedCornersShader(pub GlesTexProgram);
+
+impl RoundedCornersShader {
+    pub fn get<R: AsGlowRenderer>(renderer: &R) -> GlesTexProgram {
+        Borrow::<GlesRenderer>::borrow(renderer.glow_renderer())
+            .egl_context()
+            .user_data()
+            .get::<RoundedCornersShader>()
+            .expect("Custom Shaders not initialized")
+            .0
+            .clone()
+    }
+}
+
 pub fn init_shaders(renderer: &mut GlesRenderer) -> Result<(), GlesError> {
     {
         let egl_context = renderer.egl_context();
         if egl_context.user_data().get::<IndicatorShader>().is_some()
             && egl_context.user_data().get::<BackdropShader>().is_some()
+            && egl_context
+                .user_data()
+                .get::<RoundedCornersShader>()
+                .is_some()
+            && egl_context.user_data().get::<ShadowShader>().is_some()
+            && egl_context.user_data().get::<BlurShaders>().is_some()
+            && egl_context.user_data().get::<ColorFilterShader>().is_some()
         {
             return Ok(());
         }
@@ -385,6 +520,29 @@ pub fn init_shaders(renderer: &mut GlesRenderer) -> Result<(), GlesError> {
             UniformName::new("radius", UniformType::_1f),
         ],
     )?;
+    let rounded_shader = renderer.compile_custom_texture_shader(
+        ROUNDED_TEXTURE_SHADER,
+        &[
+            UniformName::new("geo_size", UniformType::_2f),
+            UniformName::new("elem_offset", UniformType::_2f),
+            UniformName::new("elem_size", UniformType::_2f),
+            UniformName::new("src_offset", UniformType::_2f),
+            UniformName::new("src_size", UniformType::_2f),
+            UniformName::new("radius", UniformType::_1f),
+            UniformName::new("dim", UniformType::_1f),
+            UniformName::new("saturation", UniformType::_1f),
+        ],
+    )?;
+    let shadow_shader = renderer.compile_custom_pixel_shader(
+        SHADOW_SHADER,
+        &[
+            UniformName::new("window_loc", UniformType::_2f),
+            UniformName::new("blur", UniformType::_1f),
+            UniformName::new("radius", UniformType::_1f),
+        ],
+    )?;
+    let blur_shaders = BlurShaders::compile(renderer)?;
+    let color_filter_shader = ColorFilterShader::compile(renderer)?;
 
     let egl_context = renderer.egl_context();
     egl_context
@@ -393,6 +551,16 @@ pub fn init_shaders(renderer: &mut GlesRenderer) -> Result<(), GlesError> {
     egl_context
         .user_data()
         .insert_if_missing(|| BackdropShader(rectangle_shader));
+    egl_context
+        .user_data()
+        .insert_if_missing(|| RoundedCornersShader(rounded_shader));
+    egl_context
+        .user_data()
+        .insert_if_missing(|| ShadowShader(shadow_shader));
+    egl_context.user_data().insert_if_missing(|| blur_shaders);
+    egl_context
+        .user_data()
+        .insert_if_missing(|| color_filter_shader);
 
     Ok(())
 }
@@ -450,17 +618,54 @@ where
             );
         }
 
+        if let Some((id, radius, alpha)) = seat
+            .user_data()
+            .get::<SeatLocatePointer>()
+            .and_then(|locate| locate.ring())
+        {
+            // the outline shader takes the radius in physical pixels, which has to fit a u8
+            let radius = radius.min(u8::MAX as f64 / scale);
+            let size = (radius * 2.0).round() as i32;
+            let geometry = Rectangle::from_loc_and_size(
+                location.to_i32_round().as_local() - Point::from((size / 2, size / 2)),
+                (size, size),
+            );
+            let accent = theme.cosmic().accent_color();
+            let element = IndicatorShader::element(
+                renderer,
+                id,
+                geometry,
+                locate_pointer::RING_THICKNESS,
+                ((radius - locate_pointer::RING_THICKNESS as f64 / 2.0) * scale) as u8,
+                alpha,
+                scale,
+                [accent.red, accent.green, accent.blue],
+            );
+            elements.push(CosmicMappedRenderElement::from(element).into());
+        }
+
         if !exclude_dnd_icon {
             if let Some(wl_surface) = get_dnd_icon(&seat) {
+                let icon_location = dnd_icon_location(&seat)
+                    .map(|location| location.as_logical() - output.current_location().to_f64())
+                    .unwrap_or(location);
                 elements.extend(
-                    cursor::draw_dnd_icon(renderer, &wl_surface, location.to_i32_round(), scale)
-                        .into_iter()
-                        .map(CosmicElement::Dnd),
+                    cursor::draw_dnd_icon(
+                        renderer,
+                        &wl_surface,
+                        icon_location.to_i32_round(),
+                        scale,
+                    )
+                    .into_iter()
+                    .map(CosmicElement::Dnd),
                 );
             }
         }
 
         let theme = theme.cosmic();
+        if let Some(dnd) = seat.user_data().get::<SeatDnD>() {
+            elements.extend(dnd.render::<CosmicElement<R>, R>(renderer, output, theme));
+        }
         if let Some(grab_elements) = seat
             .user_data()
             .get::<SeatMoveGrabState>()
@@ -484,6 +689,18 @@ where
         {
             elements.extend(grab_elements.into_iter().map(Into::into));
         }
+
+        if let Some(grab_elements) = seat
+            .user_data()
+            .get::<SeatScreenshotGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|state| state.render::<CosmicElement<R>, R>(renderer, output, theme))
+        {
+            elements.extend(grab_elements);
+        }
     }
 
     elements
@@ -491,6 +708,8 @@ where
 
 #[cfg(not(feature = "debug"))]
 pub type EguiState = ();
+#[cfg(not(feature = "debug"))]
+pub type FrameStats = ();
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ElementFilter {
@@ -554,8 +773,9 @@ where
 }
 
 #[profiling::function]
+#[tracing::instrument(skip_all, fields(output = %output.name()))]
 pub fn workspace_elements<R>(
-    _gpu: Option<&DrmNode>,
+    gpu: Option<&DrmNode>,
     renderer: &mut R,
     shell: &Arc<RwLock<Shell>>,
     now: Time<Monotonic>,
@@ -564,7 +784,7 @@ pub fn workspace_elements<R>(
     current: (WorkspaceHandle, usize),
     cursor_mode: CursorMode,
     element_filter: ElementFilter,
-    _fps: Option<(&EguiState, &Timings)>,
+    _fps: Option<(&EguiState, &Timings, &FrameStats)>,
 ) -> Result<Vec<CosmicElement<R>>, RenderError<R>>
 where
     R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
@@ -594,20 +814,48 @@ where
         element_filter == ElementFilter::ExcludeWorkspaceOverview,
     ));
 
+    if element_filter == ElementFilter::All {
+        for seat in &seats {
+            if let Some(switcher) = seat
+                .user_data()
+                .get::<SeatWindowSwitcherState>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .as_ref()
+            {
+                elements
+                    .p_elements
+                    .extend(switcher.render(renderer, output, gpu));
+            }
+            elements.p_elements.extend(
+                seat.user_data()
+                    .get::<SeatStickyKeysState>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .render(renderer, output),
+            );
+        }
+    }
+
     #[cfg(feature = "debug")]
     {
         let output_geo = output.geometry();
         let scale = output.current_scale().fractional_scale();
 
-        if let Some((state, timings)) = _fps {
+        if let Some((state, timings, stats)) = _fps {
             let debug_active = shell.read().unwrap().debug_active;
             let fps_overlay = fps_ui(
-                _gpu,
+                gpu,
                 debug_active,
                 seats.iter(),
                 renderer.glow_renderer_mut(),
+                shell,
+                output,
                 state,
                 timings,
+                stats,
                 Rectangle::from_loc_and_size(
                     (0, 0),
                     (output_geo.size.w.min(400), output_geo.size.h.min(800)),
@@ -620,8 +868,52 @@ where
         }
     }
 
+    // the background is only visible and worth blurring without a lock screen or fullscreen window.
+    // It's rendered offscreen and waited for, so don't hold the shell lock during that,
+    // which would stall the other outputs behind any pending writer.
+    let wants_blur = {
+        let shell = shell.read().unwrap();
+        shell.session_lock.is_none()
+            && shell
+                .workspaces
+                .sets
+                .get(output)
+                .and_then(|set| set.workspaces.iter().find(|w| w.handle == current.0))
+                .is_some_and(|w| {
+                    w.fullscreen
+                        .as_ref()
+                        .filter(|f| !f.is_animating())
+                        .is_none()
+                })
+    };
+    let blur = if wants_blur {
+        BlurredBackground::for_output(renderer, shell, output, &current.0, element_filter)
+            .unwrap_or_else(|err| {
+                warn!(?err, "Failed to blur background.");
+                None
+            })
+    } else {
+        None
+    };
+
     let shell = shell.read().unwrap();
 
+    // Dim everything but the cursor before the outputs are turned off,
+    // unless a game could be scanned out directly
+    if shell.idle_dimmed && !shell.game_mode_active(output) {
+        elements.p_elements.push(
+            WorkspaceRenderElement::from(CosmicMappedRenderElement::from(BackdropShader::element(
+                renderer,
+                shell.idle_dim_id.clone(),
+                Rectangle::from_loc_and_size((0, 0), output.geometry().size.as_local()),
+                0.,
+                0.5,
+                [0.0, 0.0, 0.0],
+            )))
+            .into(),
+        );
+    }
+
     // If session locked, only show session lock surfaces
     if let Some(session_lock) = &shell.session_lock {
         elements.p_elements.extend(
@@ -629,6 +921,14 @@ where
                 .into_iter()
                 .map(|x| WorkspaceRenderElement::from(x).into()),
         );
+        if let Some(fallback) = session_lock.fallback.as_ref() {
+            elements.p_elements.extend(
+                fallback
+                    .render(renderer, output)
+                    .into_iter()
+                    .map(|x| WorkspaceRenderElement::from(x).into()),
+            );
+        }
         return Ok(elements.join());
     }
 
@@ -652,6 +952,11 @@ where
         overview.0,
         overview.1.map(|indicator| (indicator, swap_tree)),
     );
+    // windows are moved around in the overview and while switching workspaces,
+    // so their blur would be misplaced
+    let blur_windows = element_filter != ElementFilter::LayerShellOnly
+        && matches!(overview.0, OverviewMode::None)
+        && previous.is_none();
     let last_active_seat = shell.seats.last_active();
     let move_active = last_active_seat
         .user_data()
@@ -672,12 +977,46 @@ where
         .ok_or(OutputNoMode)?;
     let is_active_space = workspace.outputs().any(|o| o == &active_output);
 
-    let has_fullscreen = workspace
-        .fullscreen
-        .as_ref()
-        .filter(|f| !f.is_animating())
-        .is_some();
-    let overlay_elements = split_layer_elements(renderer, output, Layer::Overlay, element_filter);
+    if let Some(bell) = shell.bell.as_ref().filter(|bell| bell.output == *output) {
+        let alpha = bell.alpha();
+        let element = match bell
+            .window
+            .as_ref()
+            .and_then(|window| workspace.element_geometry(window))
+        {
+            Some(geometry) => {
+                let accent = theme.accent_color();
+                IndicatorShader::focus_element(
+                    renderer,
+                    bell.id.clone(),
+                    geometry,
+                    bell::BORDER_THICKNESS,
+                    output_scale,
+                    alpha,
+                    [accent.red, accent.green, accent.blue],
+                )
+            }
+            None => BackdropShader::element(
+                renderer,
+                bell.id.clone(),
+                Rectangle::from_loc_and_size((0, 0), output_size.as_local()),
+                0.,
+                alpha * 0.3,
+                [1.0, 1.0, 1.0],
+            ),
+        };
+        elements
+            .p_elements
+            .push(WorkspaceRenderElement::from(CosmicMappedRenderElement::from(element)).into());
+    }
+
+    let overlay_elements = split_layer_elements(
+        renderer,
+        output,
+        Layer::Overlay,
+        element_filter,
+        blur.as_ref(),
+    );
 
     // overlay is above everything
     elements
@@ -689,7 +1028,7 @@ where
 
     if !has_fullscreen {
         elements.extend_from_workspace_elements(
-            split_layer_elements(renderer, output, Layer::Top, element_filter),
+            split_layer_elements(renderer, output, Layer::Top, element_filter, blur.as_ref()),
             (0, 0).into(),
         );
     };
@@ -708,6 +1047,8 @@ where
             shell
                 .override_redirect_windows
                 .iter()
+                // topmost first
+                .rev()
                 .filter(|or| {
                     (*or)
                         .geometry()
@@ -761,6 +1102,7 @@ where
                 active_hint,
                 alpha,
                 theme,
+                &shell.shadow,
             ),
             (0, 0).into(),
         );
@@ -814,16 +1156,31 @@ where
                         resize_indicator.clone(),
                         active_hint,
                         theme,
+                        &shell.shadow,
                     )
                     .map_err(|_| OutputNoMode)?,
                 offset.to_physical_precise_round(output_scale),
             );
 
+            if let Some(blur) = blur.as_ref().filter(|_| blur_windows) {
+                elements.extend_from_workspace_elements(
+                    SplitRenderElements {
+                        w_elements: blur.workspace_elements(renderer, workspace, output_scale),
+                        p_elements: Vec::new(),
+                    },
+                    offset.to_physical_precise_round(output_scale),
+                );
+            }
+
             if !has_fullscreen {
                 elements.extend_from_workspace_elements(
                     background_layer_elements(renderer, output, element_filter),
                     offset.to_physical_precise_round(output_scale),
                 );
+                elements.extend_from_workspace_elements(
+                    wallpaper_elements(renderer, &shell, output),
+                    offset.to_physical_precise_round(output_scale),
+                );
             }
 
             Point::<i32, Logical>::from(match (layout, *previous_idx < current.1) {
@@ -846,17 +1203,44 @@ where
                     resize_indicator,
                     active_hint,
                     theme,
+                    &shell.shadow,
                 )
                 .map_err(|_| OutputNoMode)?,
             offset.to_physical_precise_round(output_scale),
         );
     }
 
+    if let Some(blur) = blur.as_ref().filter(|_| blur_windows) {
+        let sticky_windows = set.sticky_layer.mapped().filter_map(|window| {
+            set.sticky_layer
+                .element_geometry(window)
+                .map(|geometry| (window, geometry))
+        });
+        elements.extend_from_workspace_elements(
+            SplitRenderElements {
+                w_elements: blur.window_elements(renderer, sticky_windows, output_scale),
+                p_elements: Vec::new(),
+            },
+            (0, 0).into(),
+        );
+        elements.extend_from_workspace_elements(
+            SplitRenderElements {
+                w_elements: blur.workspace_elements(renderer, workspace, output_scale),
+                p_elements: Vec::new(),
+            },
+            offset.to_physical_precise_round(output_scale),
+        );
+    }
+
     if !has_fullscreen {
         elements.extend_from_workspace_elements(
             background_layer_elements(renderer, output, element_filter),
             offset.to_physical_precise_round(output_scale),
         );
+        elements.extend_from_workspace_elements(
+            wallpaper_elements(renderer, &shell, output),
+            offset.to_physical_precise_round(output_scale),
+        );
     }
 
     Ok(elements.join())
@@ -867,6 +1251,7 @@ pub fn split_layer_elements<R>(
     output: &Output,
     layer: Layer,
     element_filter: ElementFilter,
+    blur: Option<&BlurredBackground>,
 ) -> SplitRenderElements<WorkspaceRenderElement<R>>
 where
     R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
@@ -887,14 +1272,10 @@ where
             !(element_filter == ElementFilter::ExcludeWorkspaceOverview
                 && s.namespace() == WORKSPACE_OVERVIEW_NAMESPACE)
         })
-        .filter_map(|surface| {
-            layer_map
-                .layer_geometry(surface)
-                .map(|geo| (geo.loc, surface))
-        })
-        .for_each(|(location, surface)| {
-            let location = location.to_physical_precise_round(output_scale);
-            let surface = surface.wl_surface();
+        .filter_map(|surface| layer_map.layer_geometry(surface).map(|geo| (geo, surface)))
+        .for_each(|(geometry, layer)| {
+            let location = geometry.loc.to_physical_precise_round(output_scale);
+            let surface = layer.wl_surface();
             let scale = Scale::from(output_scale);
 
             elements
@@ -927,6 +1308,17 @@ where
                     1.0,
                     Kind::Unspecified,
                 ));
+
+            if let Some(blur) = blur.filter(|blur| blur.wants_layer(layer)) {
+                elements.w_elements.extend(
+                    blur.element(
+                        surface,
+                        geometry.to_physical_precise_round(output_scale),
+                        1.0,
+                    )
+                    .map(Into::into),
+                );
+            }
         });
 
     elements
@@ -945,12 +1337,13 @@ where
     CosmicMappedRenderElement<R>: RenderElement<R>,
     WorkspaceRenderElement<R>: RenderElement<R>,
 {
-    let mut elements = split_layer_elements(renderer, output, Layer::Bottom, element_filter);
+    let mut elements = split_layer_elements(renderer, output, Layer::Bottom, element_filter, None);
     elements.extend(split_layer_elements(
         renderer,
         output,
         Layer::Background,
         element_filter,
+        None,
     ));
     elements
 }
@@ -980,6 +1373,7 @@ where
 }
 
 #[profiling::function]
+#[tracing::instrument(skip_all, fields(output = %output.name()))]
 pub fn render_output<'d, R, Target, OffTarget>(
     gpu: Option<&DrmNode>,
     renderer: &mut R,
@@ -998,7 +1392,9 @@ where
         + ExportMem
         + Bind<Dmabuf>
         + Bind<Target>
+        + Bind<GlesTexture>
         + Offscreen<OffTarget>
+        + Offscreen<GlesTexture>
         + Blit<Target>
         + AsGlowRenderer,
     <R as Renderer>::TextureId: Send + Clone + 'static,
@@ -1023,13 +1419,9 @@ where
         ElementFilter::All
     };
 
-    let result = render_workspace(
+    let elements = workspace_elements(
         gpu,
         renderer,
-        target.clone(),
-        damage_tracker,
-        age,
-        None,
         shell,
         now,
         output,
@@ -1037,16 +1429,73 @@ where
         workspace,
         cursor_mode,
         element_filter,
+        None,
+    )?;
+
+    let zoom_buffer = output
+        .user_data()
+        .get_or_insert::<RefCell<Option<zoom::ZoomBuffer>>, _>(Default::default);
+    let elements = zoom::zoom_elements(
+        renderer,
+        &mut zoom_buffer.borrow_mut(),
+        shell,
+        output,
+        elements,
+    )
+    .map_err(RenderError::Rendering)?;
+    let upscale_filter = {
+        let shell = shell.read().unwrap();
+        zoom::output_zoom(&shell, output).map(|_| zoom::upscale_filter(&shell))
+    };
+
+    let color_filter_buffer = output
+        .user_data()
+        .get_or_insert::<RefCell<Option<color_filter::ColorFilterBuffer>>, _>(Default::default);
+    let mut elements = color_filter::color_filter_elements(
+        renderer,
+        &mut color_filter_buffer.borrow_mut(),
+        shell,
+        output,
+        elements,
+        upscale_filter,
+    )
+    .map_err(RenderError::Rendering)?;
+
+    renderer
+        .bind(target.clone())
+        .map_err(RenderError::Rendering)?;
+    if let Some(filter) = upscale_filter {
+        renderer
+            .upscale_filter(filter)
+            .map_err(RenderError::Rendering)?;
+    }
+    let result = damage_tracker.render_output(
+        renderer,
+        age,
+        &elements,
+        CLEAR_COLOR, // TODO use a theme neutral color
     );
+    if upscale_filter.is_some() {
+        renderer
+            .upscale_filter(TextureFilter::Linear)
+            .map_err(RenderError::Rendering)?;
+    }
 
     match result {
-        Ok((res, mut elements)) => {
-            for (session, frame) in output.take_pending_frames() {
+        Ok(res) => {
+            let frames = output.take_pending_frames();
+            let blanked = if frames.is_empty() {
+                Vec::new()
+            } else {
+                output_hidden_areas(&shell.read().unwrap(), output)
+            };
+            for (session, frame) in frames {
                 if let Some((frame, damage)) = render_session(
                     renderer,
                     &session.user_data().get::<SessionData>().unwrap(),
                     frame,
                     output.current_transform(),
+                    &blanked,
                     |buffer, renderer, dt, age, additional_damage| {
                         let old_len = if !additional_damage.is_empty() {
                             let area = output
@@ -1135,6 +1584,7 @@ where
 }
 
 #[profiling::function]
+#[tracing::instrument(skip_all, fields(output = %output.name()))]
 pub fn render_workspace<'d, R, Target, OffTarget>(
     gpu: Option<&DrmNode>,
     renderer: &mut R,
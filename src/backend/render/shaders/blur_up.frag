@@ -0,0 +1,49 @@
+#version 100
+
+//_DEFINES_
+
+#if defined(EXTERNAL)
+#extension GL_OES_EGL_image_external : require
+#endif
+
+precision mediump float;
+#if defined(EXTERNAL)
+uniform samplerExternalOES tex;
+#else
+uniform sampler2D tex;
+#endif
+uniform float alpha;
+varying vec2 v_coords;
+
+#if defined(DEBUG_FLAGS)
+uniform float tint;
+#endif
+
+uniform vec2 halfpixel;
+uniform float offset;
+
+// Upsampling pass of the dual kawase blur
+void main() {
+    vec4 sum = texture2D(tex, v_coords + vec2(-halfpixel.x * 2.0, 0.0) * offset);
+    sum += texture2D(tex, v_coords + vec2(-halfpixel.x, halfpixel.y) * offset) * 2.0;
+    sum += texture2D(tex, v_coords + vec2(0.0, halfpixel.y * 2.0) * offset);
+    sum += texture2D(tex, v_coords + vec2(halfpixel.x, halfpixel.y) * offset) * 2.0;
+    sum += texture2D(tex, v_coords + vec2(halfpixel.x * 2.0, 0.0) * offset);
+    sum += texture2D(tex, v_coords + vec2(halfpixel.x, -halfpixel.y) * offset) * 2.0;
+    sum += texture2D(tex, v_coords + vec2(0.0, -halfpixel.y * 2.0) * offset);
+    sum += texture2D(tex, v_coords + vec2(-halfpixel.x, -halfpixel.y) * offset) * 2.0;
+    vec4 color = sum / 12.0;
+
+#if defined(NO_ALPHA)
+    color = vec4(color.rgb, 1.0) * alpha;
+#else
+    color = color * alpha;
+#endif
+
+#if defined(DEBUG_FLAGS)
+    if (tint == 1.0)
+        color = vec4(0.0, 0.3, 0.0, 0.2) + color * 0.8;
+#endif
+
+    gl_FragColor = color;
+}
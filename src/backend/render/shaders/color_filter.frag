@@ -0,0 +1,48 @@
+#version 100
+
+//_DEFINES_
+
+#if defined(EXTERNAL)
+#extension GL_OES_EGL_image_external : require
+#endif
+
+precision mediump float;
+#if defined(EXTERNAL)
+uniform samplerExternalOES tex;
+#else
+uniform sampler2D tex;
+#endif
+uniform float alpha;
+varying vec2 v_coords;
+
+#if defined(DEBUG_FLAGS)
+uniform float tint;
+#endif
+
+// rows of the color matrix
+uniform vec3 red;
+uniform vec3 green;
+uniform vec3 blue;
+// added to every channel, scaled by alpha
+uniform vec3 offset;
+
+void main() {
+    vec4 color = texture2D(tex, v_coords);
+
+#if defined(NO_ALPHA)
+    color = vec4(color.rgb, 1.0) * alpha;
+#else
+    color = color * alpha;
+#endif
+
+    // the color is premultiplied, so the offset has to be as well
+    vec3 filtered = vec3(dot(red, color.rgb), dot(green, color.rgb), dot(blue, color.rgb));
+    color.rgb = clamp(filtered + offset * color.a, 0.0, color.a);
+
+#if defined(DEBUG_FLAGS)
+    if (tint == 1.0)
+        color = vec4(0.0, 0.3, 0.0, 0.2) + color * 0.8;
+#endif
+
+    gl_FragColor = color;
+}
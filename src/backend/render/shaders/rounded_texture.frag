@@ -0,0 +1,63 @@
+#version 100
+
+//_DEFINES_
+
+#if defined(EXTERNAL)
+#extension GL_OES_EGL_image_external : require
+#endif
+
+precision mediump float;
+#if defined(EXTERNAL)
+uniform samplerExternalOES tex;
+#else
+uniform sampler2D tex;
+#endif
+uniform float alpha;
+varying vec2 v_coords;
+
+#if defined(DEBUG_FLAGS)
+uniform float tint;
+#endif
+
+// size of the rounded area
+uniform vec2 geo_size;
+// location and size of the drawn element relative to the rounded area
+uniform vec2 elem_offset;
+uniform vec2 elem_size;
+// part of the texture drawn by the element, in texture coordinates
+uniform vec2 src_offset;
+uniform vec2 src_size;
+uniform float radius;
+// darkening and remaining saturation of unfocused windows
+uniform float dim;
+uniform float saturation;
+
+float rounded_box(vec2 center, vec2 size, float radius) {
+    return length(max(abs(center) - size + radius, 0.0)) - radius;
+}
+
+void main() {
+    vec4 color = texture2D(tex, v_coords);
+
+#if defined(NO_ALPHA)
+    color = vec4(color.rgb, 1.0) * alpha;
+#else
+    color = color * alpha;
+#endif
+
+    // the color is premultiplied, so this leaves the alpha untouched
+    float luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    color.rgb = mix(vec3(luma), color.rgb, saturation) * (1.0 - dim);
+
+    vec2 location = (v_coords - src_offset) / src_size * elem_size + elem_offset;
+    vec2 center = geo_size / 2.0;
+    float distance = rounded_box(location - center, geo_size / 2.0, radius);
+    color = color * (1.0 - smoothstep(0.0, 1.0, distance));
+
+#if defined(DEBUG_FLAGS)
+    if (tint == 1.0)
+        color = vec4(0.0, 0.3, 0.0, 0.2) + color * 0.8;
+#endif
+
+    gl_FragColor = color;
+}
@@ -0,0 +1,37 @@
+precision mediump float;
+uniform float alpha;
+#if defined(DEBUG_FLAGS)
+uniform float tint;
+#endif
+uniform vec2 size;
+varying vec2 v_coords;
+
+uniform vec2 window_loc;
+uniform float blur;
+uniform float radius;
+
+float rounded_box(vec2 center, vec2 size, float radius) {
+    return length(max(abs(center) - size + radius, 0.0)) - radius;
+}
+
+void main() {
+    vec2 window_size = size - 2.0 * blur;
+    vec2 location = v_coords * size;
+
+    float softness = max(blur, 0.5);
+    float shadow_distance = rounded_box(location - size / 2.0, window_size / 2.0, radius);
+    float shadow = 1.0 - smoothstep(-softness, softness, shadow_distance);
+
+    // translucent windows shouldn't show their own shadow
+    float window_distance = rounded_box(location - window_loc - window_size / 2.0, window_size / 2.0, radius);
+    float outside = clamp(window_distance + 0.5, 0.0, 1.0);
+
+    vec4 mix_color = vec4(0.0, 0.0, 0.0, alpha * shadow * outside);
+
+#if defined(DEBUG_FLAGS)
+    if (tint == 1.0)
+        mix_color = vec4(0.0, 0.3, 0.0, 0.2) + mix_color * 0.8;
+#endif
+
+    gl_FragColor = mix_color;
+}
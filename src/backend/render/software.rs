@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Composition without GL, for gpus without a working EGL implementation.
+//!
+//! Only surfaces, window headers and cursors are drawn. Effects relying on custom shaders
+//! like blur, shadows, rounded corners or focus indicators are skipped.
+
+use crate::{
+    backend::render::{
+        cursor::{self, CursorRenderElement},
+        session_lock_elements, SplitRenderElements,
+    },
+    shell::{
+        element::{stack::CosmicStackRenderElement, window::CosmicWindowRenderElement},
+        grabs::SeatMoveGrabState,
+        Shell,
+    },
+    utils::prelude::*,
+    wayland::handlers::data_device::{dnd_icon_location, get_dnd_icon},
+};
+use smithay::{
+    backend::renderer::{
+        element::{
+            render_elements,
+            solid::SolidColorRenderElement,
+            surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+            utils::{Relocate, RelocateRenderElement},
+            AsRenderElements, Kind,
+        },
+        ImportAll, ImportMem, Renderer,
+    },
+    desktop::{layer_map_for_output, space::SpaceElement, PopupManager},
+    output::{Output, OutputNoMode},
+    utils::{Monotonic, Point, Scale, Time},
+    wayland::shell::wlr_layer::Layer,
+};
+use std::sync::{Arc, RwLock};
+
+render_elements! {
+    pub SoftwareRenderElement<R> where R: ImportAll + ImportMem;
+    Surface = WaylandSurfaceRenderElement<R>,
+    SolidColor = SolidColorRenderElement,
+    Window = CosmicWindowRenderElement<R>,
+    Stack = CosmicStackRenderElement<R>,
+    Cursor = RelocateRenderElement<CursorRenderElement<R>>,
+}
+
+/// Elements of the active workspace of `output`, ordered front to back
+#[profiling::function]
+pub fn software_elements<R>(
+    renderer: &mut R,
+    shell: &Arc<RwLock<Shell>>,
+    now: Time<Monotonic>,
+    output: &Output,
+) -> Result<Vec<SoftwareRenderElement<R>>, OutputNoMode>
+where
+    R: Renderer + ImportAll + ImportMem,
+    <R as Renderer>::TextureId: Send + Clone + 'static,
+{
+    let scale = output.current_scale().fractional_scale();
+    let mut elements = Vec::new();
+
+    let seats = shell
+        .read()
+        .unwrap()
+        .seats
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    for seat in &seats {
+        let Some(pointer) = seat.get_pointer() else {
+            continue;
+        };
+        let location = pointer.current_location() - output.current_location().to_f64();
+
+        elements.extend(
+            cursor::draw_cursor(renderer, seat, location, scale.into(), now, true)
+                .into_iter()
+                .map(|(elem, hotspot)| {
+                    SoftwareRenderElement::Cursor(RelocateRenderElement::from_element(
+                        elem,
+                        Point::from((-hotspot.x, -hotspot.y)),
+                        Relocate::Relative,
+                    ))
+                }),
+        );
+        if let Some(wl_surface) = get_dnd_icon(seat) {
+            let icon_location = dnd_icon_location(seat)
+                .map(|location| location.as_logical() - output.current_location().to_f64())
+                .unwrap_or(location);
+            elements.extend(
+                cursor::draw_dnd_icon(renderer, &wl_surface, icon_location.to_i32_round(), scale)
+                    .into_iter()
+                    .map(SoftwareRenderElement::Surface),
+            );
+        }
+        if let Some(grab_elements) = seat
+            .user_data()
+            .get::<SeatMoveGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|state| state.render_software::<SoftwareRenderElement<R>, R>(renderer, output))
+        {
+            elements.extend(grab_elements);
+        }
+    }
+
+    let shell = shell.read().unwrap();
+
+    if let Some(session_lock) = &shell.session_lock {
+        elements.extend(
+            session_lock_elements(renderer, output, session_lock)
+                .into_iter()
+                .map(SoftwareRenderElement::Surface),
+        );
+        return Ok(elements);
+    }
+
+    let set = shell.workspaces.sets.get(output).ok_or(OutputNoMode)?;
+    let workspace = &set.workspaces[set.active];
+
+    elements.extend(layer_elements(renderer, output, Layer::Overlay));
+
+    elements.extend(
+        shell
+            .override_redirect_windows
+            .iter()
+            // topmost first
+            .rev()
+            .filter(|or| {
+                (*or)
+                    .geometry()
+                    .as_global()
+                    .intersection(output.geometry())
+                    .is_some()
+            })
+            .flat_map(|or| {
+                AsRenderElements::<R>::render_elements::<SoftwareRenderElement<R>>(
+                    or,
+                    renderer,
+                    (or.geometry().loc - output.geometry().loc.as_logical())
+                        .to_physical_precise_round(scale),
+                    Scale::from(scale),
+                    1.0,
+                )
+            }),
+    );
+
+    if let Some(fullscreen) = workspace.get_fullscreen() {
+        elements.extend(
+            fullscreen
+                .split_render_elements::<R, SoftwareRenderElement<R>>(
+                    renderer,
+                    (0, 0).into(),
+                    Scale::from(scale),
+                    1.0,
+                )
+                .join(),
+        );
+        return Ok(elements);
+    }
+
+    elements.extend(layer_elements(renderer, output, Layer::Top));
+
+    let mut windows = SplitRenderElements::default();
+    let sticky = set
+        .sticky_layer
+        .mapped()
+        .filter_map(|mapped| Some((mapped, set.sticky_layer.element_geometry(mapped)?)));
+    let floating = workspace
+        .floating_layer
+        .mapped()
+        .filter_map(|mapped| Some((mapped, workspace.floating_layer.element_geometry(mapped)?)));
+    for (mapped, geometry) in sticky
+        .chain(floating)
+        .chain(workspace.tiling_layer.mapped())
+    {
+        let render_location = geometry.loc - mapped.geometry().loc.as_local();
+        windows.extend(
+            mapped.software_render_elements::<R, SoftwareRenderElement<R>>(
+                renderer,
+                render_location
+                    .as_logical()
+                    .to_physical_precise_round(scale),
+                Scale::from(scale),
+                1.0,
+            ),
+        );
+    }
+    elements.extend(windows.join());
+
+    elements.extend(layer_elements(renderer, output, Layer::Bottom));
+    elements.extend(layer_elements(renderer, output, Layer::Background));
+
+    Ok(elements)
+}
+
+fn layer_elements<R>(
+    renderer: &mut R,
+    output: &Output,
+    layer: Layer,
+) -> Vec<SoftwareRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    <R as Renderer>::TextureId: Clone + 'static,
+{
+    let layer_map = layer_map_for_output(output);
+    let scale = Scale::from(output.current_scale().fractional_scale());
+
+    let mut elements = SplitRenderElements::default();
+    for (geometry, surface) in layer_map
+        .layers_on(layer)
+        .rev()
+        .filter_map(|surface| layer_map.layer_geometry(surface).map(|geo| (geo, surface)))
+    {
+        let location = geometry.loc.to_physical_precise_round(scale);
+        let surface = surface.wl_surface();
+
+        elements
+            .p_elements
+            .extend(
+                PopupManager::popups_for_surface(surface).flat_map(|(popup, popup_offset)| {
+                    let offset = (popup_offset - popup.geometry().loc)
+                        .to_f64()
+                        .to_physical(scale)
+                        .to_i32_round();
+
+                    render_elements_from_surface_tree(
+                        renderer,
+                        popup.wl_surface(),
+                        location + offset,
+                        scale,
+                        1.0,
+                        Kind::Unspecified,
+                    )
+                }),
+            );
+        elements
+            .w_elements
+            .extend(render_elements_from_surface_tree(
+                renderer,
+                surface,
+                location,
+                scale,
+                1.0,
+                Kind::Unspecified,
+            ));
+    }
+
+    elements.join()
+}
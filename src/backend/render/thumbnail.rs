@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Downscaled renderings of windows, kept up to date while in use.
+
+use crate::{
+    backend::render::{element::AsGlowRenderer, RendererRef},
+    shell::CosmicSurface,
+    utils::prelude::*,
+};
+use anyhow::{Context, Result};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        drm::DrmNode,
+        renderer::{
+            damage::{Error as RenderError, OutputDamageTracker},
+            element::{
+                surface::WaylandSurfaceRenderElement, texture::TextureRenderBuffer,
+                AsRenderElements,
+            },
+            gles::GlesTexture,
+            ImportAll, Offscreen, Renderer,
+        },
+    },
+    reexports::calloop::{
+        timer::{TimeoutAction, Timer},
+        RegistrationToken,
+    },
+    utils::{Buffer as BufferCoords, IsAlive, Physical, Scale, Size, Transform},
+};
+use std::{collections::HashSet, time::Duration};
+use tracing::{error, warn};
+
+/// Longest edge of a thumbnail in pixels
+pub const THUMBNAIL_SIZE: i32 = 256;
+/// How often thumbnails are updated, while they are in use
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Parts of the compositor displaying thumbnails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThumbnailConsumer {
+    Switcher,
+}
+
+pub struct Thumbnail {
+    pub texture: TextureRenderBuffer<GlesTexture>,
+    damage_tracker: OutputDamageTracker,
+    size: Size<i32, BufferCoords>,
+}
+
+impl std::fmt::Debug for Thumbnail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Thumbnail")
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ThumbnailState {
+    consumers: HashSet<ThumbnailConsumer>,
+    timer: Option<RegistrationToken>,
+    /// Gpu the textures belong to
+    node: Option<DrmNode>,
+    windows: Vec<(CosmicSurface, Thumbnail)>,
+}
+
+impl ThumbnailState {
+    /// Returns the latest thumbnail of a window, if thumbnails are in use
+    pub fn window(&self, window: &CosmicSurface) -> Option<&TextureRenderBuffer<GlesTexture>> {
+        self.windows
+            .iter()
+            .find(|(w, _)| w == window)
+            .map(|(_, thumbnail)| &thumbnail.texture)
+    }
+
+    /// Gpu the thumbnails were rendered with
+    pub fn node(&self) -> Option<DrmNode> {
+        self.node
+    }
+}
+
+/// Returns the size of a thumbnail fitting into [`THUMBNAIL_SIZE`] and the scale to render it with
+fn thumbnail_size(size: Size<i32, Physical>) -> (Size<i32, Physical>, f64) {
+    let scale = (THUMBNAIL_SIZE as f64 / size.w.max(size.h).max(1) as f64).min(1.0);
+    let size = size.to_f64().upscale(scale).to_i32_round();
+    ((size.w.max(1), size.h.max(1)).into(), scale)
+}
+
+impl Common {
+    /// Starts or stops keeping thumbnails updated for `consumer`
+    pub fn set_thumbnail_consumer(&mut self, consumer: ThumbnailConsumer, active: bool) {
+        let state = &mut self.thumbnails;
+        let changed = if active {
+            state.consumers.insert(consumer)
+        } else {
+            state.consumers.remove(&consumer)
+        };
+        if !changed {
+            return;
+        }
+
+        if state.consumers.is_empty() {
+            if let Some(token) = state.timer.take() {
+                self.event_loop_handle.remove(token);
+            }
+            state.windows.clear();
+        } else if state.timer.is_none() {
+            match self
+                .event_loop_handle
+                .insert_source(Timer::immediate(), |_, _, state| {
+                    if let Err(err) = state.update_thumbnails() {
+                        warn!(?err, "Failed to update thumbnails.");
+                    }
+                    TimeoutAction::ToDuration(UPDATE_INTERVAL)
+                }) {
+                Ok(token) => state.timer = Some(token),
+                Err(err) => error!(?err, "Failed to start thumbnail timer."),
+            }
+        }
+    }
+}
+
+impl State {
+    fn update_thumbnails(&mut self) -> Result<()> {
+        let windows = {
+            let shell = self.common.shell.read().unwrap();
+            let mut windows = Vec::new();
+            for output in shell.outputs() {
+                for workspace in shell.workspaces.spaces_for_output(output) {
+                    windows.extend(
+                        workspace
+                            .mapped()
+                            .flat_map(|mapped| mapped.windows().map(|(w, _)| w))
+                            .chain(workspace.get_fullscreen().cloned()),
+                    );
+                }
+            }
+            windows
+        };
+
+        let thumbnails = &mut self.common.thumbnails;
+        thumbnails
+            .windows
+            .retain(|(window, _)| window.alive() && windows.contains(window));
+
+        let mut node = None;
+        let renderer = self
+            .backend
+            .offscreen_renderer(|kms| {
+                node = kms.primary_node;
+                node
+            })
+            .context("Failed to get renderer for thumbnails")?;
+        let thumbnails = &mut self.common.thumbnails;
+        if thumbnails.node != node {
+            // textures can't be shared across gpus
+            thumbnails.windows.clear();
+            thumbnails.node = node;
+        }
+
+        let result = match renderer {
+            RendererRef::Glow(renderer) => update_thumbnails(renderer, &mut self.common, &windows),
+            RendererRef::GlMulti(mut renderer) => {
+                update_thumbnails(&mut renderer, &mut self.common, &windows)
+            }
+        };
+        self.update_switcher_thumbnails();
+        result
+    }
+}
+
+fn update_thumbnails<R>(
+    renderer: &mut R,
+    common: &mut Common,
+    windows: &[CosmicSurface],
+) -> Result<()>
+where
+    R: Renderer + ImportAll + Offscreen<GlesTexture> + AsGlowRenderer,
+    <R as Renderer>::TextureId: Send + Clone + 'static,
+{
+    for window in windows {
+        let geometry = window.geometry();
+        if geometry.is_empty() {
+            continue;
+        }
+        let (physical_size, scale) = thumbnail_size(geometry.size.to_physical(1));
+        let size = physical_size.to_logical(1).to_buffer(1, Transform::Normal);
+
+        let thumbnails = &mut common.thumbnails.windows;
+        thumbnails.retain(|(w, t)| w != window || t.size == size);
+        let thumbnail = match thumbnails.iter().position(|(w, _)| w == window) {
+            Some(idx) => &mut thumbnails[idx].1,
+            None => {
+                let thumbnail = new_thumbnail(
+                    renderer,
+                    size,
+                    OutputDamageTracker::new(physical_size, scale, Transform::Normal),
+                )?;
+                thumbnails.push((window.clone(), thumbnail));
+                &mut thumbnails.last_mut().unwrap().1
+            }
+        };
+
+        let location = geometry.loc.to_physical_precise_round(scale);
+        let elements = AsRenderElements::<R>::render_elements::<WaylandSurfaceRenderElement<R>>(
+            window,
+            renderer,
+            (-location.x, -location.y).into(),
+            Scale::from(scale),
+            1.0,
+        );
+        let damage_tracker = &mut thumbnail.damage_tracker;
+        thumbnail
+            .texture
+            .render()
+            .draw::<_, <R as Renderer>::Error>(|tex| {
+                let res = match damage_tracker.render_output_with(
+                    renderer,
+                    tex.clone(),
+                    1,
+                    &elements,
+                    [0.0; 4],
+                ) {
+                    Ok(res) => res,
+                    Err(RenderError::Rendering(err)) => return Err(err),
+                    Err(RenderError::OutputNoMode(_)) => unreachable!(),
+                };
+                renderer.wait(&res.sync)?;
+                Ok(res
+                    .damage
+                    .cloned()
+                    .map(|damage| {
+                        damage
+                            .into_iter()
+                            .map(|rect| {
+                                rect.to_logical(1).to_buffer(
+                                    1,
+                                    Transform::Normal,
+                                    &size.to_logical(1, Transform::Normal),
+                                )
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default())
+            })
+            .map_err(|err| anyhow::anyhow!("Failed to render window thumbnail: {:?}", err))?;
+    }
+
+    Ok(())
+}
+
+fn new_thumbnail<R>(
+    renderer: &mut R,
+    size: Size<i32, BufferCoords>,
+    damage_tracker: OutputDamageTracker,
+) -> Result<Thumbnail>
+where
+    R: Renderer + Offscreen<GlesTexture> + AsGlowRenderer,
+{
+    let texture = Offscreen::<GlesTexture>::create_buffer(renderer, Fourcc::Abgr8888, size)
+        .map_err(|err| anyhow::anyhow!("Failed to create thumbnail texture: {:?}", err))?;
+
+    Ok(Thumbnail {
+        texture: TextureRenderBuffer::from_texture(
+            renderer.glow_renderer(),
+            texture,
+            1,
+            Transform::Normal,
+            None,
+        ),
+        damage_tracker,
+        size,
+    })
+}
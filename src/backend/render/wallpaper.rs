@@ -0,0 +1,452 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Wallpapers drawn by the compositor below all layer-shell surfaces,
+//! so sessions don't depend on a separate wallpaper client.
+
+use crate::{backend::render::SplitRenderElements, utils::prelude::*};
+use cosmic_comp_config::{Wallpaper, WallpaperScaling, WallpaperSource};
+use image::RgbaImage;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                solid::{SolidColorBuffer, SolidColorRenderElement},
+                Kind,
+            },
+            ImportMem, Renderer,
+        },
+    },
+    output::Output,
+    reexports::calloop::{
+        channel::{channel, Event},
+        timer::{TimeoutAction, Timer},
+        RegistrationToken,
+    },
+    render_elements,
+    utils::{Logical, Physical, Point, Rectangle, Size, Transform},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{error, warn};
+
+/// File extensions of the images shown by slideshows
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+render_elements! {
+    pub WallpaperRenderElement<R> where R: ImportMem;
+    Image=MemoryRenderBufferRenderElement<R>,
+    Color=SolidColorRenderElement,
+}
+
+#[derive(Debug, Clone)]
+struct WallpaperImage {
+    buffer: MemoryRenderBuffer,
+    /// Decoded image, kept around to create tiled buffers
+    pixels: Arc<RgbaImage>,
+}
+
+#[derive(Debug, Clone)]
+enum WallpaperContent {
+    Color([f32; 3]),
+    Image(WallpaperImage),
+}
+
+/// Wallpapers of all outputs, as drawn by the render loops
+#[derive(Debug, Default)]
+pub struct WallpaperState {
+    default: Option<(WallpaperScaling, WallpaperContent)>,
+    outputs: HashMap<String, (WallpaperScaling, WallpaperContent)>,
+    /// Buffers depending on the size of each output, created while rendering
+    buffers: Mutex<HashMap<String, OutputBuffers>>,
+}
+
+#[derive(Debug, Default)]
+struct OutputBuffers {
+    background: Option<(SolidColorBuffer, Size<i32, Logical>, [f32; 3])>,
+    tiled: Option<(MemoryRenderBuffer, Size<i32, Physical>, Arc<RgbaImage>)>,
+}
+
+/// Decoding and cycling of wallpaper images on the main thread
+#[derive(Debug, Default)]
+pub struct WallpaperLoader {
+    images: HashMap<PathBuf, WallpaperImage>,
+    /// Images currently decoded by helper threads
+    loading: HashMap<PathBuf, RegistrationToken>,
+    slideshows: HashMap<PathBuf, Slideshow>,
+}
+
+#[derive(Debug)]
+struct Slideshow {
+    interval: u32,
+    current: Option<PathBuf>,
+    timer: Option<RegistrationToken>,
+}
+
+/// Returns the image following `current` in `directory`, wrapping around at the end
+fn next_slideshow_image(directory: &Path, current: Option<&Path>) -> Option<PathBuf> {
+    let mut images = match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            })
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            warn!(?err, directory = %directory.display(), "Failed to read slideshow directory");
+            return None;
+        }
+    };
+    images.sort();
+
+    let next = current
+        .and_then(|current| images.iter().position(|path| path == current))
+        .map(|idx| (idx + 1) % images.len())
+        .unwrap_or(0);
+    images.into_iter().nth(next)
+}
+
+impl State {
+    /// Applies the wallpaper config, loading images, that aren't decoded yet
+    pub fn update_wallpapers(&mut self) {
+        let config = self.common.config.cosmic_conf.wallpaper.clone();
+
+        // start and stop slideshows
+        let slideshows = config
+            .default
+            .iter()
+            .chain(config.outputs.values())
+            .filter_map(|wallpaper| match &wallpaper.source {
+                WallpaperSource::Slideshow {
+                    directory,
+                    interval,
+                } => Some((directory.clone(), (*interval).max(1))),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let loop_handle = self.common.event_loop_handle.clone();
+        self.common
+            .wallpapers
+            .slideshows
+            .retain(|directory, slideshow| {
+                let keep = slideshows.get(directory) == Some(&slideshow.interval);
+                if !keep {
+                    if let Some(token) = slideshow.timer.take() {
+                        loop_handle.remove(token);
+                    }
+                }
+                keep
+            });
+        for (directory, interval) in slideshows {
+            if !self.common.wallpapers.slideshows.contains_key(&directory) {
+                self.start_slideshow(directory, interval);
+            }
+        }
+
+        // resolve the wallpapers of all outputs, keeping the previous one while the next is loading
+        let mut used = HashSet::new();
+        let mut shell = self.common.shell.write().unwrap();
+        let state = &mut shell.wallpapers;
+        let default = config.default.as_ref().and_then(|wallpaper| {
+            self.common
+                .wallpapers
+                .resolve(wallpaper, &mut used, state.default.as_ref())
+        });
+        let outputs = config
+            .outputs
+            .iter()
+            .filter_map(|(name, wallpaper)| {
+                self.common
+                    .wallpapers
+                    .resolve(wallpaper, &mut used, state.outputs.get(name))
+                    .map(|content| (name.clone(), content))
+            })
+            .collect();
+        state.default = default;
+        state.outputs = outputs;
+        let outputs = shell.outputs().cloned().collect::<Vec<_>>();
+        std::mem::drop(shell);
+
+        self.common
+            .wallpapers
+            .images
+            .retain(|path, _| used.contains(path));
+        for path in used {
+            if !self.common.wallpapers.images.contains_key(&path) {
+                self.load_wallpaper_image(path);
+            }
+        }
+
+        for output in &outputs {
+            self.backend.schedule_render(output);
+        }
+    }
+
+    fn start_slideshow(&mut self, directory: PathBuf, interval: u32) {
+        let current = next_slideshow_image(&directory, None);
+        let interval_duration = Duration::from_secs(interval as u64);
+        let directory_clone = directory.clone();
+        let timer = match self.common.event_loop_handle.insert_source(
+            Timer::from_duration(interval_duration),
+            move |_, _, state| {
+                if let Some(slideshow) =
+                    state.common.wallpapers.slideshows.get_mut(&directory_clone)
+                {
+                    slideshow.current =
+                        next_slideshow_image(&directory_clone, slideshow.current.as_deref());
+                    state.update_wallpapers();
+                }
+                TimeoutAction::ToDuration(interval_duration)
+            },
+        ) {
+            Ok(token) => Some(token),
+            Err(err) => {
+                error!(?err, "Failed to start wallpaper slideshow timer.");
+                None
+            }
+        };
+
+        self.common.wallpapers.slideshows.insert(
+            directory,
+            Slideshow {
+                interval,
+                current,
+                timer,
+            },
+        );
+    }
+
+    fn load_wallpaper_image(&mut self, path: PathBuf) {
+        if self.common.wallpapers.loading.contains_key(&path) {
+            return;
+        }
+
+        let (tx, rx) = channel::<(PathBuf, image::ImageResult<RgbaImage>)>();
+        let token = match self
+            .common
+            .event_loop_handle
+            .insert_source(rx, |event, _, state| {
+                let Event::Msg((path, result)) = event else {
+                    return;
+                };
+                if let Some(token) = state.common.wallpapers.loading.remove(&path) {
+                    state.common.event_loop_handle.remove(token);
+                }
+                match result {
+                    Ok(pixels) => {
+                        let buffer = MemoryRenderBuffer::from_slice(
+                            &pixels,
+                            Fourcc::Abgr8888,
+                            (pixels.width() as i32, pixels.height() as i32),
+                            1,
+                            Transform::Normal,
+                            None,
+                        );
+                        state.common.wallpapers.images.insert(
+                            path,
+                            WallpaperImage {
+                                buffer,
+                                pixels: Arc::new(pixels),
+                            },
+                        );
+                        state.update_wallpapers();
+                    }
+                    Err(err) => {
+                        warn!(?err, path = %path.display(), "Failed to load wallpaper.");
+                    }
+                }
+            }) {
+            Ok(token) => token,
+            Err(err) => {
+                error!(?err, "Failed to add wallpaper channel to event loop.");
+                return;
+            }
+        };
+        self.common.wallpapers.loading.insert(path.clone(), token);
+
+        // decoding large images takes a while, so don't block the event loop
+        let thread_path = path.clone();
+        if let Err(err) = std::thread::Builder::new()
+            .name("wallpaper-loader".to_string())
+            .spawn(move || {
+                let result = image::open(&thread_path).map(|image| image.into_rgba8());
+                let _ = tx.send((thread_path, result));
+            })
+        {
+            error!(?err, "Failed to spawn wallpaper loader thread.");
+            if let Some(token) = self.common.wallpapers.loading.remove(&path) {
+                self.common.event_loop_handle.remove(token);
+            }
+        }
+    }
+}
+
+impl WallpaperLoader {
+    /// Returns what to draw for `wallpaper`, or `previous` if its image isn't decoded yet.
+    ///
+    /// Images used by the wallpaper are added to `used`.
+    fn resolve(
+        &self,
+        wallpaper: &Wallpaper,
+        used: &mut HashSet<PathBuf>,
+        previous: Option<&(WallpaperScaling, WallpaperContent)>,
+    ) -> Option<(WallpaperScaling, WallpaperContent)> {
+        let path = match &wallpaper.source {
+            WallpaperSource::Color(color) => {
+                return Some((wallpaper.scaling, WallpaperContent::Color(*color)))
+            }
+            WallpaperSource::Path(path) => path.clone(),
+            WallpaperSource::Slideshow { directory, .. } => self
+                .slideshows
+                .get(directory)
+                .and_then(|slideshow| slideshow.current.clone())?,
+        };
+
+        used.insert(path.clone());
+        match self.images.get(&path) {
+            Some(image) => Some((wallpaper.scaling, WallpaperContent::Image(image.clone()))),
+            None => previous.cloned(),
+        }
+    }
+}
+
+/// Wallpaper of `output`, placed below everything else
+pub fn wallpaper_elements<R>(
+    renderer: &mut R,
+    shell: &Shell,
+    output: &Output,
+) -> SplitRenderElements<WallpaperRenderElement<R>>
+where
+    R: Renderer + ImportMem,
+    <R as Renderer>::TextureId: Clone + 'static,
+{
+    let mut elements = SplitRenderElements::default();
+
+    let state = &shell.wallpapers;
+    let name = output.name();
+    let Some((scaling, content)) = state.outputs.get(&name).or(state.default.as_ref()) else {
+        return elements;
+    };
+
+    let scale = output.current_scale().fractional_scale();
+    let output_size = output.geometry().size.as_logical();
+    let mut buffers = state.buffers.lock().unwrap();
+    let buffers = buffers.entry(name).or_default();
+
+    let background = match content {
+        WallpaperContent::Color(color) => Some(*color),
+        WallpaperContent::Image(image) => {
+            let image_size = Size::<f64, Logical>::from((
+                image.pixels.width() as f64,
+                image.pixels.height() as f64,
+            ));
+            let output_size_f64 = output_size.to_f64();
+            let mut buffer = &image.buffer;
+            let (location, src, size) = match scaling {
+                WallpaperScaling::Fill => {
+                    let factor = f64::max(
+                        output_size_f64.w / image_size.w,
+                        output_size_f64.h / image_size.h,
+                    );
+                    let visible = output_size_f64.downscale(factor);
+                    let src = Rectangle::from_loc_and_size(
+                        (
+                            (image_size.w - visible.w) / 2.,
+                            (image_size.h - visible.h) / 2.,
+                        ),
+                        visible,
+                    );
+                    (Point::default(), Some(src), output_size)
+                }
+                WallpaperScaling::Fit => {
+                    let factor = f64::min(
+                        output_size_f64.w / image_size.w,
+                        output_size_f64.h / image_size.h,
+                    );
+                    let size = image_size.upscale(factor);
+                    let location = Point::<f64, Logical>::from((
+                        (output_size_f64.w - size.w) / 2.,
+                        (output_size_f64.h - size.h) / 2.,
+                    ));
+                    (location, None, size.to_i32_round())
+                }
+                WallpaperScaling::Stretch => (Point::default(), None, output_size),
+                WallpaperScaling::Tile => {
+                    // repeat the image in physical pixels, to not scale it at all
+                    let physical_size = output_size_f64.to_physical(scale).to_i32_round();
+                    if !buffers.tiled.as_ref().is_some_and(|(_, size, pixels)| {
+                        *size == physical_size && Arc::ptr_eq(pixels, &image.pixels)
+                    }) {
+                        buffers.tiled = Some((
+                            tiled_buffer(&image.pixels, physical_size),
+                            physical_size,
+                            image.pixels.clone(),
+                        ));
+                    }
+                    buffer = &buffers.tiled.as_ref().unwrap().0;
+                    (Point::default(), None, output_size)
+                }
+            };
+
+            match MemoryRenderBufferRenderElement::from_buffer(
+                renderer,
+                location.to_physical(scale),
+                buffer,
+                None,
+                src,
+                Some(size),
+                Kind::Unspecified,
+            ) {
+                Ok(element) => elements
+                    .w_elements
+                    .push(WallpaperRenderElement::Image(element)),
+                Err(err) => warn!(?err, "Failed to import wallpaper."),
+            }
+
+            // fill the bars around the image
+            (*scaling == WallpaperScaling::Fit).then_some([0.0; 3])
+        }
+    };
+
+    if let Some(color) = background {
+        let (buffer, size, current_color) = buffers.background.get_or_insert_with(|| {
+            (
+                SolidColorBuffer::new(output_size, [color[0], color[1], color[2], 1.0]),
+                output_size,
+                color,
+            )
+        });
+        if *size != output_size || *current_color != color {
+            buffer.update(output_size, [color[0], color[1], color[2], 1.0]);
+            *size = output_size;
+            *current_color = color;
+        }
+        elements.w_elements.push(WallpaperRenderElement::Color(
+            SolidColorRenderElement::from_buffer(buffer, (0, 0), scale, 1.0, Kind::Unspecified),
+        ));
+    }
+
+    elements
+}
+
+/// Creates a buffer of `size`, that repeats `pixels`
+fn tiled_buffer(pixels: &RgbaImage, size: Size<i32, Physical>) -> MemoryRenderBuffer {
+    let tiled = RgbaImage::from_fn(size.w as u32, size.h as u32, |x, y| {
+        *pixels.get_pixel(x % pixels.width(), y % pixels.height())
+    });
+    MemoryRenderBuffer::from_slice(
+        &tiled,
+        Fourcc::Abgr8888,
+        (size.w, size.h),
+        1,
+        Transform::Normal,
+        None,
+    )
+}
@@ -0,0 +1,238 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Built-in screen magnifier, scaling the composited content of an output around the pointer.
+
+use crate::{
+    backend::render::{
+        element::{AsGlowRenderer, CosmicElement, FromGlesError},
+        CLEAR_COLOR,
+    },
+    shell::CosmicMappedRenderElement,
+    utils::prelude::*,
+};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::{Error as RenderError, OutputDamageTracker},
+            element::{
+                texture::{TextureRenderBuffer, TextureRenderElement},
+                utils::{CropRenderElement, Relocate, RelocateRenderElement, RescaleRenderElement},
+                Kind, RenderElement,
+            },
+            gles::GlesTexture,
+            Bind, ImportAll, ImportMem, Offscreen, Renderer, Texture, TextureFilter,
+        },
+    },
+    output::Output,
+    utils::{Buffer as BufferCoords, Logical, Point, Rectangle, Size, Transform},
+};
+use std::sync::{Arc, RwLock};
+
+/// Highest supported magnification
+pub const MAX_ZOOM_LEVEL: f64 = 8.0;
+
+/// Offscreen copy of the composited output, which is then scaled up
+#[derive(Debug)]
+pub struct ZoomBuffer {
+    texture: TextureRenderBuffer<GlesTexture>,
+    damage_tracker: OutputDamageTracker,
+    size: Size<i32, BufferCoords>,
+    transform: Transform,
+}
+
+impl ZoomBuffer {
+    fn new<R>(renderer: &mut R, output: &Output) -> Result<Self, R::Error>
+    where
+        R: Renderer + Offscreen<GlesTexture> + AsGlowRenderer,
+    {
+        let size = Self::buffer_size(output);
+        let opaque_regions = vec![Rectangle::from_loc_and_size((0, 0), size)];
+
+        let texture = Offscreen::<GlesTexture>::create_buffer(renderer, Fourcc::Abgr8888, size)?;
+        let transform = output.current_transform();
+        let texture = TextureRenderBuffer::from_texture(
+            renderer.glow_renderer(),
+            texture,
+            1,
+            transform,
+            Some(opaque_regions),
+        );
+
+        Ok(ZoomBuffer {
+            texture,
+            damage_tracker: OutputDamageTracker::from_output(output),
+            size,
+            transform,
+        })
+    }
+
+    fn buffer_size(output: &Output) -> Size<i32, BufferCoords> {
+        output
+            .current_mode()
+            .map(|mode| mode.size)
+            .unwrap_or_default()
+            .to_logical(1)
+            .to_buffer(1, Transform::Normal)
+    }
+
+    /// Returns if the texture doesn't match the mode or transform of `output` anymore
+    fn is_outdated(&self, output: &Output) -> bool {
+        self.size != Self::buffer_size(output) || self.transform != output.current_transform()
+    }
+}
+
+/// Returns the zoom level and the pointer position relative to `output`, if it is magnified.
+///
+/// Only the output the pointer is on is magnified.
+pub fn output_zoom(shell: &Shell, output: &Output) -> Option<(f64, Point<f64, Logical>)> {
+    if shell.zoom_level <= 1.0 {
+        return None;
+    }
+
+    let seat = shell.seats.last_active();
+    if seat.active_output() != *output {
+        return None;
+    }
+    let pointer = seat.get_pointer()?;
+    Some((
+        shell.zoom_level,
+        pointer.current_location() - output.geometry().loc.to_f64().as_logical(),
+    ))
+}
+
+/// Filter used to scale up the content of a magnified output
+pub fn upscale_filter(shell: &Shell) -> TextureFilter {
+    if shell.zoom.crisp {
+        TextureFilter::Nearest
+    } else {
+        TextureFilter::Linear
+    }
+}
+
+/// Post-composition stage magnifying `elements`, if `output` is zoomed in.
+///
+/// The elements are rendered into `buffer` and replaced by a single element scaling it up
+/// around the pointer. This keeps the content under the pointer in place, so moving the pointer
+/// pans the view smoothly and input doesn't need to be transformed.
+pub fn zoom_elements<R>(
+    renderer: &mut R,
+    buffer: &mut Option<ZoomBuffer>,
+    shell: &Arc<RwLock<Shell>>,
+    output: &Output,
+    elements: Vec<CosmicElement<R>>,
+) -> Result<Vec<CosmicElement<R>>, R::Error>
+where
+    R: Renderer
+        + ImportAll
+        + ImportMem
+        + Bind<GlesTexture>
+        + Offscreen<GlesTexture>
+        + AsGlowRenderer,
+    <R as Renderer>::TextureId: Clone + 'static,
+    <R as Renderer>::Error: FromGlesError,
+    CosmicElement<R>: RenderElement<R>,
+    CosmicMappedRenderElement<R>: RenderElement<R>,
+{
+    let Some((level, pointer)) = output_zoom(&shell.read().unwrap(), output) else {
+        // don't keep the texture around while zoom isn't used
+        *buffer = None;
+        return Ok(elements);
+    };
+
+    if buffer
+        .as_ref()
+        .is_some_and(|buffer| buffer.is_outdated(output))
+    {
+        *buffer = None;
+    }
+    if buffer.is_none() {
+        *buffer = Some(ZoomBuffer::new(renderer, output)?);
+    }
+    let zoom_buffer = buffer.as_mut().unwrap();
+
+    let damage_tracker = &mut zoom_buffer.damage_tracker;
+    zoom_buffer.texture.render().draw::<_, R::Error>(|tex| {
+        let res = match damage_tracker.render_output_with(
+            renderer,
+            tex.clone(),
+            1,
+            &elements,
+            CLEAR_COLOR,
+        ) {
+            Ok(res) => res,
+            Err(RenderError::Rendering(err)) => return Err(err),
+            Err(RenderError::OutputNoMode(_)) => unreachable!(),
+        };
+
+        renderer.wait(&res.sync)?;
+
+        let transform = output.current_transform();
+        let area = tex.size().to_logical(1, transform);
+
+        Ok(res
+            .damage
+            .cloned()
+            .map(|v| {
+                v.into_iter()
+                    .map(|r| r.to_logical(1).to_buffer(1, transform, &area))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default())
+    })?;
+
+    let scale = output.current_scale().fractional_scale();
+    let output_size = output.geometry().size.as_logical();
+    let texture_elem = TextureRenderElement::from_texture_render_buffer(
+        (0., 0.),
+        &zoom_buffer.texture,
+        Some(1.0),
+        None,
+        Some(output_size),
+        Kind::Unspecified,
+    );
+    let zoomed = RescaleRenderElement::from_element(
+        texture_elem,
+        pointer.to_physical(scale).to_i32_round(),
+        level,
+    );
+
+    Ok(CropRenderElement::from_element(
+        RelocateRenderElement::from_element(zoomed, (0, 0), Relocate::Relative),
+        scale,
+        Rectangle::from_loc_and_size(
+            (0, 0),
+            output_size.to_f64().to_physical(scale).to_i32_round(),
+        ),
+    )
+    .map(CosmicElement::Zoom)
+    .into_iter()
+    .collect())
+}
+
+impl State {
+    /// Zooms in or out by `steps` times the configured increment
+    pub fn update_zoom(&mut self, steps: f64) {
+        let (level, increment) = {
+            let shell = self.common.shell.read().unwrap();
+            (shell.zoom_level, shell.zoom.increment as f64 / 100.0)
+        };
+        self.set_zoom_level(level + steps * increment);
+    }
+
+    /// Sets the zoom level, clamped to the supported range
+    pub fn set_zoom_level(&mut self, level: f64) {
+        let mut shell = self.common.shell.write().unwrap();
+        let level = level.clamp(1.0, MAX_ZOOM_LEVEL);
+        if level == shell.zoom_level {
+            return;
+        }
+        shell.zoom_level = level;
+
+        let outputs = shell.outputs().cloned().collect::<Vec<_>>();
+        std::mem::drop(shell);
+        for output in &outputs {
+            self.backend.schedule_render(output);
+        }
+    }
+}
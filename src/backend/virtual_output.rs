@@ -0,0 +1,309 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{
+    backend::render::{
+        self,
+        element::{AsGlowRenderer, CosmicElement, FromGlesError},
+        CursorMode, RendererRef,
+    },
+    config::OutputConfig,
+    shell::{CosmicMappedRenderElement, WorkspaceRenderElement},
+    utils::prelude::*,
+};
+use anyhow::{Context, Result};
+use cosmic_comp_config::VirtualOutputConfig;
+use smithay::{
+    backend::{
+        allocator::{dmabuf::Dmabuf, Fourcc},
+        renderer::{
+            damage::{OutputDamageTracker, RenderOutputResult},
+            element::RenderElement,
+            gles::{GlesRenderbuffer, GlesTexture},
+            Bind, Blit, ExportMem, ImportAll, ImportMem, Offscreen, Renderer,
+        },
+    },
+    desktop::layer_map_for_output,
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            RegistrationToken,
+        },
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+    },
+    utils::Transform,
+};
+use std::{cell::RefCell, time::Duration};
+use tracing::{error, warn};
+
+/// Prefix of the names of virtual outputs, to avoid clashing with connector names
+const NAME_PREFIX: &str = "VIRTUAL-";
+
+/// Rendering state of an output without a physical display.
+///
+/// Virtual outputs are rendered offscreen at their refresh rate, if anything changed,
+/// so that clients get frame callbacks and screencopy sessions receive new frames.
+struct VirtualOutputState {
+    damage_tracker: OutputDamageTracker,
+    buffer: Option<GlesRenderbuffer>,
+    dirty: bool,
+    timer: Option<RegistrationToken>,
+}
+
+/// Marks `output` to be rendered on its next refresh, returns `false` if it isn't a virtual output
+pub fn schedule_render(output: &Output) -> bool {
+    match output.user_data().get::<RefCell<VirtualOutputState>>() {
+        Some(data) => {
+            data.borrow_mut().dirty = true;
+            true
+        }
+        None => false,
+    }
+}
+
+fn output_name(config: &VirtualOutputConfig) -> String {
+    format!("{}{}", NAME_PREFIX, config.name)
+}
+
+impl State {
+    /// Creates, reconfigures and removes virtual outputs to match the config
+    pub fn update_virtual_outputs(&mut self) {
+        let configs = self.common.config.cosmic_conf.virtual_outputs.clone();
+
+        let (outputs, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.common.virtual_outputs)
+            .into_iter()
+            .partition(|output| configs.iter().any(|c| output_name(c) == output.name()));
+        self.common.virtual_outputs = outputs;
+        for output in removed {
+            self.remove_virtual_output(&output);
+        }
+
+        for config in &configs {
+            if config.width == 0 || config.height == 0 {
+                warn!(name = %config.name, "Ignoring virtual output without a size");
+                continue;
+            }
+
+            let name = output_name(config);
+            match self
+                .common
+                .virtual_outputs
+                .iter()
+                .find(|output| output.name() == name)
+                .cloned()
+            {
+                Some(output) => self.configure_virtual_output(&output, config),
+                None => self.add_virtual_output(config),
+            }
+        }
+    }
+
+    fn add_virtual_output(&mut self, config: &VirtualOutputConfig) {
+        let output = Output::new(
+            output_name(config),
+            PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: "COSMIC".to_string(),
+                model: "Virtual Output".to_string(),
+            },
+        );
+        // place it right of all existing outputs
+        let x = self.common.shell.read().unwrap().global_space().size.w;
+        output.change_current_state(None, Some(Transform::Normal), None, Some((x, 0).into()));
+        output.user_data().insert_if_missing(|| {
+            RefCell::new(OutputConfig {
+                position: (x as u32, 0),
+                ..Default::default()
+            })
+        });
+        self.configure_virtual_output(&output, config);
+
+        self.common
+            .output_configuration_state
+            .add_heads(std::iter::once(&output));
+        self.common.output_configuration_state.enable_head(&output);
+        self.common.output_configuration_state.update();
+        self.common.add_output(&output);
+        self.common.virtual_outputs.push(output);
+    }
+
+    fn configure_virtual_output(&mut self, output: &Output, config: &VirtualOutputConfig) {
+        let refresh = config.refresh.max(1_000);
+        let mode = Mode {
+            size: (config.width as i32, config.height as i32).into(),
+            refresh: refresh as i32,
+        };
+        if output.current_mode() == Some(mode) {
+            return;
+        }
+
+        output.add_mode(mode);
+        output.set_preferred(mode);
+        output.change_current_state(Some(mode), None, None, None);
+        output.config_mut().mode = ((mode.size.w, mode.size.h), Some(refresh));
+        layer_map_for_output(output).arrange();
+
+        let interval = Duration::from_secs_f64(1_000.0 / refresh as f64);
+        let output_clone = output.clone();
+        let timer = match self.common.event_loop_handle.insert_source(
+            Timer::from_duration(interval),
+            move |_, _, state| {
+                if let Err(err) = state.render_virtual_output(&output_clone) {
+                    error!(?err, "Failed to render virtual output.");
+                }
+                TimeoutAction::ToDuration(interval)
+            },
+        ) {
+            Ok(token) => Some(token),
+            Err(err) => {
+                error!(?err, "Failed to start render timer for virtual output.");
+                None
+            }
+        };
+
+        output.user_data().insert_if_missing(|| {
+            RefCell::new(VirtualOutputState {
+                damage_tracker: OutputDamageTracker::from_output(output),
+                buffer: None,
+                dirty: true,
+                timer: None,
+            })
+        });
+        let mut data = output
+            .user_data()
+            .get::<RefCell<VirtualOutputState>>()
+            .unwrap()
+            .borrow_mut();
+        if let Some(token) = std::mem::replace(&mut data.timer, timer) {
+            self.common.event_loop_handle.remove(token);
+        }
+        data.damage_tracker = OutputDamageTracker::from_output(output);
+        data.buffer = None;
+        data.dirty = true;
+        std::mem::drop(data);
+
+        self.common.refresh();
+    }
+
+    fn remove_virtual_output(&mut self, output: &Output) {
+        if let Some(data) = output.user_data().get::<RefCell<VirtualOutputState>>() {
+            if let Some(token) = data.borrow_mut().timer.take() {
+                self.common.event_loop_handle.remove(token);
+            }
+        }
+
+        self.common
+            .output_configuration_state
+            .remove_heads(std::iter::once(output));
+        self.common.output_configuration_state.update();
+        self.common.remove_output(output);
+    }
+
+    fn render_virtual_output(&mut self, output: &Output) -> Result<()> {
+        let mut data = output
+            .user_data()
+            .get::<RefCell<VirtualOutputState>>()
+            .unwrap()
+            .borrow_mut();
+        if !data.dirty {
+            return Ok(());
+        }
+        data.dirty = false;
+
+        let renderer = self
+            .backend
+            .offscreen_renderer(|kms| kms.primary_node)
+            .context("Failed to get renderer for virtual output")?;
+        let result = match renderer {
+            RendererRef::Glow(renderer) => {
+                render_to_buffer(renderer, &mut data, &mut self.common, output)
+            }
+            RendererRef::GlMulti(mut renderer) => {
+                render_to_buffer(&mut renderer, &mut data, &mut self.common, output)
+            }
+        };
+        if result.is_err() {
+            // the buffer might belong to a gpu, that is gone
+            data.buffer = None;
+        }
+        result
+    }
+}
+
+fn render_to_buffer<R>(
+    renderer: &mut R,
+    data: &mut VirtualOutputState,
+    state: &mut Common,
+    output: &Output,
+) -> Result<()>
+where
+    R: Renderer
+        + ImportAll
+        + ImportMem
+        + ExportMem
+        + Bind<Dmabuf>
+        + Bind<GlesRenderbuffer>
+        + Bind<GlesTexture>
+        + Offscreen<GlesRenderbuffer>
+        + Offscreen<GlesTexture>
+        + Blit<GlesRenderbuffer>
+        + AsGlowRenderer,
+    <R as Renderer>::TextureId: Send + Clone + 'static,
+    <R as Renderer>::Error: FromGlesError,
+    CosmicElement<R>: RenderElement<R>,
+    CosmicMappedRenderElement<R>: RenderElement<R>,
+    WorkspaceRenderElement<R>: RenderElement<R>,
+{
+    let Some(mode) = output.current_mode() else {
+        return Ok(());
+    };
+
+    let age = if data.buffer.is_some() { 1 } else { 0 };
+    let buffer = match data.buffer.clone() {
+        Some(buffer) => buffer,
+        None => {
+            let size = mode.size.to_logical(1).to_buffer(1, Transform::Normal);
+            let buffer =
+                Offscreen::<GlesRenderbuffer>::create_buffer(renderer, Fourcc::Abgr8888, size)
+                    .map_err(|err| anyhow::anyhow!("Failed to create buffer: {}", err))?;
+            data.buffer = Some(buffer.clone());
+            buffer
+        }
+    };
+
+    match render::render_output::<_, _, GlesRenderbuffer>(
+        None,
+        renderer,
+        buffer,
+        &mut data.damage_tracker,
+        age,
+        &state.shell,
+        state.clock.now(),
+        output,
+        CursorMode::All,
+    ) {
+        Ok(RenderOutputResult { damage, states, .. }) => {
+            state.send_frames(output, None);
+            state.update_primary_output(output, &states);
+            if damage.is_some() {
+                let mut output_presentation_feedback = state
+                    .shell
+                    .read()
+                    .unwrap()
+                    .take_presentation_feedback(output, &states);
+                output_presentation_feedback.presented(
+                    state.clock.now(),
+                    Duration::from_secs_f64(1_000.0 / mode.refresh as f64),
+                    0,
+                    wp_presentation_feedback::Kind::empty(),
+                )
+            }
+        }
+        Err(err) => {
+            anyhow::bail!("Rendering failed: {}", err);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Visual bell, flashing the focused window or the whole output when an application rings the
+//! bell, for deaf and hard-of-hearing users.
+//!
+//! X11 applications ring the bell through the XKB extension of Xwayland, whose `BellNotify`
+//! events are received on a connection of our own next to the one of the X11 window manager.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use calloop::{
+    channel::{self, Sender},
+    timer::{TimeoutAction, Timer},
+    LoopHandle,
+};
+use cosmic_comp_config::VisualBell;
+use smithay::{backend::renderer::element::Id, output::Output};
+use tracing::{debug, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xkb::{self, ConnectionExt as _},
+        Event,
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::{
+    shell::{focus::target::KeyboardFocusTarget, CosmicMapped},
+    state::State,
+};
+
+/// How long the bell flashes, fading out
+const FLASH_DURATION: Duration = Duration::from_millis(250);
+
+/// Width of the border flashing around windows, in logical pixels
+pub const BORDER_THICKNESS: u8 = 4;
+
+/// A visual bell being shown on `output`
+#[derive(Debug)]
+pub struct BellFlash {
+    pub output: Output,
+    /// Window whose border flashes, or `None` if the whole output does
+    pub window: Option<CosmicMapped>,
+    pub id: Id,
+    started: Instant,
+}
+
+impl BellFlash {
+    /// Opacity of the flash, going from `1.0` to `0.0`
+    pub fn alpha(&self) -> f32 {
+        1.0 - (self.started.elapsed().as_secs_f32() / FLASH_DURATION.as_secs_f32()).min(1.0)
+    }
+}
+
+/// Watches the Xwayland server `display` for X11 applications ringing the bell
+pub fn listen_xwayland(evlh: &LoopHandle<'static, State>, display: u32) -> Result<()> {
+    let (tx, rx) = channel::channel();
+    evlh.insert_source(rx, |event, _, state| {
+        if let channel::Event::Msg(()) = event {
+            state.ring_bell();
+        }
+    })
+    .map_err(|err| err.error)
+    .context("Failed to add channel to event_loop")?;
+
+    std::thread::Builder::new()
+        .name("xwayland-bell".to_string())
+        .spawn(move || {
+            if let Err(err) = watch_bell(display, tx) {
+                warn!(?err, "Failed to watch Xwayland for bells");
+            }
+        })
+        .context("Failed to start helper thread")?;
+    Ok(())
+}
+
+fn watch_bell(display: u32, tx: Sender<()>) -> Result<()> {
+    let (conn, _) = RustConnection::connect(Some(&format!(":{}", display)))
+        .context("Failed to connect to Xwayland")?;
+    let reply = conn.xkb_use_extension(1, 0)?.reply()?;
+    if !reply.supported {
+        bail!("XKB extension is not supported");
+    }
+    conn.xkb_select_events(
+        xkb::ID::USE_CORE_KBD.into(),
+        xkb::EventType::from(0u16),
+        xkb::EventType::BELL_NOTIFY,
+        xkb::MapPart::from(0u16),
+        xkb::MapPart::from(0u16),
+        &xkb::SelectEventsAux::new(),
+    )?
+    .check()?;
+
+    // the connection is closed, once Xwayland exits
+    while let Ok(event) = conn.wait_for_event() {
+        if let Event::XkbBellNotify(_) = event {
+            if tx.send(()).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl State {
+    /// Flashes the focused window or the active output, as configured by `visual_bell`
+    pub fn ring_bell(&mut self) {
+        let mode = self.common.config.cosmic_conf.visual_bell;
+        if mode == VisualBell::Off {
+            return;
+        }
+
+        let mut shell = self.common.shell.write().unwrap();
+        let seat = shell.seats.last_active().clone();
+        let mut output = seat.active_output();
+        let mut window = None;
+        if mode == VisualBell::Window {
+            if let Some(KeyboardFocusTarget::Element(mapped)) =
+                seat.get_keyboard().unwrap().current_focus()
+            {
+                // windows on hidden workspaces can't flash
+                if let Some(workspace) = shell.space_for(&mapped).filter(|workspace| {
+                    shell.workspaces.active(workspace.output()).1.handle == workspace.handle
+                }) {
+                    output = workspace.output().clone();
+                    window = Some(mapped);
+                }
+            }
+        }
+        debug!(output = %output.name(), ?window, "Ringing visual bell");
+
+        let id = Id::new();
+        let previous = shell.bell.replace(BellFlash {
+            output: output.clone(),
+            window,
+            id: id.clone(),
+            started: Instant::now(),
+        });
+        std::mem::drop(shell);
+        if let Some(previous) = previous.filter(|previous| previous.output != output) {
+            self.backend.schedule_render(&previous.output);
+        }
+        self.backend.schedule_render(&output);
+
+        let res = self.common.event_loop_handle.insert_source(
+            Timer::from_duration(FLASH_DURATION),
+            move |_, _, state| {
+                let mut shell = state.common.shell.write().unwrap();
+                if shell.bell.as_ref().is_some_and(|bell| bell.id == id) {
+                    shell.bell = None;
+                    std::mem::drop(shell);
+                    state.backend.schedule_render(&output);
+                }
+                TimeoutAction::Drop
+            },
+        );
+        if let Err(err) = res {
+            warn!(?err, "Failed to time the visual bell");
+            self.common.shell.write().unwrap().bell = None;
+        }
+    }
+}
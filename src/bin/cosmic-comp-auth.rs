@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Authenticates the user running it through PAM, with the password read from stdin.
+//!
+//! Used by the built-in lock screen of cosmic-comp, so the compositor itself never links
+//! against PAM or runs its modules. Exits with 0 if the password is correct, 1 if it isn't
+//! and 2 if authentication isn't possible at all.
+
+use std::{
+    ffi::{CStr, CString},
+    io::Read,
+    process, ptr,
+};
+
+use libc::{c_char, c_int, c_void};
+
+/// Configured by `/etc/pam.d/cosmic-comp`
+const SERVICE: &CStr = c"cosmic-comp";
+
+const PAM_SUCCESS: c_int = 0;
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+const PAM_BUF_ERR: c_int = 5;
+
+#[allow(dead_code)]
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    resp_retcode: c_int,
+}
+
+#[repr(C)]
+struct PamConv {
+    conv: extern "C" fn(c_int, *mut *const PamMessage, *mut *mut PamResponse, *mut c_void) -> c_int,
+    appdata_ptr: *mut c_void,
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+struct PamHandle {
+    _private: [u8; 0],
+}
+
+#[link(name = "pam")]
+extern "C" {
+    fn pam_start(
+        service_name: *const c_char,
+        user: *const c_char,
+        pam_conversation: *const PamConv,
+        pamh: *mut *mut PamHandle,
+    ) -> c_int;
+    fn pam_authenticate(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_acct_mgmt(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_end(pamh: *mut PamHandle, pam_status: c_int) -> c_int;
+}
+
+/// Answers every prompt of the PAM modules with the password in `appdata_ptr`
+extern "C" fn conversation(
+    num_msg: c_int,
+    msg: *mut *const PamMessage,
+    resp: *mut *mut PamResponse,
+    appdata_ptr: *mut c_void,
+) -> c_int {
+    let password = appdata_ptr as *const c_char;
+    let num_msg = num_msg.max(0) as usize;
+    unsafe {
+        // freed by PAM
+        let responses =
+            libc::calloc(num_msg, std::mem::size_of::<PamResponse>()) as *mut PamResponse;
+        if responses.is_null() {
+            return PAM_BUF_ERR;
+        }
+        for i in 0..num_msg {
+            let message = &**msg.add(i);
+            if matches!(message.msg_style, PAM_PROMPT_ECHO_OFF | PAM_PROMPT_ECHO_ON) {
+                let answer = libc::strdup(password);
+                if answer.is_null() {
+                    for j in 0..i {
+                        libc::free((*responses.add(j)).resp as *mut c_void);
+                    }
+                    libc::free(responses as *mut c_void);
+                    return PAM_BUF_ERR;
+                }
+                (*responses.add(i)).resp = answer;
+            }
+        }
+        *resp = responses;
+    }
+    PAM_SUCCESS
+}
+
+fn authenticate() -> Result<bool, String> {
+    let mut password = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut password)
+        .map_err(|err| format!("failed to read the password: {}", err))?;
+    let password =
+        CString::new(password).map_err(|_| "the password contains a null byte".to_string())?;
+
+    let user = unsafe {
+        let passwd = libc::getpwuid(libc::getuid());
+        if passwd.is_null() {
+            return Err("failed to look up the current user".to_string());
+        }
+        CStr::from_ptr((*passwd).pw_name).to_owned()
+    };
+
+    let conv = PamConv {
+        conv: conversation,
+        appdata_ptr: password.as_ptr() as *mut c_void,
+    };
+    let mut handle = ptr::null_mut();
+    let res = unsafe { pam_start(SERVICE.as_ptr(), user.as_ptr(), &conv, &mut handle) };
+    if res != PAM_SUCCESS {
+        return Err(format!("pam_start failed with {}", res));
+    }
+
+    let mut res = unsafe { pam_authenticate(handle, 0) };
+    if res == PAM_SUCCESS {
+        res = unsafe { pam_acct_mgmt(handle, 0) };
+    }
+    unsafe { pam_end(handle, res) };
+    Ok(res == PAM_SUCCESS)
+}
+
+fn main() {
+    process::exit(match authenticate() {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(err) => {
+            eprintln!("cosmic-comp-auth: {}", err);
+            2
+        }
+    });
+}
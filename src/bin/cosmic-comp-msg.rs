@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Command line client for the IPC socket of cosmic-comp.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process,
+};
+
+// shared with the compositor, which uses all of it
+#[allow(dead_code)]
+#[path = "../ipc/protocol.rs"]
+mod protocol;
+
+use protocol::{EventKind, Mode, Rect, Request, Response, SOCKET_ENV};
+
+const USAGE: &str = "\
+Usage: cosmic-comp-msg <command> [arguments]
+
+Commands:
+    get_tree                              outputs with their workspaces and windows
+    get_workspaces
+    get_outputs
+    get_inputs
+    get_metrics                           frame timings and resource usage, if enabled
+    action <action>                       run a shortcut action, e.g. 'Workspace(2)'
+    focus <id>                            focus a window
+    close <id>                            close a window
+    move <id> <workspace> [output]        move a window to a workspace
+    geometry <id> <x> <y> <width> <height>
+                                          move and resize a floating window
+    workspace <number> [output]           switch to a workspace
+    output <name> [on|off] [mode <width>x<height>[@<hz>]] [scale <scale>]
+           [position <x>,<y>] [adaptive_sync on|off]
+                                          configure an output
+    trace on [name] | trace off           record tracing spans into a trace file for Perfetto
+    subscribe [window|workspace|output|binding_mode]...
+                                          print events as they happen, of all kinds by default
+    raw <json>                            send a request written in json";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let request = match parse_request(&args) {
+        Ok(request) => request,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let (response, mut stream) = match send(&request) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("Failed to talk to cosmic-comp: {}", err);
+            process::exit(1);
+        }
+    };
+    match response {
+        Response::Success => {}
+        Response::Error(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+        response => println!("{}", serde_json::to_string_pretty(&response).unwrap()),
+    }
+
+    if matches!(request, Request::Subscribe { .. }) {
+        // events are printed as they come, one per line
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match stream.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => print!("{}", line),
+                Err(err) => {
+                    eprintln!("Failed to read events: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn socket_path() -> Result<PathBuf, String> {
+    if let Some(path) = env::var_os(SOCKET_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .ok_or_else(|| format!("Neither {} nor XDG_RUNTIME_DIR are set", SOCKET_ENV))?;
+    let display = env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| String::from("wayland-0"));
+    Ok(PathBuf::from(runtime_dir).join(format!("cosmic-comp.{}.sock", display)))
+}
+
+fn send(request: &Request) -> Result<(Response, BufReader<UnixStream>), String> {
+    let path = socket_path()?;
+    let mut stream =
+        UnixStream::connect(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let mut message = serde_json::to_vec(request).map_err(|err| err.to_string())?;
+    message.push(b'\n');
+    stream.write_all(&message).map_err(|err| err.to_string())?;
+
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    stream.read_line(&mut line).map_err(|err| err.to_string())?;
+    let response =
+        serde_json::from_str(&line).map_err(|err| format!("Invalid response: {}", err))?;
+    Ok((response, stream))
+}
+
+fn parse_request(args: &[String]) -> Result<Request, String> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(String::from("No command given"));
+    };
+    let arg = |idx: usize, name: &str| {
+        args.get(idx)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Missing argument <{}>", name))
+    };
+    let number = |idx: usize, name: &str| {
+        arg(idx, name)?
+            .parse::<i64>()
+            .map_err(|_| format!("<{}> must be a number", name))
+    };
+
+    Ok(match command.as_str() {
+        "get_tree" => Request::GetTree,
+        "get_workspaces" => Request::GetWorkspaces,
+        "get_outputs" => Request::GetOutputs,
+        "get_inputs" => Request::GetInputs,
+        "get_metrics" => Request::GetMetrics,
+        "action" => Request::RunAction {
+            action: args.join(" "),
+        },
+        "focus" => Request::FocusWindow {
+            id: number(0, "id")? as u64,
+        },
+        "close" => Request::CloseWindow {
+            id: number(0, "id")? as u64,
+        },
+        "move" => Request::MoveWindowToWorkspace {
+            id: number(0, "id")? as u64,
+            workspace: number(1, "workspace")? as usize,
+            output: args.get(2).cloned(),
+        },
+        "geometry" => Request::SetWindowGeometry {
+            id: number(0, "id")? as u64,
+            geometry: Rect {
+                x: number(1, "x")? as i32,
+                y: number(2, "y")? as i32,
+                width: number(3, "width")? as i32,
+                height: number(4, "height")? as i32,
+            },
+        },
+        "workspace" => Request::SwitchWorkspace {
+            workspace: number(0, "number")? as usize,
+            output: args.get(1).cloned(),
+        },
+        "trace" => match arg(0, "on|off")? {
+            "on" => Request::SetTracing {
+                enabled: true,
+                path: args.get(1).cloned(),
+            },
+            "off" => Request::SetTracing {
+                enabled: false,
+                path: None,
+            },
+            _ => return Err(String::from("Expected `on` or `off`")),
+        },
+        "subscribe" if args.is_empty() => Request::Subscribe {
+            events: vec![
+                EventKind::Window,
+                EventKind::Workspace,
+                EventKind::Output,
+                EventKind::BindingMode,
+            ],
+        },
+        "subscribe" => Request::Subscribe {
+            events: args
+                .iter()
+                .map(|kind| match kind.as_str() {
+                    "window" => Ok(EventKind::Window),
+                    "workspace" => Ok(EventKind::Workspace),
+                    "output" => Ok(EventKind::Output),
+                    "binding_mode" => Ok(EventKind::BindingMode),
+                    kind => Err(format!("Unknown event kind `{}`", kind)),
+                })
+                .collect::<Result<_, _>>()?,
+        },
+        "output" => parse_output(arg(0, "name")?, &args[1..])?,
+        "raw" => serde_json::from_str(arg(0, "json")?)
+            .map_err(|err| format!("Invalid request: {}", err))?,
+        command => return Err(format!("Unknown command `{}`", command)),
+    })
+}
+
+fn parse_output(name: &str, args: &[String]) -> Result<Request, String> {
+    let mut enabled = None;
+    let mut mode = None;
+    let mut scale = None;
+    let mut position = None;
+    let mut adaptive_sync = None;
+
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value of `{}`", arg))
+        };
+        match arg {
+            "on" => enabled = Some(true),
+            "off" => enabled = Some(false),
+            "mode" => mode = Some(parse_mode(value()?)?),
+            "scale" => {
+                scale = Some(
+                    value()?
+                        .parse::<f64>()
+                        .map_err(|_| String::from("The scale must be a number"))?,
+                )
+            }
+            "position" => {
+                let (x, y) = value()?
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+                    .ok_or_else(|| String::from("The position must look like <x>,<y>"))?;
+                position = Some((x, y));
+            }
+            "adaptive_sync" => {
+                adaptive_sync = Some(match value()? {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(String::from("adaptive_sync must be `on` or `off`")),
+                })
+            }
+            arg => return Err(format!("Unknown output property `{}`", arg)),
+        }
+    }
+
+    Ok(Request::ConfigureOutput {
+        name: name.to_string(),
+        enabled,
+        mode,
+        scale,
+        position,
+        adaptive_sync,
+    })
+}
+
+/// Parses `<width>x<height>[@<hz>]`
+fn parse_mode(value: &str) -> Result<Mode, String> {
+    let invalid = || String::from("The mode must look like <width>x<height>[@<hz>]");
+    let (size, refresh) = match value.split_once('@') {
+        Some((size, hz)) => (
+            size,
+            Some((hz.parse::<f64>().map_err(|_| invalid())? * 1000.0).round() as u32),
+        ),
+        None => (value, None),
+    };
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    Ok(Mode {
+        width: width.parse().map_err(|_| invalid())?,
+        height: height.parse().map_err(|_| invalid())?,
+        refresh,
+    })
+}
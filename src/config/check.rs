@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `cosmic-comp --check`: parses every configuration file the compositor reads,
+//! so mistakes can be found before logging in again.
+
+use super::{OutputState, OutputsConfig};
+use cosmic_comp_config::{
+    input::InputConfig,
+    workspace::{WorkspaceConfig, WorkspaceOverride},
+    ActivationPolicy, AdaptiveSyncMode, BindingMode, BlurConfig, ColorFilterConfig,
+    CompositorAction, CursorConfig, CustomMode, DimConfig, GameModeConfig, GestureConfig,
+    GpuConfig, Hook, IdleConfig, KeybindingProfile, KeyboardAccessibilityConfig, MetricsConfig,
+    ProtocolPermissionsConfig, ShadowConfig, TearingConfig, TileBehavior, VirtualOutputConfig,
+    VisualBell, WallpaperConfig, WindowSwitcherConfig, XkbConfig, ZoomConfig,
+};
+use cosmic_settings_config::{shortcuts, Shortcuts};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+const COMP_CONFIG: &str = "com.system76.CosmicComp/v1";
+const SHORTCUTS_CONFIG: &str = "com.system76.CosmicSettings.Shortcuts/v1";
+
+/// Problems found in the configuration
+#[derive(Debug, Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn error(&mut self, path: &Path, message: impl Display) {
+        eprintln!("{}: error: {}", path.display(), message);
+        self.errors += 1;
+    }
+
+    fn warning(&mut self, path: &Path, message: impl Display) {
+        eprintln!("{}: warning: {}", path.display(), message);
+        self.warnings += 1;
+    }
+
+    fn check<T: DeserializeOwned>(&mut self, path: &Path, content: &str) -> Option<T> {
+        match ron::from_str::<T>(content) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                eprintln!(
+                    "{}:{}:{}: error: {}",
+                    path.display(),
+                    err.position.line,
+                    err.position.col,
+                    err.code
+                );
+                self.errors += 1;
+                None
+            }
+        }
+    }
+
+    fn validate<T: DeserializeOwned>(&mut self, path: &Path, content: &str) {
+        self.check::<T>(path, content);
+    }
+}
+
+/// Validates the configuration, returning `false` if it contains errors
+pub fn check_config() -> bool {
+    let mut report = Report::default();
+    let xdg = xdg::BaseDirectories::with_prefix("cosmic").ok();
+
+    for (path, key) in config_files(xdg.as_ref(), COMP_CONFIG) {
+        let Some(content) = read(&mut report, &path) else {
+            continue;
+        };
+        check_comp_key(&mut report, &path, &key, &content);
+    }
+
+    for (path, key) in config_files(xdg.as_ref(), SHORTCUTS_CONFIG) {
+        let Some(content) = read(&mut report, &path) else {
+            continue;
+        };
+        match key.as_str() {
+            "custom" | "defaults" => report.validate::<Shortcuts>(&path, &content),
+            "system_actions" => {
+                report.validate::<BTreeMap<shortcuts::action::System, String>>(&path, &content)
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(path) = xdg::BaseDirectories::new()
+        .ok()
+        .and_then(|base| base.find_state_file("cosmic-comp/outputs.ron"))
+    {
+        if let Some(content) = read(&mut report, &path) {
+            if let Some(outputs) = report.check::<OutputsConfig>(&path, &content) {
+                check_outputs(&mut report, &path, &outputs);
+            }
+        }
+    }
+
+    println!("{} error(s), {} warning(s)", report.errors, report.warnings);
+    report.errors == 0
+}
+
+/// Files of the given cosmic-config entry, user configuration first
+fn config_files(xdg: Option<&xdg::BaseDirectories>, entry: &str) -> Vec<(PathBuf, String)> {
+    let Some(xdg) = xdg else {
+        return Vec::new();
+    };
+    xdg.find_config_files(entry)
+        .chain(xdg.find_data_files(entry))
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let key = entry.file_name().into_string().ok()?;
+            // cosmic-config writes keys atomically through temporary files
+            (!key.starts_with('.')).then(|| (entry.path(), key))
+        })
+        .collect()
+}
+
+fn read(report: &mut Report, path: &Path) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Some(content),
+        Err(err) => {
+            report.error(path, err);
+            None
+        }
+    }
+}
+
+fn check_comp_key(report: &mut Report, path: &Path, key: &str, content: &str) {
+    match key {
+        "workspaces" => report.validate::<WorkspaceConfig>(path, content),
+        "input_default" | "input_touchpad" => report.validate::<InputConfig>(path, content),
+        "input_devices" => report.validate::<HashMap<String, InputConfig>>(path, content),
+        "xkb_config" => report.validate::<XkbConfig>(path, content),
+        "autotile" | "active_hint" | "xwayland" | "descale_xwayland" => {
+            report.validate::<bool>(path, content)
+        }
+        "autotile_behavior" => report.validate::<TileBehavior>(path, content),
+        "xwayland_unscaled_apps" | "force_server_side_decorations" | "capture_excluded_apps" => {
+            report.validate::<Vec<String>>(path, content)
+        }
+        "tearing" => report.validate::<TearingConfig>(path, content),
+        "game_mode" => report.validate::<GameModeConfig>(path, content),
+        "activation" => report.validate::<ActivationPolicy>(path, content),
+        "idle" => report.validate::<IdleConfig>(path, content),
+        "adaptive_sync" => report.validate::<AdaptiveSyncMode>(path, content),
+        "gpu" => report.validate::<GpuConfig>(path, content),
+        "virtual_outputs" => report.validate::<Vec<VirtualOutputConfig>>(path, content),
+        "custom_modes" => report.validate::<HashMap<String, Vec<CustomMode>>>(path, content),
+        "zoom" => report.validate::<ZoomConfig>(path, content),
+        "color_filter" => report.validate::<ColorFilterConfig>(path, content),
+        "visual_bell" => report.validate::<VisualBell>(path, content),
+        "cursor" => report.validate::<CursorConfig>(path, content),
+        "wallpaper" => report.validate::<WallpaperConfig>(path, content),
+        "blur" => report.validate::<BlurConfig>(path, content),
+        "corner_radius" => report.validate::<u32>(path, content),
+        "shadow" => report.validate::<ShadowConfig>(path, content),
+        "dim_unfocused" => report.validate::<DimConfig>(path, content),
+        "window_switcher" => report.validate::<WindowSwitcherConfig>(path, content),
+        "keyboard_accessibility" => report.validate::<KeyboardAccessibilityConfig>(path, content),
+        "gestures" => report.validate::<GestureConfig>(path, content),
+        "window_drag_modifiers" => report.validate::<shortcuts::Modifiers>(path, content),
+        "compositor_shortcuts" => {
+            report.validate::<HashMap<shortcuts::Binding, CompositorAction>>(path, content)
+        }
+        "hooks" => {
+            if let Some(hooks) = report.check::<Vec<Hook>>(path, content) {
+                for hook in hooks {
+                    if hook.command.trim().is_empty() {
+                        report.error(path, format!("hook for {:?} has no command", hook.event));
+                    }
+                }
+            }
+        }
+        "protocol_permissions" => {
+            if let Some(permissions) = report.check::<ProtocolPermissionsConfig>(path, content) {
+                for permission in permissions.allow {
+                    if permission.executable.is_none()
+                        && permission.systemd_unit.is_none()
+                        && permission.app_id.is_none()
+                    {
+                        report.error(
+                            path,
+                            "permission needs an executable, systemd_unit or app_id to match",
+                        );
+                    }
+                    if let Some(executable) = permission
+                        .executable
+                        .as_ref()
+                        .filter(|executable| !executable.is_absolute())
+                    {
+                        report.error(
+                            path,
+                            format!("executable {:?} is not an absolute path", executable),
+                        );
+                    }
+                    if permission.protocols.is_empty() {
+                        report.warning(path, "permission doesn't allow any protocols");
+                    }
+                }
+            }
+        }
+        "metrics" => {
+            if let Some(metrics) = report.check::<MetricsConfig>(path, content) {
+                if let Some(address) = metrics.prometheus_address {
+                    if address.parse::<std::net::SocketAddr>().is_err() {
+                        report.error(
+                            path,
+                            format!(
+                                "prometheus_address {:?} is not an ip address and port",
+                                address
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        "keybinding_profile" => report.validate::<KeybindingProfile>(path, content),
+        "workspace_names" => report.validate::<Vec<String>>(path, content),
+        "workspace_overrides" => {
+            report.validate::<HashMap<String, WorkspaceOverride>>(path, content)
+        }
+        "binding_modes" => {
+            if let Some(modes) = report.check::<HashMap<String, BindingMode>>(path, content) {
+                for (name, mode) in modes {
+                    if mode.enter.key.is_none() {
+                        report.error(path, format!("binding mode `{name}`: `enter` needs a key"));
+                    }
+                }
+            }
+        }
+        _ => report.warning(path, format!("unknown key `{key}`, it is ignored")),
+    }
+}
+
+fn check_outputs(report: &mut Report, path: &Path, outputs: &OutputsConfig) {
+    for (infos, configs) in outputs.config.iter() {
+        if infos.len() != configs.len() {
+            report.error(
+                path,
+                format!(
+                    "layout with {} outputs has {} configurations",
+                    infos.len(),
+                    configs.len()
+                ),
+            );
+        }
+        for (info, config) in infos.iter().zip(configs.iter()) {
+            if config.scale.is_nan() || config.scale <= 0.0 {
+                report.error(
+                    path,
+                    format!("output `{}`: scale must be positive", info.connector),
+                );
+            }
+            if let OutputState::Mirroring(conn) = &config.enabled {
+                if !infos.iter().any(|info| &info.connector == conn) {
+                    report.warning(
+                        path,
+                        format!(
+                            "output `{}` mirrors `{}`, which isn't part of the layout",
+                            info.connector, conn
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
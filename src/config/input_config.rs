@@ -65,6 +65,7 @@ pub fn for_device(device: &InputDevice) -> InputConfig {
                     None
                 },
                 scroll_factor: None,
+                discrete_scroll: None,
             })
         } else {
             None
@@ -80,6 +81,8 @@ pub fn for_device(device: &InputDevice) -> InputConfig {
             None
         },
         map_to_output: None,
+        tablet_mapping: None,
+        keep_aspect_ratio: None,
     }
 }
 
@@ -1,7 +1,11 @@
-use cosmic_comp_config::workspace::WorkspaceLayout;
+use crate::input::mouse_keys::MouseKey;
+use cosmic_comp_config::{workspace::WorkspaceLayout, CompositorAction, KeybindingProfile};
+use cosmic_config::ConfigGet;
 use cosmic_settings_config::shortcuts::State as KeyState;
-use cosmic_settings_config::shortcuts::{self, Modifiers, Shortcuts};
+use cosmic_settings_config::shortcuts::{self, Binding, Modifiers, Shortcuts};
 use smithay::input::keyboard::ModifiersState;
+use std::collections::HashMap;
+use tracing::warn;
 use xkbcommon::xkb;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -10,6 +14,8 @@ pub enum Action {
     Private(PrivateAction),
     /// Behaviors managed via cosmic-settings.
     Shortcut(shortcuts::Action),
+    /// Behaviors of the compositor, bound in its own config.
+    Compositor(CompositorAction),
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -21,6 +27,100 @@ pub enum PrivateAction {
         shortcuts::action::ResizeEdge,
         shortcuts::State,
     ),
+    /// Numpad key pressed (`true`) or released, while mouse keys are enabled
+    MouseKey(MouseKey, xkb::Keycode, bool),
+    /// Draws a ring around the pointer, after Ctrl was pressed and released on its own
+    LocatePointer,
+    /// Enters the named binding mode, or leaves the active one if `None`
+    BindingMode(Option<String>),
+}
+
+/// Default bindings of the compositor's own actions
+fn default_compositor_shortcuts() -> HashMap<Binding, CompositorAction> {
+    match ron::from_str(include_str!("../../data/compositor_keybindings.ron")) {
+        Ok(shortcuts) => shortcuts,
+        Err(err) => {
+            warn!(?err, "Failed to parse default compositor shortcuts.");
+            HashMap::new()
+        }
+    }
+}
+
+/// Loads the bindings of the compositor's own actions, the `custom` ones from the
+/// `compositor_shortcuts` config replacing defaults with the same binding.
+pub fn load_compositor_shortcuts(
+    custom: &HashMap<Binding, CompositorAction>,
+) -> HashMap<Binding, CompositorAction> {
+    let mut shortcuts = default_compositor_shortcuts();
+    shortcuts.extend(
+        custom
+            .iter()
+            .map(|(binding, action)| (binding.clone(), *action)),
+    );
+    shortcuts.retain(|_, action| *action != CompositorAction::Disable);
+    shortcuts
+}
+
+/// Default shortcuts of a built-in profile, `None` for the COSMIC defaults
+/// read from the configuration
+fn profile_shortcuts(profile: KeybindingProfile) -> Option<Shortcuts> {
+    let bindings = match profile {
+        KeybindingProfile::Cosmic => return None,
+        KeybindingProfile::I3 => include_str!("../../data/profiles/i3.ron"),
+        KeybindingProfile::Gnome => include_str!("../../data/profiles/gnome.ron"),
+    };
+    match ron::from_str(bindings) {
+        Ok(shortcuts) => Some(shortcuts),
+        Err(err) => {
+            warn!(?err, ?profile, "Failed to parse keybinding profile.");
+            None
+        }
+    }
+}
+
+/// Loads the keyboard shortcuts from `config`, adding any missing default shortcuts
+/// recommended by the compositor.
+///
+/// With a profile other than [`KeybindingProfile::Cosmic`], its shortcuts replace the
+/// configured defaults, while custom shortcuts still take precedence.
+///
+/// Bindings shadowed by `compositor_shortcuts` are reported, as they never trigger.
+pub fn load_shortcuts(
+    config: &cosmic_config::Config,
+    workspace_layout: WorkspaceLayout,
+    profile: KeybindingProfile,
+    compositor_shortcuts: &HashMap<Binding, CompositorAction>,
+) -> Shortcuts {
+    let mut shortcuts = match profile_shortcuts(profile) {
+        Some(mut shortcuts) => {
+            if let Ok(custom) = config.get::<Shortcuts>("custom") {
+                shortcuts.0.extend(custom.0);
+            }
+            shortcuts
+        }
+        None => shortcuts::shortcuts(config),
+    };
+    add_default_bindings(&mut shortcuts, workspace_layout);
+
+    for (binding, action) in shortcuts.iter() {
+        if *action == shortcuts::Action::Disable || binding.key.is_none() {
+            continue;
+        }
+        if let Some((_, compositor_action)) = compositor_shortcuts
+            .iter()
+            .find(|(other, _)| other.modifiers == binding.modifiers && other.key == binding.key)
+        {
+            warn!(
+                ?binding,
+                ?action,
+                ?compositor_action,
+                "Shortcut conflicts with a compositor shortcut and never triggers, \
+                 rebind either of them or set the compositor one to `Disable`."
+            );
+        }
+    }
+
+    shortcuts
 }
 
 pub fn add_default_bindings(shortcuts: &mut Shortcuts, workspace_layout: WorkspaceLayout) {
@@ -129,6 +229,15 @@ pub fn cosmic_modifiers_eq_smithay(this: &Modifiers, other: &ModifiersState) ->
         && this.logo == other.logo
 }
 
+/// Check that all of `this` modifiers, and at least one, are held according to `other`.
+pub fn cosmic_modifiers_held_smithay(this: &Modifiers, other: &ModifiersState) -> bool {
+    (this.ctrl || this.alt || this.shift || this.logo)
+        && (!this.ctrl || other.ctrl)
+        && (!this.alt || other.alt)
+        && (!this.shift || other.shift)
+        && (!this.logo || other.logo)
+}
+
 /// Convert `smithay::input::keyboard::ModifiersState` to `cosmic_settings_config::shortcuts::Modifiers`
 pub fn cosmic_modifiers_from_smithay(value: ModifiersState) -> Modifiers {
     Modifiers {
@@ -138,3 +247,64 @@ pub fn cosmic_modifiers_from_smithay(value: ModifiersState) -> Modifiers {
         logo: value.logo,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::default_compositor_shortcuts;
+    use cosmic_comp_config::{CompositorAction, PointerWarp};
+    use cosmic_settings_config::shortcuts::{Binding, Modifiers};
+    use xkbcommon::xkb::Keysym;
+
+    #[test]
+    fn test_default_compositor_shortcuts() {
+        let shortcuts = default_compositor_shortcuts();
+        let action = |modifiers: Modifiers, key: Keysym| {
+            shortcuts
+                .get(&Binding {
+                    modifiers,
+                    key: Some(key),
+                    description: None,
+                })
+                .copied()
+        };
+
+        assert_eq!(
+            action(Modifiers::new().logo().alt(), Keysym::equal),
+            Some(CompositorAction::ZoomIn)
+        );
+        assert_eq!(
+            action(Modifiers::new().alt().shift(), Keysym::Tab),
+            Some(CompositorAction::WindowSwitcherPrevious)
+        );
+        assert_eq!(
+            action(Modifiers::new().shift(), Keysym::XF86_ScreenSaver),
+            Some(CompositorAction::TurnOffActiveOutput)
+        );
+        assert_eq!(
+            action(Modifiers::new().shift(), Keysym::Print),
+            Some(CompositorAction::InteractiveScreenshot)
+        );
+        assert_eq!(
+            action(Modifiers::new().logo().alt(), Keysym::c),
+            Some(CompositorAction::ToggleColorFilter)
+        );
+        assert_eq!(
+            action(Modifiers::new().logo().alt(), Keysym::t),
+            Some(CompositorAction::ToggleTabletMapping)
+        );
+        assert_eq!(
+            action(Modifiers::new().logo().alt(), Keysym::Escape),
+            Some(CompositorAction::RestoreShortcuts)
+        );
+        assert_eq!(
+            action(Modifiers::new().logo().alt(), Keysym::KP_Home),
+            Some(CompositorAction::WarpPointer(PointerWarp::Cell(0, 0)))
+        );
+        assert_eq!(
+            action(Modifiers::new().logo().alt(), Keysym::_3),
+            Some(CompositorAction::WarpPointer(PointerWarp::Output(2)))
+        );
+        // Alt+Tab is `System(WindowSwitcher)` of the regular shortcuts
+        assert_eq!(action(Modifiers::new().alt(), Keysym::Tab), None);
+    }
+}
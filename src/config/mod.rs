@@ -7,6 +7,7 @@ use crate::{
         output_configuration::OutputConfigurationState, workspace::WorkspaceUpdateGuard,
     },
 };
+use calloop::ping::Ping;
 use cosmic_config::{ConfigGet, CosmicConfigEntry};
 use cosmic_settings_config::{shortcuts, Shortcuts};
 use serde::{Deserialize, Serialize};
@@ -22,17 +23,18 @@ pub use smithay::{
             TapButtonMap,
         },
     },
-    utils::{Logical, Physical, Point, Size, Transform},
+    utils::{Logical, Physical, Point, Rectangle, Size, Transform},
 };
 use std::{
     cell::RefCell,
     collections::{BTreeMap, HashMap},
     fs::OpenOptions,
     path::PathBuf,
-    sync::{atomic::AtomicBool, Arc, RwLock},
+    sync::{atomic::AtomicBool, Arc, OnceLock, RwLock},
 };
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
+pub mod check;
 mod input_config;
 pub mod key_bindings;
 pub use key_bindings::{Action, PrivateAction};
@@ -40,7 +42,13 @@ mod types;
 pub use self::types::*;
 use cosmic::config::CosmicTk;
 use cosmic_comp_config::{
-    input::InputConfig, workspace::WorkspaceConfig, CosmicCompConfig, TileBehavior, XkbConfig,
+    input::{InputConfig, TabletMapping},
+    workspace::{WorkspaceConfig, WorkspaceOverride},
+    ActivationPolicy, AdaptiveSyncMode, BindingMode, BlurConfig, ColorFilterConfig,
+    CompositorAction, CosmicCompConfig, CursorConfig, CustomMode, DimConfig, GameModeConfig,
+    GestureConfig, GpuConfig, Hook, IdleConfig, KeybindingProfile, KeyboardAccessibilityConfig,
+    MetricsConfig, ProtocolPermissionsConfig, ShadowConfig, TearingConfig, TileBehavior,
+    VirtualOutputConfig, VisualBell, WallpaperConfig, WindowSwitcherConfig, XkbConfig, ZoomConfig,
 };
 
 #[derive(Debug)]
@@ -52,6 +60,8 @@ pub struct Config {
     pub settings_context: cosmic_config::Config,
     /// Key bindings from `com.system76.CosmicSettings.Shortcuts`
     pub shortcuts: Shortcuts,
+    /// Key bindings of the compositor's own actions
+    pub compositor_shortcuts: HashMap<shortcuts::Binding, CompositorAction>,
     /// System actions from `com.system76.CosmicSettings.Shortcuts`
     pub system_actions: BTreeMap<shortcuts::action::System, String>,
 }
@@ -64,6 +74,109 @@ pub struct DynamicConfig {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OutputsConfig {
     pub config: HashMap<Vec<OutputInfo>, Vec<OutputConfig>>,
+    /// Last configuration of every monitor, for combinations of outputs without a saved layout
+    #[serde(default)]
+    pub monitors: HashMap<MonitorId, OutputConfig>,
+}
+
+/// Serial number of the monitor connected to an output, read from its EDID
+pub struct MonitorSerial(pub String);
+
+/// Identifies a monitor regardless of the connector it is plugged into
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MonitorId {
+    pub make: String,
+    pub model: String,
+    /// Serial number of the monitor, or the connector for monitors without one
+    pub serial: String,
+}
+
+impl From<&Output> for MonitorId {
+    fn from(o: &Output) -> MonitorId {
+        let physical = o.physical_properties();
+        MonitorId {
+            make: physical.make,
+            model: physical.model,
+            serial: o
+                .user_data()
+                .get::<MonitorSerial>()
+                .map(|serial| serial.0.clone())
+                .unwrap_or_else(|| o.name()),
+        }
+    }
+}
+
+impl OutputsConfig {
+    /// Configurations of `outputs` (sorted like `infos`) from their last use,
+    /// or `None` if none of the monitors are known.
+    ///
+    /// New monitors and those overlapping another one are placed right of the others.
+    fn configs_for_monitors(
+        &self,
+        infos: &[OutputInfo],
+        outputs: &[Output],
+    ) -> Option<Vec<OutputConfig>> {
+        let mut known = false;
+        let mut configs = infos
+            .iter()
+            .map(|info| {
+                let output = outputs.iter().find(|o| o.name() == info.connector).unwrap();
+                match self.monitors.get(&MonitorId::from(output)) {
+                    Some(config) => {
+                        known = true;
+                        (true, config.clone())
+                    }
+                    None => (
+                        false,
+                        output
+                            .user_data()
+                            .get::<RefCell<OutputConfig>>()
+                            .unwrap()
+                            .borrow()
+                            .clone(),
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+        if !known {
+            return None;
+        }
+
+        for (_, config) in configs.iter_mut() {
+            if let OutputState::Mirroring(conn) = &config.enabled {
+                if !infos.iter().any(|info| &info.connector == conn) {
+                    config.enabled = OutputState::Enabled;
+                }
+            }
+        }
+
+        // place the known monitors first, so they keep their positions if possible
+        let mut order = (0..configs.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| !configs[*i].0);
+        let mut placed: Vec<Rectangle<i32, Logical>> = Vec::new();
+        for i in order {
+            let config = &mut configs[i].1;
+            if config.enabled != OutputState::Enabled {
+                continue;
+            }
+            let mut geometry = Rectangle::from_loc_and_size(
+                (config.position.0 as i32, config.position.1 as i32),
+                config.transformed_size(),
+            );
+            if placed.iter().any(|other| other.overlaps(geometry)) {
+                let right = placed
+                    .iter()
+                    .map(|other| other.loc.x + other.size.w)
+                    .max()
+                    .unwrap_or(0);
+                config.position = (right as u32, 0);
+                geometry.loc = (right, 0).into();
+            }
+            placed.push(geometry);
+        }
+
+        Some(configs.into_iter().map(|(_, config)| config).collect())
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -110,6 +223,13 @@ pub struct OutputConfig {
     pub enabled: OutputState,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub max_bpc: Option<u32>,
+    /// ICC profile, whose calibration curves are loaded into the gamma ramp of the output.
+    ///
+    /// Only the `vcgt` tag is used, the color transform of the profile itself is left to
+    /// color-managed clients. Outputs without a gamma ramp can't be calibrated. The profile is
+    /// read again when the path or the night light changes, not when the file is modified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icc_profile: Option<PathBuf>,
 }
 
 impl Default for OutputConfig {
@@ -122,6 +242,7 @@ impl Default for OutputConfig {
             position: (0, 0),
             enabled: OutputState::Enabled,
             max_bpc: None,
+            icc_profile: None,
         }
     }
 }
@@ -131,6 +252,15 @@ impl OutputConfig {
         self.mode.0.into()
     }
 
+    /// Size the output occupies in the global space, after applying its transform and scale
+    pub fn transformed_size(&self) -> Size<i32, Logical> {
+        self.transform
+            .transform_size(self.mode_size())
+            .to_f64()
+            .to_logical(self.scale)
+            .to_i32_round()
+    }
+
     pub fn mode_refresh(&self) -> u32 {
         self.mode.1.unwrap_or(60_000)
     }
@@ -201,11 +331,15 @@ impl Config {
 
         // Source key bindings from com.system76.CosmicSettings.Shortcuts
         let settings_context = shortcuts::context().expect("Failed to load shortcuts config");
-        let system_actions = shortcuts::system_actions(&config);
-        let mut shortcuts = shortcuts::shortcuts(&settings_context);
-
-        // Add any missing default shortcuts recommended by the compositor.
-        key_bindings::add_default_bindings(&mut shortcuts, workspace.workspace_layout);
+        let system_actions = shortcuts::system_actions(&settings_context);
+        let compositor_shortcuts =
+            key_bindings::load_compositor_shortcuts(&cosmic_comp_config.compositor_shortcuts);
+        let shortcuts = key_bindings::load_shortcuts(
+            &settings_context,
+            workspace.workspace_layout,
+            cosmic_comp_config.keybinding_profile,
+            &compositor_shortcuts,
+        );
 
         // Listen for updates to the keybindings config.
         match cosmic_config::calloop::ConfigWatchSource::new(&settings_context) {
@@ -215,11 +349,13 @@ impl Config {
                         match key.as_str() {
                             // Reload the keyboard shortcuts config.
                             "custom" | "defaults" => {
-                                let mut shortcuts = shortcuts::shortcuts(&config);
-                                let layout = get_config::<WorkspaceConfig>(&config, "workspaces")
-                                    .workspace_layout;
-                                key_bindings::add_default_bindings(&mut shortcuts, layout);
-                                state.common.config.shortcuts = shortcuts;
+                                let comp_config = &state.common.config;
+                                state.common.config.shortcuts = key_bindings::load_shortcuts(
+                                    &config,
+                                    comp_config.cosmic_conf.workspaces.workspace_layout,
+                                    comp_config.cosmic_conf.keybinding_profile,
+                                    &comp_config.compositor_shortcuts,
+                                );
                             }
 
                             "system_actions" => {
@@ -243,16 +379,32 @@ impl Config {
             ),
         };
 
+        watch_sighup(loop_handle);
+
         Config {
             dynamic_conf: Self::load_dynamic(xdg.as_ref()),
             cosmic_conf: cosmic_comp_config,
             cosmic_helper: config,
             settings_context,
             shortcuts,
+            compositor_shortcuts,
             system_actions,
         }
     }
 
+    /// Reloads the keyboard shortcuts and system actions, e.g. on SIGHUP
+    pub fn reload_shortcuts(&mut self) {
+        self.compositor_shortcuts =
+            key_bindings::load_compositor_shortcuts(&self.cosmic_conf.compositor_shortcuts);
+        self.shortcuts = key_bindings::load_shortcuts(
+            &self.settings_context,
+            self.cosmic_conf.workspaces.workspace_layout,
+            self.cosmic_conf.keybinding_profile,
+            &self.compositor_shortcuts,
+        );
+        self.system_actions = shortcuts::system_actions(&self.settings_context);
+    }
+
     fn load_dynamic(xdg: Option<&xdg::BaseDirectories>) -> DynamicConfig {
         let output_path =
             xdg.and_then(|base| base.place_state_file("cosmic-comp/outputs.ron").ok());
@@ -304,6 +456,7 @@ impl Config {
 
         OutputsConfig {
             config: HashMap::new(),
+            monitors: HashMap::new(),
         }
     }
 
@@ -328,7 +481,18 @@ impl Config {
             .map(Into::<crate::config::OutputInfo>::into)
             .collect::<Vec<_>>();
         infos.sort();
-        if let Some(configs) = self.dynamic_conf.outputs().config.get(&infos).cloned() {
+        let saved_configs = self
+            .dynamic_conf
+            .outputs()
+            .config
+            .get(&infos)
+            .cloned()
+            .or_else(|| {
+                self.dynamic_conf
+                    .outputs()
+                    .configs_for_monitors(&infos, &outputs)
+            });
+        if let Some(configs) = saved_configs {
             let known_good_configs = outputs
                 .iter()
                 .map(|output| {
@@ -449,6 +613,7 @@ impl Config {
                 let o = o.borrow();
                 (
                     Into::<crate::config::OutputInfo>::into(o.clone()),
+                    MonitorId::from(o),
                     o.user_data()
                         .get::<RefCell<OutputConfig>>()
                         .unwrap()
@@ -456,13 +621,20 @@ impl Config {
                         .clone(),
                 )
             })
-            .collect::<Vec<(OutputInfo, OutputConfig)>>();
-        infos.sort_by(|&(ref a, _), &(ref b, _)| a.cmp(b));
-        let (infos, configs) = infos.into_iter().unzip();
-        self.dynamic_conf
-            .outputs_mut()
-            .config
-            .insert(infos, configs);
+            .collect::<Vec<(OutputInfo, MonitorId, OutputConfig)>>();
+        infos.sort_by(|&(ref a, _, _), &(ref b, _, _)| a.cmp(b));
+
+        let mut outputs_config = self.dynamic_conf.outputs_mut();
+        for (_, monitor, config) in infos.iter() {
+            outputs_config
+                .monitors
+                .insert(monitor.clone(), config.clone());
+        }
+        let (infos, configs) = infos
+            .into_iter()
+            .map(|(info, _, config)| (info, config))
+            .unzip();
+        outputs_config.config.insert(infos, configs);
     }
 
     pub fn xkb_config(&self) -> XkbConfig {
@@ -482,6 +654,14 @@ impl Config {
         .map_or(1.0, |x| x.0)
     }
 
+    pub fn discrete_scroll(&self, device: &InputDevice) -> bool {
+        let (device_config, default_config) = self.get_device_config(device);
+        input_config::get_config(device_config, default_config, |x| {
+            x.scroll_config.as_ref()?.discrete_scroll
+        })
+        .map_or(false, |x| x.0)
+    }
+
     pub fn map_to_output(&self, device: &InputDevice) -> Option<&str> {
         let (device_config, default_config) = self.get_device_config(device);
         Some(
@@ -492,13 +672,33 @@ impl Config {
         )
     }
 
+    pub fn tablet_mapping(&self, device: &InputDevice) -> TabletMapping {
+        let (device_config, default_config) = self.get_device_config(device);
+        input_config::get_config(device_config, default_config, |x| x.tablet_mapping.clone())
+            .map(|x| x.0)
+            .unwrap_or_default()
+    }
+
+    pub fn keep_aspect_ratio(&self, device: &InputDevice) -> bool {
+        let (device_config, default_config) = self.get_device_config(device);
+        input_config::get_config(device_config, default_config, |x| x.keep_aspect_ratio)
+            .map_or(false, |x| x.0)
+    }
+
     fn get_device_config(&self, device: &InputDevice) -> (Option<&InputConfig>, &InputConfig) {
         let default_config = if device.config_tap_finger_count() > 0 {
             &self.cosmic_conf.input_touchpad
         } else {
             &self.cosmic_conf.input_default
         };
-        let device_config = self.cosmic_conf.input_devices.get(device.name());
+        let devices = &self.cosmic_conf.input_devices;
+        let device_config = devices.get(device.name()).or_else(|| {
+            devices.get(&format!(
+                "{:04x}:{:04x}",
+                device.id_vendor(),
+                device.id_product()
+            ))
+        });
         (device_config, default_config)
     }
 }
@@ -548,6 +748,11 @@ impl DynamicConfig {
     pub fn outputs_mut(&mut self) -> PersistenceGuard<'_, OutputsConfig> {
         PersistenceGuard(self.outputs.0.clone(), &mut self.outputs.1)
     }
+
+    /// Reads the output configurations again, e.g. after they were edited by hand
+    pub fn reload_outputs(&mut self) {
+        self.outputs.1 = Config::load_outputs(&self.outputs.0);
+    }
 }
 
 fn get_config<T: Default + serde::de::DeserializeOwned>(
@@ -582,6 +787,7 @@ fn config_changed(config: cosmic_config::Config, keys: Vec<String>, state: &mut
                     .iter()
                     .cloned()
                     .collect::<Vec<_>>();
+                let mut failed = false;
                 for seat in seats.into_iter() {
                     if let Some(keyboard) = seat.get_keyboard() {
                         keyboard.change_repeat_info(
@@ -589,12 +795,15 @@ fn config_changed(config: cosmic_config::Config, keys: Vec<String>, state: &mut
                             (value.repeat_delay as i32).abs(),
                         );
                         if let Err(err) = keyboard.set_xkb_config(state, xkb_config_to_wl(&value)) {
+                            // the previous keymap stays active
                             error!(?err, "Failed to load provided xkb config");
-                            // TODO Revert to default?
+                            failed = true;
                         }
                     }
                 }
-                state.common.config.cosmic_conf.xkb_config = value;
+                if !failed {
+                    state.common.config.cosmic_conf.xkb_config = value;
+                }
             }
             "input_default" => {
                 let value = get_config::<InputConfig>(&config, "input_default");
@@ -614,6 +823,8 @@ fn config_changed(config: cosmic_config::Config, keys: Vec<String>, state: &mut
             "workspaces" => {
                 state.common.config.cosmic_conf.workspaces =
                     get_config::<WorkspaceConfig>(&config, "workspaces");
+                // the default workspace shortcuts follow the layout
+                state.common.config.reload_shortcuts();
                 state.common.update_config();
             }
             "autotile" => {
@@ -651,6 +862,103 @@ fn config_changed(config: cosmic_config::Config, keys: Vec<String>, state: &mut
                     state.common.update_config();
                 }
             }
+            "tearing" => {
+                let new = get_config::<TearingConfig>(&config, "tearing");
+                if new != state.common.config.cosmic_conf.tearing {
+                    state.common.config.cosmic_conf.tearing = new;
+                    state.common.update_config();
+                }
+            }
+            "game_mode" => {
+                let new = get_config::<GameModeConfig>(&config, "game_mode");
+                if new != state.common.config.cosmic_conf.game_mode {
+                    state.common.config.cosmic_conf.game_mode = new;
+                    state.common.update_config();
+                }
+            }
+            "activation" => {
+                let new = get_config::<ActivationPolicy>(&config, "activation");
+                state.common.config.cosmic_conf.activation = new;
+            }
+            "force_server_side_decorations" => {
+                let new = get_config::<Vec<String>>(&config, "force_server_side_decorations");
+                // only affects decorations negotiated from now on
+                state
+                    .common
+                    .config
+                    .cosmic_conf
+                    .force_server_side_decorations = new;
+            }
+            "capture_excluded_apps" => {
+                let new = get_config::<Vec<String>>(&config, "capture_excluded_apps");
+                if new != state.common.config.cosmic_conf.capture_excluded_apps {
+                    state.common.config.cosmic_conf.capture_excluded_apps = new;
+                    state.common.update_config();
+                }
+            }
+            "adaptive_sync" => {
+                let new = get_config::<AdaptiveSyncMode>(&config, "adaptive_sync");
+                if new != state.common.config.cosmic_conf.adaptive_sync {
+                    state.common.config.cosmic_conf.adaptive_sync = new;
+                    state.common.update_config();
+                    for output in state.common.shell.read().unwrap().outputs() {
+                        state.backend.schedule_render(output);
+                    }
+                }
+            }
+            "gpu" => {
+                let new = get_config::<GpuConfig>(&config, "gpu");
+                if new != state.common.config.cosmic_conf.gpu {
+                    state.common.config.cosmic_conf.gpu = new;
+                    if let BackendData::Kms(ref mut kms_state) = &mut state.backend {
+                        if let Err(err) =
+                            kms_state.update_render_devices(&state.common.config.cosmic_conf.gpu)
+                        {
+                            error!(?err, "Failed to apply gpu config");
+                        }
+                    }
+                }
+            }
+            "custom_modes" => {
+                let new = get_config::<HashMap<String, Vec<CustomMode>>>(&config, "custom_modes");
+                if new != state.common.config.cosmic_conf.custom_modes {
+                    state.common.config.cosmic_conf.custom_modes = new;
+                    if let BackendData::Kms(ref mut kms_state) = &mut state.backend {
+                        kms_state
+                            .update_custom_modes(&state.common.config.cosmic_conf.custom_modes);
+                        state.common.output_configuration_state.update();
+                    }
+                }
+            }
+            "virtual_outputs" => {
+                let new = get_config::<Vec<VirtualOutputConfig>>(&config, "virtual_outputs");
+                if new != state.common.config.cosmic_conf.virtual_outputs {
+                    state.common.config.cosmic_conf.virtual_outputs = new;
+                    state.update_virtual_outputs();
+                }
+            }
+            "idle" => {
+                let new = get_config::<IdleConfig>(&config, "idle");
+                if new != state.common.config.cosmic_conf.idle {
+                    state.common.config.cosmic_conf.idle = new;
+                    state.reset_idle_timer();
+                }
+            }
+            "xwayland" => {
+                let new = get_config::<bool>(&config, "xwayland");
+                if new != state.common.config.cosmic_conf.xwayland {
+                    state.common.config.cosmic_conf.xwayland = new;
+                    if new {
+                        let render_node = match &state.backend {
+                            BackendData::Kms(kms) => kms.primary_node,
+                            _ => None,
+                        };
+                        state.launch_xwayland(render_node);
+                    } else {
+                        state.stop_xwayland();
+                    }
+                }
+            }
             "descale_xwayland" => {
                 let new = get_config::<bool>(&config, "descale_xwayland");
                 if new != state.common.config.cosmic_conf.descale_xwayland {
@@ -658,17 +966,246 @@ fn config_changed(config: cosmic_config::Config, keys: Vec<String>, state: &mut
                     state.common.update_xwayland_scale();
                 }
             }
+            "xwayland_unscaled_apps" => {
+                let new = get_config::<Vec<String>>(&config, "xwayland_unscaled_apps");
+                if new != state.common.config.cosmic_conf.xwayland_unscaled_apps {
+                    state.common.config.cosmic_conf.xwayland_unscaled_apps = new;
+                    state.common.update_xwayland_scale();
+                }
+            }
+            "wallpaper" => {
+                let new = get_config::<WallpaperConfig>(&config, "wallpaper");
+                if new != state.common.config.cosmic_conf.wallpaper {
+                    state.common.config.cosmic_conf.wallpaper = new;
+                    state.update_wallpapers();
+                }
+            }
+            "blur" => {
+                let new = get_config::<BlurConfig>(&config, "blur");
+                if new != state.common.config.cosmic_conf.blur {
+                    state.common.config.cosmic_conf.blur = new;
+                    state.common.update_config();
+                    for output in state.common.shell.read().unwrap().outputs() {
+                        state.backend.schedule_render(output);
+                    }
+                }
+            }
+            "corner_radius" => {
+                let new = get_config::<u32>(&config, "corner_radius");
+                if new != state.common.config.cosmic_conf.corner_radius {
+                    state.common.config.cosmic_conf.corner_radius = new;
+                    state.common.update_config();
+                    for output in state.common.shell.read().unwrap().outputs() {
+                        state.backend.schedule_render(output);
+                    }
+                }
+            }
+            "shadow" => {
+                let new = get_config::<ShadowConfig>(&config, "shadow");
+                if new != state.common.config.cosmic_conf.shadow {
+                    state.common.config.cosmic_conf.shadow = new;
+                    state.common.update_config();
+                    for output in state.common.shell.read().unwrap().outputs() {
+                        state.backend.schedule_render(output);
+                    }
+                }
+            }
+            "dim_unfocused" => {
+                let new = get_config::<DimConfig>(&config, "dim_unfocused");
+                if new != state.common.config.cosmic_conf.dim_unfocused {
+                    state.common.config.cosmic_conf.dim_unfocused = new;
+                    state.common.update_config();
+                    for output in state.common.shell.read().unwrap().outputs() {
+                        state.backend.schedule_render(output);
+                    }
+                }
+            }
+            "window_switcher" => {
+                let new = get_config::<WindowSwitcherConfig>(&config, "window_switcher");
+                if new != state.common.config.cosmic_conf.window_switcher {
+                    state.common.config.cosmic_conf.window_switcher = new;
+                }
+            }
+            "keyboard_accessibility" => {
+                let new =
+                    get_config::<KeyboardAccessibilityConfig>(&config, "keyboard_accessibility");
+                if new != state.common.config.cosmic_conf.keyboard_accessibility {
+                    state.common.config.cosmic_conf.keyboard_accessibility = new;
+                    if !new.sticky_keys {
+                        state.release_sticky_keys();
+                    }
+                }
+            }
+            "window_drag_modifiers" => {
+                let new = get_config::<shortcuts::Modifiers>(&config, "window_drag_modifiers");
+                if new != state.common.config.cosmic_conf.window_drag_modifiers {
+                    state.common.config.cosmic_conf.window_drag_modifiers = new;
+                }
+            }
+            "gestures" => {
+                let new = get_config::<GestureConfig>(&config, "gestures");
+                if new != state.common.config.cosmic_conf.gestures {
+                    state.common.config.cosmic_conf.gestures = new;
+                }
+            }
+            "binding_modes" => {
+                let new = get_config::<HashMap<String, BindingMode>>(&config, "binding_modes");
+                if new != state.common.config.cosmic_conf.binding_modes {
+                    let removed = state
+                        .common
+                        .binding_mode
+                        .as_ref()
+                        .is_some_and(|mode| !new.contains_key(mode));
+                    state.common.config.cosmic_conf.binding_modes = new;
+                    if removed {
+                        state.set_binding_mode(None);
+                    }
+                }
+            }
+            "compositor_shortcuts" => {
+                let new = get_config::<HashMap<shortcuts::Binding, CompositorAction>>(
+                    &config,
+                    "compositor_shortcuts",
+                );
+                if new != state.common.config.cosmic_conf.compositor_shortcuts {
+                    state.common.config.cosmic_conf.compositor_shortcuts = new;
+                    state.common.config.reload_shortcuts();
+                }
+            }
+            "keybinding_profile" => {
+                let new = get_config::<KeybindingProfile>(&config, "keybinding_profile");
+                if new != state.common.config.cosmic_conf.keybinding_profile {
+                    state.common.config.cosmic_conf.keybinding_profile = new;
+                    state.common.config.reload_shortcuts();
+                }
+            }
+            "hooks" => {
+                // hooks are looked up whenever an event happens
+                state.common.config.cosmic_conf.hooks = get_config::<Vec<Hook>>(&config, "hooks");
+            }
+            "protocol_permissions" => {
+                // decided for every client when it connects
+                state.common.config.cosmic_conf.protocol_permissions =
+                    get_config::<ProtocolPermissionsConfig>(&config, "protocol_permissions");
+            }
+            "metrics" => {
+                let new = get_config::<MetricsConfig>(&config, "metrics");
+                if new != state.common.config.cosmic_conf.metrics {
+                    state.common.config.cosmic_conf.metrics = new;
+                    state.update_metrics_endpoint();
+                }
+            }
+            "workspace_names" => {
+                let new = get_config::<Vec<String>>(&config, "workspace_names");
+                if new != state.common.config.cosmic_conf.workspace_names {
+                    state.common.config.cosmic_conf.workspace_names = new;
+                    state.common.update_config();
+                }
+            }
+            "workspace_overrides" => {
+                let new = get_config::<HashMap<String, WorkspaceOverride>>(
+                    &config,
+                    "workspace_overrides",
+                );
+                if new != state.common.config.cosmic_conf.workspace_overrides {
+                    state.common.config.cosmic_conf.workspace_overrides = new;
+                    state.common.update_config();
+                }
+            }
+            "color_filter" => {
+                let new = get_config::<ColorFilterConfig>(&config, "color_filter");
+                if new != state.common.config.cosmic_conf.color_filter {
+                    state.common.config.cosmic_conf.color_filter = new;
+                    state.set_color_filter(new.enabled.then_some(new.filter));
+                }
+            }
+            "visual_bell" => {
+                state.common.config.cosmic_conf.visual_bell =
+                    get_config::<VisualBell>(&config, "visual_bell");
+            }
+            "cursor" => {
+                let new = get_config::<CursorConfig>(&config, "cursor");
+                let old = state.common.config.cosmic_conf.cursor;
+                if new != old {
+                    state.common.config.cosmic_conf.cursor = new;
+                    if new.size != old.size {
+                        state.set_cursor_size(new.size);
+                    }
+                }
+            }
+            "zoom" => {
+                let new = get_config::<ZoomConfig>(&config, "zoom");
+                if new != state.common.config.cosmic_conf.zoom {
+                    state.common.config.cosmic_conf.zoom = new;
+                    state.common.update_config();
+                    for output in state.common.shell.read().unwrap().outputs() {
+                        state.backend.schedule_render(output);
+                    }
+                }
+            }
             _ => {}
         }
     }
 }
 
+static SIGHUP_PING: OnceLock<Ping> = OnceLock::new();
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    // writing to the eventfd of the ping is async-signal-safe
+    if let Some(ping) = SIGHUP_PING.get() {
+        ping.ping();
+    }
+}
+
+/// Reloads the keyboard shortcuts and output configurations on SIGHUP.
+///
+/// Everything else is stored in cosmic-config and applied as soon as it changes.
+///
+/// A signal handler is used instead of blocking the signal, so spawned processes
+/// don't inherit a blocked SIGHUP.
+fn watch_sighup(loop_handle: &LoopHandle<'_, State>) {
+    let (ping, source) = match calloop::ping::make_ping() {
+        Ok(ping) => ping,
+        Err(err) => {
+            warn!(?err, "Failed to listen for SIGHUP");
+            return;
+        }
+    };
+    if let Err(err) = loop_handle.insert_source(source, |_, _, state| {
+        info!("Reloading shortcuts and outputs on SIGHUP");
+        state.common.config.reload_shortcuts();
+        state.common.config.dynamic_conf.reload_outputs();
+        state.common.config.read_outputs(
+            &mut state.common.output_configuration_state,
+            &mut state.backend,
+            &state.common.shell,
+            &state.common.event_loop_handle,
+            &mut state.common.workspace_state.update(),
+            &state.common.xdg_activation_state,
+            state.common.startup_done.clone(),
+        );
+        state.common.refresh();
+    }) {
+        warn!(?err, "Failed to listen for SIGHUP");
+        return;
+    }
+    if SIGHUP_PING.set(ping).is_ok() {
+        unsafe { libc::signal(libc::SIGHUP, on_sighup as libc::sighandler_t) };
+    }
+}
+
 pub fn xkb_config_to_wl(config: &XkbConfig) -> WlXkbConfig<'_> {
     WlXkbConfig {
         rules: &config.rules,
         model: &config.model,
         layout: &config.layout,
         variant: &config.variant,
-        options: config.options.clone(),
+        options: match (&config.options, &config.compose_key) {
+            (Some(options), Some(key)) if !options.is_empty() => {
+                Some(format!("{},compose:{}", options, key))
+            }
+            (_, Some(key)) => Some(format!("compose:{}", key)),
+            (options, None) => options.clone(),
+        },
     }
 }
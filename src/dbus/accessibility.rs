@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `org.cosmic.Comp.Accessibility` interface, next to `org.cosmic.Comp`, for screen readers.
+//!
+//! Clients can't observe navigation between windows, workspaces or in the window switcher,
+//! so the compositor announces it through signals, for screen readers like Orca to speak.
+//! Changes are found by [`State::update_dbus_properties`] after every dispatch of the event loop.
+//!
+//! [`State::update_dbus_properties`]: crate::state::State::update_dbus_properties
+
+use zbus::{interface, SignalContext};
+
+pub(super) struct Accessibility;
+
+/// Navigation to announce
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Announcement {
+    Focus {
+        id: u64,
+        app_id: String,
+        title: String,
+    },
+    Title {
+        id: u64,
+        title: String,
+    },
+    Workspace {
+        output: String,
+        number: u32,
+        count: u32,
+    },
+    SwitcherSelection {
+        id: u64,
+        app_id: String,
+        title: String,
+    },
+}
+
+#[interface(name = "org.cosmic.Comp.Accessibility")]
+impl Accessibility {
+    /// Keyboard focus moved to the window `id`, or away from all windows if `id` is `0`
+    #[zbus(signal)]
+    async fn focus_changed(
+        ctxt: &SignalContext<'_>,
+        id: u64,
+        app_id: &str,
+        title: &str,
+    ) -> zbus::Result<()>;
+
+    /// The title of the focused window changed
+    #[zbus(signal)]
+    async fn title_changed(ctxt: &SignalContext<'_>, id: u64, title: &str) -> zbus::Result<()>;
+
+    /// The active output or its active workspace changed, `number` counts from 1
+    #[zbus(signal)]
+    async fn workspace_changed(
+        ctxt: &SignalContext<'_>,
+        output: &str,
+        number: u32,
+        count: u32,
+    ) -> zbus::Result<()>;
+
+    /// The window switcher was opened or another window was selected in it
+    #[zbus(signal)]
+    async fn window_switcher_selection_changed(
+        ctxt: &SignalContext<'_>,
+        id: u64,
+        app_id: &str,
+        title: &str,
+    ) -> zbus::Result<()>;
+}
+
+impl Announcement {
+    pub(super) async fn emit(&self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
+        match self {
+            Announcement::Focus { id, app_id, title } => {
+                Accessibility::focus_changed(ctxt, *id, app_id, title).await
+            }
+            Announcement::Title { id, title } => {
+                Accessibility::title_changed(ctxt, *id, title).await
+            }
+            Announcement::Workspace {
+                output,
+                number,
+                count,
+            } => Accessibility::workspace_changed(ctxt, output, *number, *count).await,
+            Announcement::SwitcherSelection { id, app_id, title } => {
+                Accessibility::window_switcher_selection_changed(ctxt, *id, app_id, title).await
+            }
+        }
+    }
+}
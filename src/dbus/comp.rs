@@ -0,0 +1,552 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! `org.cosmic.Comp` service on the session bus, controlling outputs, night light,
+//! zoom, color filters and screenshots for cosmic-settings and cosmic-osd.
+//! Clients capturing the screen are listed for the privacy indicator of the panel.
+//!
+//! Method calls are forwarded to the event loop and wait for it to handle them,
+//! while properties are read from a copy kept up to date by [`State::update_dbus_properties`].
+//! Callers of methods are checked against `protocol_permissions`, like IPC clients.
+//! Navigation is announced for screen readers by the accessibility interface at the same path.
+
+use super::accessibility::{Accessibility, Announcement};
+use crate::{
+    ipc::protocol::{Mode, Request, Response},
+    shell::{focus::target::KeyboardFocusTarget, grabs::SeatWindowSwitcherState, CosmicSurface},
+    state::{BackendData, State},
+    utils::screenshot::screenshot_output,
+};
+use anyhow::{Context, Result};
+use calloop::{channel, InsertError, LoopHandle};
+use cosmic_comp_config::ColorFilter;
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+};
+use tracing::warn;
+use zbus::{fdo, interface, message::Header, zvariant::OwnedValue};
+
+const NAME: &str = "org.cosmic.Comp";
+const PATH: &str = "/org/cosmic/Comp";
+
+type Call = Box<dyn FnOnce(&mut State) + Send>;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Properties {
+    active_output: String,
+    active_workspace: u32,
+    /// IPC id of the focused window, `0` if there is none
+    focused_window: u64,
+    focused_window_title: String,
+    focused_window_app_id: String,
+    zoom_level: f64,
+    /// Color temperature in kelvin, `0` while night light is off
+    night_light: u32,
+    color_filter: Option<ColorFilter>,
+    /// Pid and name of clients capturing the screen or a window
+    capture_clients: Vec<(u32, String)>,
+}
+
+const COLOR_FILTERS: [(ColorFilter, &str); 5] = [
+    (ColorFilter::Grayscale, "grayscale"),
+    (ColorFilter::Inverted, "inverted"),
+    (ColorFilter::Protanopia, "protanopia"),
+    (ColorFilter::Deuteranopia, "deuteranopia"),
+    (ColorFilter::Tritanopia, "tritanopia"),
+];
+
+enum Change {
+    /// Previous values of changed properties
+    Properties(Properties),
+    Announcement(Announcement),
+}
+
+/// The registered service, announcing changes of its properties
+#[derive(Debug)]
+pub struct CompService {
+    properties: Arc<Mutex<Properties>>,
+    changes: mpsc::Sender<Change>,
+    /// Window selected in the window switcher, `0` while it is closed
+    switcher_selection: u64,
+}
+
+struct Comp {
+    calls: channel::Sender<Call>,
+    properties: Arc<Mutex<Properties>>,
+}
+
+impl Comp {
+    /// Runs `f` on the event loop and waits for its result
+    fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut State) -> T + Send + 'static,
+    ) -> fdo::Result<T> {
+        let gone = || fdo::Error::Failed(String::from("The compositor is shutting down"));
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.calls
+            .send(Box::new(move |state| {
+                let _ = tx.send(f(state));
+            }))
+            .map_err(|_| gone())?;
+        rx.recv().map_err(|_| gone())
+    }
+
+    /// Checks that the sender of a method call may control the compositor,
+    /// see `protocol_permissions.restrict`
+    async fn authorize(
+        &self,
+        header: &Header<'_>,
+        connection: &zbus::Connection,
+    ) -> fdo::Result<()> {
+        let pid = match header.sender() {
+            Some(sender) => fdo::DBusProxy::new(connection)
+                .await?
+                .get_connection_unix_process_id(sender.clone().into())
+                .await
+                .ok()
+                .map(|pid| pid as i32),
+            None => None,
+        };
+        if self.call(move |state| state.process_may_control(pid))? {
+            Ok(())
+        } else {
+            Err(fdo::Error::AccessDenied(String::from(
+                "Controlling the compositor isn't allowed by `protocol_permissions`",
+            )))
+        }
+    }
+}
+
+fn take<T: TryFrom<OwnedValue>>(
+    config: &mut HashMap<String, OwnedValue>,
+    key: &str,
+) -> fdo::Result<Option<T>> {
+    config
+        .remove(key)
+        .map(|value| {
+            T::try_from(value)
+                .map_err(|_| fdo::Error::InvalidArgs(format!("`{}` has the wrong type", key)))
+        })
+        .transpose()
+}
+
+#[interface(name = "org.cosmic.Comp")]
+impl Comp {
+    /// Changes the output `name`, leaving properties missing from `config` as they are.
+    ///
+    /// Known properties are `enabled` (b), the mode as `width`, `height` (i) and optionally
+    /// `refresh` (u, in mHz), `scale` (d), the position as `x`, `y` (i) and `adaptive_sync` (b).
+    async fn configure_output(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        name: String,
+        mut config: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<()> {
+        self.authorize(&header, connection).await?;
+        let enabled = take::<bool>(&mut config, "enabled")?;
+        let mode = match (
+            take::<i32>(&mut config, "width")?,
+            take::<i32>(&mut config, "height")?,
+        ) {
+            (Some(width), Some(height)) => Some(Mode {
+                width,
+                height,
+                refresh: take::<u32>(&mut config, "refresh")?,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(fdo::Error::InvalidArgs(String::from(
+                    "`width` and `height` need to be set together",
+                )))
+            }
+        };
+        let scale = take::<f64>(&mut config, "scale")?;
+        let position = match (
+            take::<i32>(&mut config, "x")?,
+            take::<i32>(&mut config, "y")?,
+        ) {
+            (Some(x), Some(y)) => Some((x, y)),
+            (None, None) => None,
+            _ => {
+                return Err(fdo::Error::InvalidArgs(String::from(
+                    "`x` and `y` need to be set together",
+                )))
+            }
+        };
+        let adaptive_sync = take::<bool>(&mut config, "adaptive_sync")?;
+        if let Some(key) = config.keys().next() {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "Unknown output property `{}`",
+                key
+            )));
+        }
+
+        let request = Request::ConfigureOutput {
+            name,
+            enabled,
+            mode,
+            scale,
+            position,
+            adaptive_sync,
+        };
+        match self.call(move |state| state.handle_ipc_request(request))? {
+            Response::Error(err) => Err(fdo::Error::Failed(err)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Shifts the colors of all outputs to `temperature` kelvin, `0` turns night light off
+    async fn set_night_light(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        temperature: u32,
+    ) -> fdo::Result<()> {
+        self.authorize(&header, connection).await?;
+        if temperature != 0 && !(1000..=6500).contains(&temperature) {
+            return Err(fdo::Error::InvalidArgs(String::from(
+                "The temperature must be between 1000 and 6500 kelvin",
+            )));
+        }
+        self.call(move |state| match &mut state.backend {
+            BackendData::Kms(kms) => {
+                kms.set_night_light((temperature != 0).then_some(temperature));
+                Ok(())
+            }
+            _ => Err(fdo::Error::NotSupported(String::from(
+                "Night light is only supported on the kms backend",
+            ))),
+        })?
+    }
+
+    /// Sets the zoom level, where `1.0` is not zoomed in
+    async fn set_zoom_level(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        level: f64,
+    ) -> fdo::Result<()> {
+        self.authorize(&header, connection).await?;
+        if !level.is_finite() {
+            return Err(fdo::Error::InvalidArgs(String::from(
+                "The zoom level must be a number",
+            )));
+        }
+        self.call(move |state| state.set_zoom_level(level))
+    }
+
+    /// Applies the color filter `name` to all outputs, an empty name turns it off.
+    ///
+    /// Known filters are `grayscale`, `inverted`, `protanopia`, `deuteranopia` and `tritanopia`.
+    async fn set_color_filter(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        name: String,
+    ) -> fdo::Result<()> {
+        self.authorize(&header, connection).await?;
+        let filter = match COLOR_FILTERS.iter().find(|(_, n)| *n == name) {
+            Some((filter, _)) => Some(*filter),
+            None if name.is_empty() => None,
+            None => {
+                return Err(fdo::Error::InvalidArgs(format!(
+                    "Unknown color filter `{}`",
+                    name
+                )))
+            }
+        };
+        self.call(move |state| state.set_color_filter(filter))
+    }
+
+    /// Saves a screenshot of the output `name`, or of the active one if empty,
+    /// to the pictures directory
+    async fn screenshot(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        name: String,
+    ) -> fdo::Result<()> {
+        self.authorize(&header, connection).await?;
+        self.call(move |state| {
+            let output = {
+                let shell = state.common.shell.read().unwrap();
+                if name.is_empty() {
+                    Some(shell.seats.last_active().active_output())
+                } else {
+                    shell.outputs().find(|o| o.name() == name).cloned()
+                }
+            };
+            match output {
+                Some(output) => {
+                    screenshot_output(state, &output, None);
+                    Ok(())
+                }
+                None => Err(fdo::Error::InvalidArgs(format!("No output named {}", name))),
+            }
+        })?
+    }
+
+    #[zbus(property)]
+    fn active_output(&self) -> String {
+        self.properties.lock().unwrap().active_output.clone()
+    }
+
+    /// Number of the active workspace of the active output
+    #[zbus(property)]
+    fn active_workspace(&self) -> u32 {
+        self.properties.lock().unwrap().active_workspace
+    }
+
+    /// Id of the focused window, as used by the IPC socket, or `0`
+    #[zbus(property)]
+    fn focused_window(&self) -> u64 {
+        self.properties.lock().unwrap().focused_window
+    }
+
+    #[zbus(property)]
+    fn focused_window_title(&self) -> String {
+        self.properties.lock().unwrap().focused_window_title.clone()
+    }
+
+    #[zbus(property)]
+    fn focused_window_app_id(&self) -> String {
+        self.properties
+            .lock()
+            .unwrap()
+            .focused_window_app_id
+            .clone()
+    }
+
+    #[zbus(property)]
+    fn zoom_level(&self) -> f64 {
+        self.properties.lock().unwrap().zoom_level
+    }
+
+    /// Color temperature in kelvin, or `0` while night light is off
+    #[zbus(property)]
+    fn night_light(&self) -> u32 {
+        self.properties.lock().unwrap().night_light
+    }
+
+    /// Name of the active color filter, or empty if there is none
+    #[zbus(property)]
+    fn color_filter(&self) -> String {
+        let filter = self.properties.lock().unwrap().color_filter;
+        COLOR_FILTERS
+            .iter()
+            .find(|(f, _)| Some(*f) == filter)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Pid and name of the clients capturing the screen or a window, for privacy indicators.
+    ///
+    /// The name is the app id of sandboxed clients and the name of the executable otherwise.
+    #[zbus(property)]
+    fn screen_capture_clients(&self) -> Vec<(u32, String)> {
+        self.properties.lock().unwrap().capture_clients.clone()
+    }
+}
+
+pub fn init(evlh: &LoopHandle<'static, State>) -> Result<CompService> {
+    let (calls, rx) = channel::channel::<Call>();
+    let properties = Arc::new(Mutex::new(Properties::default()));
+    let connection = zbus::blocking::connection::Builder::session()?
+        .name(NAME)?
+        .serve_at(
+            PATH,
+            Comp {
+                calls,
+                properties: properties.clone(),
+            },
+        )?
+        .serve_at(PATH, Accessibility)?
+        .build()
+        .with_context(|| format!("Failed to register {}", NAME))?;
+
+    evlh.insert_source(rx, |event, _, state| {
+        if let channel::Event::Msg(call) = event {
+            call(state);
+        }
+    })
+    .map_err(|InsertError { error, .. }| error)
+    .with_context(|| "Failed to add channel to event_loop")?;
+
+    // emitting signals blocks, so leave that to a helper thread
+    let (changes, changed) = mpsc::channel::<Change>();
+    let current = properties.clone();
+    std::thread::Builder::new()
+        .name("cosmic-comp-dbus".to_string())
+        .spawn(move || {
+            let object_server = connection.object_server();
+            let (iface, accessibility) = match (
+                object_server.interface::<_, Comp>(PATH),
+                object_server.interface::<_, Accessibility>(PATH),
+            ) {
+                (Ok(iface), Ok(accessibility)) => (iface, accessibility),
+                (Err(err), _) | (_, Err(err)) => {
+                    warn!(?err, "Failed to look up {} interface", NAME);
+                    return;
+                }
+            };
+            while let Ok(change) = changed.recv() {
+                let old = match change {
+                    Change::Properties(old) => old,
+                    Change::Announcement(announcement) => {
+                        let ctxt = accessibility.signal_context();
+                        if let Err(err) = zbus::block_on(announcement.emit(ctxt)) {
+                            warn!(?err, ?announcement, "Failed to announce navigation");
+                        }
+                        continue;
+                    }
+                };
+                let new = current.lock().unwrap().clone();
+                let ctxt = iface.signal_context();
+                let result = zbus::block_on(async {
+                    let comp = iface.get().await;
+                    if old.active_output != new.active_output {
+                        comp.active_output_changed(ctxt).await?;
+                    }
+                    if old.active_workspace != new.active_workspace {
+                        comp.active_workspace_changed(ctxt).await?;
+                    }
+                    if old.focused_window != new.focused_window {
+                        comp.focused_window_changed(ctxt).await?;
+                    }
+                    if old.focused_window_title != new.focused_window_title {
+                        comp.focused_window_title_changed(ctxt).await?;
+                    }
+                    if old.focused_window_app_id != new.focused_window_app_id {
+                        comp.focused_window_app_id_changed(ctxt).await?;
+                    }
+                    if old.zoom_level != new.zoom_level {
+                        comp.zoom_level_changed(ctxt).await?;
+                    }
+                    if old.night_light != new.night_light {
+                        comp.night_light_changed(ctxt).await?;
+                    }
+                    if old.color_filter != new.color_filter {
+                        comp.color_filter_changed(ctxt).await?;
+                    }
+                    if old.capture_clients != new.capture_clients {
+                        comp.screen_capture_clients_changed(ctxt).await?;
+                    }
+                    zbus::Result::Ok(())
+                });
+                if let Err(err) = result {
+                    warn!(?err, "Failed to announce changed {} properties", NAME);
+                }
+            }
+        })
+        .with_context(|| "Failed to start helper thread")?;
+
+    Ok(CompService {
+        properties,
+        changes,
+        switcher_selection: 0,
+    })
+}
+
+impl State {
+    /// Announces properties of the `org.cosmic.Comp` service, that changed since the last call
+    pub fn update_dbus_properties(&mut self) {
+        let capture_clients = self.capture_clients();
+        let Some(service) = self.common.dbus_service.as_mut() else {
+            return;
+        };
+        let mut properties = service.properties.lock().unwrap().clone();
+        let mut announcements = Vec::new();
+
+        {
+            let shell = self.common.shell.read().unwrap();
+            let seat = shell.seats.last_active();
+            let output = seat.active_output();
+            properties.active_output = output.name();
+            properties.active_workspace = shell.workspaces.active_num(&output).1 as u32 + 1;
+            properties.zoom_level = shell.zoom_level;
+            properties.color_filter = shell.color_filter;
+
+            let focused = match seat.get_keyboard().unwrap().current_focus() {
+                Some(KeyboardFocusTarget::Element(mapped)) => Some(Some(mapped.active_window())),
+                Some(KeyboardFocusTarget::Fullscreen(surface)) => Some(Some(surface)),
+                // popups belong to the window focused before
+                Some(KeyboardFocusTarget::Popup(_)) => None,
+                _ => Some(None),
+            };
+            if let Some(window) = focused {
+                properties.focused_window = window.as_ref().map(CosmicSurface::id).unwrap_or(0);
+                properties.focused_window_title = window
+                    .as_ref()
+                    .map(CosmicSurface::title)
+                    .unwrap_or_default();
+                properties.focused_window_app_id = window
+                    .as_ref()
+                    .map(CosmicSurface::app_id)
+                    .unwrap_or_default();
+            }
+
+            let old = service.properties.lock().unwrap();
+            if old.focused_window != properties.focused_window {
+                announcements.push(Announcement::Focus {
+                    id: properties.focused_window,
+                    app_id: properties.focused_window_app_id.clone(),
+                    title: properties.focused_window_title.clone(),
+                });
+            } else if old.focused_window_title != properties.focused_window_title {
+                announcements.push(Announcement::Title {
+                    id: properties.focused_window,
+                    title: properties.focused_window_title.clone(),
+                });
+            }
+            if old.active_output != properties.active_output
+                || old.active_workspace != properties.active_workspace
+            {
+                announcements.push(Announcement::Workspace {
+                    output: properties.active_output.clone(),
+                    number: properties.active_workspace,
+                    count: shell.workspaces.len(&output) as u32,
+                });
+            }
+            drop(old);
+
+            let selected = seat
+                .user_data()
+                .get::<SeatWindowSwitcherState>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|switcher| switcher.selected());
+            let selection = selected.as_ref().map(CosmicSurface::id).unwrap_or(0);
+            if selection != service.switcher_selection {
+                service.switcher_selection = selection;
+                if let Some(window) = selected {
+                    announcements.push(Announcement::SwitcherSelection {
+                        id: selection,
+                        app_id: window.app_id(),
+                        title: window.title(),
+                    });
+                }
+            }
+        }
+        properties.night_light = match &self.backend {
+            BackendData::Kms(kms) => kms.night_light.unwrap_or(0),
+            _ => 0,
+        };
+        properties.capture_clients = capture_clients
+            .into_iter()
+            .map(|client| (client.pid, client.name))
+            .collect();
+
+        let mut current = service.properties.lock().unwrap();
+        if *current != properties {
+            let old = std::mem::replace(&mut *current, properties);
+            let _ = service.changes.send(Change::Properties(old));
+        }
+        drop(current);
+        for announcement in announcements {
+            let _ = service.changes.send(Change::Announcement(announcement));
+        }
+    }
+}
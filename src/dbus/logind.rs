@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Integration with the logind session, to lock the screen before suspending.
+//!
+//! Taking control of the session and its devices is done by libseat, this only listens for
+//! `PrepareForSleep` of the manager and `Lock`/`Unlock` of our session. While awake a delay
+//! inhibitor is held, that is released once the session is locked or after [`LOCK_TIMEOUT`],
+//! so screen contents don't flash up on resume.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use calloop::{
+    channel::{self, Sender},
+    timer::{TimeoutAction, Timer},
+    LoopHandle,
+};
+use cosmic_comp_config::HookEvent;
+use tracing::{debug, info, warn};
+use zbus::{
+    blocking::Connection,
+    zvariant::{OwnedFd, OwnedObjectPath},
+};
+
+use crate::state::State;
+
+/// Longest time suspending is delayed to wait for the session to be locked
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    /// GetSession method
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+
+    /// Inhibit method
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+    /// PrepareForSleep signal
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+trait Session {
+    /// Lock signal
+    #[zbus(signal)]
+    fn lock(&self) -> zbus::Result<()>;
+
+    /// Unlock signal
+    #[zbus(signal)]
+    fn unlock(&self) -> zbus::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogindEvent {
+    PrepareForSleep,
+    Resumed,
+    Lock,
+    Unlock,
+}
+
+/// Connection to logind, held by the kms backend
+#[derive(Debug)]
+pub struct LogindState {
+    manager: ManagerProxyBlocking<'static>,
+    inhibitor: Option<OwnedFd>,
+}
+
+impl LogindState {
+    fn inhibit(&mut self) {
+        if self.inhibitor.is_some() {
+            return;
+        }
+        match self.manager.inhibit(
+            "sleep",
+            "cosmic-comp",
+            "Lock the screen before suspending",
+            "delay",
+        ) {
+            Ok(fd) => self.inhibitor = Some(fd),
+            Err(err) => warn!(?err, "Failed to take sleep inhibitor"),
+        }
+    }
+
+    fn release(&mut self) {
+        if self.inhibitor.take().is_some() {
+            debug!("Released sleep inhibitor");
+        }
+    }
+}
+
+pub fn init(evlh: &LoopHandle<'static, State>) -> Result<LogindState> {
+    let conn = Connection::system()?;
+    let manager = ManagerProxyBlocking::new(&conn)?;
+    let session_path = manager
+        .get_session("auto")
+        .context("Not running in a logind session")?;
+    let session = SessionProxyBlocking::builder(&conn)
+        .path(session_path)?
+        .build()?;
+
+    let (tx, rx) = channel::channel();
+    evlh.insert_source(rx, |event, _, state| {
+        if let channel::Event::Msg(event) = event {
+            state.handle_logind_event(event);
+        }
+    })
+    .map_err(|err| err.error)
+    .context("Failed to add channel to event_loop")?;
+
+    let sleep_signals = manager.receive_prepare_for_sleep()?;
+    spawn_listener("logind-sleep", tx.clone(), move |tx| {
+        for signal in sleep_signals {
+            let Ok(args) = signal.args() else {
+                continue;
+            };
+            let event = if args.start {
+                LogindEvent::PrepareForSleep
+            } else {
+                LogindEvent::Resumed
+            };
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    })?;
+    let lock_signals = session.receive_lock()?;
+    spawn_listener("logind-lock", tx.clone(), move |tx| {
+        for _ in lock_signals {
+            if tx.send(LogindEvent::Lock).is_err() {
+                break;
+            }
+        }
+    })?;
+    let unlock_signals = session.receive_unlock()?;
+    spawn_listener("logind-unlock", tx, move |tx| {
+        for _ in unlock_signals {
+            if tx.send(LogindEvent::Unlock).is_err() {
+                break;
+            }
+        }
+    })?;
+
+    let mut logind = LogindState {
+        manager,
+        inhibitor: None,
+    };
+    logind.inhibit();
+    Ok(logind)
+}
+
+fn spawn_listener(
+    name: &str,
+    tx: Sender<LogindEvent>,
+    f: impl FnOnce(Sender<LogindEvent>) + Send + 'static,
+) -> Result<()> {
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || f(tx))
+        .with_context(|| "Failed to start helper thread")?;
+    Ok(())
+}
+
+impl State {
+    fn handle_logind_event(&mut self, event: LogindEvent) {
+        debug!(?event, "logind event");
+        match event {
+            LogindEvent::PrepareForSleep => {
+                self.run_hooks(HookEvent::Suspending, None, &[]);
+                self.request_lock();
+                self.release_sleep_inhibitor_when_locked();
+            }
+            LogindEvent::Resumed => {
+                if let Some(logind) = self.backend.kms().logind.as_mut() {
+                    logind.inhibit();
+                }
+                self.notify_activity();
+                self.run_hooks(HookEvent::Resumed, None, &[]);
+            }
+            LogindEvent::Lock => self.request_lock(),
+            LogindEvent::Unlock => self.run_hooks(HookEvent::UnlockRequested, None, &[]),
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.common.shell.read().unwrap().session_lock.is_some()
+    }
+
+    /// Asks the lock screen configured as hook to lock the session
+    fn request_lock(&mut self) {
+        if !self.is_locked() {
+            self.run_hooks(HookEvent::LockRequested, None, &[]);
+        }
+    }
+
+    /// Lets the system suspend once the session is locked
+    fn release_sleep_inhibitor_when_locked(&mut self) {
+        // only wait, if a lock screen is started by us
+        let waiting = !self.is_locked()
+            && self
+                .common
+                .config
+                .cosmic_conf
+                .hooks
+                .iter()
+                .any(|hook| hook.event == HookEvent::LockRequested);
+        if !waiting {
+            if let Some(logind) = self.backend.kms().logind.as_mut() {
+                logind.release();
+            }
+            return;
+        }
+
+        let deadline = Instant::now() + LOCK_TIMEOUT;
+        let res = self.common.event_loop_handle.insert_source(
+            Timer::from_duration(Duration::from_millis(50)),
+            move |_, _, state| {
+                let locked = state.is_locked();
+                if !locked && Instant::now() < deadline {
+                    return TimeoutAction::ToDuration(Duration::from_millis(50));
+                }
+                if !locked {
+                    info!("Session wasn't locked in time, suspending anyway");
+                }
+                if let Some(logind) = state.backend.kms().logind.as_mut() {
+                    logind.release();
+                }
+                TimeoutAction::Drop
+            },
+        );
+        if let Err(err) = res {
+            warn!(?err, "Failed to wait for the session to be locked");
+            if let Some(logind) = self.backend.kms().logind.as_mut() {
+                logind.release();
+            }
+        }
+    }
+}
@@ -2,6 +2,9 @@ use crate::state::{BackendData, State};
 use anyhow::{Context, Result};
 use calloop::{InsertError, LoopHandle, RegistrationToken};
 
+mod accessibility;
+pub mod comp;
+pub mod logind;
 mod power;
 
 pub fn init(evlh: &LoopHandle<'static, State>) -> Result<Vec<RegistrationToken>> {
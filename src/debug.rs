@@ -1,10 +1,20 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
 use crate::{
-    backend::kms::Timings,
-    shell::focus::target::{KeyboardFocusTarget, PointerFocusTarget, PointerFocusToplevel},
+    backend::{
+        kms::Timings,
+        render::element::{AsGlowRenderer, CosmicElement},
+    },
+    shell::{
+        focus::target::{KeyboardFocusTarget, PointerFocusTarget, PointerFocusToplevel},
+        CosmicMappedRenderElement, Shell,
+    },
+    wayland::handlers::secure_input::secure_input_active,
     State,
 };
 use egui::{load::SizedTexture, Color32, Vec2};
@@ -12,15 +22,26 @@ use smithay::{
     backend::{
         drm::DrmNode,
         renderer::{
-            element::texture::TextureRenderElement,
+            damage::OutputDamageTracker,
+            element::{
+                solid::SolidColorRenderElement, texture::TextureRenderElement, Element, Id, Kind,
+                RenderElement,
+            },
             gles::{GlesError, GlesTexture},
             glow::GlowRenderer,
+            utils::CommitCounter,
+            ImportAll, ImportMem, Renderer,
         },
     },
-    desktop::WindowSurface,
+    desktop::{layer_map_for_output, WindowSurface},
     input::{keyboard::xkb, Seat},
-    reexports::wayland_server::Resource,
-    utils::{Logical, Rectangle, Time},
+    output::Output,
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    utils::{Logical, Physical, Rectangle, Time},
+    wayland::{
+        compositor::{with_surface_tree_downward, TraversalAction},
+        seat::WaylandFocus,
+    },
 };
 use smithay_egui::EguiState;
 
@@ -28,18 +49,118 @@ pub const ELEMENTS_COLOR: Color32 = Color32::from_rgb(70, 198, 115);
 pub const RENDER_COLOR: Color32 = Color32::from_rgb(29, 114, 58);
 pub const SUBMITTED_COLOR: Color32 = Color32::from_rgb(253, 178, 39);
 pub const DISPLAY_COLOR: Color32 = Color32::from_rgb(41, 184, 209);
+pub const FRAMETIME_COLOR: Color32 = Color32::from_rgb(253, 178, 39);
+pub const REFRESH_COLOR: Color32 = Color32::from_rgb(224, 27, 36);
+/// Premultiplied color of damaged regions, while the debug overlay is active
+pub const DAMAGE_COLOR: [f32; 4] = [0.3, 0.0, 0.3, 0.3];
+
+/// What the last frame of an output consisted of, collected while the debug overlay is active
+#[derive(Debug)]
+pub struct FrameStats {
+    damage_tracker: OutputDamageTracker,
+    pub element_count: usize,
+    pub damage: Vec<Rectangle<i32, Physical>>,
+    /// Element scanned out directly on the primary plane, `None` if it was composited
+    pub primary_plane: Option<Id>,
+    pub overlay_planes: Vec<Id>,
+    pub cursor_plane: Option<Id>,
+}
+
+impl FrameStats {
+    pub fn new(output: &Output) -> FrameStats {
+        FrameStats {
+            damage_tracker: OutputDamageTracker::from_output(output),
+            element_count: 0,
+            damage: Vec::new(),
+            primary_plane: None,
+            overlay_planes: Vec::new(),
+            cursor_plane: None,
+        }
+    }
+
+    /// Drops everything collected so far, e.g. when the overlay was closed
+    pub fn reset(&mut self, output: &Output) {
+        *self = FrameStats::new(output);
+    }
+
+    /// Accumulates the damage of `elements` since the last frame.
+    ///
+    /// The overlay itself is left out, otherwise its own updates would keep flashing.
+    pub fn update_elements<R>(&mut self, elements: &[CosmicElement<R>])
+    where
+        R: AsGlowRenderer + Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: 'static,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+    {
+        self.element_count = elements.len();
+        let elements = elements
+            .iter()
+            .filter(|elem| !matches!(elem, CosmicElement::Egui(_)))
+            .collect::<Vec<_>>();
+        self.damage = match self.damage_tracker.damage_output(1, &elements) {
+            Ok((damage, _)) => damage.cloned().unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+    }
+
+    pub fn update_planes<E: Element>(
+        &mut self,
+        primary: Option<&E>,
+        overlays: &[&E],
+        cursor: Option<&E>,
+    ) {
+        self.primary_plane = primary.map(|elem| elem.id().clone());
+        self.overlay_planes = overlays.iter().map(|elem| elem.id().clone()).collect();
+        self.cursor_plane = cursor.map(|elem| elem.id().clone());
+    }
+
+    /// Translucent rectangles flashing up over the regions damaged this frame
+    pub fn damage_elements(&self) -> impl Iterator<Item = SolidColorRenderElement> + '_ {
+        self.damage.iter().map(|rect| {
+            SolidColorRenderElement::new(
+                Id::new(),
+                *rect,
+                CommitCounter::default(),
+                DAMAGE_COLOR,
+                Kind::Unspecified,
+            )
+        })
+    }
+
+    fn plane_labels(&self, shell: &Shell, output: &Output) -> Vec<String> {
+        let mut labels = vec![format!(
+            "Primary: {}",
+            match &self.primary_plane {
+                Some(id) => element_label(shell, output, id),
+                None => String::from("Composited"),
+            }
+        )];
+        labels.extend(
+            self.overlay_planes
+                .iter()
+                .map(|id| format!("Overlay: {}", element_label(shell, output, id))),
+        );
+        if let Some(id) = &self.cursor_plane {
+            labels.push(format!("Cursor: {}", element_label(shell, output, id)));
+        }
+        labels
+    }
+}
 
 pub fn fps_ui<'a>(
     gpu: Option<&DrmNode>,
     debug_active: bool,
     seats: impl Iterator<Item = &'a Seat<State>>,
     renderer: &mut GlowRenderer,
+    shell: &Arc<RwLock<Shell>>,
+    output: &Output,
     state: &EguiState,
     timings: &Timings,
+    stats: &FrameStats,
     area: Rectangle<i32, Logical>,
     scale: f64,
 ) -> Result<TextureRenderElement<GlesTexture>, GlesError> {
-    use egui_plot::{Bar, BarChart, Legend, Plot};
+    use egui_plot::{Bar, BarChart, HLine, Legend, Line, Plot, PlotPoints};
 
     let (max, min, avg, avg_fps) = (
         timings.max_rendertime().as_secs_f64(),
@@ -58,6 +179,34 @@ pub fn fps_ui<'a>(
             .as_secs_f64(),
     );
 
+    let (avg_margin, min_margin, safety_margin, missed) = (
+        timings.avg_margin(amount).unwrap_or_default(),
+        timings.min_margin(amount).unwrap_or_default(),
+        timings.safety_margin().as_secs_f64(),
+        timings.missed_frames(amount),
+    );
+    let missed_total = timings.missed_frames_total();
+
+    let refresh_ms = timings.refresh_interval().as_secs_f64() * 1000.0;
+    let frame_times = timings
+        .previous_frames
+        .iter()
+        .rev()
+        .take(amount)
+        .rev()
+        .enumerate()
+        .map(|(i, frame)| [i as f64, frame.frame_time().as_secs_f64() * 1000.0])
+        .collect::<PlotPoints>();
+
+    let damaged_area = stats
+        .damage
+        .iter()
+        .map(|rect| rect.size.w as i64 * rect.size.h as i64)
+        .sum::<i64>();
+    let planes = debug_active
+        .then(|| stats.plane_labels(&shell.read().unwrap(), output))
+        .unwrap_or_default();
+
     let ((bars_elements, bars_render), (bars_submitted, bars_displayed)): (
         (Vec<Bar>, Vec<Bar>),
         (Vec<Bar>, Vec<Bar>),
@@ -161,6 +310,7 @@ pub fn fps_ui<'a>(
                                 }
                             });
                         }
+                        ui.label(egui::RichText::new(format!("Output: {}", output.name())).code());
                         ui.label(egui::RichText::new(format!("FPS: {:>7.3}", avg_fps)).heading());
                         ui.label("Render Times:");
                         ui.label(egui::RichText::new(format!("avg: {:>7.6}", avg)).code());
@@ -170,6 +320,19 @@ pub fn fps_ui<'a>(
                         ui.label(egui::RichText::new(format!("avg: {:>7.6}", avg_disp)).code());
                         ui.label(egui::RichText::new(format!("min: {:>7.6}", min_disp)).code());
                         ui.label(egui::RichText::new(format!("max: {:>7.6}", max_disp)).code());
+                        ui.label("Deadline Margins:");
+                        ui.label(egui::RichText::new(format!("avg: {:>7.6}", avg_margin)).code());
+                        ui.label(egui::RichText::new(format!("min: {:>7.6}", min_margin)).code());
+                        ui.label(
+                            egui::RichText::new(format!("slack: {:>7.6}", safety_margin)).code(),
+                        );
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "missed: {} ({} total)",
+                                missed, missed_total
+                            ))
+                            .code(),
+                        );
 
                         let elements_chart = BarChart::new(bars_elements).vertical();
                         let render_chart = BarChart::new(bars_render)
@@ -197,6 +360,49 @@ pub fn fps_ui<'a>(
                                 plot_ui.bar_chart(display_chart);
                             });
 
+                        Plot::new("Frame Times")
+                            .view_aspect(3.0)
+                            .include_x(0.0)
+                            .include_x(amount as f64)
+                            .include_y(0.0)
+                            .include_y(refresh_ms * 2.0)
+                            .show_x(false)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .allow_scroll(false)
+                            .show(ui, |plot_ui| {
+                                plot_ui.line(
+                                    Line::new(frame_times)
+                                        .color(FRAMETIME_COLOR)
+                                        .name("frame time (ms)"),
+                                );
+                                if refresh_ms > 0.0 {
+                                    plot_ui.hline(
+                                        HLine::new(refresh_ms)
+                                            .color(REFRESH_COLOR)
+                                            .name("refresh interval"),
+                                    );
+                                }
+                            });
+
+                        ui.separator();
+                        ui.label(egui::RichText::new("Last Frame").heading());
+                        ui.label(
+                            egui::RichText::new(format!("elements: {}", stats.element_count))
+                                .code(),
+                        );
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "damage: {} rects, {} px",
+                                stats.damage.len(),
+                                damaged_area
+                            ))
+                            .code(),
+                        );
+                        for plane in &planes {
+                            ui.label(egui::RichText::new(plane).code());
+                        }
+
                         ui.separator();
                         ui.label(egui::RichText::new("Input States").heading());
                         for seat in seats {
@@ -236,17 +442,22 @@ pub fn fps_ui<'a>(
                                     .rounding(5.)
                                     .inner_margin(10.)
                                     .show(ui, |ui| {
-                                        let mut keysyms = format!(
-                                            "Keys: {:?}",
-                                            kbd.with_pressed_keysyms(|syms| syms
-                                                .into_iter()
-                                                .map(|k| xkb::keysym_get_name(k.modified_sym()))
-                                                .fold(String::new(), |mut list, val| {
-                                                    list.push_str(&format!("{}, ", val));
-                                                    list
-                                                }))
-                                        );
-                                        keysyms.truncate(keysyms.len().saturating_sub(2));
+                                        let keysyms = if secure_input_active(seat) {
+                                            String::from("Keys: (secure input)")
+                                        } else {
+                                            let mut keysyms = format!(
+                                                "Keys: {:?}",
+                                                kbd.with_pressed_keysyms(|syms| syms
+                                                    .into_iter()
+                                                    .map(|k| xkb::keysym_get_name(k.modified_sym()))
+                                                    .fold(String::new(), |mut list, val| {
+                                                        list.push_str(&format!("{}, ", val));
+                                                        list
+                                                    }))
+                                            );
+                                            keysyms.truncate(keysyms.len().saturating_sub(2));
+                                            keysyms
+                                        };
                                         ui.label(egui::RichText::new(keysyms).code());
 
                                         let mods = kbd.modifier_state();
@@ -285,6 +496,57 @@ pub fn fps_ui<'a>(
     )
 }
 
+/// Names the surface an element on a plane belongs to
+fn element_label(shell: &Shell, output: &Output, id: &Id) -> String {
+    let (_, workspace) = shell.workspaces.active(output);
+    let sticky = shell
+        .workspaces
+        .sets
+        .get(output)
+        .into_iter()
+        .flat_map(|set| set.sticky_layer.mapped());
+    let window = workspace
+        .get_fullscreen()
+        .cloned()
+        .into_iter()
+        .chain(
+            workspace
+                .mapped()
+                .chain(sticky)
+                .flat_map(|mapped| mapped.windows().map(|(window, _)| window)),
+        )
+        .find(|window| {
+            window
+                .wl_surface()
+                .is_some_and(|surface| surface_tree_contains(&surface, id))
+        });
+    if let Some(window) = window {
+        return format!("Window ({})", window.title());
+    }
+
+    let layer_map = layer_map_for_output(output);
+    if let Some(layer) = layer_map
+        .layers()
+        .find(|layer| surface_tree_contains(layer.wl_surface(), id))
+    {
+        return format!("LayerSurface ({})", layer.namespace());
+    }
+
+    String::from("Compositor element")
+}
+
+fn surface_tree_contains(surface: &WlSurface, id: &Id) -> bool {
+    let mut found = false;
+    with_surface_tree_downward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |surface, _, _| found |= Id::from_wayland_resource(surface) == *id,
+        |_, _, _| !found,
+    );
+    found
+}
+
 fn format_pointer_focus(focus: Option<PointerFocusTarget>) -> String {
     use PointerFocusTarget::*;
 
@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Commands run on compositor events, configured as `hooks`.
+//!
+//! Details of the event are passed in `COSMIC_HOOK_*` environment variables.
+//! Workspace switches and output hotplug happen in too many places to hook them directly,
+//! so they are found by comparing against the last known state after every dispatch
+//! of the event loop instead.
+
+use crate::{shell::CosmicSurface, state::State};
+use calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
+};
+use cosmic_comp_config::{Hook, HookEvent};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    os::unix::process::CommandExt,
+    process::{Child, Command},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// How often hooks, that are waited for, are checked for having exited
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Seconds a hook, that is waited for, may run, if it has no `timeout` configured
+const DEFAULT_HOOK_TIMEOUT: u32 = 10;
+
+/// State of the compositor as of the last check for hook events
+#[derive(Debug, Default)]
+pub struct HookState {
+    /// `None` until the first check after hooks were configured
+    outputs: Option<HashSet<String>>,
+    /// Active workspace number by output name
+    workspaces: HashMap<String, usize>,
+}
+
+/// Hook being waited for, killed if it is still running once dropped
+struct PendingHook {
+    command: String,
+    child: Child,
+    deadline: Instant,
+}
+
+impl Drop for PendingHook {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+fn hook_matches(hook: &Hook, window: Option<&CosmicSurface>) -> bool {
+    let Some(window) = window else {
+        return hook.app_id.is_none() && hook.title.is_none();
+    };
+    hook.app_id
+        .as_ref()
+        .map_or(true, |app_id| *app_id == window.app_id())
+        && hook
+            .title
+            .as_ref()
+            .map_or(true, |title| window.title().contains(title.as_str()))
+}
+
+fn event_name(event: HookEvent) -> &'static str {
+    match event {
+        HookEvent::WindowMapped => "window_mapped",
+        HookEvent::WorkspaceSwitched => "workspace_switched",
+        HookEvent::OutputConnected => "output_connected",
+        HookEvent::OutputDisconnected => "output_disconnected",
+        HookEvent::IdleEntered => "idle_entered",
+        HookEvent::IdleExited => "idle_exited",
+        HookEvent::LockRequested => "lock_requested",
+        HookEvent::UnlockRequested => "unlock_requested",
+        HookEvent::Suspending => "suspending",
+        HookEvent::Resumed => "resumed",
+        HookEvent::ScreenCaptureRequested => "screen_capture_requested",
+    }
+}
+
+impl State {
+    /// Commands of the hooks of `event` matching `window`, passing `env` prefixed with `COSMIC_HOOK_`
+    fn hook_commands(
+        &self,
+        event: HookEvent,
+        window: Option<&CosmicSurface>,
+        env: &[(&str, String)],
+    ) -> Vec<(Hook, Command)> {
+        let hooks = self
+            .common
+            .config
+            .cosmic_conf
+            .hooks
+            .iter()
+            .filter(|hook| hook.event == event && hook_matches(hook, window))
+            .collect::<Vec<_>>();
+        if hooks.is_empty() {
+            return Vec::new();
+        }
+
+        let display = self
+            .common
+            .xwayland_state
+            .as_ref()
+            .map(|s| format!(":{}", s.display));
+        let window_env = window
+            .map(|window| {
+                vec![
+                    ("WINDOW_ID", window.id().to_string()),
+                    ("APP_ID", window.app_id()),
+                    ("TITLE", window.title()),
+                ]
+            })
+            .unwrap_or_default();
+
+        hooks
+            .into_iter()
+            .map(|hook| {
+                let mut cmd = Command::new("/bin/sh");
+                cmd.arg("-c")
+                    .arg(&hook.command)
+                    .env("WAYLAND_DISPLAY", &self.common.socket)
+                    .env("COSMIC_HOOK_EVENT", event_name(event))
+                    .envs(
+                        window_env
+                            .iter()
+                            .chain(env.iter())
+                            .map(|(key, value)| (format!("COSMIC_HOOK_{}", key), value)),
+                    )
+                    .env_remove("COSMIC_SESSION_SOCK");
+                match &display {
+                    Some(display) => cmd.env("DISPLAY", display),
+                    None => cmd.env_remove("DISPLAY"),
+                };
+                unsafe { cmd.pre_exec(|| Ok(crate::utils::rlimit::restore_nofile_limit())) };
+                (hook.clone(), cmd)
+            })
+            .collect()
+    }
+
+    /// Runs the hooks of `event` matching `window`, passing `env` prefixed with `COSMIC_HOOK_`
+    pub fn run_hooks(
+        &self,
+        event: HookEvent,
+        window: Option<&CosmicSurface>,
+        env: &[(&str, String)],
+    ) {
+        for (hook, mut cmd) in self.hook_commands(event, window, env) {
+            std::thread::spawn(move || match cmd.spawn() {
+                Ok(mut child) => {
+                    let _res = child.wait();
+                }
+                Err(err) => {
+                    warn!(?err, "Failed to run hook \"{}\"", hook.command);
+                }
+            });
+        }
+    }
+
+    /// Runs the hooks of `event` like [`State::run_hooks`], calling `done` once all of them
+    /// exited, with whether all of them succeeded. Hooks running longer than their `timeout`
+    /// are killed and count as failed, the first failure kills the remaining hooks.
+    ///
+    /// Returns the source waiting for the hooks, removing it cancels them and kills the hooks
+    /// still running. It is `None`, if `done` was called right away, e.g. because there are no
+    /// hooks to run.
+    pub fn ask_hooks(
+        &mut self,
+        event: HookEvent,
+        window: Option<&CosmicSurface>,
+        env: &[(&str, String)],
+        done: impl FnOnce(&mut State, bool) + 'static,
+    ) -> Option<RegistrationToken> {
+        let mut pending = Vec::new();
+        for (hook, mut cmd) in self.hook_commands(event, window, env) {
+            match cmd.spawn() {
+                Ok(child) => {
+                    let timeout = hook.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT);
+                    pending.push(PendingHook {
+                        command: hook.command,
+                        child,
+                        deadline: Instant::now() + Duration::from_secs(timeout.into()),
+                    });
+                }
+                Err(err) => {
+                    warn!(?err, "Failed to run hook \"{}\"", hook.command);
+                    // kills the hooks started so far
+                    std::mem::drop(pending);
+                    done(self, false);
+                    return None;
+                }
+            }
+        }
+        if pending.is_empty() {
+            done(self, true);
+            return None;
+        }
+
+        let done = Rc::new(Cell::new(Some(done)));
+        let callback = done.clone();
+        let res = self.common.event_loop_handle.insert_source(
+            Timer::from_duration(HOOK_POLL_INTERVAL),
+            move |_, _, state| {
+                let mut succeeded = true;
+                pending.retain_mut(|hook| match hook.child.try_wait() {
+                    Ok(Some(status)) => {
+                        succeeded &= status.success();
+                        false
+                    }
+                    Ok(None) if Instant::now() < hook.deadline => true,
+                    Ok(None) => {
+                        warn!("Hook \"{}\" timed out", hook.command);
+                        succeeded = false;
+                        false
+                    }
+                    Err(err) => {
+                        warn!(?err, "Failed to wait for hook \"{}\"", hook.command);
+                        succeeded = false;
+                        false
+                    }
+                });
+
+                if succeeded && !pending.is_empty() {
+                    return TimeoutAction::ToDuration(HOOK_POLL_INTERVAL);
+                }
+                // dropping the source kills the remaining hooks
+                if let Some(done) = callback.take() {
+                    done(state, succeeded);
+                }
+                TimeoutAction::Drop
+            },
+        );
+        match res {
+            Ok(token) => Some(token),
+            Err(err) => {
+                warn!(?err, "Failed to add timer to event_loop");
+                if let Some(done) = done.take() {
+                    done(self, false);
+                }
+                None
+            }
+        }
+    }
+
+    /// Runs hooks for workspace switches and output hotplug since the last call
+    pub fn check_hooks(&mut self) {
+        if self.common.config.cosmic_conf.hooks.is_empty() {
+            self.common.hook_state = HookState::default();
+            return;
+        }
+
+        let outputs = self
+            .common
+            .output_configuration_state
+            .outputs()
+            .collect::<Vec<_>>();
+        let names = outputs.iter().map(|o| o.name()).collect::<HashSet<_>>();
+        let workspaces = {
+            let shell = self.common.shell.read().unwrap();
+            shell
+                .workspaces
+                .iter()
+                .map(|(output, set)| (output.name(), set.active + 1))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let hook_state = &mut self.common.hook_state;
+        let previous_outputs = hook_state.outputs.replace(names.clone());
+        let previous_workspaces = std::mem::replace(&mut hook_state.workspaces, workspaces.clone());
+        // the first check just records the current state
+        let Some(previous_outputs) = previous_outputs else {
+            return;
+        };
+
+        for output in outputs
+            .iter()
+            .filter(|o| !previous_outputs.contains(&o.name()))
+        {
+            let physical = output.physical_properties();
+            self.run_hooks(
+                HookEvent::OutputConnected,
+                None,
+                &[
+                    ("OUTPUT", output.name()),
+                    ("MAKE", physical.make),
+                    ("MODEL", physical.model),
+                ],
+            );
+        }
+        for name in previous_outputs.difference(&names) {
+            self.run_hooks(
+                HookEvent::OutputDisconnected,
+                None,
+                &[("OUTPUT", name.clone())],
+            );
+        }
+        for (output, workspace) in &workspaces {
+            if let Some(previous) = previous_workspaces
+                .get(output)
+                .filter(|previous| *previous != workspace)
+            {
+                self.run_hooks(
+                    HookEvent::WorkspaceSwitched,
+                    None,
+                    &[
+                        ("OUTPUT", output.clone()),
+                        ("WORKSPACE", workspace.to_string()),
+                        ("PREVIOUS_WORKSPACE", previous.to_string()),
+                    ],
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::time::{Duration, Instant};
+
+use smithay::output::Output;
+use smithay::reexports::calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
+};
+use tracing::{debug, error};
+
+use cosmic_comp_config::HookEvent;
+
+use crate::{state::State, wayland::protocols::output_power::OutputPowerState};
+
+/// Explicit power change of an output, requested by a keybinding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputPowerAction {
+    On,
+    Off,
+    Toggle,
+}
+
+/// How far the session has progressed into being idle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum IdlePhase {
+    #[default]
+    Active,
+    /// Outputs are dimmed to warn the user before blanking them
+    Dimmed,
+    /// Outputs are turned off
+    Blanked,
+}
+
+/// Built-in idle manager, dimming and then blanking all outputs after
+/// the timeouts configured in [`cosmic_comp_config::IdleConfig`].
+#[derive(Debug)]
+pub struct IdleState {
+    phase: IdlePhase,
+    inhibited: bool,
+    last_activity: Instant,
+    timer: Option<RegistrationToken>,
+    /// Outputs turned off by an [`OutputPowerAction`], until the next input
+    powered_off: Vec<Output>,
+}
+
+impl Default for IdleState {
+    fn default() -> IdleState {
+        IdleState {
+            phase: IdlePhase::Active,
+            inhibited: false,
+            last_activity: Instant::now(),
+            timer: None,
+            powered_off: Vec::new(),
+        }
+    }
+}
+
+impl IdleState {
+    /// Stops the timeouts while idle-inhibitors are active
+    pub fn set_inhibited(&mut self, inhibited: bool) {
+        if self.inhibited && !inhibited {
+            // start counting from when the inhibitor went away
+            self.last_activity = Instant::now();
+        }
+        self.inhibited = inhibited;
+    }
+}
+
+impl State {
+    /// Restarts the idle timeouts and wakes up outputs dimmed or blanked because of inactivity
+    pub fn notify_activity(&mut self) {
+        self.common.idle_state.last_activity = Instant::now();
+        if self.common.idle_state.phase != IdlePhase::Active {
+            self.set_idle_phase(IdlePhase::Active);
+            // the timer stops once the last phase was reached
+            if self.common.idle_state.timer.is_none() {
+                self.arm_idle_timer();
+            }
+        }
+        if !self.common.idle_state.powered_off.is_empty() {
+            for output in std::mem::take(&mut self.common.idle_state.powered_off) {
+                self.backend.set_dpms(&output, true);
+                self.backend.schedule_render(&output);
+            }
+            OutputPowerState::refresh(self);
+        }
+    }
+
+    /// Turns `output`, or all outputs if `None`, on or off.
+    ///
+    /// Outputs turned off this way are turned back on by the next input.
+    pub fn set_output_power(&mut self, output: Option<&Output>, action: OutputPowerAction) {
+        let outputs = match output {
+            Some(output) => vec![output.clone()],
+            None => self
+                .common
+                .shell
+                .read()
+                .unwrap()
+                .outputs()
+                .cloned()
+                .collect::<Vec<_>>(),
+        };
+
+        for output in &outputs {
+            // nested backends can't turn off outputs
+            let Some(current) = self.backend.dpms(output) else {
+                continue;
+            };
+            let on = match action {
+                OutputPowerAction::On => true,
+                OutputPowerAction::Off => false,
+                OutputPowerAction::Toggle => !current,
+            };
+            if on == current {
+                continue;
+            }
+
+            debug!(output = output.name(), on, "Changing output power");
+            self.backend.set_dpms(output, on);
+            let powered_off = &mut self.common.idle_state.powered_off;
+            powered_off.retain(|o| o != output);
+            if on {
+                self.backend.schedule_render(output);
+            } else {
+                powered_off.push(output.clone());
+            }
+        }
+
+        OutputPowerState::refresh(self);
+    }
+
+    /// (Re-)arms the idle timer after the configured timeouts changed
+    pub fn reset_idle_timer(&mut self) {
+        if let Some(token) = self.common.idle_state.timer.take() {
+            self.common.event_loop_handle.remove(token);
+        }
+        self.notify_activity();
+        if self.common.idle_state.timer.is_none() {
+            self.arm_idle_timer();
+        }
+    }
+
+    fn arm_idle_timer(&mut self) {
+        let Some(timeout) = self.idle_timeouts().into_iter().flatten().min() else {
+            return;
+        };
+        match self
+            .common
+            .event_loop_handle
+            .insert_source(Timer::from_duration(timeout), |_, _, state| {
+                state.on_idle_timer()
+            }) {
+            Ok(token) => self.common.idle_state.timer = Some(token),
+            Err(err) => error!(?err, "Failed to start idle timer"),
+        }
+    }
+
+    /// Returns the dim and screen-off timeouts, dimming is skipped if it would happen last
+    fn idle_timeouts(&self) -> [Option<Duration>; 2] {
+        let config = &self.common.config.cosmic_conf.idle;
+        let screen_off = config
+            .screen_off_timeout
+            .map(|secs| Duration::from_secs(secs as u64));
+        let dim = config
+            .dim_timeout
+            .map(|secs| Duration::from_secs(secs as u64))
+            .filter(|dim| screen_off.map_or(true, |screen_off| *dim < screen_off));
+        [dim, screen_off]
+    }
+
+    fn on_idle_timer(&mut self) -> TimeoutAction {
+        let now = Instant::now();
+        if self.common.idle_state.inhibited {
+            self.common.idle_state.last_activity = now;
+        }
+        let idle_for = now.duration_since(self.common.idle_state.last_activity);
+
+        let [dim, screen_off] = self.idle_timeouts();
+        let phase = if screen_off.is_some_and(|timeout| idle_for >= timeout) {
+            IdlePhase::Blanked
+        } else if dim.is_some_and(|timeout| idle_for >= timeout) {
+            IdlePhase::Dimmed
+        } else {
+            IdlePhase::Active
+        };
+        // only input moves the session back to being active
+        if phase > self.common.idle_state.phase {
+            self.set_idle_phase(phase);
+        }
+
+        // check again once the next timeout is reached. If there is none left, the
+        // timer is re-armed by the next activity, so an idle session isn't woken up.
+        let next = [dim, screen_off]
+            .into_iter()
+            .flatten()
+            .filter(|timeout| *timeout > idle_for)
+            .min()
+            .map(|timeout| timeout - idle_for);
+        match next {
+            Some(next) => TimeoutAction::ToDuration(next),
+            None => {
+                self.common.idle_state.timer = None;
+                TimeoutAction::Drop
+            }
+        }
+    }
+
+    fn set_idle_phase(&mut self, phase: IdlePhase) {
+        let previous = std::mem::replace(&mut self.common.idle_state.phase, phase);
+        debug!(?previous, ?phase, "Idle state changed");
+
+        let outputs = {
+            let mut shell = self.common.shell.write().unwrap();
+            shell.idle_dimmed = phase == IdlePhase::Dimmed;
+            shell.outputs().cloned().collect::<Vec<_>>()
+        };
+        for output in &outputs {
+            if phase == IdlePhase::Blanked {
+                self.backend.set_dpms(output, false);
+            } else if previous == IdlePhase::Blanked {
+                self.backend.set_dpms(output, true);
+            }
+            self.backend.schedule_render(output);
+        }
+
+        if phase == IdlePhase::Blanked || previous == IdlePhase::Blanked {
+            OutputPowerState::refresh(self);
+        }
+
+        if previous == IdlePhase::Active {
+            let phase = match phase {
+                IdlePhase::Dimmed => "dimmed",
+                _ => "blanked",
+            };
+            self.run_hooks(
+                HookEvent::IdleEntered,
+                None,
+                &[("IDLE_PHASE", phase.into())],
+            );
+        } else if phase == IdlePhase::Active {
+            self.run_hooks(HookEvent::IdleExited, None, &[]);
+        }
+    }
+}
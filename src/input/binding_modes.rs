@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Binding modes: entering a mode replaces the configured shortcuts with its own,
+//! until Escape or Return is pressed, like the modes of i3.
+//!
+//! The active mode is published as `binding_mode` in the `com.system76.CosmicComp`
+//! state config, so panels can display it.
+
+use cosmic_comp_config::BindingMode;
+use cosmic_config::ConfigSet;
+use cosmic_settings_config::shortcuts;
+use smithay::input::keyboard::ModifiersState;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+};
+use tracing::{info, warn};
+use xkbcommon::xkb::Keysym;
+
+use crate::{
+    config::{key_bindings::cosmic_modifiers_eq_smithay, Action, PrivateAction},
+    state::State,
+};
+
+fn binding_matches(
+    binding: &shortcuts::Binding,
+    modifiers: &ModifiersState,
+    syms: &[Keysym],
+) -> bool {
+    binding.key.is_some_and(|key| syms.contains(&key))
+        && cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers)
+}
+
+/// Finds the action for a key press, while `active` is the active binding mode
+///
+/// Outside of a mode only the shortcuts entering one are considered, if several modes
+/// share the same shortcut the first one by name is entered.
+pub fn binding_mode_action(
+    modes: &HashMap<String, BindingMode>,
+    active: Option<&str>,
+    modifiers: &ModifiersState,
+    syms: &[Keysym],
+) -> Option<(Action, shortcuts::Binding)> {
+    let Some(mode) = active.and_then(|name| modes.get(name)) else {
+        return modes
+            .iter()
+            .filter(|(_, mode)| binding_matches(&mode.enter, modifiers, syms))
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(name, mode)| {
+                (
+                    Action::Private(PrivateAction::BindingMode(Some(name.clone()))),
+                    mode.enter.clone(),
+                )
+            });
+    };
+
+    if let Some(sym) = syms
+        .iter()
+        .find(|sym| matches!(**sym, Keysym::Escape | Keysym::Return | Keysym::KP_Enter))
+    {
+        return Some((
+            Action::Private(PrivateAction::BindingMode(None)),
+            shortcuts::Binding {
+                modifiers: shortcuts::Modifiers::new(),
+                key: Some(*sym),
+                description: None,
+            },
+        ));
+    }
+
+    mode.bindings
+        .iter()
+        .find(|(binding, action)| {
+            *action != shortcuts::Action::Disable && binding_matches(binding, modifiers, syms)
+        })
+        .map(|(binding, action)| (Action::Shortcut(action.clone()), binding.clone()))
+}
+
+impl State {
+    /// Enters the named binding mode, or leaves the active one if `None`
+    pub fn set_binding_mode(&mut self, mode: Option<String>) {
+        if self.common.binding_mode == mode {
+            return;
+        }
+        info!(?mode, "Changing binding mode.");
+
+        self.common.binding_mode_publisher.publish(mode.clone());
+        if let Some(ipc) = self.common.ipc.as_mut() {
+            ipc.binding_mode_changed(mode.clone());
+        }
+        self.common.binding_mode = mode;
+    }
+
+    /// Leaves the active binding mode, if it only applies to a single shortcut
+    pub fn binding_mode_triggered(&mut self) {
+        let oneshot = self
+            .common
+            .binding_mode
+            .as_ref()
+            .and_then(|mode| self.common.config.cosmic_conf.binding_modes.get(mode))
+            .is_some_and(|mode| mode.oneshot);
+        if oneshot {
+            self.set_binding_mode(None);
+        }
+    }
+}
+
+/// Writes the active binding mode to the state config on a helper thread,
+/// as writing the config may block the event loop on a slow disk.
+#[derive(Debug, Default)]
+pub struct BindingModePublisher {
+    tx: Option<Sender<Option<String>>>,
+}
+
+impl BindingModePublisher {
+    pub fn publish(&mut self, mode: Option<String>) {
+        if let Some(tx) = self.tx.as_ref() {
+            if tx.send(mode.clone()).is_ok() {
+                return;
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let _ = tx.send(mode);
+        if let Err(err) = std::thread::Builder::new()
+            .name("binding-mode".to_string())
+            .spawn(move || write_binding_mode(rx))
+        {
+            warn!(?err, "Failed to start helper thread");
+            return;
+        }
+        self.tx = Some(tx);
+    }
+}
+
+fn write_binding_mode(rx: Receiver<Option<String>>) {
+    let config = match cosmic_config::Config::new_state("com.system76.CosmicComp", 1) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!(?err, "Failed to open cosmic-comp state config.");
+            return;
+        }
+    };
+
+    while let Ok(mut mode) = rx.recv() {
+        // only the latest mode matters
+        while let Ok(newer) = rx.try_recv() {
+            mode = newer;
+        }
+        if let Err(err) = config.set("binding_mode", mode.as_deref()) {
+            warn!(?err, "Failed to publish binding mode.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::binding_mode_action;
+    use crate::config::{Action, PrivateAction};
+    use cosmic_comp_config::BindingMode;
+    use cosmic_settings_config::shortcuts::{self, Binding, Modifiers};
+    use smithay::input::keyboard::ModifiersState;
+    use std::collections::HashMap;
+    use xkbcommon::xkb::Keysym;
+
+    fn binding(modifiers: Modifiers, key: Keysym) -> Binding {
+        Binding {
+            modifiers,
+            key: Some(key),
+            description: None,
+        }
+    }
+
+    fn modes() -> HashMap<String, BindingMode> {
+        let resize = BindingMode {
+            enter: binding(Modifiers::new().logo(), Keysym::r),
+            bindings: vec![
+                (
+                    binding(Modifiers::new(), Keysym::Left),
+                    shortcuts::Action::Close,
+                ),
+                (
+                    binding(Modifiers::new(), Keysym::Right),
+                    shortcuts::Action::Disable,
+                ),
+            ],
+            oneshot: false,
+        };
+        let mut modes = HashMap::new();
+        // several modes sharing the same shortcut
+        for name in ["resize", "b", "a", "c"] {
+            modes.insert(name.to_string(), resize.clone());
+        }
+        modes
+    }
+
+    #[test]
+    fn test_enter_binding_mode() {
+        let modes = modes();
+        let logo = ModifiersState {
+            logo: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            binding_mode_action(&modes, None, &logo, &[Keysym::r]),
+            Some((
+                Action::Private(PrivateAction::BindingMode(Some("a".to_string()))),
+                binding(Modifiers::new().logo(), Keysym::r),
+            ))
+        );
+        assert_eq!(
+            binding_mode_action(&modes, None, &ModifiersState::default(), &[Keysym::r]),
+            None
+        );
+        // shortcuts of a mode are only active inside of it
+        assert_eq!(
+            binding_mode_action(&modes, None, &ModifiersState::default(), &[Keysym::Left]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_active_binding_mode() {
+        let modes = modes();
+        let none = ModifiersState::default();
+
+        assert_eq!(
+            binding_mode_action(&modes, Some("resize"), &none, &[Keysym::Left]),
+            Some((
+                Action::Shortcut(shortcuts::Action::Close),
+                binding(Modifiers::new(), Keysym::Left),
+            ))
+        );
+        assert_eq!(
+            binding_mode_action(&modes, Some("resize"), &none, &[Keysym::Right]),
+            None
+        );
+        assert_eq!(
+            binding_mode_action(&modes, Some("resize"), &none, &[Keysym::Escape]),
+            Some((
+                Action::Private(PrivateAction::BindingMode(None)),
+                binding(Modifiers::new(), Keysym::Escape),
+            ))
+        );
+    }
+}
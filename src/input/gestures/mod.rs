@@ -1,10 +1,13 @@
-use cosmic_settings_config::shortcuts::action::Direction;
+use cosmic_comp_config::{Gesture, GestureConfig};
+use cosmic_settings_config::shortcuts::{self, action::Direction};
 use smithay::utils::{Logical, Point};
 use std::{collections::VecDeque, time::Duration};
 use tracing::trace;
 
 const HISTORY_LIMIT: Duration = Duration::from_millis(150);
 const DECELERATION_TOUCHPAD: f64 = 0.997;
+/// Scale a pinch has to exceed, or fall below the inverse of, to trigger an action
+const PINCH_THRESHOLD: f64 = 1.25;
 
 #[derive(Debug, Clone, Copy)]
 pub struct SwipeEvent {
@@ -12,10 +15,44 @@ pub struct SwipeEvent {
     timestamp: Duration,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum SwipeAction {
     NextWorkspace,
     PrevWorkspace,
+    /// Bound action, triggered once the gesture ends
+    Action(shortcuts::Action),
+}
+
+/// Who gets to handle a touchpad gesture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureOwner {
+    /// The gesture drives a compositor action and is not sent to clients
+    Compositor,
+    /// The gesture is forwarded to the focused client via `zwp_pointer_gestures_v1`
+    Client,
+}
+
+impl GestureOwner {
+    /// Decides who handles a swipe gesture with the given finger count.
+    ///
+    /// The compositor only claims finger counts it has actions bound to,
+    /// everything else is forwarded so clients can implement their own gestures.
+    pub fn for_swipe(fingers: u32, overview_open: bool, config: &GestureConfig) -> GestureOwner {
+        match fingers {
+            _ if overview_open => GestureOwner::Client,
+            fingers if config.binds_swipe(fingers) => GestureOwner::Compositor,
+            _ => GestureOwner::Client,
+        }
+    }
+
+    /// Decides who handles a pinch gesture with the given finger count.
+    pub fn for_pinch(fingers: u32, overview_open: bool, config: &GestureConfig) -> GestureOwner {
+        match fingers {
+            _ if overview_open => GestureOwner::Client,
+            fingers if config.binds_pinch(fingers) => GestureOwner::Compositor,
+            _ => GestureOwner::Client,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +61,10 @@ pub struct GestureState {
     pub direction: Option<Direction>,
     pub action: Option<SwipeAction>,
     pub delta: f64,
+    /// Scale of a pinch gesture, relative to its start
+    pub scale: f64,
+    /// Zoom level at the start of a pinch zooming the screen
+    pub zoom_level: Option<f64>,
     // Delta tracking inspired by Niri (GPL-3.0) https://github.com/YaLTeR/niri/tree/v0.1.3
     pub history: VecDeque<SwipeEvent>,
 }
@@ -35,6 +76,8 @@ impl GestureState {
             direction: None,
             action: None,
             delta: 0.0,
+            scale: 1.0,
+            zoom_level: None,
             history: VecDeque::new(),
         }
     }
@@ -71,6 +114,17 @@ impl GestureState {
         first_update
     }
 
+    /// Pinch performed so far, if the fingers moved far enough
+    pub fn pinch(&self) -> Option<Gesture> {
+        if self.scale > PINCH_THRESHOLD {
+            Some(Gesture::PinchOut)
+        } else if self.scale < PINCH_THRESHOLD.recip() {
+            Some(Gesture::PinchIn)
+        } else {
+            None
+        }
+    }
+
     /// Pushes a new reading into the tracker.
     fn push(&mut self, delta: f64, timestamp: Duration) {
         // For the events that we care about, timestamps should always increase
@@ -0,0 +1,173 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Slow keys and bounce keys, filtering key events before they reach shortcuts or clients.
+//!
+//! With slow keys, a key has to be held for a while before its press is registered.
+//! With bounce keys, presses of a key shortly after it was released are ignored.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
+};
+use smithay::{backend::input::KeyState, input::Seat};
+use tracing::warn;
+use xkbcommon::xkb::Keycode;
+
+use crate::state::State;
+
+#[derive(Default)]
+pub struct KeyFilters(RefCell<KeyFiltersInner>);
+
+#[derive(Default)]
+struct KeyFiltersInner {
+    /// Presses waiting for the slow keys delay to pass
+    pending: HashMap<Keycode, RegistrationToken>,
+    /// Keys whose press was ignored, so their release is ignored as well
+    dropped: HashSet<Keycode>,
+    /// Last key released and when
+    last_release: Option<(Keycode, u32)>,
+}
+
+impl KeyFiltersInner {
+    /// Applies bounce keys to a press, returns `false` if it should be ignored
+    fn press(&mut self, keycode: Keycode, time: u32, bounce_keys_delay: u32) -> bool {
+        if bounce_keys_delay > 0
+            && self.last_release.is_some_and(|(code, released)| {
+                code == keycode && time.wrapping_sub(released) < bounce_keys_delay
+            })
+        {
+            self.dropped.insert(keycode);
+            return false;
+        }
+        true
+    }
+
+    /// Returns `false` if a release should be ignored, because its press was
+    fn release(&mut self, keycode: Keycode, time: u32) -> bool {
+        if self.dropped.remove(&keycode) {
+            return false;
+        }
+        self.last_release = Some((keycode, time));
+        true
+    }
+}
+
+impl State {
+    /// Applies slow and bounce keys to a key event, returns `false` if it should be ignored
+    pub fn filter_key_input(
+        &mut self,
+        seat: &Seat<State>,
+        keycode: Keycode,
+        state: KeyState,
+        time: u32,
+        needs_key_repetition: bool,
+    ) -> bool {
+        let config = self.common.config.cosmic_conf.keyboard_accessibility;
+        let filters = seat.user_data().get::<KeyFilters>().unwrap();
+        let mut filters = filters.0.borrow_mut();
+
+        match state {
+            KeyState::Pressed => {
+                if !filters.press(keycode, time, config.bounce_keys_delay) {
+                    return false;
+                }
+
+                if config.slow_keys_delay > 0 {
+                    let seat = seat.clone();
+                    match self.common.event_loop_handle.insert_source(
+                        Timer::from_duration(Duration::from_millis(config.slow_keys_delay as u64)),
+                        move |_, _, state| {
+                            let filters = seat.user_data().get::<KeyFilters>().unwrap();
+                            filters.0.borrow_mut().pending.remove(&keycode);
+                            let time = state.common.clock.now().as_millis();
+                            state.keyboard_key_input(
+                                seat.clone(),
+                                keycode,
+                                KeyState::Pressed,
+                                time,
+                                needs_key_repetition,
+                            );
+                            TimeoutAction::Drop
+                        },
+                    ) {
+                        Ok(token) => {
+                            if let Some(previous) = filters.pending.insert(keycode, token) {
+                                self.common.event_loop_handle.remove(previous);
+                            }
+                            return false;
+                        }
+                        Err(err) => warn!(?err, "Failed to delay key press."),
+                    }
+                }
+
+                true
+            }
+            KeyState::Released => {
+                // released before the slow keys delay passed
+                if let Some(token) = filters.pending.remove(&keycode) {
+                    self.common.event_loop_handle.remove(token);
+                    return false;
+                }
+                filters.release(keycode, time)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::KeyFiltersInner;
+    use xkbcommon::xkb::Keycode;
+
+    fn keys() -> (Keycode, Keycode) {
+        (Keycode::new(38), Keycode::new(56))
+    }
+
+    #[test]
+    fn test_bounce_keys() {
+        let (a, b) = keys();
+        let mut filters = KeyFiltersInner::default();
+        assert!(filters.press(a, 0, 300));
+        assert!(filters.release(a, 50));
+
+        // pressed again within the delay
+        assert!(!filters.press(a, 100, 300));
+        assert!(!filters.release(a, 150));
+
+        // the ignored release doesn't restart the delay
+        assert!(filters.press(a, 400, 300));
+        assert!(filters.release(a, 450));
+
+        // other keys aren't affected
+        assert!(filters.press(b, 500, 300));
+        assert!(filters.release(b, 550));
+        assert!(filters.press(a, 600, 300));
+    }
+
+    #[test]
+    fn test_bounce_keys_disabled() {
+        let (a, _) = keys();
+        let mut filters = KeyFiltersInner::default();
+        assert!(filters.press(a, 0, 0));
+        assert!(filters.release(a, 10));
+        assert!(filters.press(a, 11, 0));
+        assert!(filters.release(a, 20));
+    }
+
+    #[test]
+    fn test_bounce_keys_time_wraparound() {
+        let (a, _) = keys();
+        let mut filters = KeyFiltersInner::default();
+        assert!(filters.press(a, u32::MAX - 100, 300));
+        assert!(filters.release(a, u32::MAX - 50));
+        assert!(!filters.press(a, 100, 300));
+        assert!(!filters.release(a, 150));
+        assert!(filters.press(a, 400, 300));
+    }
+}
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Locating the pointer: pressing and releasing Ctrl on its own draws a ring around the
+//! pointer, shrinking towards it, to find it on large screens or across many outputs.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use calloop::timer::{TimeoutAction, Timer};
+use smithay::{
+    backend::renderer::element::Id,
+    input::{keyboard::ModifiersState, Seat},
+    output::Output,
+    utils::{Point, Rectangle},
+};
+use tracing::warn;
+use xkbcommon::xkb::{Keycode, Keysym};
+
+use crate::{state::State, utils::prelude::*};
+
+/// How long the ring takes to shrink onto the pointer
+const DURATION: Duration = Duration::from_millis(600);
+/// Radius of the ring when it appears, in logical pixels
+const MAX_RADIUS: f64 = 120.0;
+/// Width of the ring, in logical pixels
+pub const RING_THICKNESS: u8 = 4;
+
+#[derive(Default)]
+pub struct SeatLocatePointer(Mutex<LocatePointerInner>);
+
+struct LocatePointerInner {
+    /// Ctrl key held, without any other key or button pressed since
+    key: Option<Keycode>,
+    id: Id,
+    /// When the ring being shown appeared
+    started: Option<Instant>,
+}
+
+impl Default for LocatePointerInner {
+    fn default() -> Self {
+        LocatePointerInner {
+            key: None,
+            id: Id::new(),
+            started: None,
+        }
+    }
+}
+
+impl SeatLocatePointer {
+    /// Tracks a key event, returns `true` once Ctrl was pressed and released on its own
+    pub fn key_input(
+        &self,
+        keycode: Keycode,
+        sym: Keysym,
+        modifiers: &ModifiersState,
+        pressed: bool,
+    ) -> bool {
+        let mut inner = self.0.lock().unwrap();
+        if pressed {
+            let ctrl_alone = matches!(sym, Keysym::Control_L | Keysym::Control_R)
+                && !modifiers.alt
+                && !modifiers.shift
+                && !modifiers.logo;
+            inner.key = ctrl_alone.then_some(keycode);
+            false
+        } else {
+            inner.key.take() == Some(keycode)
+        }
+    }
+
+    /// Forgets the held Ctrl key, e.g. because it is used for Ctrl+click
+    pub fn cancel(&self) {
+        self.0.lock().unwrap().key = None;
+    }
+
+    /// Id, radius in logical pixels and opacity of the ring, while it is shown
+    pub fn ring(&self) -> Option<(Id, f64, f32)> {
+        let inner = self.0.lock().unwrap();
+        let started = inner.started?;
+        let progress = started.elapsed().as_secs_f64() / DURATION.as_secs_f64();
+        if progress >= 1.0 {
+            return None;
+        }
+        // ease out, so the ring slows down close to the pointer
+        let radius = MAX_RADIUS * (1.0 - progress).powi(2) + RING_THICKNESS as f64 * 2.0;
+        let alpha = (1.0 - progress * progress) as f32;
+        Some((inner.id.clone(), radius, alpha))
+    }
+
+    /// Whether the ring around the pointer of `seat` is shown on `output`
+    pub fn is_visible_on(seat: &Seat<State>, output: &Output) -> bool {
+        let Some(radius) = seat
+            .user_data()
+            .get::<SeatLocatePointer>()
+            .and_then(|locate| locate.ring())
+            .map(|(_, radius, _)| radius)
+        else {
+            return false;
+        };
+        let Some(ptr) = seat.get_pointer() else {
+            return false;
+        };
+        let location = ptr.current_location().as_global();
+        Rectangle::from_loc_and_size(
+            location - Point::from((radius, radius)),
+            (radius * 2.0, radius * 2.0),
+        )
+        .overlaps(output.geometry().to_f64())
+    }
+}
+
+impl State {
+    /// Draws a shrinking ring around the pointer of `seat`
+    pub fn locate_pointer(&mut self, seat: &Seat<State>) {
+        let started = Instant::now();
+        seat.user_data()
+            .get::<SeatLocatePointer>()
+            .unwrap()
+            .0
+            .lock()
+            .unwrap()
+            .started = Some(started);
+
+        // the ring might span multiple outputs
+        for output in self.common.shell.read().unwrap().outputs() {
+            self.backend.schedule_render(output);
+        }
+
+        let seat = seat.clone();
+        let res = self.common.event_loop_handle.insert_source(
+            Timer::from_duration(DURATION),
+            move |_, _, state| {
+                let mut inner = seat
+                    .user_data()
+                    .get::<SeatLocatePointer>()
+                    .unwrap()
+                    .0
+                    .lock()
+                    .unwrap();
+                if inner.started == Some(started) {
+                    inner.started = None;
+                    std::mem::drop(inner);
+                    for output in state.common.shell.read().unwrap().outputs() {
+                        state.backend.schedule_render(output);
+                    }
+                }
+                TimeoutAction::Drop
+            },
+        );
+        if let Err(err) = res {
+            warn!(?err, "Failed to time locating the pointer");
+        }
+    }
+}
@@ -4,14 +4,21 @@ use crate::{
     config::{
         key_bindings::{
             cosmic_keystate_from_smithay, cosmic_modifiers_eq_smithay,
-            cosmic_modifiers_from_smithay,
+            cosmic_modifiers_from_smithay, cosmic_modifiers_held_smithay,
         },
         Action, Config, PrivateAction,
     },
-    input::gestures::{GestureState, SwipeAction},
+    idle::OutputPowerAction,
+    input::{
+        binding_modes::binding_mode_action,
+        gestures::{GestureOwner, GestureState, SwipeAction},
+        locate_pointer::SeatLocatePointer,
+        mouse_keys::MouseKey,
+        sticky_keys::SeatStickyKeysState,
+    },
     shell::{
         focus::target::{KeyboardFocusTarget, PointerFocusTarget},
-        grabs::{ReleaseMode, ResizeEdge},
+        grabs::{ReleaseMode, ResizeEdge, ScreenshotGrab, WindowSwitcherGrab},
         layout::{
             floating::ResizeGrabMarker,
             tiling::{SwapWindowGrab, TilingLayout},
@@ -20,7 +27,14 @@ use crate::{
     },
     utils::{prelude::*, quirks::workspace_overview_is_open},
     wayland::{
-        handlers::{screencopy::SessionHolder, xdg_activation::ActivationContext},
+        handlers::{
+            data_device::update_dnd_target,
+            pointer_constraints::has_keyboard_focus,
+            screencopy::SessionHolder,
+            toplevel_drag::update_toplevel_drag,
+            xdg_activation::ActivationContext,
+            xwayland_keyboard_grab::{release_xwayland_keyboard_grab, xwayland_keyboard_grabbed},
+        },
         protocols::{
             screencopy::{BufferConstraints, CursorSession},
             workspace::WorkspaceUpdateGuard,
@@ -28,7 +42,9 @@ use crate::{
     },
 };
 use calloop::{timer::Timer, RegistrationToken};
-use cosmic_comp_config::{workspace::WorkspaceLayout, TileBehavior};
+use cosmic_comp_config::{
+    input::TabletMapping, workspace::WorkspaceLayout, CompositorAction, Gesture, TileBehavior,
+};
 use cosmic_config::ConfigSet;
 use cosmic_settings_config::shortcuts;
 use cosmic_settings_config::shortcuts::action::{Direction, FocusDirection, ResizeDirection};
@@ -36,9 +52,9 @@ use smithay::{
     backend::input::{
         AbsolutePositionEvent, Axis, AxisSource, Device, DeviceCapability, GestureBeginEvent,
         GestureEndEvent, GesturePinchUpdateEvent as _, GestureSwipeUpdateEvent as _, InputBackend,
-        InputEvent, KeyState, PointerAxisEvent, ProximityState, TabletToolButtonEvent,
-        TabletToolEvent, TabletToolProximityEvent, TabletToolTipEvent, TabletToolTipState,
-        TouchEvent,
+        InputEvent, KeyState, KeyboardKeyEvent, PointerAxisEvent, ProximityState,
+        TabletToolButtonEvent, TabletToolEvent, TabletToolProximityEvent, TabletToolTipEvent,
+        TabletToolTipState, TouchEvent,
     },
     desktop::{
         layer_map_for_output, space::SpaceElement, utils::under_from_surface_tree,
@@ -47,10 +63,10 @@ use smithay::{
     input::{
         keyboard::{FilterResult, KeysymHandle},
         pointer::{
-            AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent,
+            AxisFrame, ButtonEvent, Focus, GestureHoldBeginEvent, GestureHoldEndEvent,
             GesturePinchBeginEvent, GesturePinchEndEvent, GesturePinchUpdateEvent,
-            GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent, MotionEvent,
-            PointerGrab, RelativeMotionEvent,
+            GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent,
+            GrabStartData as PointerGrabStartData, MotionEvent, PointerGrab, RelativeMotionEvent,
         },
         touch::{DownEvent, MotionEvent as TouchMotionEvent, UpEvent},
         Seat,
@@ -59,7 +75,7 @@ use smithay::{
     reexports::{
         input::Device as InputDevice, wayland_server::protocol::wl_shm::Format as ShmFormat,
     },
-    utils::{Point, Serial, SERIAL_COUNTER},
+    utils::{Logical, Point, Rectangle, Serial, Transform, SERIAL_COUNTER},
     wayland::{
         keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitorSeat,
         pointer_constraints::{with_pointer_constraint, PointerConstraint},
@@ -84,7 +100,13 @@ use std::{
     time::{Duration, Instant},
 };
 
+pub mod binding_modes;
 pub mod gestures;
+pub mod key_filters;
+pub mod locate_pointer;
+pub mod mouse_keys;
+pub mod pointer_warp;
+pub mod sticky_keys;
 
 #[derive(Default)]
 pub struct SupressedKeys(RefCell<Vec<(Keycode, Option<RegistrationToken>)>>);
@@ -92,6 +114,9 @@ pub struct SupressedKeys(RefCell<Vec<(Keycode, Option<RegistrationToken>)>>);
 pub struct SupressedButtons(RefCell<HashSet<u32>>);
 #[derive(Default, Debug)]
 pub struct ModifiersShortcutQueue(RefCell<Option<shortcuts::Binding>>);
+/// Partial wheel clicks per axis, while emulating discrete scrolling
+#[derive(Default, Debug)]
+pub struct ScrollRemainder(RefCell<[f64; 2]>);
 
 impl SupressedKeys {
     fn add(&self, keysym: &KeysymHandle, token: impl Into<Option<RegistrationToken>>) {
@@ -117,6 +142,15 @@ impl SupressedKeys {
                 .collect::<Vec<_>>(),
         )
     }
+
+    /// Forgets all suppressed keys, returning the timers repeating their actions
+    fn clear(&self) -> Vec<RegistrationToken> {
+        self.0
+            .borrow_mut()
+            .drain(..)
+            .filter_map(|(_, token)| token)
+            .collect()
+    }
 }
 
 impl SupressedButtons {
@@ -151,7 +185,24 @@ impl ModifiersShortcutQueue {
     }
 }
 
+impl ScrollRemainder {
+    /// Adds a high-resolution wheel movement, returns the number of whole clicks reached
+    fn steps(&self, axis: Axis, v120: f64) -> i32 {
+        let mut remainder = self.0.borrow_mut();
+        let remainder = &mut remainder[axis as usize];
+        // the wheel changed direction
+        if remainder.signum() != v120.signum() {
+            *remainder = 0.0;
+        }
+        *remainder += v120;
+        let steps = (*remainder / 120.0).trunc();
+        *remainder -= steps * 120.0;
+        steps as i32
+    }
+}
+
 impl State {
+    #[tracing::instrument(skip_all)]
     pub fn process_input_event<B: InputBackend>(
         &mut self,
         event: InputEvent<B>,
@@ -160,6 +211,19 @@ impl State {
         <B as InputBackend>::Device: 'static,
     {
         use smithay::backend::input::Event;
+        // releasing the key, that just turned off the outputs, shouldn't wake them up again
+        let is_key_release = matches!(
+            &event,
+            InputEvent::Keyboard { event } if event.state() == KeyState::Released
+        );
+        if !is_key_release
+            && !matches!(
+                event,
+                InputEvent::DeviceAdded { .. } | InputEvent::DeviceRemoved { .. }
+            )
+        {
+            self.notify_activity();
+        }
         match event {
             InputEvent::DeviceAdded { device } => {
                 let shell = self.common.shell.read().unwrap();
@@ -189,10 +253,6 @@ impl State {
                 }
             }
             InputEvent::Keyboard { event, .. } => {
-                use smithay::backend::input::KeyboardKeyEvent;
-
-                let loop_handle = self.common.event_loop_handle.clone();
-
                 let maybe_seat = self
                     .common
                     .shell
@@ -203,512 +263,28 @@ impl State {
                     .cloned();
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
-                    let current_output = seat.active_output();
-                    let shortcuts_inhibited = self
-                        .common
-                        .shell
-                        .read()
-                        .unwrap()
-                        .active_space(&current_output)
-                        .focus_stack
-                        .get(&seat)
-                        .last()
-                        .and_then(|window| {
-                            window.wl_surface().and_then(|surface| {
-                                seat.keyboard_shortcuts_inhibitor_for_surface(&surface)
-                            })
-                        })
-                        .map(|inhibitor| inhibitor.is_active())
-                        .unwrap_or(false);
-
                     let keycode = event.key_code();
                     let state = event.state();
-                    trace!(?keycode, ?state, "key");
-
-                    let serial = SERIAL_COUNTER.next_serial();
                     let time = Event::time_msec(&event);
-                    let keyboard = seat.get_keyboard().unwrap();
-                    let pointer = seat.get_pointer().unwrap();
-                    let is_grabbed = keyboard.is_grabbed() || pointer.is_grabbed();
-                    let current_focus = keyboard.current_focus();
-                    let shell_ref = self.common.shell.clone();
-                    let mut shell = shell_ref.write().unwrap();
-                    if let Some((action, pattern)) = keyboard
-                            .input(
-                                self,
-                                keycode,
-                                state,
-                                serial,
-                                time,
-                                |data, modifiers, handle| {
-                                    // Leave move overview mode, if any modifier was released
-                                    if let Some(Trigger::KeyboardMove(action_modifiers)) =
-                                        shell.overview_mode().0.active_trigger()
-                                    {
-                                        if (action_modifiers.ctrl && !modifiers.ctrl)
-                                            || (action_modifiers.alt && !modifiers.alt)
-                                            || (action_modifiers.logo && !modifiers.logo)
-                                            || (action_modifiers.shift && !modifiers.shift)
-                                        {
-                                            shell.set_overview_mode(None, data.common.event_loop_handle.clone());
-                                        }
-                                    }
-                                    // Leave swap overview mode, if any key was released
-                                    if let Some(Trigger::KeyboardSwap(action_pattern, old_descriptor)) =
-                                        shell.overview_mode().0.active_trigger()
-                                    {
-                                        if (action_pattern.modifiers.ctrl && !modifiers.ctrl)
-                                            || (action_pattern.modifiers.alt && !modifiers.alt)
-                                            || (action_pattern.modifiers.logo && !modifiers.logo)
-                                            || (action_pattern.modifiers.shift && !modifiers.shift)
-                                            || (action_pattern.key.is_some() && handle.raw_syms().contains(&action_pattern.key.unwrap()) && state == KeyState::Released)
-                                        {
-                                            shell.set_overview_mode(None, data.common.event_loop_handle.clone());
-
-                                            if let Some(focus) = current_focus {
-                                                if let Some(new_descriptor) = shell.workspaces.active(&current_output).1.node_desc(focus) {
-                                                    let mut spaces = shell.workspaces.spaces_mut();
-                                                    if old_descriptor.handle != new_descriptor.handle {
-                                                        let (mut old_w, mut other_w) = spaces.partition::<Vec<_>, _>(|w| w.handle == old_descriptor.handle);
-                                                        if let Some(old_workspace) = old_w.get_mut(0) {
-                                                            if let Some(new_workspace) = other_w.iter_mut().find(|w| w.handle == new_descriptor.handle) {
-                                                                if let Some(focus) = TilingLayout::swap_trees(&mut old_workspace.tiling_layer, Some(&mut new_workspace.tiling_layer), &old_descriptor, &new_descriptor) {
-                                                                    let seat = seat.clone();
-                                                                    data.common.event_loop_handle.insert_idle(move |state| {
-                                                                        Shell::set_focus(state, Some(&focus), &seat, None);
-                                                                    });
-                                                                }
-                                                                old_workspace.refresh_focus_stack();
-                                                                new_workspace.refresh_focus_stack();
-                                                            }
-                                                        }
-                                                    } else {
-                                                        if let Some(workspace) = spaces.find(|w| w.handle == new_descriptor.handle) {
-                                                            if let Some(focus) = TilingLayout::swap_trees(&mut workspace.tiling_layer, None, &old_descriptor, &new_descriptor) {
-                                                                std::mem::drop(spaces);
-                                                                let seat = seat.clone();
-                                                                data.common.event_loop_handle.insert_idle(move |state| {
-                                                                    Shell::set_focus(state, Some(&focus), &seat, None);
-                                                                });
-                                                            }
-                                                            workspace.refresh_focus_stack();
-                                                        }
-                                                    }
-                                                }
-                                            } else {
-                                                let new_workspace = shell.workspaces.active(&current_output).1.handle;
-                                                if new_workspace != old_descriptor.handle {
-                                                    let spaces = shell.workspaces.spaces_mut();
-                                                    let (mut old_w, mut other_w) = spaces.partition::<Vec<_>, _>(|w| w.handle == old_descriptor.handle);
-                                                    if let Some(old_workspace) = old_w.get_mut(0) {
-                                                        if let Some(new_workspace) = other_w.iter_mut().find(|w| w.handle == new_workspace) {
-                                                            if new_workspace.tiling_layer.windows().next().is_none() {
-                                                                if let Some(focus) = TilingLayout::move_tree(&mut old_workspace.tiling_layer, &mut new_workspace.tiling_layer, &new_workspace.handle, &seat, new_workspace.focus_stack.get(&seat).iter(), old_descriptor.clone()) {
-                                                                    let seat = seat.clone();
-                                                                    data.common.event_loop_handle.insert_idle(move |state| {
-                                                                        Shell::set_focus(state, Some(&focus), &seat, None);
-                                                                    });
-                                                                }
-                                                                old_workspace.refresh_focus_stack();
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-
-                                    // Leave or update resize mode, if modifiers changed or initial key was released
-                                    if let Some(action_pattern) =
-                                        shell.resize_mode().0.active_binding()
-                                    {
-                                        if action_pattern.key.is_some() && state == KeyState::Released
-                                            && handle.raw_syms().contains(&action_pattern.key.unwrap())
-                                        {
-                                            shell.set_resize_mode(None, &data.common.config, data.common.event_loop_handle.clone());
-                                        } else if !cosmic_modifiers_eq_smithay(&action_pattern.modifiers, modifiers) {
-                                            let mut new_pattern = action_pattern.clone();
-                                            new_pattern.modifiers = cosmic_modifiers_from_smithay(modifiers.clone());
-                                            let enabled = data
-                                                .common
-                                                .config
-                                                .shortcuts
-                                                .iter()
-                                                .find_map(move |(binding, action)| {
-                                                    if binding == &new_pattern
-                                                        && matches!(action, shortcuts::Action::Resizing(_))
-                                                    {
-                                                        let shortcuts::Action::Resizing(direction) = action else { unreachable!() };
-                                                        Some((new_pattern.clone(), *direction))
-                                                    } else {
-                                                        None
-                                                    }
-                                                });
-                                            shell.set_resize_mode(enabled, &data.common.config, data.common.event_loop_handle.clone());
-                                        }
-                                    }
-
-                                    // Special case resizing with regards to arrow keys
-                                    if let Some(direction) =
-                                        shell.resize_mode().0.active_direction()
-                                    {
-                                        let resize_edge = match handle.modified_sym() {
-                                            Keysym::Left | Keysym::h | Keysym::H => Some(ResizeEdge::LEFT),
-                                            Keysym::Down | Keysym::j | Keysym::J => Some(ResizeEdge::BOTTOM),
-                                            Keysym::Up | Keysym::k | Keysym::K => Some(ResizeEdge::TOP),
-                                            Keysym::Right | Keysym::l | Keysym::L => Some(ResizeEdge::RIGHT),
-                                            _ => None,
-                                        };
-
-                                        if let Some(mut edge) = resize_edge {
-                                            if direction == ResizeDirection::Inwards {
-                                                edge.flip_direction();
-                                            }
-                                            let action = Action::Private(PrivateAction::Resizing(direction, edge.into(), cosmic_keystate_from_smithay(state)));
-                                            let key_pattern = shortcuts::Binding {
-                                                modifiers: cosmic_modifiers_from_smithay(modifiers.clone()),
-                                                key: Some(Keysym::new(handle.raw_code().raw())),
-                                                description: None,
-                                            };
-
-                                            if state == KeyState::Released {
-                                                if let Some(tokens) = seat.supressed_keys().filter(&handle) {
-                                                    for token in tokens {
-                                                        loop_handle.remove(token);
-                                                    }
-                                                }
-                                            } else {
-                                                let token = if needs_key_repetition {
-                                                    let seat_clone = seat.clone();
-                                                    let action_clone = action.clone();
-                                                    let key_pattern_clone = key_pattern.clone();
-                                                    let start = Instant::now();
-                                                    loop_handle.insert_source(Timer::from_duration(Duration::from_millis(200)), move |current, _, state| {
-                                                        let duration = current.duration_since(start).as_millis();
-                                                        state.handle_action(action_clone.clone(), &seat_clone, serial, time.overflowing_add(duration as u32).0, key_pattern_clone.clone(), None, true);
-                                                        calloop::timer::TimeoutAction::ToDuration(Duration::from_millis(25))
-                                                    }).ok()
-                                                } else { None };
-
-                                                seat.supressed_keys()
-                                                        .add(&handle, token);
-                                            }
-                                            return FilterResult::Intercept(Some((
-                                                action,
-                                                key_pattern
-                                            )));
-                                        }
-                                    }
-
-                                    std::mem::drop(shell);
-
-                                    // cancel grabs
-                                    if is_grabbed
-                                        && handle.modified_sym() == Keysym::Escape
-                                        && state == KeyState::Pressed
-                                        && !modifiers.alt
-                                        && !modifiers.ctrl
-                                        && !modifiers.logo
-                                        && !modifiers.shift
-                                    {
-                                        seat.supressed_keys()
-                                                .add(&handle, None);
-                                        return FilterResult::Intercept(Some((
-                                            Action::Private(PrivateAction::Escape),
-                                            shortcuts::Binding {
-                                                modifiers: shortcuts::Modifiers::default(),
-                                                key: Some(Keysym::Escape),
-                                                description: None,
-                                            }
-                                        )));
-                                    }
-
-                                    // Skip released events for initially surpressed keys
-                                    if state == KeyState::Released {
-                                        if let Some(tokens) = seat.supressed_keys().filter(&handle) {
-                                            for token in tokens {
-                                                loop_handle.remove(token);
-                                            }
-                                            return FilterResult::Intercept(None);
-                                        }
-                                    }
-
-                                    // Handle VT switches
-                                    if state == KeyState::Pressed
-                                        && (Keysym::XF86_Switch_VT_1.raw() ..= Keysym::XF86_Switch_VT_12.raw())
-                                            .contains(&handle.modified_sym().raw())
-                                    {
-                                        if let Err(err) = data.backend.kms().switch_vt(
-                                            (handle.modified_sym().raw() - Keysym::XF86_Switch_VT_1.raw()
-                                                + 1)
-                                                as i32,
-                                        ) {
-                                            error!(?err, "Failed switching virtual terminal.");
-                                        }
-                                        seat.supressed_keys().add(&handle, None);
-                                        return FilterResult::Intercept(None);
-                                    }
-
-                                    // handle the rest of the global shortcuts
-                                    let mut clear_queue = true;
-                                    if !shortcuts_inhibited {
-                                        let modifiers_queue = seat.modifiers_shortcut_queue();
-
-                                        for (binding, action) in
-                                            data.common.config.shortcuts.iter()
-                                        {
-                                            if *action == shortcuts::Action::Disable {
-                                                continue;
-                                            }
-
-                                            // is this a released (triggered) modifier-only binding?
-                                            if binding.key.is_none()
-                                                && state == KeyState::Released
-                                                && !cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers)
-                                                && modifiers_queue.take(binding)
-                                            {
-                                                modifiers_queue.clear();
-                                                return FilterResult::Intercept(Some((
-                                                    Action::Shortcut(action.clone()),
-                                                    binding.clone(),
-                                                )));
-                                            }
-
-                                            // could this potentially become a modifier-only binding?
-                                            if binding.key.is_none() && state == KeyState::Pressed && cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers) {
-                                                modifiers_queue.set(binding.clone());
-                                                clear_queue = false;
-                                            }
-
-                                            // is this a normal binding?
-                                            if binding.key.is_some()
-                                                && state == KeyState::Pressed
-                                                && handle.raw_syms().contains(&binding.key.unwrap())
-                                                && cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers)
-                                            {
-                                                modifiers_queue.clear();
-                                                seat.supressed_keys().add(&handle, None);
-                                                return FilterResult::Intercept(Some((
-                                                    Action::Shortcut(action.clone()),
-                                                    binding.clone(),
-                                                )));
-                                            }
-                                        }
-                                    }
-
-                                    // no binding
-                                    if clear_queue {
-                                        seat.modifiers_shortcut_queue().clear();
-                                    }
-                                    // keys are passed through to apps
-                                    FilterResult::Forward
-                                },
-                            )
-                            .flatten()
-                        {
-                            if pattern.key.is_none() && state == KeyState::Released {
-                                // we still want to send release-events and not have apps stuck on some modifiers.
-                                keyboard.input(self, keycode, state, serial, time, |_, _, _| FilterResult::<()>::Forward);
-                            }
-                            self.handle_action(action, &seat, serial, time, pattern, None, true)
-                        }
+                    if self.filter_key_input(&seat, keycode, state, time, needs_key_repetition) {
+                        self.keyboard_key_input(seat, keycode, state, time, needs_key_repetition);
+                    }
                 }
             }
             InputEvent::PointerMotion { event, .. } => {
                 use smithay::backend::input::PointerMotionEvent;
 
-                let mut shell = self.common.shell.write().unwrap();
-                if let Some(seat) = shell.seats.for_device(&event.device()).cloned() {
+                let maybe_seat = self
+                    .common
+                    .shell
+                    .read()
+                    .unwrap()
+                    .seats
+                    .for_device(&event.device())
+                    .cloned();
+                if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
-                    let current_output = seat.active_output();
-
-                    let mut position = seat.get_pointer().unwrap().current_location().as_global();
-
-                    let under = State::surface_under(position, &current_output, &mut *shell)
-                        .map(|(target, pos)| (target, pos.as_logical()));
-
-                    let ptr = seat.get_pointer().unwrap();
-
-                    let mut pointer_locked = false;
-                    let mut pointer_confined = false;
-                    let mut confine_region = None;
-                    if let Some((surface, surface_loc)) = under
-                        .as_ref()
-                        .and_then(|(target, l)| Some((target.wl_surface()?, l)))
-                    {
-                        with_pointer_constraint(&surface, &ptr, |constraint| match constraint {
-                            Some(constraint) if constraint.is_active() => {
-                                // Constraint does not apply if not within region
-                                if !constraint.region().map_or(true, |x| {
-                                    x.contains(
-                                        (ptr.current_location() - *surface_loc).to_i32_round(),
-                                    )
-                                }) {
-                                    return;
-                                }
-                                match &*constraint {
-                                    PointerConstraint::Locked(_locked) => {
-                                        pointer_locked = true;
-                                    }
-                                    PointerConstraint::Confined(confine) => {
-                                        pointer_confined = true;
-                                        confine_region = confine.region().cloned();
-                                    }
-                                }
-                            }
-                            _ => {}
-                        });
-                    }
-
-                    position += event.delta().as_global();
-
-                    let output = shell
-                        .outputs()
-                        .find(|output| output.geometry().to_f64().contains(position))
-                        .cloned()
-                        .unwrap_or(current_output.clone());
-
-                    let new_under = State::surface_under(position, &output, &mut *shell)
-                        .map(|(target, pos)| (target, pos.as_logical()));
-
-                    std::mem::drop(shell);
-                    ptr.relative_motion(
-                        self,
-                        under.clone(),
-                        &RelativeMotionEvent {
-                            delta: event.delta(),
-                            delta_unaccel: event.delta_unaccel(),
-                            utime: event.time(),
-                        },
-                    );
-
-                    if pointer_locked {
-                        ptr.frame(self);
-                        return;
-                    }
-
-                    if ptr.is_grabbed()
-                        && seat
-                            .user_data()
-                            .get::<ResizeGrabMarker>()
-                            .map(|marker| marker.get())
-                            .unwrap_or(false)
-                    {
-                        if output != current_output {
-                            ptr.frame(self);
-                            return;
-                        }
-                    }
-
-                    let output_geometry = output.geometry();
-
-                    position.x = position.x.clamp(
-                        output_geometry.loc.x as f64,
-                        ((output_geometry.loc.x + output_geometry.size.w) as f64).next_lower(), // FIXME: Replace with f64::next_down when stable
-                    );
-                    position.y = position.y.clamp(
-                        output_geometry.loc.y as f64,
-                        ((output_geometry.loc.y + output_geometry.size.h) as f64).next_lower(), // FIXME: Replace with f64::next_down when stable
-                    );
-
-                    // If confined, don't move pointer if it would go outside surface or region
-                    if pointer_confined {
-                        if let Some((surface, surface_loc)) = &under {
-                            if new_under.as_ref().and_then(|(under, _)| under.wl_surface())
-                                != surface.wl_surface()
-                            {
-                                ptr.frame(self);
-                                return;
-                            }
-                            if let PointerFocusTarget::WlSurface { surface, .. } = surface {
-                                if under_from_surface_tree(
-                                    surface,
-                                    position.as_logical() - surface_loc.to_f64(),
-                                    (0, 0),
-                                    WindowSurfaceType::ALL,
-                                )
-                                .is_none()
-                                {
-                                    ptr.frame(self);
-                                    return;
-                                }
-                            }
-                            if let Some(region) = confine_region {
-                                if !region
-                                    .contains((position.as_logical() - *surface_loc).to_i32_round())
-                                {
-                                    ptr.frame(self);
-                                    return;
-                                }
-                            }
-                        }
-                    }
-
-                    let serial = SERIAL_COUNTER.next_serial();
-                    ptr.motion(
-                        self,
-                        under,
-                        &MotionEvent {
-                            location: position.as_logical(),
-                            serial,
-                            time: event.time_msec(),
-                        },
-                    );
-                    ptr.frame(self);
-
-                    // If pointer is now in a constraint region, activate it
-                    if let Some((under, surface_location)) = new_under
-                        .and_then(|(target, loc)| Some((target.wl_surface()?.into_owned(), loc)))
-                    {
-                        with_pointer_constraint(&under, &ptr, |constraint| match constraint {
-                            Some(constraint) if !constraint.is_active() => {
-                                let region = match &*constraint {
-                                    PointerConstraint::Locked(locked) => locked.region(),
-                                    PointerConstraint::Confined(confined) => confined.region(),
-                                };
-                                let point =
-                                    (ptr.current_location() - surface_location).to_i32_round();
-                                if region.map_or(true, |region| region.contains(point)) {
-                                    constraint.activate();
-                                }
-                            }
-                            _ => {}
-                        });
-                    }
-
-                    let shell = self.common.shell.read().unwrap();
-
-                    if output != current_output {
-                        for session in cursor_sessions_for_output(&*shell, &current_output) {
-                            session.set_cursor_pos(None);
-                        }
-                        seat.set_active_output(&output);
-                    }
-
-                    for session in cursor_sessions_for_output(&shell, &output) {
-                        if let Some((geometry, offset)) = seat.cursor_geometry(
-                            position.as_logical().to_buffer(
-                                output.current_scale().fractional_scale(),
-                                output.current_transform(),
-                                &output_geometry.size.to_f64().as_logical(),
-                            ),
-                            self.common.clock.now(),
-                        ) {
-                            if session
-                                .current_constraints()
-                                .map(|constraint| constraint.size != geometry.size)
-                                .unwrap_or(true)
-                            {
-                                session.update_constraints(BufferConstraints {
-                                    size: geometry.size,
-                                    shm: vec![ShmFormat::Argb8888],
-                                    dma: None,
-                                });
-                            }
-                            session.set_cursor_hotspot(offset);
-                            session.set_cursor_pos(Some(geometry.loc));
-                        }
-                    }
+                    self.pointer_motion(&seat, event.delta(), event.delta_unaccel(), event.time());
                 }
             }
             InputEvent::PointerMotionAbsolute { event, .. } => {
@@ -723,58 +299,8 @@ impl State {
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
                     let output = seat.active_output();
-                    let geometry = output.geometry();
-                    let position = geometry.loc.to_f64()
-                        + smithay::backend::input::AbsolutePositionEvent::position_transformed(
-                            &event,
-                            geometry.size.as_logical(),
-                        )
-                        .as_global();
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let under = State::surface_under(
-                        position,
-                        &output,
-                        &mut *self.common.shell.write().unwrap(),
-                    )
-                    .map(|(target, pos)| (target, pos.as_logical()));
-
-                    let ptr = seat.get_pointer().unwrap();
-                    ptr.motion(
-                        self,
-                        under,
-                        &MotionEvent {
-                            location: position.as_logical(),
-                            serial,
-                            time: event.time_msec(),
-                        },
-                    );
-                    ptr.frame(self);
-
-                    let shell = self.common.shell.read().unwrap();
-                    for session in cursor_sessions_for_output(&*shell, &output) {
-                        if let Some((geometry, offset)) = seat.cursor_geometry(
-                            position.as_logical().to_buffer(
-                                output.current_scale().fractional_scale(),
-                                output.current_transform(),
-                                &geometry.size.to_f64().as_logical(),
-                            ),
-                            self.common.clock.now(),
-                        ) {
-                            if session
-                                .current_constraints()
-                                .map(|constraint| constraint.size != geometry.size)
-                                .unwrap_or(true)
-                            {
-                                session.update_constraints(BufferConstraints {
-                                    size: geometry.size,
-                                    shm: vec![ShmFormat::Argb8888],
-                                    dma: None,
-                                });
-                            }
-                            session.set_cursor_hotspot(offset);
-                            session.set_cursor_pos(Some(geometry.loc));
-                        }
-                    }
+                    let position = absolute_position_on_output(&event, &output);
+                    self.pointer_motion_absolute(&seat, &output, position, event.time_msec());
                 }
             }
             InputEvent::PointerButton { event, .. } => {
@@ -788,6 +314,12 @@ impl State {
                     let button = event.button_code();
                     let mut pass_event = !seat.supressed_buttons().remove(button);
                     if event.state() == ButtonState::Pressed {
+                        // Ctrl+click doesn't locate the pointer
+                        seat.user_data()
+                            .get::<SeatLocatePointer>()
+                            .unwrap()
+                            .cancel();
+
                         // change the keyboard focus unless the pointer is grabbed
                         // We test for any matching surface type here but always use the root
                         // (in case of a window the toplevel) surface for the focus.
@@ -859,7 +391,10 @@ impl State {
                                     // Don't check override redirect windows, because we don't set keyboard focus to them explicitly.
                                     // These cases are handled by the XwaylandKeyboardGrab.
                                     if let Some(target) = shell.element_under(pos, &output) {
-                                        if seat.get_keyboard().unwrap().modifier_state().logo {
+                                        if cosmic_modifiers_held_smithay(
+                                            &self.common.config.cosmic_conf.window_drag_modifiers,
+                                            &seat.get_keyboard().unwrap().modifier_state(),
+                                        ) {
                                             if let Some(surface) =
                                                 target.toplevel().map(Cow::into_owned)
                                             {
@@ -868,7 +403,7 @@ impl State {
                                                     PointerButtonEvent::button(&event);
 
                                                 let mut supress_button = || {
-                                                    // If the logo is held then the pointer event is
+                                                    // If the modifiers are held then the pointer event is
                                                     // aimed at the compositor and shouldn't be passed
                                                     // to the application.
                                                     pass_event = false;
@@ -1023,11 +558,14 @@ impl State {
                 }
             }
             InputEvent::PointerAxis { event, .. } => {
-                let scroll_factor =
+                let (scroll_factor, discrete_scroll) =
                     if let Some(device) = <dyn Any>::downcast_ref::<InputDevice>(&event.device()) {
-                        self.common.config.scroll_factor(device)
+                        (
+                            self.common.config.scroll_factor(device),
+                            self.common.config.discrete_scroll(device),
+                        )
                     } else {
-                        1.0
+                        (1.0, false)
                     };
 
                 let maybe_seat = self
@@ -1041,34 +579,65 @@ impl State {
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
 
+                    // Super+Alt+scrolling zooms the screen in or out
+                    let modifiers = seat.get_keyboard().unwrap().modifier_state();
+                    if modifiers.logo && modifiers.alt && !modifiers.ctrl {
+                        if let Some(amount) = event.amount(Axis::Vertical) {
+                            let steps = event
+                                .amount_v120(Axis::Vertical)
+                                .map(|discrete| discrete / 120.0)
+                                // libinput reports 15 degrees per wheel click
+                                .unwrap_or(amount / 15.0);
+                            self.update_zoom(-steps);
+                        }
+                        return;
+                    }
+
                     let mut frame = AxisFrame::new(event.time_msec()).source(event.source());
-                    if let Some(horizontal_amount) = event.amount(Axis::Horizontal) {
-                        if horizontal_amount != 0.0 {
-                            frame =
-                                frame.value(Axis::Horizontal, scroll_factor * horizontal_amount);
-                            if let Some(discrete) = event.amount_v120(Axis::Horizontal) {
-                                frame = frame.v120(
-                                    Axis::Horizontal,
-                                    (discrete * scroll_factor).round() as i32,
-                                );
+                    let mut empty = true;
+                    for axis in [Axis::Horizontal, Axis::Vertical] {
+                        let Some(amount) = event.amount(axis) else {
+                            continue;
+                        };
+                        if amount == 0.0 {
+                            if event.source() == AxisSource::Finger {
+                                frame = frame.stop(axis);
+                                empty = false;
                             }
-                        } else if event.source() == AxisSource::Finger {
-                            frame = frame.stop(Axis::Horizontal);
+                            continue;
                         }
-                    }
-                    if let Some(vertical_amount) = event.amount(Axis::Vertical) {
-                        if vertical_amount != 0.0 {
-                            frame = frame.value(Axis::Vertical, scroll_factor * vertical_amount);
-                            if let Some(discrete) = event.amount_v120(Axis::Vertical) {
-                                frame = frame.v120(
-                                    Axis::Vertical,
-                                    (discrete * scroll_factor).round() as i32,
-                                );
+
+                        match event.amount_v120(axis) {
+                            // only send whole wheel clicks, for clients misbehaving otherwise
+                            Some(discrete)
+                                if discrete_scroll && event.source() == AxisSource::Wheel =>
+                            {
+                                let steps = seat
+                                    .user_data()
+                                    .get::<ScrollRemainder>()
+                                    .unwrap()
+                                    .steps(axis, discrete * scroll_factor);
+                                if steps != 0 {
+                                    // libinput reports 15 degrees per wheel click
+                                    frame = frame.value(axis, steps as f64 * 15.0);
+                                    frame = frame.v120(axis, steps * 120);
+                                    empty = false;
+                                }
+                            }
+                            discrete => {
+                                frame = frame.value(axis, scroll_factor * amount);
+                                if let Some(discrete) = discrete {
+                                    frame =
+                                        frame.v120(axis, (discrete * scroll_factor).round() as i32);
+                                }
+                                empty = false;
                             }
-                        } else if event.source() == AxisSource::Finger {
-                            frame = frame.stop(Axis::Vertical);
                         }
                     }
+                    // partial wheel clicks are held back
+                    if empty {
+                        return;
+                    }
                     let ptr = seat.get_pointer().unwrap();
                     ptr.axis(self, frame);
                     ptr.frame(self);
@@ -1085,7 +654,20 @@ impl State {
                     .cloned();
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
-                    if event.fingers() >= 3 && !workspace_overview_is_open(&seat.active_output()) {
+                    // games get all swipes, so they can't switch workspaces by accident
+                    let game_focused = self
+                        .common
+                        .shell
+                        .read()
+                        .unwrap()
+                        .game_mode_active(&seat.active_output());
+                    if !game_focused
+                        && GestureOwner::for_swipe(
+                            event.fingers(),
+                            workspace_overview_is_open(&seat.active_output()),
+                            &self.common.config.cosmic_conf.gestures,
+                        ) == GestureOwner::Compositor
+                    {
                         self.common.gesture_state = Some(GestureState::new(event.fingers()));
                     } else {
                         let serial = SERIAL_COUNTER.next_serial();
@@ -1126,53 +708,42 @@ impl State {
                                     natural_scroll = natural;
                                 }
                             }
-                            activate_action = match gesture_state.fingers {
-                                3 => None, // TODO: 3 finger gestures
-                                4 => {
-                                    if self.common.config.cosmic_conf.workspaces.workspace_layout
-                                        == WorkspaceLayout::Horizontal
-                                    {
-                                        match gesture_state.direction {
-                                            Some(Direction::Left) => {
-                                                if natural_scroll {
-                                                    Some(SwipeAction::NextWorkspace)
-                                                } else {
-                                                    Some(SwipeAction::PrevWorkspace)
-                                                }
-                                            }
-                                            Some(Direction::Right) => {
-                                                if natural_scroll {
-                                                    Some(SwipeAction::PrevWorkspace)
-                                                } else {
-                                                    Some(SwipeAction::NextWorkspace)
-                                                }
-                                            }
-                                            _ => None, // TODO: Other actions
-                                        }
-                                    } else {
-                                        match gesture_state.direction {
-                                            Some(Direction::Up) => {
-                                                if natural_scroll {
-                                                    Some(SwipeAction::NextWorkspace)
-                                                } else {
-                                                    Some(SwipeAction::PrevWorkspace)
-                                                }
-                                            },
-                                            Some(Direction::Down) => {
-                                                if natural_scroll {
-                                                    Some(SwipeAction::PrevWorkspace)
-                                                } else {
-                                                    Some(SwipeAction::NextWorkspace)
-                                                }
-                                            }
-                                            _ => None, // TODO: Other actions
-                                        }
+                            let direction = gesture_state.direction.map(|direction| {
+                                if natural_scroll {
+                                    match direction {
+                                        Direction::Left => Direction::Right,
+                                        Direction::Right => Direction::Left,
+                                        Direction::Up => Direction::Down,
+                                        Direction::Down => Direction::Up,
                                     }
+                                } else {
+                                    direction
                                 }
-                                _ => None,
-                            };
+                            });
+                            // workspaces only follow the fingers along the axis of the layout
+                            let along_layout =
+                                matches!(direction, Some(Direction::Left) | Some(Direction::Right))
+                                    == (self.common.config.cosmic_conf.workspaces.workspace_layout
+                                        == WorkspaceLayout::Horizontal);
+                            activate_action = direction
+                                .and_then(|direction| {
+                                    self.common
+                                        .config
+                                        .cosmic_conf
+                                        .gestures
+                                        .action(gesture_state.fingers, Gesture::Swipe(direction))
+                                })
+                                .map(|action| match action {
+                                    shortcuts::Action::NextWorkspace if along_layout => {
+                                        SwipeAction::NextWorkspace
+                                    }
+                                    shortcuts::Action::PreviousWorkspace if along_layout => {
+                                        SwipeAction::PrevWorkspace
+                                    }
+                                    action => SwipeAction::Action(action.clone()),
+                                });
 
-                            gesture_state.action = activate_action;
+                            gesture_state.action = activate_action.clone();
                         }
 
                         match gesture_state.action {
@@ -1226,7 +797,7 @@ impl State {
                     .cloned();
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
-                    if let Some(ref gesture_state) = self.common.gesture_state {
+                    if let Some(gesture_state) = self.common.gesture_state.take() {
                         match gesture_state.action {
                             Some(SwipeAction::NextWorkspace) | Some(SwipeAction::PrevWorkspace) => {
                                 let velocity = gesture_state.velocity();
@@ -1244,9 +815,11 @@ impl State {
                                     &mut self.common.workspace_state.update(),
                                 );
                             }
+                            Some(SwipeAction::Action(action)) if !event.cancelled() => {
+                                self.gesture_action(action, &seat, event.time_msec());
+                            }
                             _ => {}
                         }
-                        self.common.gesture_state = None;
                     } else {
                         let serial = SERIAL_COUNTER.next_serial();
                         let pointer = seat.get_pointer().unwrap();
@@ -1272,16 +845,35 @@ impl State {
                     .cloned();
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let pointer = seat.get_pointer().unwrap();
-                    pointer.gesture_pinch_begin(
-                        self,
-                        &GesturePinchBeginEvent {
-                            serial,
-                            time: event.time_msec(),
-                            fingers: event.fingers(),
-                        },
-                    );
+                    // pinching with Super+Alt held or the configured fingers zooms the screen
+                    let modifiers = seat.get_keyboard().unwrap().modifier_state();
+                    let pinch_fingers = self.common.config.cosmic_conf.zoom.pinch_fingers;
+                    if (modifiers.logo && modifiers.alt && !modifiers.ctrl)
+                        || (pinch_fingers != 0 && event.fingers() == pinch_fingers)
+                    {
+                        let mut gesture_state = GestureState::new(event.fingers());
+                        gesture_state.zoom_level =
+                            Some(self.common.shell.read().unwrap().zoom_level);
+                        self.common.gesture_state = Some(gesture_state);
+                    } else if GestureOwner::for_pinch(
+                        event.fingers(),
+                        workspace_overview_is_open(&seat.active_output()),
+                        &self.common.config.cosmic_conf.gestures,
+                    ) == GestureOwner::Compositor
+                    {
+                        self.common.gesture_state = Some(GestureState::new(event.fingers()));
+                    } else {
+                        let serial = SERIAL_COUNTER.next_serial();
+                        let pointer = seat.get_pointer().unwrap();
+                        pointer.gesture_pinch_begin(
+                            self,
+                            &GesturePinchBeginEvent {
+                                serial,
+                                time: event.time_msec(),
+                                fingers: event.fingers(),
+                            },
+                        );
+                    }
                 }
             }
             InputEvent::GesturePinchUpdate { event, .. } => {
@@ -1295,16 +887,23 @@ impl State {
                     .cloned();
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
-                    let pointer = seat.get_pointer().unwrap();
-                    pointer.gesture_pinch_update(
-                        self,
-                        &GesturePinchUpdateEvent {
-                            time: event.time_msec(),
-                            delta: event.delta(),
-                            scale: event.scale(),
-                            rotation: event.rotation(),
-                        },
-                    );
+                    if let Some(ref mut gesture_state) = self.common.gesture_state {
+                        gesture_state.scale = event.scale();
+                        if let Some(zoom_level) = gesture_state.zoom_level {
+                            self.set_zoom_level(zoom_level * event.scale());
+                        }
+                    } else {
+                        let pointer = seat.get_pointer().unwrap();
+                        pointer.gesture_pinch_update(
+                            self,
+                            &GesturePinchUpdateEvent {
+                                time: event.time_msec(),
+                                delta: event.delta(),
+                                scale: event.scale(),
+                                rotation: event.rotation(),
+                            },
+                        );
+                    }
                 }
             }
             InputEvent::GesturePinchEnd { event, .. } => {
@@ -1318,16 +917,39 @@ impl State {
                     .cloned();
                 if let Some(seat) = maybe_seat {
                     self.common.idle_notifier_state.notify_activity(&seat);
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let pointer = seat.get_pointer().unwrap();
-                    pointer.gesture_pinch_end(
-                        self,
-                        &GesturePinchEndEvent {
-                            serial,
-                            time: event.time_msec(),
-                            cancelled: event.cancelled(),
-                        },
-                    );
+                    if let Some(gesture_state) = self.common.gesture_state.take() {
+                        if let Some(zoom_level) = gesture_state.zoom_level {
+                            if event.cancelled() {
+                                self.set_zoom_level(zoom_level);
+                            }
+                            return;
+                        }
+                        let action = gesture_state
+                            .pinch()
+                            .filter(|_| !event.cancelled())
+                            .and_then(|gesture| {
+                                self.common
+                                    .config
+                                    .cosmic_conf
+                                    .gestures
+                                    .action(gesture_state.fingers, gesture)
+                                    .cloned()
+                            });
+                        if let Some(action) = action {
+                            self.gesture_action(action, &seat, event.time_msec());
+                        }
+                    } else {
+                        let serial = SERIAL_COUNTER.next_serial();
+                        let pointer = seat.get_pointer().unwrap();
+                        pointer.gesture_pinch_end(
+                            self,
+                            &GesturePinchEndEvent {
+                                serial,
+                                time: event.time_msec(),
+                                cancelled: event.cancelled(),
+                            },
+                        );
+                    }
                 }
             }
             InputEvent::GestureHoldBegin { event, .. } => {
@@ -1387,20 +1009,25 @@ impl State {
                         return;
                     };
 
-                    let geometry = output.geometry();
-
-                    let position = geometry.loc.to_f64()
-                        + event
-                            .position_transformed(geometry.size.as_logical())
-                            .as_global();
+                    let position = absolute_position_on_output(&event, &output);
 
                     let under = State::surface_under(position, &output, &mut *shell)
                         .map(|(target, pos)| (target, pos.as_logical()));
 
-                    std::mem::drop(shell);
-
                     let serial = SERIAL_COUNTER.next_serial();
                     let touch = seat.get_touch().unwrap();
+                    // tapping focuses and raises, like clicking, unless a touch grab is active
+                    if !touch.is_grabbed() {
+                        let focus = keyboard_focus_under(&mut shell, position, &output);
+                        std::mem::drop(shell);
+                        if output != seat.active_output() {
+                            seat.set_active_output(&output);
+                        }
+                        Shell::set_focus(self, focus.as_ref(), &seat, Some(serial));
+                    } else {
+                        std::mem::drop(shell);
+                    }
+
                     touch.down(
                         self,
                         under,
@@ -1424,12 +1051,7 @@ impl State {
                         return;
                     };
 
-                    let geometry = output.geometry();
-
-                    let position = geometry.loc.to_f64()
-                        + event
-                            .position_transformed(geometry.size.as_logical())
-                            .as_global();
+                    let position = absolute_position_on_output(&event, &output);
 
                     let under = State::surface_under(position, &output, &mut *shell)
                         .map(|(target, pos)| (target, pos.as_logical()));
@@ -1512,171 +1134,1009 @@ impl State {
                     else {
                         return;
                     };
-                    let geometry = output.geometry();
+                    let position = tablet_position(
+                        &event,
+                        &self.common.config,
+                        &shell,
+                        &seat,
+                        &output,
+                        self.common.tablet_mapping_toggled,
+                    );
 
-                    let position = event
-                        .position_transformed(geometry.size.as_logical())
-                        .as_global()
-                        + geometry.loc.to_f64();
+                    let under = State::surface_under(position, &output, &mut *shell)
+                        .map(|(target, pos)| (target, pos.as_logical()));
+
+                    std::mem::drop(shell);
+
+                    let pointer = seat.get_pointer().unwrap();
+                    pointer.motion(
+                        self,
+                        under.clone(),
+                        &MotionEvent {
+                            location: position.as_logical(),
+                            serial: SERIAL_COUNTER.next_serial(),
+                            time: self.common.clock.now().as_millis(),
+                        },
+                    );
+
+                    let tablet_seat = seat.tablet_seat();
+
+                    let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
+                    let tool = tablet_seat.get_tool(&event.tool());
+
+                    if let (Some(tablet), Some(tool)) = (tablet, tool) {
+                        if event.pressure_has_changed() {
+                            tool.pressure(event.pressure());
+                        }
+                        if event.distance_has_changed() {
+                            tool.distance(event.distance());
+                        }
+                        if event.tilt_has_changed() {
+                            tool.tilt(event.tilt());
+                        }
+                        if event.slider_has_changed() {
+                            tool.slider_position(event.slider_position());
+                        }
+                        if event.rotation_has_changed() {
+                            tool.rotation(event.rotation());
+                        }
+                        if event.wheel_has_changed() {
+                            tool.wheel(event.wheel_delta(), event.wheel_delta_discrete());
+                        }
+
+                        tool.motion(
+                            position.as_logical(),
+                            under
+                                .and_then(|(f, loc)| f.wl_surface().map(|s| (s.into_owned(), loc))),
+                            &tablet,
+                            SERIAL_COUNTER.next_serial(),
+                            event.time_msec(),
+                        );
+                    }
+                }
+            }
+            InputEvent::TabletToolProximity { event, .. } => {
+                let mut shell = self.common.shell.write().unwrap();
+                if let Some(seat) = shell.seats.for_device(&event.device()).cloned() {
+                    self.common.idle_notifier_state.notify_activity(&seat);
+                    let Some(output) =
+                        mapped_output_for_device(&self.common.config, &shell, &event.device())
+                            .cloned()
+                    else {
+                        return;
+                    };
+                    let position = tablet_position(
+                        &event,
+                        &self.common.config,
+                        &shell,
+                        &seat,
+                        &output,
+                        self.common.tablet_mapping_toggled,
+                    );
 
                     let under = State::surface_under(position, &output, &mut *shell)
                         .map(|(target, pos)| (target, pos.as_logical()));
 
                     std::mem::drop(shell);
 
-                    let pointer = seat.get_pointer().unwrap();
-                    pointer.motion(
-                        self,
-                        under.clone(),
-                        &MotionEvent {
-                            location: position.as_logical(),
-                            serial: SERIAL_COUNTER.next_serial(),
-                            time: self.common.clock.now().as_millis(),
-                        },
-                    );
+                    let pointer = seat.get_pointer().unwrap();
+                    pointer.motion(
+                        self,
+                        under.clone(),
+                        &MotionEvent {
+                            location: position.as_logical(),
+                            serial: SERIAL_COUNTER.next_serial(),
+                            time: self.common.clock.now().as_millis(),
+                        },
+                    );
+
+                    let tablet_seat = seat.tablet_seat();
+
+                    let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
+                    let dh = self.common.display_handle.clone();
+                    let tool = tablet_seat.add_tool::<Self>(self, &dh, &event.tool());
+
+                    if let Some(tablet) = tablet {
+                        match event.state() {
+                            ProximityState::In => {
+                                if let Some(under) = under.and_then(|(f, loc)| {
+                                    f.wl_surface().map(|s| (s.into_owned(), loc))
+                                }) {
+                                    tool.proximity_in(
+                                        position.as_logical(),
+                                        under,
+                                        &tablet,
+                                        SERIAL_COUNTER.next_serial(),
+                                        event.time_msec(),
+                                    )
+                                }
+                            }
+                            ProximityState::Out => tool.proximity_out(event.time_msec()),
+                        }
+                    }
+                }
+            }
+            InputEvent::TabletToolTip { event, .. } => {
+                let maybe_seat = self
+                    .common
+                    .shell
+                    .read()
+                    .unwrap()
+                    .seats
+                    .for_device(&event.device())
+                    .cloned();
+                if let Some(seat) = maybe_seat {
+                    self.common.idle_notifier_state.notify_activity(&seat);
+                    if let Some(tool) = seat.tablet_seat().get_tool(&event.tool()) {
+                        match event.tip_state() {
+                            TabletToolTipState::Down => {
+                                tool.tip_down(SERIAL_COUNTER.next_serial(), event.time_msec());
+                            }
+                            TabletToolTipState::Up => {
+                                tool.tip_up(event.time_msec());
+                            }
+                        }
+                    }
+                }
+            }
+            InputEvent::TabletToolButton { event, .. } => {
+                let maybe_seat = self
+                    .common
+                    .shell
+                    .read()
+                    .unwrap()
+                    .seats
+                    .for_device(&event.device())
+                    .cloned();
+                if let Some(seat) = maybe_seat {
+                    self.common.idle_notifier_state.notify_activity(&seat);
+                    if let Some(tool) = seat.tablet_seat().get_tool(&event.tool()) {
+                        tool.button(
+                            event.button(),
+                            event.button_state(),
+                            SERIAL_COUNTER.next_serial(),
+                            event.time_msec(),
+                        );
+                    }
+                }
+            }
+            InputEvent::Special(_) => {}
+            InputEvent::SwitchToggle { event: _ } => {}
+        }
+    }
+
+    /// Stops repeating shortcut actions, e.g. when the session is paused
+    /// and the releases of the held keys won't arrive anymore
+    pub fn cancel_key_repetition(&mut self) {
+        let seats = self
+            .common
+            .shell
+            .read()
+            .unwrap()
+            .seats
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for seat in seats {
+            for token in seat.supressed_keys().clear() {
+                self.common.event_loop_handle.remove(token);
+            }
+        }
+    }
+
+    /// Moves the pointer of `seat` by `delta`, respecting pointer constraints
+    pub fn pointer_motion(
+        &mut self,
+        seat: &Seat<State>,
+        delta: Point<f64, Logical>,
+        delta_unaccel: Point<f64, Logical>,
+        utime: u64,
+    ) {
+        let mut shell = self.common.shell.write().unwrap();
+        let current_output = seat.active_output();
+
+        let mut position = seat.get_pointer().unwrap().current_location().as_global();
+
+        let under = State::surface_under(position, &current_output, &mut *shell)
+            .map(|(target, pos)| (target, pos.as_logical()));
+
+        let ptr = seat.get_pointer().unwrap();
+
+        let mut pointer_locked = false;
+        let mut pointer_confined = false;
+        let mut confine_region = None;
+        if let Some((surface, surface_loc)) = under
+            .as_ref()
+            .and_then(|(target, l)| Some((target.wl_surface()?, l)))
+        {
+            with_pointer_constraint(&surface, &ptr, |constraint| match constraint {
+                Some(constraint) if constraint.is_active() => {
+                    // Constraint does not apply if not within region
+                    if !constraint.region().map_or(true, |x| {
+                        x.contains((ptr.current_location() - *surface_loc).to_i32_round())
+                    }) {
+                        return;
+                    }
+                    match &*constraint {
+                        PointerConstraint::Locked(_locked) => {
+                            pointer_locked = true;
+                        }
+                        PointerConstraint::Confined(confine) => {
+                            pointer_confined = true;
+                            confine_region = confine.region().cloned();
+                        }
+                    }
+                }
+                _ => {}
+            });
+        }
+
+        position += delta.as_global();
+
+        let output = shell
+            .outputs()
+            .find(|output| output.geometry().to_f64().contains(position))
+            .cloned()
+            .unwrap_or(current_output.clone());
+
+        let new_under = State::surface_under(position, &output, &mut *shell)
+            .map(|(target, pos)| (target, pos.as_logical()));
+
+        std::mem::drop(shell);
+        ptr.relative_motion(
+            self,
+            under.clone(),
+            &RelativeMotionEvent {
+                delta,
+                delta_unaccel,
+                utime,
+            },
+        );
+
+        if pointer_locked {
+            ptr.frame(self);
+            return;
+        }
+
+        if ptr.is_grabbed()
+            && seat
+                .user_data()
+                .get::<ResizeGrabMarker>()
+                .map(|marker| marker.get())
+                .unwrap_or(false)
+        {
+            if output != current_output {
+                ptr.frame(self);
+                return;
+            }
+        }
+
+        let output_geometry = output.geometry();
+
+        position.x = position.x.clamp(
+            output_geometry.loc.x as f64,
+            ((output_geometry.loc.x + output_geometry.size.w) as f64).next_lower(), // FIXME: Replace with f64::next_down when stable
+        );
+        position.y = position.y.clamp(
+            output_geometry.loc.y as f64,
+            ((output_geometry.loc.y + output_geometry.size.h) as f64).next_lower(), // FIXME: Replace with f64::next_down when stable
+        );
+
+        // If confined, don't move pointer if it would go outside surface or region
+        if pointer_confined {
+            if let Some((surface, surface_loc)) = &under {
+                if new_under.as_ref().and_then(|(under, _)| under.wl_surface())
+                    != surface.wl_surface()
+                {
+                    ptr.frame(self);
+                    return;
+                }
+                if let PointerFocusTarget::WlSurface { surface, .. } = surface {
+                    if under_from_surface_tree(
+                        surface,
+                        position.as_logical() - surface_loc.to_f64(),
+                        (0, 0),
+                        WindowSurfaceType::ALL,
+                    )
+                    .is_none()
+                    {
+                        ptr.frame(self);
+                        return;
+                    }
+                }
+                if let Some(region) = confine_region {
+                    if !region.contains((position.as_logical() - *surface_loc).to_i32_round()) {
+                        ptr.frame(self);
+                        return;
+                    }
+                }
+            }
+        }
+
+        let serial = SERIAL_COUNTER.next_serial();
+        ptr.motion(
+            self,
+            under,
+            &MotionEvent {
+                location: position.as_logical(),
+                serial,
+                time: (utime / 1000) as u32,
+            },
+        );
+        ptr.frame(self);
+        update_toplevel_drag(self, seat, position.as_logical());
+        update_dnd_target(self, seat, position, &output);
+
+        // If pointer is now in a constraint region, activate it
+        if let Some((under, surface_location)) = new_under
+            .and_then(|(target, loc)| Some((target.wl_surface()?.into_owned(), loc)))
+            .filter(|(under, _)| has_keyboard_focus(self, seat, under))
+        {
+            with_pointer_constraint(&under, &ptr, |constraint| match constraint {
+                Some(constraint) if !constraint.is_active() => {
+                    let region = match &*constraint {
+                        PointerConstraint::Locked(locked) => locked.region(),
+                        PointerConstraint::Confined(confined) => confined.region(),
+                    };
+                    let point = (ptr.current_location() - surface_location).to_i32_round();
+                    if region.map_or(true, |region| region.contains(point)) {
+                        constraint.activate();
+                    }
+                }
+                _ => {}
+            });
+        }
+
+        let shell = self.common.shell.read().unwrap();
+
+        if output != current_output {
+            for session in cursor_sessions_for_output(&*shell, &current_output) {
+                session.set_cursor_pos(None);
+            }
+            seat.set_active_output(&output);
+        }
+
+        for session in cursor_sessions_for_output(&shell, &output) {
+            if let Some((geometry, offset)) = seat.cursor_geometry(
+                position.as_logical().to_buffer(
+                    output.current_scale().fractional_scale(),
+                    output.current_transform(),
+                    &output_geometry.size.to_f64().as_logical(),
+                ),
+                self.common.clock.now(),
+            ) {
+                if session
+                    .current_constraints()
+                    .map(|constraint| constraint.size != geometry.size)
+                    .unwrap_or(true)
+                {
+                    session.update_constraints(BufferConstraints {
+                        size: geometry.size,
+                        shm: vec![ShmFormat::Argb8888],
+                        dma: None,
+                    });
+                }
+                session.set_cursor_hotspot(offset);
+                session.set_cursor_pos(Some(geometry.loc));
+            }
+        }
+    }
+
+    /// Moves the pointer of `seat` to `position` on `output`
+    pub fn pointer_motion_absolute(
+        &mut self,
+        seat: &Seat<State>,
+        output: &Output,
+        position: Point<f64, Global>,
+        time: u32,
+    ) {
+        let geometry = output.geometry();
+        let serial = SERIAL_COUNTER.next_serial();
+        let under =
+            State::surface_under(position, output, &mut *self.common.shell.write().unwrap())
+                .map(|(target, pos)| (target, pos.as_logical()));
+
+        let ptr = seat.get_pointer().unwrap();
+        ptr.motion(
+            self,
+            under,
+            &MotionEvent {
+                location: position.as_logical(),
+                serial,
+                time,
+            },
+        );
+        ptr.frame(self);
+        update_toplevel_drag(self, seat, position.as_logical());
+        update_dnd_target(self, seat, position, output);
+
+        let shell = self.common.shell.read().unwrap();
+        for session in cursor_sessions_for_output(&*shell, output) {
+            if let Some((geometry, offset)) = seat.cursor_geometry(
+                position.as_logical().to_buffer(
+                    output.current_scale().fractional_scale(),
+                    output.current_transform(),
+                    &geometry.size.to_f64().as_logical(),
+                ),
+                self.common.clock.now(),
+            ) {
+                if session
+                    .current_constraints()
+                    .map(|constraint| constraint.size != geometry.size)
+                    .unwrap_or(true)
+                {
+                    session.update_constraints(BufferConstraints {
+                        size: geometry.size,
+                        shm: vec![ShmFormat::Argb8888],
+                        dma: None,
+                    });
+                }
+                session.set_cursor_hotspot(offset);
+                session.set_cursor_pos(Some(geometry.loc));
+            }
+        }
+    }
+
+    /// Processes a key event of `seat`, checking it against compositor shortcuts first
+    pub fn keyboard_key_input(
+        &mut self,
+        seat: Seat<State>,
+        keycode: Keycode,
+        state: KeyState,
+        time: u32,
+        needs_key_repetition: bool,
+    ) {
+        let loop_handle = self.common.event_loop_handle.clone();
+        let current_output = seat.active_output();
+        let shortcuts_inhibited = self
+            .common
+            .shell
+            .read()
+            .unwrap()
+            .active_space(&current_output)
+            .focus_stack
+            .get(&seat)
+            .last()
+            .and_then(|window| {
+                window
+                    .wl_surface()
+                    .and_then(|surface| seat.keyboard_shortcuts_inhibitor_for_surface(&surface))
+            })
+            .map(|inhibitor| inhibitor.is_active())
+            .unwrap_or(false)
+            || xwayland_keyboard_grabbed(&seat);
+
+        trace!(?keycode, ?state, "key");
+        self.release_secure_input_grab(&seat);
+
+        let serial = SERIAL_COUNTER.next_serial();
+        let keyboard = seat.get_keyboard().unwrap();
+        let pointer = seat.get_pointer().unwrap();
+        let is_grabbed = keyboard.is_grabbed() || pointer.is_grabbed();
+        let current_focus = keyboard.current_focus();
+        let shell_ref = self.common.shell.clone();
+        let mut shell = shell_ref.write().unwrap();
+        if let Some((action, pattern)) = keyboard
+            .input(
+                self,
+                keycode,
+                state,
+                serial,
+                time,
+                |data, modifiers, handle| {
+                    // The built-in lock screen takes all keys but VT switches
+                    if !(Keysym::XF86_Switch_VT_1.raw()..=Keysym::XF86_Switch_VT_12.raw())
+                        .contains(&handle.modified_sym().raw())
+                        && data.lock_fallback_key_input(
+                            &mut shell,
+                            handle.modified_sym(),
+                            state == KeyState::Pressed,
+                        )
+                    {
+                        return FilterResult::Intercept(None);
+                    }
+
+                    // Latch modifiers before any shortcut sees the key
+                    if data
+                        .common
+                        .config
+                        .cosmic_conf
+                        .keyboard_accessibility
+                        .sticky_keys
+                        && !seat
+                            .user_data()
+                            .get::<SeatStickyKeysState>()
+                            .unwrap()
+                            .lock()
+                            .unwrap()
+                            .key_input(handle.raw_code(), handle.raw_syms(), state)
+                    {
+                        return FilterResult::Intercept(None);
+                    }
+
+                    // Mouse keys, the numpad controls the pointer
+                    if data
+                        .common
+                        .config
+                        .cosmic_conf
+                        .keyboard_accessibility
+                        .mouse_keys
+                    {
+                        if let Some(key) = MouseKey::from_keysym(handle.modified_sym()) {
+                            return FilterResult::Intercept(Some((
+                                Action::Private(PrivateAction::MouseKey(
+                                    key,
+                                    handle.raw_code(),
+                                    state == KeyState::Pressed,
+                                )),
+                                shortcuts::Binding {
+                                    modifiers: cosmic_modifiers_from_smithay(modifiers.clone()),
+                                    key: Some(handle.modified_sym()),
+                                    description: None,
+                                },
+                            )));
+                        }
+                    }
+
+                    // Leave move overview mode, if any modifier was released
+                    if let Some(Trigger::KeyboardMove(action_modifiers)) =
+                        shell.overview_mode().0.active_trigger()
+                    {
+                        if (action_modifiers.ctrl && !modifiers.ctrl)
+                            || (action_modifiers.alt && !modifiers.alt)
+                            || (action_modifiers.logo && !modifiers.logo)
+                            || (action_modifiers.shift && !modifiers.shift)
+                        {
+                            shell.set_overview_mode(None, data.common.event_loop_handle.clone());
+                        }
+                    }
+                    // Leave swap overview mode, if any key was released
+                    if let Some(Trigger::KeyboardSwap(action_pattern, old_descriptor)) =
+                        shell.overview_mode().0.active_trigger()
+                    {
+                        if (action_pattern.modifiers.ctrl && !modifiers.ctrl)
+                            || (action_pattern.modifiers.alt && !modifiers.alt)
+                            || (action_pattern.modifiers.logo && !modifiers.logo)
+                            || (action_pattern.modifiers.shift && !modifiers.shift)
+                            || (action_pattern.key.is_some()
+                                && handle.raw_syms().contains(&action_pattern.key.unwrap())
+                                && state == KeyState::Released)
+                        {
+                            shell.set_overview_mode(None, data.common.event_loop_handle.clone());
+
+                            if let Some(focus) = current_focus {
+                                if let Some(new_descriptor) =
+                                    shell.workspaces.active(&current_output).1.node_desc(focus)
+                                {
+                                    let mut spaces = shell.workspaces.spaces_mut();
+                                    if old_descriptor.handle != new_descriptor.handle {
+                                        let (mut old_w, mut other_w) = spaces
+                                            .partition::<Vec<_>, _>(|w| {
+                                                w.handle == old_descriptor.handle
+                                            });
+                                        if let Some(old_workspace) = old_w.get_mut(0) {
+                                            if let Some(new_workspace) = other_w
+                                                .iter_mut()
+                                                .find(|w| w.handle == new_descriptor.handle)
+                                            {
+                                                if let Some(focus) = TilingLayout::swap_trees(
+                                                    &mut old_workspace.tiling_layer,
+                                                    Some(&mut new_workspace.tiling_layer),
+                                                    &old_descriptor,
+                                                    &new_descriptor,
+                                                ) {
+                                                    let seat = seat.clone();
+                                                    data.common.event_loop_handle.insert_idle(
+                                                        move |state| {
+                                                            Shell::set_focus(
+                                                                state,
+                                                                Some(&focus),
+                                                                &seat,
+                                                                None,
+                                                            );
+                                                        },
+                                                    );
+                                                }
+                                                old_workspace.refresh_focus_stack();
+                                                new_workspace.refresh_focus_stack();
+                                            }
+                                        }
+                                    } else {
+                                        if let Some(workspace) =
+                                            spaces.find(|w| w.handle == new_descriptor.handle)
+                                        {
+                                            if let Some(focus) = TilingLayout::swap_trees(
+                                                &mut workspace.tiling_layer,
+                                                None,
+                                                &old_descriptor,
+                                                &new_descriptor,
+                                            ) {
+                                                std::mem::drop(spaces);
+                                                let seat = seat.clone();
+                                                data.common.event_loop_handle.insert_idle(
+                                                    move |state| {
+                                                        Shell::set_focus(
+                                                            state,
+                                                            Some(&focus),
+                                                            &seat,
+                                                            None,
+                                                        );
+                                                    },
+                                                );
+                                            }
+                                            workspace.refresh_focus_stack();
+                                        }
+                                    }
+                                }
+                            } else {
+                                let new_workspace =
+                                    shell.workspaces.active(&current_output).1.handle;
+                                if new_workspace != old_descriptor.handle {
+                                    let spaces = shell.workspaces.spaces_mut();
+                                    let (mut old_w, mut other_w) =
+                                        spaces.partition::<Vec<_>, _>(|w| {
+                                            w.handle == old_descriptor.handle
+                                        });
+                                    if let Some(old_workspace) = old_w.get_mut(0) {
+                                        if let Some(new_workspace) =
+                                            other_w.iter_mut().find(|w| w.handle == new_workspace)
+                                        {
+                                            if new_workspace.tiling_layer.windows().next().is_none()
+                                            {
+                                                if let Some(focus) = TilingLayout::move_tree(
+                                                    &mut old_workspace.tiling_layer,
+                                                    &mut new_workspace.tiling_layer,
+                                                    &new_workspace.handle,
+                                                    &seat,
+                                                    new_workspace.focus_stack.get(&seat).iter(),
+                                                    old_descriptor.clone(),
+                                                ) {
+                                                    let seat = seat.clone();
+                                                    data.common.event_loop_handle.insert_idle(
+                                                        move |state| {
+                                                            Shell::set_focus(
+                                                                state,
+                                                                Some(&focus),
+                                                                &seat,
+                                                                None,
+                                                            );
+                                                        },
+                                                    );
+                                                }
+                                                old_workspace.refresh_focus_stack();
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // Leave or update resize mode, if modifiers changed or initial key was released
+                    if let Some(action_pattern) = shell.resize_mode().0.active_binding() {
+                        if action_pattern.key.is_some()
+                            && state == KeyState::Released
+                            && handle.raw_syms().contains(&action_pattern.key.unwrap())
+                        {
+                            shell.set_resize_mode(
+                                None,
+                                &data.common.config,
+                                data.common.event_loop_handle.clone(),
+                            );
+                        } else if !cosmic_modifiers_eq_smithay(&action_pattern.modifiers, modifiers)
+                        {
+                            let mut new_pattern = action_pattern.clone();
+                            new_pattern.modifiers =
+                                cosmic_modifiers_from_smithay(modifiers.clone());
+                            let enabled = data.common.config.shortcuts.iter().find_map(
+                                move |(binding, action)| {
+                                    if binding == &new_pattern
+                                        && matches!(action, shortcuts::Action::Resizing(_))
+                                    {
+                                        let shortcuts::Action::Resizing(direction) = action else {
+                                            unreachable!()
+                                        };
+                                        Some((new_pattern.clone(), *direction))
+                                    } else {
+                                        None
+                                    }
+                                },
+                            );
+                            shell.set_resize_mode(
+                                enabled,
+                                &data.common.config,
+                                data.common.event_loop_handle.clone(),
+                            );
+                        }
+                    }
+
+                    // Special case resizing with regards to arrow keys
+                    if let Some(direction) = shell.resize_mode().0.active_direction() {
+                        let resize_edge = match handle.modified_sym() {
+                            Keysym::Left | Keysym::h | Keysym::H => Some(ResizeEdge::LEFT),
+                            Keysym::Down | Keysym::j | Keysym::J => Some(ResizeEdge::BOTTOM),
+                            Keysym::Up | Keysym::k | Keysym::K => Some(ResizeEdge::TOP),
+                            Keysym::Right | Keysym::l | Keysym::L => Some(ResizeEdge::RIGHT),
+                            _ => None,
+                        };
 
-                    let tablet_seat = seat.tablet_seat();
+                        if let Some(mut edge) = resize_edge {
+                            if direction == ResizeDirection::Inwards {
+                                edge.flip_direction();
+                            }
+                            let action = Action::Private(PrivateAction::Resizing(
+                                direction,
+                                edge.into(),
+                                cosmic_keystate_from_smithay(state),
+                            ));
+                            let key_pattern = shortcuts::Binding {
+                                modifiers: cosmic_modifiers_from_smithay(modifiers.clone()),
+                                key: Some(Keysym::new(handle.raw_code().raw())),
+                                description: None,
+                            };
 
-                    let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
-                    let tool = tablet_seat.get_tool(&event.tool());
+                            if state == KeyState::Released {
+                                if let Some(tokens) = seat.supressed_keys().filter(&handle) {
+                                    for token in tokens {
+                                        loop_handle.remove(token);
+                                    }
+                                }
+                            } else {
+                                let token = if needs_key_repetition {
+                                    let seat_clone = seat.clone();
+                                    let action_clone = action.clone();
+                                    let key_pattern_clone = key_pattern.clone();
+                                    let start = Instant::now();
+                                    loop_handle
+                                        .insert_source(
+                                            Timer::from_duration(Duration::from_millis(200)),
+                                            move |current, _, state| {
+                                                let duration =
+                                                    current.duration_since(start).as_millis();
+                                                state.handle_action(
+                                                    action_clone.clone(),
+                                                    &seat_clone,
+                                                    serial,
+                                                    time.overflowing_add(duration as u32).0,
+                                                    key_pattern_clone.clone(),
+                                                    None,
+                                                    true,
+                                                );
+                                                calloop::timer::TimeoutAction::ToDuration(
+                                                    Duration::from_millis(25),
+                                                )
+                                            },
+                                        )
+                                        .ok()
+                                } else {
+                                    None
+                                };
 
-                    if let (Some(tablet), Some(tool)) = (tablet, tool) {
-                        if event.pressure_has_changed() {
-                            tool.pressure(event.pressure());
-                        }
-                        if event.distance_has_changed() {
-                            tool.distance(event.distance());
-                        }
-                        if event.tilt_has_changed() {
-                            tool.tilt(event.tilt());
-                        }
-                        if event.slider_has_changed() {
-                            tool.slider_position(event.slider_position());
-                        }
-                        if event.rotation_has_changed() {
-                            tool.rotation(event.rotation());
-                        }
-                        if event.wheel_has_changed() {
-                            tool.wheel(event.wheel_delta(), event.wheel_delta_discrete());
+                                seat.supressed_keys().add(&handle, token);
+                            }
+                            return FilterResult::Intercept(Some((action, key_pattern)));
                         }
-
-                        tool.motion(
-                            position.as_logical(),
-                            under
-                                .and_then(|(f, loc)| f.wl_surface().map(|s| (s.into_owned(), loc))),
-                            &tablet,
-                            SERIAL_COUNTER.next_serial(),
-                            event.time_msec(),
-                        );
                     }
-                }
-            }
-            InputEvent::TabletToolProximity { event, .. } => {
-                let mut shell = self.common.shell.write().unwrap();
-                if let Some(seat) = shell.seats.for_device(&event.device()).cloned() {
-                    self.common.idle_notifier_state.notify_activity(&seat);
-                    let Some(output) =
-                        mapped_output_for_device(&self.common.config, &shell, &event.device())
-                            .cloned()
-                    else {
-                        return;
-                    };
-                    let geometry = output.geometry();
 
-                    let position = event
-                        .position_transformed(geometry.size.as_logical())
-                        .as_global()
-                        + geometry.loc.to_f64();
+                    let session_locked = shell.session_lock.is_some();
+                    std::mem::drop(shell);
 
-                    let under = State::surface_under(position, &output, &mut *shell)
-                        .map(|(target, pos)| (target, pos.as_logical()));
+                    // cancel grabs, X11 keyboard grabs need the escape shortcut instead
+                    if is_grabbed
+                        && !xwayland_keyboard_grabbed(&seat)
+                        && handle.modified_sym() == Keysym::Escape
+                        && state == KeyState::Pressed
+                        && !modifiers.alt
+                        && !modifiers.ctrl
+                        && !modifiers.logo
+                        && !modifiers.shift
+                    {
+                        seat.supressed_keys().add(&handle, None);
+                        return FilterResult::Intercept(Some((
+                            Action::Private(PrivateAction::Escape),
+                            shortcuts::Binding {
+                                modifiers: shortcuts::Modifiers::default(),
+                                key: Some(Keysym::Escape),
+                                description: None,
+                            },
+                        )));
+                    }
 
-                    std::mem::drop(shell);
+                    // Skip released events for initially surpressed keys
+                    if state == KeyState::Released {
+                        if let Some(tokens) = seat.supressed_keys().filter(&handle) {
+                            for token in tokens {
+                                loop_handle.remove(token);
+                            }
+                            return FilterResult::Intercept(None);
+                        }
+                    }
 
-                    let pointer = seat.get_pointer().unwrap();
-                    pointer.motion(
-                        self,
-                        under.clone(),
-                        &MotionEvent {
-                            location: position.as_logical(),
-                            serial: SERIAL_COUNTER.next_serial(),
-                            time: self.common.clock.now().as_millis(),
-                        },
-                    );
+                    // Locate the pointer, if Ctrl was pressed and released on its own
+                    if data.common.config.cosmic_conf.cursor.locate_pointer
+                        && seat
+                            .user_data()
+                            .get::<SeatLocatePointer>()
+                            .unwrap()
+                            .key_input(
+                                handle.raw_code(),
+                                handle.modified_sym(),
+                                modifiers,
+                                state == KeyState::Pressed,
+                            )
+                    {
+                        return FilterResult::Intercept(Some((
+                            Action::Private(PrivateAction::LocatePointer),
+                            shortcuts::Binding {
+                                modifiers: cosmic_modifiers_from_smithay(modifiers.clone()),
+                                key: None,
+                                description: None,
+                            },
+                        )));
+                    }
 
-                    let tablet_seat = seat.tablet_seat();
+                    // Handle VT switches
+                    if state == KeyState::Pressed
+                        && (Keysym::XF86_Switch_VT_1.raw()..=Keysym::XF86_Switch_VT_12.raw())
+                            .contains(&handle.modified_sym().raw())
+                    {
+                        if let Err(err) = data.backend.kms().switch_vt(
+                            (handle.modified_sym().raw() - Keysym::XF86_Switch_VT_1.raw() + 1)
+                                as i32,
+                        ) {
+                            error!(?err, "Failed switching virtual terminal.");
+                        }
+                        seat.supressed_keys().add(&handle, None);
+                        return FilterResult::Intercept(None);
+                    }
 
-                    let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
-                    let dh = self.common.display_handle.clone();
-                    let tool = tablet_seat.add_tool::<Self>(self, &dh, &event.tool());
+                    // Compositor shortcuts, some of them stay usable while shortcuts are inhibited
+                    if state == KeyState::Pressed {
+                        let pointer_warping = data
+                            .common
+                            .config
+                            .cosmic_conf
+                            .keyboard_accessibility
+                            .pointer_warping;
+                        let compositor_action = data
+                            .common
+                            .config
+                            .compositor_shortcuts
+                            .iter()
+                            .find(|(binding, _)| {
+                                binding
+                                    .key
+                                    .is_some_and(|key| handle.raw_syms().contains(&key))
+                                    && cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers)
+                            })
+                            .filter(|(_, action)| match action {
+                                CompositorAction::RestoreShortcuts => shortcuts_inhibited,
+                                CompositorAction::WarpPointer(_) => pointer_warping,
+                                CompositorAction::InteractiveScreenshot => !is_grabbed,
+                                CompositorAction::WindowSwitcherPrevious => {
+                                    !is_grabbed && !shortcuts_inhibited
+                                }
+                                CompositorAction::ToggleTabletMapping => !shortcuts_inhibited,
+                                _ => true,
+                            })
+                            .map(|(binding, action)| (binding.clone(), *action));
+                        if let Some((binding, action)) = compositor_action {
+                            seat.supressed_keys().add(&handle, None);
+                            return FilterResult::Intercept(Some((
+                                Action::Compositor(action),
+                                binding,
+                            )));
+                        }
+                    }
 
-                    if let Some(tablet) = tablet {
-                        match event.state() {
-                            ProximityState::In => {
-                                if let Some(under) = under.and_then(|(f, loc)| {
-                                    f.wl_surface().map(|s| (s.into_owned(), loc))
-                                }) {
-                                    tool.proximity_in(
-                                        position.as_logical(),
-                                        under,
-                                        &tablet,
-                                        SERIAL_COUNTER.next_serial(),
-                                        event.time_msec(),
-                                    )
+                    // binding modes replace the configured shortcuts while active
+                    if !shortcuts_inhibited && !session_locked {
+                        if state == KeyState::Pressed {
+                            if let Some((action, binding)) = binding_mode_action(
+                                &data.common.config.cosmic_conf.binding_modes,
+                                data.common.binding_mode.as_deref(),
+                                modifiers,
+                                handle.raw_syms(),
+                            ) {
+                                if matches!(action, Action::Shortcut(_)) {
+                                    data.binding_mode_triggered();
                                 }
+                                seat.modifiers_shortcut_queue().clear();
+                                seat.supressed_keys().add(&handle, None);
+                                return FilterResult::Intercept(Some((action, binding)));
                             }
-                            ProximityState::Out => tool.proximity_out(event.time_msec()),
+                        }
+                        if data.common.binding_mode.is_some() {
+                            seat.modifiers_shortcut_queue().clear();
+                            return FilterResult::Forward;
                         }
                     }
-                }
-            }
-            InputEvent::TabletToolTip { event, .. } => {
-                let maybe_seat = self
-                    .common
-                    .shell
-                    .read()
-                    .unwrap()
-                    .seats
-                    .for_device(&event.device())
-                    .cloned();
-                if let Some(seat) = maybe_seat {
-                    self.common.idle_notifier_state.notify_activity(&seat);
-                    if let Some(tool) = seat.tablet_seat().get_tool(&event.tool()) {
-                        match event.tip_state() {
-                            TabletToolTipState::Down => {
-                                tool.tip_down(SERIAL_COUNTER.next_serial(), event.time_msec());
+
+                    // handle the rest of the global shortcuts
+                    let mut clear_queue = true;
+                    if !shortcuts_inhibited {
+                        let modifiers_queue = seat.modifiers_shortcut_queue();
+
+                        for (binding, action) in data.common.config.shortcuts.iter() {
+                            if *action == shortcuts::Action::Disable {
+                                continue;
                             }
-                            TabletToolTipState::Up => {
-                                tool.tip_up(event.time_msec());
+
+                            // is this a released (triggered) modifier-only binding?
+                            if binding.key.is_none()
+                                && state == KeyState::Released
+                                && !cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers)
+                                && modifiers_queue.take(binding)
+                            {
+                                modifiers_queue.clear();
+                                return FilterResult::Intercept(Some((
+                                    Action::Shortcut(action.clone()),
+                                    binding.clone(),
+                                )));
+                            }
+
+                            // could this potentially become a modifier-only binding?
+                            if binding.key.is_none()
+                                && state == KeyState::Pressed
+                                && cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers)
+                            {
+                                modifiers_queue.set(binding.clone());
+                                clear_queue = false;
+                            }
+
+                            // is this a normal binding?
+                            if binding.key.is_some()
+                                && state == KeyState::Pressed
+                                && handle.raw_syms().contains(&binding.key.unwrap())
+                                && cosmic_modifiers_eq_smithay(&binding.modifiers, modifiers)
+                            {
+                                modifiers_queue.clear();
+                                seat.supressed_keys().add(&handle, None);
+                                return FilterResult::Intercept(Some((
+                                    Action::Shortcut(action.clone()),
+                                    binding.clone(),
+                                )));
                             }
                         }
                     }
-                }
-            }
-            InputEvent::TabletToolButton { event, .. } => {
-                let maybe_seat = self
-                    .common
-                    .shell
-                    .read()
-                    .unwrap()
-                    .seats
-                    .for_device(&event.device())
-                    .cloned();
-                if let Some(seat) = maybe_seat {
-                    self.common.idle_notifier_state.notify_activity(&seat);
-                    if let Some(tool) = seat.tablet_seat().get_tool(&event.tool()) {
-                        tool.button(
-                            event.button(),
-                            event.button_state(),
-                            SERIAL_COUNTER.next_serial(),
-                            event.time_msec(),
-                        );
+
+                    // no binding
+                    if clear_queue {
+                        seat.modifiers_shortcut_queue().clear();
                     }
-                }
+                    // keys are passed through to apps
+                    FilterResult::Forward
+                },
+            )
+            .flatten()
+        {
+            if pattern.key.is_none() && state == KeyState::Released {
+                // we still want to send release-events and not have apps stuck on some modifiers.
+                keyboard.input(self, keycode, state, serial, time, |_, _, _| {
+                    FilterResult::<()>::Forward
+                });
             }
-            InputEvent::Special(_) => {}
-            InputEvent::SwitchToggle { event: _ } => {}
+            self.handle_action(action, &seat, serial, time, pattern, None, true)
         }
+        self.apply_sticky_keys(&seat, time);
+    }
+
+    /// Triggers an action bound to a touchpad gesture
+    fn gesture_action(&mut self, action: shortcuts::Action, seat: &Seat<State>, time: u32) {
+        let pattern = shortcuts::Binding {
+            modifiers: cosmic_modifiers_from_smithay(seat.get_keyboard().unwrap().modifier_state()),
+            key: None,
+            description: None,
+        };
+        self.handle_action(
+            Action::Shortcut(action),
+            seat,
+            SERIAL_COUNTER.next_serial(),
+            time,
+            pattern,
+            None,
+            true,
+        )
     }
 
     pub fn handle_action(
@@ -1696,6 +2156,13 @@ impl State {
                 action,
                 Action::Shortcut(shortcuts::Action::Terminate)
                     | Action::Shortcut(shortcuts::Action::Debug)
+                    | Action::Compositor(
+                        CompositorAction::TurnOffOutputs
+                            | CompositorAction::TurnOffActiveOutput
+                            | CompositorAction::WarpPointer(_)
+                    )
+                    | Action::Private(PrivateAction::MouseKey(..))
+                    | Action::Private(PrivateAction::LocatePointer)
             )
         {
             return;
@@ -1705,6 +2172,10 @@ impl State {
             Action::Shortcut(action) => self
                 .handle_shortcut_action(action, seat, serial, time, pattern, direction, propagate),
 
+            Action::Compositor(action) => {
+                self.handle_compositor_action(action, seat, serial, pattern)
+            }
+
             Action::Private(PrivateAction::Escape) => {
                 {
                     let mut shell = self.common.shell.write().unwrap();
@@ -1725,6 +2196,14 @@ impl State {
                 }
             }
 
+            Action::Private(PrivateAction::BindingMode(mode)) => self.set_binding_mode(mode),
+
+            Action::Private(PrivateAction::MouseKey(key, keycode, pressed)) => {
+                self.mouse_key_input(seat, key, keycode, pressed, time)
+            }
+
+            Action::Private(PrivateAction::LocatePointer) => self.locate_pointer(seat),
+
             Action::Private(PrivateAction::Resizing(direction, edge, state)) => {
                 if state == shortcuts::State::Pressed {
                     self.common
@@ -1743,6 +2222,80 @@ impl State {
         }
     }
 
+    fn handle_compositor_action(
+        &mut self,
+        action: CompositorAction,
+        seat: &Seat<State>,
+        serial: Serial,
+        pattern: shortcuts::Binding,
+    ) {
+        match action {
+            CompositorAction::Disable => {}
+            CompositorAction::ZoomIn => self.update_zoom(1.0),
+            CompositorAction::ZoomOut => self.update_zoom(-1.0),
+            CompositorAction::ToggleColorFilter => self.toggle_color_filter(),
+            CompositorAction::TurnOffOutputs => self.set_output_power(None, OutputPowerAction::Off),
+            CompositorAction::TurnOffActiveOutput => {
+                self.set_output_power(Some(&seat.active_output()), OutputPowerAction::Off)
+            }
+            CompositorAction::InteractiveScreenshot => {
+                let pointer = seat.get_pointer().unwrap();
+                if pointer.is_grabbed() {
+                    return;
+                }
+                let start_data = PointerGrabStartData {
+                    focus: None,
+                    button: 0x110,
+                    location: pointer.current_location(),
+                };
+                let grab = ScreenshotGrab::new(start_data, seat);
+                pointer.set_grab(self, grab, serial, Focus::Clear);
+            }
+            CompositorAction::WindowSwitcherPrevious => {
+                self.open_window_switcher(seat, serial, pattern, true)
+            }
+            CompositorAction::ToggleTabletMapping => {
+                self.common.tablet_mapping_toggled = !self.common.tablet_mapping_toggled;
+            }
+            CompositorAction::WarpPointer(warp) => self.warp_pointer(seat, warp),
+            CompositorAction::RestoreShortcuts => {
+                let keyboard = seat.get_keyboard().unwrap();
+                if let Some(surface) = keyboard
+                    .current_focus()
+                    .and_then(|focus| focus.wl_surface().map(Cow::into_owned))
+                {
+                    if let Some(inhibitor) = seat.keyboard_shortcuts_inhibitor_for_surface(&surface)
+                    {
+                        inhibitor.inactivate();
+                    }
+                }
+                release_xwayland_keyboard_grab(self, seat);
+            }
+        }
+    }
+
+    /// Opens the window switcher, kept open while the modifiers of `pattern` are held
+    fn open_window_switcher(
+        &mut self,
+        seat: &Seat<State>,
+        serial: Serial,
+        pattern: shortcuts::Binding,
+        reverse: bool,
+    ) {
+        let keyboard = seat.get_keyboard().unwrap();
+        if keyboard.is_grabbed() {
+            return;
+        }
+        // Shift may be released while cycling
+        let modifiers = shortcuts::Modifiers {
+            shift: false,
+            ..pattern.modifiers
+        };
+        if let Some(grab) = WindowSwitcherGrab::new(self, seat, modifiers, reverse) {
+            keyboard.set_grab(self, grab, serial);
+        }
+    }
+
     pub fn handle_shortcut_action(
         &mut self,
         action: shortcuts::Action,
@@ -2535,6 +3088,11 @@ impl State {
                     .toggle_sticky_current(seat);
             }
 
+            // The window switcher is built into the compositor
+            Action::System(shortcuts::action::System::WindowSwitcher) => {
+                self.open_window_switcher(seat, serial, pattern, false)
+            }
+
             // Gets the configured command for a given system action.
             Action::System(system) => {
                 if let Some(command) = self.common.config.system_actions.get(&system) {
@@ -2634,6 +3192,7 @@ impl State {
             if let Some((surface, geo)) = shell
                 .override_redirect_windows
                 .iter()
+                .rev()
                 .find(|or| {
                     or.is_in_input_region(
                         &(global_pos.as_logical() - X11Surface::geometry(*or).loc.to_f64()),
@@ -2683,6 +3242,7 @@ impl State {
             if let Some((surface, geo)) = shell
                 .override_redirect_windows
                 .iter()
+                .rev()
                 .find(|or| {
                     or.is_in_input_region(
                         &(global_pos.as_logical() - X11Surface::geometry(*or).loc.to_f64()),
@@ -2799,8 +3359,11 @@ fn cursor_sessions_for_output(
         .chain(output.cursor_sessions().into_iter())
 }
 
-// TODO Is it possible to determine mapping for external touchscreen?
-// Support map_to_region like sway?
+/// Output an absolute input device like a touchscreen is attached to.
+///
+/// Uses the configured output first, then the output udev associated with the device,
+/// then the builtin panel and finally the only output, if there is just one.
+// TODO Support map_to_region like sway?
 fn mapped_output_for_device<'a, D: Device + 'static>(
     config: &Config,
     shell: &'a Shell,
@@ -2809,15 +3372,153 @@ fn mapped_output_for_device<'a, D: Device + 'static>(
     let map_to_output = if let Some(device) = <dyn Any>::downcast_ref::<InputDevice>(device) {
         config
             .map_to_output(device)
+            .or_else(|| device.output_name())
             .and_then(|name| shell.outputs().find(|output| output.name() == name))
     } else {
         None
     };
-    map_to_output.or_else(|| shell.builtin_output())
+    map_to_output
+        .or_else(|| shell.builtin_output())
+        .or_else(|| {
+            let mut outputs = shell.outputs();
+            outputs.next().filter(|_| outputs.next().is_none())
+        })
+}
+
+/// Keyboard focus target at `position`, as if it was clicked
+fn keyboard_focus_under(
+    shell: &mut Shell,
+    position: Point<f64, Global>,
+    output: &Output,
+) -> Option<KeyboardFocusTarget> {
+    if let Some(session_lock) = shell.session_lock.as_ref() {
+        return session_lock
+            .surfaces
+            .get(output)
+            .map(|lock| lock.clone().into());
+    }
+
+    let relative_pos = position.to_local(output).as_logical();
+    let layer_under = |layers: &[WlrLayer]| {
+        let map = layer_map_for_output(output);
+        layers
+            .iter()
+            .find_map(|layer| map.layer_under(*layer, relative_pos))
+            .filter(|layer| {
+                let layer_loc = map.layer_geometry(layer).unwrap().loc;
+                layer.can_receive_keyboard_focus()
+                    && layer
+                        .surface_under(relative_pos - layer_loc.to_f64(), WindowSurfaceType::ALL)
+                        .is_some()
+            })
+            .map(|layer| KeyboardFocusTarget::from(layer.clone()))
+    };
+
+    if let Some(window) = shell.active_space(output).get_fullscreen() {
+        let window = window.clone();
+        return layer_under(&[WlrLayer::Overlay]).or(Some(window.into()));
+    }
+
+    layer_under(&[WlrLayer::Overlay, WlrLayer::Top])
+        .or_else(|| shell.element_under(position, output))
+        .or_else(|| layer_under(&[WlrLayer::Bottom, WlrLayer::Background]))
+}
+
+/// Maps the position of an absolute input device into the global space.
+///
+/// Touchscreens and tablets are attached to the panel, so their position
+/// needs to be rotated by the transform of the output.
+fn absolute_position_on_output<B: InputBackend, E: AbsolutePositionEvent<B>>(
+    event: &E,
+    output: &Output,
+) -> Point<f64, Global> {
+    absolute_position_in_area(event, output.geometry(), output.current_transform(), None)
+}
+
+/// Maps the position of an absolute input device into `area`, rotated by `transform`.
+///
+/// With an `aspect_ratio`, only the largest centered part of `area` matching it is used,
+/// so the input isn't stretched.
+fn absolute_position_in_area<B: InputBackend, E: AbsolutePositionEvent<B>>(
+    event: &E,
+    area: Rectangle<i32, Global>,
+    transform: Transform,
+    aspect_ratio: Option<f64>,
+) -> Point<f64, Global> {
+    let panel_size = transform.invert().transform_size(area.size.as_logical());
+    let mut mapped = Rectangle::from_loc_and_size((0, 0), panel_size);
+    if let Some(aspect_ratio) = aspect_ratio {
+        if panel_size.w as f64 / panel_size.h as f64 > aspect_ratio {
+            mapped.size.w = (panel_size.h as f64 * aspect_ratio).round() as i32;
+            mapped.loc.x = (panel_size.w - mapped.size.w) / 2;
+        } else {
+            mapped.size.h = (panel_size.w as f64 / aspect_ratio).round() as i32;
+            mapped.loc.y = (panel_size.h - mapped.size.h) / 2;
+        }
+    }
+    let position = mapped.loc.to_f64() + event.position_transformed(mapped.size);
+    area.loc.to_f64()
+        + transform
+            .invert()
+            .transform_point_in(position, &panel_size.to_f64())
+            .as_global()
+}
+
+/// Maps the position of a tablet tool into the global space, according to the mapping
+/// configured for the tablet. `toggled` switches between the configured mapping and
+/// the focused window.
+fn tablet_position<B: InputBackend, E: AbsolutePositionEvent<B>>(
+    event: &E,
+    config: &Config,
+    shell: &Shell,
+    seat: &Seat<State>,
+    output: &Output,
+    toggled: bool,
+) -> Point<f64, Global>
+where
+    B::Device: 'static,
+{
+    let device = event.device();
+    let Some(device) = <dyn Any>::downcast_ref::<InputDevice>(&device) else {
+        return absolute_position_on_output(event, output);
+    };
+
+    let mapping = match config.tablet_mapping(device) {
+        TabletMapping::FocusedWindow if toggled => TabletMapping::Output,
+        _ if toggled => TabletMapping::FocusedWindow,
+        mapping => mapping,
+    };
+    let (area, transform) = match mapping {
+        TabletMapping::Region {
+            x,
+            y,
+            width,
+            height,
+        } => (
+            Rectangle::from_loc_and_size(
+                output.geometry().loc + Point::from((x, y)),
+                (width, height),
+            ),
+            Transform::Normal,
+        ),
+        TabletMapping::FocusedWindow => match shell.focused_geometry(seat) {
+            Some(geometry) => (geometry, Transform::Normal),
+            None => (output.geometry(), output.current_transform()),
+        },
+        TabletMapping::Output => (output.geometry(), output.current_transform()),
+    };
+    let aspect_ratio = config
+        .keep_aspect_ratio(device)
+        .then(|| device.size())
+        .flatten()
+        .filter(|(width, height)| *width > 0.0 && *height > 0.0)
+        .map(|(width, height)| width / height);
+
+    absolute_position_in_area(event, area, transform, aspect_ratio)
 }
 
 // FIXME: When f64::next_down reaches stable rust, use that instead
-trait NextDown {
+pub(crate) trait NextDown {
     fn next_lower(self) -> Self;
 }
 
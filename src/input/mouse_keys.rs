@@ -0,0 +1,283 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Mouse keys: numpad keys move the pointer and click its buttons.
+//!
+//! The arrow keys of the numpad move the pointer, accelerating while they are held.
+//! `5` clicks, `+` double clicks, `0` presses and `.` releases the selected button
+//! for dragging. `/`, `*` and `-` select the left, middle and right button.
+
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
+};
+use smithay::{
+    backend::input::ButtonState,
+    input::{
+        pointer::{ButtonEvent, MotionEvent},
+        Seat,
+    },
+    utils::{Point, Rectangle, SERIAL_COUNTER},
+};
+use tracing::warn;
+use xkbcommon::xkb::{Keycode, Keysym};
+
+use crate::{input::NextDown, shell::Shell, state::State, utils::prelude::*};
+
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+
+const UPDATE_INTERVAL: Duration = Duration::from_millis(16);
+/// Pointer speed in logical pixels per second, when starting to move
+const MIN_SPEED: f64 = 60.0;
+const MAX_SPEED: f64 = 1200.0;
+/// Time it takes to accelerate to [`MAX_SPEED`]
+const ACCELERATION_TIME: Duration = Duration::from_millis(1500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseKey {
+    Move(i32, i32),
+    Click,
+    DoubleClick,
+    /// Holds the selected button down, until [`MouseKey::Release`]
+    Press,
+    Release,
+    SelectButton(u32),
+}
+
+impl MouseKey {
+    pub fn from_keysym(sym: Keysym) -> Option<MouseKey> {
+        Some(match sym {
+            Keysym::KP_Home | Keysym::KP_7 => MouseKey::Move(-1, -1),
+            Keysym::KP_Up | Keysym::KP_8 => MouseKey::Move(0, -1),
+            Keysym::KP_Prior | Keysym::KP_9 => MouseKey::Move(1, -1),
+            Keysym::KP_Left | Keysym::KP_4 => MouseKey::Move(-1, 0),
+            Keysym::KP_Right | Keysym::KP_6 => MouseKey::Move(1, 0),
+            Keysym::KP_End | Keysym::KP_1 => MouseKey::Move(-1, 1),
+            Keysym::KP_Down | Keysym::KP_2 => MouseKey::Move(0, 1),
+            Keysym::KP_Next | Keysym::KP_3 => MouseKey::Move(1, 1),
+            Keysym::KP_Begin | Keysym::KP_5 => MouseKey::Click,
+            Keysym::KP_Add => MouseKey::DoubleClick,
+            Keysym::KP_Insert | Keysym::KP_0 => MouseKey::Press,
+            Keysym::KP_Delete | Keysym::KP_Decimal => MouseKey::Release,
+            Keysym::KP_Divide => MouseKey::SelectButton(BTN_LEFT),
+            Keysym::KP_Multiply => MouseKey::SelectButton(BTN_MIDDLE),
+            Keysym::KP_Subtract => MouseKey::SelectButton(BTN_RIGHT),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct MouseKeys(RefCell<MouseKeysInner>);
+
+struct MouseKeysInner {
+    /// Held keys moving the pointer and their direction
+    directions: Vec<(Keycode, (i32, i32))>,
+    timer: Option<RegistrationToken>,
+    /// When the pointer started moving and when it was last moved
+    started: Instant,
+    last_update: Instant,
+    button: u32,
+    /// Button held down by [`MouseKey::Press`]
+    pressed: Option<u32>,
+}
+
+impl Default for MouseKeysInner {
+    fn default() -> Self {
+        MouseKeysInner {
+            directions: Vec::new(),
+            timer: None,
+            started: Instant::now(),
+            last_update: Instant::now(),
+            button: BTN_LEFT,
+            pressed: None,
+        }
+    }
+}
+
+impl State {
+    /// Handles a key of the numpad, while mouse keys are enabled
+    pub fn mouse_key_input(
+        &mut self,
+        seat: &Seat<State>,
+        key: MouseKey,
+        keycode: Keycode,
+        pressed: bool,
+        time: u32,
+    ) {
+        let mouse_keys = seat.user_data().get::<MouseKeys>().unwrap();
+        let button = mouse_keys.0.borrow().button;
+
+        match key {
+            MouseKey::Move(dx, dy) => {
+                let mut inner = mouse_keys.0.borrow_mut();
+                inner.directions.retain(|(code, _)| *code != keycode);
+                if !pressed {
+                    return;
+                }
+                inner.directions.push((keycode, (dx, dy)));
+                if inner.timer.is_none() {
+                    let now = Instant::now();
+                    inner.started = now;
+                    inner.last_update = now - UPDATE_INTERVAL;
+                    let seat = seat.clone();
+                    match self.common.event_loop_handle.insert_source(
+                        Timer::immediate(),
+                        move |_, _, state| {
+                            if state.mouse_keys_motion(&seat) {
+                                TimeoutAction::ToDuration(UPDATE_INTERVAL)
+                            } else {
+                                TimeoutAction::Drop
+                            }
+                        },
+                    ) {
+                        Ok(token) => inner.timer = Some(token),
+                        Err(err) => warn!(?err, "Failed to start mouse keys timer."),
+                    }
+                }
+            }
+            _ if !pressed => {}
+            MouseKey::Click => {
+                self.mouse_keys_button(seat, button, ButtonState::Pressed, time);
+                self.mouse_keys_button(seat, button, ButtonState::Released, time);
+            }
+            MouseKey::DoubleClick => {
+                for _ in 0..2 {
+                    self.mouse_keys_button(seat, button, ButtonState::Pressed, time);
+                    self.mouse_keys_button(seat, button, ButtonState::Released, time);
+                }
+            }
+            MouseKey::Press => {
+                let mut inner = mouse_keys.0.borrow_mut();
+                if inner.pressed.is_none() {
+                    inner.pressed = Some(button);
+                    std::mem::drop(inner);
+                    self.mouse_keys_button(seat, button, ButtonState::Pressed, time);
+                }
+            }
+            MouseKey::Release => {
+                let pressed = mouse_keys.0.borrow_mut().pressed.take();
+                if let Some(button) = pressed {
+                    self.mouse_keys_button(seat, button, ButtonState::Released, time);
+                }
+            }
+            MouseKey::SelectButton(button) => mouse_keys.0.borrow_mut().button = button,
+        }
+    }
+
+    /// Moves the pointer by the held mouse keys, returns `false` once none are held anymore
+    fn mouse_keys_motion(&mut self, seat: &Seat<State>) -> bool {
+        let delta = {
+            let mut inner = seat.user_data().get::<MouseKeys>().unwrap().0.borrow_mut();
+            if inner.directions.is_empty() {
+                inner.timer = None;
+                return false;
+            }
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(inner.started).as_secs_f64();
+            let dt = now.duration_since(inner.last_update).as_secs_f64();
+            inner.last_update = now;
+
+            let progress = (elapsed / ACCELERATION_TIME.as_secs_f64()).min(1.0);
+            let speed = MIN_SPEED + (MAX_SPEED - MIN_SPEED) * progress * progress;
+            let (dx, dy) = inner
+                .directions
+                .iter()
+                .fold((0, 0), |(x, y), (_, (dx, dy))| (x + dx, y + dy));
+            // opposing keys cancel each other out
+            let (dx, dy) = (dx.signum() as f64, dy.signum() as f64);
+            let len = (dx * dx + dy * dy).sqrt();
+            if len == 0.0 {
+                return true;
+            }
+            Point::<f64, Global>::from((dx / len * speed * dt, dy / len * speed * dt))
+        };
+
+        let Some(ptr) = seat.get_pointer() else {
+            return false;
+        };
+        self.common.idle_notifier_state.notify_activity(seat);
+        self.notify_activity();
+
+        let mut shell = self.common.shell.write().unwrap();
+        let mut position = ptr.current_location().as_global() + delta;
+        let output = shell
+            .outputs()
+            .find(|output| output.geometry().to_f64().contains(position))
+            .cloned()
+            .unwrap_or_else(|| seat.active_output());
+        let output_geometry: Rectangle<f64, Global> = output.geometry().to_f64();
+        position.x = position.x.clamp(
+            output_geometry.loc.x,
+            (output_geometry.loc.x + output_geometry.size.w).next_lower(),
+        );
+        position.y = position.y.clamp(
+            output_geometry.loc.y,
+            (output_geometry.loc.y + output_geometry.size.h).next_lower(),
+        );
+
+        let under = State::surface_under(position, &output, &mut *shell)
+            .map(|(target, pos)| (target, pos.as_logical()));
+        std::mem::drop(shell);
+
+        if output != seat.active_output() {
+            seat.set_active_output(&output);
+        }
+        ptr.motion(
+            self,
+            under,
+            &MotionEvent {
+                location: position.as_logical(),
+                serial: SERIAL_COUNTER.next_serial(),
+                time: self.common.clock.now().as_millis(),
+            },
+        );
+        ptr.frame(self);
+        true
+    }
+
+    fn mouse_keys_button(
+        &mut self,
+        seat: &Seat<State>,
+        button: u32,
+        state: ButtonState,
+        time: u32,
+    ) {
+        let Some(ptr) = seat.get_pointer() else {
+            return;
+        };
+
+        // clicking focuses the window under the pointer
+        if state == ButtonState::Pressed && !ptr.is_grabbed() {
+            let output = seat.active_output();
+            let position = ptr.current_location().as_global();
+            let target = self
+                .common
+                .shell
+                .write()
+                .unwrap()
+                .element_under(position, &output);
+            if let Some(target) = target {
+                Shell::set_focus(self, Some(&target), seat, None);
+            }
+        }
+
+        ptr.button(
+            self,
+            &ButtonEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                button,
+                state,
+            },
+        );
+        ptr.frame(self);
+    }
+}
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Pointer warping: the `WarpPointer` compositor shortcuts move the pointer to a region of the screen.
+//!
+//! By default Super+Alt with the keys of the numpad splits the active output into a 3×3 grid
+//! and warps to the center of the matching cell, `0` warps to the center of the focused window.
+//! The number keys warp to the center of the outputs, numbered from left to right.
+
+use cosmic_comp_config::PointerWarp;
+use smithay::{
+    input::{pointer::MotionEvent, Seat},
+    utils::{Point, SERIAL_COUNTER},
+};
+
+use crate::{state::State, utils::prelude::*};
+
+impl State {
+    /// Moves the pointer of `seat` to the center of the region selected by `warp`
+    pub fn warp_pointer(&mut self, seat: &Seat<State>, warp: PointerWarp) {
+        let Some(ptr) = seat.get_pointer() else {
+            return;
+        };
+
+        let mut shell = self.common.shell.write().unwrap();
+        let region = match warp {
+            PointerWarp::Window => shell.focused_geometry(seat),
+            PointerWarp::Output(n) => {
+                let mut geometries = shell
+                    .outputs()
+                    .map(|output| output.geometry())
+                    .collect::<Vec<_>>();
+                geometries.sort_by_key(|geo| (geo.loc.x, geo.loc.y));
+                geometries.get(n).copied()
+            }
+            PointerWarp::Cell(column, row) => {
+                let mut geo = seat.active_output().geometry();
+                geo.size.w /= 3;
+                geo.size.h /= 3;
+                geo.loc.x += geo.size.w * column as i32;
+                geo.loc.y += geo.size.h * row as i32;
+                Some(geo)
+            }
+        };
+        let Some(region) = region else {
+            return;
+        };
+
+        let position = region.loc.to_f64()
+            + Point::<f64, Global>::from((region.size.w as f64 / 2.0, region.size.h as f64 / 2.0));
+        // the center of a window may be outside of its output
+        let Some(output) = shell
+            .outputs()
+            .find(|output| output.geometry().to_f64().contains(position))
+            .cloned()
+        else {
+            return;
+        };
+
+        let under = State::surface_under(position, &output, &mut *shell)
+            .map(|(target, pos)| (target, pos.as_logical()));
+        std::mem::drop(shell);
+
+        if output != seat.active_output() {
+            seat.set_active_output(&output);
+        }
+        ptr.motion(
+            self,
+            under,
+            &MotionEvent {
+                location: position.as_logical(),
+                serial: SERIAL_COUNTER.next_serial(),
+                time: self.common.clock.now().as_millis(),
+            },
+        );
+        ptr.frame(self);
+    }
+}
@@ -0,0 +1,489 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Sticky keys: modifiers pressed and released on their own apply to the next key,
+//! pressed twice they stay active until pressed once more.
+//!
+//! Active modifiers are kept pressed by synthetic key events, so they work with every client
+//! and with compositor shortcuts alike.
+
+use std::sync::Mutex;
+
+use cosmic::{
+    iced::widget::{container, row},
+    iced_core::{Alignment, Background, Border, Color, Length},
+    theme,
+    widget::text,
+    Apply,
+};
+use smithay::{
+    backend::{
+        input::KeyState,
+        renderer::{
+            element::{AsRenderElements, RenderElement},
+            ImportAll, ImportMem, Renderer,
+        },
+    },
+    desktop::space::SpaceElement,
+    input::{keyboard::FilterResult, Seat},
+    output::Output,
+    utils::{Logical, Point, Rectangle, Size, SERIAL_COUNTER},
+};
+use xkbcommon::xkb::{Keycode, Keysym};
+
+use crate::{
+    backend::render::element::{AsGlowRenderer, CosmicElement},
+    shell::CosmicMappedRenderElement,
+    state::State,
+    utils::{
+        iced::{IcedElement, Program},
+        prelude::*,
+    },
+};
+
+const LABEL_WIDTH: i32 = 72;
+const LABEL_HEIGHT: i32 = 32;
+const SPACING: i32 = 8;
+const PADDING: i32 = 8;
+/// Distance of the indicator to the bottom of the output
+const MARGIN: i32 = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModifierState {
+    /// Applies to the next key
+    Latched,
+    /// Applies until the modifier is pressed again
+    Locked,
+}
+
+#[derive(Default)]
+pub struct StickyKeysState {
+    /// Modifier pressed without any other key so far
+    pending: Option<Keycode>,
+    modifiers: Vec<(Keycode, Keysym, ModifierState)>,
+    /// Key pressed while modifiers were latched, releasing it releases them
+    latched_key: Option<Keycode>,
+    /// Synthetic key events to send after the current one
+    queued: Vec<(Keycode, KeyState)>,
+    changed: bool,
+    indicator: Option<(Output, StickyKeysIndicator)>,
+}
+pub type SeatStickyKeysState = Mutex<StickyKeysState>;
+
+fn is_sticky_modifier(sym: Keysym) -> bool {
+    matches!(
+        sym,
+        Keysym::Shift_L
+            | Keysym::Shift_R
+            | Keysym::Control_L
+            | Keysym::Control_R
+            | Keysym::Alt_L
+            | Keysym::Alt_R
+            | Keysym::Meta_L
+            | Keysym::Meta_R
+            | Keysym::Super_L
+            | Keysym::Super_R
+            | Keysym::Hyper_L
+            | Keysym::Hyper_R
+            | Keysym::ISO_Level3_Shift
+    )
+}
+
+fn modifier_label(sym: Keysym) -> &'static str {
+    match sym {
+        Keysym::Shift_L | Keysym::Shift_R => "Shift",
+        Keysym::Control_L | Keysym::Control_R => "Ctrl",
+        Keysym::ISO_Level3_Shift => "AltGr",
+        Keysym::Super_L | Keysym::Super_R | Keysym::Hyper_L | Keysym::Hyper_R => "Super",
+        _ => "Alt",
+    }
+}
+
+impl StickyKeysState {
+    /// Tracks a key event, returns `false` if it shouldn't be forwarded to clients
+    pub fn key_input(&mut self, code: Keycode, syms: &[Keysym], state: KeyState) -> bool {
+        let Some(sym) = syms.iter().copied().find(|sym| is_sticky_modifier(*sym)) else {
+            match state {
+                KeyState::Pressed => {
+                    self.pending = None;
+                    if self.latched_key.is_none()
+                        && self
+                            .modifiers
+                            .iter()
+                            .any(|(_, _, state)| *state == ModifierState::Latched)
+                    {
+                        self.latched_key = Some(code);
+                    }
+                }
+                KeyState::Released if self.latched_key == Some(code) => {
+                    self.latched_key = None;
+                    let (latched, locked) = std::mem::take(&mut self.modifiers)
+                        .into_iter()
+                        .partition::<Vec<_>, _>(|(_, _, state)| *state == ModifierState::Latched);
+                    self.queued.extend(
+                        latched
+                            .into_iter()
+                            .map(|(code, _, _)| (code, KeyState::Released)),
+                    );
+                    self.modifiers = locked;
+                    self.changed = true;
+                }
+                KeyState::Released => {}
+            }
+            return true;
+        };
+
+        match state {
+            KeyState::Pressed => {
+                self.pending = Some(code);
+                true
+            }
+            // used together with another key
+            KeyState::Released if self.pending != Some(code) => true,
+            KeyState::Released => {
+                self.pending = None;
+                self.changed = true;
+                let idx = self.modifiers.iter().position(|(c, _, _)| *c == code);
+                match idx.map(|idx| (idx, self.modifiers[idx].2)) {
+                    Some((idx, ModifierState::Locked)) => {
+                        self.modifiers.remove(idx);
+                        true
+                    }
+                    Some((idx, ModifierState::Latched)) => {
+                        self.modifiers[idx].2 = ModifierState::Locked;
+                        // the release also cleared the synthetic press
+                        self.queued.push((code, KeyState::Pressed));
+                        false
+                    }
+                    None => {
+                        self.modifiers.push((code, sym, ModifierState::Latched));
+                        self.queued.push((code, KeyState::Pressed));
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    /// Releases all latched and locked modifiers
+    pub fn release_all(&mut self) {
+        self.pending = None;
+        self.latched_key = None;
+        for (code, _, _) in self.modifiers.drain(..) {
+            self.queued.push((code, KeyState::Released));
+            self.changed = true;
+        }
+    }
+
+    pub fn render<R>(&self, renderer: &mut R, output: &Output) -> Vec<CosmicElement<R>>
+    where
+        R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+    {
+        let Some((indicator_output, indicator)) = self.indicator.as_ref() else {
+            return Vec::new();
+        };
+        if indicator_output != output {
+            return Vec::new();
+        }
+
+        let scale = output.current_scale().fractional_scale();
+        let output_size = output.geometry().size;
+        let size = indicator.with_program(|p| p.size());
+        let location = Point::<i32, Logical>::from((
+            (output_size.w - size.w) / 2,
+            output_size.h - size.h - MARGIN,
+        ));
+
+        indicator
+            .render_elements::<CosmicMappedRenderElement<R>>(
+                renderer,
+                location.to_physical_precise_round(scale),
+                scale.into(),
+                1.0,
+            )
+            .into_iter()
+            .map(CosmicElement::from)
+            .collect()
+    }
+}
+
+pub type StickyKeysIndicator = IcedElement<StickyKeysIndicatorInternal>;
+
+pub struct StickyKeysIndicatorInternal {
+    modifiers: Vec<(&'static str, bool)>,
+}
+
+impl StickyKeysIndicatorInternal {
+    fn size(&self) -> Size<i32, Logical> {
+        let count = self.modifiers.len() as i32;
+        Size::from((
+            count * (LABEL_WIDTH + SPACING) - SPACING + 2 * PADDING,
+            LABEL_HEIGHT + 2 * PADDING,
+        ))
+    }
+}
+
+impl Program for StickyKeysIndicatorInternal {
+    type Message = ();
+
+    fn view(&self) -> cosmic::Element<'_, Self::Message> {
+        row(self
+            .modifiers
+            .iter()
+            .map(|(label, locked)| {
+                let locked = *locked;
+                text(*label)
+                    .font(cosmic::font::FONT_SEMIBOLD)
+                    .size(14)
+                    .apply(container)
+                    .center_x()
+                    .center_y()
+                    .width(Length::Fixed(LABEL_WIDTH as f32))
+                    .height(Length::Fixed(LABEL_HEIGHT as f32))
+                    .style(theme::Container::custom(move |theme| {
+                        // locked modifiers are highlighted
+                        let (background, text_color) = if locked {
+                            (theme.cosmic().accent_color(), theme.cosmic().accent.on)
+                        } else {
+                            (
+                                theme.cosmic().primary.component.base,
+                                theme.cosmic().primary.component.on,
+                            )
+                        };
+                        container::Appearance {
+                            icon_color: None,
+                            text_color: Some(Color::from(text_color)),
+                            background: Some(Background::Color(background.into())),
+                            border: Border {
+                                radius: 8.0.into(),
+                                width: 0.0,
+                                color: Color::TRANSPARENT,
+                            },
+                            shadow: Default::default(),
+                        }
+                    }))
+                    .into()
+            })
+            .collect())
+        .spacing(SPACING as u16)
+        .align_items(Alignment::Center)
+        .apply(container)
+        .padding(PADDING as u16)
+        .style(theme::Container::custom(|theme| container::Appearance {
+            icon_color: None,
+            text_color: None,
+            background: Some(Background::Color(theme.cosmic().background.base.into())),
+            border: Border {
+                radius: 16.0.into(),
+                width: 0.0,
+                color: Color::TRANSPARENT,
+            },
+            shadow: Default::default(),
+        }))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
+    }
+}
+
+impl State {
+    /// Sends the synthetic key events queued by sticky keys and updates their indicator
+    pub fn apply_sticky_keys(&mut self, seat: &Seat<State>, time: u32) {
+        let (queued, changed) = {
+            let mut sticky_keys = seat
+                .user_data()
+                .get::<SeatStickyKeysState>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            let changed = std::mem::take(&mut sticky_keys.changed);
+            (std::mem::take(&mut sticky_keys.queued), changed)
+        };
+
+        let keyboard = seat.get_keyboard().unwrap();
+        for (code, state) in queued {
+            keyboard.input(
+                self,
+                code,
+                state,
+                SERIAL_COUNTER.next_serial(),
+                time,
+                |_, _, _| FilterResult::<()>::Forward,
+            );
+        }
+
+        if changed {
+            self.update_sticky_keys_indicator(seat);
+        }
+    }
+
+    /// Releases the modifiers held by sticky keys on every seat
+    pub fn release_sticky_keys(&mut self) {
+        let seats = self
+            .common
+            .shell
+            .read()
+            .unwrap()
+            .seats
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for seat in &seats {
+            seat.user_data()
+                .get::<SeatStickyKeysState>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .release_all();
+            let time = self.common.clock.now().as_millis();
+            self.apply_sticky_keys(seat, time);
+        }
+    }
+
+    fn update_sticky_keys_indicator(&mut self, seat: &Seat<State>) {
+        let output = seat.active_output();
+        let theme = self.common.shell.read().unwrap().theme().clone();
+
+        let previous = {
+            let mut sticky_keys = seat
+                .user_data()
+                .get::<SeatStickyKeysState>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            let modifiers = sticky_keys
+                .modifiers
+                .iter()
+                .map(|(_, sym, state)| (modifier_label(*sym), *state == ModifierState::Locked))
+                .collect::<Vec<_>>();
+
+            let indicator = (!modifiers.is_empty()).then(|| {
+                let program = StickyKeysIndicatorInternal { modifiers };
+                let size = program.size();
+                let element = StickyKeysIndicator::new(
+                    program,
+                    size,
+                    self.common.event_loop_handle.clone(),
+                    theme,
+                );
+                element.output_enter(&output, Rectangle::default() /* unused */);
+                (output.clone(), element)
+            });
+            std::mem::replace(&mut sticky_keys.indicator, indicator)
+        };
+
+        if let Some((previous, _)) = previous {
+            self.backend.schedule_render(&previous);
+        }
+        self.backend.schedule_render(&output);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SHIFT: Keycode = Keycode::new(50);
+    const CTRL: Keycode = Keycode::new(37);
+    const A: Keycode = Keycode::new(38);
+
+    fn tap(state: &mut StickyKeysState, code: Keycode, sym: Keysym) -> (bool, bool) {
+        (
+            state.key_input(code, &[sym], KeyState::Pressed),
+            state.key_input(code, &[sym], KeyState::Released),
+        )
+    }
+
+    fn modifiers(state: &StickyKeysState) -> Vec<(Keycode, ModifierState)> {
+        state
+            .modifiers
+            .iter()
+            .map(|(code, _, state)| (*code, *state))
+            .collect()
+    }
+
+    #[test]
+    fn test_latch_applies_to_next_key() {
+        let mut state = StickyKeysState::default();
+        assert_eq!(tap(&mut state, SHIFT, Keysym::Shift_L), (true, false));
+        assert_eq!(modifiers(&state), vec![(SHIFT, ModifierState::Latched)]);
+        assert_eq!(
+            std::mem::take(&mut state.queued),
+            vec![(SHIFT, KeyState::Pressed)]
+        );
+
+        assert!(state.key_input(A, &[Keysym::a], KeyState::Pressed));
+        assert!(state.queued.is_empty());
+        assert!(state.key_input(A, &[Keysym::a], KeyState::Released));
+        assert!(modifiers(&state).is_empty());
+        assert_eq!(state.queued, vec![(SHIFT, KeyState::Released)]);
+    }
+
+    #[test]
+    fn test_latched_modifiers_combine() {
+        let mut state = StickyKeysState::default();
+        tap(&mut state, SHIFT, Keysym::Shift_L);
+        tap(&mut state, CTRL, Keysym::Control_L);
+        assert_eq!(
+            modifiers(&state),
+            vec![
+                (SHIFT, ModifierState::Latched),
+                (CTRL, ModifierState::Latched)
+            ]
+        );
+        state.queued.clear();
+
+        tap(&mut state, A, Keysym::a);
+        assert!(modifiers(&state).is_empty());
+        assert_eq!(
+            state.queued,
+            vec![(SHIFT, KeyState::Released), (CTRL, KeyState::Released)]
+        );
+    }
+
+    #[test]
+    fn test_double_tap_locks() {
+        let mut state = StickyKeysState::default();
+        tap(&mut state, SHIFT, Keysym::Shift_L);
+        assert_eq!(tap(&mut state, SHIFT, Keysym::Shift_L), (true, false));
+        assert_eq!(modifiers(&state), vec![(SHIFT, ModifierState::Locked)]);
+        state.queued.clear();
+
+        // stays active for more than one key
+        tap(&mut state, A, Keysym::a);
+        tap(&mut state, A, Keysym::a);
+        assert_eq!(modifiers(&state), vec![(SHIFT, ModifierState::Locked)]);
+        assert!(state.queued.is_empty());
+
+        // the real release of the third tap releases it
+        assert_eq!(tap(&mut state, SHIFT, Keysym::Shift_L), (true, true));
+        assert!(modifiers(&state).is_empty());
+    }
+
+    #[test]
+    fn test_modifier_held_with_key() {
+        let mut state = StickyKeysState::default();
+        assert!(state.key_input(SHIFT, &[Keysym::Shift_L], KeyState::Pressed));
+        tap(&mut state, A, Keysym::a);
+        assert!(state.key_input(SHIFT, &[Keysym::Shift_L], KeyState::Released));
+        assert!(modifiers(&state).is_empty());
+        assert!(state.queued.is_empty());
+    }
+
+    #[test]
+    fn test_release_all() {
+        let mut state = StickyKeysState::default();
+        tap(&mut state, SHIFT, Keysym::Shift_L);
+        tap(&mut state, SHIFT, Keysym::Shift_L);
+        tap(&mut state, CTRL, Keysym::Control_L);
+        state.queued.clear();
+
+        state.release_all();
+        assert!(modifiers(&state).is_empty());
+        assert_eq!(
+            state.queued,
+            vec![(SHIFT, KeyState::Released), (CTRL, KeyState::Released)]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Events for subscribed IPC clients.
+//!
+//! Window, focus and binding mode events are queued where those change.
+//! Titles are compared, when a window commits. The workspace and output summaries
+//! are small and changed from many places, so they are compared against
+//! the last ones sent instead, as long as anyone is subscribed to them.
+//! Queued events are written after every dispatch of the event loop.
+
+use super::{protocol::*, IpcState};
+use crate::{
+    shell::{focus::target::KeyboardFocusTarget, CosmicMapped, CosmicSurface, Shell},
+    state::State,
+};
+use std::collections::HashMap;
+
+/// State last sent to subscribers
+#[derive(Debug, Default)]
+pub(super) struct EventState {
+    pending: Vec<Event>,
+    /// Titles of the windows known to subscribers
+    titles: HashMap<u64, String>,
+    focused: Option<u64>,
+    workspaces: Option<Vec<WorkspaceInfo>>,
+    outputs: Option<Vec<OutputInfo>>,
+}
+
+impl IpcState {
+    fn subscribed(&self, kind: EventKind) -> bool {
+        self.clients
+            .values()
+            .any(|client| client.events.contains(&kind))
+    }
+
+    fn emit(&mut self, event: Event) {
+        if self.subscribed(event.kind()) {
+            self.events.pending.push(event);
+        }
+    }
+
+    /// Needs to be called after `window` got mapped
+    pub fn window_opened(&mut self, shell: &Shell, window: &CosmicSurface) {
+        if !self.subscribed(EventKind::Window) {
+            return;
+        }
+        let Some(mapped) = shell.element_for_surface(window) else {
+            return;
+        };
+        let (geometry, floating) = shell
+            .space_for(mapped)
+            .map(|workspace| {
+                (
+                    workspace
+                        .element_geometry(mapped)
+                        .map(|geo| geo.as_logical())
+                        .unwrap_or_default(),
+                    workspace.floating_layer.mapped().any(|m| m == mapped),
+                )
+            })
+            .unwrap_or_default();
+        let window = super::window_info(window, geometry, floating);
+        self.events.titles.insert(window.id, window.title.clone());
+        self.emit(Event::WindowOpened { window });
+    }
+
+    /// Needs to be called before the window of `surface` gets unmapped
+    pub fn window_closed<S>(&mut self, shell: &Shell, surface: &S)
+    where
+        CosmicSurface: PartialEq<S>,
+    {
+        let Some(id) = shell.element_for_surface(surface).and_then(|mapped| {
+            mapped
+                .windows()
+                .find(|(window, _)| window == surface)
+                .map(|(window, _)| window.id())
+        }) else {
+            return;
+        };
+        self.events.titles.remove(&id);
+        self.emit(Event::WindowClosed { id });
+    }
+
+    pub fn window_committed(&mut self, mapped: &CosmicMapped) {
+        if !self.subscribed(EventKind::Window) {
+            return;
+        }
+        for (window, _) in mapped.windows() {
+            let title = window.title();
+            match self.events.titles.get_mut(&window.id()) {
+                Some(known) if *known != title => {
+                    *known = title.clone();
+                    self.emit(Event::WindowTitleChanged {
+                        id: window.id(),
+                        title,
+                    });
+                }
+                Some(_) => {}
+                // opened before anyone subscribed
+                None => {
+                    self.events.titles.insert(window.id(), title);
+                }
+            }
+        }
+    }
+
+    pub fn focus_changed(&mut self, target: Option<&KeyboardFocusTarget>) {
+        let focused = match target {
+            Some(KeyboardFocusTarget::Element(mapped)) => Some(mapped.active_window().id()),
+            Some(KeyboardFocusTarget::Fullscreen(surface)) => Some(surface.id()),
+            // popups belong to the window focused before
+            Some(KeyboardFocusTarget::Popup(_)) => return,
+            _ => None,
+        };
+        if self.events.focused != focused {
+            self.events.focused = focused;
+            self.emit(Event::WindowFocused { id: focused });
+        }
+    }
+
+    pub fn binding_mode_changed(&mut self, mode: Option<String>) {
+        self.emit(Event::BindingModeChanged { mode });
+    }
+}
+
+impl State {
+    pub(super) fn ipc_subscribe(&mut self, client: u64, events: Vec<EventKind>) -> Response {
+        match self
+            .common
+            .ipc
+            .as_mut()
+            .and_then(|ipc| ipc.clients.get_mut(&client))
+        {
+            Some(client) => {
+                client.events.extend(events);
+                Response::Success
+            }
+            None => Response::Error(String::from("The IPC client is gone")),
+        }
+    }
+
+    /// Sends the events queued since the last call to subscribed IPC clients
+    pub fn send_ipc_events(&mut self) {
+        let Some(ipc) = self.common.ipc.as_ref() else {
+            return;
+        };
+        let workspaces = ipc
+            .subscribed(EventKind::Workspace)
+            .then(|| self.ipc_workspaces());
+        let outputs = ipc
+            .subscribed(EventKind::Output)
+            .then(|| self.ipc_outputs());
+
+        let ipc = self.common.ipc.as_mut().unwrap();
+        // the first summary after subscribing is just the baseline
+        match (ipc.events.workspaces.take(), workspaces) {
+            (Some(old), Some(new)) if old != new => {
+                ipc.events.workspaces = Some(new.clone());
+                ipc.emit(Event::WorkspacesChanged { workspaces: new });
+            }
+            (_, new) => ipc.events.workspaces = new,
+        }
+        match (ipc.events.outputs.take(), outputs) {
+            (Some(old), Some(new)) if old != new => {
+                ipc.events.outputs = Some(new.clone());
+                ipc.emit(Event::OutputsChanged { outputs: new });
+            }
+            (_, new) => ipc.events.outputs = new,
+        }
+        if !ipc.subscribed(EventKind::Window) {
+            ipc.events.titles.clear();
+        }
+
+        if ipc.events.pending.is_empty() {
+            return;
+        }
+        let messages = ipc
+            .events
+            .pending
+            .drain(..)
+            .map(|event| {
+                let mut message = serde_json::to_vec(&event).unwrap();
+                message.push(b'\n');
+                (event.kind(), message)
+            })
+            .collect::<Vec<_>>();
+
+        let mut gone = Vec::new();
+        for (id, client) in ipc.clients.iter_mut() {
+            if messages
+                .iter()
+                .filter(|(kind, _)| client.events.contains(kind))
+                .any(|(_, message)| !client.send(message))
+            {
+                gone.push(*id);
+            }
+        }
+        for id in gone {
+            let client = ipc.clients.remove(&id).unwrap();
+            self.common.event_loop_handle.remove(client.token);
+        }
+    }
+}
@@ -0,0 +1,760 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! IPC socket for scripts and tools like `cosmic-comp-msg`, see [`protocol`].
+
+use crate::{
+    config::{Action, OutputState},
+    shell::{layout::tiling::Data, CosmicSurface, WorkspaceDelta},
+    state::{BackendData, State},
+    utils::prelude::*,
+    wayland::{
+        permissions::peer_pid,
+        protocols::{
+            output_configuration::{ModeConfiguration, OutputConfiguration},
+            toplevel_management::ToplevelManagementHandler,
+        },
+    },
+};
+use anyhow::{Context, Result};
+use calloop::{
+    generic::Generic, InsertError, Interest, LoopHandle, Mode as IoMode, PostAction, Readiness,
+    RegistrationToken,
+};
+use cosmic_settings_config::shortcuts;
+use id_tree::{NodeId, Tree};
+use smithay::{
+    backend::input::{Device, DeviceCapability},
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    io::{ErrorKind, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{debug, warn};
+
+mod events;
+pub mod protocol;
+use self::{events::*, protocol::*};
+
+/// Size of a request without a line break, after which the client is disconnected
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+/// Size of unread responses and events, after which the client is disconnected
+const MAX_PENDING_SIZE: usize = 1024 * 1024;
+/// Largest output scale that can be set
+const MAX_SCALE: f64 = 10.0;
+
+/// The IPC socket, which is removed again once dropped
+#[derive(Debug)]
+pub struct IpcState {
+    path: PathBuf,
+    clients: HashMap<u64, Client>,
+    next_client: u64,
+    events: EventState,
+}
+
+impl IpcState {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for IpcState {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+struct Client {
+    /// Clone of the stream read by the event loop source, used for writing
+    stream: UnixStream,
+    token: RegistrationToken,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    /// Kinds of events the client subscribed to
+    events: HashSet<EventKind>,
+}
+
+impl Client {
+    /// Queues `message` and writes as much as the socket takes without blocking.
+    ///
+    /// Returns `false`, if the client is gone or doesn't keep up with reading.
+    fn send(&mut self, message: &[u8]) -> bool {
+        self.outgoing.extend_from_slice(message);
+        self.flush()
+    }
+
+    fn flush(&mut self) -> bool {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return false,
+                Ok(len) => {
+                    self.outgoing.drain(..len);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    debug!(?err, "Error writing to IPC client");
+                    return false;
+                }
+            }
+        }
+        if self.outgoing.len() > MAX_PENDING_SIZE {
+            debug!("Disconnecting IPC client, which doesn't read its messages.");
+            return false;
+        }
+        true
+    }
+}
+
+/// Creates the IPC socket next to the wayland socket and exports its path in `COSMIC_COMP_SOCK`
+pub fn init(evlh: &LoopHandle<'static, State>, wayland_socket: &OsStr) -> Result<IpcState> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR is not set")?;
+    let path = PathBuf::from(runtime_dir).join(format!(
+        "cosmic-comp.{}.sock",
+        wayland_socket.to_string_lossy()
+    ));
+    // left behind by a crashed compositor, as the wayland socket is ours
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind IPC socket at {}", path.display()))?;
+    listener
+        .set_nonblocking(true)
+        .context("Failed to make IPC socket non-blocking")?;
+    evlh.insert_source(
+        Generic::new(listener, Interest::READ, IoMode::Level),
+        |_, listener, state| {
+            // SAFETY: We don't drop the listener!
+            let listener = unsafe { listener.get_mut() };
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if state.process_may_control(peer_pid(&stream)) {
+                            state.add_ipc_client(stream);
+                        } else {
+                            debug!("Refusing IPC client, which isn't allowed `CompositorControl`.");
+                        }
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        warn!(?err, "Failed to accept IPC client.");
+                        break;
+                    }
+                }
+            }
+            Ok(PostAction::Continue)
+        },
+    )
+    .map_err(|InsertError { error, .. }| error)
+    .context("Failed to add IPC socket to the event loop")?;
+
+    std::env::set_var(SOCKET_ENV, &path);
+    Ok(IpcState {
+        path,
+        clients: HashMap::new(),
+        next_client: 0,
+        events: EventState::default(),
+    })
+}
+
+impl State {
+    fn add_ipc_client(&mut self, stream: UnixStream) {
+        let Some(ipc) = self.common.ipc.as_mut() else {
+            return;
+        };
+        let writer = match stream
+            .set_nonblocking(true)
+            .and_then(|_| stream.try_clone())
+        {
+            Ok(writer) => writer,
+            Err(err) => {
+                warn!(?err, "Failed to set up IPC client.");
+                return;
+            }
+        };
+        let id = ipc.next_client;
+        ipc.next_client += 1;
+        let token = match self.common.event_loop_handle.insert_source(
+            Generic::new(stream, Interest::BOTH, IoMode::Edge),
+            move |readiness, stream, state| {
+                // SAFETY: We don't drop the stream!
+                let stream = unsafe { stream.get_mut() };
+                Ok(state.ipc_client_ready(id, stream, readiness))
+            },
+        ) {
+            Ok(token) => token,
+            Err(err) => {
+                warn!(?err, "Failed to add IPC client to the event loop.");
+                return;
+            }
+        };
+        ipc.clients.insert(
+            id,
+            Client {
+                stream: writer,
+                token,
+                incoming: Vec::new(),
+                outgoing: Vec::new(),
+                events: HashSet::new(),
+            },
+        );
+    }
+
+    fn ipc_client_ready(
+        &mut self,
+        id: u64,
+        stream: &mut UnixStream,
+        readiness: Readiness,
+    ) -> PostAction {
+        if readiness.writable && !self.with_ipc_client(id, Client::flush) {
+            return self.remove_ipc_client(id);
+        }
+        if !readiness.readable {
+            return PostAction::Continue;
+        }
+
+        // the source is edge-triggered, so read until there is nothing left
+        let mut data = [0u8; 4096];
+        loop {
+            let len = match stream.read(&mut data) {
+                Ok(0) => return self.remove_ipc_client(id),
+                Ok(len) => len,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return PostAction::Continue,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    debug!(?err, "Error reading from IPC client");
+                    return self.remove_ipc_client(id);
+                }
+            };
+
+            let mut requests = Vec::new();
+            let too_large = self.with_ipc_client(id, |client| {
+                client.incoming.extend_from_slice(&data[..len]);
+                while let Some(pos) = client.incoming.iter().position(|b| *b == b'\n') {
+                    requests.push(client.incoming.drain(..=pos).collect::<Vec<_>>());
+                }
+                client.incoming.len() > MAX_REQUEST_SIZE
+            });
+            if too_large {
+                debug!("Disconnecting IPC client, which sent a too large request.");
+                return self.remove_ipc_client(id);
+            }
+
+            for request in requests {
+                let response = match serde_json::from_slice::<Request>(&request) {
+                    Ok(Request::Subscribe { events }) => self.ipc_subscribe(id, events),
+                    Ok(request) => self.handle_ipc_request(request),
+                    Err(err) => Response::Error(format!("Invalid request: {}", err)),
+                };
+                let mut message = serde_json::to_vec(&response).unwrap();
+                message.push(b'\n');
+                if !self.with_ipc_client(id, |client| client.send(&message)) {
+                    return self.remove_ipc_client(id);
+                }
+            }
+        }
+    }
+
+    /// Runs `f` with the client `id`, returning `false` if it is already gone
+    fn with_ipc_client(&mut self, id: u64, f: impl FnOnce(&mut Client) -> bool) -> bool {
+        self.common
+            .ipc
+            .as_mut()
+            .and_then(|ipc| ipc.clients.get_mut(&id))
+            .map(f)
+            .unwrap_or(false)
+    }
+
+    fn remove_ipc_client(&mut self, id: u64) -> PostAction {
+        if let Some(ipc) = self.common.ipc.as_mut() {
+            ipc.clients.remove(&id);
+        }
+        PostAction::Remove
+    }
+
+    pub fn handle_ipc_request(&mut self, request: Request) -> Response {
+        match request {
+            Request::RunAction { action } => {
+                let action = match ron::from_str::<shortcuts::Action>(&action) {
+                    Ok(action) => action,
+                    Err(err) => return Response::Error(format!("Invalid action: {}", err)),
+                };
+                let seat = self
+                    .common
+                    .shell
+                    .read()
+                    .unwrap()
+                    .seats
+                    .last_active()
+                    .clone();
+                let pattern = shortcuts::Binding {
+                    modifiers: shortcuts::Modifiers::new(),
+                    key: None,
+                    description: None,
+                };
+                self.handle_action(
+                    Action::Shortcut(action),
+                    &seat,
+                    SERIAL_COUNTER.next_serial(),
+                    self.common.clock.now().as_millis(),
+                    pattern,
+                    None,
+                    true,
+                );
+                Response::Success
+            }
+            Request::FocusWindow { id } => {
+                let Some(window) = self.window_for_id(id) else {
+                    return unknown_window(id);
+                };
+                let dh = self.common.display_handle.clone();
+                ToplevelManagementHandler::activate(self, &dh, &window, None);
+                Response::Success
+            }
+            Request::CloseWindow { id } => {
+                let Some(window) = self.window_for_id(id) else {
+                    return unknown_window(id);
+                };
+                window.close();
+                Response::Success
+            }
+            Request::MoveWindowToWorkspace {
+                id,
+                workspace,
+                output,
+            } => self.ipc_move_window(id, workspace, output),
+            Request::SetWindowGeometry { id, geometry } => {
+                self.ipc_set_window_geometry(id, geometry)
+            }
+            Request::SwitchWorkspace { workspace, output } => {
+                let mut shell = self.common.shell.write().unwrap();
+                let output = match output {
+                    Some(name) => match shell.outputs().find(|o| o.name() == name).cloned() {
+                        Some(output) => output,
+                        None => return unknown_output(&name),
+                    },
+                    None => shell.seats.last_active().active_output(),
+                };
+                match shell.activate(
+                    &output,
+                    workspace.saturating_sub(1),
+                    WorkspaceDelta::new_shortcut(),
+                    &mut self.common.workspace_state.update(),
+                ) {
+                    Ok(_) => Response::Success,
+                    Err(_) => Response::Error(format!("No workspace {}", workspace)),
+                }
+            }
+            Request::ConfigureOutput {
+                name,
+                enabled,
+                mode,
+                scale,
+                position,
+                adaptive_sync,
+            } => self.ipc_configure_output(name, enabled, mode, scale, position, adaptive_sync),
+            Request::GetTree => Response::Tree(self.ipc_tree()),
+            Request::GetWorkspaces => Response::Workspaces(self.ipc_workspaces()),
+            Request::GetOutputs => Response::Outputs(self.ipc_outputs()),
+            Request::GetInputs => Response::Inputs(self.ipc_inputs()),
+            Request::GetMetrics => match self.metrics() {
+                Some(metrics) => Response::Metrics(metrics),
+                None => Response::Error(String::from("Metrics are disabled")),
+            },
+            Request::SetTracing {
+                enabled: true,
+                path,
+            } => match path
+                .map(|name| crate::logger::trace::client_path(&name))
+                .transpose()
+                .and_then(crate::logger::trace::start)
+            {
+                Ok(path) => Response::TraceFile(path.to_string_lossy().into_owned()),
+                Err(err) => Response::Error(format!("{:#}", err)),
+            },
+            Request::SetTracing { enabled: false, .. } => match crate::logger::trace::stop() {
+                Some(path) => Response::TraceFile(path.to_string_lossy().into_owned()),
+                None => Response::Error(String::from("No trace is being recorded")),
+            },
+            Request::Subscribe { .. } => {
+                Response::Error(String::from("Subscriptions need a connection"))
+            }
+        }
+    }
+
+    fn window_for_id(&self, id: u64) -> Option<CosmicSurface> {
+        self.common
+            .shell
+            .read()
+            .unwrap()
+            .mapped()
+            .flat_map(|mapped| mapped.windows().map(|(window, _)| window))
+            .find(|window| window.id() == id)
+    }
+
+    fn ipc_move_window(&mut self, id: u64, workspace: usize, output: Option<String>) -> Response {
+        let mut shell = self.common.shell.write().unwrap();
+        let Some((mapped, from)) = shell.workspaces.spaces().find_map(|w| {
+            w.mapped()
+                .find(|m| m.windows().any(|(window, _)| window.id() == id))
+                .map(|m| (m.clone(), w))
+        }) else {
+            return unknown_window(id);
+        };
+        let from_handle = from.handle;
+        let output = match output {
+            Some(name) => match shell.outputs().find(|o| o.name() == name).cloned() {
+                Some(output) => output,
+                None => return unknown_output(&name),
+            },
+            None => from.output().clone(),
+        };
+        let Some(to_handle) = shell
+            .workspaces
+            .get(workspace.saturating_sub(1), &output)
+            .map(|w| w.handle)
+        else {
+            return Response::Error(format!("No workspace {} on {}", workspace, output.name()));
+        };
+        if from_handle == to_handle {
+            return Response::Success;
+        }
+
+        let seat = shell.seats.last_active().clone();
+        shell.move_window(
+            Some(&seat),
+            &mapped,
+            &from_handle,
+            &to_handle,
+            false,
+            None,
+            &mut self.common.workspace_state.update(),
+        );
+        Response::Success
+    }
+
+    fn ipc_set_window_geometry(&mut self, id: u64, geometry: Rect) -> Response {
+        if geometry.width <= 0 || geometry.height <= 0 {
+            return Response::Error(String::from("The size must be positive"));
+        }
+        let mut shell = self.common.shell.write().unwrap();
+        let Some(mapped) = shell
+            .mapped()
+            .find(|m| m.windows().any(|(window, _)| window.id() == id))
+            .cloned()
+        else {
+            return unknown_window(id);
+        };
+        let Some(workspace) = shell.space_for_mut(&mapped) else {
+            return Response::Error(String::from("The window is minimized or sticky"));
+        };
+        let geometry = Rectangle::<i32, Logical>::from_loc_and_size(
+            (geometry.x, geometry.y),
+            (geometry.width, geometry.height),
+        )
+        .as_local();
+        if workspace.floating_layer.set_geometry(&mapped, geometry) {
+            Response::Success
+        } else {
+            Response::Error(String::from("Only floating windows can be moved"))
+        }
+    }
+
+    fn ipc_configure_output(
+        &mut self,
+        name: String,
+        enabled: Option<bool>,
+        mode: Option<Mode>,
+        scale: Option<f64>,
+        position: Option<(i32, i32)>,
+        adaptive_sync: Option<bool>,
+    ) -> Response {
+        if scale.is_some_and(|scale| !scale.is_finite() || scale <= 0.0 || scale > MAX_SCALE) {
+            return Response::Error(format!("The scale must be between 0 and {}", MAX_SCALE));
+        }
+        let outputs = self
+            .common
+            .output_configuration_state
+            .outputs()
+            .collect::<Vec<_>>();
+        if !outputs.iter().any(|o| o.name() == name) {
+            return unknown_output(&name);
+        }
+
+        // outputs missing from the configuration would be disabled
+        let conf = outputs
+            .iter()
+            .map(|output| {
+                let state = output.config().enabled.clone();
+                let mirroring = match &state {
+                    OutputState::Mirroring(conn) => outputs.iter().find(|o| &o.name() == conn),
+                    _ => None,
+                };
+                let conf = if output.name() != name {
+                    match state {
+                        OutputState::Disabled => OutputConfiguration::Disabled,
+                        _ => OutputConfiguration::Enabled {
+                            mirroring: mirroring.cloned(),
+                            mode: None,
+                            position: None,
+                            transform: None,
+                            scale: None,
+                            adaptive_sync: None,
+                        },
+                    }
+                } else if enabled == Some(false)
+                    || (enabled.is_none() && state == OutputState::Disabled)
+                {
+                    OutputConfiguration::Disabled
+                } else {
+                    OutputConfiguration::Enabled {
+                        // enabling an output explicitly stops mirroring
+                        mirroring: mirroring.filter(|_| enabled.is_none()).cloned(),
+                        mode: mode.map(|mode| ModeConfiguration::Custom {
+                            size: (mode.width, mode.height).into(),
+                            refresh: mode.refresh.map(|refresh| refresh as i32),
+                        }),
+                        position: position.map(Point::from),
+                        transform: None,
+                        scale,
+                        adaptive_sync,
+                    }
+                };
+                (output.clone(), conf)
+            })
+            .collect::<Vec<_>>();
+
+        if self.output_configuration(false, conf) {
+            Response::Success
+        } else {
+            Response::Error(String::from("Failed to apply the output configuration"))
+        }
+    }
+
+    fn ipc_tree(&self) -> Vec<OutputNode> {
+        let shell = self.common.shell.read().unwrap();
+        shell
+            .workspaces
+            .iter()
+            .map(|(output, set)| OutputNode {
+                name: output.name(),
+                geometry: rect(output.geometry().as_logical()),
+                workspaces: set
+                    .workspaces
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, workspace)| WorkspaceNode {
+                        number: idx + 1,
+                        name: workspace.name.clone(),
+                        active: idx == set.active,
+                        tiling: workspace.tiling_enabled,
+                        windows: workspace
+                            .mapped()
+                            .flat_map(|mapped| {
+                                let geometry = workspace
+                                    .element_geometry(mapped)
+                                    .map(|geo| geo.as_logical())
+                                    .unwrap_or_default();
+                                let floating =
+                                    workspace.floating_layer.mapped().any(|m| m == mapped);
+                                mapped.windows().map(move |(window, _)| {
+                                    window_info(&window, geometry, floating)
+                                })
+                            })
+                            .chain(workspace.minimized_windows.iter().flat_map(|m| {
+                                m.window.windows().map(|(window, _)| {
+                                    window_info(&window, Rectangle::default(), false)
+                                })
+                            }))
+                            .collect(),
+                        tiling_tree: {
+                            let tree = workspace.tiling_layer.tree();
+                            tree.root_node_id().map(|root| tiling_node(tree, root))
+                        },
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn ipc_workspaces(&self) -> Vec<WorkspaceInfo> {
+        let shell = self.common.shell.read().unwrap();
+        shell
+            .workspaces
+            .iter()
+            .flat_map(|(output, set)| {
+                set.workspaces
+                    .iter()
+                    .enumerate()
+                    .map(move |(idx, workspace)| WorkspaceInfo {
+                        number: idx + 1,
+                        name: workspace.name.clone(),
+                        output: output.name(),
+                        active: idx == set.active,
+                        tiling: workspace.tiling_enabled,
+                        windows: workspace
+                            .mapped()
+                            .map(|m| m.windows().count())
+                            .sum::<usize>()
+                            + workspace.minimized_windows.len(),
+                    })
+            })
+            .collect()
+    }
+
+    fn ipc_outputs(&self) -> Vec<OutputInfo> {
+        self.common
+            .output_configuration_state
+            .outputs()
+            .map(|output| {
+                let config = output.config();
+                let physical = output.physical_properties();
+                OutputInfo {
+                    name: output.name(),
+                    make: physical.make,
+                    model: physical.model,
+                    enabled: config.enabled != OutputState::Disabled,
+                    mirroring: match &config.enabled {
+                        OutputState::Mirroring(conn) => Some(conn.clone()),
+                        _ => None,
+                    },
+                    geometry: (config.enabled == OutputState::Enabled)
+                        .then(|| rect(output.geometry().as_logical())),
+                    scale: config.scale,
+                    current_mode: output.current_mode().map(mode),
+                    modes: output.modes().into_iter().map(mode).collect(),
+                    adaptive_sync: output.adaptive_sync(),
+                }
+            })
+            .collect()
+    }
+
+    fn ipc_inputs(&self) -> Vec<InputInfo> {
+        let BackendData::Kms(kms) = &self.backend else {
+            return Vec::new();
+        };
+        kms.input_devices
+            .values()
+            .map(|device| InputInfo {
+                name: device.name().to_string(),
+                vendor: device.id_vendor(),
+                product: device.id_product(),
+                capabilities: [
+                    (DeviceCapability::Keyboard, "keyboard"),
+                    (DeviceCapability::Pointer, "pointer"),
+                    (DeviceCapability::Touch, "touch"),
+                    (DeviceCapability::TabletTool, "tablet_tool"),
+                    (DeviceCapability::TabletPad, "tablet_pad"),
+                    (DeviceCapability::Gesture, "gesture"),
+                    (DeviceCapability::Switch, "switch"),
+                ]
+                .into_iter()
+                .filter(|(capability, _)| Device::has_capability(device, *capability))
+                .map(|(_, name)| name.to_string())
+                .collect(),
+            })
+            .collect()
+    }
+}
+
+fn unknown_window(id: u64) -> Response {
+    Response::Error(format!("No window with id {}", id))
+}
+
+fn unknown_output(name: &str) -> Response {
+    Response::Error(format!("No output named {}", name))
+}
+
+fn rect(rect: Rectangle<i32, Logical>) -> Rect {
+    Rect {
+        x: rect.loc.x,
+        y: rect.loc.y,
+        width: rect.size.w,
+        height: rect.size.h,
+    }
+}
+
+fn mode(mode: smithay::output::Mode) -> Mode {
+    Mode {
+        width: mode.size.w,
+        height: mode.size.h,
+        refresh: Some(mode.refresh as u32),
+    }
+}
+
+fn tiling_node(tree: &Tree<Data>, id: &NodeId) -> TilingNode {
+    let node = tree.get(id).unwrap();
+    match node.data() {
+        Data::Group {
+            orientation,
+            sizes,
+            last_geometry,
+            alive,
+            ..
+        } => {
+            let total = sizes.iter().sum::<i32>().max(1) as f64;
+            TilingNode::Group {
+                id: Arc::as_ptr(alive) as usize as u64,
+                orientation: match orientation {
+                    shortcuts::action::Orientation::Horizontal => Orientation::Horizontal,
+                    shortcuts::action::Orientation::Vertical => Orientation::Vertical,
+                },
+                ratios: sizes.iter().map(|size| *size as f64 / total).collect(),
+                geometry: rect(last_geometry.as_logical()),
+                children: node
+                    .children()
+                    .iter()
+                    .map(|child| tiling_node(tree, child))
+                    .collect(),
+            }
+        }
+        Data::Mapped {
+            mapped,
+            last_geometry,
+            ..
+        } => {
+            let geometry = last_geometry.as_logical();
+            if mapped.is_stack() {
+                TilingNode::Stack {
+                    id: mapped.key().id(),
+                    geometry: rect(geometry),
+                    active: mapped.active_window().id(),
+                    windows: mapped
+                        .windows()
+                        .map(|(window, _)| window_info(&window, geometry, false))
+                        .collect(),
+                }
+            } else {
+                TilingNode::Window {
+                    window: window_info(&mapped.active_window(), geometry, false),
+                }
+            }
+        }
+        Data::Placeholder { last_geometry, .. } => TilingNode::Placeholder {
+            geometry: rect(last_geometry.as_logical()),
+        },
+    }
+}
+
+fn window_info(
+    window: &CosmicSurface,
+    geometry: Rectangle<i32, Logical>,
+    floating: bool,
+) -> WindowInfo {
+    WindowInfo {
+        id: window.id(),
+        app_id: window.app_id(),
+        title: window.title(),
+        geometry: rect(geometry),
+        floating,
+        focused: window.is_activated(false),
+        maximized: window.is_maximized(false),
+        fullscreen: window.is_fullscreen(false),
+        minimized: window.is_minimized(),
+    }
+}
@@ -0,0 +1,329 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Messages of the IPC socket, shared with `cosmic-comp-msg`.
+//!
+//! Clients write requests as JSON objects, one per line,
+//! and get a response on a single line for each of them.
+//! After subscribing, [`Event`]s are written on their own lines as well.
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable holding the path of the IPC socket
+pub const SOCKET_ENV: &str = "COSMIC_COMP_SOCK";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "request")]
+pub enum Request {
+    /// Runs a shortcut action, written like in the shortcuts configuration, e.g. `Workspace(2)`
+    RunAction {
+        action: String,
+    },
+    /// Focuses a window, switching to its workspace
+    FocusWindow {
+        id: u64,
+    },
+    /// Asks a window to close
+    CloseWindow {
+        id: u64,
+    },
+    /// Moves a window to a workspace of the given output, or of the output it is on
+    MoveWindowToWorkspace {
+        id: u64,
+        workspace: usize,
+        #[serde(default)]
+        output: Option<String>,
+    },
+    /// Moves and resizes a floating window, relative to its output
+    SetWindowGeometry {
+        id: u64,
+        geometry: Rect,
+    },
+    /// Switches to a workspace of the given output, or of the active one
+    SwitchWorkspace {
+        workspace: usize,
+        #[serde(default)]
+        output: Option<String>,
+    },
+    /// Changes the configuration of an output, leaving unset properties as they are
+    ConfigureOutput {
+        name: String,
+        #[serde(default)]
+        enabled: Option<bool>,
+        #[serde(default)]
+        mode: Option<Mode>,
+        #[serde(default)]
+        scale: Option<f64>,
+        #[serde(default)]
+        position: Option<(i32, i32)>,
+        #[serde(default)]
+        adaptive_sync: Option<bool>,
+    },
+    /// Outputs with their workspaces and windows
+    GetTree,
+    GetWorkspaces,
+    GetOutputs,
+    GetInputs,
+    /// Frame timings and resource usage, if enabled in the `metrics` configuration
+    GetMetrics,
+    /// Starts or stops recording tracing spans into a trace file in the Chrome JSON format.
+    /// Responds with the path of the file.
+    SetTracing {
+        enabled: bool,
+        /// Name of the file to record to in `$XDG_RUNTIME_DIR/cosmic-comp-traces`,
+        /// a new one in the temporary directory by default
+        #[serde(default)]
+        path: Option<String>,
+    },
+    /// Sends events of the given kinds on this connection from now on
+    Subscribe {
+        events: Vec<EventKind>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Success,
+    Error(String),
+    Tree(Vec<OutputNode>),
+    Workspaces(Vec<WorkspaceInfo>),
+    Outputs(Vec<OutputInfo>),
+    Inputs(Vec<InputInfo>),
+    Metrics(Metrics),
+    TraceFile(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Window,
+    Workspace,
+    Output,
+    BindingMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "event")]
+pub enum Event {
+    WindowOpened {
+        window: WindowInfo,
+    },
+    WindowClosed {
+        id: u64,
+    },
+    /// Keyboard focus moved to another window, or away from all of them
+    WindowFocused {
+        id: Option<u64>,
+    },
+    WindowTitleChanged {
+        id: u64,
+        title: String,
+    },
+    /// Any workspace was added, removed, activated or changed its windows
+    WorkspacesChanged {
+        workspaces: Vec<WorkspaceInfo>,
+    },
+    /// Any output was added, removed or reconfigured
+    OutputsChanged {
+        outputs: Vec<OutputInfo>,
+    },
+    BindingModeChanged {
+        mode: Option<String>,
+    },
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::WindowOpened { .. }
+            | Event::WindowClosed { .. }
+            | Event::WindowFocused { .. }
+            | Event::WindowTitleChanged { .. } => EventKind::Window,
+            Event::WorkspacesChanged { .. } => EventKind::Workspace,
+            Event::OutputsChanged { .. } => EventKind::Output,
+            Event::BindingModeChanged { .. } => EventKind::BindingMode,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mode {
+    pub width: i32,
+    pub height: i32,
+    /// Refresh rate in mHz
+    #[serde(default)]
+    pub refresh: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputNode {
+    pub name: String,
+    /// Position and logical size in the global space
+    pub geometry: Rect,
+    pub workspaces: Vec<WorkspaceNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceNode {
+    pub number: usize,
+    /// Name from the config, `None` if the workspace is named after its number
+    pub name: Option<String>,
+    pub active: bool,
+    pub tiling: bool,
+    pub windows: Vec<WindowInfo>,
+    /// Layout of the tiled windows, if there are any
+    #[serde(default)]
+    pub tiling_tree: Option<TilingNode>,
+}
+
+/// Node of the tiling tree of a workspace, with geometries relative to the output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum TilingNode {
+    /// Container splitting its space between its children
+    Group {
+        /// Identifier of the group, stable as long as it exists
+        id: u64,
+        orientation: Orientation,
+        /// Share of the space of each child, adding up to 1
+        ratios: Vec<f64>,
+        geometry: Rect,
+        children: Vec<TilingNode>,
+    },
+    Window {
+        window: WindowInfo,
+    },
+    /// Windows sharing the same space as tabs
+    Stack {
+        /// Identifier of the stack, stable as long as it exists
+        id: u64,
+        geometry: Rect,
+        /// Id of the window of the selected tab
+        active: u64,
+        windows: Vec<WindowInfo>,
+    },
+    /// Space reserved for a window about to be tiled
+    Placeholder {
+        geometry: Rect,
+    },
+}
+
+/// Direction of the lines separating the children of a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    /// Children are stacked on top of each other
+    Horizontal,
+    /// Children are placed side by side
+    Vertical,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    /// Identifier of the window, unique for the lifetime of the compositor
+    pub id: u64,
+    pub app_id: String,
+    pub title: String,
+    /// Position relative to the output and size
+    pub geometry: Rect,
+    pub floating: bool,
+    pub focused: bool,
+    pub maximized: bool,
+    pub fullscreen: bool,
+    pub minimized: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceInfo {
+    pub number: usize,
+    /// Name from the config, `None` if the workspace is named after its number
+    pub name: Option<String>,
+    pub output: String,
+    pub active: bool,
+    pub tiling: bool,
+    pub windows: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputInfo {
+    pub name: String,
+    pub make: String,
+    pub model: String,
+    pub enabled: bool,
+    /// Output, whose content is shown instead
+    pub mirroring: Option<String>,
+    /// Position and logical size in the global space, while enabled
+    pub geometry: Option<Rect>,
+    pub scale: f64,
+    pub current_mode: Option<Mode>,
+    pub modes: Vec<Mode>,
+    pub adaptive_sync: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputInfo {
+    pub name: String,
+    pub vendor: u32,
+    pub product: u32,
+    /// Device types, like `keyboard`, `pointer` or `touch`
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub outputs: Vec<OutputMetrics>,
+    pub clients: Vec<ClientMetrics>,
+    /// Rough estimate of the GPU memory used for client buffers in bytes,
+    /// assuming 4 bytes per pixel
+    pub gpu_memory_estimate: u64,
+    /// Event loop wakeups since the compositor started
+    pub wakeups: u64,
+    /// Event loop wakeups per second, over the last ten seconds or more
+    pub wakeups_per_second: f64,
+}
+
+/// Timings of the recently presented frames of an output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputMetrics {
+    pub name: String,
+    /// Frames considered for the statistics
+    pub frames: usize,
+    pub fps: f64,
+    /// Time from starting to render a frame until it was presented, in milliseconds
+    pub frame_time: Percentiles,
+    /// Time spent rendering a frame, in milliseconds
+    pub render_time: Percentiles,
+    /// Frames presented at least one refresh later than scheduled
+    pub missed_deadlines: usize,
+    /// Missed deadlines since the output was enabled
+    pub missed_deadlines_total: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Buffers currently attached to the surfaces of a client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientMetrics {
+    pub pid: Option<i32>,
+    /// App ids of the windows of the client
+    pub app_ids: Vec<String>,
+    pub surfaces: usize,
+    pub shm_buffers: usize,
+    pub dmabuf_buffers: usize,
+    /// Size of the buffers in bytes, assuming 4 bytes per pixel
+    pub buffer_bytes: u64,
+}
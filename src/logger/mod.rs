@@ -8,6 +8,8 @@ use tracing::{debug, info, warn};
 use tracing_journald as journald;
 use tracing_subscriber::{filter::Directive, fmt, prelude::*, EnvFilter};
 
+pub mod trace;
+
 pub fn init_logger() -> Result<()> {
     let level = if cfg!(debug_assertions) {
         "debug"
@@ -28,23 +30,28 @@ pub fn init_logger() -> Result<()> {
         .add_directive(Directive::from_str(&format!("cosmic_comp={level}")).unwrap());
 
     let fmt_layer = fmt::layer().compact();
-
-    match journald::layer() {
-        Ok(journald_layer) => tracing_subscriber::registry()
-            .with(fmt_layer)
-            .with(journald_layer)
-            .with(filter)
-            .init(),
-        Err(err) => {
-            tracing_subscriber::registry()
-                .with(fmt_layer)
-                .with(filter)
-                .init();
-            warn!(?err, "Failed to init journald logging.");
-        }
+    let (journald_layer, journald_err) = match journald::layer() {
+        Ok(layer) => (Some(layer), None),
+        Err(err) => (None, Some(err)),
     };
+
+    // the filter only applies to logging, traces record spans regardless of it
+    tracing_subscriber::registry()
+        .with(fmt_layer.and_then(journald_layer).with_filter(filter))
+        .with(trace::layer())
+        .init();
+    if let Some(err) = journald_err {
+        warn!(?err, "Failed to init journald logging.");
+    }
     log_panics::init();
 
+    if let Some(path) = std::env::var_os("COSMIC_COMP_TRACE") {
+        match trace::start(Some(path.into())) {
+            Ok(path) => info!("Recording trace to {}", path.display()),
+            Err(err) => warn!(?err, "Failed to start recording a trace"),
+        }
+    }
+
     info!("Version: {}", std::env!("CARGO_PKG_VERSION"));
     if cfg!(feature = "debug") {
         debug!(
@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Recording tracing spans into a trace file, for investigating performance issues.
+//!
+//! Traces are written in the Chrome JSON trace format, which can be opened in Perfetto
+//! or converted with the `import-chrome` tool of Tracy. Recording is toggled at runtime
+//! through the IPC socket, or started right away by setting `COSMIC_COMP_TRACE` to a path.
+
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context as _, Result};
+use serde_json::{json, Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Level, Subscriber,
+};
+use tracing_subscriber::{
+    filter::filter_fn,
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TRACE: Mutex<Option<TraceFile>> = Mutex::new(None);
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static THREAD_ID: Cell<u64> = Cell::new(NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed));
+}
+
+struct TraceFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    start: Instant,
+    first: bool,
+    /// Threads, whose names were already written
+    named_threads: HashSet<u64>,
+}
+
+impl TraceFile {
+    fn write(&mut self, event: &Value) {
+        let separator: &[u8] = if self.first { b"" } else { b",\n" };
+        self.first = false;
+        let _ = self.writer.write_all(separator);
+        let _ = serde_json::to_writer(&mut self.writer, event);
+    }
+
+    fn finish(mut self) -> PathBuf {
+        let _ = self.writer.write_all(b"\n]\n");
+        let _ = self.writer.flush();
+        self.path
+    }
+}
+
+/// Layer writing spans of debug level or above to the trace file, while recording
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    TraceLayer.with_filter(filter_fn(|metadata| {
+        ENABLED.load(Ordering::Relaxed) && metadata.is_span() && *metadata.level() <= Level::DEBUG
+    }))
+}
+
+/// Starts recording to `path`, or a new file in the temporary directory, returning its path
+pub fn start(path: Option<PathBuf>) -> Result<PathBuf> {
+    let mut trace = TRACE.lock().unwrap();
+    if let Some(trace) = trace.as_ref() {
+        bail!("Already recording to {}", trace.path.display());
+    }
+
+    let path = path.unwrap_or_else(|| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        std::env::temp_dir().join(format!("cosmic-comp-{}.trace.json", now))
+    });
+    let file = File::create(&path)
+        .with_context(|| format!("Failed to create trace file {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(b"[\n")?;
+
+    *trace = Some(TraceFile {
+        path: path.clone(),
+        writer,
+        start: Instant::now(),
+        first: true,
+        named_threads: HashSet::new(),
+    });
+    ENABLED.store(true, Ordering::SeqCst);
+    Ok(path)
+}
+
+/// Path of the trace file `name` requested by an IPC client
+///
+/// Clients may only choose a file name in `$XDG_RUNTIME_DIR/cosmic-comp-traces`, as the compositor
+/// could overwrite any file of the user otherwise.
+pub fn client_path(name: &str) -> Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    let (Some(Component::Normal(file_name)), None) = (components.next(), components.next()) else {
+        bail!("Expected the name of a trace file, not a path: {}", name);
+    };
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR is not set")?;
+    let dir = PathBuf::from(runtime_dir).join("cosmic-comp-traces");
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    Ok(dir.join(file_name))
+}
+
+/// Stops recording, returning the path of the finished trace file
+pub fn stop() -> Option<PathBuf> {
+    ENABLED.store(false, Ordering::SeqCst);
+    TRACE.lock().unwrap().take().map(TraceFile::finish)
+}
+
+struct TraceLayer;
+
+/// Fields of a span, shown as its arguments
+#[derive(Default)]
+struct Args(Map<String, Value>);
+
+impl Visit for Args {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+}
+
+impl<S> Layer<S> for TraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut args = Args::default();
+        attrs.record(&mut args);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(args);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(args) = span.extensions_mut().get_mut::<Args>() {
+                values.record(args);
+            }
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let args = span.extensions().get::<Args>().map(|args| args.0.clone());
+            write_event("B", span.name(), span.metadata().target(), args);
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            write_event("E", span.name(), span.metadata().target(), None);
+        }
+    }
+}
+
+fn write_event(phase: &str, name: &str, category: &str, args: Option<Map<String, Value>>) {
+    let mut trace = TRACE.lock().unwrap();
+    let Some(trace) = trace.as_mut() else {
+        return;
+    };
+    let pid = std::process::id();
+    let tid = THREAD_ID.with(Cell::get);
+
+    if trace.named_threads.insert(tid) {
+        let thread = std::thread::current();
+        let thread_name = thread.name().unwrap_or("unnamed");
+        trace.write(&json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": pid,
+            "tid": tid,
+            "args": { "name": thread_name },
+        }));
+    }
+
+    let mut event = json!({
+        "name": name,
+        "cat": category,
+        "ph": phase,
+        "ts": trace.start.elapsed().as_secs_f64() * 1_000_000.0,
+        "pid": pid,
+        "tid": tid,
+    });
+    if let Some(args) = args.filter(|args| !args.is_empty()) {
+        event["args"] = Value::Object(args);
+    }
+    trace.write(&event);
+}
@@ -16,12 +16,17 @@ use tracing::{error, info, warn};
 use crate::wayland::handlers::compositor::client_compositor_state;
 
 pub mod backend;
+pub mod bell;
 pub mod config;
 pub mod dbus;
 #[cfg(feature = "debug")]
 pub mod debug;
+pub mod hooks;
+pub mod idle;
 pub mod input;
+pub mod ipc;
 mod logger;
+pub mod metrics;
 pub mod session;
 pub mod shell;
 pub mod state;
@@ -40,8 +45,16 @@ static GLOBAL: profiling::tracy_client::ProfiledAllocator<std::alloc::System> =
 // called by the Xwayland source, either after starting or failing
 impl State {
     fn notify_ready(&mut self) {
-        // TODO: Don't notify again, but potentially import updated env-variables
-        // into systemd and the session?
+        if self.ready.is_completed() {
+            // Xwayland was started or stopped later on, update `DISPLAY`
+            // TODO: Also update the environment of the session
+            #[cfg(feature = "systemd")]
+            if let state::BackendData::Kms(_) = &self.backend {
+                systemd::import_environment(&self.common);
+            }
+            return;
+        }
+
         self.ready.call_once(|| {
             // potentially tell systemd we are setup now
             #[cfg(feature = "systemd")]
@@ -83,6 +96,10 @@ impl State {
 }
 
 fn main() -> Result<()> {
+    if env::args().nth(1).as_deref() == Some("--check") {
+        process::exit(if config::check::check_config() { 0 } else { 1 });
+    }
+
     // setup logger
     logger::init_logger()?;
     info!("Cosmic starting up!");
@@ -121,6 +138,9 @@ fn main() -> Result<()> {
             return;
         }
 
+        let _span = tracing::info_span!("event_loop_iteration").entered();
+        state.common.metrics_state.count_wakeup();
+
         // trigger routines
         let clients = state.common.shell.write().unwrap().update_animations();
         {
@@ -131,18 +151,29 @@ fn main() -> Result<()> {
         }
         state.common.refresh();
         state::Common::refresh_focus(state);
+        state.send_ipc_events();
+        state.update_dbus_properties();
+        state.check_hooks();
+        state.check_xwayland();
+        state.check_session_lock();
         state.common.update_x11_stacking_order();
 
         {
+            // every output keeps its own pace, so only wake up the ones with something to animate
             let shell = state.common.shell.read().unwrap();
-            if shell.animations_going() {
-                for output in shell.outputs().cloned().collect::<Vec<_>>().into_iter() {
-                    state.backend.schedule_render(&output);
-                }
+            let animated = shell
+                .outputs()
+                .filter(|output| shell.output_animations_going(output))
+                .cloned()
+                .collect::<Vec<_>>();
+            std::mem::drop(shell);
+            for output in &animated {
+                state.backend.schedule_render(output);
             }
         }
 
-        // send out events
+        // send out events, layout changes are configured once with their final geometry
+        state.common.shell.write().unwrap().flush_configures();
         let _ = state.common.display_handle.flush_clients();
 
         // check if kiosk child is running
@@ -194,7 +225,7 @@ fn init_wayland_display(
     event_loop
         .handle()
         .insert_source(source, |client_stream, _, state| {
-            let client_state = state.new_client_state();
+            let client_state = state.new_client_state(&client_stream);
             if let Err(err) = state
                 .common
                 .display_handle
@@ -209,10 +240,19 @@ fn init_wayland_display(
         .insert_source(
             Generic::new(display, Interest::READ, Mode::Level),
             move |_, display, state| {
+                let _span = tracing::info_span!("dispatch_clients").entered();
+                let display_handle = state.common.display_handle.clone();
                 // SAFETY: We don't drop the display
-                match unsafe { display.get_mut().dispatch_clients(state) } {
-                    Ok(_) => Ok(PostAction::Continue),
-                    Err(err) => {
+                let res = utils::recover::dispatch_clients(&display_handle, || unsafe {
+                    display.get_mut().dispatch_clients(state)
+                });
+                match res {
+                    Some(Ok(_)) => Ok(PostAction::Continue),
+                    None => {
+                        utils::recover::check_poison(&state.common.shell);
+                        Ok(PostAction::Continue)
+                    }
+                    Some(Err(err)) => {
                         error!(?err, "I/O error on the Wayland display");
                         state.common.should_stop = true;
                         Err(err)
@@ -0,0 +1,460 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Frame timings and resource usage, configured as `metrics`.
+//!
+//! Metrics are collected on request, through `GetMetrics` on the IPC socket or by scraping
+//! the optional Prometheus endpoint, so there is no cost while nobody is looking.
+
+use crate::{
+    ipc::protocol::{ClientMetrics, Metrics, OutputMetrics, Percentiles},
+    state::State,
+};
+use anyhow::{Context, Result};
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction, RegistrationToken};
+use smithay::{
+    backend::renderer::{
+        buffer_dimensions, buffer_type, utils::with_renderer_surface_state, BufferType,
+    },
+    desktop::{layer_map_for_output, PopupManager},
+    reexports::wayland_server::{backend::ClientId, protocol::wl_surface::WlSurface, Resource},
+    wayland::{
+        compositor::{with_surface_tree_downward, TraversalAction},
+        seat::WaylandFocus,
+    },
+};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::io::{AsFd, BorrowedFd},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+/// Time after which writing to a scraper, that doesn't read the response, is given up
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Minimum time the wakeup rate is averaged over
+const WAKEUP_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+pub struct MetricsState {
+    /// Address and event source of the Prometheus endpoint
+    prometheus: Option<(String, RegistrationToken)>,
+    wakeups: Wakeups,
+}
+
+/// Event loop wakeups, counted even with metrics disabled, as that's just an increment
+#[derive(Debug)]
+struct Wakeups {
+    total: u64,
+    /// Start of the current interval and the wakeups since
+    interval: (Instant, u64),
+    /// Wakeups per second of the last completed interval
+    rate: f64,
+}
+
+impl Default for Wakeups {
+    fn default() -> Self {
+        Wakeups {
+            total: 0,
+            interval: (Instant::now(), 0),
+            rate: 0.0,
+        }
+    }
+}
+
+impl Wakeups {
+    /// Wakeups per second of the current interval, if it is long enough, or of the last one
+    fn rate(&self, now: Instant) -> f64 {
+        let (start, count) = self.interval;
+        let elapsed = now.duration_since(start);
+        if elapsed >= WAKEUP_INTERVAL {
+            count as f64 / elapsed.as_secs_f64()
+        } else {
+            self.rate
+        }
+    }
+}
+
+impl MetricsState {
+    /// Counts an iteration of the event loop
+    pub fn count_wakeup(&mut self) {
+        let wakeups = &mut self.wakeups;
+        let now = Instant::now();
+        if now.duration_since(wakeups.interval.0) >= WAKEUP_INTERVAL {
+            wakeups.rate = wakeups.rate(now);
+            wakeups.interval = (now, 0);
+        }
+        wakeups.total += 1;
+        wakeups.interval.1 += 1;
+    }
+}
+
+struct Scraper {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl AsFd for Scraper {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+}
+
+impl State {
+    /// Starts or stops the Prometheus endpoint after the configuration changed
+    pub fn update_metrics_endpoint(&mut self) {
+        let config = &self.common.config.cosmic_conf.metrics;
+        let address = config.prometheus_address.clone().filter(|_| config.enabled);
+        let state = &mut self.common.metrics_state;
+        if state.prometheus.as_ref().map(|(address, _)| address) == address.as_ref() {
+            return;
+        }
+
+        if let Some((_, token)) = state.prometheus.take() {
+            self.common.event_loop_handle.remove(token);
+        }
+        let Some(address) = address else {
+            return;
+        };
+        match listen(&self.common.event_loop_handle, &address) {
+            Ok(token) => {
+                info!("Serving metrics on {}", address);
+                self.common.metrics_state.prometheus = Some((address, token));
+            }
+            Err(err) => warn!(?err, "Failed to start the metrics endpoint"),
+        }
+    }
+
+    /// Collects the current metrics, `None` if they are disabled
+    pub fn metrics(&self) -> Option<Metrics> {
+        if !self.common.config.cosmic_conf.metrics.enabled {
+            return None;
+        }
+
+        let clients = self.client_metrics();
+        let gpu_memory_estimate = clients.iter().map(|client| client.buffer_bytes).sum();
+        let wakeups = &self.common.metrics_state.wakeups;
+        Some(Metrics {
+            outputs: self.backend.output_metrics(),
+            clients,
+            gpu_memory_estimate,
+            wakeups: wakeups.total,
+            wakeups_per_second: wakeups.rate(Instant::now()),
+        })
+    }
+
+    fn client_metrics(&self) -> Vec<ClientMetrics> {
+        let mut surfaces = Vec::new();
+        let mut app_ids = HashMap::<ClientId, Vec<String>>::new();
+        {
+            let shell = self.common.shell.read().unwrap();
+            for mapped in shell.mapped() {
+                for (window, _) in mapped.windows() {
+                    let Some(surface) = window.wl_surface() else {
+                        continue;
+                    };
+                    if let Some(client) = surface.client() {
+                        let ids = app_ids.entry(client.id()).or_default();
+                        let app_id = window.app_id();
+                        if !ids.contains(&app_id) {
+                            ids.push(app_id);
+                        }
+                    }
+                    surfaces.extend(
+                        PopupManager::popups_for_surface(&surface)
+                            .map(|(popup, _)| popup.wl_surface().clone()),
+                    );
+                    surfaces.push(surface.into_owned());
+                }
+            }
+            for output in shell.outputs() {
+                let map = layer_map_for_output(output);
+                surfaces.extend(map.layers().map(|layer| layer.wl_surface().clone()));
+            }
+        }
+
+        let mut clients = HashMap::<ClientId, ClientMetrics>::new();
+        for root in surfaces {
+            let Some(client) = root.client() else {
+                continue;
+            };
+            let metrics = clients.entry(client.id()).or_insert_with(|| ClientMetrics {
+                pid: client
+                    .get_credentials(&self.common.display_handle)
+                    .ok()
+                    .map(|credentials| credentials.pid),
+                app_ids: app_ids.remove(&client.id()).unwrap_or_default(),
+                surfaces: 0,
+                shm_buffers: 0,
+                dmabuf_buffers: 0,
+                buffer_bytes: 0,
+            });
+
+            for surface in surface_tree(&root) {
+                metrics.surfaces += 1;
+                let buffer = with_renderer_surface_state(&surface, |state| {
+                    state.buffer().map(|buffer| (**buffer).clone())
+                })
+                .flatten();
+                let Some(buffer) = buffer else {
+                    continue;
+                };
+                match buffer_type(&buffer) {
+                    Some(BufferType::Shm) => metrics.shm_buffers += 1,
+                    Some(BufferType::Dma) => metrics.dmabuf_buffers += 1,
+                    _ => {}
+                }
+                if let Some(size) = buffer_dimensions(&buffer) {
+                    metrics.buffer_bytes += size.w as u64 * size.h as u64 * 4;
+                }
+            }
+        }
+
+        let mut clients = clients.into_values().collect::<Vec<_>>();
+        clients.sort_by_key(|client| std::cmp::Reverse(client.buffer_bytes));
+        clients
+    }
+}
+
+fn surface_tree(root: &WlSurface) -> Vec<WlSurface> {
+    let mut surfaces = Vec::new();
+    with_surface_tree_downward(
+        root,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |surface, _, _| surfaces.push(surface.clone()),
+        |_, _, _| true,
+    );
+    surfaces
+}
+
+fn listen(evlh: &LoopHandle<'static, State>, address: &str) -> Result<RegistrationToken> {
+    let listener = TcpListener::bind(address)
+        .with_context(|| format!("Failed to bind metrics endpoint at {}", address))?;
+    listener
+        .set_nonblocking(true)
+        .context("Failed to make metrics endpoint non-blocking")?;
+    evlh.insert_source(
+        Generic::new(listener, Interest::READ, Mode::Level),
+        |_, listener, state| {
+            // SAFETY: We don't drop the listener!
+            let listener = unsafe { listener.get_mut() };
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => add_scraper(&state.common.event_loop_handle, stream),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        warn!(?err, "Failed to accept metrics scraper.");
+                        break;
+                    }
+                }
+            }
+            Ok(PostAction::Continue)
+        },
+    )
+    .map_err(|err| err.error)
+    .context("Failed to add metrics endpoint to the event loop")
+}
+
+fn add_scraper(evlh: &LoopHandle<'static, State>, stream: TcpStream) {
+    // the request is only read once the socket is readable, but the response is written blocking
+    if let Err(err) = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+    {
+        warn!(?err, "Failed to set up metrics scraper.");
+        return;
+    }
+    let scraper = Scraper {
+        stream,
+        buffer: Vec::new(),
+    };
+    if let Err(err) = evlh.insert_source(
+        Generic::new(scraper, Interest::READ, Mode::Level),
+        |_, scraper, state| {
+            // SAFETY: We don't drop the stream!
+            let scraper = unsafe { scraper.get_mut() };
+
+            let mut data = [0u8; 4096];
+            match scraper.stream.read(&mut data) {
+                Ok(0) => return Ok(PostAction::Remove),
+                Ok(len) => scraper.buffer.extend_from_slice(&data[..len]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {
+                    return Ok(PostAction::Continue)
+                }
+                Err(err) => {
+                    debug!(?err, "Error reading from metrics scraper");
+                    return Ok(PostAction::Remove);
+                }
+            }
+            // wait for the end of the request headers
+            if !scraper.buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+                return Ok(if scraper.buffer.len() > 64 * 1024 {
+                    PostAction::Remove
+                } else {
+                    PostAction::Continue
+                });
+            }
+
+            let response = match state.metrics() {
+                Some(metrics) => {
+                    let body = prometheus_text(&metrics);
+                    format!(
+                        "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                }
+                None => String::from("HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+            };
+            if let Err(err) = scraper.stream.write_all(response.as_bytes()) {
+                debug!(?err, "Error writing to metrics scraper");
+            }
+            Ok(PostAction::Remove)
+        },
+    ) {
+        warn!(?err, "Failed to add metrics scraper to the event loop.");
+    }
+}
+
+/// Formats `metrics` in the Prometheus text exposition format
+fn prometheus_text(metrics: &Metrics) -> String {
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, f64)>| {
+        let _ = writeln!(text, "# HELP cosmic_comp_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE cosmic_comp_{} {}", name, kind);
+        for (labels, value) in samples {
+            if labels.is_empty() {
+                let _ = writeln!(text, "cosmic_comp_{} {}", name, value);
+            } else {
+                let _ = writeln!(text, "cosmic_comp_{}{{{}}} {}", name, labels, value);
+            }
+        }
+    };
+
+    let outputs = |f: &dyn Fn(&OutputMetrics) -> f64| {
+        metrics
+            .outputs
+            .iter()
+            .map(|output| (format!("output=\"{}\"", escape(&output.name)), f(output)))
+            .collect::<Vec<_>>()
+    };
+    let quantiles = |f: &dyn Fn(&OutputMetrics) -> Percentiles| {
+        metrics
+            .outputs
+            .iter()
+            .flat_map(|output| {
+                let percentiles = f(output);
+                let name = escape(&output.name);
+                [
+                    ("0.5", percentiles.p50),
+                    ("0.9", percentiles.p90),
+                    ("0.99", percentiles.p99),
+                    ("1", percentiles.max),
+                ]
+                .into_iter()
+                .map(move |(quantile, ms)| {
+                    (
+                        format!("output=\"{}\",quantile=\"{}\"", name, quantile),
+                        ms / 1000.0,
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    metric(
+        "output_fps",
+        "gauge",
+        "Frames per second over the recent frames",
+        outputs(&|o| o.fps),
+    );
+    metric(
+        "output_frame_time_seconds",
+        "summary",
+        "Time from starting to render a frame until it was presented",
+        quantiles(&|o| o.frame_time),
+    );
+    metric(
+        "output_render_time_seconds",
+        "summary",
+        "Time spent rendering a frame",
+        quantiles(&|o| o.render_time),
+    );
+    metric(
+        "output_missed_deadlines",
+        "gauge",
+        "Recent frames presented at least one refresh later than scheduled",
+        outputs(&|o| o.missed_deadlines as f64),
+    );
+    metric(
+        "output_missed_deadlines_total",
+        "counter",
+        "Frames presented later than scheduled since the output was enabled",
+        outputs(&|o| o.missed_deadlines_total as f64),
+    );
+
+    let clients = |f: &dyn Fn(&ClientMetrics) -> f64| {
+        metrics
+            .clients
+            .iter()
+            .map(|client| {
+                let pid = client.pid.map(|pid| pid.to_string()).unwrap_or_default();
+                let app_id = escape(&client.app_ids.join(","));
+                (format!("pid=\"{}\",app_id=\"{}\"", pid, app_id), f(client))
+            })
+            .collect::<Vec<_>>()
+    };
+    metric(
+        "client_surfaces",
+        "gauge",
+        "Surfaces of a client",
+        clients(&|c| c.surfaces as f64),
+    );
+    metric(
+        "client_shm_buffers",
+        "gauge",
+        "Shared memory buffers attached to the surfaces of a client",
+        clients(&|c| c.shm_buffers as f64),
+    );
+    metric(
+        "client_dmabuf_buffers",
+        "gauge",
+        "Dmabufs attached to the surfaces of a client",
+        clients(&|c| c.dmabuf_buffers as f64),
+    );
+    metric(
+        "client_buffer_bytes",
+        "gauge",
+        "Size of the buffers attached to the surfaces of a client",
+        clients(&|c| c.buffer_bytes as f64),
+    );
+    metric(
+        "gpu_memory_estimate_bytes",
+        "gauge",
+        "Rough estimate of the GPU memory used for client buffers",
+        vec![(String::new(), metrics.gpu_memory_estimate as f64)],
+    );
+    metric(
+        "wakeups_total",
+        "counter",
+        "Event loop wakeups since the compositor started",
+        vec![(String::new(), metrics.wakeups as f64)],
+    );
+    metric(
+        "wakeups_per_second",
+        "gauge",
+        "Event loop wakeups per second over the last ten seconds or more",
+        vec![(String::new(), metrics.wakeups_per_second)],
+    );
+
+    text
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
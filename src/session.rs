@@ -6,6 +6,7 @@ use smithay::reexports::{
 };
 
 use anyhow::{anyhow, Context, Result};
+use cosmic_comp_config::PrivilegedProtocol;
 use sendfd::RecvWithFd;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -72,6 +73,12 @@ pub fn get_env(common: &Common) -> Result<HashMap<String, String>> {
     if let Some(display) = common.xwayland_state.as_ref().map(|s| s.display) {
         env.insert(String::from("DISPLAY"), format!(":{}", display));
     }
+    if let Some(ipc) = common.ipc.as_ref() {
+        env.insert(
+            String::from(crate::ipc::protocol::SOCKET_ENV),
+            ipc.path().to_string_lossy().into_owned(),
+        );
+    }
     Ok(env)
 }
 
@@ -147,7 +154,8 @@ pub fn setup_socket(handle: LoopHandle<State>, common: &Common) -> Result<()> {
                                                     let stream = unsafe { UnixStream::from_raw_fd(fd) };
                                                     let client_state = Arc::new(ClientState {
                                                         privileged: true,
-                                                        ..state.new_client_state()
+                                                        privileged_protocols: PrivilegedProtocol::ALL.to_vec(),
+                                                        ..state.new_client_state(&stream)
                                                     });
                                                     if let Err(err) = state.common.display_handle.insert_client(stream, client_state) {
                                                         warn!(?err, "Failed to add privileged client to display");
@@ -1,12 +1,13 @@
 use crate::{
     backend::render::{
-        element::{AsGlowRenderer, FromGlesError},
-        SplitRenderElements,
+        element::{AsGlowRenderer, ClippedRenderElement, EffectDamageElement, FromGlesError},
+        Key, ShadowShader, SplitRenderElements, Usage,
     },
     state::State,
-    utils::{iced::IcedElementInternal, prelude::*},
+    utils::{iced::IcedElementInternal, prelude::*, recover},
 };
 use calloop::LoopHandle;
+use cosmic_comp_config::{DimConfig, ShadowConfig};
 use id_tree::NodeId;
 use smithay::{
     backend::{
@@ -15,11 +16,11 @@ use smithay::{
             element::{
                 memory::MemoryRenderBufferRenderElement,
                 utils::{CropRenderElement, RelocateRenderElement, RescaleRenderElement},
-                Element, RenderElement, UnderlyingStorage,
+                Element, Id, RenderElement, UnderlyingStorage,
             },
             gles::element::PixelShaderElement,
             glow::GlowRenderer,
-            utils::{DamageSet, OpaqueRegions},
+            utils::{CommitCounter, DamageSet, OpaqueRegions},
             ImportAll, ImportMem, Renderer,
         },
     },
@@ -45,10 +46,14 @@ use window::CosmicWindowInternal;
 
 use std::{
     borrow::Cow,
+    cell::Cell,
     collections::HashMap,
     fmt,
     hash::Hash,
-    sync::{atomic::AtomicBool, Arc, Mutex, Weak},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock, Weak,
+    },
 };
 
 pub mod surface;
@@ -88,6 +93,73 @@ space_elements! {
     Stack=CosmicStack,
 }
 
+/// Rounding and dimming of windows, as configured in the shell
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WindowStyle {
+    /// Radius of the rounded corners in logical pixels, `0` disables rounding
+    pub corner_radius: u32,
+    pub dim_unfocused: DimConfig,
+}
+
+thread_local! {
+    /// Whether the workspace currently rendered on this thread dims unfocused windows
+    static DIMMING_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Enables or disables dimming for the elements rendered on this thread,
+/// until the guard is dropped
+pub struct DimmingGuard(bool);
+
+impl DimmingGuard {
+    pub fn new(enabled: bool) -> DimmingGuard {
+        DimmingGuard(DIMMING_ENABLED.with(|dimming| dimming.replace(enabled)))
+    }
+}
+
+impl Drop for DimmingGuard {
+    fn drop(&mut self) {
+        DIMMING_ENABLED.with(|dimming| dimming.set(self.0));
+    }
+}
+
+/// Dimming of an element, when it was last rendered
+#[derive(Debug)]
+struct DimState {
+    id: Id,
+    level: (f32, f32),
+    commit: CommitCounter,
+}
+
+impl Default for DimState {
+    fn default() -> Self {
+        DimState {
+            id: Id::new(),
+            level: (0., 0.),
+            commit: CommitCounter::default(),
+        }
+    }
+}
+
+/// Returns if `point` is inside of `geometry`, but outside of its rounded corners
+fn is_in_rounded_corner(
+    geometry: Rectangle<i32, Logical>,
+    radius: f64,
+    point: Point<f64, Logical>,
+) -> bool {
+    let geometry = geometry.to_f64();
+    if !geometry.contains(point) {
+        return false;
+    }
+
+    let radius = radius.min(geometry.size.w / 2.).min(geometry.size.h / 2.);
+    let x = (point.x - geometry.loc.x).min(geometry.loc.x + geometry.size.w - point.x);
+    let y = (point.y - geometry.loc.y).min(geometry.loc.y + geometry.size.h - point.y);
+    if x >= radius || y >= radius {
+        return false;
+    }
+    (radius - x).hypot(radius - y) > radius
+}
+
 #[derive(Debug, Clone)]
 pub struct MaximizedState {
     pub original_geometry: Rectangle<i32, Local>,
@@ -99,18 +171,22 @@ pub struct CosmicMapped {
     element: CosmicMappedInternal,
 
     // associated data
-    last_cursor_position: Arc<Mutex<HashMap<usize, Point<f64, Logical>>>>,
+    last_cursor_position: Arc<RwLock<HashMap<usize, Point<f64, Logical>>>>,
     pub maximized_state: Arc<Mutex<Option<MaximizedState>>>,
 
     //tiling
     pub tiling_node_id: Arc<Mutex<Option<NodeId>>>,
     //floating
-    pub(super) resize_state: Arc<Mutex<Option<ResizeState>>>,
+    pub(super) resize_state: Arc<RwLock<Option<ResizeState>>>,
     pub last_geometry: Arc<Mutex<Option<Rectangle<i32, Local>>>>,
     pub moved_since_mapped: Arc<AtomicBool>,
     pub floating_tiled: Arc<Mutex<Option<TiledCorners>>>,
     //sticky
     pub previous_layer: Arc<Mutex<Option<ManagedLayer>>>,
+    dim_state: Arc<RwLock<DimState>>,
+    style: Arc<RwLock<Arc<WindowStyle>>>,
+    /// Rendering the element panicked before, it isn't drawn anymore
+    render_failed: Arc<AtomicBool>,
 
     #[cfg(feature = "debug")]
     debug: Arc<Mutex<Option<smithay_egui::EguiState>>>,
@@ -148,6 +224,16 @@ impl Hash for CosmicMappedKey {
     }
 }
 
+impl CosmicMappedKey {
+    /// Identifier of the element, unique while it is alive
+    pub fn id(&self) -> u64 {
+        match &self.0 {
+            CosmicMappedKeyInner::Window(weak) => weak.as_ptr() as usize as u64,
+            CosmicMappedKeyInner::Stack(weak) => weak.as_ptr() as usize as u64,
+        }
+    }
+}
+
 impl IsAlive for CosmicMappedKey {
     fn alive(&self) -> bool {
         match &self.0 {
@@ -242,7 +328,7 @@ impl CosmicMapped {
 
     pub fn cursor_position(&self, seat: &Seat<State>) -> Option<Point<f64, Logical>> {
         self.last_cursor_position
-            .lock()
+            .read()
             .unwrap()
             .get(&seat.id())
             .cloned()
@@ -274,8 +360,6 @@ impl CosmicMapped {
             }
 
             if surface_type.contains(WindowSurfaceType::SUBSURFACE) {
-                use std::sync::atomic::Ordering;
-
                 let found = AtomicBool::new(false);
                 with_surface_tree_downward(
                     &toplevel,
@@ -657,6 +741,9 @@ impl CosmicMapped {
         }
     }
 
+    /// Render elements of the window, or none if rendering it panicked before.
+    ///
+    /// A window that panics every frame is left out, instead of freezing the whole output.
     pub fn split_render_elements<R, C>(
         &self,
         renderer: &mut R,
@@ -664,6 +751,31 @@ impl CosmicMapped {
         scale: smithay::utils::Scale<f64>,
         alpha: f32,
     ) -> SplitRenderElements<C>
+    where
+        R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+        C: From<CosmicMappedRenderElement<R>>,
+    {
+        if self.render_failed.load(Ordering::SeqCst) {
+            return SplitRenderElements::default();
+        }
+        recover::catch_panic("rendering a window, it is hidden from now on", || {
+            self.collect_render_elements(renderer, location, scale, alpha)
+        })
+        .unwrap_or_else(|| {
+            self.render_failed.store(true, Ordering::SeqCst);
+            SplitRenderElements::default()
+        })
+    }
+
+    fn collect_render_elements<R, C>(
+        &self,
+        renderer: &mut R,
+        location: smithay::utils::Point<i32, smithay::utils::Physical>,
+        scale: smithay::utils::Scale<f64>,
+        alpha: f32,
+    ) -> SplitRenderElements<C>
     where
         R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
         <R as Renderer>::TextureId: Send + Clone + 'static,
@@ -847,17 +959,40 @@ impl CosmicMapped {
             p_elements: Vec::new(),
         };
 
+        let dim = self.dim_level();
+        if let Some(damage) = self.dim_damage_element(dim, location, scale) {
+            elements
+                .w_elements
+                .push(CosmicMappedRenderElement::EffectDamage(damage).into());
+        }
+
         #[cfg_attr(not(feature = "debug"), allow(unused_mut))]
         elements.extend_map(
             match &self.element {
-                CosmicMappedInternal::Stack(s) => s
-                    .split_render_elements::<R, CosmicMappedRenderElement<R>>(
-                        renderer, location, scale, alpha,
-                    ),
-                CosmicMappedInternal::Window(w) => w
-                    .split_render_elements::<R, CosmicMappedRenderElement<R>>(
-                        renderer, location, scale, alpha,
-                    ),
+                CosmicMappedInternal::Stack(s) => {
+                    let stack_elements = s
+                        .split_render_elements::<R, stack::CosmicStackRenderElement<R>>(
+                            renderer, location, scale, alpha,
+                        );
+                    let mut clipped = SplitRenderElements::default();
+                    clipped.extend_map(
+                        self.clip_render_elements(renderer, stack_elements, location, scale, dim),
+                        CosmicMappedRenderElement::Stack,
+                    );
+                    clipped
+                }
+                CosmicMappedInternal::Window(w) => {
+                    let window_elements = w
+                        .split_render_elements::<R, window::CosmicWindowRenderElement<R>>(
+                            renderer, location, scale, alpha,
+                        );
+                    let mut clipped = SplitRenderElements::default();
+                    clipped.extend_map(
+                        self.clip_render_elements(renderer, window_elements, location, scale, dim),
+                        CosmicMappedRenderElement::Window,
+                    );
+                    clipped
+                }
                 _ => unreachable!(),
             },
             C::from,
@@ -866,6 +1001,174 @@ impl CosmicMapped {
         elements
     }
 
+    /// Render elements without any effects, for renderers without GL support
+    pub fn software_render_elements<R, C>(
+        &self,
+        renderer: &mut R,
+        location: smithay::utils::Point<i32, smithay::utils::Physical>,
+        scale: smithay::utils::Scale<f64>,
+        alpha: f32,
+    ) -> SplitRenderElements<C>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+        C: From<window::CosmicWindowRenderElement<R>> + From<stack::CosmicStackRenderElement<R>>,
+    {
+        match &self.element {
+            CosmicMappedInternal::Stack(s) => {
+                s.split_render_elements::<R, C>(renderer, location, scale, alpha)
+            }
+            CosmicMappedInternal::Window(w) => {
+                w.split_render_elements::<R, C>(renderer, location, scale, alpha)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Takes over the style of the shell, if it changed
+    pub(crate) fn set_style(&self, style: &Arc<WindowStyle>) {
+        if !Arc::ptr_eq(&self.style.read().unwrap(), style) {
+            *self.style.write().unwrap() = style.clone();
+        }
+    }
+
+    /// Radius of the rounded corners in logical pixels
+    pub fn corner_radius(&self) -> u32 {
+        self.style.read().unwrap().corner_radius
+    }
+
+    /// Area of the element, that has rounded corners, or `None` if it is drawn without rounding
+    pub fn rounded_geometry(&self) -> Option<Rectangle<i32, Logical>> {
+        (self.corner_radius() > 0 && !self.is_maximized(false) && !self.is_fullscreen(false))
+            .then(|| SpaceElement::geometry(self))
+    }
+
+    /// Shadow drawn below the element at `geometry`, if shadows are enabled and it isn't maximized
+    pub fn shadow_element<R: AsGlowRenderer>(
+        &self,
+        renderer: &R,
+        geometry: Rectangle<i32, Local>,
+        scale: f64,
+        alpha: f32,
+        config: &ShadowConfig,
+    ) -> Option<PixelShaderElement> {
+        if config.opacity <= 0.0 || self.is_maximized(false) || self.is_fullscreen(false) {
+            return None;
+        }
+
+        let radius = if self.rounded_geometry().is_some() {
+            self.corner_radius() as f32
+        } else {
+            0.0
+        };
+        Some(ShadowShader::element(
+            renderer,
+            Key::Window(Usage::Shadow, self.key()),
+            geometry,
+            radius,
+            alpha,
+            scale,
+            config,
+        ))
+    }
+
+    /// Darkening and desaturation of the element, which is dimmed while it isn't focused
+    fn dim_level(&self) -> (f32, f32) {
+        let style = self.style.read().unwrap().clone();
+        let config = &style.dim_unfocused;
+        if !DIMMING_ENABLED.with(Cell::get)
+            || (config.strength <= 0. && config.desaturate <= 0.)
+            || self.is_activated(false)
+            || config
+                .exceptions
+                .iter()
+                .any(|app_id| *app_id == self.active_window().app_id())
+        {
+            return (0., 0.);
+        }
+        (
+            config.strength.clamp(0., 1.),
+            config.desaturate.clamp(0., 1.),
+        )
+    }
+
+    /// Damages the element, when its dimming changes.
+    ///
+    /// The element is only emitted while dimmed or just after, so it doesn't keep
+    /// undimmed windows from being scanned out.
+    fn dim_damage_element(
+        &self,
+        level: (f32, f32),
+        location: Point<i32, Physical>,
+        scale: Scale<f64>,
+    ) -> Option<EffectDamageElement> {
+        // the level rarely changes, so only take the write lock when it did
+        let (previous, id, mut commit) = {
+            let state = self.dim_state.read().unwrap();
+            (state.level, state.id.clone(), state.commit)
+        };
+        if previous != level {
+            let mut state = self.dim_state.write().unwrap();
+            state.level = level;
+            state.commit.increment();
+            commit = state.commit;
+        }
+        if previous == (0., 0.) && level == (0., 0.) {
+            return None;
+        }
+
+        let bbox = self.bbox();
+        Some(EffectDamageElement::new(
+            id,
+            commit,
+            Rectangle::from_loc_and_size(
+                location + bbox.loc.to_physical_precise_round(scale),
+                bbox.size.to_physical_precise_round(scale),
+            ),
+        ))
+    }
+
+    /// Clips the window elements to the rounded corners and dims them, popups are left as they are
+    fn clip_render_elements<R, E>(
+        &self,
+        renderer: &R,
+        elements: SplitRenderElements<E>,
+        location: Point<i32, Physical>,
+        scale: Scale<f64>,
+        (dim, desaturate): (f32, f32),
+    ) -> SplitRenderElements<ClippedRenderElement<E>>
+    where
+        R: AsGlowRenderer,
+        E: Element,
+    {
+        let geo = SpaceElement::geometry(self);
+        let geometry = Rectangle::from_loc_and_size(
+            location + geo.loc.to_physical_precise_round(scale),
+            geo.size.to_physical_precise_round(scale),
+        );
+        let radius = if self.rounded_geometry().is_some() {
+            (self.corner_radius() as f64 * scale.x) as f32
+        } else {
+            0.
+        };
+
+        SplitRenderElements {
+            w_elements: elements
+                .w_elements
+                .into_iter()
+                .map(|elem| {
+                    ClippedRenderElement::new(renderer, elem, geometry, radius, scale)
+                        .with_dim(renderer, dim, desaturate)
+                })
+                .collect(),
+            p_elements: elements
+                .p_elements
+                .into_iter()
+                .map(ClippedRenderElement::unclipped)
+                .collect(),
+        }
+    }
+
     pub(crate) fn update_theme(&self, theme: cosmic::Theme) {
         match &self.element {
             CosmicMappedInternal::Window(w) => w.set_theme(theme),
@@ -906,6 +1209,12 @@ impl SpaceElement for CosmicMapped {
         SpaceElement::bbox(&self.element)
     }
     fn is_in_input_region(&self, point: &Point<f64, Logical>) -> bool {
+        // let clicks into the rounded-off corners pass through to whatever is below
+        if let Some(geometry) = self.rounded_geometry() {
+            if is_in_rounded_corner(geometry, self.corner_radius() as f64, *point) {
+                return false;
+            }
+        }
         SpaceElement::is_in_input_region(&self.element, point)
     }
     fn set_activate(&self, activated: bool) {
@@ -1020,14 +1329,17 @@ impl From<CosmicWindow> for CosmicMapped {
     fn from(w: CosmicWindow) -> Self {
         CosmicMapped {
             element: CosmicMappedInternal::Window(w),
-            last_cursor_position: Arc::new(Mutex::new(HashMap::new())),
+            last_cursor_position: Arc::new(RwLock::new(HashMap::new())),
             maximized_state: Arc::new(Mutex::new(None)),
             tiling_node_id: Arc::new(Mutex::new(None)),
-            resize_state: Arc::new(Mutex::new(None)),
+            resize_state: Arc::new(RwLock::new(None)),
             last_geometry: Arc::new(Mutex::new(None)),
             moved_since_mapped: Arc::new(AtomicBool::new(false)),
             floating_tiled: Arc::new(Mutex::new(None)),
             previous_layer: Arc::new(Mutex::new(None)),
+            dim_state: Arc::new(RwLock::new(DimState::default())),
+            style: Arc::new(RwLock::new(Arc::default())),
+            render_failed: Arc::new(AtomicBool::new(false)),
             #[cfg(feature = "debug")]
             debug: Arc::new(Mutex::new(None)),
         }
@@ -1038,14 +1350,17 @@ impl From<CosmicStack> for CosmicMapped {
     fn from(s: CosmicStack) -> Self {
         CosmicMapped {
             element: CosmicMappedInternal::Stack(s),
-            last_cursor_position: Arc::new(Mutex::new(HashMap::new())),
+            last_cursor_position: Arc::new(RwLock::new(HashMap::new())),
             maximized_state: Arc::new(Mutex::new(None)),
             tiling_node_id: Arc::new(Mutex::new(None)),
-            resize_state: Arc::new(Mutex::new(None)),
+            resize_state: Arc::new(RwLock::new(None)),
             last_geometry: Arc::new(Mutex::new(None)),
             moved_since_mapped: Arc::new(AtomicBool::new(false)),
             floating_tiled: Arc::new(Mutex::new(None)),
             previous_layer: Arc::new(Mutex::new(None)),
+            dim_state: Arc::new(RwLock::new(DimState::default())),
+            style: Arc::new(RwLock::new(Arc::default())),
+            render_failed: Arc::new(AtomicBool::new(false)),
             #[cfg(feature = "debug")]
             debug: Arc::new(Mutex::new(None)),
         }
@@ -1057,32 +1372,49 @@ where
     R: Renderer + ImportAll + ImportMem,
     <R as Renderer>::TextureId: 'static,
 {
-    Stack(self::stack::CosmicStackRenderElement<R>),
-    Window(self::window::CosmicWindowRenderElement<R>),
+    Stack(ClippedRenderElement<self::stack::CosmicStackRenderElement<R>>),
+    Window(ClippedRenderElement<self::window::CosmicWindowRenderElement<R>>),
     TiledStack(
         CropRenderElement<
-            RelocateRenderElement<RescaleRenderElement<self::stack::CosmicStackRenderElement<R>>>,
+            RelocateRenderElement<
+                RescaleRenderElement<
+                    ClippedRenderElement<self::stack::CosmicStackRenderElement<R>>,
+                >,
+            >,
         >,
     ),
     TiledWindow(
         CropRenderElement<
-            RelocateRenderElement<RescaleRenderElement<self::window::CosmicWindowRenderElement<R>>>,
+            RelocateRenderElement<
+                RescaleRenderElement<
+                    ClippedRenderElement<self::window::CosmicWindowRenderElement<R>>,
+                >,
+            >,
         >,
     ),
     TiledOverlay(
         CropRenderElement<RelocateRenderElement<RescaleRenderElement<PixelShaderElement>>>,
     ),
     MovingStack(
-        RelocateRenderElement<RescaleRenderElement<self::stack::CosmicStackRenderElement<R>>>,
+        RelocateRenderElement<
+            RescaleRenderElement<ClippedRenderElement<self::stack::CosmicStackRenderElement<R>>>,
+        >,
     ),
     MovingWindow(
-        RelocateRenderElement<RescaleRenderElement<self::window::CosmicWindowRenderElement<R>>>,
+        RelocateRenderElement<
+            RescaleRenderElement<ClippedRenderElement<self::window::CosmicWindowRenderElement<R>>>,
+        >,
+    ),
+    GrabbedStack(
+        RescaleRenderElement<ClippedRenderElement<self::stack::CosmicStackRenderElement<R>>>,
+    ),
+    GrabbedWindow(
+        RescaleRenderElement<ClippedRenderElement<self::window::CosmicWindowRenderElement<R>>>,
     ),
-    GrabbedStack(RescaleRenderElement<self::stack::CosmicStackRenderElement<R>>),
-    GrabbedWindow(RescaleRenderElement<self::window::CosmicWindowRenderElement<R>>),
     FocusIndicator(PixelShaderElement),
     Overlay(PixelShaderElement),
     StackHoverIndicator(MemoryRenderBufferRenderElement<R>),
+    EffectDamage(EffectDamageElement),
     #[cfg(feature = "debug")]
     Egui(TextureRenderElement<GlesTexture>),
 }
@@ -1106,6 +1438,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.id(),
             CosmicMappedRenderElement::Overlay(elem) => elem.id(),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.id(),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.id(),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.id(),
         }
@@ -1125,6 +1458,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.current_commit(),
             CosmicMappedRenderElement::Overlay(elem) => elem.current_commit(),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.current_commit(),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.current_commit(),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.current_commit(),
         }
@@ -1144,6 +1478,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.src(),
             CosmicMappedRenderElement::Overlay(elem) => elem.src(),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.src(),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.src(),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.src(),
         }
@@ -1163,6 +1498,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.geometry(scale),
             CosmicMappedRenderElement::Overlay(elem) => elem.geometry(scale),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.geometry(scale),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.geometry(scale),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.geometry(scale),
         }
@@ -1182,6 +1518,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.location(scale),
             CosmicMappedRenderElement::Overlay(elem) => elem.location(scale),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.location(scale),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.location(scale),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.location(scale),
         }
@@ -1201,6 +1538,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.transform(),
             CosmicMappedRenderElement::Overlay(elem) => elem.transform(),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.transform(),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.transform(),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.transform(),
         }
@@ -1226,6 +1564,7 @@ where
             CosmicMappedRenderElement::StackHoverIndicator(elem) => {
                 elem.damage_since(scale, commit)
             }
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.damage_since(scale, commit),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.damage_since(scale, commit),
         }
@@ -1245,6 +1584,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.opaque_regions(scale),
             CosmicMappedRenderElement::Overlay(elem) => elem.opaque_regions(scale),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.opaque_regions(scale),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.opaque_regions(scale),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.opaque_regions(scale),
         }
@@ -1264,6 +1604,7 @@ where
             CosmicMappedRenderElement::FocusIndicator(elem) => elem.alpha(),
             CosmicMappedRenderElement::Overlay(elem) => elem.alpha(),
             CosmicMappedRenderElement::StackHoverIndicator(elem) => elem.alpha(),
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.alpha(),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => elem.alpha(),
         }
@@ -1339,6 +1680,9 @@ where
             CosmicMappedRenderElement::StackHoverIndicator(elem) => {
                 elem.draw(frame, src, dst, damage, opaque_regions)
             }
+            CosmicMappedRenderElement::EffectDamage(elem) => {
+                elem.draw(frame, src, dst, damage, opaque_regions)
+            }
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => {
                 let glow_frame = R::glow_frame_mut(frame);
@@ -1377,6 +1721,7 @@ where
             CosmicMappedRenderElement::StackHoverIndicator(elem) => {
                 elem.underlying_storage(renderer)
             }
+            CosmicMappedRenderElement::EffectDamage(elem) => elem.underlying_storage(renderer),
             #[cfg(feature = "debug")]
             CosmicMappedRenderElement::Egui(elem) => {
                 let glow_renderer = renderer.glow_renderer_mut();
@@ -1398,7 +1743,7 @@ where
     CosmicMappedRenderElement<R>: RenderElement<R>,
 {
     fn from(elem: stack::CosmicStackRenderElement<R>) -> Self {
-        CosmicMappedRenderElement::Stack(elem)
+        CosmicMappedRenderElement::Stack(ClippedRenderElement::unclipped(elem))
     }
 }
 impl<R> From<window::CosmicWindowRenderElement<R>> for CosmicMappedRenderElement<R>
@@ -1408,7 +1753,7 @@ where
     CosmicMappedRenderElement<R>: RenderElement<R>,
 {
     fn from(elem: window::CosmicWindowRenderElement<R>) -> Self {
-        CosmicMappedRenderElement::Window(elem)
+        CosmicMappedRenderElement::Window(ClippedRenderElement::unclipped(elem))
     }
 }
 
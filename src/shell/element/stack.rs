@@ -14,6 +14,7 @@ use crate::{
         iced::{IcedElement, Program},
         prelude::*,
     },
+    wayland::protocols::toplevel_icon::ToplevelIcon,
 };
 use calloop::LoopHandle;
 use cosmic::{
@@ -31,8 +32,8 @@ use smithay::{
         input::KeyState,
         renderer::{
             element::{
-                memory::MemoryRenderBufferRenderElement, surface::WaylandSurfaceRenderElement,
-                AsRenderElements,
+                memory::MemoryRenderBufferRenderElement, solid::SolidColorRenderElement,
+                surface::WaylandSurfaceRenderElement, AsRenderElements,
             },
             ImportAll, ImportMem, Renderer,
         },
@@ -105,6 +106,42 @@ pub struct CosmicStackInternal {
     last_seat: Arc<Mutex<Option<(Seat<State>, Serial)>>>,
     geometry: Arc<Mutex<Option<Rectangle<i32, Global>>>>,
     mask: Arc<Mutex<Option<tiny_skia::Mask>>>,
+    /// Contents of the tabs, as last drawn by the header
+    last_tabs: Arc<Mutex<Vec<TabContent>>>,
+}
+
+/// Everything a tab of the header is drawn from
+#[derive(Debug, Clone)]
+struct TabContent {
+    title: String,
+    app_id: String,
+    icon: Option<Arc<ToplevelIcon>>,
+    activated: bool,
+}
+
+impl TabContent {
+    fn new(window: &CosmicSurface) -> TabContent {
+        TabContent {
+            title: window.title(),
+            app_id: window.app_id(),
+            icon: window.icon(),
+            activated: window.is_activated(false),
+        }
+    }
+}
+
+impl PartialEq for TabContent {
+    fn eq(&self, other: &Self) -> bool {
+        let icon_eq = match (&self.icon, &other.icon) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.title == other.title
+            && self.app_id == other.app_id
+            && self.activated == other.activated
+            && icon_eq
+    }
 }
 
 impl CosmicStackInternal {
@@ -143,6 +180,7 @@ impl CosmicStack {
         }
 
         let width = windows[0].geometry().size.w;
+        let last_tabs = windows.iter().map(TabContent::new).collect();
         CosmicStack(IcedElement::new(
             CosmicStackInternal {
                 windows: Arc::new(Mutex::new(windows)),
@@ -158,6 +196,7 @@ impl CosmicStack {
                 last_seat: Arc::new(Mutex::new(None)),
                 geometry: Arc::new(Mutex::new(None)),
                 mask: Arc::new(Mutex::new(None)),
+                last_tabs: Arc::new(Mutex::new(last_tabs)),
             },
             (width, TAB_HEIGHT),
             handle,
@@ -872,11 +911,7 @@ impl Program for CosmicStackInternal {
                 .icon()
                 .style(if group_focused {
                     theme::Svg::custom(|theme| iced_widget::svg::Appearance {
-                        color: Some(if theme.cosmic().is_dark {
-                            Color::BLACK
-                        } else {
-                            Color::WHITE
-                        }),
+                        color: Some(Color::from(theme.cosmic().accent.on)),
                     })
                 } else {
                     theme::Svg::Default
@@ -895,7 +930,7 @@ impl Program for CosmicStackInternal {
                         user_data.insert_if_missing(Id::unique);
                         Tab::new(
                             w.title(),
-                            w.app_id(),
+                            w.icon_handle(16),
                             user_data.get::<Id>().unwrap().clone(),
                         )
                         .on_press(Message::PotentialTabDragStart(i))
@@ -1072,7 +1107,7 @@ impl SpaceElement for CosmicStack {
     }
     fn refresh(&self) {
         SpaceElement::refresh(&self.0);
-        self.0.with_program(|p| {
+        if self.0.with_program(|p| {
             let mut windows = p.windows.lock().unwrap();
 
             // don't let the stack become empty
@@ -1107,7 +1142,17 @@ impl SpaceElement for CosmicStack {
 
                 SpaceElement::refresh(w)
             });
-        });
+
+            let tabs = windows.iter().map(TabContent::new).collect::<Vec<_>>();
+            let mut last_tabs = p.last_tabs.lock().unwrap();
+            let tabs_changed = *last_tabs != tabs;
+            if tabs_changed {
+                *last_tabs = tabs;
+            }
+            tabs_changed
+        }) {
+            self.0.force_update();
+        }
     }
 }
 
@@ -1499,4 +1544,5 @@ render_elements! {
     pub CosmicStackRenderElement<R> where R: ImportAll + ImportMem;
     Header = MemoryRenderBufferRenderElement<R>,
     Window = WaylandSurfaceRenderElement<R>,
+    SolidColor = SolidColorRenderElement,
 }
@@ -14,7 +14,10 @@ use cosmic::{
     },
     iced_widget::scrollable::AbsoluteOffset,
     theme,
-    widget::{icon::from_name, Icon},
+    widget::{
+        icon::{self, from_name},
+        Icon,
+    },
     Apply,
 };
 
@@ -22,26 +25,12 @@ use super::tab_text::tab_text;
 
 /// The background color of the stack tab header.
 pub(super) fn primary_container_color(theme: &cosmic::cosmic_theme::Theme) -> Color {
-    const PRIMARY_CONTAINER_DARK: Color = Color::from_rgba(0.149, 0.149, 0.149, 1.0);
-    const PRIMARY_CONTAINER_LIGHT: Color = Color::from_rgba(0.894, 0.894, 0.894, 1.0);
-
-    if theme.is_dark {
-        PRIMARY_CONTAINER_DARK
-    } else {
-        PRIMARY_CONTAINER_LIGHT
-    }
+    theme.primary.base.into()
 }
 
 /// The background color for the selected stack tab.
 pub(super) fn selected_state_color(theme: &cosmic::cosmic_theme::Theme) -> Color {
-    const SELECTED_STATE_DARK: Color = Color::from_rgba(0.195, 0.195, 0.195, 1.0);
-    const SELECTED_STATE_LIGHT: Color = Color::from_rgba(0.8344, 0.8344, 0.8344, 1.0);
-
-    if theme.is_dark {
-        SELECTED_STATE_DARK
-    } else {
-        SELECTED_STATE_LIGHT
-    }
+    theme.primary.component.selected.into()
 }
 
 #[derive(Clone, Copy)]
@@ -153,10 +142,10 @@ pub struct Tab<Message: TabMessage> {
 }
 
 impl<Message: TabMessage + 'static> Tab<Message> {
-    pub fn new(title: impl Into<String>, app_id: impl Into<String>, id: Id) -> Self {
+    pub fn new(title: impl Into<String>, app_icon: icon::Handle, id: Id) -> Self {
         Tab {
             id,
-            app_icon: from_name(app_id.into()).size(16).icon(),
+            app_icon: icon::icon(app_icon).size(16),
             title: title.into(),
             font: cosmic::font::FONT,
             close_message: None,
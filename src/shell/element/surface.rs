@@ -1,17 +1,25 @@
 use std::{
     borrow::Cow,
-    sync::atomic::{AtomicBool, Ordering},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     time::Duration,
 };
 
+use cosmic::widget::icon;
+
 use smithay::{
     backend::renderer::{
         element::{
             self,
+            solid::SolidColorRenderElement,
             surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
             utils::select_dmabuf_feedback,
-            AsRenderElements, RenderElementStates,
+            AsRenderElements, Element, Id, RenderElementStates,
         },
+        utils::with_renderer_surface_state,
         ImportAll, Renderer,
     },
     desktop::{
@@ -34,9 +42,14 @@ use smithay::{
     },
     utils::{user_data::UserDataMap, IsAlive, Logical, Rectangle, Serial, Size},
     wayland::{
-        compositor::{with_states, SurfaceData},
+        compositor::{
+            with_states, with_surface_tree_downward, BufferAssignment, SurfaceAttributes,
+            SurfaceData, TraversalAction,
+        },
+        content_type::ContentType,
         seat::WaylandFocus,
         shell::xdg::{SurfaceCachedState, ToplevelSurface, XdgToplevelSurfaceData},
+        single_pixel_buffer::get_single_pixel_buffer,
     },
     xwayland::{xwm::X11Relatable, X11Surface},
 };
@@ -45,7 +58,10 @@ use crate::{
     backend::render::SplitRenderElements,
     state::{State, SurfaceDmabufFeedback},
     utils::prelude::*,
-    wayland::handlers::decoration::PreferredDecorationMode,
+    wayland::{
+        handlers::{content_type::surface_content_type, decoration::PreferredDecorationMode},
+        protocols::toplevel_icon::{toplevel_icon, ToplevelIcon},
+    },
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -87,7 +103,68 @@ struct Minimized(AtomicBool);
 pub const SSD_HEIGHT: i32 = 36;
 pub const RESIZE_BORDER: i32 = 10;
 
+/// Identifier of a window, see [`CosmicSurface::id`]
+struct WindowId(u64);
+
+static NEXT_WINDOW_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Serial of the last configure a toplevel committed a buffer for,
+/// or acked without its size changing
+#[derive(Default)]
+struct BufferSerial(Mutex<Option<Serial>>);
+
+/// Remembers which configure a newly attached buffer of a toplevel was drawn for.
+/// Configures keeping the size don't need a new buffer, committing the ack is enough.
+///
+/// Has to be called before the commit is applied, so blocked commits are tracked as well.
+pub fn track_buffer_serial(surface: &WlSurface) {
+    with_states(surface, |states| {
+        let Some(attrs) = states.data_map.get::<XdgToplevelSurfaceData>() else {
+            return;
+        };
+        let new_buffer = matches!(
+            states
+                .cached_state
+                .get::<SurfaceAttributes>()
+                .pending()
+                .buffer,
+            Some(BufferAssignment::NewBuffer(_))
+        );
+        let attrs = attrs.lock().unwrap();
+        // an ack committed for the first time, that keeps the current size
+        let same_size = attrs.configure_serial != attrs.current_serial
+            && attrs
+                .last_acked
+                .as_ref()
+                .is_some_and(|acked| acked.size == attrs.current.size);
+        if !new_buffer && !same_size {
+            return;
+        }
+
+        let serial = attrs.configure_serial;
+        std::mem::drop(attrs);
+        states
+            .data_map
+            .insert_if_missing_threadsafe(BufferSerial::default);
+        *states
+            .data_map
+            .get::<BufferSerial>()
+            .unwrap()
+            .0
+            .lock()
+            .unwrap() = serial;
+    })
+}
+
 impl CosmicSurface {
+    /// Identifier of the window, unique for the lifetime of the compositor
+    pub fn id(&self) -> u64 {
+        self.0
+            .user_data()
+            .get_or_insert_threadsafe(|| WindowId(NEXT_WINDOW_ID.fetch_add(1, Ordering::SeqCst)))
+            .0
+    }
+
     pub fn title(&self) -> String {
         match self.0.underlying_surface() {
             WindowSurface::Wayland(toplevel) => with_states(toplevel.wl_surface(), |states| {
@@ -122,6 +199,33 @@ impl CosmicSurface {
         }
     }
 
+    /// Icon set by the client, if any
+    pub fn icon(&self) -> Option<Arc<ToplevelIcon>> {
+        match self.0.underlying_surface() {
+            WindowSurface::Wayland(toplevel) => toplevel_icon(toplevel.wl_surface()),
+            WindowSurface::X11(_surface) => None,
+        }
+    }
+
+    /// Icon to display for this window at `size`,
+    /// falling back to the icon theme entry of the app-id.
+    pub fn icon_handle(&self, size: u16) -> icon::Handle {
+        if let Some(client_icon) = self.icon() {
+            if let Some(name) = client_icon.name.as_ref() {
+                return icon::from_name(name.clone()).size(size).handle();
+            }
+            // pick the buffer for scale 2, downscaling still looks fine on low-dpi outputs
+            if let Some(buffer) = client_icon.best_buffer(size as i32, 2) {
+                return icon::from_raster_pixels(
+                    buffer.size as u32,
+                    buffer.size as u32,
+                    buffer.pixels.clone(),
+                );
+            }
+        }
+        icon::from_name(self.app_id()).size(size).handle()
+    }
+
     pub fn pending_size(&self) -> Option<Size<i32, Logical>> {
         match self.0.underlying_surface() {
             WindowSurface::Wayland(toplevel) => toplevel.with_pending_state(|state| state.size),
@@ -448,20 +552,16 @@ impl CosmicSurface {
         })
     }
 
-    pub fn serial_acked(&self, serial: &Serial) -> bool {
+    /// Returns `true` if the client committed a buffer for the configure with the given serial, or a later one.
+    /// Configures, that didn't change the size, only need to be acked.
+    pub fn serial_committed(&self, serial: &Serial) -> bool {
         match self.0.underlying_surface() {
             WindowSurface::Wayland(toplevel) => with_states(toplevel.wl_surface(), |states| {
-                let attrs = states
+                states
                     .data_map
-                    .get::<XdgToplevelSurfaceData>()
-                    .unwrap()
-                    .lock()
-                    .unwrap();
-                attrs
-                    .configure_serial
-                    .as_ref()
-                    .map(|s| s >= serial)
-                    .unwrap_or(false)
+                    .get::<BufferSerial>()
+                    .and_then(|committed| *committed.0.lock().unwrap())
+                    .is_some_and(|committed| committed >= *serial)
             }),
             WindowSurface::X11(_surface) => true,
         }
@@ -568,7 +668,7 @@ impl CosmicSurface {
     where
         R: Renderer + ImportAll,
         <R as Renderer>::TextureId: Clone + 'static,
-        C: From<WaylandSurfaceRenderElement<R>>,
+        C: From<WaylandSurfaceRenderElement<R>> + From<SolidColorRenderElement>,
     {
         match self.0.underlying_surface() {
             WindowSurface::Wayland(toplevel) => {
@@ -579,7 +679,7 @@ impl CosmicSurface {
                         let offset = (self.0.geometry().loc + popup_offset - popup.geometry().loc)
                             .to_physical_precise_round(scale);
 
-                        render_elements_from_surface_tree(
+                        surface_tree_render_elements(
                             renderer,
                             popup.wl_surface(),
                             location + offset,
@@ -590,13 +690,13 @@ impl CosmicSurface {
                     })
                     .collect();
 
-                let w_elements = render_elements_from_surface_tree(
+                let w_elements = surface_tree_render_elements(
                     renderer,
                     surface,
                     location,
                     scale,
                     alpha,
-                    element::Kind::Unspecified,
+                    self.element_kind(),
                 );
 
                 SplitRenderElements {
@@ -611,6 +711,26 @@ impl CosmicSurface {
         }
     }
 
+    /// Fullscreen windows, videos and games are worth trying to put on a hardware plane.
+    ///
+    /// This is only a hint. Which candidates end up on the primary or an overlay plane is
+    /// decided by the drm compositor of smithay, testing them with atomic commits. Nothing else
+    /// is assigned to planes here, e.g. subtitles or picture-in-picture windows, and the cursor
+    /// has its own plane already.
+    fn element_kind(&self) -> element::Kind {
+        let content_type = self
+            .wl_surface()
+            .map(|surface| surface_content_type(&surface))
+            .unwrap_or(ContentType::None);
+        if self.is_fullscreen(false)
+            || matches!(content_type, ContentType::Video | ContentType::Game)
+        {
+            element::Kind::ScanoutCandidate
+        } else {
+            element::Kind::Unspecified
+        }
+    }
+
     pub fn x11_surface(&self) -> Option<&X11Surface> {
         self.0.x11_surface()
     }
@@ -742,6 +862,64 @@ impl X11Relatable for CosmicSurface {
     }
 }
 
+/// Renders the surfaces of `surface`s tree like [`render_elements_from_surface_tree`],
+/// but surfaces showing a single-pixel buffer become solid colors instead of textures.
+fn surface_tree_render_elements<R, C>(
+    renderer: &mut R,
+    surface: &WlSurface,
+    location: smithay::utils::Point<i32, smithay::utils::Physical>,
+    scale: smithay::utils::Scale<f64>,
+    alpha: f32,
+    kind: element::Kind,
+) -> Vec<C>
+where
+    R: Renderer + ImportAll,
+    <R as Renderer>::TextureId: Clone + 'static,
+    C: From<WaylandSurfaceRenderElement<R>> + From<SolidColorRenderElement>,
+{
+    let colors = single_pixel_colors(surface);
+    render_elements_from_surface_tree::<R, WaylandSurfaceRenderElement<R>>(
+        renderer, surface, location, scale, alpha, kind,
+    )
+    .into_iter()
+    .map(|elem| match colors.get(elem.id()) {
+        Some(color) => SolidColorRenderElement::new(
+            elem.id().clone(),
+            elem.geometry(scale),
+            elem.current_commit(),
+            color.map(|channel| channel * alpha),
+            elem.kind(),
+        )
+        .into(),
+        None => elem.into(),
+    })
+    .collect()
+}
+
+/// Premultiplied colors of the surfaces in `surface`s tree, that show a single-pixel buffer
+fn single_pixel_colors(surface: &WlSurface) -> HashMap<Id, [f32; 4]> {
+    let mut surfaces = Vec::new();
+    with_surface_tree_downward(
+        surface,
+        (),
+        |_, _, _| TraversalAction::DoChildren(()),
+        |surface, _, _| surfaces.push(surface.clone()),
+        |_, _, _| true,
+    );
+
+    surfaces
+        .into_iter()
+        .filter_map(|surface| {
+            let color = with_renderer_surface_state(&surface, |state| {
+                let pixel = get_single_pixel_buffer(state.buffer()?).ok()?;
+                Some([pixel.r, pixel.g, pixel.b, pixel.a].map(|c| c as f32 / u32::MAX as f32))
+            })
+            .flatten()?;
+            Some((Id::from_wayland_resource(&surface), color))
+        })
+        .collect()
+}
+
 impl<R> AsRenderElements<R> for CosmicSurface
 where
     R: Renderer + ImportAll,
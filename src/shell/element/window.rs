@@ -12,12 +12,13 @@ use crate::{
         iced::{IcedElement, Program},
         prelude::*,
     },
+    wayland::protocols::toplevel_icon::ToplevelIcon,
 };
 use calloop::LoopHandle;
 use cosmic::{
     config::Density,
     iced::{Color, Command},
-    widget::mouse_area,
+    widget::{container, icon, mouse_area},
     Apply,
 };
 use smithay::{
@@ -25,8 +26,8 @@ use smithay::{
         input::KeyState,
         renderer::{
             element::{
-                memory::MemoryRenderBufferRenderElement, surface::WaylandSurfaceRenderElement,
-                AsRenderElements,
+                memory::MemoryRenderBufferRenderElement, solid::SolidColorRenderElement,
+                surface::WaylandSurfaceRenderElement, AsRenderElements,
             },
             ImportAll, ImportMem, Renderer,
         },
@@ -87,6 +88,10 @@ pub struct CosmicWindowInternal {
     pointer_entered: Arc<AtomicU8>,
     last_seat: Arc<Mutex<Option<(Seat<State>, Serial)>>>,
     last_title: Arc<Mutex<String>>,
+    last_icon: Arc<Mutex<Option<Arc<ToplevelIcon>>>>,
+    /// Activated and maximized state of the window, as last drawn by the header
+    last_activated: Arc<AtomicBool>,
+    last_maximized: Arc<AtomicBool>,
 }
 
 impl fmt::Debug for CosmicWindowInternal {
@@ -192,6 +197,9 @@ impl CosmicWindow {
         let window = window.into();
         let width = window.geometry().size.w;
         let last_title = window.title();
+        let last_icon = window.icon();
+        let last_activated = window.is_activated(false);
+        let last_maximized = window.is_maximized(false);
         CosmicWindow(IcedElement::new(
             CosmicWindowInternal {
                 window,
@@ -199,6 +207,9 @@ impl CosmicWindow {
                 pointer_entered: Arc::new(AtomicU8::new(0)),
                 last_seat: Arc::new(Mutex::new(None)),
                 last_title: Arc::new(Mutex::new(last_title)),
+                last_icon: Arc::new(Mutex::new(last_icon)),
+                last_activated: Arc::new(AtomicBool::new(last_activated)),
+                last_maximized: Arc::new(AtomicBool::new(last_maximized)),
             },
             (width, SSD_HEIGHT),
             handle,
@@ -509,7 +520,14 @@ impl Program for CosmicWindowInternal {
             .on_close(Message::Close)
             .focused(self.window.is_activated(false))
             .density(Density::Compact)
-            .on_double_click(Message::Maximize);
+            .on_double_click(Message::Maximize)
+            .start(
+                icon(self.window.icon_handle(16))
+                    .size(16)
+                    .apply(container)
+                    .padding([0, 4])
+                    .center_y(),
+            );
 
         if cosmic::config::show_minimize() {
             header = header.on_minimize(Message::Minimize);
@@ -588,12 +606,28 @@ impl SpaceElement for CosmicWindow {
             SpaceElement::refresh(&p.window);
             let title = p.window.title();
             let mut last_title = p.last_title.lock().unwrap();
-            if *last_title != title {
+            let title_changed = *last_title != title;
+            if title_changed {
                 *last_title = title;
-                true
-            } else {
-                false
             }
+
+            let icon = p.window.icon();
+            let mut last_icon = p.last_icon.lock().unwrap();
+            let icon_changed = match (&*last_icon, &icon) {
+                (Some(last), Some(icon)) => !Arc::ptr_eq(last, icon),
+                (None, None) => false,
+                _ => true,
+            };
+            if icon_changed {
+                *last_icon = icon;
+            }
+
+            let activated = p.window.is_activated(false);
+            let maximized = p.window.is_maximized(false);
+            let activated_changed = p.last_activated.swap(activated, Ordering::SeqCst) != activated;
+            let maximized_changed = p.last_maximized.swap(maximized, Ordering::SeqCst) != maximized;
+
+            title_changed || icon_changed || activated_changed || maximized_changed
         }) {
             self.0.force_update();
         }
@@ -885,4 +919,5 @@ render_elements! {
     pub CosmicWindowRenderElement<R> where R: ImportAll + ImportMem;
     Header = MemoryRenderBufferRenderElement<R>,
     Window = WaylandSurfaceRenderElement<R>,
+    SolidColor = SolidColorRenderElement,
 }
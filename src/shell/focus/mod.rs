@@ -2,7 +2,9 @@ use crate::{
     shell::{element::CosmicMapped, Shell},
     state::Common,
     utils::prelude::*,
-    wayland::handlers::xdg_shell::PopupGrabData,
+    wayland::handlers::{
+        pointer_constraints::release_unfocused_constraints, xdg_shell::PopupGrabData,
+    },
 };
 use indexmap::IndexSet;
 use smithay::{
@@ -360,6 +362,7 @@ impl Common {
                 set_data_device_focus(&state.common.display_handle, &seat, client.clone());
                 set_primary_focus(&state.common.display_handle, &seat, client);
             }
+            release_unfocused_constraints(state, seat);
         }
 
         state.common.shell.write().unwrap().update_active()
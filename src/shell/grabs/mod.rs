@@ -71,6 +71,10 @@ mod menu;
 pub use self::menu::*;
 mod moving;
 pub use self::moving::*;
+mod screenshot;
+pub use self::screenshot::*;
+mod window_switcher;
+pub use self::window_switcher::*;
 
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -8,7 +8,9 @@ use crate::{
     },
     shell::{
         element::{
+            stack::CosmicStackRenderElement,
             stack_hover::{stack_hover, StackHover},
+            window::CosmicWindowRenderElement,
             CosmicMappedRenderElement,
         },
         focus::target::{KeyboardFocusTarget, PointerFocusTarget},
@@ -21,6 +23,7 @@ use crate::{
 
 use calloop::LoopHandle;
 use cosmic::theme::CosmicTheme;
+use cosmic_comp_config::ShadowConfig;
 use smithay::{
     backend::{
         input::ButtonState,
@@ -60,6 +63,7 @@ pub struct MoveGrabState {
     window: CosmicMapped,
     window_offset: Point<i32, Logical>,
     indicator_thickness: u8,
+    shadow: ShadowConfig,
     start: Instant,
     previous: ManagedLayer,
     snapping_zone: Option<SnappingZone>,
@@ -69,6 +73,23 @@ pub struct MoveGrabState {
 }
 
 impl MoveGrabState {
+    /// Opacity of the grabbed window on `output`, or `None` if it isn't visible there
+    fn alpha_on(&self, output: &Output) -> Option<f32> {
+        let mut window_geo = self.window.geometry();
+        window_geo.loc += self.location.to_i32_round() + self.window_offset;
+        output
+            .geometry()
+            .as_logical()
+            .intersection(window_geo)
+            .map(|_| {
+                if &self.cursor_output == output {
+                    1.0
+                } else {
+                    0.4
+                }
+            })
+    }
+
     #[profiling::function]
     pub fn render<I, R>(&self, renderer: &mut R, output: &Output, theme: &CosmicTheme) -> Vec<I>
     where
@@ -86,22 +107,9 @@ impl MoveGrabState {
         } else {
             1.0
         };
-        let alpha = if &self.cursor_output == output {
-            1.0
-        } else {
-            0.4
-        };
-
-        let mut window_geo = self.window.geometry();
-        window_geo.loc += self.location.to_i32_round() + self.window_offset;
-        if !output
-            .geometry()
-            .as_logical()
-            .intersection(window_geo)
-            .is_some()
-        {
+        let Some(alpha) = self.alpha_on(output) else {
             return Vec::new();
-        }
+        };
 
         let output_scale: Scale<f64> = output.current_scale().fractional_scale().into();
         let scaling_offset =
@@ -182,6 +190,23 @@ impl MoveGrabState {
             _ => vec![],
         };
 
+        let shadow_element = self.window.shadow_element(
+            renderer,
+            Rectangle::from_loc_and_size(
+                render_location,
+                self.window
+                    .geometry()
+                    .size
+                    .to_f64()
+                    .upscale(scale)
+                    .to_i32_round(),
+            )
+            .as_local(),
+            output_scale.x,
+            alpha,
+            &self.shadow,
+        );
+
         let SplitRenderElements {
             w_elements,
             p_elements,
@@ -232,11 +257,42 @@ impl MoveGrabState {
                 }
                 x => x,
             }))
+            .chain(
+                shadow_element
+                    .into_iter()
+                    .map(CosmicMappedRenderElement::from),
+            )
             .chain(snapping_indicator)
             .map(I::from)
             .collect()
     }
 
+    /// Renders just the grabbed window, unscaled and without indicators, for software rendering
+    pub fn render_software<I, R>(&self, renderer: &mut R, output: &Output) -> Vec<I>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+        I: From<CosmicWindowRenderElement<R>> + From<CosmicStackRenderElement<R>>,
+    {
+        let Some(alpha) = self.alpha_on(output) else {
+            return Vec::new();
+        };
+
+        let output_scale: Scale<f64> = output.current_scale().fractional_scale().into();
+        let render_location =
+            self.location.to_i32_round() - output.geometry().loc.as_logical() + self.window_offset;
+
+        self.window
+            .software_render_elements::<R, I>(
+                renderer,
+                (render_location - self.window.geometry().loc)
+                    .to_physical_precise_round(output_scale),
+                output_scale,
+                alpha,
+            )
+            .join()
+    }
+
     pub fn element(&self) -> CosmicMapped {
         self.window.clone()
     }
@@ -342,7 +398,7 @@ pub struct MoveGrab {
 }
 
 impl MoveGrab {
-    fn update_location(&mut self, state: &mut State, location: Point<f64, Logical>) {
+    pub fn update_location(&mut self, state: &mut State, location: Point<f64, Logical>) {
         let mut shell = state.common.shell.write().unwrap();
 
         let Some(current_output) =
@@ -678,6 +734,7 @@ impl MoveGrab {
         initial_window_location: Point<i32, Global>,
         cursor_output: Output,
         indicator_thickness: u8,
+        shadow: ShadowConfig,
         previous_layer: ManagedLayer,
         release: ReleaseMode,
         evlh: LoopHandle<'static, State>,
@@ -693,6 +750,7 @@ impl MoveGrab {
                 - start_data.location().as_global().to_i32_round())
             .as_logical(),
             indicator_thickness,
+            shadow,
             start: Instant::now(),
             stacking_indicator: None,
             snapping_zone: None,
@@ -725,6 +783,21 @@ impl MoveGrab {
         }
     }
 
+    /// Places the window at `offset` relative to the cursor instead of keeping its initial position
+    pub fn set_window_offset(&mut self, offset: Point<i32, Logical>) {
+        if let Some(grab_state) = self
+            .seat
+            .user_data()
+            .get::<SeatMoveGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .as_mut()
+        {
+            grab_state.window_offset = offset;
+        }
+    }
+
     pub fn is_tiling_grab(&self) -> bool {
         self.previous == ManagedLayer::Tiling
     }
@@ -0,0 +1,417 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::sync::{Arc, Mutex};
+
+use cosmic::theme::CosmicTheme;
+use smithay::{
+    backend::{
+        input::ButtonState,
+        renderer::{element::RenderElement, Renderer},
+    },
+    input::{
+        pointer::{
+            AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent,
+            GesturePinchBeginEvent, GesturePinchEndEvent, GesturePinchUpdateEvent,
+            GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent,
+            GrabStartData as PointerGrabStartData, MotionEvent, PointerGrab, PointerInnerHandle,
+            RelativeMotionEvent,
+        },
+        Seat,
+    },
+    output::Output,
+    utils::{Logical, Point, Rectangle},
+};
+
+use crate::{
+    backend::render::{element::AsGlowRenderer, BackdropShader, IndicatorShader, Key},
+    shell::{
+        element::CosmicMappedRenderElement,
+        focus::target::{KeyboardFocusTarget, PointerFocusTarget},
+    },
+    state::State,
+    utils::{
+        prelude::*,
+        screenshot::{screenshot_output, screenshot_window},
+    },
+};
+
+const BTN_LEFT: u32 = 0x110;
+/// Drags shorter than this are treated as clicks
+const MIN_SELECTION: i32 = 4;
+const DIM_ALPHA: f32 = 0.4;
+
+pub struct ScreenshotGrabState {
+    start: Option<Point<f64, Global>>,
+    current: Point<f64, Global>,
+    // keys for the dimmed areas around the selection and its border
+    keys: [Arc<()>; 5],
+}
+pub type SeatScreenshotGrabState = Mutex<Option<ScreenshotGrabState>>;
+
+impl ScreenshotGrabState {
+    /// The dragged out region, if the user isn't just clicking
+    fn selection(&self) -> Option<Rectangle<i32, Global>> {
+        let start = self.start?;
+        let region = Rectangle::<f64, Global>::from_extemities(
+            (start.x.min(self.current.x), start.y.min(self.current.y)),
+            (start.x.max(self.current.x), start.y.max(self.current.y)),
+        )
+        .to_i32_round();
+        (region.size.w >= MIN_SELECTION || region.size.h >= MIN_SELECTION).then_some(region)
+    }
+
+    fn key(&self, idx: usize) -> Key {
+        Key::Group(Arc::downgrade(&self.keys[idx]))
+    }
+
+    pub fn render<I, R>(&self, renderer: &R, output: &Output, theme: &CosmicTheme) -> Vec<I>
+    where
+        R: Renderer + AsGlowRenderer,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+        I: From<CosmicMappedRenderElement<R>>,
+    {
+        let output_geo = Rectangle::from_loc_and_size((0, 0), output.geometry().size.as_local());
+        let dim = |key: Key, geo: Rectangle<i32, Local>| {
+            I::from(CosmicMappedRenderElement::from(BackdropShader::element(
+                renderer,
+                key,
+                geo,
+                0.,
+                DIM_ALPHA,
+                [0.0, 0.0, 0.0],
+            )))
+        };
+
+        let Some(selection) = self
+            .selection()
+            .and_then(|selection| selection.to_local(output).intersection(output_geo))
+        else {
+            return vec![dim(self.key(0), output_geo)];
+        };
+
+        // leave the selection itself undimmed
+        let top = Rectangle::from_extemities((0, 0), (output_geo.size.w, selection.loc.y));
+        let bottom = Rectangle::from_extemities(
+            (0, selection.loc.y + selection.size.h),
+            (output_geo.size.w, output_geo.size.h),
+        );
+        let left = Rectangle::from_extemities(
+            (0, selection.loc.y),
+            (selection.loc.x, selection.loc.y + selection.size.h),
+        );
+        let right = Rectangle::from_extemities(
+            (selection.loc.x + selection.size.w, selection.loc.y),
+            (output_geo.size.w, selection.loc.y + selection.size.h),
+        );
+
+        let accent = theme.accent_color();
+        let mut elements = vec![I::from(CosmicMappedRenderElement::from(
+            IndicatorShader::element(
+                renderer,
+                self.key(4),
+                selection,
+                1,
+                0,
+                1.0,
+                output.current_scale().fractional_scale(),
+                [accent.red, accent.green, accent.blue],
+            ),
+        ))];
+        elements.extend(
+            [top, bottom, left, right]
+                .into_iter()
+                .enumerate()
+                .filter(|(_, geo)| !geo.is_empty())
+                .map(|(idx, geo)| dim(self.key(idx), geo)),
+        );
+        elements
+    }
+}
+
+/// What the user chose to take a screenshot of
+enum ScreenshotTarget {
+    Region(Rectangle<i32, Global>, Point<f64, Global>),
+    Click(Point<f64, Global>),
+}
+
+/// Lets the user drag out a region, or click a window or output, to take a screenshot of.
+///
+/// Escape or any other button cancels the grab.
+pub struct ScreenshotGrab {
+    start_data: PointerGrabStartData<State>,
+    seat: Seat<State>,
+    target: Option<ScreenshotTarget>,
+}
+
+impl ScreenshotGrab {
+    pub fn new(start_data: PointerGrabStartData<State>, seat: &Seat<State>) -> ScreenshotGrab {
+        *seat
+            .user_data()
+            .get::<SeatScreenshotGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap() = Some(ScreenshotGrabState {
+            start: None,
+            current: start_data.location.as_global(),
+            keys: Default::default(),
+        });
+
+        ScreenshotGrab {
+            start_data,
+            seat: seat.clone(),
+            target: None,
+        }
+    }
+
+    fn update(&self, state: &mut State, f: impl FnOnce(&mut ScreenshotGrabState)) {
+        if let Some(grab_state) = self
+            .seat
+            .user_data()
+            .get::<SeatScreenshotGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .as_mut()
+        {
+            f(grab_state);
+        }
+        schedule_render_all(state);
+    }
+}
+
+fn schedule_render_all(state: &mut State) {
+    let outputs = state
+        .common
+        .shell
+        .read()
+        .unwrap()
+        .outputs()
+        .cloned()
+        .collect::<Vec<_>>();
+    for output in &outputs {
+        state.backend.schedule_render(output);
+    }
+}
+
+fn take_screenshot(state: &mut State, target: ScreenshotTarget) {
+    let location = match &target {
+        ScreenshotTarget::Region(_, start) => *start,
+        ScreenshotTarget::Click(location) => *location,
+    };
+    let Some(output) = state
+        .common
+        .shell
+        .read()
+        .unwrap()
+        .outputs()
+        .find(|output| output.geometry().to_f64().contains(location))
+        .cloned()
+    else {
+        return;
+    };
+
+    match target {
+        ScreenshotTarget::Region(region, _) => {
+            // regions spanning multiple outputs are cut off at the one they started on
+            if let Some(region) = region.intersection(output.geometry()) {
+                screenshot_output(state, &output, Some(region.to_local(&output)));
+            }
+        }
+        ScreenshotTarget::Click(location) => {
+            let window = state
+                .common
+                .shell
+                .write()
+                .unwrap()
+                .element_under(location, &output)
+                .and_then(|target| match target {
+                    KeyboardFocusTarget::Element(mapped) => Some(mapped.active_window()),
+                    KeyboardFocusTarget::Fullscreen(surface) => Some(surface),
+                    _ => None,
+                });
+            match window {
+                Some(window) => screenshot_window(state, &window),
+                None => screenshot_output(state, &output, None),
+            }
+        }
+    }
+}
+
+impl PointerGrab<State> for ScreenshotGrab {
+    fn motion(
+        &mut self,
+        state: &mut State,
+        handle: &mut PointerInnerHandle<'_, State>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // While the grab is active, no client has pointer focus
+        handle.motion(state, None, event);
+        self.update(state, |grab_state| {
+            grab_state.current = event.location.as_global()
+        });
+    }
+
+    fn relative_motion(
+        &mut self,
+        state: &mut State,
+        handle: &mut PointerInnerHandle<'_, State>,
+        _focus: Option<(PointerFocusTarget, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(state, None, event);
+    }
+
+    fn button(
+        &mut self,
+        state: &mut State,
+        handle: &mut PointerInnerHandle<'_, State>,
+        event: &ButtonEvent,
+    ) {
+        if event.button != BTN_LEFT {
+            if event.state == ButtonState::Pressed {
+                handle.unset_grab(self, state, event.serial, event.time, true);
+            }
+            return;
+        }
+
+        let location = handle.current_location().as_global();
+        match event.state {
+            ButtonState::Pressed => self.update(state, |grab_state| {
+                grab_state.start = Some(location);
+                grab_state.current = location;
+            }),
+            ButtonState::Released => {
+                let selection = self
+                    .seat
+                    .user_data()
+                    .get::<SeatScreenshotGrabState>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|grab_state| Some((grab_state.selection(), grab_state.start?)));
+                let Some((selection, start)) = selection else {
+                    // the button was already pressed, when the grab started
+                    return;
+                };
+                self.target = Some(match selection {
+                    Some(region) => ScreenshotTarget::Region(region, start),
+                    None => ScreenshotTarget::Click(location),
+                });
+                handle.unset_grab(self, state, event.serial, event.time, true);
+            }
+        }
+    }
+
+    fn axis(
+        &mut self,
+        _state: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _details: AxisFrame,
+    ) {
+    }
+
+    fn frame(&mut self, data: &mut State, handle: &mut PointerInnerHandle<'_, State>) {
+        handle.frame(data)
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GestureSwipeBeginEvent,
+    ) {
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GestureSwipeUpdateEvent,
+    ) {
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GestureSwipeEndEvent,
+    ) {
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GesturePinchBeginEvent,
+    ) {
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GesturePinchUpdateEvent,
+    ) {
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GesturePinchEndEvent,
+    ) {
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GestureHoldBeginEvent,
+    ) {
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        _data: &mut State,
+        _handle: &mut PointerInnerHandle<'_, State>,
+        _event: &GestureHoldEndEvent,
+    ) {
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<State> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, state: &mut State) {
+        self.seat
+            .user_data()
+            .get::<SeatScreenshotGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .take();
+        schedule_render_all(state);
+
+        if let Some(target) = self.target.take() {
+            // render without the dimmed overlay, outside of the pointer grab
+            state
+                .common
+                .event_loop_handle
+                .insert_idle(move |state| take_screenshot(state, target));
+        }
+    }
+}
+
+impl Drop for ScreenshotGrab {
+    fn drop(&mut self) {
+        self.seat
+            .user_data()
+            .get::<SeatScreenshotGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .take();
+    }
+}
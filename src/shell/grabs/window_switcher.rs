@@ -0,0 +1,626 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use cosmic::{
+    iced::widget::{column, container, row},
+    iced_core::{Background, Border, Color, Length},
+    theme,
+    widget::{icon::from_name, text},
+    Apply,
+};
+use cosmic_settings_config::shortcuts;
+use smithay::{
+    backend::{
+        drm::DrmNode,
+        input::KeyState,
+        renderer::{
+            element::{
+                texture::{TextureRenderBuffer, TextureRenderElement},
+                AsRenderElements, Kind, RenderElement,
+            },
+            gles::GlesTexture,
+            ImportAll, ImportMem, Renderer,
+        },
+    },
+    desktop::space::SpaceElement,
+    input::{
+        keyboard::{
+            GrabStartData as KeyboardGrabStartData, KeyboardGrab, KeyboardInnerHandle,
+            ModifiersState,
+        },
+        Seat, SeatHandler,
+    },
+    output::Output,
+    utils::{IsAlive, Logical, Point, Rectangle, Serial, Size},
+};
+use xkbcommon::xkb::Keysym;
+
+use crate::{
+    backend::render::{
+        element::{AsGlowRenderer, CosmicElement},
+        thumbnail::ThumbnailConsumer,
+    },
+    shell::{CosmicMappedRenderElement, CosmicSurface, Shell, WorkspaceRenderElement},
+    state::State,
+    utils::{
+        iced::{IcedElement, Program},
+        prelude::*,
+    },
+    wayland::protocols::toplevel_management::ToplevelManagementHandler,
+};
+
+/// Logical size of the area every window preview is fitted into
+const PREVIEW_SIZE: (i32, i32) = (192, 128);
+const TITLE_HEIGHT: i32 = 24;
+const CARD_PADDING: i32 = 8;
+const SPACING: i32 = 8;
+const PANEL_PADDING: i32 = 16;
+/// Titles are cut off after this many characters
+const MAX_TITLE_LEN: usize = 24;
+
+const CARD_SIZE: (i32, i32) = (
+    PREVIEW_SIZE.0 + 2 * CARD_PADDING,
+    PREVIEW_SIZE.1 + TITLE_HEIGHT + 2 * CARD_PADDING,
+);
+
+pub type WindowSwitcher = IcedElement<WindowSwitcherInternal>;
+
+pub struct WindowSwitcherInternal {
+    // title and app-id of every window
+    entries: Vec<(String, String)>,
+    columns: usize,
+    selected: AtomicUsize,
+}
+
+impl Program for WindowSwitcherInternal {
+    type Message = ();
+
+    fn view(&self) -> cosmic::Element<'_, Self::Message> {
+        let selected = self.selected.load(Ordering::SeqCst);
+        let mut cards = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(idx, (title, app_id))| {
+                // only visible, if there is no thumbnail drawn on top
+                let preview = from_name(app_id.clone())
+                    .size(64)
+                    .prefer_svg(true)
+                    .icon()
+                    .apply(container)
+                    .center_x()
+                    .center_y()
+                    .width(Length::Fixed(PREVIEW_SIZE.0 as f32))
+                    .height(Length::Fixed(PREVIEW_SIZE.1 as f32));
+                let title = text(title.clone())
+                    .font(cosmic::font::FONT)
+                    .size(14)
+                    .apply(container)
+                    .center_x()
+                    .center_y()
+                    .width(Length::Fixed(PREVIEW_SIZE.0 as f32))
+                    .height(Length::Fixed(TITLE_HEIGHT as f32));
+
+                let active = idx == selected;
+                column(vec![preview.into(), title.into()])
+                    .apply(container)
+                    .padding(CARD_PADDING as u16)
+                    .style(theme::Container::custom(move |theme| {
+                        container::Appearance {
+                            icon_color: None,
+                            text_color: active.then(|| Color::from(theme.cosmic().accent.on)),
+                            background: active
+                                .then(|| Background::Color(theme.cosmic().accent_color().into())),
+                            border: Border {
+                                radius: 12.0.into(),
+                                width: 0.0,
+                                color: Color::TRANSPARENT,
+                            },
+                            shadow: Default::default(),
+                        }
+                    }))
+                    .into()
+            })
+            .peekable();
+
+        let mut rows = Vec::new();
+        while cards.peek().is_some() {
+            rows.push(
+                row(cards.by_ref().take(self.columns).collect())
+                    .spacing(SPACING as u16)
+                    .into(),
+            );
+        }
+
+        column(rows)
+            .spacing(SPACING as u16)
+            .apply(container)
+            .padding(PANEL_PADDING as u16)
+            .style(theme::Container::custom(|theme| container::Appearance {
+                icon_color: Some(Color::from(theme.cosmic().background.on)),
+                text_color: Some(Color::from(theme.cosmic().background.on)),
+                background: Some(Background::Color(theme.cosmic().background.base.into())),
+                border: Border {
+                    radius: 18.0.into(),
+                    width: 0.0,
+                    color: Color::TRANSPARENT,
+                },
+                shadow: Default::default(),
+            }))
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    }
+}
+
+/// Location of the preview of the window at `idx`, relative to the switcher
+fn preview_location(idx: usize, columns: usize) -> Point<i32, Local> {
+    let (column, row) = ((idx % columns) as i32, (idx / columns) as i32);
+    Point::from((
+        PANEL_PADDING + column * (CARD_SIZE.0 + SPACING) + CARD_PADDING,
+        PANEL_PADDING + row * (CARD_SIZE.1 + SPACING) + CARD_PADDING,
+    ))
+}
+
+fn ellipsize(title: &str) -> String {
+    if title.chars().count() > MAX_TITLE_LEN {
+        let mut title = title.chars().take(MAX_TITLE_LEN - 1).collect::<String>();
+        title.push('…');
+        title
+    } else {
+        title.to_string()
+    }
+}
+
+/// Windows to switch between, the most recently focused first
+fn switcher_windows(
+    shell: &Shell,
+    seat: &Seat<State>,
+    output: &Output,
+    all_workspaces: bool,
+) -> Vec<CosmicSurface> {
+    let active = shell.active_space(output);
+    let sticky = shell
+        .workspaces
+        .sets
+        .get(output)
+        .into_iter()
+        .flat_map(|set| set.sticky_layer.mapped())
+        .cloned()
+        .collect::<Vec<_>>();
+    let workspaces = std::iter::once(active).chain(
+        shell
+            .workspaces
+            .spaces()
+            .filter(move |workspace| all_workspaces && workspace.handle != active.handle),
+    );
+
+    let mut windows = Vec::new();
+    for (idx, workspace) in workspaces.enumerate() {
+        let mut mapped = workspace
+            .focus_stack
+            .get(seat)
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let unfocused = workspace
+            .mapped()
+            .chain(
+                workspace
+                    .minimized_windows
+                    .iter()
+                    .map(|minimized| &minimized.window),
+            )
+            .filter(|m| !mapped.contains(m))
+            .cloned()
+            .collect::<Vec<_>>();
+        mapped.extend(unfocused);
+        if idx == 0 {
+            let sticky = sticky
+                .iter()
+                .filter(|m| !mapped.contains(m))
+                .cloned()
+                .collect::<Vec<_>>();
+            mapped.extend(sticky);
+        }
+
+        windows.extend(workspace.get_fullscreen().cloned());
+        for mapped in mapped {
+            // the visible window of a stack comes first
+            let active = mapped.active_window();
+            windows.push(active.clone());
+            windows.extend(
+                mapped
+                    .windows()
+                    .map(|(window, _)| window)
+                    .filter(|window| window != &active),
+            );
+        }
+    }
+
+    let mut unique = Vec::with_capacity(windows.len());
+    for window in windows {
+        if !unique.contains(&window) {
+            unique.push(window);
+        }
+    }
+    unique
+}
+
+pub struct WindowSwitcherState {
+    output: Output,
+    windows: Vec<CosmicSurface>,
+    thumbnails: Vec<Option<TextureRenderBuffer<GlesTexture>>>,
+    /// Gpu the thumbnails belong to
+    node: Option<DrmNode>,
+    element: WindowSwitcher,
+}
+pub type SeatWindowSwitcherState = Mutex<Option<WindowSwitcherState>>;
+
+impl WindowSwitcherState {
+    fn columns(&self) -> usize {
+        self.element.with_program(|p| p.columns)
+    }
+
+    /// Window currently selected in the switcher
+    pub fn selected(&self) -> Option<CosmicSurface> {
+        let idx = self
+            .element
+            .with_program(|p| p.selected.load(Ordering::SeqCst));
+        self.windows.get(idx).cloned()
+    }
+
+    fn select(&self, offset: isize) {
+        let len = self.windows.len() as isize;
+        self.element.with_program(|p| {
+            let selected = p.selected.load(Ordering::SeqCst) as isize;
+            p.selected.store(
+                (selected + offset).rem_euclid(len) as usize,
+                Ordering::SeqCst,
+            );
+        });
+        self.element.force_update();
+    }
+
+    /// The switcher, centered on its output
+    fn geometry(&self) -> Rectangle<i32, Local> {
+        let output_size = self.output.geometry().size.as_local();
+        let size = self.element.bbox().size.as_local();
+        Rectangle::from_loc_and_size(
+            ((output_size.w - size.w) / 2, (output_size.h - size.h) / 2),
+            size,
+        )
+    }
+
+    pub fn render<R>(
+        &self,
+        renderer: &mut R,
+        output: &Output,
+        gpu: Option<&DrmNode>,
+    ) -> Vec<CosmicElement<R>>
+    where
+        R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+    {
+        if output != &self.output {
+            return Vec::new();
+        }
+
+        let scale = output.current_scale().fractional_scale();
+        let geometry = self.geometry();
+        let columns = self.columns();
+        let mut elements = Vec::new();
+
+        // thumbnails can't be shared across gpus
+        if gpu.is_none() || gpu == self.node.as_ref() {
+            let previews = self.windows.iter().zip(&self.thumbnails).enumerate();
+            for (idx, (window, thumbnail)) in previews {
+                let Some(thumbnail) = thumbnail else {
+                    continue;
+                };
+                let window_size = window.geometry().size.to_f64();
+                if window_size.w <= 0. || window_size.h <= 0. {
+                    continue;
+                }
+                let factor = (PREVIEW_SIZE.0 as f64 / window_size.w)
+                    .min(PREVIEW_SIZE.1 as f64 / window_size.h);
+                let size: Size<i32, Logical> = window_size.upscale(factor).to_i32_round();
+                let centered = ((PREVIEW_SIZE.0 - size.w) / 2, (PREVIEW_SIZE.1 - size.h) / 2);
+                let location = geometry.loc + preview_location(idx, columns) + centered.into();
+
+                let elem = TextureRenderElement::from_texture_render_buffer(
+                    location.as_logical().to_f64().to_physical(scale),
+                    thumbnail,
+                    Some(1.0),
+                    None,
+                    Some(size),
+                    Kind::Unspecified,
+                );
+                elements.push(CosmicElement::from(WorkspaceRenderElement::from(elem)));
+            }
+        }
+
+        elements.extend(
+            self.element
+                .render_elements::<CosmicMappedRenderElement<R>>(
+                    renderer,
+                    geometry.loc.as_logical().to_physical_precise_round(scale),
+                    scale.into(),
+                    1.0,
+                )
+                .into_iter()
+                .map(CosmicElement::from),
+        );
+        elements
+    }
+}
+
+impl State {
+    /// Hands the latest window thumbnails to open window switchers
+    pub fn update_switcher_thumbnails(&mut self) {
+        let seats = self
+            .common
+            .shell
+            .read()
+            .unwrap()
+            .seats
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut outputs = Vec::new();
+        for seat in &seats {
+            let mut switcher = seat
+                .user_data()
+                .get::<SeatWindowSwitcherState>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            if let Some(switcher) = switcher.as_mut() {
+                switcher.node = self.common.thumbnails.node();
+                switcher.thumbnails = switcher
+                    .windows
+                    .iter()
+                    .map(|window| self.common.thumbnails.window(window).cloned())
+                    .collect();
+                outputs.push(switcher.output.clone());
+            }
+        }
+
+        for output in &outputs {
+            self.backend.schedule_render(output);
+        }
+    }
+}
+
+/// Alt+Tab window switcher, showing previews of the windows on the active output.
+///
+/// Tab and the arrow keys move the selection, releasing the modifier activates the selected window
+/// and Escape closes the switcher without changing focus.
+pub struct WindowSwitcherGrab {
+    seat: Seat<State>,
+    modifiers: shortcuts::Modifiers,
+}
+
+impl WindowSwitcherGrab {
+    /// Opens the switcher, `None` if there are no windows to switch between.
+    ///
+    /// With `reverse` the least recently used window is selected initially,
+    /// instead of the previously focused one.
+    pub fn new(
+        state: &mut State,
+        seat: &Seat<State>,
+        modifiers: shortcuts::Modifiers,
+        reverse: bool,
+    ) -> Option<WindowSwitcherGrab> {
+        let output = seat.active_output();
+        let all_workspaces = state
+            .common
+            .config
+            .cosmic_conf
+            .window_switcher
+            .all_workspaces;
+
+        let (windows, theme) = {
+            let shell = state.common.shell.read().unwrap();
+            (
+                switcher_windows(&shell, seat, &output, all_workspaces),
+                shell.theme().clone(),
+            )
+        };
+        if windows.is_empty() {
+            return None;
+        }
+
+        let available_width = output.geometry().size.w - 2 * PANEL_PADDING + SPACING;
+        let columns =
+            ((available_width / (CARD_SIZE.0 + SPACING)).max(1) as usize).min(windows.len());
+        let rows = windows.len().div_ceil(columns);
+        let size = Size::<i32, Logical>::from((
+            2 * PANEL_PADDING + columns as i32 * (CARD_SIZE.0 + SPACING) - SPACING,
+            2 * PANEL_PADDING + rows as i32 * (CARD_SIZE.1 + SPACING) - SPACING,
+        ));
+
+        let selected = if reverse {
+            windows.len() - 1
+        } else {
+            1.min(windows.len() - 1)
+        };
+        let element = WindowSwitcher::new(
+            WindowSwitcherInternal {
+                entries: windows
+                    .iter()
+                    .map(|window| {
+                        let title = window.title();
+                        let app_id = window.app_id();
+                        (
+                            ellipsize(if title.is_empty() { &app_id } else { &title }),
+                            app_id,
+                        )
+                    })
+                    .collect(),
+                columns,
+                selected: AtomicUsize::new(selected),
+            },
+            size,
+            state.common.event_loop_handle.clone(),
+            theme,
+        );
+        element.output_enter(&output, Rectangle::default() /* unused */);
+
+        *seat
+            .user_data()
+            .get::<SeatWindowSwitcherState>()
+            .unwrap()
+            .lock()
+            .unwrap() = Some(WindowSwitcherState {
+            output: output.clone(),
+            thumbnails: vec![None; windows.len()],
+            windows,
+            node: None,
+            element,
+        });
+
+        state
+            .common
+            .set_thumbnail_consumer(ThumbnailConsumer::Switcher, true);
+        state.update_switcher_thumbnails();
+
+        Some(WindowSwitcherGrab {
+            seat: seat.clone(),
+            modifiers,
+        })
+    }
+
+    fn select(&self, data: &mut State, offset: isize) {
+        let output = {
+            let switcher = self
+                .seat
+                .user_data()
+                .get::<SeatWindowSwitcherState>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            let Some(switcher) = switcher.as_ref() else {
+                return;
+            };
+            switcher.select(offset);
+            switcher.output.clone()
+        };
+        data.backend.schedule_render(&output);
+    }
+
+    fn activate_selected(&self, data: &mut State) {
+        let Some(window) = self
+            .seat
+            .user_data()
+            .get::<SeatWindowSwitcherState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|switcher| switcher.selected())
+        else {
+            return;
+        };
+
+        let seat = self.seat.clone();
+        data.common.event_loop_handle.insert_idle(move |state| {
+            if window.alive() {
+                let dh = state.common.display_handle.clone();
+                state.activate(&dh, &window, Some(seat));
+            }
+        });
+    }
+}
+
+impl KeyboardGrab<State> for WindowSwitcherGrab {
+    fn input(
+        &mut self,
+        data: &mut State,
+        handle: &mut KeyboardInnerHandle<'_, State>,
+        keycode: u32,
+        state: KeyState,
+        modifiers: Option<ModifiersState>,
+        serial: Serial,
+        time: u32,
+    ) {
+        // Activate the selected window, once any modifier of the binding was released
+        if let Some(mods) = modifiers.as_ref() {
+            if (self.modifiers.ctrl && !mods.ctrl)
+                || (self.modifiers.alt && !mods.alt)
+                || (self.modifiers.logo && !mods.logo)
+                || (self.modifiers.shift && !mods.shift)
+            {
+                // clients shouldn't think the modifier is still held
+                handle.input(data, keycode, state, modifiers, serial, time);
+                self.activate_selected(data);
+                handle.unset_grab(self, data, serial, false);
+                return;
+            }
+        }
+
+        if state == KeyState::Released {
+            return;
+        }
+
+        let columns = self
+            .seat
+            .user_data()
+            .get::<SeatWindowSwitcherState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|switcher| switcher.columns() as isize)
+            .unwrap_or(1);
+        match handle.keysym_handle(keycode).modified_sym() {
+            Keysym::Tab | Keysym::Right => self.select(data, 1),
+            Keysym::ISO_Left_Tab | Keysym::Left => self.select(data, -1),
+            Keysym::Down => self.select(data, columns),
+            Keysym::Up => self.select(data, -columns),
+            Keysym::Return | Keysym::KP_Enter => {
+                self.activate_selected(data);
+                handle.unset_grab(self, data, serial, false);
+            }
+            // the global escape binding doesn't apply, while the modifier is held
+            Keysym::Escape => handle.unset_grab(self, data, serial, false),
+            _ => {}
+        }
+    }
+
+    fn set_focus(
+        &mut self,
+        data: &mut State,
+        handle: &mut KeyboardInnerHandle<'_, State>,
+        focus: Option<<State as SeatHandler>::KeyboardFocus>,
+        serial: Serial,
+    ) {
+        handle.set_focus(data, focus, serial)
+    }
+
+    fn start_data(&self) -> &KeyboardGrabStartData<State> {
+        &KeyboardGrabStartData { focus: None }
+    }
+
+    fn unset(&mut self, data: &mut State) {
+        let switcher = self
+            .seat
+            .user_data()
+            .get::<SeatWindowSwitcherState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .take();
+        data.common
+            .set_thumbnail_consumer(ThumbnailConsumer::Switcher, false);
+        if let Some(switcher) = switcher {
+            data.backend.schedule_render(&switcher.output);
+        }
+    }
+}
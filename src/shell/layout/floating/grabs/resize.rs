@@ -384,7 +384,7 @@ impl ResizeSurfaceGrab {
             initial_window_size,
         });
 
-        *mapped.resize_state.lock().unwrap() = Some(resize_state);
+        *mapped.resize_state.write().unwrap() = Some(resize_state);
         seat.user_data()
             .get_or_insert::<ResizeGrabMarker, _>(|| ResizeGrabMarker(AtomicBool::new(true)))
             .0
@@ -402,9 +402,8 @@ impl ResizeSurfaceGrab {
     }
 
     pub fn apply_resize_to_location(window: CosmicMapped, shell: &mut Shell) {
-        let mut resize_state = window.resize_state.lock().unwrap();
-
-        if resize_state.is_none() {
+        // called on every commit, so check without blocking other readers first
+        if window.resize_state.read().unwrap().is_none() {
             return;
         }
 
@@ -428,6 +427,7 @@ impl ResizeSurfaceGrab {
             .map(|p| p.to_global(output))
         {
             let mut new_location = None;
+            let mut resize_state = window.resize_state.write().unwrap();
 
             // If the window is being resized by top or left, its location must be adjusted
             // accordingly.
@@ -516,7 +516,7 @@ impl ResizeSurfaceGrab {
         ));
         self.window.configure();
 
-        let mut resize_state = self.window.resize_state.lock().unwrap();
+        let mut resize_state = self.window.resize_state.write().unwrap();
         if let Some(ResizeState::Resizing(resize_data)) = *resize_state {
             *resize_state = Some(ResizeState::WaitingForCommit(resize_data));
         } else {
@@ -6,6 +6,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use cosmic_comp_config::ShadowConfig;
 use cosmic_settings_config::shortcuts::action::ResizeDirection;
 use keyframe::{ease, functions::EaseInOutCubic};
 use smithay::{
@@ -58,6 +59,8 @@ pub struct FloatingLayout {
     hovered_stack: Option<(CosmicMapped, Rectangle<i32, Local>)>,
     dirty: AtomicBool,
     pub theme: cosmic::Theme,
+    /// Gaps used instead of the ones of the theme
+    pub gaps_override: Option<(u32, u32)>,
 }
 
 #[derive(Debug)]
@@ -724,6 +727,21 @@ impl FloatingLayout {
         }
     }
 
+    /// Moves and resizes a floating window, returning `false` if it isn't mapped in this layer
+    pub fn set_geometry(&mut self, mapped: &CosmicMapped, geometry: Rectangle<i32, Local>) -> bool {
+        if self.space.element_geometry(mapped).is_none() {
+            return false;
+        }
+        mapped.moved_since_mapped.store(true, Ordering::SeqCst);
+        self.map_internal(
+            mapped.clone(),
+            Some(geometry.loc),
+            Some(geometry.size.as_logical()),
+            None,
+        );
+        true
+    }
+
     pub fn element_geometry(&self, elem: &CosmicMapped) -> Option<Rectangle<i32, Local>> {
         self.space.element_geometry(elem).map(RectExt::as_local)
     }
@@ -865,7 +883,7 @@ impl FloatingLayout {
         geo.size.h = min_height.max(geo.size.h).min(max_height);
         geo = geo.intersection(bounding_box).unwrap();
 
-        *mapped.resize_state.lock().unwrap() = Some(ResizeState::Resizing(ResizeData {
+        *mapped.resize_state.write().unwrap() = Some(ResizeState::Resizing(ResizeData {
             edges: edge,
             initial_window_location: original_geo.loc.as_local(),
             initial_window_size: original_geo.size,
@@ -1173,6 +1191,7 @@ impl FloatingLayout {
         self.mapped().flat_map(|e| e.windows().map(|(w, _)| w))
     }
 
+    #[tracing::instrument(name = "floating_recalculate", skip_all)]
     pub fn recalculate(&mut self) {
         let output = self.space.outputs().next().unwrap().clone();
         let geometry = layer_map_for_output(&output)
@@ -1270,6 +1289,7 @@ impl FloatingLayout {
         indicator_thickness: u8,
         alpha: f32,
         theme: &cosmic::theme::CosmicTheme,
+        shadow: &ShadowConfig,
     ) -> SplitRenderElements<CosmicMappedRenderElement<R>>
     where
         R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
@@ -1425,13 +1445,18 @@ impl FloatingLayout {
 
             elements.w_elements.extend(w_elements);
             elements.p_elements.extend(p_elements);
+            if let Some(shadow) =
+                elem.shadow_element(renderer, geometry, output_scale, alpha, shadow)
+            {
+                elements.w_elements.push(shadow.into());
+            }
         }
 
         elements
     }
 
     fn gaps(&self) -> (i32, i32) {
-        let g = self.theme.cosmic().gaps;
+        let g = self.gaps_override.unwrap_or(self.theme.cosmic().gaps);
         (g.0 as i32, g.1 as i32)
     }
 }
@@ -105,10 +105,8 @@ pub fn is_dialog(window: &CosmicSurface) -> bool {
         WindowSurface::X11(surface) => {
             if surface.is_override_redirect()
                 || surface.is_popup()
-                || !matches!(
-                    surface.window_type(),
-                    None | Some(WmWindowType::Normal) | Some(WmWindowType::Utility)
-                )
+                || surface.is_transient_for().is_some()
+                || !matches!(surface.window_type(), None | Some(WmWindowType::Normal))
             {
                 return true;
             }
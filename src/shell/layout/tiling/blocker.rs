@@ -1,9 +1,9 @@
-use crate::shell::element::CosmicSurface;
+use crate::shell::{element::CosmicSurface, CosmicMapped};
 use smithay::{
     reexports::wayland_server::{backend::ClientId, Client, Resource},
     utils::{IsAlive, Serial},
     wayland::{
-        compositor::{Blocker, BlockerState},
+        compositor::{add_blocker, Blocker, BlockerState},
         seat::WaylandFocus,
     },
 };
@@ -16,9 +16,14 @@ use std::{
     time::{Duration, Instant},
 };
 
+/// How long a layout change waits for the windows to draw at their new sizes
+const TRANSACTION_TIMEOUT: Duration = Duration::from_millis(300);
+
 #[derive(Debug, Clone)]
 pub struct TilingBlocker {
     pub necessary_acks: Vec<(CosmicSurface, Serial)>,
+    /// Windows still waiting for their configure, until the layout transaction is flushed
+    pending_configures: Vec<CosmicMapped>,
     ready: Arc<AtomicBool>,
     signaled: Arc<AtomicBool>,
     start: Instant,
@@ -35,22 +40,66 @@ impl Blocker for TilingBlocker {
     }
 }
 
+impl Drop for TilingBlocker {
+    fn drop(&mut self) {
+        // dropped before the flush, e.g. with its workspace or output,
+        // the windows still need to learn about their new geometry
+        for mapped in self.pending_configures.drain(..) {
+            if mapped.alive() {
+                mapped.configure();
+            }
+        }
+    }
+}
+
 impl TilingBlocker {
-    pub fn new(configures: impl IntoIterator<Item = (CosmicSurface, Serial)>) -> Self {
+    pub fn new(configures: impl IntoIterator<Item = CosmicMapped>) -> Self {
         TilingBlocker {
-            necessary_acks: configures.into_iter().collect(),
+            necessary_acks: Vec::new(),
+            pending_configures: configures.into_iter().collect(),
             ready: Arc::new(AtomicBool::new(false)),
             signaled: Arc::new(AtomicBool::new(false)),
             start: Instant::now(),
         }
     }
 
+    /// Sends the held back configures and starts blocking the commits of the windows.
+    ///
+    /// Windows that already got a configure during this flush, because they are part of
+    /// multiple trees, aren't configured again and have to ack the same serial instead.
+    pub fn flush_configures(
+        &mut self,
+        sent: &mut HashMap<CosmicMapped, Option<(CosmicSurface, Serial)>>,
+    ) {
+        if self.pending_configures.is_empty() {
+            return;
+        }
+
+        for mapped in self.pending_configures.drain(..) {
+            let ack = sent.entry(mapped.clone()).or_insert_with(|| {
+                mapped
+                    .configure()
+                    .map(|serial| (mapped.active_window(), serial))
+            });
+            if let Some(ack) = ack {
+                self.necessary_acks.push(ack.clone());
+            }
+        }
+
+        for (surface, _) in &self.necessary_acks {
+            if let Some(surface) = surface.wl_surface() {
+                add_blocker(&surface, self.clone());
+            }
+        }
+    }
+
     pub fn is_ready(&self) -> bool {
-        Instant::now().duration_since(self.start) >= Duration::from_millis(300)
-            || self
-                .necessary_acks
-                .iter()
-                .all(|(surf, serial)| !surf.alive() || surf.serial_acked(serial))
+        Instant::now().duration_since(self.start) >= TRANSACTION_TIMEOUT
+            || (self.pending_configures.is_empty()
+                && self
+                    .necessary_acks
+                    .iter()
+                    .all(|(surf, serial)| !surf.alive() || surf.serial_committed(serial)))
     }
 
     pub fn is_signaled(&self) -> bool {
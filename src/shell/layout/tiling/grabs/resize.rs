@@ -317,7 +317,12 @@ impl ResizeForkGrab {
                 }
 
                 self.last_loc = location.as_global();
-                let blocker = TilingLayout::update_positions(&output, tree, gaps);
+                let blocker = TilingLayout::update_subtree_positions(
+                    &output,
+                    tree,
+                    gaps,
+                    Some(self.node.clone()),
+                );
                 tiling_layer.pending_blockers.extend(blocker);
             } else {
                 return true;
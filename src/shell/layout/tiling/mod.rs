@@ -60,8 +60,8 @@ use smithay::{
     input::Seat,
     output::Output,
     reexports::wayland_server::Client,
-    utils::{IsAlive, Logical, Point, Rectangle, Scale, Size},
-    wayland::{compositor::add_blocker, seat::WaylandFocus},
+    utils::{IsAlive, Logical, Point, Rectangle, Scale, Serial, Size},
+    wayland::seat::WaylandFocus,
 };
 use std::{
     collections::{HashMap, VecDeque},
@@ -134,6 +134,8 @@ pub struct TilingLayout {
     swapping_stack_surface_id: Id,
     last_overview_hover: Option<(Option<Instant>, TargetZone)>,
     pub theme: cosmic::Theme,
+    /// Gaps used instead of the ones of the theme
+    pub gaps_override: Option<(u32, u32)>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -357,6 +359,7 @@ impl TilingLayout {
             swapping_stack_surface_id: Id::new(),
             last_overview_hover: None,
             theme,
+            gaps_override: None,
         }
     }
 
@@ -411,15 +414,23 @@ impl TilingLayout {
             ANIMATION_DURATION
         };
 
+        let window = window.into();
         TilingLayout::map_to_tree(
             &mut tree,
-            window,
+            window.clone(),
             &self.output,
             last_active,
             direction,
             minimize_rect,
         );
-        let blocker = TilingLayout::update_positions(&self.output, &mut tree, gaps);
+        let subtree = window
+            .tiling_node_id
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|node_id| TilingLayout::unaffected_ancestor(&tree, node_id));
+        let blocker =
+            TilingLayout::update_subtree_positions(&self.output, &mut tree, gaps, subtree);
         self.queue.push_tree(tree, duration, blocker);
     }
 
@@ -1387,6 +1398,7 @@ impl TilingLayout {
             {
                 let mut tree = self.queue.trees.back().unwrap().0.copy_clone();
 
+                let subtree = TilingLayout::unaffected_ancestor(&tree, &node_id);
                 TilingLayout::unmap_internal(&mut tree, &node_id);
 
                 let duration = if minimizing {
@@ -1394,7 +1406,8 @@ impl TilingLayout {
                 } else {
                     ANIMATION_DURATION
                 };
-                let blocker = TilingLayout::update_positions(&self.output, &mut tree, gaps);
+                let blocker =
+                    TilingLayout::update_subtree_positions(&self.output, &mut tree, gaps, subtree);
                 self.queue.push_tree(tree, duration, blocker);
 
                 return true;
@@ -2298,6 +2311,7 @@ impl TilingLayout {
         None
     }
 
+    #[tracing::instrument(name = "tiling_recalculate", skip_all)]
     pub fn recalculate(&mut self) {
         let gaps = self.gaps();
 
@@ -2326,6 +2340,23 @@ impl TilingLayout {
         self.queue.animation_start.is_some()
     }
 
+    /// Sends the configures of all layout changes since the last flush,
+    /// so every window gets at most one configure with its final geometry
+    pub fn flush_configures(
+        &mut self,
+        sent: &mut HashMap<CosmicMapped, Option<(CosmicSurface, Serial)>>,
+    ) {
+        for blocker in self
+            .queue
+            .trees
+            .iter_mut()
+            .filter_map(|(_, _, blocker)| blocker.as_mut())
+            .chain(self.pending_blockers.iter_mut())
+        {
+            blocker.flush_configures(sent);
+        }
+    }
+
     pub fn update_animation_state(&mut self) -> HashMap<ClientId, Client> {
         let mut clients = HashMap::new();
         for blocker in self.pending_blockers.drain(..) {
@@ -2572,8 +2603,14 @@ impl TilingLayout {
                 }
                 _ => unreachable!(),
             }
-            let blocker = TilingLayout::update_positions(&self.output, &mut tree, gaps);
-            self.queue.push_tree(tree, None, blocker);
+            let blocker = TilingLayout::update_subtree_positions(
+                &self.output,
+                &mut tree,
+                gaps,
+                Some(group_id),
+            );
+            // trees queued up by key repeat during the animation get merged into the next one
+            self.queue.push_tree(tree, ANIMATION_DURATION, blocker);
 
             return true;
         }
@@ -2907,6 +2944,13 @@ impl TilingLayout {
         }
     }
 
+    /// Closest ancestor, whose geometry isn't changed by adding or removing `node`.
+    ///
+    /// The parent may be replaced by a new group or flattened in the process, the grandparent is not.
+    fn unaffected_ancestor(tree: &Tree<Data>, node: &NodeId) -> Option<NodeId> {
+        tree.ancestor_ids(node).ok()?.nth(1).cloned()
+    }
+
     fn has_sibling_node(tree: &Tree<Data>, node: &NodeId, direction: Direction) -> bool {
         match tree.get(node).ok().and_then(|node| node.parent()) {
             Some(parent_id) => {
@@ -2935,27 +2979,61 @@ impl TilingLayout {
         }
     }
 
-    #[profiling::function]
     fn update_positions(
         output: &Output,
         tree: &mut Tree<Data>,
         gaps: (i32, i32),
     ) -> Option<TilingBlocker> {
-        if let Some(root_id) = tree.root_node_id() {
+        TilingLayout::update_subtree_positions(output, tree, gaps, None)
+    }
+
+    /// Recomputes the geometries below `subtree`, or of the whole tree if `None`.
+    ///
+    /// The geometry of the subtree itself has to be unchanged, e.g. because only its children
+    /// were inserted, removed or resized.
+    #[profiling::function]
+    #[tracing::instrument(skip_all, fields(output = %output.name()))]
+    fn update_subtree_positions(
+        output: &Output,
+        tree: &mut Tree<Data>,
+        gaps: (i32, i32),
+        subtree: Option<NodeId>,
+    ) -> Option<TilingBlocker> {
+        if let Some(root_id) = tree.root_node_id().cloned() {
             let mut configures = Vec::new();
 
             let (outer, inner) = gaps;
-            let mut geo = layer_map_for_output(&output)
-                .non_exclusive_zone()
-                .as_local();
-            geo.loc.x += outer;
-            geo.loc.y += outer;
-            geo.size.w -= outer * 2;
-            geo.size.h -= outer * 2;
-            let mut stack = vec![geo];
+            let start_id = subtree
+                .filter(|id| id != &root_id && tree.get(id).is_ok())
+                .unwrap_or(root_id);
+            let start = if tree.get(&start_id).unwrap().parent().is_some() {
+                // edges of the subtree, that are adjacent to other nodes of the tree
+                let adjacent = [
+                    (Direction::Up, ResizeEdge::TOP),
+                    (Direction::Down, ResizeEdge::BOTTOM),
+                    (Direction::Left, ResizeEdge::LEFT),
+                    (Direction::Right, ResizeEdge::RIGHT),
+                ]
+                .into_iter()
+                .filter(|(direction, _)| {
+                    TilingLayout::has_adjacent_node(tree, &start_id, *direction)
+                })
+                .fold(ResizeEdge::empty(), |edges, (_, edge)| edges | edge);
+                (*tree.get(&start_id).unwrap().data().geometry(), adjacent)
+            } else {
+                let mut geo = layer_map_for_output(&output)
+                    .non_exclusive_zone()
+                    .as_local();
+                geo.loc.x += outer;
+                geo.loc.y += outer;
+                geo.size.w -= outer * 2;
+                geo.size.h -= outer * 2;
+                (geo, ResizeEdge::empty())
+            };
+            let mut stack = vec![start];
 
             for node_id in tree
-                .traverse_pre_order_ids(root_id)
+                .traverse_pre_order_ids(&start_id)
                 .unwrap()
                 .collect::<Vec<_>>()
                 .into_iter()
@@ -2992,38 +3070,20 @@ impl TilingLayout {
                     continue;
                 }
 
-                if let Some(mut geo) = stack.pop() {
+                if let Some((mut geo, adjacent)) = stack.pop() {
                     let node = tree.get(&node_id).unwrap();
                     let data = node.data();
                     if data.is_mapped(None) {
+                        let gap_for = |edge| {
+                            if adjacent.contains(edge) {
+                                inner / 2
+                            } else {
+                                inner
+                            }
+                        };
                         let gap = (
-                            (
-                                if TilingLayout::has_adjacent_node(tree, &node_id, Direction::Left)
-                                {
-                                    inner / 2
-                                } else {
-                                    inner
-                                },
-                                if TilingLayout::has_adjacent_node(tree, &node_id, Direction::Up) {
-                                    inner / 2
-                                } else {
-                                    inner
-                                },
-                            ),
-                            (
-                                if TilingLayout::has_adjacent_node(tree, &node_id, Direction::Right)
-                                {
-                                    inner / 2
-                                } else {
-                                    inner
-                                },
-                                if TilingLayout::has_adjacent_node(tree, &node_id, Direction::Down)
-                                {
-                                    inner / 2
-                                } else {
-                                    inner
-                                },
-                            ),
+                            (gap_for(ResizeEdge::LEFT), gap_for(ResizeEdge::TOP)),
+                            (gap_for(ResizeEdge::RIGHT), gap_for(ResizeEdge::BOTTOM)),
                         );
                         geo.loc += gap.0.into();
                         geo.size -= gap.0.into();
@@ -3037,36 +3097,58 @@ impl TilingLayout {
                     match data {
                         Data::Group {
                             orientation, sizes, ..
-                        } => match orientation {
-                            Orientation::Horizontal => {
-                                let mut previous: i32 = sizes.iter().sum();
-                                for size in sizes.iter().rev() {
-                                    previous -= *size;
-                                    stack.push(Rectangle::from_loc_and_size(
-                                        (geo.loc.x, geo.loc.y + previous),
-                                        (geo.size.w, *size),
-                                    ));
+                        } => {
+                            // children inherit the adjacent edges of their group
+                            let last = sizes.len().saturating_sub(1);
+                            match orientation {
+                                Orientation::Horizontal => {
+                                    let mut previous: i32 = sizes.iter().sum();
+                                    for (i, size) in sizes.iter().enumerate().rev() {
+                                        previous -= *size;
+                                        let mut adjacent = adjacent;
+                                        if i > 0 {
+                                            adjacent.insert(ResizeEdge::TOP);
+                                        }
+                                        if i < last {
+                                            adjacent.insert(ResizeEdge::BOTTOM);
+                                        }
+                                        stack.push((
+                                            Rectangle::from_loc_and_size(
+                                                (geo.loc.x, geo.loc.y + previous),
+                                                (geo.size.w, *size),
+                                            ),
+                                            adjacent,
+                                        ));
+                                    }
                                 }
-                            }
-                            Orientation::Vertical => {
-                                let mut previous: i32 = sizes.iter().sum();
-                                for size in sizes.iter().rev() {
-                                    previous -= *size;
-                                    stack.push(Rectangle::from_loc_and_size(
-                                        (geo.loc.x + previous, geo.loc.y),
-                                        (*size, geo.size.h),
-                                    ));
+                                Orientation::Vertical => {
+                                    let mut previous: i32 = sizes.iter().sum();
+                                    for (i, size) in sizes.iter().enumerate().rev() {
+                                        previous -= *size;
+                                        let mut adjacent = adjacent;
+                                        if i > 0 {
+                                            adjacent.insert(ResizeEdge::LEFT);
+                                        }
+                                        if i < last {
+                                            adjacent.insert(ResizeEdge::RIGHT);
+                                        }
+                                        stack.push((
+                                            Rectangle::from_loc_and_size(
+                                                (geo.loc.x + previous, geo.loc.y),
+                                                (*size, geo.size.h),
+                                            ),
+                                            adjacent,
+                                        ));
+                                    }
                                 }
                             }
-                        },
+                        }
                         Data::Mapped { mapped, .. } => {
                             if !(mapped.is_fullscreen(true) || mapped.is_maximized(true)) {
                                 mapped.set_tiled(true);
                                 let internal_geometry = geo.to_global(&output);
                                 mapped.set_geometry(internal_geometry);
-                                if let Some(serial) = mapped.configure() {
-                                    configures.push((mapped.active_window(), serial));
-                                }
+                                configures.push(mapped.clone());
                             }
                         }
                         Data::Placeholder { .. } => {}
@@ -3075,13 +3157,7 @@ impl TilingLayout {
             }
 
             if !configures.is_empty() {
-                let blocker = TilingBlocker::new(configures);
-                for (surface, _) in &blocker.necessary_acks {
-                    if let Some(surface) = surface.wl_surface() {
-                        add_blocker(&surface, blocker.clone());
-                    }
-                }
-                return Some(blocker);
+                return Some(TilingBlocker::new(configures));
             }
         }
 
@@ -3986,7 +4062,7 @@ impl TilingLayout {
     }
 
     fn gaps(&self) -> (i32, i32) {
-        let g = self.theme.cosmic().gaps;
+        let g = self.gaps_override.unwrap_or(self.theme.cosmic().gaps);
         (g.0 as i32, g.1 as i32)
     }
 }
@@ -5391,3 +5467,190 @@ fn scale_to_center<C>(
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use smithay::{
+        output::{Mode, PhysicalProperties, Scale, Subpixel},
+        utils::Transform,
+    };
+
+    fn test_output() -> Output {
+        let output = Output::new(
+            String::from("test"),
+            PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: String::from("COSMIC"),
+                model: String::from("Test"),
+            },
+        );
+        output.change_current_state(
+            Some(Mode {
+                size: (1000, 800).into(),
+                refresh: 60_000,
+            }),
+            Some(Transform::Normal),
+            Some(Scale::Integer(1)),
+            Some((0, 0).into()),
+        );
+        layer_map_for_output(&output).arrange();
+        output
+    }
+
+    fn placeholder() -> Node<Data> {
+        Node::new(Data::Placeholder {
+            last_geometry: Rectangle::default(),
+            initial_placeholder: false,
+        })
+    }
+
+    struct TestTree {
+        tree: Tree<Data>,
+        root: NodeId,
+        left: NodeId,
+        right: NodeId,
+        top: NodeId,
+        bottom: NodeId,
+    }
+
+    /// A placeholder on the left, a group of two placeholders on top of each other on the right
+    fn test_tree(output: &Output) -> TestTree {
+        let geo = Rectangle::from_loc_and_size((0, 0), (1000, 800));
+        let mut tree = Tree::new();
+        let root = tree
+            .insert(
+                Node::new(Data::new_group(Orientation::Vertical, geo)),
+                InsertBehavior::AsRoot,
+            )
+            .unwrap();
+        let left = tree
+            .insert(placeholder(), InsertBehavior::UnderNode(&root))
+            .unwrap();
+        let right = tree
+            .insert(
+                Node::new(Data::new_group(
+                    Orientation::Horizontal,
+                    Rectangle::from_loc_and_size((500, 0), (500, 800)),
+                )),
+                InsertBehavior::UnderNode(&root),
+            )
+            .unwrap();
+        let top = tree
+            .insert(placeholder(), InsertBehavior::UnderNode(&right))
+            .unwrap();
+        let bottom = tree
+            .insert(placeholder(), InsertBehavior::UnderNode(&right))
+            .unwrap();
+        TilingLayout::update_positions(output, &mut tree, (0, 0));
+
+        TestTree {
+            tree,
+            root,
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+
+    fn geometry(tree: &Tree<Data>, node: &NodeId) -> Rectangle<i32, Local> {
+        *tree.get(node).unwrap().data().geometry()
+    }
+
+    fn set_sizes(tree: &mut Tree<Data>, node: &NodeId, new_sizes: Vec<i32>) {
+        match tree.get_mut(node).unwrap().data_mut() {
+            Data::Group { sizes, .. } => *sizes = new_sizes,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_unaffected_ancestor() {
+        let output = test_output();
+        let TestTree {
+            tree,
+            root,
+            left,
+            right,
+            top,
+            ..
+        } = test_tree(&output);
+
+        assert_eq!(TilingLayout::unaffected_ancestor(&tree, &top), Some(root));
+        // the root itself may be replaced, when flattening its group
+        assert_eq!(TilingLayout::unaffected_ancestor(&tree, &left), None);
+        assert_eq!(TilingLayout::unaffected_ancestor(&tree, &right), None);
+    }
+
+    #[test]
+    fn test_update_subtree_positions() {
+        let output = test_output();
+        let TestTree {
+            mut tree,
+            left,
+            right,
+            top,
+            bottom,
+            ..
+        } = test_tree(&output);
+        assert_eq!(
+            geometry(&tree, &left),
+            Rectangle::from_loc_and_size((0, 0), (500, 800))
+        );
+        assert_eq!(
+            geometry(&tree, &top),
+            Rectangle::from_loc_and_size((500, 0), (500, 400))
+        );
+
+        // nodes outside of the subtree aren't touched
+        let untouched = Rectangle::from_loc_and_size((1, 2), (3, 4));
+        tree.get_mut(&left)
+            .unwrap()
+            .data_mut()
+            .update_geometry(untouched);
+        set_sizes(&mut tree, &right, vec![200, 600]);
+        TilingLayout::update_subtree_positions(&output, &mut tree, (0, 0), Some(right.clone()));
+        assert_eq!(geometry(&tree, &left), untouched);
+        assert_eq!(
+            geometry(&tree, &top),
+            Rectangle::from_loc_and_size((500, 0), (500, 200))
+        );
+        assert_eq!(
+            geometry(&tree, &bottom),
+            Rectangle::from_loc_and_size((500, 200), (500, 600))
+        );
+
+        // and the subtree ends up like after a relayout of the whole tree
+        let TestTree {
+            tree: mut full,
+            right: full_right,
+            top: full_top,
+            bottom: full_bottom,
+            ..
+        } = test_tree(&output);
+        set_sizes(&mut full, &full_right, vec![200, 600]);
+        TilingLayout::update_positions(&output, &mut full, (0, 0));
+        assert_eq!(geometry(&tree, &top), geometry(&full, &full_top));
+        assert_eq!(geometry(&tree, &bottom), geometry(&full, &full_bottom));
+    }
+
+    #[test]
+    fn test_update_subtree_positions_falls_back_to_root() {
+        let output = test_output();
+        let TestTree {
+            mut tree,
+            root,
+            left,
+            ..
+        } = test_tree(&output);
+
+        set_sizes(&mut tree, &root, vec![300, 700]);
+        TilingLayout::update_subtree_positions(&output, &mut tree, (0, 0), Some(root));
+        assert_eq!(
+            geometry(&tree, &left),
+            Rectangle::from_loc_and_size((0, 0), (300, 800))
+        );
+    }
+}
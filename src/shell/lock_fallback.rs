@@ -0,0 +1,435 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Built-in lock screen, shown if the client locking the session goes away without unlocking it.
+//!
+//! `ext-session-lock` keeps the session locked in that case, so instead of leaving the outputs
+//! blank, the compositor asks for the password itself. It is checked by `cosmic-comp-auth`,
+//! a helper authenticating the user through PAM. A new lock client can still take over.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use calloop::{
+    channel::{self, Event},
+    LoopHandle, RegistrationToken,
+};
+use cosmic::{
+    iced::widget::{column, container},
+    iced_core::{Alignment, Background, Border, Color, Length},
+    theme,
+    widget::text,
+    Apply,
+};
+use smithay::{
+    backend::renderer::{
+        element::{AsRenderElements, RenderElement},
+        ImportAll, ImportMem, Renderer,
+    },
+    desktop::space::SpaceElement,
+    output::Output,
+    reexports::wayland_server::Resource,
+    utils::{Logical, Rectangle, Size},
+};
+use tracing::{info, warn};
+use xkbcommon::xkb::Keysym;
+
+use crate::{
+    backend::render::element::AsGlowRenderer,
+    fl,
+    shell::{CosmicMappedRenderElement, Shell},
+    state::State,
+    utils::{
+        iced::{IcedElement, Program},
+        prelude::*,
+    },
+};
+
+const PROMPT_SIZE: (i32, i32) = (480, 176);
+/// Longer passwords don't add any more dots
+const MAX_DOTS: usize = 24;
+const AUTH_HELPER: &str = "cosmic-comp-auth";
+
+pub type LockPrompt = IcedElement<LockPromptInternal>;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct PromptStatus {
+    /// Number of characters entered
+    length: usize,
+    checking: bool,
+    failed: bool,
+}
+
+pub struct LockPromptInternal(Mutex<PromptStatus>);
+
+impl Program for LockPromptInternal {
+    type Message = ();
+
+    fn view(&self) -> cosmic::Element<'_, Self::Message> {
+        let status = *self.0.lock().unwrap();
+        let message = if status.checking {
+            fl!("lock-fallback-checking")
+        } else if status.failed {
+            fl!("lock-fallback-failed")
+        } else {
+            fl!("lock-fallback-prompt")
+        };
+
+        column(vec![
+            text(fl!("lock-fallback-title"))
+                .font(cosmic::font::FONT_SEMIBOLD)
+                .size(24)
+                .into(),
+            text(message).font(cosmic::font::FONT).size(14).into(),
+            text("•".repeat(status.length.min(MAX_DOTS)))
+                .font(cosmic::font::FONT)
+                .size(24)
+                .into(),
+        ])
+        .spacing(16)
+        .align_items(Alignment::Center)
+        .apply(container)
+        .center_x()
+        .center_y()
+        .padding(24)
+        .style(theme::Container::custom(|theme| container::Appearance {
+            icon_color: Some(Color::from(theme.cosmic().background.on)),
+            text_color: Some(Color::from(theme.cosmic().background.on)),
+            background: Some(Background::Color(theme.cosmic().background.base.into())),
+            border: Border {
+                radius: 18.0.into(),
+                width: 0.0,
+                color: Color::TRANSPARENT,
+            },
+            shadow: Default::default(),
+        }))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
+    }
+}
+
+/// Entered password, its bytes are overwritten once they aren't needed anymore
+#[derive(Default)]
+struct Password(String);
+
+impl Password {
+    fn push(&mut self, c: char) {
+        if self.0.len() + c.len_utf8() > self.0.capacity() {
+            // grow by hand, reallocating would leave a copy behind
+            let mut grown = String::with_capacity((self.0.capacity() * 2).max(64));
+            grown.push_str(&self.0);
+            self.truncate(0);
+            self.0 = grown;
+        }
+        self.0.push(c);
+    }
+
+    fn pop(&mut self) {
+        let len = self.0.char_indices().last().map_or(0, |(i, _)| i);
+        self.truncate(len);
+    }
+
+    fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        // SAFETY: `len` is a char boundary and only zeroes are written after it
+        let bytes = unsafe { self.0.as_mut_vec() };
+        for byte in &mut bytes[len..] {
+            unsafe { std::ptr::write_volatile(byte, 0) };
+        }
+        bytes.truncate(len);
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Password prompt of a session, whose lock client is gone
+pub struct LockFallback {
+    prompt: LockPrompt,
+    password: Password,
+    /// Outputs the prompt was shown on
+    outputs: Vec<Output>,
+    /// Channel of the running helper
+    checking: Option<RegistrationToken>,
+}
+
+impl fmt::Debug for LockFallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockFallback")
+            .field("prompt", &self.prompt)
+            .field("checking", &self.checking)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for LockFallback {
+    fn drop(&mut self) {
+        if let Some(token) = self.checking.take() {
+            self.prompt.loop_handle().remove(token);
+        }
+    }
+}
+
+impl LockFallback {
+    fn new(handle: LoopHandle<'static, State>, theme: cosmic::Theme) -> LockFallback {
+        LockFallback {
+            prompt: LockPrompt::new(
+                LockPromptInternal(Mutex::new(PromptStatus::default())),
+                Size::<i32, Logical>::from(PROMPT_SIZE),
+                handle,
+                theme,
+            ),
+            password: Password::default(),
+            outputs: Vec::new(),
+            checking: None,
+        }
+    }
+
+    fn update(&self, failed: bool) {
+        self.prompt.with_program(|p| {
+            *p.0.lock().unwrap() = PromptStatus {
+                length: self.password.0.chars().count(),
+                checking: self.checking.is_some(),
+                failed,
+            }
+        });
+        self.prompt.force_update();
+    }
+
+    /// The prompt, centered on `output`
+    pub fn render<R>(&self, renderer: &mut R, output: &Output) -> Vec<CosmicMappedRenderElement<R>>
+    where
+        R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+    {
+        let scale = output.current_scale().fractional_scale();
+        let output_size = output.geometry().size.as_local();
+        let size = Size::from(PROMPT_SIZE);
+        let geometry = Rectangle::<i32, Local>::from_loc_and_size(
+            ((output_size.w - size.w) / 2, (output_size.h - size.h) / 2),
+            size,
+        );
+        self.prompt.render_elements::<CosmicMappedRenderElement<R>>(
+            renderer,
+            geometry.loc.as_logical().to_physical_precise_round(scale),
+            scale.into(),
+            1.0,
+        )
+    }
+}
+
+/// Checks `password` with the helper, preferring the one next to the compositor binary
+fn authenticate(password: Password) -> io::Result<bool> {
+    let helper = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(AUTH_HELPER)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(AUTH_HELPER));
+
+    let mut cmd = Command::new(helper);
+    cmd.stdin(Stdio::piped()).stdout(Stdio::null());
+    unsafe { cmd.pre_exec(|| Ok(crate::utils::rlimit::restore_nofile_limit())) };
+    let mut child = cmd.spawn()?;
+    // closing stdin ends the password
+    let res = child.stdin.take().unwrap().write_all(password.0.as_bytes());
+    std::mem::drop(password);
+    let status = child.wait()?;
+    res?;
+    match status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        _ => Err(io::Error::other(format!(
+            "{} failed with {}",
+            AUTH_HELPER, status
+        ))),
+    }
+}
+
+impl State {
+    /// Shows the built-in lock screen, once the client locking the session is gone
+    pub fn check_session_lock(&mut self) {
+        {
+            // checked every loop iteration, so don't block other threads without a reason
+            let shell = self.common.shell.read().unwrap();
+            let up_to_date = match shell.session_lock.as_ref() {
+                None => true,
+                Some(session_lock) => match session_lock.fallback.as_ref() {
+                    None => session_lock.ext_session_lock.is_alive(),
+                    Some(fallback) => shell
+                        .outputs()
+                        .all(|output| fallback.outputs.contains(output)),
+                },
+            };
+            if up_to_date {
+                return;
+            }
+        }
+
+        let mut shell = self.common.shell.write().unwrap();
+        let theme = shell.theme().clone();
+        let outputs = shell.outputs().cloned().collect::<Vec<_>>();
+        let Some(session_lock) = shell.session_lock.as_mut() else {
+            return;
+        };
+
+        let created = session_lock.fallback.is_none();
+        if created {
+            if session_lock.ext_session_lock.is_alive() {
+                return;
+            }
+            warn!("Session lock client is gone, showing the built-in lock screen");
+            session_lock.surfaces.clear();
+        }
+        let fallback = session_lock
+            .fallback
+            .get_or_insert_with(|| LockFallback::new(self.common.event_loop_handle.clone(), theme));
+        // outputs might have been added since
+        for output in &outputs {
+            if !fallback.outputs.contains(output) {
+                fallback
+                    .prompt
+                    .output_enter(output, Rectangle::default() /* unused */);
+                fallback.outputs.push(output.clone());
+            }
+        }
+        std::mem::drop(shell);
+
+        if created {
+            for output in &outputs {
+                self.backend.schedule_render(output);
+            }
+        }
+    }
+
+    /// Handles a key while the built-in lock screen is shown, returns `false` if it isn't.
+    ///
+    /// Takes the locked `shell`, as keys reach the lock screen before anything else.
+    pub fn lock_fallback_key_input(
+        &mut self,
+        shell: &mut Shell,
+        sym: Keysym,
+        pressed: bool,
+    ) -> bool {
+        let Some(fallback) = shell
+            .session_lock
+            .as_mut()
+            .and_then(|session_lock| session_lock.fallback.as_mut())
+        else {
+            return false;
+        };
+        if !pressed || fallback.checking.is_some() {
+            return true;
+        }
+
+        let mut submitted = None;
+        match sym {
+            Keysym::Return | Keysym::KP_Enter => {
+                if !fallback.password.0.is_empty() {
+                    submitted = Some(std::mem::take(&mut fallback.password));
+                }
+            }
+            Keysym::BackSpace => {
+                fallback.password.pop();
+            }
+            Keysym::Escape => fallback.password.clear(),
+            sym => {
+                if let Some(c) = sym.key_char().filter(|c| !c.is_control()) {
+                    fallback.password.push(c);
+                }
+            }
+        }
+        fallback.update(false);
+
+        if let Some(password) = submitted {
+            self.check_lock_password(shell, password);
+        }
+        for output in shell.outputs() {
+            self.backend.schedule_render(output);
+        }
+        true
+    }
+
+    fn check_lock_password(&mut self, shell: &mut Shell, password: Password) {
+        let (tx, rx) = channel::channel();
+        let token = match self
+            .common
+            .event_loop_handle
+            .insert_source(rx, |event, _, state| {
+                if let Event::Msg(result) = event {
+                    state.lock_fallback_result(result);
+                }
+            }) {
+            Ok(token) => token,
+            Err(err) => {
+                warn!(?err, "Failed to add channel to event_loop");
+                return;
+            }
+        };
+
+        let res = std::thread::Builder::new()
+            .name("lock-auth".to_string())
+            .spawn(move || {
+                let _ = tx.send(authenticate(password));
+            });
+        if let Err(err) = res {
+            warn!(?err, "Failed to start helper thread");
+            self.common.event_loop_handle.remove(token);
+            return;
+        }
+
+        match shell
+            .session_lock
+            .as_mut()
+            .and_then(|session_lock| session_lock.fallback.as_mut())
+        {
+            Some(fallback) => {
+                fallback.checking = Some(token);
+                fallback.update(false);
+            }
+            None => self.common.event_loop_handle.remove(token),
+        }
+    }
+
+    fn lock_fallback_result(&mut self, result: io::Result<bool>) {
+        let mut shell = self.common.shell.write().unwrap();
+        let Some(fallback) = shell
+            .session_lock
+            .as_mut()
+            .and_then(|session_lock| session_lock.fallback.as_mut())
+        else {
+            return;
+        };
+        if let Some(token) = fallback.checking.take() {
+            self.common.event_loop_handle.remove(token);
+        }
+
+        match result {
+            Ok(true) => {
+                info!("Session unlocked by the built-in lock screen");
+                shell.session_lock = None;
+            }
+            Ok(false) => fallback.update(true),
+            Err(err) => {
+                warn!(?err, "Failed to check the password");
+                fallback.update(true);
+            }
+        }
+
+        for output in shell.outputs() {
+            self.backend.schedule_render(output);
+        }
+    }
+}
@@ -2,15 +2,17 @@ use calloop::LoopHandle;
 use grabs::SeatMoveGrabState;
 use indexmap::IndexMap;
 use std::{
+    borrow::Cow,
     collections::HashMap,
-    sync::atomic::Ordering,
+    sync::{atomic::Ordering, Arc},
     time::{Duration, Instant},
 };
 use wayland_backend::server::ClientId;
 
 use cosmic_comp_config::{
-    workspace::{WorkspaceLayout, WorkspaceMode},
-    TileBehavior,
+    workspace::{WorkspaceLayout, WorkspaceMode, WorkspaceOverride},
+    AdaptiveSyncMode, BlurConfig, ColorFilter, GameModeConfig, ShadowConfig, TearingConfig,
+    TearingMode, TileBehavior, ZoomConfig,
 };
 use cosmic_protocols::workspace::v1::server::zcosmic_workspace_handle_v1::{
     State as WState, TilingState,
@@ -19,7 +21,14 @@ use cosmic_settings_config::shortcuts;
 use cosmic_settings_config::shortcuts::action::{Direction, FocusDirection, ResizeDirection};
 use keyframe::{ease, functions::EaseInOutCubic};
 use smithay::{
-    backend::{input::TouchSlot, renderer::element::RenderElementStates},
+    backend::{
+        allocator::dmabuf::Dmabuf,
+        input::TouchSlot,
+        renderer::{
+            element::{Id, RenderElementStates},
+            utils::with_renderer_surface_state,
+        },
+    },
     desktop::{
         layer_map_for_output,
         space::SpaceElement,
@@ -27,7 +36,7 @@ use smithay::{
             surface_presentation_feedback_flags_from_states, surface_primary_scanout_output,
             take_presentation_feedback_surface_tree, OutputPresentationFeedback,
         },
-        LayerSurface, PopupKind, WindowSurface, WindowSurfaceType,
+        LayerSurface, PopupKind, PopupManager, WindowSurface, WindowSurfaceType,
     },
     input::{
         pointer::{Focus, GrabStartData as PointerGrabStartData},
@@ -40,7 +49,9 @@ use smithay::{
     },
     utils::{IsAlive, Logical, Point, Rectangle, Serial, Size},
     wayland::{
-        compositor::with_states,
+        compositor::{with_states, with_surface_tree_downward, TraversalAction},
+        content_type::ContentType,
+        dmabuf::get_dmabuf,
         foreign_toplevel_list::ForeignToplevelListState,
         seat::WaylandFocus,
         session_lock::LockSurface,
@@ -51,11 +62,18 @@ use smithay::{
 };
 
 use crate::{
-    backend::render::animations::spring::{Spring, SpringParams},
+    backend::render::{
+        animations::spring::{Spring, SpringParams},
+        wallpaper::WallpaperState,
+    },
+    bell::BellFlash,
     config::Config,
+    input::locate_pointer::SeatLocatePointer,
+    shell::lock_fallback::LockFallback,
     utils::{prelude::*, quirks::WORKSPACE_OVERVIEW_NAMESPACE},
     wayland::{
         handlers::{
+            content_type::surface_content_type,
             foreign_toplevel_list::{
                 new_foreign_toplevel, refresh_foreign_toplevels, remove_foreign_toplevel,
             },
@@ -64,6 +82,7 @@ use crate::{
             xdg_shell::popup::get_popup_toplevel,
         },
         protocols::{
+            tearing_control::surface_wants_tearing,
             toplevel_info::{
                 toplevel_enter_output, toplevel_enter_workspace, toplevel_leave_output,
                 toplevel_leave_workspace, ToplevelInfoState,
@@ -80,6 +99,7 @@ pub mod element;
 pub mod focus;
 pub mod grabs;
 pub mod layout;
+pub mod lock_fallback;
 mod seats;
 mod workspace;
 pub use self::element::{CosmicMapped, CosmicMappedRenderElement, CosmicSurface};
@@ -90,7 +110,7 @@ use self::{
     element::{
         resize_indicator::{resize_indicator, ResizeIndicator},
         swap_indicator::{swap_indicator, SwapIndicator},
-        CosmicWindow, MaximizedState,
+        CosmicWindow, MaximizedState, WindowStyle,
     },
     focus::target::{KeyboardFocusTarget, PointerFocusTarget},
     grabs::{
@@ -237,12 +257,32 @@ pub struct Shell {
     pub pending_windows: Vec<(CosmicSurface, Seat<State>, Option<Output>)>,
     pub pending_layers: Vec<(LayerSurface, Output, Seat<State>)>,
     pub pending_activations: HashMap<ActivationKey, ActivationContext>,
+    /// Override-redirect windows in X11 stacking order, bottom to top
     pub override_redirect_windows: Vec<X11Surface>,
     pub session_lock: Option<SessionLock>,
     pub seats: Seats,
 
     theme: cosmic::Theme,
     pub active_hint: bool,
+    pub tearing: TearingConfig,
+    pub game_mode: GameModeConfig,
+    pub adaptive_sync: AdaptiveSyncMode,
+    pub zoom: ZoomConfig,
+    /// Magnification of the output the pointer is on, `1.0` if zoom is off
+    pub zoom_level: f64,
+    /// Filter applied to the content of all outputs, `None` if colors are shown as is
+    pub color_filter: Option<ColorFilter>,
+    pub wallpapers: WallpaperState,
+    pub blur: BlurConfig,
+    pub capture_excluded_apps: Vec<String>,
+    pub shadow: ShadowConfig,
+    /// Rounding and dimming of windows, taken over by the elements on refresh
+    pub window_style: Arc<WindowStyle>,
+    /// Outputs are dimmed, because the session is about to go idle
+    pub idle_dimmed: bool,
+    pub idle_dim_id: Id,
+    /// Visual bell currently flashing
+    pub bell: Option<BellFlash>,
     overview_mode: OverviewMode,
     swap_indicator: Option<SwapIndicator>,
     resize_mode: ResizeMode,
@@ -264,6 +304,8 @@ pub struct Shell {
 pub struct SessionLock {
     pub ext_session_lock: ExtSessionLockV1,
     pub surfaces: HashMap<Output, LockSurface>,
+    /// Built-in lock screen, once the lock client is gone
+    pub fallback: Option<LockFallback>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -314,6 +356,7 @@ pub struct WorkspaceSet {
     tiling_enabled: bool,
     output: Output,
     theme: cosmic::Theme,
+    overrides: WorkspaceOverrides,
     pub sticky_layer: FloatingLayout,
     pub minimized_windows: Vec<MinimizedWindow>,
     pub workspaces: Vec<Workspace>,
@@ -326,7 +369,9 @@ fn create_workspace(
     active: bool,
     tiling: bool,
     theme: cosmic::Theme,
+    (name, settings): (Option<String>, WorkspaceOverride),
 ) -> Workspace {
+    let tiling = settings.tiling.unwrap_or(tiling);
     let workspace_handle = state
         .create_workspace(
             &group_handle,
@@ -344,7 +389,48 @@ fn create_workspace(
         &workspace_handle,
         [WorkspaceCapabilities::Activate].into_iter(),
     );
-    Workspace::new(workspace_handle, output.clone(), tiling, theme.clone())
+    Workspace::new(
+        workspace_handle,
+        output.clone(),
+        tiling,
+        theme.clone(),
+        name,
+        settings,
+    )
+}
+
+/// Names and settings of individual workspaces from the config
+#[derive(Debug, Clone, Default)]
+struct WorkspaceOverrides {
+    names: Vec<String>,
+    settings: HashMap<String, WorkspaceOverride>,
+}
+
+impl WorkspaceOverrides {
+    fn new(config: &Config) -> WorkspaceOverrides {
+        WorkspaceOverrides {
+            names: config.cosmic_conf.workspace_names.clone(),
+            settings: config.cosmic_conf.workspace_overrides.clone(),
+        }
+    }
+
+    /// Name and settings of the workspace with the given number.
+    ///
+    /// Settings for the name of the workspace take precedence over the ones for its number.
+    fn resolve(&self, number: usize) -> (Option<String>, WorkspaceOverride) {
+        let name = self
+            .names
+            .get(number.wrapping_sub(1))
+            .filter(|name| !name.is_empty())
+            .cloned();
+        let settings = name
+            .as_ref()
+            .and_then(|name| self.settings.get(name))
+            .or_else(|| self.settings.get(&number.to_string()))
+            .cloned()
+            .unwrap_or_default();
+        (name, settings)
+    }
 }
 
 fn move_workspace_to_group(
@@ -415,6 +501,7 @@ impl WorkspaceSet {
         idx: usize,
         tiling_enabled: bool,
         theme: cosmic::Theme,
+        overrides: WorkspaceOverrides,
     ) -> WorkspaceSet {
         let group_handle = state.create_workspace_group();
         let workspaces = {
@@ -425,8 +512,9 @@ impl WorkspaceSet {
                 true,
                 tiling_enabled,
                 theme.clone(),
+                overrides.resolve(1),
             );
-            workspace_set_idx(state, 1, idx, &workspace.handle);
+            workspace_set_idx(state, 1, idx, &workspace);
             state.set_workspace_capabilities(
                 &workspace.handle,
                 [WorkspaceCapabilities::Activate].into_iter(),
@@ -442,6 +530,7 @@ impl WorkspaceSet {
             idx,
             tiling_enabled,
             theme,
+            overrides,
             sticky_layer,
             minimized_windows: Vec::new(),
             workspaces,
@@ -556,12 +645,13 @@ impl WorkspaceSet {
             false,
             self.tiling_enabled,
             self.theme.clone(),
+            self.overrides.resolve(self.workspaces.len() + 1),
         );
         workspace_set_idx(
             state,
             self.workspaces.len() as u8 + 1,
             self.idx,
-            &workspace.handle,
+            &workspace,
             // this method is only used by code paths related to dynamic workspaces, so this should be fine
         );
         self.workspaces.push(workspace);
@@ -598,7 +688,7 @@ impl WorkspaceSet {
 
         if keep.iter().any(|val| *val == false) {
             for (i, workspace) in self.workspaces.iter().enumerate() {
-                workspace_set_idx(state, i as u8 + 1, self.idx, &workspace.handle);
+                workspace_set_idx(state, i as u8 + 1, self.idx, workspace);
             }
         }
     }
@@ -606,7 +696,7 @@ impl WorkspaceSet {
     fn update_idx(&mut self, state: &mut WorkspaceUpdateGuard<'_, State>, idx: usize) {
         self.idx = idx;
         for (i, workspace) in self.workspaces.iter().enumerate() {
-            workspace_set_idx(state, i as u8 + 1, idx, &workspace.handle);
+            workspace_set_idx(state, i as u8 + 1, idx, workspace);
         }
     }
 }
@@ -620,6 +710,7 @@ pub struct Workspaces {
     autotile: bool,
     autotile_behavior: TileBehavior,
     theme: cosmic::Theme,
+    overrides: WorkspaceOverrides,
 }
 
 impl Workspaces {
@@ -632,6 +723,7 @@ impl Workspaces {
             autotile: config.cosmic_conf.autotile,
             autotile_behavior: config.cosmic_conf.autotile_behavior,
             theme,
+            overrides: WorkspaceOverrides::new(config),
         }
     }
 
@@ -659,6 +751,7 @@ impl Workspaces {
                     self.sets.len(),
                     self.autotile,
                     self.theme.clone(),
+                    self.overrides.clone(),
                 )
             });
         workspace_state.add_group_output(&set.group, &output);
@@ -686,7 +779,7 @@ impl Workspaces {
             for (i, workspace) in set.workspaces.iter_mut().enumerate() {
                 workspace.set_output(output);
                 workspace.refresh(xdg_activation_state);
-                workspace_set_idx(workspace_state, i as u8 + 1, set.idx, &workspace.handle);
+                workspace_set_idx(workspace_state, i as u8 + 1, set.idx, workspace);
                 if i == set.active {
                     workspace_state.add_workspace_state(&workspace.handle, WState::Active);
                 }
@@ -802,6 +895,10 @@ impl Workspaces {
         let old_mode = self.mode;
         self.mode = config.cosmic_conf.workspaces.workspace_mode;
         self.layout = config.cosmic_conf.workspaces.workspace_layout;
+        self.overrides = WorkspaceOverrides::new(config);
+        for set in self.sets.values_mut().chain(self.backup_set.as_mut()) {
+            set.overrides = self.overrides.clone();
+        }
 
         if self.sets.len() <= 1 {
             return;
@@ -848,6 +945,7 @@ impl Workspaces {
                                     false,
                                     config.cosmic_conf.autotile,
                                     self.theme.clone(),
+                                    self.overrides.resolve(j + 1),
                                 ),
                             );
                         }
@@ -932,12 +1030,7 @@ impl Workspaces {
                 if keep.iter().any(|val| *val == false) {
                     for set in self.sets.values_mut() {
                         for (i, workspace) in set.workspaces.iter().enumerate() {
-                            workspace_set_idx(
-                                workspace_state,
-                                i as u8 + 1,
-                                set.idx,
-                                &workspace.handle,
-                            );
+                            workspace_set_idx(workspace_state, i as u8 + 1, set.idx, workspace);
                         }
                     }
                 }
@@ -1096,7 +1189,7 @@ impl Workspaces {
             if matches!(self.autotile_behavior, TileBehavior::Global) {
                 // must apply change to all workspaces now
                 for w in &mut set.workspaces {
-                    if w.tiling_enabled == self.autotile {
+                    if w.tiling_enabled == w.settings.tiling.unwrap_or(self.autotile) {
                         continue;
                     }
                     for s in &seats {
@@ -1169,6 +1262,19 @@ impl Common {
     pub fn update_config(&mut self) {
         let mut shell = self.shell.write().unwrap();
         shell.active_hint = self.config.cosmic_conf.active_hint;
+        shell.tearing = self.config.cosmic_conf.tearing.clone();
+        shell.game_mode = self.config.cosmic_conf.game_mode;
+        shell.adaptive_sync = self.config.cosmic_conf.adaptive_sync;
+        shell.zoom = self.config.cosmic_conf.zoom;
+        shell.blur = self.config.cosmic_conf.blur.clone();
+        shell.capture_excluded_apps = self.config.cosmic_conf.capture_excluded_apps.clone();
+        shell.shadow = self.config.cosmic_conf.shadow;
+        shell.window_style = Arc::new(WindowStyle {
+            corner_radius: self.config.cosmic_conf.corner_radius,
+            dim_unfocused: self.config.cosmic_conf.dim_unfocused.clone(),
+        });
+        let style = shell.window_style.clone();
+        shell.mapped().for_each(|mapped| mapped.set_style(&style));
 
         let mut workspace_state = self.workspace_state.update();
         shell.workspaces.update_config(
@@ -1179,6 +1285,7 @@ impl Common {
     }
 
     #[profiling::function]
+    #[tracing::instrument(name = "shell_refresh", skip_all)]
     pub fn refresh(&mut self) {
         self.xdg_activation_state.retain_tokens(|_, data| {
             Instant::now().duration_since(data.timestamp) < Duration::from_secs(5)
@@ -1201,7 +1308,19 @@ impl Common {
                 surface_primary_scanout_output(surface, states).is_some()
             })
         });
-        self.idle_notifier_state.set_is_inhibited(is_inhibited);
+        // Fullscreen videos and games are being watched, even if the client forgot to inhibit.
+        let is_watched = {
+            let shell = self.shell.read().unwrap();
+            shell.outputs().any(|output| {
+                matches!(
+                    shell.fullscreen_content_type(output),
+                    Some(ContentType::Video | ContentType::Game)
+                )
+            })
+        };
+        self.idle_notifier_state
+            .set_is_inhibited(is_inhibited || is_watched);
+        self.idle_state.set_inhibited(is_inhibited || is_watched);
     }
 
     pub fn on_commit(&mut self, surface: &WlSurface) {
@@ -1243,6 +1362,27 @@ impl Shell {
 
             theme,
             active_hint: config.cosmic_conf.active_hint,
+            tearing: config.cosmic_conf.tearing.clone(),
+            game_mode: config.cosmic_conf.game_mode,
+            adaptive_sync: config.cosmic_conf.adaptive_sync,
+            zoom: config.cosmic_conf.zoom,
+            zoom_level: 1.0,
+            color_filter: config
+                .cosmic_conf
+                .color_filter
+                .enabled
+                .then_some(config.cosmic_conf.color_filter.filter),
+            wallpapers: WallpaperState::default(),
+            blur: config.cosmic_conf.blur.clone(),
+            capture_excluded_apps: config.cosmic_conf.capture_excluded_apps.clone(),
+            shadow: config.cosmic_conf.shadow,
+            window_style: Arc::new(WindowStyle {
+                corner_radius: config.cosmic_conf.corner_radius,
+                dim_unfocused: config.cosmic_conf.dim_unfocused.clone(),
+            }),
+            idle_dimmed: false,
+            idle_dim_id: Id::new(),
+            bell: None,
             overview_mode: OverviewMode::None,
             swap_indicator: None,
             resize_mode: ResizeMode::None,
@@ -1398,6 +1538,58 @@ impl Shell {
         self.workspaces.active(output).1
     }
 
+    /// Whether a game, i.e. a fullscreen X11 window, is shown on `output`
+    /// and the low latency policy of the game mode applies to it
+    pub fn game_mode_active(&self, output: &Output) -> bool {
+        self.game_mode.enabled
+            && self
+                .active_space(output)
+                .get_fullscreen()
+                .is_some_and(|surface| surface.x11_surface().is_some())
+    }
+
+    /// Whether the active fullscreen window of `output` may be presented with tearing
+    // TODO: Submit async page flips while this holds, once `DrmCompositor` supports them
+    #[allow(dead_code)]
+    pub fn tearing_allowed(&self, output: &Output) -> bool {
+        let Some(surface) = self.active_space(output).get_fullscreen() else {
+            return false;
+        };
+
+        if let Some(allowed) = self.tearing.app_overrides.get(&surface.app_id()) {
+            return *allowed;
+        }
+        if self.game_mode.tearing && self.game_mode_active(output) {
+            return true;
+        }
+        match self.tearing.mode {
+            TearingMode::Never => false,
+            TearingMode::Always => true,
+            TearingMode::ClientHint => surface
+                .wl_surface()
+                .is_some_and(|surface| surface_wants_tearing(&surface)),
+        }
+    }
+
+    /// Returns if adaptive sync should currently be used on `output`, given it supports it
+    pub fn adaptive_sync_wanted(&self, output: &Output) -> bool {
+        match self.adaptive_sync {
+            AdaptiveSyncMode::Always => true,
+            AdaptiveSyncMode::OnlyFullscreen => {
+                self.active_space(output).get_fullscreen().is_some()
+            }
+            AdaptiveSyncMode::Never => false,
+        }
+    }
+
+    /// Content type hint of the active fullscreen window of `output`, if any
+    pub fn fullscreen_content_type(&self, output: &Output) -> Option<ContentType> {
+        self.active_space(output)
+            .get_fullscreen()
+            .and_then(|surface| surface.wl_surface())
+            .map(|surface| surface_content_type(&surface))
+    }
+
     pub fn active_space_mut(&mut self, output: &Output) -> &mut Workspace {
         self.workspaces.active_mut(output)
     }
@@ -1611,6 +1803,39 @@ impl Shell {
             .workspaces
             .spaces()
             .any(|workspace| workspace.animations_going())
+            || self.bell.is_some()
+            || self.seats.iter().any(|seat| {
+                seat.user_data()
+                    .get::<SeatLocatePointer>()
+                    .is_some_and(|locate| locate.ring().is_some())
+            })
+    }
+
+    /// Like [`Shell::animations_going`], but only considers animations visible on `output`
+    pub fn output_animations_going(&self, output: &Output) -> bool {
+        !matches!(
+            self.overview_mode,
+            OverviewMode::None | OverviewMode::Active(_)
+        ) || !matches!(
+            self.resize_mode,
+            ResizeMode::None | ResizeMode::Active(_, _)
+        ) || self.workspaces.sets.get(output).is_some_and(|set| {
+            set.previously_active
+                .as_ref()
+                .is_some_and(|(_, delta)| delta.is_animating())
+                || set.sticky_layer.animations_going()
+                || set
+                    .workspaces
+                    .iter()
+                    .any(|workspace| workspace.animations_going())
+        }) || self
+            .bell
+            .as_ref()
+            .is_some_and(|bell| bell.output == *output)
+            || self
+                .seats
+                .iter()
+                .any(|seat| SeatLocatePointer::is_visible_on(seat, output))
     }
 
     pub fn update_animations(&mut self) -> HashMap<ClientId, Client> {
@@ -1624,6 +1849,14 @@ impl Shell {
         clients
     }
 
+    /// Sends the configures held back by the tiling layouts during this loop iteration
+    pub fn flush_configures(&mut self) {
+        let mut sent = HashMap::new();
+        for workspace in self.workspaces.spaces_mut() {
+            workspace.tiling_layer.flush_configures(&mut sent);
+        }
+    }
+
     pub fn set_overview_mode(
         &mut self,
         enabled: Option<Trigger>,
@@ -1635,7 +1868,7 @@ impl Shell {
                 OverviewMode::Started(_, _) | OverviewMode::Active(_)
             ) {
                 if matches!(trigger, Trigger::KeyboardSwap(_, _)) {
-                    self.swap_indicator = Some(swap_indicator(evlh, self.theme.clone()));
+                    self.swap_indicator = Some(swap_indicator(evlh.clone(), self.theme.clone()));
                 }
                 self.overview_mode = OverviewMode::Started(trigger, Instant::now());
             }
@@ -1781,6 +2014,10 @@ impl Shell {
 
         self.workspaces
             .refresh(workspace_state, xdg_activation_state);
+        // windows mapped since the last refresh
+        for mapped in self.mapped() {
+            mapped.set_style(&self.window_style);
+        }
 
         for output in self.outputs() {
             let mut map = layer_map_for_output(output);
@@ -1868,22 +2105,27 @@ impl Shell {
             .unwrap();
         let (window, seat, output) = self.pending_windows.remove(pos);
 
-        let parent_is_sticky = if let Some(toplevel) = window.0.toplevel() {
-            if let Some(parent) = toplevel.parent() {
-                if let Some(elem) = self.element_for_surface(&parent) {
-                    self.workspaces
-                        .sets
-                        .values()
-                        .any(|set| set.sticky_layer.mapped().any(|m| m == elem))
-                } else {
-                    false
-                }
-            } else {
-                false
-            }
+        // xdg parent or X11 transient-for window
+        let parent = if let Some(toplevel) = window.0.toplevel() {
+            toplevel
+                .parent()
+                .and_then(|parent| self.element_for_surface(&parent).cloned())
+        } else if let Some(owner) = window.x11_surface().and_then(|x| x.is_transient_for()) {
+            self.mapped()
+                .find(|m| {
+                    m.windows()
+                        .any(|(w, _)| w.x11_surface().is_some_and(|x| x.window_id() == owner))
+                })
+                .cloned()
         } else {
-            false
+            None
         };
+        let parent_is_sticky = parent.as_ref().is_some_and(|elem| {
+            self.workspaces
+                .sets
+                .values()
+                .any(|set| set.sticky_layer.mapped().any(|m| m == elem))
+        });
 
         let pending_activation = self.pending_activations.remove(&(&window).into());
         let workspace_handle = match pending_activation {
@@ -1976,7 +2218,20 @@ impl Shell {
 
         let workspace_empty = workspace.mapped().next().is_none();
         if is_dialog || floating_exception || !workspace.tiling_enabled {
-            workspace.floating_layer.map(mapped.clone(), None);
+            // center dialogs over their parent
+            let position = parent
+                .as_ref()
+                .filter(|_| is_dialog)
+                .and_then(|parent| workspace.element_geometry(parent))
+                .map(|parent_geo| {
+                    let size = mapped.geometry().size;
+                    parent_geo.loc
+                        + Point::from((
+                            (parent_geo.size.w - size.w) / 2,
+                            (parent_geo.size.h - size.h) / 2,
+                        ))
+                });
+            workspace.floating_layer.map(mapped.clone(), position);
         } else {
             for mapped in workspace
                 .mapped()
@@ -2156,6 +2411,44 @@ impl Shell {
         })
     }
 
+    /// Geometry of the window focused by `seat`
+    pub fn focused_geometry(&self, seat: &Seat<State>) -> Option<Rectangle<i32, Global>> {
+        self.target_geometry(seat.get_keyboard()?.current_focus()?)
+    }
+
+    /// Geometry of a window or fullscreen surface
+    pub fn target_geometry(&self, target: KeyboardFocusTarget) -> Option<Rectangle<i32, Global>> {
+        match target {
+            KeyboardFocusTarget::Element(mapped) => {
+                if let Some(set) = self
+                    .workspaces
+                    .sets
+                    .values()
+                    .find(|set| set.sticky_layer.mapped().any(|m| m == &mapped))
+                {
+                    Some(
+                        set.sticky_layer
+                            .element_geometry(&mapped)?
+                            .to_global(&set.output),
+                    )
+                } else {
+                    let workspace = self.space_for(&mapped)?;
+                    Some(
+                        workspace
+                            .element_geometry(&mapped)?
+                            .to_global(workspace.output()),
+                    )
+                }
+            }
+            KeyboardFocusTarget::Fullscreen(surface) => self
+                .workspaces
+                .spaces()
+                .find(|workspace| workspace.get_fullscreen() == Some(&surface))
+                .map(|workspace| workspace.output().geometry()),
+            _ => None,
+        }
+    }
+
     #[must_use]
     pub fn move_window(
         &mut self,
@@ -2629,6 +2922,7 @@ impl Shell {
             initial_window_location,
             cursor_output,
             active_hint,
+            self.shadow,
             layer,
             release,
             evlh.clone(),
@@ -3191,7 +3485,7 @@ impl Shell {
                     return;
                 };
 
-                let mut resize_state = mapped.resize_state.lock().unwrap();
+                let mut resize_state = mapped.resize_state.write().unwrap();
                 if let Some(ResizeState::Resizing(data)) = *resize_state {
                     mapped.set_resizing(false);
                     *resize_state = Some(ResizeState::WaitingForCommit(data));
@@ -3434,16 +3728,76 @@ impl Shell {
                 }))
         })
     }
+
+    /// Dmabufs currently attached to any client surface, including subsurfaces and popups
+    pub fn client_dmabufs(&self) -> Vec<Dmabuf> {
+        let mut surfaces = self
+            .mapped()
+            .flat_map(|mapped| mapped.windows())
+            .filter_map(|(window, _)| window.wl_surface().map(Cow::into_owned))
+            .chain(
+                self.outputs()
+                    .flat_map(|output| {
+                        layer_map_for_output(output)
+                            .layers()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                    })
+                    .map(|layer| layer.wl_surface().clone()),
+            )
+            .chain(
+                self.override_redirect_windows
+                    .iter()
+                    .filter_map(|or| or.wl_surface()),
+            )
+            .chain(self.session_lock.iter().flat_map(|lock| {
+                lock.surfaces
+                    .values()
+                    .map(|surface| surface.wl_surface().clone())
+            }))
+            .collect::<Vec<_>>();
+        let popups = surfaces
+            .iter()
+            .flat_map(PopupManager::popups_for_surface)
+            .map(|(popup, _)| popup.wl_surface().clone())
+            .collect::<Vec<_>>();
+        surfaces.extend(popups);
+
+        let mut dmabufs = Vec::new();
+        for surface in &surfaces {
+            with_surface_tree_downward(
+                surface,
+                (),
+                |_, _, _| TraversalAction::DoChildren(()),
+                |surface, _, _| {
+                    if let Some(dmabuf) = with_renderer_surface_state(surface, |state| {
+                        let buffer = state.buffer()?;
+                        get_dmabuf(&*buffer).ok().cloned()
+                    })
+                    .flatten()
+                    {
+                        dmabufs.push(dmabuf);
+                    }
+                },
+                |_, _, _| true,
+            );
+        }
+        dmabufs
+    }
 }
 
 fn workspace_set_idx(
     state: &mut WorkspaceUpdateGuard<'_, State>,
     idx: u8,
     output_pos: usize,
-    handle: &WorkspaceHandle,
+    workspace: &Workspace,
 ) {
-    state.set_workspace_name(handle, format!("{}", idx));
-    state.set_workspace_coordinates(handle, [Some(idx as u32), Some(output_pos as u32), None]);
+    let name = workspace.name.clone().unwrap_or_else(|| format!("{}", idx));
+    state.set_workspace_name(&workspace.handle, name);
+    state.set_workspace_coordinates(
+        &workspace.handle,
+        [Some(idx as u32), Some(output_pos as u32), None],
+    );
 }
 
 pub fn check_grab_preconditions(
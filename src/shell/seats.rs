@@ -5,8 +5,13 @@ use std::{any::Any, cell::RefCell, collections::HashMap, sync::Mutex, time::Dura
 use crate::{
     backend::render::cursor::{CursorShape, CursorState},
     config::{xkb_config_to_wl, Config},
-    input::{ModifiersShortcutQueue, SupressedButtons, SupressedKeys},
+    input::{
+        key_filters::KeyFilters, locate_pointer::SeatLocatePointer, mouse_keys::MouseKeys,
+        sticky_keys::SeatStickyKeysState, ModifiersShortcutQueue, ScrollRemainder,
+        SupressedButtons, SupressedKeys,
+    },
     state::State,
+    wayland::handlers::xwayland_keyboard_grab::SeatXWaylandGrab,
 };
 use smithay::{
     backend::input::{Device, DeviceCapability},
@@ -23,7 +28,9 @@ use smithay::{
 };
 use tracing::warn;
 
-use super::grabs::{SeatMenuGrabState, SeatMoveGrabState};
+use super::grabs::{
+    SeatMenuGrabState, SeatMoveGrabState, SeatScreenshotGrabState, SeatWindowSwitcherState,
+};
 
 crate::utils::id_gen!(next_seat_id, SEAT_ID, SEAT_IDS);
 
@@ -171,9 +178,23 @@ pub fn create_seat(
     userdata.insert_if_missing(SupressedKeys::default);
     userdata.insert_if_missing(SupressedButtons::default);
     userdata.insert_if_missing(ModifiersShortcutQueue::default);
+    userdata.insert_if_missing(ScrollRemainder::default);
+    userdata.insert_if_missing(KeyFilters::default);
+    userdata.insert_if_missing(MouseKeys::default);
     userdata.insert_if_missing_threadsafe(SeatMoveGrabState::default);
     userdata.insert_if_missing_threadsafe(SeatMenuGrabState::default);
+    userdata.insert_if_missing_threadsafe(SeatScreenshotGrabState::default);
+    userdata.insert_if_missing_threadsafe(SeatWindowSwitcherState::default);
+    userdata.insert_if_missing_threadsafe(SeatStickyKeysState::default);
+    userdata.insert_if_missing_threadsafe(SeatLocatePointer::default);
+    userdata.insert_if_missing_threadsafe(SeatXWaylandGrab::default);
     userdata.insert_if_missing_threadsafe(CursorState::default);
+    userdata
+        .get::<CursorState>()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .set_size(config.cosmic_conf.cursor.size);
     userdata.insert_if_missing_threadsafe(|| ActiveOutput(Mutex::new(output.clone())));
     userdata.insert_if_missing_threadsafe(|| Mutex::new(CursorImageStatus::default_named()));
 
@@ -1,9 +1,12 @@
 use crate::{
     backend::render::{
-        element::{AsGlowRenderer, FromGlesError},
+        blur::BlurElement,
+        element::{AsGlowRenderer, ClippedRenderElement, FromGlesError},
+        wallpaper::WallpaperRenderElement,
         BackdropShader, SplitRenderElements,
     },
     shell::{
+        element,
         layout::{floating::FloatingLayout, tiling::TilingLayout},
         OverviewMode, ANIMATION_DURATION,
     },
@@ -19,6 +22,7 @@ use crate::{
 };
 
 use cosmic::theme::CosmicTheme;
+use cosmic_comp_config::{workspace::WorkspaceOverride, ShadowConfig};
 use cosmic_protocols::workspace::v1::server::zcosmic_workspace_handle_v1::TilingState;
 use id_tree::Tree;
 use indexmap::IndexSet;
@@ -80,6 +84,10 @@ pub struct Workspace {
     pub minimized_windows: Vec<MinimizedWindow>,
     pub tiling_enabled: bool,
     pub fullscreen: Option<FullscreenSurface>,
+    /// Name from the config, shown instead of the number of the workspace
+    pub name: Option<String>,
+    /// Settings of this workspace overriding the global ones
+    pub settings: WorkspaceOverride,
 
     pub handle: WorkspaceHandle,
     pub focus_stack: FocusStacks,
@@ -237,9 +245,13 @@ impl Workspace {
         output: Output,
         tiling_enabled: bool,
         theme: cosmic::Theme,
+        name: Option<String>,
+        settings: WorkspaceOverride,
     ) -> Workspace {
-        let tiling_layer = TilingLayout::new(theme.clone(), &output);
-        let floating_layer = FloatingLayout::new(theme, &output);
+        let mut tiling_layer = TilingLayout::new(theme.clone(), &output);
+        tiling_layer.gaps_override = settings.gaps;
+        let mut floating_layer = FloatingLayout::new(theme, &output);
+        floating_layer.gaps_override = settings.gaps;
         let output_name = output.name();
 
         Workspace {
@@ -249,6 +261,8 @@ impl Workspace {
             tiling_enabled,
             minimized_windows: Vec::new(),
             fullscreen: None,
+            name,
+            settings,
             handle,
             focus_stack: FocusStacks::default(),
             screencopy: ScreencopySessions::default(),
@@ -472,6 +486,15 @@ impl Workspace {
     pub fn recalculate(&mut self) {
         self.tiling_layer.recalculate();
         self.floating_layer.recalculate();
+
+        if let Some(fullscreen) = self
+            .fullscreen
+            .as_ref()
+            .filter(|fullscreen| fullscreen.ended_at.is_none())
+        {
+            fullscreen.surface.set_geometry(self.output.geometry());
+            fullscreen.surface.send_configure();
+        }
     }
 
     pub fn unmaximize_request(&mut self, elem: &CosmicMapped) -> Option<Size<i32, Logical>> {
@@ -713,6 +736,18 @@ impl Workspace {
         });
     }
 
+    /// Shows the new fullscreen window in place right away, instead of animating it
+    pub fn skip_fullscreen_animation(&mut self) {
+        if let Some(f) = self.fullscreen.as_mut().filter(|f| f.start_at.is_some()) {
+            f.start_at = None;
+            // the client didn't commit since the blocker was added, nothing to notify
+            if let Some(signal) = f.animation_signal.take() {
+                signal.store(true, Ordering::SeqCst);
+            }
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+    }
+
     #[must_use]
     pub fn unfullscreen_request(
         &mut self,
@@ -1005,6 +1040,7 @@ impl Workspace {
         resize_indicator: Option<(ResizeMode, ResizeIndicator)>,
         indicator_thickness: u8,
         theme: &CosmicTheme,
+        shadow: &ShadowConfig,
     ) -> Result<SplitRenderElements<WorkspaceRenderElement<R>>, OutputNotMapped>
     where
         R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
@@ -1015,6 +1051,7 @@ impl Workspace {
         WorkspaceRenderElement<R>: RenderElement<R>,
     {
         let mut elements = SplitRenderElements::default();
+        let _dimming = element::DimmingGuard::new(self.settings.dim_unfocused != Some(false));
 
         let output_scale = self.output.current_scale().fractional_scale();
         let zone = {
@@ -1156,6 +1193,7 @@ impl Workspace {
                     indicator_thickness,
                     alpha,
                     theme,
+                    shadow,
                 ),
                 WorkspaceRenderElement::from,
             );
@@ -1173,6 +1211,12 @@ impl Workspace {
                 OverviewMode::None => None,
             };
 
+            // tiled windows don't overlap, so their shadows can all go below them,
+            // but they would lag behind while the windows are animated
+            let tiled_shadows = shadow.tiled
+                && matches!(overview.0, OverviewMode::None)
+                && !self.tiling_layer.animations_going();
+
             //tiling surfaces
             elements.extend_map(
                 self.tiling_layer.render::<R>(
@@ -1187,6 +1231,18 @@ impl Workspace {
                 WorkspaceRenderElement::from,
             );
 
+            if tiled_shadows {
+                for (mapped, geometry) in self.tiling_layer.mapped() {
+                    if let Some(shadow) =
+                        mapped.shadow_element(renderer, geometry, output_scale, 1.0, shadow)
+                    {
+                        elements
+                            .w_elements
+                            .push(CosmicMappedRenderElement::from(shadow).into());
+                    }
+                }
+            }
+
             if let Some(alpha) = alpha {
                 elements.w_elements.push(
                     Into::<CosmicMappedRenderElement<R>>::into(BackdropShader::element(
@@ -1231,6 +1287,8 @@ where
     FullscreenPopup(CosmicWindowRenderElement<R>),
     Window(CosmicMappedRenderElement<R>),
     Backdrop(TextureRenderElement<GlesTexture>),
+    Wallpaper(WallpaperRenderElement<R>),
+    Blur(ClippedRenderElement<BlurElement>),
 }
 
 impl<R> Element for WorkspaceRenderElement<R>
@@ -1245,6 +1303,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.id(),
             WorkspaceRenderElement::Window(elem) => elem.id(),
             WorkspaceRenderElement::Backdrop(elem) => elem.id(),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.id(),
+            WorkspaceRenderElement::Blur(elem) => elem.id(),
         }
     }
 
@@ -1255,6 +1315,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.current_commit(),
             WorkspaceRenderElement::Window(elem) => elem.current_commit(),
             WorkspaceRenderElement::Backdrop(elem) => elem.current_commit(),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.current_commit(),
+            WorkspaceRenderElement::Blur(elem) => elem.current_commit(),
         }
     }
 
@@ -1265,6 +1327,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.src(),
             WorkspaceRenderElement::Window(elem) => elem.src(),
             WorkspaceRenderElement::Backdrop(elem) => elem.src(),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.src(),
+            WorkspaceRenderElement::Blur(elem) => elem.src(),
         }
     }
 
@@ -1275,6 +1339,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.geometry(scale),
             WorkspaceRenderElement::Window(elem) => elem.geometry(scale),
             WorkspaceRenderElement::Backdrop(elem) => elem.geometry(scale),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.geometry(scale),
+            WorkspaceRenderElement::Blur(elem) => elem.geometry(scale),
         }
     }
 
@@ -1285,6 +1351,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.location(scale),
             WorkspaceRenderElement::Window(elem) => elem.location(scale),
             WorkspaceRenderElement::Backdrop(elem) => elem.location(scale),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.location(scale),
+            WorkspaceRenderElement::Blur(elem) => elem.location(scale),
         }
     }
 
@@ -1295,6 +1363,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.transform(),
             WorkspaceRenderElement::Window(elem) => elem.transform(),
             WorkspaceRenderElement::Backdrop(elem) => elem.transform(),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.transform(),
+            WorkspaceRenderElement::Blur(elem) => elem.transform(),
         }
     }
 
@@ -1309,6 +1379,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.damage_since(scale, commit),
             WorkspaceRenderElement::Window(elem) => elem.damage_since(scale, commit),
             WorkspaceRenderElement::Backdrop(elem) => elem.damage_since(scale, commit),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.damage_since(scale, commit),
+            WorkspaceRenderElement::Blur(elem) => elem.damage_since(scale, commit),
         }
     }
 
@@ -1319,6 +1391,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.opaque_regions(scale),
             WorkspaceRenderElement::Window(elem) => elem.opaque_regions(scale),
             WorkspaceRenderElement::Backdrop(elem) => elem.opaque_regions(scale),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.opaque_regions(scale),
+            WorkspaceRenderElement::Blur(elem) => elem.opaque_regions(scale),
         }
     }
 
@@ -1329,6 +1403,8 @@ where
             WorkspaceRenderElement::FullscreenPopup(elem) => elem.alpha(),
             WorkspaceRenderElement::Window(elem) => elem.alpha(),
             WorkspaceRenderElement::Backdrop(elem) => elem.alpha(),
+            WorkspaceRenderElement::Wallpaper(elem) => elem.alpha(),
+            WorkspaceRenderElement::Blur(elem) => elem.alpha(),
         }
     }
 }
@@ -1369,6 +1445,18 @@ where
                 opaque_regions,
             )
             .map_err(FromGlesError::from_gles_error),
+            WorkspaceRenderElement::Wallpaper(elem) => {
+                elem.draw(frame, src, dst, damage, opaque_regions)
+            }
+            WorkspaceRenderElement::Blur(elem) => RenderElement::<GlowRenderer>::draw(
+                elem,
+                R::glow_frame_mut(frame),
+                src,
+                dst,
+                damage,
+                opaque_regions,
+            )
+            .map_err(FromGlesError::from_gles_error),
         }
     }
 
@@ -1384,6 +1472,10 @@ where
             WorkspaceRenderElement::Backdrop(elem) => {
                 elem.underlying_storage(renderer.glow_renderer_mut())
             }
+            WorkspaceRenderElement::Wallpaper(elem) => elem.underlying_storage(renderer),
+            WorkspaceRenderElement::Blur(elem) => {
+                elem.underlying_storage(renderer.glow_renderer_mut())
+            }
         }
     }
 }
@@ -1442,3 +1534,36 @@ where
         WorkspaceRenderElement::Backdrop(elem)
     }
 }
+
+impl<R> From<WallpaperRenderElement<R>> for WorkspaceRenderElement<R>
+where
+    R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+    <R as Renderer>::TextureId: 'static,
+    CosmicMappedRenderElement<R>: RenderElement<R>,
+{
+    fn from(elem: WallpaperRenderElement<R>) -> Self {
+        WorkspaceRenderElement::Wallpaper(elem)
+    }
+}
+
+impl<R> From<BlurElement> for WorkspaceRenderElement<R>
+where
+    R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+    <R as Renderer>::TextureId: 'static,
+    CosmicMappedRenderElement<R>: RenderElement<R>,
+{
+    fn from(elem: BlurElement) -> Self {
+        WorkspaceRenderElement::Blur(ClippedRenderElement::unclipped(elem))
+    }
+}
+
+impl<R> From<ClippedRenderElement<BlurElement>> for WorkspaceRenderElement<R>
+where
+    R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+    <R as Renderer>::TextureId: 'static,
+    CosmicMappedRenderElement<R>: RenderElement<R>,
+{
+    fn from(elem: ClippedRenderElement<BlurElement>) -> Self {
+        WorkspaceRenderElement::Blur(elem)
+    }
+}
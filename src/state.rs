@@ -3,26 +3,44 @@
 use crate::{
     backend::{
         kms::KmsState,
-        render::{GlMultiError, RendererRef},
+        render::{
+            thumbnail::ThumbnailState, wallpaper::WallpaperLoader, GlMultiError, RendererRef,
+        },
+        virtual_output,
         winit::WinitState,
         x11::X11State,
     },
     config::{Config, OutputConfig, OutputState},
-    input::gestures::GestureState,
+    dbus::comp::CompService,
+    hooks::HookState,
+    idle::IdleState,
+    input::{binding_modes::BindingModePublisher, gestures::GestureState},
+    ipc::{protocol::OutputMetrics, IpcState},
+    metrics::MetricsState,
     shell::{grabs::SeatMoveGrabState, CosmicSurface, SeatExt, Shell},
     utils::prelude::OutputExt,
+    wayland::handlers::data_device::get_dnd_icon,
+    wayland::permissions::client_may_use,
     wayland::protocols::{
+        capture_exclusion::CaptureExclusionState,
         drm::WlDrmState,
         image_source::ImageSourceState,
         output_configuration::OutputConfigurationState,
+        output_power::OutputPowerState,
         screencopy::ScreencopyState,
+        secure_input::SecureInputState,
+        tearing_control::TearingControlState,
+        toplevel_drag::ToplevelDragState,
+        toplevel_icon::ToplevelIconState,
         toplevel_info::ToplevelInfoState,
         toplevel_management::{ManagementCapabilities, ToplevelManagementState},
+        virtual_pointer::VirtualPointerManagerState,
         workspace::{WorkspaceClientState, WorkspaceState, WorkspaceUpdateGuard},
     },
     xwayland::XWaylandState,
 };
 use anyhow::Context;
+use cosmic_comp_config::PrivilegedProtocol;
 use i18n_embed::{
     fluent::{fluent_language_loader, FluentLanguageLoader},
     DesktopLanguageRequester,
@@ -66,6 +84,7 @@ use smithay::{
     wayland::{
         alpha_modifier::AlphaModifierState,
         compositor::{CompositorClientState, CompositorState, SurfaceData},
+        content_type::ContentTypeState,
         dmabuf::{DmabufFeedback, DmabufGlobal, DmabufState},
         foreign_toplevel_list::ForeignToplevelListState,
         fractional_scale::{with_fractional_scale, FractionalScaleManagerState},
@@ -108,6 +127,7 @@ use std::{
     cell::RefCell,
     collections::HashSet,
     ffi::OsString,
+    os::unix::net::UnixStream,
     process::Child,
     sync::{atomic::AtomicBool, Arc, Mutex, Once, RwLock},
     time::Duration,
@@ -135,6 +155,8 @@ pub struct ClientState {
     pub workspace_client_state: WorkspaceClientState,
     pub advertised_drm_node: Option<DrmNode>,
     pub privileged: bool,
+    /// Privileged protocols, whose globals the client can see
+    pub privileged_protocols: Vec<PrivilegedProtocol>,
     pub evls: LoopSignal,
     pub security_context: Option<SecurityContext>,
 }
@@ -186,9 +208,18 @@ pub struct Common {
     pub should_stop: bool,
     pub local_offset: time::UtcOffset,
     pub gesture_state: Option<GestureState>,
+    pub tablet_mapping_toggled: bool,
+    /// Active binding mode, replacing the configured shortcuts
+    pub binding_mode: Option<String>,
+    pub binding_mode_publisher: BindingModePublisher,
+    pub virtual_outputs: Vec<Output>,
+    pub thumbnails: ThumbnailState,
+    pub wallpapers: WallpaperLoader,
 
     pub kiosk_child: Option<Child>,
     pub theme: cosmic::Theme,
+    pub ipc: Option<IpcState>,
+    pub dbus_service: Option<CompService>,
 
     // wayland state
     pub compositor_state: CompositorState,
@@ -199,6 +230,7 @@ pub struct Common {
     pub keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
     pub output_state: OutputManagerState,
     pub output_configuration_state: OutputConfigurationState<State>,
+    pub output_power_state: OutputPowerState,
     pub presentation_state: PresentationState,
     pub primary_selection_state: PrimarySelectionState,
     pub data_control_state: Option<DataControlState>,
@@ -209,6 +241,9 @@ pub struct Common {
     pub idle_notifier_state: IdleNotifierState<State>,
     pub idle_inhibit_manager_state: IdleInhibitManagerState,
     pub idle_inhibiting_surfaces: HashSet<WlSurface>,
+    pub idle_state: IdleState,
+    pub hook_state: HookState,
+    pub metrics_state: MetricsState,
     pub shm_state: ShmState,
     pub wl_drm_state: WlDrmState<Option<DrmNode>>,
     pub viewporter_state: ViewporterState,
@@ -220,6 +255,7 @@ pub struct Common {
     pub layer_shell_state: WlrLayerShellState,
     pub toplevel_info_state: ToplevelInfoState<State, CosmicSurface>,
     pub toplevel_management_state: ToplevelManagementState,
+    pub toplevel_drag_state: ToplevelDragState,
     pub xdg_activation_state: XdgActivationState,
     pub xdg_foreign_state: XdgForeignState,
     pub workspace_state: WorkspaceState<State>,
@@ -297,6 +333,7 @@ impl BackendData {
         }?;
 
         let mut shell = shell.write().unwrap();
+        let mut geometry_changed = false;
         for output in result {
             // apply to Output
             let final_config = output
@@ -322,6 +359,7 @@ impl BackendData {
                 final_config.position.1 as i32,
             )))
             .filter(|x| *x != output.current_location());
+            geometry_changed |= mode.is_some() || transform.is_some() || scale.is_some();
             output.change_current_state(mode, transform, scale.map(Scale::Fractional), location);
 
             output.set_adaptive_sync(final_config.vrr);
@@ -355,12 +393,22 @@ impl BackendData {
             self.schedule_render(&output);
         }
 
+        // e.g. a rotated output, needs its windows to be laid out for the new logical size
+        if geometry_changed {
+            shell.workspaces.recalculate();
+        }
+        std::mem::drop(shell);
+
         loop_handle.insert_idle(|state| state.common.update_xwayland_scale());
 
         Ok(())
     }
 
     pub fn schedule_render(&mut self, output: &Output) {
+        if virtual_output::schedule_render(output) {
+            return;
+        }
+
         match self {
             BackendData::Winit(_) => {} // We cannot do this on the winit backend.
             // Winit has a very strict render-loop and skipping frames breaks atleast the wayland winit-backend.
@@ -371,6 +419,29 @@ impl BackendData {
         }
     }
 
+    /// Frame timings of all outputs, only tracked by the kms backend
+    pub fn output_metrics(&self) -> Vec<OutputMetrics> {
+        match self {
+            BackendData::Kms(state) => state.output_metrics(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns if the display of `output` is turned on, `None` if the backend can't turn it off
+    pub fn dpms(&mut self, output: &Output) -> Option<bool> {
+        match self {
+            BackendData::Kms(ref mut state) => state.dpms(output),
+            // Nested backends can't turn off the displays of the host.
+            _ => None,
+        }
+    }
+
+    pub fn set_dpms(&mut self, output: &Output, on: bool) {
+        if let BackendData::Kms(ref mut state) = self {
+            state.set_dpms(output, on);
+        }
+    }
+
     pub fn dmabuf_imported(
         &mut self,
         client: Option<Client>,
@@ -450,12 +521,6 @@ pub fn client_has_no_security_context(client: &Client) -> bool {
         .map_or(true, |client_state| client_state.security_context.is_none())
 }
 
-pub fn client_is_privileged(client: &Client) -> bool {
-    client
-        .get_data::<ClientState>()
-        .map_or(false, |client_state| client_state.privileged)
-}
-
 fn enable_wayland_security() -> bool {
     std::env::var("COSMIC_ENABLE_WAYLAND_SECURITY")
         .map(|x| {
@@ -494,15 +559,26 @@ impl State {
         let data_device_state = DataDeviceState::new::<Self>(dh);
         let dmabuf_state = DmabufState::new();
         let foreign_toplevel_list =
-            ForeignToplevelListState::new_with_filter::<State>(dh, client_is_privileged);
+            ForeignToplevelListState::new_with_filter::<State>(dh, |client: &Client| {
+                client_may_use(client, PrivilegedProtocol::ForeignToplevel)
+            });
         let fractional_scale_state = FractionalScaleManagerState::new::<State>(dh);
         let keyboard_shortcuts_inhibit_state = KeyboardShortcutsInhibitState::new::<Self>(dh);
         let output_state = OutputManagerState::new_with_xdg_output::<Self>(dh);
-        let output_configuration_state = OutputConfigurationState::new(dh, client_is_privileged);
+        let output_configuration_state = OutputConfigurationState::new(dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::OutputManagement)
+        });
+        let output_power_state = OutputPowerState::new::<Self, _>(dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::OutputManagement)
+        });
         let presentation_state = PresentationState::new::<Self>(dh, clock.id() as u32);
         let primary_selection_state = PrimarySelectionState::new::<Self>(dh);
-        let image_source_state = ImageSourceState::new::<Self, _>(dh, client_is_privileged);
-        let screencopy_state = ScreencopyState::new::<Self, _>(dh, client_is_privileged);
+        let image_source_state = ImageSourceState::new::<Self, _>(dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::Screencopy)
+        });
+        let screencopy_state = ScreencopyState::new::<Self, _>(dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::Screencopy)
+        });
         let shm_state =
             ShmState::new::<Self>(dh, vec![wl_shm::Format::Xbgr8888, wl_shm::Format::Abgr8888]);
         let seat_state = SeatState::<Self>::new();
@@ -511,33 +587,56 @@ impl State {
         let kde_decoration_state = KdeDecorationState::new::<Self>(&dh, Mode::Client);
         let xdg_decoration_state = XdgDecorationState::new::<Self>(&dh);
         let session_lock_manager_state =
-            SessionLockManagerState::new::<Self, _>(&dh, client_is_privileged);
+            SessionLockManagerState::new::<Self, _>(&dh, |client: &Client| {
+                client_may_use(client, PrivilegedProtocol::SessionLock)
+            });
         XWaylandKeyboardGrabState::new::<Self>(&dh);
         let xwayland_shell_state = XWaylandShellState::new::<Self>(&dh);
         PointerConstraintsState::new::<Self>(&dh);
         PointerGesturesState::new::<Self>(&dh);
         TabletManagerState::new::<Self>(&dh);
         SecurityContextState::new::<Self, _>(&dh, client_has_no_security_context);
-        InputMethodManagerState::new::<Self, _>(&dh, client_is_privileged);
+        InputMethodManagerState::new::<Self, _>(&dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::InputMethod)
+        });
         TextInputManagerState::new::<Self>(&dh);
-        VirtualKeyboardManagerState::new::<State, _>(&dh, client_is_privileged);
+        VirtualKeyboardManagerState::new::<State, _>(&dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::VirtualInput)
+        });
+        VirtualPointerManagerState::new::<State, _>(&dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::VirtualInput)
+        });
+        SecureInputState::new::<State>(&dh);
+        CaptureExclusionState::new::<State>(&dh);
+        TearingControlState::new::<State>(&dh);
+        ToplevelIconState::new::<State>(&dh);
         AlphaModifierState::new::<Self>(&dh);
+        ContentTypeState::new::<Self>(&dh);
         SinglePixelBufferState::new::<Self>(&dh);
 
         let idle_notifier_state = IdleNotifierState::<Self>::new(&dh, handle.clone());
         let idle_inhibit_manager_state = IdleInhibitManagerState::new::<State>(&dh);
         let idle_inhibiting_surfaces = HashSet::new();
+        let idle_state = IdleState::default();
+        handle.insert_idle(|state| state.reset_idle_timer());
+        handle.insert_idle(|state| state.update_metrics_endpoint());
 
         let data_control_state = std::env::var("COSMIC_DATA_CONTROL_ENABLED")
             .is_ok_and(|value| value == "1")
             .then(|| {
-                DataControlState::new::<Self, _>(dh, Some(&primary_selection_state), |_| true)
+                DataControlState::new::<Self, _>(
+                    dh,
+                    Some(&primary_selection_state),
+                    |client: &Client| client_may_use(client, PrivilegedProtocol::DataControl),
+                )
             });
 
         let shell = Arc::new(RwLock::new(Shell::new(&config)));
 
         let layer_shell_state =
-            WlrLayerShellState::new_with_filter::<State, _>(dh, client_is_privileged);
+            WlrLayerShellState::new_with_filter::<State, _>(dh, |client: &Client| {
+                client_may_use(client, PrivilegedProtocol::LayerShell)
+            });
         let xdg_shell_state = XdgShellState::new_with_capabilities::<State>(
             dh,
             [
@@ -549,7 +648,9 @@ impl State {
         );
         let xdg_activation_state = XdgActivationState::new::<State>(dh);
         let xdg_foreign_state = XdgForeignState::new::<State>(dh);
-        let toplevel_info_state = ToplevelInfoState::new(dh, client_is_privileged);
+        let toplevel_info_state = ToplevelInfoState::new(dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::ForeignToplevel)
+        });
         let toplevel_management_state = ToplevelManagementState::new::<State, _>(
             dh,
             vec![
@@ -559,13 +660,22 @@ impl State {
                 ManagementCapabilities::Minimize,
                 ManagementCapabilities::MoveToWorkspace,
             ],
-            client_is_privileged,
+            |client: &Client| client_may_use(client, PrivilegedProtocol::ForeignToplevel),
         );
-        let workspace_state = WorkspaceState::new(dh, client_is_privileged);
+        let toplevel_drag_state = ToplevelDragState::new::<State>(dh);
+        let workspace_state = WorkspaceState::new(dh, |client: &Client| {
+            client_may_use(client, PrivilegedProtocol::Workspace)
+        });
 
         if let Err(err) = crate::dbus::init(&handle) {
             tracing::warn!(?err, "Failed to initialize dbus handlers");
         }
+        let dbus_service = crate::dbus::comp::init(&handle)
+            .map_err(|err| tracing::warn!(?err, "Failed to register dbus service"))
+            .ok();
+        let ipc = crate::ipc::init(&handle, &socket)
+            .map_err(|err| tracing::warn!(?err, "Failed to initialize the IPC socket"))
+            .ok();
 
         State {
             common: Common {
@@ -584,9 +694,17 @@ impl State {
                 startup_done: Arc::new(AtomicBool::new(false)),
                 should_stop: false,
                 gesture_state: None,
+                tablet_mapping_toggled: false,
+                binding_mode: None,
+                binding_mode_publisher: BindingModePublisher::default(),
+                virtual_outputs: Vec::new(),
+                thumbnails: ThumbnailState::default(),
+                wallpapers: WallpaperLoader::default(),
 
                 kiosk_child: None,
                 theme: cosmic::theme::system_preference(),
+                ipc,
+                dbus_service,
 
                 compositor_state,
                 data_device_state,
@@ -596,6 +714,9 @@ impl State {
                 idle_notifier_state,
                 idle_inhibit_manager_state,
                 idle_inhibiting_surfaces,
+                idle_state,
+                hook_state: HookState::default(),
+                metrics_state: MetricsState::default(),
                 image_source_state,
                 screencopy_state,
                 shm_state,
@@ -604,6 +725,7 @@ impl State {
                 keyboard_shortcuts_inhibit_state,
                 output_state,
                 output_configuration_state,
+                output_power_state,
                 presentation_state,
                 primary_selection_state,
                 data_control_state,
@@ -615,6 +737,7 @@ impl State {
                 layer_shell_state,
                 toplevel_info_state,
                 toplevel_management_state,
+                toplevel_drag_state,
                 xdg_activation_state,
                 xdg_foreign_state,
                 workspace_state,
@@ -627,7 +750,8 @@ impl State {
         }
     }
 
-    pub fn new_client_state(&self) -> ClientState {
+    pub fn new_client_state(&self, stream: &UnixStream) -> ClientState {
+        let privileged = !enable_wayland_security();
         ClientState {
             compositor_client_state: CompositorClientState::default(),
             workspace_client_state: WorkspaceClientState::default(),
@@ -635,7 +759,8 @@ impl State {
                 BackendData::Kms(kms_state) => kms_state.primary_node,
                 _ => None,
             },
-            privileged: !enable_wayland_security(),
+            privileged,
+            privileged_protocols: self.permitted_protocols(stream, None, privileged),
             evls: self.common.event_loop_signal.clone(),
             security_context: None,
         }
@@ -695,6 +820,11 @@ impl Common {
                 with_surfaces_surface_tree(&wl_surface, processor);
             }
 
+            // dnd icon, so it is rendered at the fractional scale of the output it is on
+            if let Some(icon) = get_dnd_icon(seat) {
+                with_surfaces_surface_tree(&icon, processor);
+            }
+
             // grabs
             if let Some(move_grab) = seat.user_data().get::<SeatMoveGrabState>() {
                 if let Some(grab_state) = move_grab.lock().unwrap().as_ref() {
@@ -7,29 +7,38 @@ use tracing::{error, warn};
 
 pub fn ready(common: &Common) {
     if booted() {
-        match Command::new("systemctl")
-            .args(["--user", "import-environment", "WAYLAND_DISPLAY", "DISPLAY"])
-            .env("WAYLAND_DISPLAY", &common.socket)
-            .env(
-                "DISPLAY",
-                &common
-                    .xwayland_state
-                    .as_ref()
-                    .map(|s| format!(":{}", s.display))
-                    .unwrap_or(String::new()),
-            )
-            .status()
-        {
-            Ok(x) if x.success() => {}
-            Ok(x) => warn!(
-                exit_code = ?x.code(),
-                "Failed to import WAYLAND_DISPLAY/DISPLAY into systemd",
-            ),
-            Err(err) => error!(?err, "Failed to run systemctl although booted with systemd",),
-        };
+        import_environment(common);
 
         if let Err(err) = notify(false, &[NotifyState::Ready]) {
             error!(?err, "Failed to notify systemd");
         }
     }
 }
+
+/// Imports `WAYLAND_DISPLAY` and `DISPLAY` into the systemd user session
+pub fn import_environment(common: &Common) {
+    if !booted() {
+        return;
+    }
+
+    match Command::new("systemctl")
+        .args(["--user", "import-environment", "WAYLAND_DISPLAY", "DISPLAY"])
+        .env("WAYLAND_DISPLAY", &common.socket)
+        .env(
+            "DISPLAY",
+            &common
+                .xwayland_state
+                .as_ref()
+                .map(|s| format!(":{}", s.display))
+                .unwrap_or(String::new()),
+        )
+        .status()
+    {
+        Ok(x) if x.success() => {}
+        Ok(x) => warn!(
+            exit_code = ?x.code(),
+            "Failed to import WAYLAND_DISPLAY/DISPLAY into systemd",
+        ),
+        Err(err) => error!(?err, "Failed to run systemctl although booted with systemd",),
+    };
+}
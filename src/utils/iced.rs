@@ -143,8 +143,11 @@ impl<P: Program> IcedProgram for ProgramWrapper<P> {
 pub(crate) struct IcedElementInternal<P: Program + Send + 'static> {
     // draw buffer
     outputs: HashSet<Output>,
-    buffers: HashMap<OrderedFloat<f64>, (MemoryRenderBuffer, Option<(Vec<Primitive>, Color)>)>,
+    /// Buffers per scale, with the primitives and the generation last drawn into them
+    buffers: HashMap<OrderedFloat<f64>, (MemoryRenderBuffer, Option<(Vec<Primitive>, Color, u64)>)>,
     pending_update: Option<Instant>,
+    /// Incremented on every update of the state, buffers of older generations need to be redrawn
+    generation: u64,
 
     // state
     size: Size<i32, Logical>,
@@ -196,6 +199,7 @@ impl<P: Program + Send + Clone + 'static> Clone for IcedElementInternal<P> {
             outputs: self.outputs.clone(),
             buffers: self.buffers.clone(),
             pending_update: self.pending_update.clone(),
+            generation: self.generation,
             size: self.size.clone(),
             cursor_pos: self.cursor_pos.clone(),
             touch_map: self.touch_map.clone(),
@@ -217,6 +221,7 @@ impl<P: Program + Send + 'static> fmt::Debug for IcedElementInternal<P> {
             .field("buffers", &"...")
             .field("size", &self.size)
             .field("pending_update", &self.pending_update)
+            .field("generation", &self.generation)
             .field("cursor_pos", &self.cursor_pos)
             .field("theme", &"...")
             .field("renderer", &"...")
@@ -271,6 +276,7 @@ impl<P: Program + Send + 'static> IcedElement<P> {
             outputs: HashSet::new(),
             buffers: HashMap::new(),
             pending_update: None,
+            generation: 0,
             size,
             cursor_pos: None,
             touch_map: HashMap::new(),
@@ -345,6 +351,7 @@ impl<P: Program + Send + 'static> IcedElement<P> {
     pub fn set_theme(&self, theme: cosmic::Theme) {
         let mut guard = self.0.lock().unwrap();
         guard.theme = theme.clone();
+        guard.update(true);
     }
 
     pub fn force_redraw(&self) {
@@ -377,6 +384,8 @@ impl<P: Program + Send + 'static> IcedElementInternal<P> {
         if !force {
             return Vec::new();
         }
+        self.pending_update = None;
+        self.generation = self.generation.wrapping_add(1);
 
         let cursor = self
             .cursor_pos
@@ -830,7 +839,9 @@ impl<P: Program + Send + 'static> SpaceElement for IcedElement<P> {
                 ),
             );
         }
-        internal.update(true);
+        // programs call `force_update` when their view changes, so the buffers
+        // are only redrawn if needed
+        internal.update(false);
     }
 }
 
@@ -871,8 +882,12 @@ where
                 .to_buffer(scale.x, Transform::Normal)
                 .to_i32_round();
 
-            if size.w > 0 && size.h > 0 {
+            let up_to_date = old_primitives
+                .as_ref()
+                .is_some_and(|(_, _, generation)| *generation == internal_ref.generation);
+            if size.w > 0 && size.h > 0 && !up_to_date {
                 let cosmic::Renderer::TinySkia(renderer) = &mut internal_ref.renderer;
+                let generation = internal_ref.generation;
                 let state_ref = &internal_ref.state;
                 let mut clip_mask = tiny_skia::Mask::new(size.w as u32, size.h as u32).unwrap();
                 let overlay = internal_ref.debug.overlay();
@@ -892,7 +907,7 @@ where
 
                             let mut damage = old_primitives
                                 .as_ref()
-                                .and_then(|(last_primitives, last_color)| {
+                                .and_then(|(last_primitives, last_color, _)| {
                                     (last_color == &background_color)
                                         .then(|| damage::list(last_primitives, primitives))
                                 })
@@ -912,7 +927,10 @@ where
                                     &overlay,
                                 );
 
-                                *old_primitives = Some((primitives.to_vec(), background_color));
+                                *old_primitives =
+                                    Some((primitives.to_vec(), background_color, generation));
+                            } else if let Some((_, _, drawn)) = old_primitives {
+                                *drawn = generation;
                             }
 
                             let damage = damage
@@ -6,6 +6,7 @@ pub mod geometry;
 pub mod iced;
 pub mod prelude;
 pub mod quirks;
+pub mod recover;
 pub mod rlimit;
 pub mod screenshot;
 pub mod tween;
@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Recovering from panics, so a bug hit while rendering a window or handling a single client
+//! doesn't take the whole session down.
+//!
+//! A panic while the shell is locked for writing can't be recovered from, the shell may be left
+//! half updated. See [`check_poison`]. The same goes for any other lock held while panicking, once
+//! it is used again the resulting panic isn't caught either.
+//!
+//! The panic itself, including its backtrace, is logged by the panic hook installed in
+//! `logger::init_logger`. Set `COSMIC_DISABLE_PANIC_RECOVERY=1` to crash instead, e.g. to
+//! get a core dump.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::RwLock,
+};
+
+use once_cell::sync::Lazy;
+use smithay::reexports::wayland_server::{
+    backend::{ClientId, DisconnectReason},
+    Client, DisplayHandle, Resource,
+};
+use tracing::error;
+
+static RECOVERY_ENABLED: Lazy<bool> =
+    Lazy::new(|| !std::env::var("COSMIC_DISABLE_PANIC_RECOVERY").is_ok_and(|value| value == "1"));
+
+thread_local! {
+    /// Client whose request is currently being handled
+    static CURRENT_CLIENT: RefCell<Option<ClientId>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, returning `None` if it panicked
+///
+/// Everything `f` touches may be left half updated, callers have to make sure it isn't used
+/// afterwards, see [`check_poison`].
+pub fn catch_panic<R>(what: &str, f: impl FnOnce() -> R) -> Option<R> {
+    if !*RECOVERY_ENABLED {
+        return Some(f());
+    }
+
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(res) => Some(res),
+        Err(payload) if is_poison_error(&*payload) => {
+            // recovering would just panic again on every use of the lock
+            error!(
+                "Unable to recover from panic while {}, a lock was poisoned",
+                what
+            );
+            resume_unwind(payload)
+        }
+        Err(_) => {
+            error!("Recovered from panic while {}", what);
+            None
+        }
+    }
+}
+
+/// Whether a panic was caused by unwrapping the result of locking a poisoned `Mutex` or `RwLock`
+fn is_poison_error(payload: &(dyn Any + Send)) -> bool {
+    let message = payload
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| payload.downcast_ref::<&str>().copied());
+    message.is_some_and(|message| message.contains("PoisonError"))
+}
+
+/// Gives up, if `lock` was held for writing while panicking
+///
+/// The state behind it may be half updated and isn't safe to continue with.
+pub fn check_poison<T>(lock: &RwLock<T>) {
+    if lock.is_poisoned() {
+        panic!("Unable to recover from a panic, that left the state half updated");
+    }
+}
+
+/// Marks the client owning `resource` as the one being handled, until the returned guard is dropped
+///
+/// If a panic is caught by [`dispatch_clients`] meanwhile, the client gets disconnected.
+pub fn handling_client(resource: &impl Resource) -> ClientGuard {
+    handling(resource.client())
+}
+
+/// Like [`handling_client`], for objects that aren't a resource themselves
+pub fn handling(client: Option<Client>) -> ClientGuard {
+    let client = client.map(|client| client.id());
+    let previous =
+        CURRENT_CLIENT.with(|current| std::mem::replace(&mut *current.borrow_mut(), client));
+    ClientGuard { previous }
+}
+
+/// Restores the previously handled client on drop
+#[must_use]
+pub struct ClientGuard {
+    previous: Option<ClientId>,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        // not while unwinding, the client is needed to recover
+        if !std::thread::panicking() {
+            let previous = self.previous.take();
+            CURRENT_CLIENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Runs `dispatch`, disconnecting the client that caused a panic instead of propagating it
+pub fn dispatch_clients<R>(
+    display_handle: &DisplayHandle,
+    dispatch: impl FnOnce() -> R,
+) -> Option<R> {
+    let res = catch_panic("handling client requests", dispatch);
+    let client = CURRENT_CLIENT.with(|current| current.borrow_mut().take());
+    if res.is_none() {
+        match client {
+            Some(client) => {
+                error!(?client, "Disconnecting client, that caused a panic");
+                display_handle
+                    .backend_handle()
+                    .kill_client(client, DisconnectReason::ConnectionClosed);
+            }
+            None => error!("Unable to tell which client caused a panic"),
+        }
+    }
+    res
+}
@@ -1,26 +1,77 @@
 use anyhow::Context;
 use smithay::{
     backend::{
-        allocator::Fourcc,
+        allocator::{dmabuf::Dmabuf, Fourcc},
         renderer::{
-            damage::OutputDamageTracker,
-            element::{surface::WaylandSurfaceRenderElement, AsRenderElements},
+            damage::{Error as DTError, OutputDamageTracker},
+            element::{surface::WaylandSurfaceRenderElement, AsRenderElements, RenderElement},
             gles::GlesRenderbuffer,
-            ExportMem, ImportAll, Offscreen, Renderer,
+            Bind, ExportMem, ImportAll, ImportMem, Offscreen, Renderer,
         },
     },
     desktop::utils::bbox_from_surface_tree,
-    utils::{Scale, Transform},
+    output::Output,
+    utils::{Rectangle, Scale, Transform},
     wayland::seat::WaylandFocus,
 };
-use tracing::warn;
+use tracing::{info, warn};
 
 use crate::{
-    backend::render::RendererRef,
-    shell::element::CosmicSurface,
-    state::{advertised_node_for_surface, State},
+    backend::render::{
+        element::{AsGlowRenderer, CosmicElement, FromGlesError},
+        render_workspace, CursorMode, ElementFilter, RendererRef,
+    },
+    shell::{element::CosmicSurface, CosmicMappedRenderElement, WorkspaceRenderElement},
+    state::{advertised_node_for_surface, Common, State},
+    utils::prelude::*,
+    wayland::handlers::screencopy::{blank_areas, output_hidden_areas, window_hidden_areas},
 };
 
+/// Saves the rgba8888 `data` to the pictures directory of the user
+fn write_png(
+    title: &str,
+    width: i32,
+    height: i32,
+    data: &[u8],
+    offset: &time::UtcOffset,
+) -> anyhow::Result<()> {
+    let Ok(Some(path)) = xdg_user::pictures() else {
+        return Ok(());
+    };
+
+    let local_timestamp = time::OffsetDateTime::now_utc().to_offset(*offset);
+    let mut title = title.to_string();
+    title.truncate(227); // 255 - time - png
+    let name = sanitize_filename::sanitize(format!(
+        "{}_{}.png",
+        title,
+        local_timestamp
+            .format(time::macros::format_description!(
+                "[year]-[month]-[day]_[hour]:[minute]:[second]_[subsecond digits:4]"
+            ))
+            .unwrap(),
+    ));
+    let file = std::fs::File::create(path.join(name))?;
+
+    let ref mut writer = std::io::BufWriter::new(file);
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2)); // 1.0 / 2.2, unscaled, but rounded
+    let source_chromaticities = png::SourceChromaticities::new(
+        // Using unscaled instantiation here
+        (0.31270, 0.32900),
+        (0.64000, 0.33000),
+        (0.30000, 0.60000),
+        (0.15000, 0.06000),
+    );
+    encoder.set_source_chromaticities(source_chromaticities);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+
+    Ok(())
+}
+
 pub fn screenshot_window(state: &mut State, surface: &CosmicSurface) {
     fn render_window<R>(
         renderer: &mut R,
@@ -61,41 +112,19 @@ pub fn screenshot_window(state: &mut State, surface: &CosmicSurface) {
             renderer.copy_framebuffer(bbox.to_buffer(1, Transform::Normal, &bbox.size), format)?;
         let gl_data = renderer.map_texture(&mapping)?;
 
-        if let Ok(Some(path)) = xdg_user::pictures() {
-            let local_timestamp = time::OffsetDateTime::now_utc().to_offset(*offset);
-            let mut title = window.title();
-            title.truncate(227); // 255 - time - png
-            let name = sanitize_filename::sanitize(format!(
-                "{}_{}.png",
-                title,
-                local_timestamp
-                    .format(time::macros::format_description!(
-                        "[year]-[month]-[day]_[hour]:[minute]:[second]_[subsecond digits:4]"
-                    ))
-                    .unwrap(),
-            ));
-            let file = std::fs::File::create(path.join(name))?;
-
-            let ref mut writer = std::io::BufWriter::new(file);
-            let mut encoder = png::Encoder::new(writer, bbox.size.w as u32, bbox.size.h as u32);
-            encoder.set_color(png::ColorType::Rgba);
-            encoder.set_depth(png::BitDepth::Eight);
-            encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2)); // 1.0 / 2.2, unscaled, but rounded
-            let source_chromaticities = png::SourceChromaticities::new(
-                // Using unscaled instantiation here
-                (0.31270, 0.32900),
-                (0.64000, 0.33000),
-                (0.30000, 0.60000),
-                (0.15000, 0.06000),
-            );
-            encoder.set_source_chromaticities(source_chromaticities);
-            let mut writer = encoder.write_header()?;
-            writer.write_image_data(&gl_data)?;
-        }
+        write_png(&window.title(), bbox.size.w, bbox.size.h, &gl_data, offset)?;
 
         Ok(())
     }
 
+    let shell = state.common.shell.read().unwrap();
+    let hidden = !window_hidden_areas(surface, &shell.capture_excluded_apps).is_empty();
+    std::mem::drop(shell);
+    if hidden {
+        info!("Not taking a screenshot of a window excluded from captures");
+        return;
+    }
+
     if let Some(wl_surface) = surface.wl_surface() {
         let res = state
             .backend
@@ -117,3 +146,109 @@ pub fn screenshot_window(state: &mut State, surface: &CosmicSurface) {
         }
     }
 }
+
+/// Takes a screenshot of the active workspace of `output`, optionally cropped to `region`
+pub fn screenshot_output(
+    state: &mut State,
+    output: &Output,
+    region: Option<Rectangle<i32, Local>>,
+) {
+    fn render_output<R>(
+        renderer: &mut R,
+        common: &Common,
+        output: &Output,
+        region: Rectangle<i32, Local>,
+    ) -> anyhow::Result<()>
+    where
+        R: Renderer
+            + ImportAll
+            + ImportMem
+            + ExportMem
+            + Bind<Dmabuf>
+            + Offscreen<GlesRenderbuffer>
+            + AsGlowRenderer,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+        <R as Renderer>::Error: FromGlesError + Send + Sync + 'static,
+        CosmicElement<R>: RenderElement<R>,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+        WorkspaceRenderElement<R>: RenderElement<R>,
+    {
+        let scale = output.current_scale().fractional_scale();
+        let size = output
+            .geometry()
+            .size
+            .as_logical()
+            .to_physical_precise_round(scale);
+        let region = region.as_logical().to_physical_precise_round(scale);
+
+        let (workspace, blanked) = {
+            let shell = common.shell.read().unwrap();
+            let (_, workspace) = shell.workspaces.active(output);
+            let (_, idx) = shell.workspaces.active_num(output);
+            ((workspace.handle, idx), output_hidden_areas(&shell, output))
+        };
+
+        // TODO: 10-bit
+        let format = Fourcc::Abgr8888;
+        let render_buffer = Offscreen::<GlesRenderbuffer>::create_buffer(
+            renderer,
+            format,
+            size.to_logical(1).to_buffer(1, Transform::Normal),
+        )?;
+        let mut output_damage_tracker = OutputDamageTracker::new(size, scale, Transform::Normal);
+        render_workspace::<_, _, GlesRenderbuffer>(
+            None,
+            renderer,
+            render_buffer,
+            &mut output_damage_tracker,
+            0,
+            None,
+            &common.shell,
+            common.clock.now(),
+            output,
+            None,
+            workspace,
+            CursorMode::None,
+            ElementFilter::ExcludeWorkspaceOverview,
+        )
+        .map_err(|err| match err {
+            DTError::Rendering(err) => anyhow::Error::new(err),
+            DTError::OutputNoMode(err) => anyhow::Error::new(err),
+        })?;
+        if !blanked.is_empty() {
+            blank_areas(renderer, size, Transform::Normal, &blanked)?;
+        }
+
+        let mapping = renderer.copy_framebuffer(
+            Rectangle::from_loc_and_size(
+                (region.loc.x, region.loc.y),
+                (region.size.w, region.size.h),
+            ),
+            format,
+        )?;
+        let gl_data = renderer.map_texture(&mapping)?;
+        write_png(
+            &output.name(),
+            region.size.w,
+            region.size.h,
+            &gl_data,
+            &common.local_offset,
+        )
+    }
+
+    let region = region
+        .unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), output.geometry().size.as_local()));
+    let res = state
+        .backend
+        .offscreen_renderer(|kms| kms.target_node_for_output(output).or(kms.primary_node))
+        .with_context(|| "Failed to get renderer for screenshot")
+        .and_then(|renderer| match renderer {
+            RendererRef::Glow(renderer) => render_output(renderer, &state.common, output, region),
+            RendererRef::GlMulti(mut renderer) => {
+                render_output(&mut renderer, &state.common, output, region)
+            }
+        });
+    if let Err(err) = res {
+        warn!(?err, "Failed to take screenshot")
+    }
+}
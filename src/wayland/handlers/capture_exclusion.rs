@@ -0,0 +1,5 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{state::State, wayland::protocols::capture_exclusion::delegate_capture_exclusion};
+
+delegate_capture_exclusion!(State);
@@ -1,12 +1,24 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::{shell::grabs::SeatMoveGrabState, state::ClientState, utils::prelude::*};
+use crate::{
+    shell::{element::surface::track_buffer_serial, grabs::SeatMoveGrabState},
+    state::ClientState,
+    utils::{prelude::*, recover},
+    wayland::handlers::{
+        decoration::forces_server_side,
+        toplevel_drag::{cancel_toplevel_drag, start_toplevel_drag},
+    },
+};
 use calloop::Interest;
+use cosmic_comp_config::HookEvent;
 use smithay::{
     backend::renderer::utils::{on_commit_buffer_handler, with_renderer_surface_state},
     delegate_compositor,
     desktop::{layer_map_for_output, LayerSurface, PopupKind, WindowSurfaceType},
-    reexports::wayland_server::{protocol::wl_surface::WlSurface, Client, Resource},
+    reexports::{
+        wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as XdgMode,
+        wayland_server::{protocol::wl_surface::WlSurface, Client, Resource},
+    },
     utils::SERIAL_COUNTER,
     wayland::{
         compositor::{
@@ -26,7 +38,7 @@ use smithay::{
 };
 use std::sync::Mutex;
 
-fn toplevel_ensure_initial_configure(toplevel: &ToplevelSurface) -> bool {
+fn toplevel_ensure_initial_configure(toplevel: &ToplevelSurface, force_ssd: bool) -> bool {
     // send the initial configure if relevant
     let initial_configure_sent = with_states(toplevel.wl_surface(), |states| {
         states
@@ -39,7 +51,13 @@ fn toplevel_ensure_initial_configure(toplevel: &ToplevelSurface) -> bool {
     });
     if !initial_configure_sent {
         // TODO: query expected size from shell (without inserting and mapping)
-        toplevel.with_pending_state(|states| states.size = None);
+        toplevel.with_pending_state(|states| {
+            states.size = None;
+            // the app-id might not have been known, when the decoration mode was negotiated
+            if force_ssd && states.decoration_mode.is_some() {
+                states.decoration_mode = Some(XdgMode::ServerSide);
+            }
+        });
         toplevel.send_configure();
     }
     initial_configure_sent
@@ -100,6 +118,9 @@ impl CompositorHandler for State {
 
     fn new_surface(&mut self, surface: &WlSurface) {
         add_pre_commit_hook::<Self, _>(surface, move |state, _dh, surface| {
+            // tiling transactions wait for buffers drawn at the new sizes
+            track_buffer_serial(surface);
+
             let maybe_dmabuf = with_states(surface, |surface_data| {
                 surface_data
                     .cached_state
@@ -135,6 +156,9 @@ impl CompositorHandler for State {
     }
 
     fn commit(&mut self, surface: &WlSurface) {
+        // most work caused by clients happens here
+        let _client = recover::handling_client(surface);
+
         // first load the buffer for various smithay helper functions (which also initializes the RendererSurfaceState)
         on_commit_buffer_handler::<Self>(surface);
 
@@ -199,9 +223,14 @@ impl CompositorHandler for State {
                     }
                 } else {
                     std::mem::drop(shell);
-                    seat.get_pointer()
-                        .unwrap()
-                        .unset_grab(self, SERIAL_COUNTER.next_serial(), 0);
+                    // windows attached to a drag-and-drop operation aren't moved by a pointer grab
+                    if !cancel_toplevel_drag(&seat, surface) {
+                        seat.get_pointer().unwrap().unset_grab(
+                            self,
+                            SERIAL_COUNTER.next_serial(),
+                            0,
+                        );
+                    }
                     return;
                 }
             }
@@ -220,6 +249,9 @@ impl CompositorHandler for State {
             // We only want to resize once the client has acknoledged & commited the new size,
             // so we need to carefully track the state through different handlers.
             if let Some(element) = shell.element_for_surface(surface).cloned() {
+                if let Some(ipc) = self.common.ipc.as_mut() {
+                    ipc.window_committed(&element);
+                }
                 crate::shell::layout::floating::ResizeSurfaceGrab::apply_resize_to_location(
                     element.clone(),
                     &mut *shell,
@@ -257,7 +289,8 @@ impl State {
             .cloned()
         {
             if let Some(toplevel) = window.0.toplevel() {
-                if toplevel_ensure_initial_configure(&toplevel)
+                let force_ssd = forces_server_side(&self.common.config, surface);
+                if toplevel_ensure_initial_configure(&toplevel, force_ssd)
                     && with_renderer_surface_state(&surface, |state| state.buffer().is_some())
                         .unwrap_or(false)
                 {
@@ -269,10 +302,15 @@ impl State {
                         &mut self.common.workspace_state,
                         &self.common.event_loop_handle,
                     );
+                    self.run_hooks(HookEvent::WindowMapped, Some(&window), &[]);
+                    if let Some(ipc) = self.common.ipc.as_mut() {
+                        ipc.window_opened(&shell, &window);
+                    }
                     if let Some(target) = res {
                         let seat = shell.seats.last_active().clone();
                         std::mem::drop(shell);
                         Shell::set_focus(self, Some(&target), &seat, None);
+                        start_toplevel_drag(self, &seat);
                         return true;
                     }
                 }
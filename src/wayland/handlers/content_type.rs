@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::state::State;
+use smithay::{
+    delegate_content_type,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    wayland::{
+        compositor::with_states,
+        content_type::{ContentType, ContentTypeSurfaceCachedState},
+    },
+};
+
+delegate_content_type!(State);
+
+/// Returns the content type hint the client committed for `surface`
+pub fn surface_content_type(surface: &WlSurface) -> ContentType {
+    with_states(surface, |states| {
+        *states
+            .cached_state
+            .get::<ContentTypeSurfaceCachedState>()
+            .current()
+            .content_type()
+    })
+}
@@ -1,50 +1,231 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::state::State;
+use crate::{
+    backend::render::{element::AsGlowRenderer, IndicatorShader, Key},
+    shell::element::CosmicMappedRenderElement,
+    state::State,
+    utils::{prelude::*, recover},
+    wayland::handlers::toplevel_drag::{dnd_ended, dnd_started},
+};
+use calloop::timer::{TimeoutAction, Timer};
+use cosmic::cosmic_theme::CosmicTheme;
 use smithay::{
+    backend::renderer::{element::RenderElement, Renderer},
     delegate_data_device,
     input::Seat,
-    reexports::wayland_server::protocol::{wl_data_source::WlDataSource, wl_surface::WlSurface},
-    utils::IsAlive,
+    output::Output,
+    reexports::wayland_server::{
+        protocol::{wl_data_source::WlDataSource, wl_surface::WlSurface},
+        Resource,
+    },
+    utils::{IsAlive, Point, Rectangle},
     wayland::selection::data_device::{
         ClientDndGrabHandler, DataDeviceHandler, DataDeviceState, ServerDndGrabHandler,
     },
 };
-use std::sync::Mutex;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Duration of the icon returning to where the drag started, after it was cancelled
+const RETURN_DURATION: Duration = Duration::from_millis(250);
+const RETURN_UPDATE_INTERVAL: Duration = Duration::from_millis(16);
+const TARGET_ALPHA: f32 = 0.6;
+
+/// Drag-and-drop operation of a seat, as far as it is visible
+pub struct SeatDnD {
+    state: Mutex<DnDState>,
+    // key for the drop target indicator
+    key: Arc<()>,
+}
 
-pub struct DnDIcon {
-    surface: Mutex<Option<WlSurface>>,
+#[derive(Default)]
+struct DnDState {
+    icon: Option<WlSurface>,
+    dragging: bool,
+    /// Pointer location when the drag started
+    origin: Point<f64, Global>,
+    /// Window below the pointer, highlighted as the drop target
+    target: Option<Rectangle<i32, Global>>,
+    /// Where the icon was dropped and when, while it returns to the origin
+    returning: Option<(Point<f64, Global>, Instant)>,
 }
 
 pub fn get_dnd_icon(seat: &Seat<State>) -> Option<WlSurface> {
     let userdata = seat.user_data();
     userdata
-        .get::<DnDIcon>()
-        .and_then(|x| x.surface.lock().unwrap().clone())
+        .get::<SeatDnD>()
+        .and_then(|x| x.state.lock().unwrap().icon.clone())
         .filter(IsAlive::alive)
 }
 
+/// Location of the dnd icon while it returns to the origin of a cancelled drag,
+/// otherwise it follows the pointer
+pub fn dnd_icon_location(seat: &Seat<State>) -> Option<Point<f64, Global>> {
+    let dnd = seat.user_data().get::<SeatDnD>()?;
+    let state = dnd.state.lock().unwrap();
+    let (dropped, start) = state.returning?;
+    let progress = (start.elapsed().as_secs_f64() / RETURN_DURATION.as_secs_f64()).min(1.0);
+    // ease out
+    let progress = 1.0 - (1.0 - progress).powi(3);
+    Some(dropped + (state.origin - dropped).upscale(progress))
+}
+
+/// Updates the drop target of the drag-and-drop operation of `seat`, if there is one
+pub fn update_dnd_target(
+    state: &mut State,
+    seat: &Seat<State>,
+    location: Point<f64, Global>,
+    output: &Output,
+) {
+    let Some(dnd) = seat.user_data().get::<SeatDnD>() else {
+        return;
+    };
+    if !dnd.state.lock().unwrap().dragging {
+        return;
+    }
+
+    let target = {
+        let mut shell = state.common.shell.write().unwrap();
+        shell
+            .element_under(location, output)
+            .and_then(|target| shell.target_geometry(target))
+    };
+
+    let previous = std::mem::replace(&mut dnd.state.lock().unwrap().target, target);
+    if previous != target {
+        schedule_render_all(state);
+    }
+}
+
+impl SeatDnD {
+    pub fn render<I, R>(&self, renderer: &R, output: &Output, theme: &CosmicTheme) -> Vec<I>
+    where
+        R: Renderer + AsGlowRenderer,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+        I: From<CosmicMappedRenderElement<R>>,
+    {
+        let state = self.state.lock().unwrap();
+        let Some(target) = state.target.filter(|_| state.dragging) else {
+            return Vec::new();
+        };
+        if target.intersection(output.geometry()).is_none() {
+            return Vec::new();
+        }
+
+        let accent = theme.accent_color();
+        vec![I::from(CosmicMappedRenderElement::from(
+            IndicatorShader::focus_element(
+                renderer,
+                Key::Group(Arc::downgrade(&self.key)),
+                target.to_local(output),
+                theme.active_hint as u8,
+                output.current_scale().fractional_scale(),
+                TARGET_ALPHA,
+                [accent.red, accent.green, accent.blue],
+            ),
+        ))]
+    }
+}
+
+fn schedule_render_all(state: &mut State) {
+    let outputs = state
+        .common
+        .shell
+        .read()
+        .unwrap()
+        .outputs()
+        .cloned()
+        .collect::<Vec<_>>();
+    for output in &outputs {
+        state.backend.schedule_render(output);
+    }
+}
+
 impl ClientDndGrabHandler for State {
-    fn started(
-        &mut self,
-        _source: Option<WlDataSource>,
-        icon: Option<WlSurface>,
-        seat: Seat<Self>,
-    ) {
+    fn started(&mut self, source: Option<WlDataSource>, icon: Option<WlSurface>, seat: Seat<Self>) {
+        let _client = recover::handling(source.as_ref().and_then(Resource::client));
         let user_data = seat.user_data();
-        user_data.insert_if_missing_threadsafe(|| DnDIcon {
-            surface: Mutex::new(None),
+        user_data.insert_if_missing_threadsafe(|| SeatDnD {
+            state: Mutex::new(DnDState::default()),
+            key: Arc::new(()),
         });
-        *user_data.get::<DnDIcon>().unwrap().surface.lock().unwrap() = icon;
+        *user_data.get::<SeatDnD>().unwrap().state.lock().unwrap() = DnDState {
+            icon,
+            dragging: true,
+            origin: seat.get_pointer().unwrap().current_location().as_global(),
+            target: None,
+            returning: None,
+        };
+        dnd_started(self, &seat, source);
     }
     fn dropped(&mut self, seat: Seat<Self>) {
-        seat.user_data()
-            .get::<DnDIcon>()
-            .unwrap()
-            .surface
-            .lock()
-            .unwrap()
-            .take();
+        let cancelled = {
+            let mut dnd = seat
+                .user_data()
+                .get::<SeatDnD>()
+                .unwrap()
+                .state
+                .lock()
+                .unwrap();
+            dnd.dragging = false;
+            // dropped outside of any window, let the icon return to where it came from
+            let cancelled =
+                dnd.target.take().is_none() && dnd.icon.as_ref().is_some_and(IsAlive::alive);
+            if cancelled {
+                let location = seat.get_pointer().unwrap().current_location().as_global();
+                dnd.returning = Some((location, Instant::now()));
+            } else {
+                dnd.icon = None;
+            }
+            cancelled
+        };
+        dnd_ended(&seat);
+
+        if cancelled {
+            let timer_seat = seat.clone();
+            if let Err(err) = self.common.event_loop_handle.insert_source(
+                Timer::immediate(),
+                move |_, _, state| {
+                    let done = {
+                        let mut dnd = timer_seat
+                            .user_data()
+                            .get::<SeatDnD>()
+                            .unwrap()
+                            .state
+                            .lock()
+                            .unwrap();
+                        match dnd.returning {
+                            Some((_, start)) if start.elapsed() >= RETURN_DURATION => {
+                                dnd.returning = None;
+                                dnd.icon = None;
+                                true
+                            }
+                            Some(_) => false,
+                            // another drag started in the meantime
+                            None => return TimeoutAction::Drop,
+                        }
+                    };
+                    schedule_render_all(state);
+                    if done {
+                        TimeoutAction::Drop
+                    } else {
+                        TimeoutAction::ToDuration(RETURN_UPDATE_INTERVAL)
+                    }
+                },
+            ) {
+                warn!(?err, "Failed to animate cancelled drag.");
+                seat.user_data()
+                    .get::<SeatDnD>()
+                    .unwrap()
+                    .state
+                    .lock()
+                    .unwrap()
+                    .icon = None;
+            }
+        }
     }
 }
 impl ServerDndGrabHandler for State {}
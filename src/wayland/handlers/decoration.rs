@@ -11,16 +11,17 @@ use smithay::{
         wayland_server::protocol::wl_surface::WlSurface,
     },
     wayland::{
+        compositor::with_states,
         seat::WaylandFocus,
         shell::{
             kde::decoration::{KdeDecorationHandler, KdeDecorationState},
-            xdg::{decoration::XdgDecorationHandler, ToplevelSurface},
+            xdg::{decoration::XdgDecorationHandler, ToplevelSurface, XdgToplevelSurfaceData},
         },
     },
 };
 use wayland_backend::protocol::WEnum;
 
-use crate::{shell::CosmicMapped, state::State};
+use crate::{config::Config, shell::CosmicMapped, state::State};
 
 pub struct PreferredDecorationMode(RefCell<Option<XdgMode>>);
 
@@ -54,6 +55,22 @@ impl PreferredDecorationMode {
     }
 }
 
+/// Returns `true`, if the user configured the app-id of `surface` to always use server-side decorations
+pub fn forces_server_side(config: &Config, surface: &WlSurface) -> bool {
+    let app_id = with_states(surface, |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .and_then(|data| data.lock().unwrap().app_id.clone())
+    });
+    app_id.is_some_and(|app_id| {
+        config
+            .cosmic_conf
+            .force_server_side_decorations
+            .contains(&app_id)
+    })
+}
+
 pub fn new_decoration(mapped: &CosmicMapped, surface: &WlSurface) -> KdeMode {
     if mapped.is_stack() {
         if let Some((window, _)) = mapped
@@ -114,13 +131,26 @@ pub fn unset_mode(mapped: &CosmicMapped, surface: &WlSurface) {
 
 impl XdgDecorationHandler for State {
     fn new_decoration(&mut self, toplevel: ToplevelSurface) {
+        let force_ssd = forces_server_side(&self.common.config, toplevel.wl_surface());
         let shell = self.common.shell.read().unwrap();
         if let Some(mapped) = shell.element_for_surface(toplevel.wl_surface()) {
-            new_decoration(mapped, toplevel.wl_surface());
+            if force_ssd {
+                request_mode(mapped, toplevel.wl_surface(), XdgMode::ServerSide);
+            } else {
+                new_decoration(mapped, toplevel.wl_surface());
+            }
+        } else if force_ssd {
+            toplevel.with_pending_state(|state| state.decoration_mode = Some(XdgMode::ServerSide));
         }
     }
 
     fn request_mode(&mut self, toplevel: ToplevelSurface, mode: XdgMode) {
+        let mode = if forces_server_side(&self.common.config, toplevel.wl_surface()) {
+            XdgMode::ServerSide
+        } else {
+            mode
+        };
+
         let shell = self.common.shell.read().unwrap();
         if let Some(mapped) = shell.element_for_surface(toplevel.wl_surface()) {
             request_mode(mapped, toplevel.wl_surface(), mode);
@@ -130,9 +160,14 @@ impl XdgDecorationHandler for State {
     }
 
     fn unset_mode(&mut self, toplevel: ToplevelSurface) {
+        let force_ssd = forces_server_side(&self.common.config, toplevel.wl_surface());
         let shell = self.common.shell.read().unwrap();
         if let Some(mapped) = shell.element_for_surface(toplevel.wl_surface()) {
-            unset_mode(mapped, toplevel.wl_surface())
+            if force_ssd {
+                request_mode(mapped, toplevel.wl_surface(), XdgMode::ServerSide);
+            } else {
+                unset_mode(mapped, toplevel.wl_surface())
+            }
         }
     }
 }
@@ -143,9 +178,15 @@ impl KdeDecorationHandler for State {
     }
 
     fn new_decoration(&mut self, surface: &WlSurface, decoration: &OrgKdeKwinServerDecoration) {
+        let force_ssd = forces_server_side(&self.common.config, surface);
         let shell = self.common.shell.read().unwrap();
         if let Some(mapped) = shell.element_for_surface(surface) {
-            let mode = new_decoration(mapped, surface);
+            let mode = if force_ssd {
+                request_mode(mapped, surface, XdgMode::ServerSide);
+                KdeMode::Server
+            } else {
+                new_decoration(mapped, surface)
+            };
             decoration.mode(mode);
         }
     }
@@ -157,6 +198,11 @@ impl KdeDecorationHandler for State {
         mode: WEnum<KdeMode>,
     ) {
         if let WEnum::Value(mode) = mode {
+            let mode = if forces_server_side(&self.common.config, surface) {
+                KdeMode::Server
+            } else {
+                mode
+            };
             let shell = self.common.shell.read().unwrap();
             // TODO: We need to store this value until it gets mapped and apply it then, if it is not mapped yet.
             if let Some(mapped) = shell.element_for_surface(surface) {
@@ -174,9 +220,14 @@ impl KdeDecorationHandler for State {
     }
 
     fn release(&mut self, _decoration: &OrgKdeKwinServerDecoration, surface: &WlSurface) {
+        let force_ssd = forces_server_side(&self.common.config, surface);
         let shell = self.common.shell.read().unwrap();
         if let Some(mapped) = shell.element_for_surface(surface) {
-            unset_mode(mapped, surface)
+            if force_ssd {
+                request_mode(mapped, surface, XdgMode::ServerSide);
+            } else {
+                unset_mode(mapped, surface)
+            }
         }
     }
 }
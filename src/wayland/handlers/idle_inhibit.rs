@@ -9,6 +9,7 @@ impl IdleInhibitHandler for State {
     fn inhibit(&mut self, surface: WlSurface) {
         self.common.idle_inhibiting_surfaces.insert(surface);
         self.common.idle_notifier_state.set_is_inhibited(true);
+        self.common.idle_state.set_inhibited(true);
     }
 
     fn uninhibit(&mut self, surface: WlSurface) {
@@ -3,7 +3,9 @@
 use crate::state::State;
 use smithay::{
     delegate_input_method_manager,
-    desktop::{space::SpaceElement, PopupKind, PopupManager},
+    desktop::{
+        layer_map_for_output, space::SpaceElement, PopupKind, PopupManager, WindowSurfaceType,
+    },
     reexports::wayland_server::protocol::wl_surface::WlSurface,
     utils::Rectangle,
     wayland::input_method::{InputMethodHandler, PopupSurface},
@@ -24,12 +26,26 @@ impl InputMethodHandler for State {
     }
 
     fn parent_geometry(&self, parent: &WlSurface) -> Rectangle<i32, smithay::utils::Logical> {
-        self.common
-            .shell
-            .read()
-            .unwrap()
-            .element_for_surface(parent)
-            .map(|e| e.geometry())
+        let shell = self.common.shell.read().unwrap();
+        if let Some(mapped) = shell.element_for_surface(parent) {
+            return mapped.geometry();
+        }
+
+        // text fields of layer-shell clients, like the launcher or the panel
+        shell
+            .outputs()
+            .find_map(|output| {
+                let map = layer_map_for_output(output);
+                let layer = map.layer_for_surface(parent, WindowSurfaceType::TOPLEVEL)?;
+                map.layer_geometry(layer)
+            })
+            // text fields inside of popups
+            .or_else(|| {
+                self.common
+                    .popups
+                    .find_popup(parent)
+                    .map(|popup| popup.geometry())
+            })
             .unwrap_or_default()
     }
 
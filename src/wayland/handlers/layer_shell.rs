@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::utils::prelude::*;
+use crate::utils::{prelude::*, recover};
 use smithay::{
     delegate_layer_shell,
     desktop::{layer_map_for_output, LayerSurface, PopupKind, WindowSurfaceType},
@@ -26,6 +26,7 @@ impl WlrLayerShellHandler for State {
         _layer: Layer,
         namespace: String,
     ) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         let seat = shell.seats.last_active().clone();
         let output = wl_output
@@ -38,6 +39,7 @@ impl WlrLayerShellHandler for State {
     }
 
     fn new_popup(&mut self, _parent: WlrLayerSurface, popup: PopupSurface) {
+        let _client = recover::handling_client(popup.wl_surface());
         self.common.shell.read().unwrap().unconstrain_popup(&popup);
 
         if popup.send_configure().is_ok() {
@@ -49,6 +51,7 @@ impl WlrLayerShellHandler for State {
     }
 
     fn layer_destroyed(&mut self, surface: WlrLayerSurface) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         let maybe_output = shell
             .outputs()
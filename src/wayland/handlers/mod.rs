@@ -2,7 +2,9 @@
 
 pub mod alpha_modifier;
 pub mod buffer;
+pub mod capture_exclusion;
 pub mod compositor;
+pub mod content_type;
 pub mod data_control;
 pub mod data_device;
 pub mod decoration;
@@ -19,6 +21,7 @@ pub mod keyboard_shortcuts_inhibit;
 pub mod layer_shell;
 pub mod output;
 pub mod output_configuration;
+pub mod output_power;
 pub mod pointer_constraints;
 pub mod pointer_gestures;
 pub mod presentation;
@@ -26,17 +29,22 @@ pub mod primary_selection;
 pub mod relative_pointer;
 pub mod screencopy;
 pub mod seat;
+pub mod secure_input;
 pub mod security_context;
 pub mod selection;
 pub mod session_lock;
 pub mod shm;
 pub mod single_pixel_buffer;
 pub mod tablet_manager;
+pub mod tearing_control;
 pub mod text_input;
+pub mod toplevel_drag;
+pub mod toplevel_icon;
 pub mod toplevel_info;
 pub mod toplevel_management;
 pub mod viewporter;
 pub mod virtual_keyboard;
+pub mod virtual_pointer;
 pub mod workspace;
 pub mod xdg_activation;
 pub mod xdg_foreign;
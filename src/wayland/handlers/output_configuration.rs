@@ -28,7 +28,7 @@ impl OutputConfigurationHandler for State {
 }
 
 impl State {
-    fn output_configuration(
+    pub(crate) fn output_configuration(
         &mut self,
         test_only: bool,
         mut conf: Vec<(Output, OutputConfiguration)>,
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{
+    state::State,
+    wayland::protocols::output_power::{
+        delegate_output_power, OutputPowerHandler, OutputPowerState,
+    },
+};
+use smithay::output::Output;
+
+impl OutputPowerHandler for State {
+    fn output_power_state(&mut self) -> &mut OutputPowerState {
+        &mut self.common.output_power_state
+    }
+
+    fn get_dpms(&mut self, output: &Output) -> Option<bool> {
+        self.backend.dpms(output)
+    }
+
+    fn set_dpms(&mut self, output: &Output, on: bool) {
+        self.backend.set_dpms(output, on);
+    }
+}
+
+delegate_output_power!(State);
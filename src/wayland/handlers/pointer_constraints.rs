@@ -1,27 +1,106 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::state::State;
+use crate::{state::State, utils::prelude::*};
 use smithay::{
     delegate_pointer_constraints,
-    input::pointer::PointerHandle,
-    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    input::{pointer::PointerHandle, Seat},
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, Resource},
     wayland::{
-        pointer_constraints::{with_pointer_constraint, PointerConstraintsHandler},
+        pointer_constraints::{
+            with_pointer_constraint, PointerConstraint, PointerConstraintsHandler,
+        },
         seat::WaylandFocus,
     },
 };
 
 impl PointerConstraintsHandler for State {
     fn new_constraint(&mut self, surface: &WlSurface, pointer: &PointerHandle<Self>) {
-        // XXX region
-        if pointer
+        if !pointer
             .current_focus()
             .map_or(false, |x| x.wl_surface().as_deref() == Some(surface))
         {
-            with_pointer_constraint(surface, pointer, |constraint| {
-                constraint.unwrap().activate();
-            });
+            return;
         }
+
+        // Games usually request a constraint right away, but it should only take effect once
+        // the pointer is actually within the region the client asked for.
+        let mut shell = self.common.shell.write().unwrap();
+        let Some(seat) = shell
+            .seats
+            .iter()
+            .find(|seat| seat.get_pointer().as_ref() == Some(pointer))
+            .cloned()
+        else {
+            return;
+        };
+        let position = pointer.current_location().as_global();
+        let output = seat.active_output();
+        let surface_loc = State::surface_under(position, &output, &mut shell)
+            .filter(|(target, _)| target.wl_surface().as_deref() == Some(surface))
+            .map(|(_, loc)| loc);
+        std::mem::drop(shell);
+
+        let Some(surface_loc) = surface_loc else {
+            return;
+        };
+        if !has_keyboard_focus(self, &seat, surface) {
+            return;
+        }
+        with_pointer_constraint(surface, pointer, |constraint| {
+            let Some(constraint) = constraint else {
+                return;
+            };
+            let region = match &*constraint {
+                PointerConstraint::Locked(locked) => locked.region(),
+                PointerConstraint::Confined(confined) => confined.region(),
+            };
+            let point = (position - surface_loc).as_logical().to_i32_round();
+            if region.map_or(true, |region| region.contains(point)) {
+                constraint.activate();
+            }
+        });
     }
 }
 delegate_pointer_constraints!(State);
+
+/// Releases any active pointer constraint of the current pointer focus,
+/// if that surface doesn't belong to the client holding keyboard focus.
+///
+/// Clients like games should never keep the cursor captured after the user switched away.
+pub fn release_unfocused_constraints(state: &mut State, seat: &Seat<State>) {
+    let Some(pointer) = seat.get_pointer() else {
+        return;
+    };
+    let Some(surface) = pointer
+        .current_focus()
+        .and_then(|target| target.wl_surface().map(|s| s.into_owned()))
+    else {
+        return;
+    };
+
+    if has_keyboard_focus(state, seat, &surface) {
+        return;
+    }
+
+    with_pointer_constraint(&surface, &pointer, |constraint| {
+        if let Some(constraint) = constraint {
+            if constraint.is_active() {
+                constraint.deactivate();
+            }
+        }
+    });
+}
+
+/// Checks if `surface` belongs to the client holding keyboard focus,
+/// pointer constraints must only be active for that client.
+pub fn has_keyboard_focus(state: &State, seat: &Seat<State>, surface: &WlSurface) -> bool {
+    let Some(keyboard) = seat.get_keyboard() else {
+        return false;
+    };
+    let focused_client = keyboard
+        .current_focus()
+        .and_then(|target| target.wl_surface().map(|s| s.into_owned()))
+        .and_then(|s| state.common.display_handle.get_client(s.id()).ok());
+    let surface_client = state.common.display_handle.get_client(surface.id()).ok();
+    focused_client.is_some() && focused_client == surface_client
+}
@@ -21,8 +21,11 @@ use smithay::{
 use crate::{
     shell::CosmicSurface,
     state::{BackendData, State},
-    utils::prelude::{
-        OutputExt, PointExt, PointGlobalExt, PointLocalExt, RectExt, RectLocalExt, SeatExt,
+    utils::{
+        prelude::{
+            OutputExt, PointExt, PointGlobalExt, PointLocalExt, RectExt, RectLocalExt, SeatExt,
+        },
+        recover,
     },
     wayland::protocols::{
         image_source::ImageSourceData,
@@ -33,8 +36,10 @@ use crate::{
     },
 };
 
+mod privacy;
 mod render;
 mod user_data;
+pub use self::privacy::{output_hidden_areas, window_hidden_areas, CaptureClient};
 pub use self::render::*;
 use self::user_data::*;
 pub use self::user_data::{FrameHolder, ScreencopySessions, SessionData, SessionHolder};
@@ -83,6 +88,8 @@ impl ScreencopyHandler for State {
     }
 
     fn new_session(&mut self, session: Session) {
+        let _client = recover::handling(session.client());
+        self.request_capture_consent(&session);
         match session.source() {
             ImageSourceData::Output(weak) => {
                 let Some(mut output) = weak.upgrade() else {
@@ -250,6 +257,10 @@ impl ScreencopyHandler for State {
     }
 
     fn frame(&mut self, session: Session, frame: Frame) {
+        let _client = recover::handling_client(&frame.buffer());
+        let Some(frame) = self.hold_back_capture(&session, frame) else {
+            return;
+        };
         match session.source() {
             ImageSourceData::Output(weak) => {
                 let Some(mut output) = weak.upgrade() else {
@@ -271,6 +282,7 @@ impl ScreencopyHandler for State {
     }
 
     fn cursor_frame(&mut self, session: CursorSession, frame: Frame) {
+        let _client = recover::handling_client(&frame.buffer());
         if !session.has_cursor() {
             frame.success(Transform::Normal, Vec::new(), self.common.clock.now());
             return;
@@ -288,6 +300,7 @@ impl ScreencopyHandler for State {
     }
 
     fn frame_aborted(&mut self, frame: Frame) {
+        self.abort_held_back_capture(&frame);
         let shell = self.common.shell.read().unwrap();
         for mut output in shell.outputs().cloned() {
             output.remove_frame(&frame)
@@ -295,6 +308,7 @@ impl ScreencopyHandler for State {
     }
 
     fn session_destroyed(&mut self, session: Session) {
+        self.cancel_capture_consent(&session);
         match session.source() {
             ImageSourceData::Output(weak) => {
                 if let Some(mut output) = weak.upgrade() {
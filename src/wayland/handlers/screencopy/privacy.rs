@@ -0,0 +1,308 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Which clients capture the screen, and asking before they may.
+//!
+//! Sandboxed clients capture through the portal, which asks the user itself. Capturing by
+//! clients without a security context only starts, once all `screen_capture_requested` hooks
+//! exited successfully. Frames requested meanwhile are held back.
+//!
+//! Surfaces with secure input or excluded from captures, either by their client or by
+//! `capture_excluded_apps`, are blanked out in every capture.
+
+use std::sync::Mutex;
+
+use calloop::RegistrationToken;
+use cosmic_comp_config::HookEvent;
+use smithay::{
+    desktop::{layer_map_for_output, space::SpaceElement},
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Physical, Rectangle},
+    wayland::seat::WaylandFocus,
+};
+use tracing::info;
+
+use crate::{
+    shell::{CosmicMapped, CosmicSurface, Shell, Workspace},
+    state::{ClientState, State},
+    utils::prelude::*,
+    wayland::protocols::{
+        capture_exclusion::surface_is_excluded_from_capture,
+        image_source::ImageSourceData,
+        screencopy::{Frame, ScreencopyHandler, Session},
+        secure_input::surface_has_secure_input,
+    },
+};
+
+/// Consent of the hooks to a capture session, missing if none was needed
+#[derive(Debug)]
+pub struct CaptureConsent(Mutex<ConsentState>);
+
+#[derive(Debug)]
+enum ConsentState {
+    /// Waiting for the hooks, holding back the frames requested meanwhile
+    Pending {
+        token: RegistrationToken,
+        frames: Vec<Frame>,
+    },
+    Granted,
+}
+
+impl CaptureConsent {
+    fn is_granted(session: &Session) -> bool {
+        session
+            .user_data()
+            .get::<CaptureConsent>()
+            .map_or(true, |consent| {
+                matches!(*consent.0.lock().unwrap(), ConsentState::Granted)
+            })
+    }
+}
+
+/// A client capturing the screen or a window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureClient {
+    pub pid: u32,
+    /// App id of sandboxed clients, the name of the executable otherwise
+    pub name: String,
+}
+
+fn process_name(pid: i32) -> String {
+    std::fs::read_to_string(format!("/proc/{}/comm", pid))
+        .map(|comm| comm.trim_end().to_string())
+        .unwrap_or_default()
+}
+
+/// Whether `surface` has to be blanked out in captures
+fn is_hidden(surface: &WlSurface) -> bool {
+    surface_has_secure_input(surface) || surface_is_excluded_from_capture(surface)
+}
+
+fn is_hidden_window(window: &CosmicSurface, excluded_apps: &[String]) -> bool {
+    excluded_apps.contains(&window.app_id())
+        || window
+            .wl_surface()
+            .is_some_and(|surface| is_hidden(&surface))
+}
+
+fn has_hidden_window(mapped: &CosmicMapped, excluded_apps: &[String]) -> bool {
+    mapped
+        .windows()
+        .any(|(window, _)| is_hidden_window(&window, excluded_apps))
+}
+
+fn whole_output(output: &Output) -> Rectangle<i32, Physical> {
+    let scale = output.current_scale().fractional_scale();
+    Rectangle::from_loc_and_size(
+        (0, 0),
+        output
+            .geometry()
+            .size
+            .as_logical()
+            .to_physical_precise_round(scale),
+    )
+}
+
+/// Areas of windows to blank out on `workspace`, in pixels of its output
+pub fn workspace_hidden_areas(
+    workspace: &Workspace,
+    excluded_apps: &[String],
+) -> Vec<Rectangle<i32, Physical>> {
+    let output = workspace.output();
+    if workspace
+        .get_fullscreen()
+        .is_some_and(|window| is_hidden_window(window, excluded_apps))
+    {
+        return vec![whole_output(output)];
+    }
+    let scale = output.current_scale().fractional_scale();
+
+    workspace
+        .mapped()
+        .filter(|mapped| has_hidden_window(mapped, excluded_apps))
+        .filter_map(|mapped| workspace.element_geometry(mapped))
+        .map(|geometry| geometry.as_logical().to_physical_precise_round(scale))
+        .collect()
+}
+
+/// Areas of surfaces to blank out shown on `output`, in its pixels
+pub fn output_hidden_areas(shell: &Shell, output: &Output) -> Vec<Rectangle<i32, Physical>> {
+    // lock surfaces always have secure input
+    if shell.session_lock.is_some() {
+        return vec![whole_output(output)];
+    }
+    let excluded_apps = &shell.capture_excluded_apps;
+    let scale = output.current_scale().fractional_scale();
+
+    let mut areas = workspace_hidden_areas(shell.active_space(output), excluded_apps);
+    if let Some(set) = shell.workspaces.sets.get(output) {
+        areas.extend(
+            set.sticky_layer
+                .mapped()
+                .filter(|mapped| has_hidden_window(mapped, excluded_apps))
+                .filter_map(|mapped| set.sticky_layer.element_geometry(mapped))
+                .map(|geometry| geometry.as_logical().to_physical_precise_round(scale)),
+        );
+    }
+    let map = layer_map_for_output(output);
+    areas.extend(
+        map.layers()
+            .filter(|layer| is_hidden(layer.wl_surface()))
+            .filter_map(|layer| map.layer_geometry(layer))
+            .map(|geometry| geometry.to_physical_precise_round(scale)),
+    );
+    areas
+}
+
+/// Area of `window` in its own captures, if it has to be blanked out
+pub fn window_hidden_areas(
+    window: &CosmicSurface,
+    excluded_apps: &[String],
+) -> Vec<Rectangle<i32, Physical>> {
+    if is_hidden_window(window, excluded_apps) {
+        vec![Rectangle::from_loc_and_size(
+            (0, 0),
+            window.geometry().size.to_physical(1),
+        )]
+    } else {
+        Vec::new()
+    }
+}
+
+impl State {
+    /// Records the client of `session` and asks the hooks, if it may capture at all
+    pub(super) fn request_capture_consent(&mut self, session: &Session) {
+        let Some(client) = session.client() else {
+            return;
+        };
+        let pid = client
+            .get_credentials(&self.common.display_handle)
+            .map(|credentials| credentials.pid)
+            .unwrap_or(0);
+        let security_context = client
+            .get_data::<ClientState>()
+            .and_then(|data| data.security_context.clone());
+        let program = process_name(pid);
+        session
+            .user_data()
+            .insert_if_missing_threadsafe(|| CaptureClient {
+                pid: pid as u32,
+                name: security_context
+                    .as_ref()
+                    .and_then(|context| context.app_id.clone())
+                    .unwrap_or_else(|| program.clone()),
+            });
+        if security_context.is_some() {
+            return;
+        }
+
+        let mut env = vec![("PID", pid.to_string()), ("PROGRAM", program)];
+        let output = |output: Option<Output>| output.map(|o| o.name()).unwrap_or_default();
+        match session.source() {
+            ImageSourceData::Output(weak) => {
+                env.push(("SOURCE", String::from("output")));
+                env.push(("OUTPUT", output(weak.upgrade())));
+            }
+            ImageSourceData::Workspace(handle) => {
+                let shell = self.common.shell.read().unwrap();
+                let workspace_output = shell
+                    .workspaces
+                    .space_for_handle(&handle)
+                    .map(|workspace| workspace.output().clone());
+                env.push(("SOURCE", String::from("workspace")));
+                env.push(("OUTPUT", output(workspace_output)));
+            }
+            ImageSourceData::Toplevel(window) => {
+                env.push(("SOURCE", String::from("window")));
+                env.push(("WINDOW_APP_ID", window.app_id()));
+                env.push(("WINDOW_TITLE", window.title()));
+            }
+            ImageSourceData::Destroyed => return,
+        }
+
+        let answered = session.clone();
+        let token = self.ask_hooks(
+            HookEvent::ScreenCaptureRequested,
+            None,
+            &env,
+            move |state, granted| {
+                let Some(consent) = answered.user_data().get::<CaptureConsent>() else {
+                    return;
+                };
+                let previous =
+                    std::mem::replace(&mut *consent.0.lock().unwrap(), ConsentState::Granted);
+                let ConsentState::Pending { frames, .. } = previous else {
+                    return;
+                };
+                if !granted {
+                    info!(pid, "Screen capture denied by hooks");
+                    // fails the frames held back as well
+                    answered.stop();
+                    return;
+                }
+                for frame in frames {
+                    state.frame(answered.clone(), frame);
+                }
+            },
+        );
+        if let Some(token) = token {
+            session.user_data().insert_if_missing_threadsafe(|| {
+                CaptureConsent(Mutex::new(ConsentState::Pending {
+                    token,
+                    frames: Vec::new(),
+                }))
+            });
+        }
+    }
+
+    /// Holds `frame` back while the hooks didn't consent to `session` yet,
+    /// returns it if it can be captured right away
+    pub(super) fn hold_back_capture(&mut self, session: &Session, frame: Frame) -> Option<Frame> {
+        let Some(consent) = session.user_data().get::<CaptureConsent>() else {
+            return Some(frame);
+        };
+        match &mut *consent.0.lock().unwrap() {
+            ConsentState::Pending { frames, .. } => {
+                frames.push(frame);
+                None
+            }
+            ConsentState::Granted => Some(frame),
+        }
+    }
+
+    /// Forgets the frame, if it was held back
+    pub(super) fn abort_held_back_capture(&mut self, frame: &Frame) {
+        for session in self.common.screencopy_state.sessions() {
+            if let Some(consent) = session.user_data().get::<CaptureConsent>() {
+                if let ConsentState::Pending { frames, .. } = &mut *consent.0.lock().unwrap() {
+                    frames.retain(|f| f != frame);
+                }
+            }
+        }
+    }
+
+    /// Stops asking the hooks about a destroyed session
+    pub(super) fn cancel_capture_consent(&mut self, session: &Session) {
+        if let Some(consent) = session.user_data().get::<CaptureConsent>() {
+            if let ConsentState::Pending { token, .. } =
+                std::mem::replace(&mut *consent.0.lock().unwrap(), ConsentState::Granted)
+            {
+                self.common.event_loop_handle.remove(token);
+            }
+        }
+    }
+
+    /// Clients with capture sessions, that are allowed to capture
+    pub fn capture_clients(&self) -> Vec<CaptureClient> {
+        let mut clients = self
+            .common
+            .screencopy_state
+            .sessions()
+            .filter(|session| !session.is_stopped() && CaptureConsent::is_granted(session))
+            .filter_map(|session| session.user_data().get::<CaptureClient>().cloned())
+            .collect::<Vec<_>>();
+        clients.sort_by_key(|client| client.pid);
+        clients.dedup();
+        clients
+    }
+}
@@ -12,7 +12,8 @@ use smithay::{
             gles::{GlesError, GlesRenderbuffer},
             sync::SyncPoint,
             utils::with_renderer_surface_state,
-            Bind, Blit, BufferType, ExportMem, ImportAll, ImportMem, Offscreen, Renderer,
+            Bind, Blit, BufferType, Color32F, ExportMem, Frame as _, ImportAll, ImportMem,
+            Offscreen, Renderer,
         },
     },
     desktop::space::SpaceElement,
@@ -52,7 +53,10 @@ use crate::{
     },
 };
 
-use super::super::data_device::get_dnd_icon;
+use super::{
+    super::data_device::{dnd_icon_location, get_dnd_icon},
+    privacy::{window_hidden_areas, workspace_hidden_areas},
+};
 
 pub fn submit_buffer<R>(
     frame: Frame,
@@ -134,11 +138,25 @@ where
     )))
 }
 
+/// Draws black over `areas` of hidden surfaces in the bound buffer of `size`
+pub fn blank_areas<R: Renderer>(
+    renderer: &mut R,
+    size: Size<i32, Physical>,
+    transform: Transform,
+    areas: &[Rectangle<i32, Physical>],
+) -> Result<(), <R as Renderer>::Error> {
+    let mut frame = renderer.render(size, transform)?;
+    frame.clear(Color32F::new(0.0, 0.0, 0.0, 1.0), areas)?;
+    frame.finish()?;
+    Ok(())
+}
+
 pub fn render_session<F, R>(
     renderer: &mut R,
     session: &SessionData,
     frame: Frame,
     transform: Transform,
+    blanked: &[Rectangle<i32, Physical>],
     render_fn: F,
 ) -> Result<Option<(Frame, Vec<Rectangle<i32, BufferCoords>>)>, DTError<R>>
 where
@@ -165,14 +183,23 @@ where
     );
 
     match res {
-        Ok(result) => submit_buffer(
-            frame,
-            renderer,
-            transform,
-            result.damage.map(|x| x.as_slice()),
-            result.sync,
-        )
-        .map_err(DTError::Rendering),
+        Ok(result) => {
+            let mut damage = result.damage.cloned();
+            // also without damage, the buffer might still show what got hidden since
+            if !blanked.is_empty() {
+                let size = buffer_dimensions(&buffer).unwrap();
+                let size = Size::from((size.w, size.h));
+                if let Err(err) = blank_areas(renderer, size, transform, blanked) {
+                    frame.fail(FailureReason::Unknown);
+                    return Err(DTError::Rendering(err));
+                }
+                damage
+                    .get_or_insert_with(Vec::new)
+                    .extend_from_slice(blanked);
+            }
+            submit_buffer(frame, renderer, transform, damage.as_deref(), result.sync)
+                .map_err(DTError::Rendering)
+        }
         Err(err) => {
             frame.fail(FailureReason::Unknown);
             Err(err)
@@ -194,6 +221,7 @@ pub fn render_workspace_to_buffer(
 
     let output = workspace.output().clone();
     let idx = shell.workspaces.idx_for_handle(&output, &handle).unwrap();
+    let blanked = workspace_hidden_areas(workspace, &shell.capture_excluded_apps);
     std::mem::drop(shell);
 
     let mode = output
@@ -363,6 +391,7 @@ pub fn render_workspace_to_buffer(
                 session.user_data().get::<SessionData>().unwrap(),
                 frame,
                 transform,
+                &blanked,
                 |buffer, renderer, dt, age, additional_damage| {
                     render_fn(
                         buffer,
@@ -390,6 +419,7 @@ pub fn render_workspace_to_buffer(
                 session.user_data().get::<SessionData>().unwrap(),
                 frame,
                 transform,
+                &blanked,
                 |buffer, renderer, dt, age, additional_damage| {
                     render_fn(
                         buffer,
@@ -540,7 +570,10 @@ pub fn render_window_to_buffer(
                 );
             }
 
-            if let Some(wl_surface) = get_dnd_icon(&seat) {
+            // the icon of a cancelled drag doesn't follow the cursor anymore
+            if let Some(wl_surface) =
+                get_dnd_icon(&seat).filter(|_| dnd_icon_location(&seat).is_none())
+            {
                 elements.extend(
                     cursor::draw_dnd_icon(renderer, &wl_surface, location.to_i32_round(), 1.0)
                         .into_iter()
@@ -604,12 +637,17 @@ pub fn render_window_to_buffer(
             return;
         }
     };
+    let blanked = window_hidden_areas(
+        toplevel,
+        &common.shell.read().unwrap().capture_excluded_apps,
+    );
     let result = match renderer {
         RendererRef::Glow(renderer) => match render_session::<_, _>(
             renderer,
             session.user_data().get::<SessionData>().unwrap(),
             frame,
             Transform::Normal,
+            &blanked,
             |buffer, renderer, dt, age, additional_damage| {
                 render_fn(
                     buffer,
@@ -635,6 +673,7 @@ pub fn render_window_to_buffer(
             session.user_data().get::<SessionData>().unwrap(),
             frame,
             Transform::Normal,
+            &blanked,
             |buffer, renderer, dt, age, additional_damage| {
                 render_fn(
                     buffer,
@@ -777,6 +816,7 @@ pub fn render_cursor_to_buffer(
                 session.user_data().get::<SessionData>().unwrap(),
                 frame,
                 Transform::Normal,
+                &[],
                 |buffer, renderer, dt, age, additional_damage| {
                     render_fn(buffer, renderer, dt, age, additional_damage, common, seat)
                 },
@@ -794,6 +834,7 @@ pub fn render_cursor_to_buffer(
                 session.user_data().get::<SessionData>().unwrap(),
                 frame,
                 Transform::Normal,
+                &[],
                 |buffer, renderer, dt, age, additional_damage| {
                     render_fn(buffer, renderer, dt, age, additional_damage, common, seat)
                 },
@@ -36,8 +36,11 @@ impl SeatHandler for State {
     fn focus_changed(
         &mut self,
         _seat: &smithay::input::Seat<Self>,
-        _focused: Option<&Self::KeyboardFocus>,
+        focused: Option<&Self::KeyboardFocus>,
     ) {
+        if let Some(ipc) = self.common.ipc.as_mut() {
+            ipc.focus_changed(focused);
+        }
     }
 
     fn led_state_changed(&mut self, seat: &smithay::input::Seat<Self>, led_state: LedState) {
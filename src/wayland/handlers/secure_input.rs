@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Secure input, requested by clients asking for secrets like polkit agents
+//! and always active for lock surfaces.
+//!
+//! While a surface with secure input has the keyboard focus, keys bypass the keyboard grab
+//! of input methods, selections set aren't kept and the debug overlay doesn't show the
+//! pressed keys. Screen captures blank those surfaces out regardless of the focus.
+//!
+//! Virtual keyboards aren't covered, there is nothing to hold back from them: clients of
+//! `zwp_virtual_keyboard_v1` only send keys and never receive any. The keyboard grab of an
+//! input method is the only way a wayland client gets the keys of another surface.
+
+use smithay::{
+    input::Seat,
+    wayland::{input_method::InputMethodKeyboardGrab, seat::WaylandFocus},
+};
+use tracing::debug;
+
+use crate::{
+    shell::focus::target::KeyboardFocusTarget,
+    state::State,
+    wayland::protocols::secure_input::{delegate_secure_input, surface_has_secure_input},
+};
+
+/// Whether the keyboard focus of `seat` is a surface with secure input
+pub fn secure_input_active(seat: &Seat<State>) -> bool {
+    match seat
+        .get_keyboard()
+        .and_then(|keyboard| keyboard.current_focus())
+    {
+        Some(KeyboardFocusTarget::LockSurface(_)) => true,
+        Some(target) => target
+            .wl_surface()
+            .is_some_and(|surface| surface_has_secure_input(&surface)),
+        None => false,
+    }
+}
+
+impl State {
+    /// Ends the keyboard grab of an input method before keys reach a surface with
+    /// secure input. Other grabs, like those of popups or the compositor itself, are kept.
+    pub fn release_secure_input_grab(&mut self, seat: &Seat<State>) {
+        let keyboard = seat.get_keyboard().unwrap();
+        if !keyboard.is_grabbed() || !secure_input_active(seat) {
+            return;
+        }
+
+        let input_method_grab = keyboard
+            .with_grab(|_, grab| grab.is::<InputMethodKeyboardGrab>())
+            .unwrap_or(false);
+        if !input_method_grab {
+            return;
+        }
+
+        debug!("Ending input method keyboard grab for secure input");
+        keyboard.unset_grab(self);
+    }
+}
+
+delegate_secure_input!(State);
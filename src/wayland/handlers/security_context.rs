@@ -26,13 +26,25 @@ impl SecurityContextHandler for State {
                     .get_client_data(security_context.creator_client_id.clone())
                     .ok();
 
-                let privileged = client_data
+                // applets share the permissions of the panel
+                let panel = client_data
                     .as_ref()
                     .and_then(|data| data.downcast_ref::<ClientState>())
-                    .map(|data| data.privileged)
-                    .unwrap_or(false);
+                    .filter(|_| {
+                        security_context.sandbox_engine.as_deref()
+                            == Some("com.system76.CosmicPanel")
+                    });
+                let privileged = panel.map_or(false, |data| data.privileged);
+                let privileged_protocols = match panel {
+                    Some(data) => data.privileged_protocols.clone(),
+                    None => state.permitted_protocols(
+                        &client_stream,
+                        security_context.app_id.clone(),
+                        false,
+                    ),
+                };
 
-                let new_state = state.new_client_state();
+                let new_state = state.new_client_state(&client_stream);
 
                 let drm_node = client_data
                     .as_ref()
@@ -50,9 +62,8 @@ impl SecurityContextHandler for State {
                     client_stream,
                     Arc::new(ClientState {
                         security_context: Some(security_context.clone()),
-                        privileged: privileged
-                            && security_context.sandbox_engine.as_deref()
-                                == Some("com.system76.CosmicPanel"),
+                        privileged,
+                        privileged_protocols,
                         advertised_drm_node: drm_node,
                         ..new_state
                     }),
@@ -1,13 +1,48 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::state::State;
+use crate::{state::State, wayland::handlers::secure_input::secure_input_active};
 use smithay::{
     input::Seat,
-    wayland::selection::{SelectionHandler, SelectionSource, SelectionTarget},
+    wayland::selection::{
+        data_device::clear_data_device_selection, primary_selection::clear_primary_selection,
+        SelectionHandler, SelectionSource, SelectionTarget,
+    },
     xwayland::xwm::XwmId,
 };
-use std::os::unix::io::OwnedFd;
-use tracing::warn;
+use std::{os::unix::io::OwnedFd, sync::Mutex};
+use tracing::{debug, warn};
+
+/// Mime types of the last selections set by wayland clients on a seat.
+///
+/// Used to announce already existing selections to a newly started Xwayland.
+#[derive(Debug, Default)]
+pub struct SeatSelections {
+    clipboard: Option<Vec<String>>,
+    primary: Option<Vec<String>>,
+}
+
+impl SeatSelections {
+    fn get_mut(&mut self, target: SelectionTarget) -> &mut Option<Vec<String>> {
+        match target {
+            SelectionTarget::Clipboard => &mut self.clipboard,
+            SelectionTarget::Primary => &mut self.primary,
+        }
+    }
+}
+
+pub fn seat_selections(seat: &Seat<State>) -> Vec<(SelectionTarget, Vec<String>)> {
+    let Some(selections) = seat.user_data().get::<Mutex<SeatSelections>>() else {
+        return Vec::new();
+    };
+    let selections = selections.lock().unwrap();
+    [
+        (SelectionTarget::Clipboard, selections.clipboard.clone()),
+        (SelectionTarget::Primary, selections.primary.clone()),
+    ]
+    .into_iter()
+    .filter_map(|(target, mime_types)| Some((target, mime_types?)))
+    .collect()
+}
 
 impl SelectionHandler for State {
     type SelectionUserData = XwmId;
@@ -16,8 +51,31 @@ impl SelectionHandler for State {
         &mut self,
         target: SelectionTarget,
         source: Option<SelectionSource>,
-        _seat: Seat<State>,
+        seat: Seat<State>,
     ) {
+        // keep clipboard managers from learning what was copied out of a password prompt
+        let source = if source.is_some() && secure_input_active(&seat) {
+            debug!(?target, "Dropping selection set during secure input");
+            let dh = &self.common.display_handle;
+            match target {
+                SelectionTarget::Clipboard => clear_data_device_selection(dh, &seat),
+                SelectionTarget::Primary => clear_primary_selection(dh, &seat),
+            }
+            None
+        } else {
+            source
+        };
+
+        seat.user_data()
+            .insert_if_missing_threadsafe(|| Mutex::new(SeatSelections::default()));
+        *seat
+            .user_data()
+            .get::<Mutex<SeatSelections>>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .get_mut(target) = source.as_ref().map(|source| source.mime_types());
+
         if let Some(xwm) = self
             .common
             .xwayland_state
@@ -1,6 +1,10 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use crate::{shell::SessionLock, state::State, utils::prelude::*};
+use crate::{
+    shell::SessionLock,
+    state::State,
+    utils::{prelude::*, recover},
+};
 use smithay::{
     delegate_session_lock,
     output::Output,
@@ -37,6 +41,7 @@ impl SessionLockHandler for State {
         shell.session_lock = Some(SessionLock {
             ext_session_lock,
             surfaces: HashMap::new(),
+            fallback: None,
         });
 
         for output in shell.outputs() {
@@ -54,6 +59,7 @@ impl SessionLockHandler for State {
     }
 
     fn new_surface(&mut self, lock_surface: LockSurface, wl_output: WlOutput) {
+        let _client = recover::handling_client(lock_surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         if let Some(session_lock) = &mut shell.session_lock {
             if let Some(output) = Output::from_resource(&wl_output) {
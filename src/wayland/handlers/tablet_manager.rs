@@ -2,13 +2,29 @@
 
 use crate::state::State;
 use smithay::{
-    backend::input::TabletToolDescriptor, delegate_tablet_manager,
-    input::pointer::CursorImageStatus, wayland::tablet_manager::TabletSeatHandler,
+    backend::input::TabletToolDescriptor,
+    delegate_tablet_manager,
+    input::pointer::CursorImageStatus,
+    wayland::tablet_manager::{TabletSeatHandler, TabletSeatTrait},
 };
+use std::sync::Mutex;
 
 impl TabletSeatHandler for State {
-    fn tablet_tool_image(&mut self, _tool: &TabletToolDescriptor, _image: CursorImageStatus) {
-        // TODO display cursor for each tablet tool
+    fn tablet_tool_image(&mut self, tool: &TabletToolDescriptor, image: CursorImageStatus) {
+        // Tools move the pointer alongside them, so the tool image replaces the cursor
+        // of the seat the tool belongs to, until the pointer focus sets a new one.
+        let shell = self.common.shell.read().unwrap();
+        let Some(seat) = shell
+            .seats
+            .iter()
+            .find(|seat| seat.tablet_seat().get_tool(tool).is_some())
+        else {
+            return;
+        };
+
+        if let Some(cursor_status) = seat.user_data().get::<Mutex<CursorImageStatus>>() {
+            *cursor_status.lock().unwrap() = image;
+        }
     }
 }
 
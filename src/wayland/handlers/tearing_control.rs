@@ -0,0 +1,5 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{state::State, wayland::protocols::tearing_control::delegate_tearing_control};
+
+delegate_tearing_control!(State);
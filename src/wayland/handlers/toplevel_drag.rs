@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{
+    shell::grabs::{MoveGrab, ReleaseMode, SeatMoveGrabState},
+    state::State,
+    wayland::protocols::toplevel_drag::{
+        delegate_toplevel_drag, ToplevelDragHandler, ToplevelDragState,
+    },
+};
+use smithay::{
+    input::Seat,
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel::XdgToplevel,
+        wayland_server::protocol::{wl_data_source::WlDataSource, wl_surface::WlSurface},
+    },
+    utils::{Logical, Point},
+    wayland::seat::WaylandFocus,
+};
+use std::sync::Mutex;
+
+/// Drag-and-drop operation of a seat and the toplevel moved alongside it
+#[derive(Default)]
+pub struct SeatToplevelDrag {
+    source: Option<WlDataSource>,
+    attached: Option<(WlSurface, Point<i32, Logical>)>,
+    grab: Option<MoveGrab>,
+}
+
+impl ToplevelDragHandler for State {
+    fn toplevel_drag_state(&mut self) -> &mut ToplevelDragState {
+        &mut self.common.toplevel_drag_state
+    }
+
+    fn toplevel_attached(
+        &mut self,
+        source: &WlDataSource,
+        toplevel: &XdgToplevel,
+        offset: Point<i32, Logical>,
+    ) {
+        let Some(surface) = self
+            .common
+            .xdg_shell_state
+            .toplevel_surfaces()
+            .iter()
+            .find(|surface| surface.xdg_toplevel() == toplevel)
+            .map(|surface| surface.wl_surface().clone())
+        else {
+            return;
+        };
+
+        let seats = self
+            .common
+            .shell
+            .read()
+            .unwrap()
+            .seats
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for seat in seats {
+            let Some(drag) = seat.user_data().get::<Mutex<SeatToplevelDrag>>() else {
+                continue;
+            };
+            let mut drag = drag.lock().unwrap();
+            if drag.source.as_ref() == Some(source) {
+                drag.attached = Some((surface, offset));
+                std::mem::drop(drag);
+                start_toplevel_drag(self, &seat);
+                return;
+            }
+        }
+    }
+}
+
+/// Called when a drag-and-drop operation started on `seat`
+pub fn dnd_started(state: &mut State, seat: &Seat<State>, source: Option<WlDataSource>) {
+    let attached = source.as_ref().and_then(|source| {
+        let (toplevel, offset) = state
+            .common
+            .toplevel_drag_state
+            .toplevel_for_source(source)?;
+        let surface = state
+            .common
+            .xdg_shell_state
+            .toplevel_surfaces()
+            .iter()
+            .find(|surface| surface.xdg_toplevel() == &toplevel)?
+            .wl_surface()
+            .clone();
+        Some((surface, offset))
+    });
+
+    let user_data = seat.user_data();
+    user_data.insert_if_missing_threadsafe(|| Mutex::new(SeatToplevelDrag::default()));
+    *user_data
+        .get::<Mutex<SeatToplevelDrag>>()
+        .unwrap()
+        .lock()
+        .unwrap() = SeatToplevelDrag {
+        source,
+        attached,
+        grab: None,
+    };
+
+    start_toplevel_drag(state, seat);
+}
+
+/// Called when the drag-and-drop operation of `seat` ended,
+/// places an attached toplevel where it was dropped.
+pub fn dnd_ended(seat: &Seat<State>) {
+    if let Some(drag) = seat.user_data().get::<Mutex<SeatToplevelDrag>>() {
+        // dropping the grab maps the window again
+        *drag.lock().unwrap() = SeatToplevelDrag::default();
+    }
+}
+
+/// Starts moving the attached toplevel of the current drag-and-drop operation, if it is mapped.
+///
+/// Toplevels are often attached before being mapped, so this is also called after mapping new windows.
+pub fn start_toplevel_drag(state: &mut State, seat: &Seat<State>) {
+    let Some(drag) = seat.user_data().get::<Mutex<SeatToplevelDrag>>() else {
+        return;
+    };
+    let mut drag = drag.lock().unwrap();
+    if drag.grab.is_some() {
+        return;
+    }
+    let Some((surface, offset)) = drag.attached.clone() else {
+        return;
+    };
+
+    let mut shell = state.common.shell.write().unwrap();
+    let Some(mapped) = shell.element_for_surface(&surface).cloned() else {
+        return;
+    };
+    if let Some((mut grab, _)) = shell.move_request(
+        &surface,
+        seat,
+        None,
+        ReleaseMode::NoMouseButtons,
+        mapped.is_stack(),
+        &state.common.config,
+        &state.common.event_loop_handle,
+        &state.common.xdg_activation_state,
+        false,
+    ) {
+        std::mem::drop(shell);
+        let window_offset = mapped
+            .windows()
+            .find(|(window, _)| window.wl_surface().as_deref() == Some(&surface))
+            .map(|(_, loc)| loc)
+            .unwrap_or_default();
+        grab.set_window_offset(-(offset + window_offset));
+        drag.grab = Some(grab);
+    }
+}
+
+/// Moves the attached toplevel of an ongoing drag-and-drop operation with the pointer
+pub fn update_toplevel_drag(state: &mut State, seat: &Seat<State>, location: Point<f64, Logical>) {
+    let Some(drag) = seat.user_data().get::<Mutex<SeatToplevelDrag>>() else {
+        return;
+    };
+    if let Some(grab) = drag.lock().unwrap().grab.as_mut() {
+        grab.update_location(state, location);
+    }
+}
+
+/// Stops moving `surface` without mapping it again, e.g. because the client unmapped it.
+///
+/// Returns `false` if `surface` wasn't attached to a drag-and-drop operation of `seat`.
+pub fn cancel_toplevel_drag(seat: &Seat<State>, surface: &WlSurface) -> bool {
+    let Some(drag) = seat.user_data().get::<Mutex<SeatToplevelDrag>>() else {
+        return false;
+    };
+    let mut drag = drag.lock().unwrap();
+    if drag
+        .attached
+        .as_ref()
+        .map_or(true, |(attached, _)| attached != surface)
+    {
+        return false;
+    }
+
+    drag.attached = None;
+    if let Some(grab) = drag.grab.take() {
+        seat.user_data()
+            .get::<SeatMoveGrabState>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .take();
+        std::mem::drop(grab);
+    }
+    true
+}
+
+delegate_toplevel_drag!(State);
@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{
+    state::State,
+    wayland::protocols::toplevel_icon::{
+        delegate_toplevel_icon, set_toplevel_icon, ToplevelIcon, ToplevelIconHandler,
+    },
+};
+use smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::XdgToplevel;
+use std::sync::Arc;
+
+impl ToplevelIconHandler for State {
+    fn set_icon(&mut self, toplevel: &XdgToplevel, icon: Option<Arc<ToplevelIcon>>) {
+        let Some(surface) = self
+            .common
+            .xdg_shell_state
+            .toplevel_surfaces()
+            .iter()
+            .find(|surface| surface.xdg_toplevel() == toplevel)
+            .map(|surface| surface.wl_surface().clone())
+        else {
+            return;
+        };
+
+        // headers pick up the new icon on their next refresh
+        set_toplevel_icon(&surface, icon);
+    }
+}
+
+delegate_toplevel_icon!(State);
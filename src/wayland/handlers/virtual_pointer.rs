@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{
+    input::NextDown,
+    state::State,
+    utils::prelude::*,
+    wayland::protocols::virtual_pointer::{
+        delegate_virtual_pointer, VirtualPointerEvent, VirtualPointerHandler,
+        VirtualPointerUserData,
+    },
+};
+use smithay::{
+    input::pointer::ButtonEvent,
+    utils::{Point, Rectangle, SERIAL_COUNTER},
+};
+
+impl VirtualPointerHandler for State {
+    fn virtual_pointer_event(
+        &mut self,
+        pointer: &VirtualPointerUserData<Self>,
+        event: VirtualPointerEvent,
+    ) {
+        let seat = pointer.seat().cloned().unwrap_or_else(|| {
+            self.common
+                .shell
+                .read()
+                .unwrap()
+                .seats
+                .last_active()
+                .clone()
+        });
+        let Some(ptr) = seat.get_pointer() else {
+            return;
+        };
+        self.common.idle_notifier_state.notify_activity(&seat);
+        self.notify_activity();
+
+        let (time, mut position) = match event {
+            VirtualPointerEvent::Motion { time, delta } => {
+                // virtual pointers are not accelerated
+                self.pointer_motion(&seat, delta, delta, time as u64 * 1000);
+                return;
+            }
+            VirtualPointerEvent::MotionAbsolute {
+                time,
+                position,
+                extent,
+            } => {
+                // without an output, coordinates are relative to the whole layout
+                let Some(region) = pointer.output().map(|o| o.geometry()).or_else(|| {
+                    self.common
+                        .shell
+                        .read()
+                        .unwrap()
+                        .outputs()
+                        .map(|o| o.geometry())
+                        .reduce(|acc, geo| acc.merge(geo))
+                }) else {
+                    return;
+                };
+                let region = region.to_f64();
+                let position = Point::<f64, Global>::from((
+                    position.x / extent.x * region.size.w,
+                    position.y / extent.y * region.size.h,
+                ));
+                (time, region.loc + position)
+            }
+            VirtualPointerEvent::Button {
+                time,
+                button,
+                state,
+            } => {
+                ptr.button(
+                    self,
+                    &ButtonEvent {
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time,
+                        button,
+                        state,
+                    },
+                );
+                ptr.frame(self);
+                return;
+            }
+            VirtualPointerEvent::Axis(frame) => {
+                ptr.axis(self, frame);
+                ptr.frame(self);
+                return;
+            }
+        };
+
+        let output = self
+            .common
+            .shell
+            .read()
+            .unwrap()
+            .outputs()
+            .find(|output| output.geometry().to_f64().contains(position))
+            .cloned()
+            .unwrap_or_else(|| seat.active_output());
+        let output_geometry: Rectangle<f64, Global> = output.geometry().to_f64();
+        position.x = position.x.clamp(
+            output_geometry.loc.x,
+            (output_geometry.loc.x + output_geometry.size.w).next_lower(),
+        );
+        position.y = position.y.clamp(
+            output_geometry.loc.y,
+            (output_geometry.loc.y + output_geometry.size.h).next_lower(),
+        );
+
+        if output != seat.active_output() {
+            seat.set_active_output(&output);
+        }
+        self.pointer_motion_absolute(&seat, &output, position, time);
+    }
+}
+
+delegate_virtual_pointer!(State);
@@ -1,5 +1,10 @@
-use crate::{shell::ActivationKey, state::ClientState, utils::prelude::*};
+use crate::{
+    shell::ActivationKey,
+    state::ClientState,
+    utils::{prelude::*, recover},
+};
 use crate::{state::State, wayland::protocols::workspace::WorkspaceHandle};
+use cosmic_comp_config::ActivationPolicy;
 use cosmic_protocols::workspace::v1::server::zcosmic_workspace_handle_v1::State as WState;
 use smithay::{
     delegate_xdg_activation,
@@ -17,6 +22,17 @@ pub enum ActivationContext {
     Workspace(WorkspaceHandle),
 }
 
+impl ActivationContext {
+    /// Restricts the context by the configured policy, `None` if the request is ignored
+    pub fn with_policy(self, policy: ActivationPolicy) -> Option<ActivationContext> {
+        match policy {
+            ActivationPolicy::Allow => Some(self),
+            ActivationPolicy::MarkUrgent => Some(ActivationContext::UrgentOnly),
+            ActivationPolicy::Deny => None,
+        }
+    }
+}
+
 impl XdgActivationHandler for State {
     fn activation_state(&mut self) -> &mut XdgActivationState {
         &mut self.common.xdg_activation_state
@@ -106,71 +122,87 @@ impl XdgActivationHandler for State {
         token_data: XdgActivationTokenData,
         surface: WlSurface,
     ) {
-        if let Some(context) = token_data.user_data.get::<ActivationContext>() {
-            let mut shell = self.common.shell.write().unwrap();
-            if let Some(element) = shell.element_for_surface(&surface).cloned() {
-                match context {
-                    ActivationContext::UrgentOnly => {
-                        if let Some((workspace, _output)) = shell.workspace_for_surface(&surface) {
-                            let mut workspace_guard = self.common.workspace_state.update();
-                            workspace_guard.add_workspace_state(&workspace, WState::Urgent);
-                        }
-                    }
-                    ActivationContext::Workspace(workspace) => {
-                        let seat = shell.seats.last_active().clone();
-                        let current_output = seat.active_output();
-
-                        if element.is_minimized() {
-                            shell.unminimize_request(&element, &seat);
-                        }
-
-                        let current_workspace = shell.active_space_mut(&current_output);
-
-                        let in_current_workspace = current_workspace
-                            .floating_layer
-                            .mapped()
-                            .any(|m| m == &element);
-
-                        if in_current_workspace {
-                            current_workspace
-                                .floating_layer
-                                .space
-                                .raise_element(&element, true);
-                        }
-
-                        if element.is_stack() {
-                            if let Some((window, _)) = element.windows().find(|(window, _)| {
-                                let mut found = false;
-                                window.with_surfaces(|wl_surface, _| {
-                                    if wl_surface == &surface {
-                                        found = true;
-                                    }
-                                });
-                                found
-                            }) {
-                                element.set_active(&window);
+        let _client = recover::handling_client(&surface);
+        if let Some(context) = token_data
+            .user_data
+            .get::<ActivationContext>()
+            .and_then(|context| context.with_policy(self.common.config.cosmic_conf.activation))
+        {
+            if !self.activate_surface(&surface, &context) {
+                self.common
+                    .shell
+                    .write()
+                    .unwrap()
+                    .pending_activations
+                    .insert(ActivationKey::Wayland(surface), context);
+            }
+        }
+    }
+}
+
+impl State {
+    /// Activates the window of `surface` as far as `context` allows, `false` if it isn't mapped
+    pub fn activate_surface(&mut self, surface: &WlSurface, context: &ActivationContext) -> bool {
+        let mut shell = self.common.shell.write().unwrap();
+        let Some(element) = shell.element_for_surface(surface).cloned() else {
+            return false;
+        };
+        match context {
+            ActivationContext::UrgentOnly => {
+                if let Some((workspace, _output)) = shell.workspace_for_surface(surface) {
+                    let mut workspace_guard = self.common.workspace_state.update();
+                    workspace_guard.add_workspace_state(&workspace, WState::Urgent);
+                }
+            }
+            ActivationContext::Workspace(workspace) => {
+                let seat = shell.seats.last_active().clone();
+                let current_output = seat.active_output();
+
+                if element.is_minimized() {
+                    shell.unminimize_request(&element, &seat);
+                }
+
+                let current_workspace = shell.active_space_mut(&current_output);
+
+                let in_current_workspace = current_workspace
+                    .floating_layer
+                    .mapped()
+                    .any(|m| m == &element);
+
+                if in_current_workspace {
+                    current_workspace
+                        .floating_layer
+                        .space
+                        .raise_element(&element, true);
+                }
+
+                if element.is_stack() {
+                    if let Some((window, _)) = element.windows().find(|(window, _)| {
+                        let mut found = false;
+                        window.with_surfaces(|wl_surface, _| {
+                            if wl_surface == surface {
+                                found = true;
                             }
-                        }
-
-                        if workspace == &current_workspace.handle || in_current_workspace {
-                            let target = element.into();
-
-                            std::mem::drop(shell);
-                            Shell::set_focus(self, Some(&target), &seat, None);
-                        } else if let Some(w) = shell.space_for(&element).map(|w| w.handle.clone())
-                        {
-                            shell.append_focus_stack(&element, &seat);
-                            let mut workspace_guard = self.common.workspace_state.update();
-                            workspace_guard.add_workspace_state(&w, WState::Urgent);
-                        }
+                        });
+                        found
+                    }) {
+                        element.set_active(&window);
                     }
                 }
-            } else {
-                shell
-                    .pending_activations
-                    .insert(ActivationKey::Wayland(surface), context.clone());
+
+                if workspace == &current_workspace.handle || in_current_workspace {
+                    let target = element.into();
+
+                    std::mem::drop(shell);
+                    Shell::set_focus(self, Some(&target), &seat, None);
+                } else if let Some(w) = shell.space_for(&element).map(|w| w.handle.clone()) {
+                    shell.append_focus_stack(&element, &seat);
+                    let mut workspace_guard = self.common.workspace_state.update();
+                    workspace_guard.add_workspace_state(&w, WState::Urgent);
+                }
             }
         }
+        true
     }
 }
 
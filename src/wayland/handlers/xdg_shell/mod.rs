@@ -2,7 +2,7 @@
 
 use crate::{
     shell::{element::CosmicWindow, grabs::ReleaseMode, CosmicMapped, CosmicSurface, ManagedLayer},
-    utils::prelude::*,
+    utils::{prelude::*, recover},
     wayland::protocols::toplevel_info::{toplevel_enter_output, toplevel_enter_workspace},
 };
 use smithay::{
@@ -42,6 +42,7 @@ impl XdgShellHandler for State {
     }
 
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         let seat = shell.seats.last_active().clone();
         let window = CosmicSurface::from(surface);
@@ -50,6 +51,7 @@ impl XdgShellHandler for State {
     }
 
     fn new_popup(&mut self, surface: PopupSurface, positioner: PositionerState) {
+        let _client = recover::handling_client(surface.wl_surface());
         surface.with_pending_state(|state| {
             state.geometry = positioner.get_geometry();
             state.positioner = positioner;
@@ -73,6 +75,7 @@ impl XdgShellHandler for State {
     }
 
     fn grab(&mut self, surface: PopupSurface, seat: WlSeat, serial: Serial) {
+        let _client = recover::handling_client(surface.wl_surface());
         let seat = Seat::from_resource(&seat).unwrap();
         let kind = PopupKind::Xdg(surface);
         let maybe_root = find_popup_root_surface(&kind).ok().and_then(|root| {
@@ -129,6 +132,7 @@ impl XdgShellHandler for State {
         positioner: PositionerState,
         token: u32,
     ) {
+        let _client = recover::handling_client(surface.wl_surface());
         surface.with_pending_state(|state| {
             let geometry = positioner.get_geometry();
             state.geometry = geometry;
@@ -150,6 +154,7 @@ impl XdgShellHandler for State {
     }
 
     fn move_request(&mut self, surface: ToplevelSurface, seat: WlSeat, serial: Serial) {
+        let _client = recover::handling_client(surface.wl_surface());
         let seat = Seat::from_resource(&seat).unwrap();
         let mut shell = self.common.shell.write().unwrap();
         if let Some((grab, focus)) = shell.move_request(
@@ -181,6 +186,7 @@ impl XdgShellHandler for State {
         serial: Serial,
         edges: xdg_toplevel::ResizeEdge,
     ) {
+        let _client = recover::handling_client(surface.wl_surface());
         let seat = Seat::from_resource(&seat).unwrap();
         let mut shell = self.common.shell.write().unwrap();
         if let Some((grab, focus)) =
@@ -198,6 +204,7 @@ impl XdgShellHandler for State {
     }
 
     fn minimize_request(&mut self, surface: ToplevelSurface) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         if let Some(mapped) = shell.element_for_surface(surface.wl_surface()).cloned() {
             if !mapped.is_stack()
@@ -209,6 +216,7 @@ impl XdgShellHandler for State {
     }
 
     fn maximize_request(&mut self, surface: ToplevelSurface) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         if let Some(mapped) = shell.element_for_surface(surface.wl_surface()).cloned() {
             let seat = shell.seats.last_active().clone();
@@ -217,6 +225,7 @@ impl XdgShellHandler for State {
     }
 
     fn unmaximize_request(&mut self, surface: ToplevelSurface) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         if let Some(mapped) = shell.element_for_surface(surface.wl_surface()).cloned() {
             shell.unmaximize_request(&mapped);
@@ -224,6 +233,7 @@ impl XdgShellHandler for State {
     }
 
     fn fullscreen_request(&mut self, surface: ToplevelSurface, output: Option<WlOutput>) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         let seat = shell.seats.last_active().clone();
         let active_output = seat.active_output();
@@ -344,6 +354,7 @@ impl XdgShellHandler for State {
     }
 
     fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
+        let _client = recover::handling_client(surface.wl_surface());
         let mut shell = self.common.shell.write().unwrap();
         if let Some(mapped) = shell.element_for_surface(surface.wl_surface()).cloned() {
             if let Some(workspace) = shell.space_for_mut(&mapped) {
@@ -372,8 +383,12 @@ impl XdgShellHandler for State {
     }
 
     fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        let _client = recover::handling_client(surface.wl_surface());
         let (output, clients) = {
             let mut shell = self.common.shell.write().unwrap();
+            if let Some(ipc) = self.common.ipc.as_mut() {
+                ipc.window_closed(&shell, surface.wl_surface());
+            }
             let seat = shell.seats.last_active().clone();
             shell.unmap_surface(
                 surface.wl_surface(),
@@ -412,6 +427,7 @@ impl XdgShellHandler for State {
         serial: Serial,
         mut location: Point<i32, Logical>,
     ) {
+        let _client = recover::handling_client(surface.wl_surface());
         let seat = Seat::from_resource(&seat).unwrap();
         location -= with_states(surface.wl_surface(), |states| {
             states
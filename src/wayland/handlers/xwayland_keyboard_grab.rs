@@ -2,9 +2,58 @@
 
 use crate::{shell::focus::target::KeyboardFocusTarget, state::State};
 use smithay::{
-    delegate_xwayland_keyboard_grab, reexports::wayland_server::protocol::wl_surface::WlSurface,
-    wayland::xwayland_keyboard_grab::XWaylandKeyboardGrabHandler,
+    delegate_xwayland_keyboard_grab,
+    input::Seat,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{IsAlive, SERIAL_COUNTER},
+    wayland::{
+        seat::WaylandFocus,
+        xwayland_keyboard_grab::{XWaylandKeyboardGrab, XWaylandKeyboardGrabHandler},
+    },
 };
+use std::sync::Mutex;
+
+/// Surface of the X11 window grabbing the keyboard of a seat
+#[derive(Default)]
+pub struct SeatXWaylandGrab(Mutex<Option<WlSurface>>);
+
+/// Whether an X11 window actively grabs the keyboard of `seat`,
+/// receiving all keys instead of the compositor shortcuts
+pub fn xwayland_keyboard_grabbed(seat: &Seat<State>) -> bool {
+    let keyboard = seat.get_keyboard().unwrap();
+    if !keyboard.is_grabbed() {
+        return false;
+    }
+    let grab = seat
+        .user_data()
+        .get::<SeatXWaylandGrab>()
+        .unwrap()
+        .0
+        .lock()
+        .unwrap();
+    grab.as_ref()
+        .filter(|surface| surface.alive())
+        .is_some_and(|surface| {
+            keyboard
+                .current_focus()
+                .is_some_and(|focus| focus.wl_surface().as_deref() == Some(surface))
+        })
+}
+
+/// Ends the keyboard grab of an X11 window on `seat`, if there is one
+pub fn release_xwayland_keyboard_grab(state: &mut State, seat: &Seat<State>) {
+    if !xwayland_keyboard_grabbed(seat) {
+        return;
+    }
+    seat.user_data()
+        .get::<SeatXWaylandGrab>()
+        .unwrap()
+        .0
+        .lock()
+        .unwrap()
+        .take();
+    seat.get_keyboard().unwrap().unset_grab(state);
+}
 
 impl XWaylandKeyboardGrabHandler for State {
     fn keyboard_focus_for_xsurface(&self, surface: &WlSurface) -> Option<KeyboardFocusTarget> {
@@ -18,5 +67,17 @@ impl XWaylandKeyboardGrabHandler for State {
             .find_map(|x| x.element_for_surface(surface).cloned())?;
         Some(KeyboardFocusTarget::Element(element))
     }
+
+    fn grab(&mut self, surface: WlSurface, seat: Seat<Self>, grab: XWaylandKeyboardGrab<Self>) {
+        *seat
+            .user_data()
+            .get::<SeatXWaylandGrab>()
+            .unwrap()
+            .0
+            .lock()
+            .unwrap() = Some(surface);
+        let keyboard = seat.get_keyboard().unwrap();
+        keyboard.set_grab(self, grab, SERIAL_COUNTER.next_serial());
+    }
 }
 delegate_xwayland_keyboard_grab!(State);
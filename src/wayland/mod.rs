@@ -1,4 +1,5 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 pub mod handlers;
+pub mod permissions;
 pub mod protocols;
@@ -0,0 +1,159 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Which clients may use privileged protocols, that can read or control the whole session.
+//!
+//! Clients started by cosmic-session can use all of them. So can all other clients outside of
+//! a sandbox, unless `protocol_permissions.restrict` is set. Beyond that, clients matching an
+//! entry of `protocol_permissions.allow` can use the protocols listed by it.
+//! Permissions are decided once, when a client connects.
+//!
+//! The IPC socket and the `org.cosmic.Comp` D-Bus service follow the same policy,
+//! as `CompositorControl`. Their clients are identified by the process id only, so only
+//! Flatpak sandboxes are recognized.
+
+use std::{
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
+
+use cosmic_comp_config::{PrivilegedProtocol, ProtocolPermission};
+use smithay::reexports::wayland_server::Client;
+use tracing::debug;
+
+use crate::state::{ClientState, State};
+
+/// What a connecting client can be recognized by
+#[derive(Debug, Default)]
+struct ClientIdentity {
+    executable: Option<PathBuf>,
+    systemd_unit: Option<String>,
+    app_id: Option<String>,
+}
+
+impl ClientIdentity {
+    fn of(stream: &UnixStream, app_id: Option<String>) -> ClientIdentity {
+        match peer_pid(stream) {
+            Some(pid) => ClientIdentity::of_pid(pid, app_id),
+            None => ClientIdentity {
+                app_id,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn of_pid(pid: i32, app_id: Option<String>) -> ClientIdentity {
+        ClientIdentity {
+            executable: std::fs::read_link(format!("/proc/{}/exe", pid)).ok(),
+            systemd_unit: systemd_unit(pid),
+            app_id,
+        }
+    }
+
+    fn matches(&self, permission: &ProtocolPermission) -> bool {
+        fn matches<T: PartialEq>(wanted: &Option<T>, actual: &Option<T>) -> bool {
+            wanted.is_none() || wanted == actual
+        }
+
+        (permission.executable.is_some()
+            || permission.systemd_unit.is_some()
+            || permission.app_id.is_some())
+            && matches(&permission.executable, &self.executable)
+            && matches(&permission.systemd_unit, &self.systemd_unit)
+            && matches(&permission.app_id, &self.app_id)
+    }
+}
+
+/// Process id of the other end of `stream`
+pub fn peer_pid(stream: &UnixStream) -> Option<i32> {
+    rustix::net::sockopt::get_socket_peercred(stream)
+        .ok()
+        .map(|credentials| credentials.pid.as_raw_nonzero().get())
+}
+
+/// Innermost systemd service or scope the process `pid` runs in
+fn systemd_unit(pid: i32) -> Option<String> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    // the unified hierarchy, like `0::/user.slice/user-1000.slice/user@1000.service/app.slice/obs.service`
+    let path = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+    path.rsplit('/')
+        .find(|unit| unit.ends_with(".service") || unit.ends_with(".scope"))
+        .map(String::from)
+}
+
+/// Whether the process `pid` runs in a Flatpak sandbox, the only one recognized by its process
+fn is_sandboxed(pid: i32) -> bool {
+    // bind mounted into the root of every Flatpak sandbox
+    Path::new(&format!("/proc/{}/root/.flatpak-info", pid)).exists()
+}
+
+/// Whether `client` may use `protocol`, used as filter of its globals
+pub fn client_may_use(client: &Client, protocol: PrivilegedProtocol) -> bool {
+    client
+        .get_data::<ClientState>()
+        .map_or(false, |client_state| {
+            client_state.privileged_protocols.contains(&protocol)
+        })
+}
+
+impl State {
+    /// Privileged protocols the client connected by `stream` may use.
+    ///
+    /// `trusted` clients, which aren't sandboxed, get all of them, unless the configuration
+    /// restricts them. `app_id` is taken from the security context of sandboxed clients.
+    pub fn permitted_protocols(
+        &self,
+        stream: &UnixStream,
+        app_id: Option<String>,
+        trusted: bool,
+    ) -> Vec<PrivilegedProtocol> {
+        let config = &self.common.config.cosmic_conf.protocol_permissions;
+        if trusted && !config.restrict {
+            return PrivilegedProtocol::ALL.to_vec();
+        }
+        if config.allow.is_empty() {
+            return Vec::new();
+        }
+
+        let identity = ClientIdentity::of(stream, app_id);
+        let mut protocols = Vec::new();
+        for permission in config
+            .allow
+            .iter()
+            .filter(|permission| identity.matches(permission))
+        {
+            for protocol in &permission.protocols {
+                if !protocols.contains(protocol) {
+                    protocols.push(*protocol);
+                }
+            }
+        }
+        debug!(?identity, ?protocols, "Privileged protocols of new client");
+        protocols
+    }
+
+    /// Whether the process `pid` may control the compositor through the IPC socket
+    /// or the `org.cosmic.Comp` D-Bus service.
+    ///
+    /// Without `restrict` every process outside of a sandbox may, like unsandboxed wayland
+    /// clients get all privileged protocols. Sandboxes can give access to both, so sandboxed
+    /// processes need to be allowed `CompositorControl`.
+    pub fn process_may_control(&self, pid: Option<i32>) -> bool {
+        let config = &self.common.config.cosmic_conf.protocol_permissions;
+        if !config.restrict && !pid.is_some_and(is_sandboxed) {
+            return true;
+        }
+        let Some(pid) = pid else {
+            return false;
+        };
+
+        let identity = ClientIdentity::of_pid(pid, None);
+        let allowed = config.allow.iter().any(|permission| {
+            identity.matches(permission)
+                && permission
+                    .protocols
+                    .contains(&PrivilegedProtocol::CompositorControl)
+        });
+        debug!(?identity, allowed, "Compositor control of process");
+        allowed
+    }
+}
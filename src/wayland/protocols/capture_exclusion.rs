@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+pub use generated::{zcosmic_capture_exclusion_manager_v1, zcosmic_capture_exclusion_v1};
+
+#[allow(non_snake_case, non_upper_case_globals, non_camel_case_types)]
+mod generated {
+    use smithay::reexports::wayland_server::{self, protocol::*};
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        use wayland_backend;
+        wayland_scanner::generate_interfaces!(
+            "resources/protocols/cosmic-capture-exclusion-unstable-v1.xml"
+        );
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!(
+        "resources/protocols/cosmic-capture-exclusion-unstable-v1.xml"
+    );
+}
+
+use smithay::{
+    reexports::wayland_server::{
+        backend::GlobalId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch,
+        DisplayHandle, GlobalDispatch, New, Resource, Weak,
+    },
+    wayland::compositor::with_states,
+};
+use std::sync::Mutex;
+use zcosmic_capture_exclusion_manager_v1::ZcosmicCaptureExclusionManagerV1;
+use zcosmic_capture_exclusion_v1::ZcosmicCaptureExclusionV1;
+
+/// Global state of the `zcosmic_capture_exclusion_manager_v1` protocol
+#[derive(Debug)]
+pub struct CaptureExclusionState {
+    global: GlobalId,
+}
+
+/// Capture exclusion object of a surface, stored in its data map
+#[derive(Debug, Default)]
+struct CaptureExclusionSurfaceData(Option<Weak<ZcosmicCaptureExclusionV1>>);
+
+/// Returns `true` if the client excluded the surface from screen captures
+pub fn surface_is_excluded_from_capture(surface: &WlSurface) -> bool {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<Mutex<CaptureExclusionSurfaceData>>()
+            .is_some_and(|data| {
+                data.lock()
+                    .unwrap()
+                    .0
+                    .as_ref()
+                    .is_some_and(|exclusion| exclusion.upgrade().is_ok())
+            })
+    })
+}
+
+impl CaptureExclusionState {
+    pub fn new<D>(dh: &DisplayHandle) -> CaptureExclusionState
+    where
+        D: GlobalDispatch<ZcosmicCaptureExclusionManagerV1, ()>
+            + Dispatch<ZcosmicCaptureExclusionManagerV1, ()>
+            + Dispatch<ZcosmicCaptureExclusionV1, WlSurface>
+            + 'static,
+    {
+        let global = dh.create_global::<D, ZcosmicCaptureExclusionManagerV1, _>(1, ());
+        CaptureExclusionState { global }
+    }
+
+    pub fn global_id(&self) -> GlobalId {
+        self.global.clone()
+    }
+}
+
+impl<D> GlobalDispatch<ZcosmicCaptureExclusionManagerV1, (), D> for CaptureExclusionState
+where
+    D: GlobalDispatch<ZcosmicCaptureExclusionManagerV1, ()>
+        + Dispatch<ZcosmicCaptureExclusionManagerV1, ()>
+        + Dispatch<ZcosmicCaptureExclusionV1, WlSurface>
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZcosmicCaptureExclusionManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<ZcosmicCaptureExclusionManagerV1, (), D> for CaptureExclusionState
+where
+    D: GlobalDispatch<ZcosmicCaptureExclusionManagerV1, ()>
+        + Dispatch<ZcosmicCaptureExclusionManagerV1, ()>
+        + Dispatch<ZcosmicCaptureExclusionV1, WlSurface>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        obj: &ZcosmicCaptureExclusionManagerV1,
+        request: zcosmic_capture_exclusion_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zcosmic_capture_exclusion_manager_v1::Request::Exclude { id, surface } => {
+                if surface_is_excluded_from_capture(&surface) {
+                    obj.post_error(
+                        zcosmic_capture_exclusion_manager_v1::Error::AlreadyExcluded,
+                        "the surface is already excluded from captures",
+                    );
+                    return;
+                }
+
+                let exclusion = data_init.init(id, surface.clone());
+                with_states(&surface, |states| {
+                    states.data_map.insert_if_missing_threadsafe(|| {
+                        Mutex::new(CaptureExclusionSurfaceData::default())
+                    });
+                    states
+                        .data_map
+                        .get::<Mutex<CaptureExclusionSurfaceData>>()
+                        .unwrap()
+                        .lock()
+                        .unwrap()
+                        .0 = Some(exclusion.downgrade());
+                });
+            }
+            zcosmic_capture_exclusion_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZcosmicCaptureExclusionV1, WlSurface, D> for CaptureExclusionState
+where
+    D: GlobalDispatch<ZcosmicCaptureExclusionManagerV1, ()>
+        + Dispatch<ZcosmicCaptureExclusionManagerV1, ()>
+        + Dispatch<ZcosmicCaptureExclusionV1, WlSurface>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _obj: &ZcosmicCaptureExclusionV1,
+        request: zcosmic_capture_exclusion_v1::Request,
+        surface: &WlSurface,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zcosmic_capture_exclusion_v1::Request::Destroy => {
+                if surface.is_alive() {
+                    with_states(surface, |states| {
+                        if let Some(data) =
+                            states.data_map.get::<Mutex<CaptureExclusionSurfaceData>>()
+                        {
+                            data.lock().unwrap().0 = None;
+                        }
+                    });
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+macro_rules! delegate_capture_exclusion {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocols::capture_exclusion::zcosmic_capture_exclusion_manager_v1::ZcosmicCaptureExclusionManagerV1: ()
+        ] => $crate::wayland::protocols::capture_exclusion::CaptureExclusionState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocols::capture_exclusion::zcosmic_capture_exclusion_manager_v1::ZcosmicCaptureExclusionManagerV1: ()
+        ] => $crate::wayland::protocols::capture_exclusion::CaptureExclusionState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocols::capture_exclusion::zcosmic_capture_exclusion_v1::ZcosmicCaptureExclusionV1: smithay::reexports::wayland_server::protocol::wl_surface::WlSurface
+        ] => $crate::wayland::protocols::capture_exclusion::CaptureExclusionState);
+    };
+}
+pub(crate) use delegate_capture_exclusion;
@@ -1,9 +1,16 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
+pub mod capture_exclusion;
 pub mod drm;
 pub mod image_source;
 pub mod output_configuration;
+pub mod output_power;
 pub mod screencopy;
+pub mod secure_input;
+pub mod tearing_control;
+pub mod toplevel_drag;
+pub mod toplevel_icon;
 pub mod toplevel_info;
 pub mod toplevel_management;
+pub mod virtual_pointer;
 pub mod workspace;
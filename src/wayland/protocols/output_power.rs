@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    output::{Output, WeakOutput},
+    reexports::{
+        wayland_protocols_wlr::output_power_management::v1::server::{
+            zwlr_output_power_manager_v1::{self, ZwlrOutputPowerManagerV1},
+            zwlr_output_power_v1::{self, ZwlrOutputPowerV1},
+        },
+        wayland_server::{
+            backend::GlobalId, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
+            Resource,
+        },
+    },
+};
+use std::sync::Mutex;
+
+/// Global state of the `zwlr_output_power_manager_v1` protocol
+#[derive(Debug)]
+pub struct OutputPowerState {
+    global: GlobalId,
+    output_powers: Vec<ZwlrOutputPowerV1>,
+}
+
+pub struct OutputPowerGlobalData {
+    filter: Box<dyn for<'a> Fn(&'a Client) -> bool + Send + Sync>,
+}
+
+#[derive(Debug)]
+pub struct OutputPowerData {
+    output: Option<WeakOutput>,
+    /// Last mode sent to the client
+    mode: Mutex<Option<bool>>,
+}
+
+pub trait OutputPowerHandler {
+    fn output_power_state(&mut self) -> &mut OutputPowerState;
+
+    /// Returns if `output` is turned on, `None` if it can't be turned off.
+    fn get_dpms(&mut self, output: &Output) -> Option<bool>;
+    fn set_dpms(&mut self, output: &Output, on: bool);
+}
+
+impl OutputPowerState {
+    pub fn new<D, F>(dh: &DisplayHandle, client_filter: F) -> OutputPowerState
+    where
+        D: GlobalDispatch<ZwlrOutputPowerManagerV1, OutputPowerGlobalData>
+            + Dispatch<ZwlrOutputPowerManagerV1, ()>
+            + Dispatch<ZwlrOutputPowerV1, OutputPowerData>
+            + OutputPowerHandler
+            + 'static,
+        F: for<'a> Fn(&'a Client) -> bool + Send + Sync + 'static,
+    {
+        let global = dh.create_global::<D, ZwlrOutputPowerManagerV1, _>(
+            1,
+            OutputPowerGlobalData {
+                filter: Box::new(client_filter),
+            },
+        );
+        OutputPowerState {
+            global,
+            output_powers: Vec::new(),
+        }
+    }
+
+    pub fn global_id(&self) -> GlobalId {
+        self.global.clone()
+    }
+
+    /// Notifies clients about outputs, that were turned on or off
+    pub fn refresh<D: OutputPowerHandler>(state: &mut D) {
+        let output_powers = {
+            let power_state = state.output_power_state();
+            power_state.output_powers.retain(|power| power.is_alive());
+            power_state.output_powers.clone()
+        };
+
+        for power in output_powers {
+            let data = power.data::<OutputPowerData>().unwrap();
+            let mode = data
+                .output
+                .as_ref()
+                .and_then(|output| output.upgrade())
+                .and_then(|output| state.get_dpms(&output));
+            match mode {
+                Some(on) => {
+                    let mut last_mode = data.mode.lock().unwrap();
+                    if *last_mode != Some(on) {
+                        *last_mode = Some(on);
+                        power.mode(if on {
+                            zwlr_output_power_v1::Mode::On
+                        } else {
+                            zwlr_output_power_v1::Mode::Off
+                        });
+                    }
+                }
+                None => {
+                    // the object is inert after failing
+                    power.failed();
+                    state
+                        .output_power_state()
+                        .output_powers
+                        .retain(|p| p != &power);
+                }
+            }
+        }
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrOutputPowerManagerV1, OutputPowerGlobalData, D> for OutputPowerState
+where
+    D: GlobalDispatch<ZwlrOutputPowerManagerV1, OutputPowerGlobalData>
+        + Dispatch<ZwlrOutputPowerManagerV1, ()>
+        + Dispatch<ZwlrOutputPowerV1, OutputPowerData>
+        + OutputPowerHandler
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrOutputPowerManagerV1>,
+        _global_data: &OutputPowerGlobalData,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &OutputPowerGlobalData) -> bool {
+        (global_data.filter)(&client)
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputPowerManagerV1, (), D> for OutputPowerState
+where
+    D: GlobalDispatch<ZwlrOutputPowerManagerV1, OutputPowerGlobalData>
+        + Dispatch<ZwlrOutputPowerManagerV1, ()>
+        + Dispatch<ZwlrOutputPowerV1, OutputPowerData>
+        + OutputPowerHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _obj: &ZwlrOutputPowerManagerV1,
+        request: zwlr_output_power_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_output_power_manager_v1::Request::GetOutputPower { id, output } => {
+                let power = data_init.init(
+                    id,
+                    OutputPowerData {
+                        output: Output::from_resource(&output).map(|output| output.downgrade()),
+                        mode: Mutex::new(None),
+                    },
+                );
+                state.output_power_state().output_powers.push(power);
+                OutputPowerState::refresh(state);
+            }
+            zwlr_output_power_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZwlrOutputPowerV1, OutputPowerData, D> for OutputPowerState
+where
+    D: GlobalDispatch<ZwlrOutputPowerManagerV1, OutputPowerGlobalData>
+        + Dispatch<ZwlrOutputPowerManagerV1, ()>
+        + Dispatch<ZwlrOutputPowerV1, OutputPowerData>
+        + OutputPowerHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        obj: &ZwlrOutputPowerV1,
+        request: zwlr_output_power_v1::Request,
+        data: &OutputPowerData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_output_power_v1::Request::SetMode { mode } => {
+                let on = match mode.into_result() {
+                    Ok(zwlr_output_power_v1::Mode::On) => true,
+                    Ok(zwlr_output_power_v1::Mode::Off) => false,
+                    _ => {
+                        obj.post_error(
+                            zwlr_output_power_v1::Error::InvalidMode,
+                            "unknown power mode",
+                        );
+                        return;
+                    }
+                };
+                if let Some(output) = data.output.as_ref().and_then(|output| output.upgrade()) {
+                    state.set_dpms(&output, on);
+                }
+                OutputPowerState::refresh(state);
+            }
+            zwlr_output_power_v1::Request::Destroy => {
+                state
+                    .output_power_state()
+                    .output_powers
+                    .retain(|power| power != obj);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+macro_rules! delegate_output_power {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_power_management::v1::server::zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1: $crate::wayland::protocols::output_power::OutputPowerGlobalData
+        ] => $crate::wayland::protocols::output_power::OutputPowerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_power_management::v1::server::zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1: ()
+        ] => $crate::wayland::protocols::output_power::OutputPowerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::output_power_management::v1::server::zwlr_output_power_v1::ZwlrOutputPowerV1: $crate::wayland::protocols::output_power::OutputPowerData
+        ] => $crate::wayland::protocols::output_power::OutputPowerState);
+    };
+}
+pub(crate) use delegate_output_power;
@@ -67,6 +67,11 @@ impl ScreencopyState {
     pub fn global_id(&self) -> &GlobalId {
         &self.global
     }
+
+    /// Capture sessions of all clients
+    pub fn sessions(&self) -> impl Iterator<Item = &Session> {
+        self.known_sessions.iter()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -171,6 +176,14 @@ impl Session {
         &*self.user_data
     }
 
+    pub fn client(&self) -> Option<Client> {
+        self.obj.client()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.inner.lock().unwrap().stopped
+    }
+
     pub fn stop(self) {
         let mut inner = self.inner.lock().unwrap();
 
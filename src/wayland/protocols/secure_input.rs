@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+pub use generated::{zcosmic_secure_input_manager_v1, zcosmic_secure_input_v1};
+
+#[allow(non_snake_case, non_upper_case_globals, non_camel_case_types)]
+mod generated {
+    use smithay::reexports::wayland_server::{self, protocol::*};
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::protocol::__interfaces::*;
+        use wayland_backend;
+        wayland_scanner::generate_interfaces!(
+            "resources/protocols/cosmic-secure-input-unstable-v1.xml"
+        );
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!(
+        "resources/protocols/cosmic-secure-input-unstable-v1.xml"
+    );
+}
+
+use smithay::{
+    reexports::wayland_server::{
+        backend::GlobalId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch,
+        DisplayHandle, GlobalDispatch, New, Resource, Weak,
+    },
+    wayland::compositor::with_states,
+};
+use std::sync::Mutex;
+use zcosmic_secure_input_manager_v1::ZcosmicSecureInputManagerV1;
+use zcosmic_secure_input_v1::ZcosmicSecureInputV1;
+
+/// Global state of the `zcosmic_secure_input_manager_v1` protocol
+#[derive(Debug)]
+pub struct SecureInputState {
+    global: GlobalId,
+}
+
+/// Secure input object of a surface, stored in its data map
+#[derive(Debug, Default)]
+struct SecureInputSurfaceData(Option<Weak<ZcosmicSecureInputV1>>);
+
+/// Returns `true` if the client requested secure input for the surface
+pub fn surface_has_secure_input(surface: &WlSurface) -> bool {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<Mutex<SecureInputSurfaceData>>()
+            .is_some_and(|data| {
+                data.lock()
+                    .unwrap()
+                    .0
+                    .as_ref()
+                    .is_some_and(|secure_input| secure_input.upgrade().is_ok())
+            })
+    })
+}
+
+impl SecureInputState {
+    pub fn new<D>(dh: &DisplayHandle) -> SecureInputState
+    where
+        D: GlobalDispatch<ZcosmicSecureInputManagerV1, ()>
+            + Dispatch<ZcosmicSecureInputManagerV1, ()>
+            + Dispatch<ZcosmicSecureInputV1, WlSurface>
+            + 'static,
+    {
+        let global = dh.create_global::<D, ZcosmicSecureInputManagerV1, _>(1, ());
+        SecureInputState { global }
+    }
+
+    pub fn global_id(&self) -> GlobalId {
+        self.global.clone()
+    }
+}
+
+impl<D> GlobalDispatch<ZcosmicSecureInputManagerV1, (), D> for SecureInputState
+where
+    D: GlobalDispatch<ZcosmicSecureInputManagerV1, ()>
+        + Dispatch<ZcosmicSecureInputManagerV1, ()>
+        + Dispatch<ZcosmicSecureInputV1, WlSurface>
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZcosmicSecureInputManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<ZcosmicSecureInputManagerV1, (), D> for SecureInputState
+where
+    D: GlobalDispatch<ZcosmicSecureInputManagerV1, ()>
+        + Dispatch<ZcosmicSecureInputManagerV1, ()>
+        + Dispatch<ZcosmicSecureInputV1, WlSurface>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        obj: &ZcosmicSecureInputManagerV1,
+        request: zcosmic_secure_input_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zcosmic_secure_input_manager_v1::Request::GetSecureInput { id, surface } => {
+                if surface_has_secure_input(&surface) {
+                    obj.post_error(
+                        zcosmic_secure_input_manager_v1::Error::AlreadyRequested,
+                        "the surface already has a secure input object",
+                    );
+                    return;
+                }
+
+                let secure_input = data_init.init(id, surface.clone());
+                with_states(&surface, |states| {
+                    states.data_map.insert_if_missing_threadsafe(|| {
+                        Mutex::new(SecureInputSurfaceData::default())
+                    });
+                    states
+                        .data_map
+                        .get::<Mutex<SecureInputSurfaceData>>()
+                        .unwrap()
+                        .lock()
+                        .unwrap()
+                        .0 = Some(secure_input.downgrade());
+                });
+            }
+            zcosmic_secure_input_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<ZcosmicSecureInputV1, WlSurface, D> for SecureInputState
+where
+    D: GlobalDispatch<ZcosmicSecureInputManagerV1, ()>
+        + Dispatch<ZcosmicSecureInputManagerV1, ()>
+        + Dispatch<ZcosmicSecureInputV1, WlSurface>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _obj: &ZcosmicSecureInputV1,
+        request: zcosmic_secure_input_v1::Request,
+        surface: &WlSurface,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zcosmic_secure_input_v1::Request::Destroy => {
+                if surface.is_alive() {
+                    with_states(surface, |states| {
+                        if let Some(data) = states.data_map.get::<Mutex<SecureInputSurfaceData>>() {
+                            data.lock().unwrap().0 = None;
+                        }
+                    });
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+macro_rules! delegate_secure_input {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocols::secure_input::zcosmic_secure_input_manager_v1::ZcosmicSecureInputManagerV1: ()
+        ] => $crate::wayland::protocols::secure_input::SecureInputState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocols::secure_input::zcosmic_secure_input_manager_v1::ZcosmicSecureInputManagerV1: ()
+        ] => $crate::wayland::protocols::secure_input::SecureInputState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            $crate::wayland::protocols::secure_input::zcosmic_secure_input_v1::ZcosmicSecureInputV1: smithay::reexports::wayland_server::protocol::wl_surface::WlSurface
+        ] => $crate::wayland::protocols::secure_input::SecureInputState);
+    };
+}
+pub(crate) use delegate_secure_input;
@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    reexports::{
+        wayland_protocols::wp::tearing_control::v1::server::{
+            wp_tearing_control_manager_v1::{self, WpTearingControlManagerV1},
+            wp_tearing_control_v1::{self, PresentationHint, WpTearingControlV1},
+        },
+        wayland_server::{
+            backend::GlobalId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch,
+            DisplayHandle, GlobalDispatch, New, Resource, Weak,
+        },
+    },
+    wayland::compositor::with_states,
+};
+use std::sync::Mutex;
+
+/// Global state of the `wp_tearing_control_manager_v1` protocol
+#[derive(Debug)]
+pub struct TearingControlState {
+    global: GlobalId,
+}
+
+/// Presentation hint of a surface, stored in its data map
+#[derive(Debug, Default)]
+struct TearingControlSurfaceData {
+    control: Option<Weak<WpTearingControlV1>>,
+    hint: Option<PresentationHint>,
+}
+
+/// Returns `true` if the client asked for async presentation of the surface
+pub fn surface_wants_tearing(surface: &WlSurface) -> bool {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<Mutex<TearingControlSurfaceData>>()
+            .map(|data| data.lock().unwrap().hint == Some(PresentationHint::Async))
+            .unwrap_or(false)
+    })
+}
+
+impl TearingControlState {
+    pub fn new<D>(dh: &DisplayHandle) -> TearingControlState
+    where
+        D: GlobalDispatch<WpTearingControlManagerV1, ()>
+            + Dispatch<WpTearingControlManagerV1, ()>
+            + Dispatch<WpTearingControlV1, WlSurface>
+            + 'static,
+    {
+        let global = dh.create_global::<D, WpTearingControlManagerV1, _>(1, ());
+        TearingControlState { global }
+    }
+
+    pub fn global_id(&self) -> GlobalId {
+        self.global.clone()
+    }
+}
+
+impl<D> GlobalDispatch<WpTearingControlManagerV1, (), D> for TearingControlState
+where
+    D: GlobalDispatch<WpTearingControlManagerV1, ()>
+        + Dispatch<WpTearingControlManagerV1, ()>
+        + Dispatch<WpTearingControlV1, WlSurface>
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpTearingControlManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<WpTearingControlManagerV1, (), D> for TearingControlState
+where
+    D: GlobalDispatch<WpTearingControlManagerV1, ()>
+        + Dispatch<WpTearingControlManagerV1, ()>
+        + Dispatch<WpTearingControlV1, WlSurface>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        obj: &WpTearingControlManagerV1,
+        request: wp_tearing_control_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            wp_tearing_control_manager_v1::Request::GetTearingControl { id, surface } => {
+                let already_exists = with_states(&surface, |states| {
+                    states.data_map.insert_if_missing_threadsafe(|| {
+                        Mutex::new(TearingControlSurfaceData::default())
+                    });
+                    let data = states
+                        .data_map
+                        .get::<Mutex<TearingControlSurfaceData>>()
+                        .unwrap()
+                        .lock()
+                        .unwrap();
+                    data.control
+                        .as_ref()
+                        .is_some_and(|control| control.upgrade().is_ok())
+                });
+                if already_exists {
+                    obj.post_error(
+                        wp_tearing_control_manager_v1::Error::TearingControlExists,
+                        "the surface already has a tearing control object",
+                    );
+                    return;
+                }
+
+                let control = data_init.init(id, surface.clone());
+                with_states(&surface, |states| {
+                    let mut data = states
+                        .data_map
+                        .get::<Mutex<TearingControlSurfaceData>>()
+                        .unwrap()
+                        .lock()
+                        .unwrap();
+                    data.control = Some(control.downgrade());
+                    data.hint = None;
+                });
+            }
+            wp_tearing_control_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<WpTearingControlV1, WlSurface, D> for TearingControlState
+where
+    D: GlobalDispatch<WpTearingControlManagerV1, ()>
+        + Dispatch<WpTearingControlManagerV1, ()>
+        + Dispatch<WpTearingControlV1, WlSurface>
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _obj: &WpTearingControlV1,
+        request: wp_tearing_control_v1::Request,
+        surface: &WlSurface,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            wp_tearing_control_v1::Request::SetPresentationHint { hint } => {
+                if let Ok(hint) = hint.into_result() {
+                    if surface.is_alive() {
+                        with_states(surface, |states| {
+                            if let Some(data) =
+                                states.data_map.get::<Mutex<TearingControlSurfaceData>>()
+                            {
+                                data.lock().unwrap().hint = Some(hint);
+                            }
+                        });
+                    }
+                }
+            }
+            wp_tearing_control_v1::Request::Destroy => {
+                if surface.is_alive() {
+                    with_states(surface, |states| {
+                        if let Some(data) =
+                            states.data_map.get::<Mutex<TearingControlSurfaceData>>()
+                        {
+                            *data.lock().unwrap() = TearingControlSurfaceData::default();
+                        }
+                    });
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+macro_rules! delegate_tearing_control {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::tearing_control::v1::server::wp_tearing_control_manager_v1::WpTearingControlManagerV1: ()
+        ] => $crate::wayland::protocols::tearing_control::TearingControlState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::tearing_control::v1::server::wp_tearing_control_manager_v1::WpTearingControlManagerV1: ()
+        ] => $crate::wayland::protocols::tearing_control::TearingControlState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::wp::tearing_control::v1::server::wp_tearing_control_v1::WpTearingControlV1: smithay::reexports::wayland_server::protocol::wl_surface::WlSurface
+        ] => $crate::wayland::protocols::tearing_control::TearingControlState);
+    };
+}
+pub(crate) use delegate_tearing_control;
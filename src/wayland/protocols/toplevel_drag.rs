@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    reexports::{
+        wayland_protocols::xdg::{
+            shell::server::xdg_toplevel::XdgToplevel,
+            toplevel_drag::v1::server::{
+                xdg_toplevel_drag_manager_v1::{self, XdgToplevelDragManagerV1},
+                xdg_toplevel_drag_v1::{self, XdgToplevelDragV1},
+            },
+        },
+        wayland_server::{
+            backend::GlobalId, protocol::wl_data_source::WlDataSource, Client, DataInit, Dispatch,
+            DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    utils::{Logical, Point},
+};
+use std::sync::Mutex;
+
+/// Global state of the `xdg_toplevel_drag_manager_v1` protocol
+#[derive(Debug)]
+pub struct ToplevelDragState {
+    global: GlobalId,
+    drags: Vec<XdgToplevelDragV1>,
+}
+
+#[derive(Debug)]
+pub struct ToplevelDragData {
+    source: WlDataSource,
+    toplevel: Mutex<Option<(XdgToplevel, Point<i32, Logical>)>>,
+}
+
+pub trait ToplevelDragHandler {
+    fn toplevel_drag_state(&mut self) -> &mut ToplevelDragState;
+
+    /// A toplevel was attached to the drag-and-drop operation of `source`,
+    /// `offset` is the position of the pointer relative to the toplevels geometry.
+    fn toplevel_attached(
+        &mut self,
+        source: &WlDataSource,
+        toplevel: &XdgToplevel,
+        offset: Point<i32, Logical>,
+    );
+}
+
+impl ToplevelDragState {
+    pub fn new<D>(dh: &DisplayHandle) -> ToplevelDragState
+    where
+        D: GlobalDispatch<XdgToplevelDragManagerV1, ()>
+            + Dispatch<XdgToplevelDragManagerV1, ()>
+            + Dispatch<XdgToplevelDragV1, ToplevelDragData>
+            + ToplevelDragHandler
+            + 'static,
+    {
+        let global = dh.create_global::<D, XdgToplevelDragManagerV1, _>(1, ());
+        ToplevelDragState {
+            global,
+            drags: Vec::new(),
+        }
+    }
+
+    pub fn global_id(&self) -> GlobalId {
+        self.global.clone()
+    }
+
+    /// Returns the toplevel attached to the drag-and-drop operation of `source` and its offset
+    pub fn toplevel_for_source(
+        &self,
+        source: &WlDataSource,
+    ) -> Option<(XdgToplevel, Point<i32, Logical>)> {
+        self.drags
+            .iter()
+            .filter_map(|drag| drag.data::<ToplevelDragData>())
+            .find(|data| &data.source == source)
+            .and_then(|data| data.toplevel.lock().unwrap().clone())
+            .filter(|(toplevel, _)| toplevel.is_alive())
+    }
+}
+
+impl<D> GlobalDispatch<XdgToplevelDragManagerV1, (), D> for ToplevelDragState
+where
+    D: GlobalDispatch<XdgToplevelDragManagerV1, ()>
+        + Dispatch<XdgToplevelDragManagerV1, ()>
+        + Dispatch<XdgToplevelDragV1, ToplevelDragData>
+        + ToplevelDragHandler
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<XdgToplevelDragManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl<D> Dispatch<XdgToplevelDragManagerV1, (), D> for ToplevelDragState
+where
+    D: GlobalDispatch<XdgToplevelDragManagerV1, ()>
+        + Dispatch<XdgToplevelDragManagerV1, ()>
+        + Dispatch<XdgToplevelDragV1, ToplevelDragData>
+        + ToplevelDragHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        obj: &XdgToplevelDragManagerV1,
+        request: xdg_toplevel_drag_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            xdg_toplevel_drag_manager_v1::Request::GetXdgToplevelDrag { id, data_source } => {
+                let drag_state = state.toplevel_drag_state();
+                drag_state.drags.retain(|drag| drag.is_alive());
+                if drag_state.drags.iter().any(|drag| {
+                    drag.data::<ToplevelDragData>()
+                        .is_some_and(|data| data.source == data_source)
+                }) {
+                    obj.post_error(
+                        xdg_toplevel_drag_manager_v1::Error::InvalidSource,
+                        "data source already used for a toplevel drag",
+                    );
+                    return;
+                }
+
+                let drag = data_init.init(
+                    id,
+                    ToplevelDragData {
+                        source: data_source,
+                        toplevel: Mutex::new(None),
+                    },
+                );
+                drag_state.drags.push(drag);
+            }
+            xdg_toplevel_drag_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<D> Dispatch<XdgToplevelDragV1, ToplevelDragData, D> for ToplevelDragState
+where
+    D: GlobalDispatch<XdgToplevelDragManagerV1, ()>
+        + Dispatch<XdgToplevelDragManagerV1, ()>
+        + Dispatch<XdgToplevelDragV1, ToplevelDragData>
+        + ToplevelDragHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        obj: &XdgToplevelDragV1,
+        request: xdg_toplevel_drag_v1::Request,
+        data: &ToplevelDragData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            xdg_toplevel_drag_v1::Request::Attach {
+                toplevel,
+                x_offset,
+                y_offset,
+            } => {
+                let attached = data
+                    .toplevel
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .is_some_and(|(toplevel, _)| toplevel.is_alive());
+                if attached {
+                    obj.post_error(
+                        xdg_toplevel_drag_v1::Error::ToplevelAttached,
+                        "a toplevel is already attached to this drag",
+                    );
+                    return;
+                }
+
+                let offset = Point::from((x_offset, y_offset));
+                *data.toplevel.lock().unwrap() = Some((toplevel.clone(), offset));
+                state.toplevel_attached(&data.source, &toplevel, offset);
+            }
+            xdg_toplevel_drag_v1::Request::Destroy => {
+                state
+                    .toplevel_drag_state()
+                    .drags
+                    .retain(|drag| drag != obj && drag.is_alive());
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+macro_rules! delegate_toplevel_drag {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::xdg::toplevel_drag::v1::server::xdg_toplevel_drag_manager_v1::XdgToplevelDragManagerV1: ()
+        ] => $crate::wayland::protocols::toplevel_drag::ToplevelDragState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::xdg::toplevel_drag::v1::server::xdg_toplevel_drag_manager_v1::XdgToplevelDragManagerV1: ()
+        ] => $crate::wayland::protocols::toplevel_drag::ToplevelDragState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::xdg::toplevel_drag::v1::server::xdg_toplevel_drag_v1::XdgToplevelDragV1: $crate::wayland::protocols::toplevel_drag::ToplevelDragData
+        ] => $crate::wayland::protocols::toplevel_drag::ToplevelDragState);
+    };
+}
+pub(crate) use delegate_toplevel_drag;
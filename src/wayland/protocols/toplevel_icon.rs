@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    reexports::{
+        wayland_protocols::xdg::{
+            shell::server::xdg_toplevel::XdgToplevel,
+            toplevel_icon::v1::server::{
+                xdg_toplevel_icon_manager_v1::{self, XdgToplevelIconManagerV1},
+                xdg_toplevel_icon_v1::{self, XdgToplevelIconV1},
+            },
+        },
+        wayland_server::{
+            backend::GlobalId,
+            protocol::{wl_buffer::WlBuffer, wl_shm, wl_surface::WlSurface},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+    wayland::{compositor::with_states, shm::with_buffer_contents},
+};
+use std::sync::{Arc, Mutex};
+
+/// Icon sizes in logical pixels advertised to clients
+const ICON_SIZES: [i32; 2] = [16, 32];
+
+/// Global state of the `xdg_toplevel_icon_manager_v1` protocol
+#[derive(Debug)]
+pub struct ToplevelIconState {
+    global: GlobalId,
+}
+
+/// Icon set by a client for one of its toplevels
+#[derive(Debug, Default, Clone)]
+pub struct ToplevelIcon {
+    /// Name of an icon of the icon theme
+    pub name: Option<String>,
+    /// Icon pixel data, used if `name` can't be found
+    pub buffers: Vec<IconBuffer>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IconBuffer {
+    pub size: i32,
+    pub scale: i32,
+    /// Pixels in RGBA order
+    pub pixels: Vec<u8>,
+}
+
+impl ToplevelIcon {
+    /// Returns the buffer best suited to be displayed at `size` logical pixels
+    pub fn best_buffer(&self, size: i32, scale: i32) -> Option<&IconBuffer> {
+        let wanted = size * scale;
+        self.buffers.iter().min_by_key(|buffer| {
+            // prefer downscaling over upscaling
+            let diff = buffer.size - wanted;
+            if diff >= 0 {
+                diff
+            } else {
+                -diff * 2
+            }
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ToplevelIconData {
+    icon: Mutex<ToplevelIcon>,
+    immutable: Mutex<bool>,
+}
+
+pub trait ToplevelIconHandler {
+    /// The client changed the icon of `toplevel`, `None` resets it to the default icon.
+    fn set_icon(&mut self, toplevel: &XdgToplevel, icon: Option<Arc<ToplevelIcon>>);
+}
+
+struct SurfaceIcon(Mutex<Option<Arc<ToplevelIcon>>>);
+
+/// Returns the icon the client has set for the toplevel `surface`
+pub fn toplevel_icon(surface: &WlSurface) -> Option<Arc<ToplevelIcon>> {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<SurfaceIcon>()
+            .and_then(|icon| icon.0.lock().unwrap().clone())
+    })
+}
+
+/// Stores `icon` for the toplevel `surface`, to be retrieved with [`toplevel_icon`]
+pub fn set_toplevel_icon(surface: &WlSurface, icon: Option<Arc<ToplevelIcon>>) {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .insert_if_missing_threadsafe(|| SurfaceIcon(Mutex::new(None)));
+        *states
+            .data_map
+            .get::<SurfaceIcon>()
+            .unwrap()
+            .0
+            .lock()
+            .unwrap() = icon;
+    })
+}
+
+impl ToplevelIconState {
+    pub fn new<D>(dh: &DisplayHandle) -> ToplevelIconState
+    where
+        D: GlobalDispatch<XdgToplevelIconManagerV1, ()>
+            + Dispatch<XdgToplevelIconManagerV1, ()>
+            + Dispatch<XdgToplevelIconV1, ToplevelIconData>
+            + ToplevelIconHandler
+            + 'static,
+    {
+        let global = dh.create_global::<D, XdgToplevelIconManagerV1, _>(1, ());
+        ToplevelIconState { global }
+    }
+
+    pub fn global_id(&self) -> GlobalId {
+        self.global.clone()
+    }
+}
+
+impl<D> GlobalDispatch<XdgToplevelIconManagerV1, (), D> for ToplevelIconState
+where
+    D: GlobalDispatch<XdgToplevelIconManagerV1, ()>
+        + Dispatch<XdgToplevelIconManagerV1, ()>
+        + Dispatch<XdgToplevelIconV1, ToplevelIconData>
+        + ToplevelIconHandler
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<XdgToplevelIconManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        let manager = data_init.init(resource, ());
+        for size in ICON_SIZES {
+            manager.icon_size(size);
+        }
+        manager.done();
+    }
+}
+
+impl<D> Dispatch<XdgToplevelIconManagerV1, (), D> for ToplevelIconState
+where
+    D: GlobalDispatch<XdgToplevelIconManagerV1, ()>
+        + Dispatch<XdgToplevelIconManagerV1, ()>
+        + Dispatch<XdgToplevelIconV1, ToplevelIconData>
+        + ToplevelIconHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _obj: &XdgToplevelIconManagerV1,
+        request: xdg_toplevel_icon_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            xdg_toplevel_icon_manager_v1::Request::CreateIcon { id } => {
+                data_init.init(id, ToplevelIconData::default());
+            }
+            xdg_toplevel_icon_manager_v1::Request::SetIcon { toplevel, icon } => {
+                let icon = icon.and_then(|icon| {
+                    let data = icon.data::<ToplevelIconData>()?;
+                    *data.immutable.lock().unwrap() = true;
+                    Some(Arc::new(data.icon.lock().unwrap().clone()))
+                });
+                // an icon without any name or buffer resets the icon
+                let icon = icon.filter(|icon| icon.name.is_some() || !icon.buffers.is_empty());
+                state.set_icon(&toplevel, icon);
+            }
+            xdg_toplevel_icon_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Copies the contents of a square argb8888/xrgb8888 shm buffer in RGBA order
+fn copy_buffer(buffer: &WlBuffer) -> Option<(i32, Vec<u8>)> {
+    with_buffer_contents(buffer, |ptr, len, data| {
+        if data.width != data.height
+            || !matches!(
+                data.format,
+                wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888
+            )
+        {
+            return None;
+        }
+
+        let size = data.width as usize;
+        let stride = data.stride as usize;
+        let offset = data.offset as usize;
+        if offset + stride * size > len || stride < size * 4 {
+            return None;
+        }
+        let contents = unsafe { std::slice::from_raw_parts(ptr, len) };
+
+        let mut pixels = Vec::with_capacity(size * size * 4);
+        for row in 0..size {
+            let start = offset + row * stride;
+            for pixel in contents[start..start + size * 4].chunks_exact(4) {
+                // little-endian argb is stored as bgra
+                let alpha = if data.format == wl_shm::Format::Xrgb8888 {
+                    0xff
+                } else {
+                    pixel[3]
+                };
+                pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], alpha]);
+            }
+        }
+        Some((data.width, pixels))
+    })
+    .ok()
+    .flatten()
+}
+
+impl<D> Dispatch<XdgToplevelIconV1, ToplevelIconData, D> for ToplevelIconState
+where
+    D: GlobalDispatch<XdgToplevelIconManagerV1, ()>
+        + Dispatch<XdgToplevelIconManagerV1, ()>
+        + Dispatch<XdgToplevelIconV1, ToplevelIconData>
+        + ToplevelIconHandler
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        obj: &XdgToplevelIconV1,
+        request: xdg_toplevel_icon_v1::Request,
+        data: &ToplevelIconData,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        if *data.immutable.lock().unwrap()
+            && !matches!(request, xdg_toplevel_icon_v1::Request::Destroy)
+        {
+            obj.post_error(
+                xdg_toplevel_icon_v1::Error::Immutable,
+                "the icon was already assigned to a toplevel",
+            );
+            return;
+        }
+
+        match request {
+            xdg_toplevel_icon_v1::Request::SetName { icon_name } => {
+                data.icon.lock().unwrap().name = Some(icon_name);
+            }
+            xdg_toplevel_icon_v1::Request::AddBuffer { buffer, scale } => {
+                let Some((size, pixels)) = copy_buffer(&buffer) else {
+                    obj.post_error(
+                        xdg_toplevel_icon_v1::Error::InvalidBuffer,
+                        "icon buffers need to be square shm buffers",
+                    );
+                    return;
+                };
+
+                let mut icon = data.icon.lock().unwrap();
+                // a new buffer replaces any existing buffer of the same size and scale
+                icon.buffers
+                    .retain(|buffer| buffer.size != size || buffer.scale != scale);
+                icon.buffers.push(IconBuffer {
+                    size,
+                    scale,
+                    pixels,
+                });
+            }
+            xdg_toplevel_icon_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+macro_rules! delegate_toplevel_icon {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::xdg::toplevel_icon::v1::server::xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1: ()
+        ] => $crate::wayland::protocols::toplevel_icon::ToplevelIconState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::xdg::toplevel_icon::v1::server::xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1: ()
+        ] => $crate::wayland::protocols::toplevel_icon::ToplevelIconState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols::xdg::toplevel_icon::v1::server::xdg_toplevel_icon_v1::XdgToplevelIconV1: $crate::wayland::protocols::toplevel_icon::ToplevelIconData
+        ] => $crate::wayland::protocols::toplevel_icon::ToplevelIconState);
+    };
+}
+pub(crate) use delegate_toplevel_icon;
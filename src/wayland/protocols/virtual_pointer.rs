@@ -0,0 +1,355 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use smithay::{
+    backend::input::{Axis, AxisSource, ButtonState},
+    input::{pointer::AxisFrame, Seat, SeatHandler},
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::virtual_pointer::v1::server::{
+            zwlr_virtual_pointer_manager_v1::{self, ZwlrVirtualPointerManagerV1},
+            zwlr_virtual_pointer_v1::{self, ZwlrVirtualPointerV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId},
+            protocol::{wl_output::WlOutput, wl_pointer, wl_seat::WlSeat},
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+        },
+    },
+    utils::{Logical, Point},
+};
+use std::{collections::HashSet, sync::Mutex};
+
+/// Global state of the `zwlr_virtual_pointer_manager_v1` protocol
+#[derive(Debug)]
+pub struct VirtualPointerManagerState {
+    global: GlobalId,
+}
+
+pub struct VirtualPointerManagerGlobalData {
+    filter: Box<dyn for<'a> Fn(&'a Client) -> bool + Send + Sync>,
+}
+
+/// Events emitted by a virtual pointer
+#[derive(Debug, Clone)]
+pub enum VirtualPointerEvent {
+    /// Relative motion in logical coordinates
+    Motion {
+        time: u32,
+        delta: Point<f64, Logical>,
+    },
+    /// Absolute motion, `position` is relative to `extent`
+    MotionAbsolute {
+        time: u32,
+        position: Point<f64, Logical>,
+        extent: Point<f64, Logical>,
+    },
+    Button {
+        time: u32,
+        button: u32,
+        state: ButtonState,
+    },
+    /// A complete axis frame, send on `frame`
+    Axis(AxisFrame),
+}
+
+pub struct VirtualPointerUserData<D: SeatHandler> {
+    seat: Option<Seat<D>>,
+    output: Option<Output>,
+    pending_axis: Mutex<Option<AxisFrame>>,
+    /// Buttons held down, released once the virtual pointer is destroyed
+    pressed: Mutex<HashSet<u32>>,
+    /// Time of the latest button event, for releasing the buttons
+    time: Mutex<u32>,
+}
+
+impl<D: SeatHandler> VirtualPointerUserData<D> {
+    /// Seat the virtual pointer was created for, if any
+    pub fn seat(&self) -> Option<&Seat<D>> {
+        self.seat.as_ref()
+    }
+
+    /// Output absolute motion events should be mapped to, if any
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+}
+
+pub trait VirtualPointerHandler: SeatHandler + Sized {
+    fn virtual_pointer_event(
+        &mut self,
+        pointer: &VirtualPointerUserData<Self>,
+        event: VirtualPointerEvent,
+    );
+}
+
+impl VirtualPointerManagerState {
+    pub fn new<D, F>(dh: &DisplayHandle, client_filter: F) -> VirtualPointerManagerState
+    where
+        D: GlobalDispatch<ZwlrVirtualPointerManagerV1, VirtualPointerManagerGlobalData>
+            + Dispatch<ZwlrVirtualPointerManagerV1, ()>
+            + Dispatch<ZwlrVirtualPointerV1, VirtualPointerUserData<D>>
+            + VirtualPointerHandler
+            + 'static,
+        F: for<'a> Fn(&'a Client) -> bool + Send + Sync + 'static,
+    {
+        let global = dh.create_global::<D, ZwlrVirtualPointerManagerV1, _>(
+            2,
+            VirtualPointerManagerGlobalData {
+                filter: Box::new(client_filter),
+            },
+        );
+        VirtualPointerManagerState { global }
+    }
+
+    pub fn global_id(&self) -> GlobalId {
+        self.global.clone()
+    }
+}
+
+impl<D> GlobalDispatch<ZwlrVirtualPointerManagerV1, VirtualPointerManagerGlobalData, D>
+    for VirtualPointerManagerState
+where
+    D: GlobalDispatch<ZwlrVirtualPointerManagerV1, VirtualPointerManagerGlobalData>
+        + Dispatch<ZwlrVirtualPointerManagerV1, ()>
+        + Dispatch<ZwlrVirtualPointerV1, VirtualPointerUserData<D>>
+        + VirtualPointerHandler
+        + 'static,
+{
+    fn bind(
+        _state: &mut D,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrVirtualPointerManagerV1>,
+        _global_data: &VirtualPointerManagerGlobalData,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &VirtualPointerManagerGlobalData) -> bool {
+        (global_data.filter)(&client)
+    }
+}
+
+fn init_pointer<D>(
+    data_init: &mut DataInit<'_, D>,
+    id: New<ZwlrVirtualPointerV1>,
+    seat: Option<WlSeat>,
+    output: Option<WlOutput>,
+) where
+    D: Dispatch<ZwlrVirtualPointerV1, VirtualPointerUserData<D>> + SeatHandler + 'static,
+{
+    data_init.init(
+        id,
+        VirtualPointerUserData {
+            seat: seat.as_ref().and_then(Seat::from_resource),
+            output: output.as_ref().and_then(Output::from_resource),
+            pending_axis: Mutex::new(None),
+            pressed: Mutex::new(HashSet::new()),
+            time: Mutex::new(0),
+        },
+    );
+}
+
+impl<D> Dispatch<ZwlrVirtualPointerManagerV1, (), D> for VirtualPointerManagerState
+where
+    D: GlobalDispatch<ZwlrVirtualPointerManagerV1, VirtualPointerManagerGlobalData>
+        + Dispatch<ZwlrVirtualPointerManagerV1, ()>
+        + Dispatch<ZwlrVirtualPointerV1, VirtualPointerUserData<D>>
+        + VirtualPointerHandler
+        + 'static,
+{
+    fn request(
+        _state: &mut D,
+        _client: &Client,
+        _obj: &ZwlrVirtualPointerManagerV1,
+        request: zwlr_virtual_pointer_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, D>,
+    ) {
+        match request {
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointer { seat, id } => {
+                init_pointer(data_init, id, seat, None);
+            }
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointerWithOutput {
+                seat,
+                output,
+                id,
+            } => {
+                init_pointer(data_init, id, seat, output);
+            }
+            zwlr_virtual_pointer_manager_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn axis_from_wl(axis: WEnum<wl_pointer::Axis>) -> Option<Axis> {
+    match axis.into_result().ok()? {
+        wl_pointer::Axis::HorizontalScroll => Some(Axis::Horizontal),
+        wl_pointer::Axis::VerticalScroll => Some(Axis::Vertical),
+        _ => None,
+    }
+}
+
+impl<D> Dispatch<ZwlrVirtualPointerV1, VirtualPointerUserData<D>, D> for VirtualPointerManagerState
+where
+    D: GlobalDispatch<ZwlrVirtualPointerManagerV1, VirtualPointerManagerGlobalData>
+        + Dispatch<ZwlrVirtualPointerManagerV1, ()>
+        + Dispatch<ZwlrVirtualPointerV1, VirtualPointerUserData<D>>
+        + VirtualPointerHandler
+        + 'static,
+{
+    fn request(
+        state: &mut D,
+        _client: &Client,
+        _obj: &ZwlrVirtualPointerV1,
+        request: zwlr_virtual_pointer_v1::Request,
+        data: &VirtualPointerUserData<D>,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, D>,
+    ) {
+        let with_frame = |time: u32, f: &dyn Fn(AxisFrame) -> AxisFrame| {
+            let mut pending = data.pending_axis.lock().unwrap();
+            let frame = pending.take().unwrap_or_else(|| AxisFrame::new(time));
+            *pending = Some(f(frame));
+        };
+
+        match request {
+            zwlr_virtual_pointer_v1::Request::Motion { time, dx, dy } => {
+                state.virtual_pointer_event(
+                    data,
+                    VirtualPointerEvent::Motion {
+                        time,
+                        delta: (dx, dy).into(),
+                    },
+                );
+            }
+            zwlr_virtual_pointer_v1::Request::MotionAbsolute {
+                time,
+                x,
+                y,
+                x_extent,
+                y_extent,
+            } => {
+                if x_extent == 0 || y_extent == 0 {
+                    return;
+                }
+                state.virtual_pointer_event(
+                    data,
+                    VirtualPointerEvent::MotionAbsolute {
+                        time,
+                        position: (x as f64, y as f64).into(),
+                        extent: (x_extent as f64, y_extent as f64).into(),
+                    },
+                );
+            }
+            zwlr_virtual_pointer_v1::Request::Button {
+                time,
+                button,
+                state: button_state,
+            } => {
+                let button_state = match button_state.into_result() {
+                    Ok(wl_pointer::ButtonState::Pressed) => ButtonState::Pressed,
+                    Ok(wl_pointer::ButtonState::Released) => ButtonState::Released,
+                    _ => return,
+                };
+                let mut pressed = data.pressed.lock().unwrap();
+                let changed = match button_state {
+                    ButtonState::Pressed => pressed.insert(button),
+                    ButtonState::Released => pressed.remove(&button),
+                };
+                std::mem::drop(pressed);
+                // don't press buttons twice, or release what isn't pressed
+                if !changed {
+                    return;
+                }
+                *data.time.lock().unwrap() = time;
+                state.virtual_pointer_event(
+                    data,
+                    VirtualPointerEvent::Button {
+                        time,
+                        button,
+                        state: button_state,
+                    },
+                );
+            }
+            zwlr_virtual_pointer_v1::Request::Axis { time, axis, value } => {
+                if let Some(axis) = axis_from_wl(axis) {
+                    with_frame(time, &|frame| frame.value(axis, value));
+                }
+            }
+            zwlr_virtual_pointer_v1::Request::AxisSource { axis_source } => {
+                let source = match axis_source.into_result() {
+                    Ok(wl_pointer::AxisSource::Wheel) => AxisSource::Wheel,
+                    Ok(wl_pointer::AxisSource::Finger) => AxisSource::Finger,
+                    Ok(wl_pointer::AxisSource::Continuous) => AxisSource::Continuous,
+                    Ok(wl_pointer::AxisSource::WheelTilt) => AxisSource::WheelTilt,
+                    _ => return,
+                };
+                with_frame(0, &|frame| frame.source(source));
+            }
+            zwlr_virtual_pointer_v1::Request::AxisStop { time, axis } => {
+                if let Some(axis) = axis_from_wl(axis) {
+                    with_frame(time, &|frame| frame.stop(axis));
+                }
+            }
+            zwlr_virtual_pointer_v1::Request::AxisDiscrete {
+                time,
+                axis,
+                value,
+                discrete,
+            } => {
+                if let Some(axis) = axis_from_wl(axis) {
+                    with_frame(time, &|frame| {
+                        frame.value(axis, value).v120(axis, discrete * 120)
+                    });
+                }
+            }
+            zwlr_virtual_pointer_v1::Request::Frame => {
+                if let Some(frame) = data.pending_axis.lock().unwrap().take() {
+                    state.virtual_pointer_event(data, VirtualPointerEvent::Axis(frame));
+                }
+            }
+            zwlr_virtual_pointer_v1::Request::Destroy => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(
+        state: &mut D,
+        _client: ClientId,
+        _resource: &ZwlrVirtualPointerV1,
+        data: &VirtualPointerUserData<D>,
+    ) {
+        // also called if the client disconnects, don't leave its buttons held down
+        let pressed = std::mem::take(&mut *data.pressed.lock().unwrap());
+        let time = *data.time.lock().unwrap();
+        for button in pressed {
+            state.virtual_pointer_event(
+                data,
+                VirtualPointerEvent::Button {
+                    time,
+                    button,
+                    state: ButtonState::Released,
+                },
+            );
+        }
+    }
+}
+
+macro_rules! delegate_virtual_pointer {
+    ($(@<$( $lt:tt $( : $clt:tt $(+ $dlt:tt )* )? ),+>)? $ty: ty) => {
+        smithay::reexports::wayland_server::delegate_global_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1: $crate::wayland::protocols::virtual_pointer::VirtualPointerManagerGlobalData
+        ] => $crate::wayland::protocols::virtual_pointer::VirtualPointerManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1: ()
+        ] => $crate::wayland::protocols::virtual_pointer::VirtualPointerManagerState);
+        smithay::reexports::wayland_server::delegate_dispatch!($(@< $( $lt $( : $clt $(+ $dlt )* )? ),+ >)? $ty: [
+            smithay::reexports::wayland_protocols_wlr::virtual_pointer::v1::server::zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1: $crate::wayland::protocols::virtual_pointer::VirtualPointerUserData<$ty>
+        ] => $crate::wayland::protocols::virtual_pointer::VirtualPointerManagerState);
+    };
+}
+pub(crate) use delegate_virtual_pointer;
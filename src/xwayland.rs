@@ -1,21 +1,32 @@
-use std::{ffi::OsString, os::unix::io::OwnedFd, process::Stdio};
+use std::{
+    ffi::OsString,
+    os::unix::io::OwnedFd,
+    process::Stdio,
+    time::{Duration, Instant},
+};
 
 use crate::{
     backend::render::cursor::{load_cursor_theme, Cursor, CursorShape},
     shell::{
         element::surface::SSD_HEIGHT, focus::target::KeyboardFocusTarget, grabs::ReleaseMode,
-        CosmicSurface, Shell,
+        ActivationKey, CosmicSurface, Shell,
     },
     state::State,
     utils::prelude::*,
     wayland::handlers::{
-        toplevel_management::minimize_rectangle, xdg_activation::ActivationContext,
+        selection::seat_selections, toplevel_management::minimize_rectangle,
+        xdg_activation::ActivationContext,
     },
 };
+use cosmic_comp_config::HookEvent;
+use rustix::process::{waitpid, Pid, WaitOptions};
 use smithay::{
     backend::drm::DrmNode,
     desktop::space::SpaceElement,
-    reexports::{wayland_server::Client, x11rb::protocol::xproto::Window as X11Window},
+    reexports::{
+        wayland_server::{backend::protocol::ProtocolError, Client},
+        x11rb::protocol::xproto::Window as X11Window,
+    },
     utils::{Logical, Point, Rectangle, Size, SERIAL_COUNTER},
     wayland::{
         selection::{
@@ -36,13 +47,22 @@ use smithay::{
         X11Surface, X11Wm, XWayland, XWaylandClientData, XWaylandEvent, XwmHandler,
     },
 };
-use tracing::{error, trace, warn};
+use tracing::{error, info, trace, warn};
+
+mod activation;
+
+/// Xwayland crashing sooner than this after starting isn't restarted, to avoid a crash loop
+const MIN_XWAYLAND_UPTIME: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub struct XWaylandState {
     pub client: Client,
     pub xwm: Option<X11Wm>,
     pub display: u32,
+    /// Process of the Xwayland server, to reap it after a crash
+    pid: Option<Pid>,
+    render_node: Option<DrmNode>,
+    started: Instant,
 }
 
 impl State {
@@ -50,6 +70,10 @@ impl State {
         if self.common.xwayland_state.is_some() {
             return;
         }
+        if !self.common.config.cosmic_conf.xwayland {
+            self.notify_ready();
+            return;
+        }
 
         let (xwayland, client) = match XWayland::spawn(
             &self.common.display_handle,
@@ -80,10 +104,17 @@ impl State {
                     x11_socket,
                     display_number,
                 } => {
+                    let pid = client
+                        .get_credentials(&data.common.display_handle)
+                        .ok()
+                        .and_then(|credentials| Pid::from_raw(credentials.pid));
                     data.common.xwayland_state = Some(XWaylandState {
                         client: client.clone(),
                         xwm: None,
                         display: display_number,
+                        pid,
+                        render_node,
+                        started: Instant::now(),
                     });
 
                     let mut wm = match X11Wm::start_wm(
@@ -99,6 +130,7 @@ impl State {
                     };
 
                     let (theme, size) = load_cursor_theme();
+                    let size = data.common.config.cosmic_conf.cursor.size.unwrap_or(size);
                     let cursor = Cursor::load(&theme, CursorShape::Default, size);
                     let image = cursor.get_image(1, 0);
                     if let Err(err) = wm.set_cursor(
@@ -113,6 +145,38 @@ impl State {
                         );
                     }
 
+                    // announce selections wayland clients made before Xwayland was ready,
+                    // so middle-click and clipboard paste work right away in X11 clients.
+                    let seat = data
+                        .common
+                        .shell
+                        .read()
+                        .unwrap()
+                        .seats
+                        .last_active()
+                        .clone();
+                    for (target, mime_types) in seat_selections(&seat) {
+                        if let Err(err) = wm.new_selection(target, Some(mime_types)) {
+                            warn!(
+                                id = ?wm.id(),
+                                ?err,
+                                ?target,
+                                "Failed to sync selection to Xwayland WM",
+                            );
+                        }
+                    }
+
+                    if let Err(err) =
+                        crate::bell::listen_xwayland(&data.common.event_loop_handle, display_number)
+                    {
+                        warn!(?err, "Failed to listen for bells of X11 clients");
+                    }
+                    if let Err(err) =
+                        activation::listen_xwayland(&data.common.event_loop_handle, display_number)
+                    {
+                        warn!(?err, "Failed to listen for X11 activation requests");
+                    }
+
                     let xwayland_state = data.common.xwayland_state.as_mut().unwrap();
                     xwayland_state.xwm = Some(wm);
                     data.notify_ready();
@@ -134,6 +198,91 @@ impl State {
             }
         }
     }
+
+    /// Stops Xwayland, closing all X11 applications
+    pub fn stop_xwayland(&mut self) {
+        let Some(mut xwayland_state) = self.common.xwayland_state.take() else {
+            return;
+        };
+        xwayland_state.xwm = None;
+        // Xwayland exits, once it loses its wayland connection
+        xwayland_state.client.kill(
+            &self.common.display_handle,
+            ProtocolError {
+                code: 0,
+                object_id: 0,
+                object_interface: "wl_display".into(),
+                message: "Xwayland was disabled".into(),
+            },
+        );
+        self.common.xwayland_scale = None;
+        self.notify_ready();
+    }
+
+    /// Cleans up after Xwayland, if it crashed, and starts it again
+    pub fn check_xwayland(&mut self) {
+        let Some(xwayland_state) = self.common.xwayland_state.as_ref() else {
+            return;
+        };
+        if self
+            .common
+            .display_handle
+            .backend_handle()
+            .get_client_data(xwayland_state.client.id())
+            .is_ok()
+        {
+            return;
+        }
+
+        let xwayland_state = self.common.xwayland_state.take().unwrap();
+        error!(display = xwayland_state.display, "Xwayland crashed.");
+        if let Some(pid) = xwayland_state.pid {
+            // Fails, if it was already reaped or isn't our child
+            let _ = waitpid(Some(pid), WaitOptions::NOHANG);
+        }
+        std::mem::drop(xwayland_state.xwm);
+        self.common.xwayland_scale = None;
+
+        // unmap every X11 window, their surfaces are gone with the connection
+        let mut shell = self.common.shell.write().unwrap();
+        let windows = shell
+            .mapped()
+            .flat_map(|mapped| mapped.windows().map(|(window, _)| window))
+            .filter_map(|window| window.x11_surface().cloned())
+            .collect::<Vec<_>>();
+        let seat = shell.seats.last_active().clone();
+        for window in &windows {
+            shell.unmap_surface(
+                window,
+                &seat,
+                &mut self.common.toplevel_info_state,
+                &mut self.common.foreign_toplevel_list,
+            );
+        }
+        shell.override_redirect_windows.clear();
+        shell
+            .pending_windows
+            .retain(|(window, _, _)| window.x11_surface().is_none());
+        shell
+            .pending_activations
+            .retain(|key, _| !matches!(key, ActivationKey::X11(_)));
+
+        let outputs = shell.outputs().cloned().collect::<Vec<_>>();
+        for output in outputs.iter() {
+            shell.refresh_active_space(output, &self.common.xdg_activation_state);
+        }
+        std::mem::drop(shell);
+        for output in outputs.into_iter() {
+            self.backend.schedule_render(&output);
+        }
+
+        if xwayland_state.started.elapsed() < MIN_XWAYLAND_UPTIME {
+            error!("Xwayland crashed right after starting, not restarting it.");
+            return;
+        }
+        info!("Restarting Xwayland.");
+        self.launch_xwayland(xwayland_state.render_node);
+    }
 }
 
 impl Common {
@@ -248,8 +397,19 @@ impl Common {
     }
 
     pub fn update_xwayland_scale(&mut self) {
-        let new_scale = if self.config.cosmic_conf.descale_xwayland {
-            let shell = self.shell.read().unwrap();
+        let shell = self.shell.read().unwrap();
+        let unscaled_app_mapped = !self.config.cosmic_conf.xwayland_unscaled_apps.is_empty()
+            && shell
+                .mapped()
+                .flat_map(|m| m.windows().map(|(s, _)| s))
+                .filter(|s| s.0.x11_surface().is_some())
+                .any(|s| {
+                    self.config
+                        .cosmic_conf
+                        .xwayland_unscaled_apps
+                        .contains(&s.app_id())
+                });
+        let new_scale = if self.config.cosmic_conf.descale_xwayland || unscaled_app_mapped {
             shell
                 .outputs()
                 .map(|o| o.current_scale().integer_scale())
@@ -258,6 +418,7 @@ impl Common {
         } else {
             1
         };
+        std::mem::drop(shell);
 
         // compare with current scale
         if Some(new_scale) != self.xwayland_scale {
@@ -343,10 +504,11 @@ impl XwmHandler for State {
             .map(XdgActivationToken::from)
             .and_then(|token| self.common.xdg_activation_state.data_for_token(&token))
             .and_then(|data| data.user_data.get::<ActivationContext>())
+            .and_then(|context| context.with_policy(self.common.config.cosmic_conf.activation))
         {
             shell.pending_activations.insert(
                 crate::shell::ActivationKey::X11(window.window_id()),
-                context.clone(),
+                context,
             );
         }
 
@@ -372,10 +534,13 @@ impl XwmHandler for State {
                         .xdg_activation_state
                         .data_for_token(&XdgActivationToken::from(startup_id))
                         .and_then(|data| data.user_data.get::<ActivationContext>())
+                        .and_then(|context| {
+                            context.with_policy(self.common.config.cosmic_conf.activation)
+                        })
                     {
                         shell.pending_activations.insert(
                             crate::shell::ActivationKey::X11(surface.window_id()),
-                            context.clone(),
+                            context,
                         );
                     }
                 }
@@ -387,11 +552,18 @@ impl XwmHandler for State {
                 &mut self.common.workspace_state,
                 &self.common.event_loop_handle,
             );
+            self.run_hooks(HookEvent::WindowMapped, Some(&window), &[]);
+            if let Some(ipc) = self.common.ipc.as_mut() {
+                ipc.window_opened(&shell, &window);
+            }
             if let Some(target) = res {
                 let seat = shell.seats.last_active().clone();
                 std::mem::drop(shell);
                 Shell::set_focus(self, Some(&target), &seat, None);
+            } else {
+                std::mem::drop(shell);
             }
+            self.common.update_xwayland_scale();
         }
     }
 
@@ -412,6 +584,9 @@ impl XwmHandler for State {
         if window.is_override_redirect() {
             shell.override_redirect_windows.retain(|or| or != &window);
         } else {
+            if let Some(ipc) = self.common.ipc.as_mut() {
+                ipc.window_closed(&shell, &window);
+            }
             let seat = shell.seats.last_active().clone();
             shell.unmap_surface(
                 &window,
@@ -434,9 +609,14 @@ impl XwmHandler for State {
             shell.refresh_active_space(output, &self.common.xdg_activation_state);
         }
 
+        std::mem::drop(shell);
+
         for output in outputs.into_iter() {
             self.backend.schedule_render(&output);
         }
+        if !window.is_override_redirect() {
+            self.common.update_xwayland_scale();
+        }
     }
 
     fn configure_request(
@@ -518,17 +698,20 @@ impl XwmHandler for State {
     ) {
         if window.is_override_redirect() {
             let mut shell = self.common.shell.write().unwrap();
-            if let Some(id) = above {
-                let or_windows = &mut shell.override_redirect_windows;
-                if let Some(own_pos) = or_windows.iter().position(|or| or == &window) {
-                    let compare_pos = or_windows
-                        .iter()
-                        .position(|or| or.window_id() == id)
-                        .unwrap_or(0);
-                    if compare_pos > own_pos {
-                        let this = or_windows.remove(own_pos);
-                        or_windows.insert(compare_pos, this);
-                    }
+            // follow the X11 stacking order, `above` is the sibling directly below the window
+            let or_windows = &mut shell.override_redirect_windows;
+            if let Some(own_pos) = or_windows.iter().position(|or| or == &window) {
+                let sibling_pos =
+                    above.map(|id| or_windows.iter().position(|or| or.window_id() == id));
+                let new_pos = match sibling_pos {
+                    None => Some(0),
+                    Some(Some(pos)) if pos < own_pos => Some(pos + 1),
+                    Some(pos) => pos,
+                };
+                // stacked relative to a managed window, which are all below anyway
+                if let Some(new_pos) = new_pos.filter(|new_pos| *new_pos != own_pos) {
+                    let this = or_windows.remove(own_pos);
+                    or_windows.insert(new_pos, this);
                 }
             }
 
@@ -659,11 +842,12 @@ impl XwmHandler for State {
                     .find(|(w, _)| w.x11_surface() == Some(&window))
                 {
                     let from = minimize_rectangle(&output, &surface);
-                    shell
-                        .workspaces
-                        .space_for_handle_mut(&handle)
-                        .unwrap()
-                        .fullscreen_request(&surface, None, from, &seat);
+                    let game_mode = shell.game_mode.enabled;
+                    let workspace = shell.workspaces.space_for_handle_mut(&handle).unwrap();
+                    workspace.fullscreen_request(&surface, None, from, &seat);
+                    if game_mode {
+                        workspace.skip_fullscreen_animation();
+                    }
                 }
             }
         } else {
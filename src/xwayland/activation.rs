@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Activation requests of X11 clients, that the X11 window manager of smithay doesn't report.
+//!
+//! `_NET_ACTIVE_WINDOW` client messages and the urgency flag of `WM_HINTS` are received on a
+//! connection of our own to Xwayland, like the bells of [`crate::bell`], and are subject to the
+//! same activation policy as xdg-activation requests of Wayland clients.
+
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+use calloop::{
+    channel::{self, Sender},
+    LoopHandle,
+};
+use tracing::{debug, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{
+            AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, EventMask, Property, Window,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::{
+    shell::ActivationKey, state::State, wayland::handlers::xdg_activation::ActivationContext,
+};
+
+/// `UrgencyHint` flag of `WM_HINTS`
+const URGENCY_HINT: u32 = 1 << 8;
+/// Source indication of `_NET_ACTIVE_WINDOW` requests made by pagers on behalf of the user
+const SOURCE_PAGER: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum X11Activation {
+    /// `_NET_ACTIVE_WINDOW` was requested for `window`
+    Activate { window: Window, pager: bool },
+    /// The urgency flag of `window` was set
+    Urgent { window: Window },
+}
+
+/// Watches the Xwayland server `display` for X11 applications requesting activation
+pub fn listen_xwayland(evlh: &LoopHandle<'static, State>, display: u32) -> Result<()> {
+    let (tx, rx) = channel::channel();
+    evlh.insert_source(rx, |event, _, state| {
+        if let channel::Event::Msg(activation) = event {
+            state.x11_activation(activation);
+        }
+    })
+    .map_err(|err| err.error)
+    .context("Failed to add channel to event_loop")?;
+
+    std::thread::Builder::new()
+        .name("xwayland-activation".to_string())
+        .spawn(move || {
+            if let Err(err) = watch_activation(display, tx) {
+                warn!(?err, "Failed to watch Xwayland for activation requests");
+            }
+        })
+        .context("Failed to start helper thread")?;
+    Ok(())
+}
+
+fn watch_activation(display: u32, tx: Sender<X11Activation>) -> Result<()> {
+    let (conn, screen) = RustConnection::connect(Some(&format!(":{}", display)))
+        .context("Failed to connect to Xwayland")?;
+    let root = conn.setup().roots[screen].root;
+    let net_active_window = conn
+        .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+        .reply()?
+        .atom;
+
+    // client messages to the root window are sent to everyone selecting substructure events
+    conn.change_window_attributes(
+        root,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::SUBSTRUCTURE_NOTIFY),
+    )?
+    .check()?;
+    for window in conn.query_tree(root)?.reply()?.children {
+        watch_window(&conn, window)?;
+    }
+
+    let mut urgent = HashSet::new();
+    // the connection is closed, once Xwayland exits
+    while let Ok(event) = conn.wait_for_event() {
+        let activation = match event {
+            Event::CreateNotify(event) if !event.override_redirect => {
+                watch_window(&conn, event.window)?;
+                None
+            }
+            Event::DestroyNotify(event) => {
+                urgent.remove(&event.window);
+                None
+            }
+            Event::ClientMessage(event) if event.type_ == net_active_window => {
+                let [source, ..] = event.data.as_data32();
+                Some(X11Activation::Activate {
+                    window: event.window,
+                    pager: source == SOURCE_PAGER,
+                })
+            }
+            Event::PropertyNotify(event)
+                if event.atom == u32::from(AtomEnum::WM_HINTS)
+                    && event.state == Property::NEW_VALUE =>
+            {
+                // the hints are rewritten for other reasons, only report the flag being set
+                if is_urgent(&conn, event.window) {
+                    urgent
+                        .insert(event.window)
+                        .then_some(X11Activation::Urgent {
+                            window: event.window,
+                        })
+                } else {
+                    urgent.remove(&event.window);
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(activation) = activation {
+            if tx.send(activation).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn watch_window(conn: &RustConnection, window: Window) -> Result<()> {
+    // the window might already be gone, which only results in an ignored error event
+    conn.change_window_attributes(
+        window,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+fn is_urgent(conn: &RustConnection, window: Window) -> bool {
+    conn.get_property(false, window, AtomEnum::WM_HINTS, AtomEnum::WM_HINTS, 0, 1)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .and_then(|reply| reply.value32().and_then(|mut flags| flags.next()))
+        .is_some_and(|flags| flags & URGENCY_HINT != 0)
+}
+
+impl State {
+    fn x11_activation(&mut self, activation: X11Activation) {
+        let (window, context) = match activation {
+            X11Activation::Activate { window, pager } => {
+                // like tokens without a valid serial, applications may only move the focus
+                // away from one of their own windows
+                let focused = self
+                    .common
+                    .xwayland_state
+                    .as_ref()
+                    .and_then(|xwayland_state| xwayland_state.xwm.as_ref())
+                    .is_some_and(|xwm| self.common.is_x_focused(xwm.id()));
+                let context = if pager || focused {
+                    let shell = self.common.shell.read().unwrap();
+                    let output = shell.seats.last_active().active_output();
+                    ActivationContext::Workspace(shell.active_space(&output).handle)
+                } else {
+                    ActivationContext::UrgentOnly
+                };
+                (window, context)
+            }
+            X11Activation::Urgent { window } => (window, ActivationContext::UrgentOnly),
+        };
+        let Some(context) = context.with_policy(self.common.config.cosmic_conf.activation) else {
+            debug!(?activation, "Ignoring activation request of X11 window");
+            return;
+        };
+
+        let mut shell = self.common.shell.write().unwrap();
+        let surface = shell
+            .mapped()
+            .flat_map(|mapped| mapped.windows().map(|(window, _)| window))
+            .find_map(|surface| {
+                surface
+                    .x11_surface()
+                    .filter(|surface| surface.window_id() == window)
+                    .and_then(|surface| surface.wl_surface())
+            });
+        match surface {
+            Some(surface) => {
+                std::mem::drop(shell);
+                self.activate_surface(&surface, &context);
+            }
+            None => {
+                if shell.pending_windows.iter().any(|(pending, _, _)| {
+                    pending
+                        .x11_surface()
+                        .is_some_and(|surface| surface.window_id() == window)
+                }) {
+                    shell
+                        .pending_activations
+                        .insert(ActivationKey::X11(window), context);
+                }
+            }
+        }
+    }
+}